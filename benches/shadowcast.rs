@@ -0,0 +1,120 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ggez::graphics::Color;
+use na::Point3;
+use ndarray::prelude::*;
+use rand::prelude::*;
+
+use katakomb::{
+    constants::*,
+    generation::world::*,
+    rendering::{drawable::Drawable, lighting::*, tile::Tile},
+};
+
+/// Every fixture comes from this, so runs are comparable.
+const SEED: u64 = 0x6b61_7461;
+
+fn chunk() -> Array3<Tile> {
+    generate_chunk(Point3::origin(), &ChunkGenPackage::from_seed(SEED))
+}
+
+fn open_cells(tiles: &Array3<Tile>) -> Vec<Point3<usize>> {
+    tiles
+        .indexed_iter()
+        .filter(|(_, tile)| tile.tile_type.is_transparent())
+        .map(|((x, y, z), _)| Point3::new(x, y, z))
+        .collect()
+}
+
+fn lights(tiles: &Array3<Tile>, count: usize) -> Vec<(Point3<usize>, Color)> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+
+    open_cells(tiles)
+        .choose_multiple(&mut rng, count)
+        .map(|&pos| (pos, Color::WHITE))
+        .collect()
+}
+
+/// The open cell nearest the middle of the chunk.
+fn eye(tiles: &Array3<Tile>) -> Point3<usize> {
+    let middle = CHUNK_SIZE as i64 / 2;
+
+    open_cells(tiles)
+        .into_iter()
+        .min_by_key(|pos| {
+            (pos.x as i64 - middle).abs()
+                + (pos.y as i64 - middle).abs()
+                + (pos.z as i64 - middle).abs()
+        })
+        .expect("The fixture chunk has no open cells")
+}
+
+fn bench_lighting(c: &mut Criterion) {
+    let mut tiles = chunk();
+    let mut scratch = ShadowcastScratch::new(MAX_CAST_RANGE);
+    let mut group = c.benchmark_group("lighting");
+
+    for &range in &[6, 16] {
+        for &count in &[1, 10, 50] {
+            let lights = lights(&tiles, count);
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("range {}", range), count),
+                &lights,
+                |b, lights| b.iter(|| compute_lighting(&mut tiles, lights, range, &mut scratch)),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn bench_fov(c: &mut Criterion) {
+    let mut tiles = chunk();
+    let eye_cell = eye(&tiles);
+    let eye = eye_cell.map(|c| c as f32);
+    let mut scratch = ShadowcastScratch::new(MAX_CAST_RANGE);
+
+    // Light the area around the eye, like the game does before the FOV pass
+    compute_lighting(
+        &mut tiles,
+        &[(eye_cell, Color::WHITE)],
+        LIGHT_RANGE,
+        &mut scratch,
+    );
+
+    // The default sight range, and the furthest anything can cast, where one cast costs the most
+    let mut group = c.benchmark_group("fov");
+    for &range in &[12, MAX_CAST_RANGE] {
+        group.bench_with_input(BenchmarkId::new("range", range), &range, |b, &range| {
+            b.iter(|| {
+                cast_fov(
+                    tiles.view_mut(),
+                    eye_cell,
+                    eye,
+                    range,
+                    LightShape::Sphere,
+                    &mut scratch,
+                    |t, _| t.illuminated(),
+                )
+                .into_iter()
+                .filter(|&lit| lit)
+                .count()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_generation(c: &mut Criterion) {
+    let package = ChunkGenPackage::from_seed(SEED);
+
+    c.bench_function("generate_chunk", |b| {
+        b.iter(|| generate_chunk(Point3::origin(), &package))
+    });
+}
+
+// To measure a change, run `cargo bench --bench shadowcast -- --save-baseline before` without
+// it, then `cargo bench --bench shadowcast -- --baseline before` with it.
+criterion_group!(benches, bench_lighting, bench_fov, bench_generation);
+criterion_main!(benches);
@@ -1,3 +1,101 @@
+use std::{fs::File, io::BufReader, time::Duration};
+
+use failure::Fallible;
+use rodio::{source::Buffered, Decoder, OutputStream, OutputStreamHandle, Source};
+
+use crate::world::sound::Attenuation;
+
+pub const GUNSHOT_PATH: &str = "resources/gunshot.wav";
+pub const SCREAM_PATH: &str = "resources/scream.wav";
+
+/// How many tiles sound travels in a second, taking a tile to be about a metre across.
+const SPEED_OF_SOUND: f32 = 343.0;
+/// How loud the echo off the nearest wall is, and off the farthest, when they're right there.
+const NEAR_ECHO_AMPLITUDE: f32 = 0.5;
+const FAR_ECHO_AMPLITUDE: f32 = 0.25;
+
+/// A sound decoded once, so that it can be played again and again.
+type Sound = Buffered<Decoder<BufReader<File>>>;
+
+fn load_sound(path: &str) -> Fallible<Sound> {
+    let file = File::open(path)?;
+    Ok(Decoder::new(BufReader::new(file))?.buffered())
+}
+
+/// The delay and loudness of the reverbs that make a sound seem to be made `distances` away
+/// from the walls around it, as found by `echo_distances` out to `range`. Only the nearest and
+/// the farthest wall get one, as using more than two reverbs sounds muddy.
+pub fn echoes(distances: &[f32], range: f32) -> Vec<(Duration, f32)> {
+    let nearest = distances.iter().copied().fold(f32::INFINITY, f32::min);
+    let farthest = distances.iter().copied().fold(0.0, f32::max);
+
+    if distances.is_empty() || range <= 0.0 {
+        return Vec::new();
+    }
+
+    // There and back again, fading the farther the wall is
+    let echo = |dist: f32, amplitude: f32| {
+        let delay = Duration::from_secs_f32(2.0 * dist / SPEED_OF_SOUND);
+        (delay, amplitude * (1.0 - dist / range).max(0.0))
+    };
+
+    vec![
+        echo(nearest, NEAR_ECHO_AMPLITUDE),
+        echo(farthest, FAR_ECHO_AMPLITUDE),
+    ]
+}
+
+/// The sound output, along with the sounds the game plays through it.
+pub struct Audio {
+    /// Nothing can be heard once this is dropped.
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    gunshot: Sound,
+    scream: Sound,
+}
+
+impl Audio {
+    /// Opens the default output device and loads the sounds.
+    pub fn new() -> Fallible<Self> {
+        let (stream, handle) = OutputStream::try_default()?;
+
+        Ok(Self {
+            _stream: stream,
+            handle,
+            gunshot: load_sound(GUNSHOT_PATH)?,
+            scream: load_sound(SCREAM_PATH)?,
+        })
+    }
+
+    /// Plays a gunshot, echoing off walls `echo_distances` away in each direction, see
+    /// `world::util::echo_distances`.
+    pub fn play_gunshot(&self, echo_distances: &[f32], range: f32) -> Fallible<()> {
+        let mut source: Box<dyn Source<Item = f32> + Send> =
+            Box::new(self.gunshot.clone().convert_samples());
+
+        for (delay, amplitude) in echoes(echo_distances, range) {
+            source = Box::new(source.buffered().reverb(delay, amplitude));
+        }
+
+        self.handle.play_raw(source)?;
+        Ok(())
+    }
+
+    /// Plays the scream of something that was shot, heard through `attenuation`, see
+    /// `world::sound::attenuation`.
+    pub fn play_scream(&self, attenuation: Attenuation) -> Fallible<()> {
+        let source = self
+            .scream
+            .clone()
+            .convert_samples()
+            .low_pass(attenuation.cutoff_hz)
+            .amplify(attenuation.volume);
+
+        self.handle.play_raw(source)?;
+        Ok(())
+    }
+}
+
 /*
 use std::{
     cmp::Ordering,
@@ -86,3 +184,26 @@ where
 
 const MAX_RESAMPLE_CHUNK_SIZE: usize = 1024 * 100;
 */
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_echoes() {
+        assert!(echoes(&[], 16.0).is_empty());
+        assert!(echoes(&[4.0], 0.0).is_empty());
+
+        let echoes = echoes(&[12.0, 4.0, 8.0, 16.0, 20.0], 16.0);
+        assert_eq!(echoes.len(), 2);
+
+        let (near_delay, near_amplitude) = echoes[0];
+        assert_eq!(near_delay, Duration::from_secs_f32(8.0 / SPEED_OF_SOUND));
+        assert_eq!(near_amplitude, NEAR_ECHO_AMPLITUDE * 0.75);
+
+        // Past the range it's too far away to hear
+        let (far_delay, far_amplitude) = echoes[1];
+        assert!(far_delay > near_delay);
+        assert_eq!(far_amplitude, 0.0);
+    }
+}
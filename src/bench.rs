@@ -0,0 +1,236 @@
+use std::{
+    f32::consts::PI,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use failure::{format_err, Fallible};
+use float_ord::FloatOrd;
+use ggez::graphics::Color;
+use na::{Point3, Vector3};
+
+use crate::{
+    constants::MAX_CAST_RANGE,
+    generation::world::generate_world,
+    geometry::util::{distance_squared, floor_point, to_usize_point},
+    metrics::{Phase, PhaseStats},
+    rendering::{drawable::Drawable, fov::FovCache, lighting::*},
+    replay::Replay,
+    settings::Settings,
+    world::World,
+    Player,
+};
+
+/// Generates a chunk from `seed` and runs the lighting and FOV passes `iterations` times as
+/// the camera goes around a circle through it, without a window. Prints the timings and
+/// returns an error if any iteration panicked.
+pub fn run(settings: &Settings, seed: u64, iterations: usize) -> Fallible<()> {
+    let generation_start = Instant::now();
+    let mut world = generate_world(seed, settings);
+    let generation_time = generation_start.elapsed();
+
+    let path = camera_path(&world, iterations);
+    if path.is_empty() {
+        return Err(format_err!("Seed {} left nowhere to put the camera", seed));
+    }
+
+    let mut scratch = ShadowcastScratch::new(settings.light_range.max(settings.sight_range));
+    let mut light_sources = Vec::new();
+    let mut lighting_times = Vec::with_capacity(path.len());
+    let mut fov_times = Vec::with_capacity(path.len());
+    let mut draw_tiles = Vec::with_capacity(path.len());
+    let mut panics = 0;
+
+    for (i, &eye_cell) in path.iter().enumerate() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            // The player carries a light around, like the muzzle flash in the game
+            light_sources.clear();
+            light_sources.push((eye_cell, Color::WHITE));
+            light_sources.extend(world.lights().iter().cloned());
+
+            let lighting_start = Instant::now();
+            compute_lighting(
+                world.tiles_mut(),
+                &light_sources,
+                settings.light_range,
+                &mut scratch,
+            );
+            let lighting_time = lighting_start.elapsed();
+
+            // A fresh cache, so that every iteration has to cast
+            let fov_start = Instant::now();
+            let mut fov_cache = FovCache::default();
+            let visible =
+                fov_cache.visible(&mut world, eye_cell, settings.sight_range, &mut scratch);
+            let tiles = world.tiles();
+            let count = visible
+                .iter()
+                .map(|visible| &tiles[visible.index()])
+                .filter(|t| !t.tile_type.is_transparent() && t.illuminated())
+                .count();
+            let fov_time = fov_start.elapsed();
+
+            (lighting_time, fov_time, count)
+        }));
+
+        match result {
+            Ok((lighting_time, fov_time, count)) => {
+                lighting_times.push(lighting_time);
+                fov_times.push(fov_time);
+                draw_tiles.push(count);
+            }
+            Err(_) => {
+                println!("Iteration {} at {:?} panicked", i, eye_cell);
+                panics += 1;
+            }
+        }
+    }
+
+    println!(
+        "Seed {}, chunk size {}: generated in {:.2} ms with {} lights",
+        seed,
+        settings.chunk_size,
+        ms(generation_time),
+        world.lights().len()
+    );
+    println!("{} of {} iterations ran", draw_tiles.len(), path.len());
+
+    print_phase(Phase::Lighting, &mut lighting_times);
+    print_phase(Phase::Fov, &mut fov_times);
+
+    if let (Some(min), Some(max)) = (draw_tiles.iter().min(), draw_tiles.iter().max()) {
+        let mean = draw_tiles.iter().sum::<usize>() as f32 / draw_tiles.len() as f32;
+        println!("draw tiles: {:.0} avg, {} min, {} max", mean, min, max);
+    }
+
+    if panics > 0 {
+        return Err(format_err!(
+            "{} of {} iterations panicked",
+            panics,
+            path.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Plays `replay` through without a window, in the world it was recorded in. The player moves
+/// as recorded, and lighting and FOV run after every update as they do in the game. Prints the
+/// timings and where the player ended up, and returns an error at the first update that
+/// panicked.
+pub fn run_replay(settings: &Settings, replay: &Replay) -> Fallible<()> {
+    let settings = replay.header.apply_to(settings.clone());
+    let seed = replay.header.seed;
+    let mut world = generate_world(seed, &settings);
+    let mut player = Player::new(&settings);
+
+    let mut scratch = ShadowcastScratch::new(MAX_CAST_RANGE);
+    let mut fov_cache = FovCache::default();
+    let mut light_sources = Vec::new();
+    let mut lighting_times = Vec::with_capacity(replay.frames.len());
+    let mut fov_times = Vec::with_capacity(replay.frames.len());
+
+    for (i, input) in replay.frames.iter().enumerate() {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            light_sources.clear();
+            player.step(world.tiles(), input, &settings.input, &mut light_sources);
+            light_sources.extend(world.lights().iter().cloned());
+
+            let lighting_start = Instant::now();
+            compute_lighting(
+                world.tiles_mut(),
+                &light_sources,
+                settings.light_range,
+                &mut scratch,
+            );
+            let lighting_time = lighting_start.elapsed();
+
+            // The cache is kept between updates, as in the game
+            let fov_start = Instant::now();
+            let eye_cell = to_usize_point(floor_point(player.entity.pos) + Vector3::y());
+            fov_cache.visible(&mut world, eye_cell, settings.sight_range, &mut scratch);
+            let fov_time = fov_start.elapsed();
+
+            (lighting_time, fov_time)
+        }));
+
+        match result {
+            Ok((lighting_time, fov_time)) => {
+                lighting_times.push(lighting_time);
+                fov_times.push(fov_time);
+            }
+            Err(_) => {
+                return Err(format_err!(
+                    "Update {} of {} panicked with the player at {:?}",
+                    i,
+                    replay.frames.len(),
+                    player.entity.pos
+                ));
+            }
+        }
+    }
+
+    println!(
+        "Seed {}, chunk size {}: played {} updates",
+        seed,
+        settings.chunk_size,
+        replay.frames.len()
+    );
+
+    print_phase(Phase::Lighting, &mut lighting_times);
+    print_phase(Phase::Fov, &mut fov_times);
+
+    let pos = player.entity.pos;
+    println!(
+        "The player ended up at ({:.3}, {:.3}, {:.3})",
+        pos.x, pos.y, pos.z
+    );
+
+    Ok(())
+}
+
+fn ms(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+fn print_phase(phase: Phase, times: &mut [Duration]) {
+    let stats = PhaseStats::from_samples(times);
+    println!(
+        "{}: {:.2} ms avg, {:.2} p95, {:.2} max",
+        phase.name(),
+        stats.mean,
+        stats.p95,
+        stats.max
+    );
+}
+
+/// `len` steps around a circle a quarter of the way in from the sides of the world, halfway
+/// up, each moved to the nearest cell the camera could be in.
+fn camera_path(world: &World, len: usize) -> Vec<Point3<usize>> {
+    let open_cells: Vec<_> = world
+        .tiles()
+        .indexed_iter()
+        .filter(|(_, tile)| tile.tile_type.is_transparent())
+        .map(|((x, y, z), _)| Point3::new(x, y, z))
+        .collect();
+
+    if open_cells.is_empty() {
+        return Vec::new();
+    }
+
+    let (width, height, depth) = world.tiles().dim();
+    let center = Point3::new(width as f32, height as f32, depth as f32) / 2.0;
+    let radius = width.min(depth) as f32 / 4.0;
+
+    (0..len)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f32 / len as f32;
+            let target = center + Vector3::new(angle.cos(), 0.0, angle.sin()) * radius;
+
+            *open_cells
+                .iter()
+                .min_by_key(|cell| FloatOrd(distance_squared(cell.map(|c| c as f32), target)))
+                .unwrap()
+        })
+        .collect()
+}
@@ -1,2 +1,6 @@
+pub mod collider;
+pub mod hit_flash;
+pub mod pickup;
 pub mod position;
+pub mod save;
 pub mod velocity;
@@ -1,8 +1,8 @@
 use specs::prelude::*;
 use specs_derive::Component;
 
+/// A sphere around an entity's position that shots can hit.
 #[derive(Component, Debug)]
-pub struct ColliderComponent
-{
+pub struct ColliderComponent {
     pub radius: f32,
-}
\ No newline at end of file
+}
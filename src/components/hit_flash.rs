@@ -0,0 +1,39 @@
+use specs::prelude::*;
+use specs_derive::Component;
+
+/// How many updates something that's been shot is drawn white for.
+pub const HIT_FLASH_TICS: u64 = 2;
+
+/// Set on an entity when it's shot, so that it's drawn white for a moment.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct HitFlashComponent {
+    /// The update the flash stops on.
+    pub until: u64,
+}
+
+impl HitFlashComponent {
+    /// A flash starting on update `now`.
+    pub fn new(now: u64) -> Self {
+        Self {
+            until: now + HIT_FLASH_TICS,
+        }
+    }
+
+    pub fn is_flashing(&self, now: u64) -> bool {
+        now < self.until
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_flashing() {
+        let flash = HitFlashComponent::new(10);
+
+        assert!(flash.is_flashing(10));
+        assert!(flash.is_flashing(10 + HIT_FLASH_TICS - 1));
+        assert!(!flash.is_flashing(10 + HIT_FLASH_TICS));
+    }
+}
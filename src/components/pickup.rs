@@ -0,0 +1,116 @@
+use std::f32::consts::PI;
+
+use na::{Point3, Vector3};
+use rand::Rng;
+use specs::prelude::*;
+use specs_derive::Component;
+
+use crate::{components::position::PositionComponent, inventory::ItemKind};
+
+/// How many updates a pickup takes to fly to the player.
+pub const PICKUP_FLIGHT_TICS: u32 = 8;
+/// How far a pickup bobs up and down from where it's lying.
+pub const BOB_HEIGHT: f32 = 0.15;
+/// How many updates a pickup takes to bob up and down once.
+const BOB_PERIOD: u64 = 90;
+
+/// Something lying in the world for the player to pick up.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct PickupComponent {
+    pub item: ItemKind,
+    pub amount: u32,
+    /// Set once it's close enough to the player to fly to them.
+    pub flight: Option<Flight>,
+}
+
+/// A pickup on its way to the player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flight {
+    /// Where it was lying, which it goes back to if the player has no room for it.
+    pub from: Point3<f32>,
+    /// How many updates it's been flying for.
+    pub tics: u32,
+}
+
+impl PickupComponent {
+    pub fn new(item: ItemKind, amount: u32) -> Self {
+        Self {
+            item,
+            amount,
+            flight: None,
+        }
+    }
+}
+
+/// How far above where it's lying a pickup is drawn on update `current_tic`. Each entity bobs
+/// at its own point in the cycle, so they don't all move together.
+pub fn bob_offset(current_tic: u64, id: u32) -> Vector3<f32> {
+    let phase = current_tic.wrapping_add(u64::from(id) * 17) % BOB_PERIOD;
+    let angle = phase as f32 / BOB_PERIOD as f32 * 2.0 * PI;
+
+    Vector3::y() * angle.sin() * BOB_HEIGHT
+}
+
+pub fn spawn_pickup(ecs: &mut World, pos: Point3<f32>, item: ItemKind, amount: u32) -> Entity {
+    ecs.create_entity()
+        .with(PositionComponent { value: pos })
+        .with(PickupComponent::new(item, amount))
+        .build()
+}
+
+/// Leaves a few flares or a handful of ammo at each of `spots`, floating over the middle of the
+/// tile.
+pub fn spawn_pickups<R>(ecs: &mut World, spots: &[Point3<usize>], rng: &mut R)
+where
+    R: Rng,
+{
+    for spot in spots {
+        let pos = spot.map(|c| c as f32) + Vector3::new(0.5, 0.5, 0.5);
+        let (item, amount) = if rng.gen_range(0, 3) == 0 {
+            (ItemKind::Flare, rng.gen_range(1, 3))
+        } else {
+            (ItemKind::Ammo, rng.gen_range(10, 31))
+        };
+
+        spawn_pickup(ecs, pos, item, amount);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_bob_offset() {
+        let offsets: Vec<_> = (0..BOB_PERIOD).map(|tic| bob_offset(tic, 3).y).collect();
+
+        assert!(offsets.iter().all(|y| y.abs() <= BOB_HEIGHT));
+        assert!(offsets.iter().any(|&y| y > BOB_HEIGHT * 0.9));
+        assert!(offsets.iter().any(|&y| y < -BOB_HEIGHT * 0.9));
+        // Round again after a whole cycle, but not in step with other entities
+        assert_eq!(bob_offset(BOB_PERIOD + 5, 3), bob_offset(5, 3));
+        assert_ne!(bob_offset(5, 3), bob_offset(5, 4));
+    }
+
+    #[test]
+    fn test_spawn_pickups() {
+        let mut ecs = World::new();
+        ecs.register::<PositionComponent>();
+        ecs.register::<PickupComponent>();
+
+        let spots = [Point3::new(1, 2, 3), Point3::new(4, 5, 6)];
+        spawn_pickups(&mut ecs, &spots, &mut StdRng::seed_from_u64(0));
+
+        let positions = ecs.read_storage::<PositionComponent>();
+        let pickups = ecs.read_storage::<PickupComponent>();
+        let spawned: Vec<_> = (&positions, &pickups).join().collect();
+        assert_eq!(spawned.len(), 2);
+        assert_eq!(spawned[0].0.value, Point3::new(1.5, 2.5, 3.5));
+        for (_, pickup) in spawned {
+            assert!(pickup.amount > 0);
+            assert!(pickup.amount <= pickup.item.max_carried());
+            assert_eq!(pickup.flight, None);
+        }
+    }
+}
@@ -1,8 +1,12 @@
 use na::Point3;
 use specs::prelude::*;
-use specs_derive::Component;
 
-#[derive(Component, Debug)]
+#[derive(Debug)]
 pub struct PositionComponent {
     pub value: Point3<f32>,
 }
+
+// Flagged so that `SpatialHashSystem` can tell which entities have moved
+impl Component for PositionComponent {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
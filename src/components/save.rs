@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use failure::{format_err, Fallible};
+use na::{Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use specs::prelude::*;
+
+use crate::{
+    components::{
+        collider::ColliderComponent, pickup::PickupComponent, position::PositionComponent,
+        velocity::VelocityComponent,
+    },
+    inventory::ItemKind,
+    util::write_json,
+    world::save::SAVE_VERSION,
+};
+
+/// Kept next to the rest of a save, in the same folder.
+pub const ENTITIES_FILE: &str = "entities.json";
+
+/// Where the entities of the world saved at `save_path` go.
+pub fn entities_path(save_path: &Path) -> PathBuf {
+    save_path.with_file_name(ENTITIES_FILE)
+}
+
+/// An entity as it's saved. Only the components here are kept, so anything else an entity has,
+/// like a handle into another system, has to be built again after it's loaded.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SerializableEntity {
+    /// The entity's id when it was saved, for anything saved along with it that refers to it.
+    pub id: u32,
+    pub pos: [f32; 3],
+    #[serde(default)]
+    pub vel: Option<[f32; 3]>,
+    #[serde(default)]
+    pub pickup: Option<SavedPickup>,
+    /// The radius of its collider, if it can be shot.
+    #[serde(default)]
+    pub collider: Option<f32>,
+}
+
+/// A pickup as it's saved. One that was flying to the player is saved lying where it started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SavedPickup {
+    pub item: ItemKind,
+    pub amount: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct EntitiesFile {
+    pub version: u32,
+    pub entities: Vec<SerializableEntity>,
+}
+
+/// Registers the components that entities are saved with.
+pub fn register_components(ecs: &mut World) {
+    ecs.register::<PositionComponent>();
+    ecs.register::<VelocityComponent>();
+    ecs.register::<PickupComponent>();
+    ecs.register::<ColliderComponent>();
+}
+
+/// Every entity in `ecs` with a position, as it's saved.
+pub fn save_entities(ecs: &World) -> Vec<SerializableEntity> {
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<PositionComponent>();
+    let velocities = ecs.read_storage::<VelocityComponent>();
+    let pickups = ecs.read_storage::<PickupComponent>();
+    let colliders = ecs.read_storage::<ColliderComponent>();
+
+    (&entities, &positions)
+        .join()
+        .map(|(entity, pos)| {
+            let pickup = pickups.get(entity);
+            let pos = pickup
+                .and_then(|pickup| pickup.flight)
+                .map_or(pos.value, |flight| flight.from);
+
+            SerializableEntity {
+                id: entity.id(),
+                pos: pos.coords.into(),
+                vel: velocities.get(entity).map(|vel| vel.value.into()),
+                pickup: pickup.map(|pickup| SavedPickup {
+                    item: pickup.item,
+                    amount: pickup.amount,
+                }),
+                collider: colliders.get(entity).map(|collider| collider.radius),
+            }
+        })
+        .collect()
+}
+
+/// Creates the `saved` entities in `ecs`, returning the entity each saved id became.
+pub fn load_entities(ecs: &mut World, saved: &[SerializableEntity]) -> HashMap<u32, Entity> {
+    saved
+        .iter()
+        .map(|saved| {
+            let mut builder = ecs.create_entity().with(PositionComponent {
+                value: Point3::from(saved.pos),
+            });
+            if let Some(vel) = saved.vel {
+                builder = builder.with(VelocityComponent {
+                    value: Vector3::from(vel),
+                });
+            }
+            if let Some(pickup) = saved.pickup {
+                builder = builder.with(PickupComponent::new(pickup.item, pickup.amount));
+            }
+            if let Some(radius) = saved.collider {
+                builder = builder.with(ColliderComponent { radius });
+            }
+
+            (saved.id, builder.build())
+        })
+        .collect()
+}
+
+impl EntitiesFile {
+    pub fn new(ecs: &World) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            entities: save_entities(ecs),
+        }
+    }
+
+    /// Writes the entities to `path`, making its folder if need be.
+    pub fn write<P>(&self, path: P) -> Fallible<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        write_json(path, self)
+    }
+
+    /// Reads the entities at `path`. A save from before entities were kept has none.
+    pub fn read<P>(path: P) -> Fallible<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(Self {
+                version: SAVE_VERSION,
+                entities: Vec::new(),
+            });
+        }
+
+        let json = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read {}: {}", path.display(), e))?;
+        let file: Self = serde_json::from_str(&json)
+            .map_err(|e| format_err!("{} is corrupt: {}", path.display(), e))?;
+
+        if file.version != SAVE_VERSION {
+            return Err(format_err!(
+                "{} is in version {} of the save format, but only version {} can be loaded",
+                path.display(),
+                file.version,
+                SAVE_VERSION
+            ));
+        }
+
+        if let Some(entity) = file.entities.iter().find(|entity| {
+            !entity
+                .pos
+                .iter()
+                .chain(entity.vel.iter().flatten())
+                .chain(entity.collider.iter())
+                .all(|c| c.is_finite())
+        }) {
+            return Err(format_err!(
+                "{} is corrupt: entity {} is at {:?}",
+                path.display(),
+                entity.id,
+                entity.pos
+            ));
+        }
+
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::components::pickup::Flight;
+
+    fn ecs() -> World {
+        let mut ecs = World::new();
+        register_components(&mut ecs);
+        ecs
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut ecs = ecs();
+        let still = ecs
+            .create_entity()
+            .with(PositionComponent {
+                value: Point3::new(1.0, 2.0, 3.0),
+            })
+            .build();
+        let gone = ecs.create_entity().build();
+        let moving = ecs
+            .create_entity()
+            .with(PositionComponent {
+                value: Point3::new(4.5, 1.0, 0.5),
+            })
+            .with(VelocityComponent {
+                value: Vector3::new(0.0, -0.1, 0.2),
+            })
+            .build();
+        // Nowhere to put it, so there's nothing to save
+        ecs.create_entity()
+            .with(VelocityComponent {
+                value: Vector3::x(),
+            })
+            .build();
+        ecs.delete_entity(gone).unwrap();
+        ecs.maintain();
+
+        let path = std::env::temp_dir()
+            .join("katakomb_test_entities")
+            .join(ENTITIES_FILE);
+        EntitiesFile::new(&ecs).write(&path).unwrap();
+        let file = EntitiesFile::read(&path).unwrap();
+        assert_eq!(file.entities.len(), 2);
+
+        // Into a world that already has an entity, so the ids don't line up
+        let mut loaded = ecs();
+        loaded.create_entity().build();
+        let ids = load_entities(&mut loaded, &file.entities);
+
+        let positions = loaded.read_storage::<PositionComponent>();
+        let velocities = loaded.read_storage::<VelocityComponent>();
+        let still = ids[&still.id()];
+        let moving = ids[&moving.id()];
+        assert_eq!(
+            positions.get(still).unwrap().value,
+            Point3::new(1.0, 2.0, 3.0)
+        );
+        assert!(velocities.get(still).is_none());
+        assert_eq!(
+            positions.get(moving).unwrap().value,
+            Point3::new(4.5, 1.0, 0.5)
+        );
+        assert_eq!(
+            velocities.get(moving).unwrap().value,
+            Vector3::new(0.0, -0.1, 0.2)
+        );
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(EntitiesFile::read(&path).unwrap().entities.is_empty());
+    }
+
+    #[test]
+    fn test_pickups_and_colliders() {
+        let mut ecs = ecs();
+        let target = ecs
+            .create_entity()
+            .with(PositionComponent {
+                value: Point3::new(2.5, 1.0, 2.5),
+            })
+            .with(ColliderComponent { radius: 0.5 })
+            .build();
+        let flying = ecs
+            .create_entity()
+            .with(PositionComponent {
+                value: Point3::new(3.0, 1.5, 3.0),
+            })
+            .with(PickupComponent {
+                flight: Some(Flight {
+                    from: Point3::new(6.5, 1.0, 6.5),
+                    tics: 3,
+                }),
+                ..PickupComponent::new(ItemKind::Ammo, 12)
+            })
+            .build();
+
+        let mut loaded = ecs();
+        let ids = load_entities(&mut loaded, &save_entities(&ecs));
+
+        let positions = loaded.read_storage::<PositionComponent>();
+        let pickups = loaded.read_storage::<PickupComponent>();
+        let colliders = loaded.read_storage::<ColliderComponent>();
+        let target = ids[&target.id()];
+        let flying = ids[&flying.id()];
+        assert_eq!(colliders.get(target).unwrap().radius, 0.5);
+        assert!(pickups.get(target).is_none());
+        // Back where it was lying, and no longer on its way
+        assert_eq!(
+            positions.get(flying).unwrap().value,
+            Point3::new(6.5, 1.0, 6.5)
+        );
+        assert_eq!(
+            pickups.get(flying),
+            Some(&PickupComponent::new(ItemKind::Ammo, 12))
+        );
+        assert!(colliders.get(flying).is_none());
+    }
+
+    #[test]
+    fn test_entities_path() {
+        assert_eq!(
+            entities_path(Path::new("saves/caves/quicksave.json")),
+            Path::new("saves/caves").join(ENTITIES_FILE)
+        );
+    }
+}
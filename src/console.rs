@@ -0,0 +1,652 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    str::FromStr,
+};
+
+use flo_binding::{bind, Binding, MutableBound};
+use ggez::event::KeyCode;
+use na::{Point3, Vector3};
+use specs::{Builder, WorldExt};
+
+use crate::{
+    components::{collider::ColliderComponent, pickup::spawn_pickup, position::PositionComponent},
+    geometry::rect::SRect,
+    inventory::ItemKind,
+    rendering::{
+        color::{self, Color},
+        voxel::Voxel2,
+    },
+    settings::Settings,
+    status::StatusKind,
+    ui::*,
+    weapons::{Weapons, DEFAULT_WEAPON},
+    world::{DecalType, World},
+    Item, Player,
+};
+
+/// How many lines of output are kept for scrolling back through.
+const MAX_HISTORY: usize = 200;
+
+/// What console commands can change. It's only `pub(crate)` because `Player` is private to the
+/// game.
+pub(crate) struct GameCtx<'a> {
+    pub world: &'a mut World,
+    pub player: &'a mut Player,
+    pub settings: &'a mut Settings,
+    pub ecs: &'a mut specs::World,
+    /// The models that weapons can be given with.
+    pub weapons: &'a Weapons,
+    /// What the world was generated from, unless it was loaded from a save.
+    pub seed: Option<u64>,
+    /// Set to light the world again from scratch after the command.
+    pub relight: bool,
+}
+
+/// Runs a command with the words after its name, returning what to print or what went wrong.
+pub(crate) type Command = Box<dyn Fn(&mut GameCtx, &[&str]) -> Result<String, String>>;
+
+/// The commands the console knows, by name.
+pub(crate) struct Commands {
+    commands: HashMap<&'static str, Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        let mut commands: HashMap<&'static str, Command> = HashMap::new();
+        commands.insert("tp", Box::new(teleport));
+        commands.insert("give", Box::new(give));
+        commands.insert("seed", Box::new(seed));
+        commands.insert("relight", Box::new(relight));
+        commands.insert("set", Box::new(set));
+        commands.insert("noclip", Box::new(noclip));
+        commands.insert("effect", Box::new(effect));
+        commands.insert("spawn", Box::new(spawn));
+        commands.insert("decal", Box::new(decal));
+
+        Self { commands }
+    }
+
+    /// The names of the commands, in order.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<_> = self.commands.keys().cloned().collect();
+        names.push("help");
+        names.sort_unstable();
+        names
+    }
+
+    /// Runs `line`, the command's name followed by its arguments, separated by spaces.
+    pub fn run(&self, game: &mut GameCtx, line: &str) -> Result<String, String> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let (name, args) = match words.split_first() {
+            Some((name, args)) => (*name, args),
+            None => return Ok(String::new()),
+        };
+
+        if name == "help" {
+            return Ok(format!("Commands: {}", self.names().join(", ")));
+        }
+
+        match self.commands.get(name) {
+            Some(command) => command(game, args),
+            None => Err(format!("There's no command called {}, try help", name)),
+        }
+    }
+}
+
+/// Parses the argument at `index`, calling it `name` if it's missing or isn't valid.
+fn arg<T: FromStr>(args: &[&str], index: usize, name: &str) -> Result<T, String> {
+    let arg = args
+        .get(index)
+        .ok_or_else(|| format!("Missing the {}", name))?;
+    arg.parse()
+        .map_err(|_| format!("{} isn't a valid {}", arg, name))
+}
+
+/// Fails with `usage` unless there are between `min` and `max` arguments.
+fn expect_args(args: &[&str], min: usize, max: usize, usage: &str) -> Result<(), String> {
+    if args.len() < min || args.len() > max {
+        Err(format!("Usage: {}", usage))
+    } else {
+        Ok(())
+    }
+}
+
+/// `tp x y z`: moves the player to a position in the world.
+fn teleport(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 3, 3, "tp x y z")?;
+    let pos = Point3::new(arg(args, 0, "x")?, arg(args, 1, "y")?, arg(args, 2, "z")?);
+
+    let (width, height, depth) = game.world.tiles().dim();
+    let size = Point3::new(width as f32, height as f32, depth as f32);
+    if (0..3).any(|i| !(0.0..size[i]).contains(&pos[i])) {
+        return Err(format!(
+            "({}, {}, {}) is outside of the world, which is {}x{}x{}",
+            pos.x, pos.y, pos.z, width, height, depth
+        ));
+    }
+
+    let entity = &mut game.player.entity;
+    entity.pos = pos;
+    entity.vel = Vector3::zeros();
+    game.player.ground = None;
+
+    Ok(format!("Moved to ({}, {}, {})", pos.x, pos.y, pos.z))
+}
+
+/// `give item [model]`: puts a new item in the player's hand, in place of what they were holding.
+/// Weapons can be given with any model in `weapons.json`, or the default one.
+fn give(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 1, 2, "give item [model]")?;
+
+    let (item, given) = match (args[0], args.get(1)) {
+        ("glowstick", None) => (Item::glowstick(), args[0]),
+        ("weapon", model) => {
+            let model = model.copied().unwrap_or(DEFAULT_WEAPON);
+            let weapon = Item::weapon(game.weapons, model).ok_or_else(|| {
+                let models: Vec<&str> = game.weapons.names().map(|name| &**name).collect();
+                format!("There's no {} weapon, only {}", model, models.join(", "))
+            })?;
+
+            (weapon, model)
+        }
+        ("glowstick", Some(_)) => return Err(String::from("Glowsticks only come in one model")),
+        (item, _) => return Err(format!("There's no {}, only glowstick and weapon", item)),
+    };
+
+    game.player.equipped_item = item;
+    Ok(format!("Holding a new {}", given))
+}
+
+/// `seed`: prints what the world was generated from.
+fn seed(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 0, 0, "seed")?;
+
+    game.seed
+        .map(|seed| seed.to_string())
+        .ok_or_else(|| String::from("This world was loaded from a save, which has no seed"))
+}
+
+/// `relight`: lights the whole world again.
+fn relight(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 0, 0, "relight")?;
+    game.relight = true;
+
+    Ok(String::from("Relighting the world"))
+}
+
+/// `set name [value]`: prints a setting, or changes it for the rest of the session. The value
+/// is written as in settings.json.
+fn set(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 1, 2, "set name [value]")?;
+    let name = args[0];
+
+    let mut values = match serde_json::to_value(&*game.settings) {
+        Ok(serde_json::Value::Object(values)) => values,
+        _ => return Err(String::from("The settings can't be read")),
+    };
+
+    let value = match values.get_mut(name) {
+        Some(value) => value,
+        None => {
+            let mut names: Vec<_> = values.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            return Err(format!(
+                "There's no setting called {}, there's {}",
+                name,
+                names.join(", ")
+            ));
+        }
+    };
+
+    let new_value = match args.get(1) {
+        // Anything that isn't JSON is taken as a string
+        Some(arg) => serde_json::from_str(arg).unwrap_or_else(|_| arg.to_string().into()),
+        None => return Ok(format!("{} is {}", name, value)),
+    };
+    *value = new_value;
+
+    let settings: Settings = serde_json::from_value(serde_json::Value::Object(values))
+        .map_err(|e| format!("{} can't be {}: {}", name, args[1], e))?;
+    *game.settings = settings.sanitized();
+
+    let value = serde_json::to_value(&*game.settings)
+        .ok()
+        .and_then(|values| values.get(name).cloned())
+        .unwrap_or_default();
+    Ok(format!("{} is now {}", name, value))
+}
+
+/// `noclip`: lets the player fly through rock, or stops them.
+fn noclip(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 0, 0, "noclip")?;
+    game.player.noclip = !game.player.noclip;
+
+    Ok(String::from(if game.player.noclip {
+        "Noclip on"
+    } else {
+        "Noclip off"
+    }))
+}
+
+/// `effect name updates`: puts an effect on the player for a number of updates, as if they'd
+/// walked into something that causes it.
+fn effect(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 2, 2, "effect name updates")?;
+    let kind = StatusKind::from_name(args[0]).ok_or_else(|| {
+        let names: Vec<_> = StatusKind::ALL.iter().map(|kind| kind.name()).collect();
+        format!("There's no {} effect, only {}", args[0], names.join(", "))
+    })?;
+    let updates = arg(args, 1, "number of updates")?;
+
+    game.player.status.apply(kind, updates);
+
+    Ok(format!("{} for {} updates", kind.name(), updates))
+}
+
+/// `decal x y z kind`: leaves a mark on a tile, as if it had been shot or burnt.
+fn decal(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 4, 4, "decal x y z kind")?;
+    let pos = Point3::new(arg(args, 0, "x")?, arg(args, 1, "y")?, arg(args, 2, "z")?);
+    let decal = DecalType::from_name(args[3]).ok_or_else(|| {
+        let names: Vec<_> = DecalType::ALL.iter().map(|decal| decal.name()).collect();
+        format!("There's no {} decal, only {}", args[3], names.join(", "))
+    })?;
+
+    if !game.world.add_decal(pos, decal) {
+        return Err(format!(
+            "({}, {}, {}) is outside of the world",
+            pos.x, pos.y, pos.z
+        ));
+    }
+
+    Ok(format!(
+        "Left a {} at ({}, {}, {})",
+        decal.name(),
+        pos.x,
+        pos.y,
+        pos.z
+    ))
+}
+
+/// `spawn item [amount]`: leaves a pickup in front of the player. `spawn target` leaves something
+/// there to shoot at instead.
+fn spawn(game: &mut GameCtx, args: &[&str]) -> Result<String, String> {
+    expect_args(args, 1, 2, "spawn item [amount]")?;
+
+    // Just out of reach, so that a pickup is seen before it flies in
+    let entity = &game.player.entity;
+    let yaw = entity.facing.x;
+    let pos = entity.pos + Vector3::new(yaw.sin() * 2.0, 0.5, yaw.cos() * 2.0);
+
+    if args[0] == "target" {
+        expect_args(args, 1, 1, "spawn target")?;
+        game.ecs
+            .create_entity()
+            .with(PositionComponent { value: pos })
+            .with(ColliderComponent { radius: 0.5 })
+            .build();
+
+        return Ok(format!("Spawned a target at {}", pos));
+    }
+
+    let item = ItemKind::from_name(args[0]).ok_or_else(|| {
+        let mut names: Vec<_> = ItemKind::ALL.iter().map(|kind| kind.name()).collect();
+        names.push("target");
+        format!("There's no {}, only {}", args[0], names.join(", "))
+    })?;
+    let amount = match args.get(1) {
+        Some(_) => arg(args, 1, "amount")?,
+        None => 1,
+    };
+    if amount == 0 {
+        return Err(String::from("There's nothing to spawn"));
+    }
+
+    spawn_pickup(game.ecs, pos, item, amount);
+
+    Ok(format!("Spawned {} {} at {}", amount, item.name(), pos))
+}
+
+/// A quake-style console: a line to type commands into, under what they printed.
+pub(crate) struct Console {
+    commands: Commands,
+    history: VecDeque<(String, Color)>,
+    /// How many lines back from the newest the history is scrolled.
+    scroll: usize,
+    text: Binding<String>,
+    input: TextInput,
+    /// The line Enter was last pressed on, until it's run.
+    submitted: Rc<RefCell<Option<String>>>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        let text = bind(String::new());
+        let submitted = Rc::new(RefCell::new(None));
+
+        Self {
+            commands: Commands::new(),
+            history: VecDeque::new(),
+            scroll: 0,
+            input: TextInput::new(text.clone()).with_focus().with_on_submit({
+                let submitted = Rc::clone(&submitted);
+                move |line| *submitted.borrow_mut() = Some(line)
+            }),
+            text,
+            submitted,
+        }
+    }
+
+    /// Runs the line Enter was pressed on since the last call, if there was one.
+    pub fn run_submitted(&mut self, game: &mut GameCtx) {
+        let line = match self.submitted.borrow_mut().take() {
+            Some(line) => line,
+            None => return,
+        };
+        self.text.set(String::new());
+
+        self.run(game, &line);
+    }
+
+    /// Runs `line` and adds it to the history along with what it printed.
+    pub fn run(&mut self, game: &mut GameCtx, line: &str) {
+        self.push(format!("> {}", line), color::GRAY);
+
+        match self.commands.run(game, line) {
+            Ok(output) => {
+                for line in output.lines() {
+                    self.push(line.to_string(), color::WHITE);
+                }
+            }
+            Err(e) => self.push(e, color::RED),
+        }
+
+        self.scroll = 0;
+    }
+
+    fn push(&mut self, line: String, color: Color) {
+        self.history.push_back((line, color));
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    /// Draws the history that fits above the input, newest at the bottom.
+    fn draw_history(&self, ctx: &mut UiContext, bounds: SRect) {
+        let rows = bounds.h as usize;
+        let end = self.history.len().saturating_sub(self.scroll);
+        let start = end.saturating_sub(rows);
+
+        for (i, (line, color)) in self.history.range(start..end).enumerate() {
+            let y = bounds.bottom() - (end - start - i) as i32;
+            let text = KataText::from_colored_str(line, *color);
+
+            for (x, voxel) in text.voxels.iter().take(bounds.w as usize).enumerate() {
+                ctx.draw(voxel, [bounds.x + x as i32, y]);
+            }
+        }
+    }
+}
+
+impl Element for Console {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        constraints.max
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if bounds.w < 3 || bounds.h == 0 {
+            return Ok(Continue);
+        }
+
+        let history_bounds = SRect::new(bounds.x, bounds.y, bounds.w, bounds.h - 1);
+        let input_bounds = SRect::new(bounds.x + 2, bounds.bottom() - 1, bounds.w - 2, 1);
+
+        match event {
+            Event::KeyDown {
+                keycode: KeyCode::PageUp,
+                ..
+            } => {
+                let max_scroll = self.history.len().saturating_sub(history_bounds.h as usize);
+                self.scroll = (self.scroll + history_bounds.h as usize).min(max_scroll);
+                return Err(Stop);
+            }
+
+            Event::KeyDown {
+                keycode: KeyCode::PageDown,
+                ..
+            } => {
+                self.scroll = self.scroll.saturating_sub(history_bounds.h as usize);
+                return Err(Stop);
+            }
+
+            Event::Draw => {
+                let background = Voxel2::new(0).background(Some(color::BLACK));
+                for p in bounds.points() {
+                    ctx.draw(&background, p);
+                }
+
+                self.draw_history(ctx, history_bounds);
+                ctx.draw(
+                    &Voxel2::new(u16::from(b'>')).foreground(color::YELLOW),
+                    [bounds.x, input_bounds.y],
+                );
+            }
+
+            _ => {}
+        }
+
+        self.input.handle_event(ctx, event, input_bounds)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        components::pickup::PickupComponent, constants::MAX_CAST_RANGE,
+        generation::world::generate_world,
+    };
+    use specs::Join;
+
+    /// A small world to run commands in.
+    struct Game {
+        world: World,
+        player: Player,
+        settings: Settings,
+        ecs: specs::World,
+        weapons: Weapons,
+        relight: bool,
+    }
+
+    impl Game {
+        fn new() -> Self {
+            let settings = Settings {
+                chunk_size: 8,
+                ..Settings::default()
+            };
+
+            let mut ecs = specs::World::new();
+            ecs.register::<PositionComponent>();
+            ecs.register::<PickupComponent>();
+            ecs.register::<ColliderComponent>();
+
+            Self {
+                world: generate_world(0, &settings),
+                player: Player::new(&settings),
+                settings,
+                ecs,
+                weapons: Weapons::default(),
+                relight: false,
+            }
+        }
+
+        fn with_ctx<T>(&mut self, f: impl FnOnce(&mut GameCtx) -> T) -> T {
+            let mut game = GameCtx {
+                world: &mut self.world,
+                player: &mut self.player,
+                settings: &mut self.settings,
+                ecs: &mut self.ecs,
+                weapons: &self.weapons,
+                seed: Some(7),
+                relight: false,
+            };
+            let result = f(&mut game);
+            self.relight = game.relight;
+            result
+        }
+
+        fn run(&mut self, line: &str) -> Result<String, String> {
+            self.with_ctx(|game| Commands::new().run(game, line))
+        }
+    }
+
+    fn assert_err_contains(result: Result<String, String>, expected: &str) {
+        match result {
+            Err(e) => assert!(e.contains(expected), "{:?} doesn't say {:?}", e, expected),
+            Ok(output) => panic!("Expected an error, got {:?}", output),
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let mut game = Game::new();
+
+        assert_eq!(game.run("  "), Ok(String::new()));
+        assert_err_contains(game.run("fly"), "try help");
+        assert!(game.run("help").unwrap().contains("noclip, relight, seed"));
+        assert_eq!(game.run("seed"), Ok(String::from("7")));
+        assert_err_contains(game.run("seed 3"), "Usage: seed");
+
+        assert_eq!(
+            game.run("relight"),
+            Ok(String::from("Relighting the world"))
+        );
+        assert!(game.relight);
+
+        assert!(game.run("noclip").is_ok());
+        assert!(game.player.noclip);
+        assert_eq!(game.run("noclip"), Ok(String::from("Noclip off")));
+
+        assert!(game.run("give glowstick").is_ok());
+        assert_err_contains(game.run("give flare"), "only glowstick");
+        assert_eq!(
+            game.run("give weapon"),
+            Ok(String::from("Holding a new rifle"))
+        );
+        match &game.player.equipped_item {
+            Item::Weapon { name, .. } => assert_eq!(&**name, "rifle"),
+            _ => panic!("The player isn't holding a weapon"),
+        }
+        assert!(game.run("give weapon rifle").is_ok());
+        assert_err_contains(game.run("give weapon bow"), "only rifle");
+        assert_err_contains(game.run("give glowstick red"), "one model");
+
+        assert!(game.run("effect slowed 30").is_ok());
+        assert!(game.player.status.get(StatusKind::Slowed).is_some());
+        assert_err_contains(game.run("effect wet 30"), "only burning, slowed, dizzy");
+        assert_err_contains(game.run("effect dizzy"), "Usage: effect name updates");
+    }
+
+    #[test]
+    fn test_spawn() {
+        let mut game = Game::new();
+
+        assert!(game.run("spawn ammo 20").is_ok());
+        assert!(game.run("spawn flare").is_ok());
+        assert_err_contains(game.run("spawn rope"), "only flare, ammo, target");
+        assert_err_contains(game.run("spawn ammo 0"), "nothing to spawn");
+        assert_err_contains(game.run("spawn ammo lots"), "lots isn't a valid amount");
+        assert!(game.run("spawn target").is_ok());
+        assert_err_contains(game.run("spawn target 2"), "Usage: spawn target");
+        assert_eq!(
+            game.ecs.read_storage::<ColliderComponent>().join().count(),
+            1
+        );
+
+        let pickups = game.ecs.read_storage::<PickupComponent>();
+        let spawned: Vec<_> = pickups
+            .join()
+            .map(|pickup| (pickup.item, pickup.amount))
+            .collect();
+        assert_eq!(spawned, vec![(ItemKind::Ammo, 20), (ItemKind::Flare, 1)]);
+    }
+
+    #[test]
+    fn test_teleport() {
+        let mut game = Game::new();
+
+        assert!(game.run("tp 1 2.5 3").is_ok());
+        assert_eq!(game.player.entity.pos, Point3::new(1.0, 2.5, 3.0));
+
+        assert_err_contains(game.run("tp 1 2"), "Usage: tp x y z");
+        assert_err_contains(game.run("tp 1 two 3"), "two isn't a valid y");
+        assert_err_contains(game.run("tp 1 2 8"), "outside of the world");
+        assert_err_contains(game.run("tp -1 2 3"), "outside of the world");
+        assert_eq!(game.player.entity.pos, Point3::new(1.0, 2.5, 3.0));
+    }
+
+    #[test]
+    fn test_decal() {
+        let mut game = Game::new();
+
+        assert!(game.run("decal 1 2 3 scorch").is_ok());
+        assert_eq!(
+            game.world
+                .metadata(Point3::new(1, 2, 3))
+                .and_then(|m| m.decal),
+            Some(DecalType::Scorch)
+        );
+
+        assert_err_contains(game.run("decal 1 2 3"), "Usage: decal x y z kind");
+        assert_err_contains(game.run("decal 1 2 3 footprint"), "only pock, scorch");
+        assert_err_contains(game.run("decal 1 2 -3 pock"), "outside of the world");
+        assert_eq!(game.world.decals().len(), 1);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut game = Game::new();
+
+        assert_eq!(
+            game.run("set light_range 5"),
+            Ok(String::from("light_range is now 5"))
+        );
+        assert_eq!(game.settings.light_range, 5);
+        assert_eq!(
+            game.run("set light_range"),
+            Ok(String::from("light_range is 5"))
+        );
+
+        // Out of range values are clamped like in the settings file
+        game.run("set light_range 100000").unwrap();
+        assert_eq!(game.settings.light_range, MAX_CAST_RANGE);
+
+        game.run("set max_fps null").unwrap();
+        assert_eq!(game.settings.max_fps, None);
+
+        assert_err_contains(game.run("set ambient 0.1"), "no setting called ambient");
+        assert_err_contains(game.run("set light_range bright"), "can't be bright");
+        assert_eq!(game.settings.light_range, MAX_CAST_RANGE);
+    }
+
+    #[test]
+    fn test_console() {
+        let mut game = Game::new();
+        let mut console = Console::new();
+
+        game.with_ctx(|ctx| {
+            console.run(ctx, "seed");
+            console.run(ctx, "fly");
+        });
+
+        // Blank cells on the console's background show up as dots
+        let lines = TextGridBatch::render(&mut console, Size::new(40, 5)).lines();
+        assert!(lines[0].starts_with(">.seed."), "{:?}", lines);
+        assert!(lines[1].starts_with("7."), "{:?}", lines);
+        assert!(lines[2].starts_with(">.fly."), "{:?}", lines);
+        assert!(lines[3].starts_with("There's.no.command"), "{:?}", lines);
+        assert!(lines[4].starts_with(">."), "{:?}", lines);
+    }
+}
@@ -3,7 +3,41 @@ pub const WINDOW_HEIGHT: f32 = 768.0;
 pub const CHUNK_SIZE: usize = 64;
 pub const LIGHT_RANGE: usize = 6;
 pub const PLAYER_SIGHT_RANGE: usize = 12;
+/// The furthest lights and sight can be set to reach, which is how big the casting lookups are.
+pub const MAX_CAST_RANGE: usize = 32;
+/// The biggest the world can be set to along each side.
+pub const MAX_CHUNK_SIZE: usize = 256;
+/// How far the player's footprint reaches from their position along x and z.
+pub const PLAYER_HALF_WIDTH: f32 = 0.3;
+pub const PLAYER_MAX_HEALTH: f32 = 100.0;
+/// How far above their feet the player's eye is when there's room for it.
+pub const PLAYER_EYE_HEIGHT: f32 = 1.0;
+/// How close to the camera things can be drawn. The eye is kept at least this far from rock.
+pub const NEAR_PLANE: f32 = 0.1;
+/// How far moving the mouse a pixel turns the view by default, in radians.
+pub const MOUSE_SENSITIVITY: f32 = 0.0025;
+/// The most mouse smoothing can be set to, past which the view would hardly move.
+pub const MAX_LOOK_SMOOTHING: f32 = 0.9;
+/// How close the ground has to be for the player to count as standing on it.
+pub const GROUND_EPSILON: f32 = 0.1;
 pub const MAX_SOUND_RANGE: f32 = 16.0;
+pub const STAMP_RANGE: f32 = 16.0;
+/// How far away the player can open a door or pull a lever from.
+pub const INTERACT_RANGE: f32 = 3.0;
+/// How far away a shot can hit something.
+pub const WEAPON_RANGE: f32 = 64.0;
+/// How much damage a shot does.
+pub const WEAPON_DAMAGE: u32 = 10;
+/// The most decals a chunk keeps before clearing the oldest.
+pub const DECALS_PER_CHUNK: usize = 256;
+/// How much darker a decal is drawn than the tile it's on.
+pub const DECAL_SHADE: f32 = 0.6;
 
 pub const NOISE_SCALE: f64 = 0.05;
 pub const NOISE_WEIGHT_SCALE: f64 = 0.01;
+/// One in how many of the places a light-giving decoration could go get one.
+pub const LIGHT_RARITY: u32 = 1000;
+/// One in how many doorways through the caves get a door.
+pub const DOOR_RARITY: u32 = 8;
+/// One in how many of the places a pickup could be left get one.
+pub const PICKUP_RARITY: u32 = 500;
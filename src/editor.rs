@@ -1,88 +1,1186 @@
+mod history;
+mod io;
+mod model_mode;
+mod palette_mode;
+mod panels;
+mod remap_mode;
+mod tools;
+mod voxel_mode;
+mod weapon_mode;
+
+use io::{
+    image_to_text, load_image, load_store, spawn_save, PendingSave, SaveFiles, SAVE_PROGRESS_WIDTH,
+};
+use model_mode::ModelMode;
+use palette_mode::PaletteMode;
+use panels::{dialog, help_panel};
+use remap_mode::RemapMode;
+use tools::ModelTool;
+use voxel_mode::{spawn_usage_search, unique_name, VoxelMode};
+use weapon_mode::{weapon_models, weapon_slices, WeaponMode};
+
 use std::{
+    cell::Cell,
     collections::{BTreeMap, HashMap},
-    convert::TryInto,
-    fs,
-    path::Path,
-    sync::{Arc, Mutex},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
+use chrono::{DateTime, Local};
 use failure::Fallible;
-use flo_binding::{Binding, Bound, MutableBound};
+use flo_binding::{bind, Binding, Bound, MutableBound};
 use ggez::{
-    event::EventHandler,
+    event::{self, EventHandler, KeyCode, KeyMods},
     graphics::{self, DrawParam, Image},
-    input::mouse::MouseButton,
+    input::{keyboard, mouse::MouseButton},
     mint, Context, GameResult,
 };
+use image::RgbaImage;
 use internship::IStr;
-use log::debug;
-use na::Point3;
-use ndarray::Array3;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use log::{debug, info, warn};
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    geometry::rect::IRect,
+    geometry::rect::SRect,
+    interop::{sheet, vox},
     rendering::{
-        color::{self, Color},
+        color::{self, Color, Palette},
         font::{KataFont, KataFontBatch},
+        tile::TileType,
         voxel::{Model, Voxel2, Voxel3, VoxelFace},
     },
+    strings::{StringTable, STRINGS_PATH},
+    tr,
     ui::*,
+    util::{back_up_file, try_load, write_json},
+    weapons::{WeaponModels, WEAPONS_PATH},
+    world::stamp,
 };
 
 pub struct Editor {
     voxels: BTreeMap<IStr, Voxel3>,
     models: BTreeMap<IStr, Model>,
+    palette: Palette,
+    weapons: WeaponModels,
+    /// Models that were edited and then switched away from, kept here until they're saved.
+    edited_models: BTreeMap<IStr, Model>,
 
     recent: Recent,
-
-    ui_context: UiContext,
-    mode: EditorMode,
+    settings: Settings,
+
+    ui_context: UiContext<KataFontBatch>,
+    tabs: Modal<Tabs<EditorMode>>,
+    dialogs: DialogHandle,
+    /// What the quit dialog asked for, to be carried out on the next update.
+    quit_choice: Rc<Cell<Option<QuitChoice>>>,
+    /// What the import dialog asked for, to be carried out on the next update.
+    import_choice: Rc<Cell<Option<ImportTarget>>>,
+    /// Whether the .vox import dialog was answered with Import, to be carried out on the next
+    /// update.
+    vox_import_choice: Rc<Cell<bool>>,
+    /// The path last typed into the import dialog.
+    import_path: Binding<String>,
+    /// Files that weren't valid JSON when the editor started, and what was wrong with them.
+    /// Saving would overwrite them, so it waits until they've been backed up or ignored.
+    corrupt_files: Vec<(PathBuf, String)>,
+    /// Whether the corrupt files dialog asked to back them up, to be carried out on the next
+    /// update.
+    backup_choice: Rc<Cell<Option<bool>>>,
 
     held_buttons: HashMap<MouseButton, HeldButton>,
+    clicks: ClickTracker,
     mouse_wheel_scroll: f32,
+
+    /// The last message, e.g. when saving fails, shown in the status bar for a few seconds.
+    notifications: Notifications,
+    last_saved: Option<DateTime<Local>>,
+    /// The save running in the background, if any.
+    saving: Option<PendingSave>,
+    /// At the right end of the status bar, only enabled while there's something to save.
+    save_button: Button,
+    can_save: Binding<bool>,
+    /// Whether the save button was clicked, to be carried out on the next update.
+    save_clicked: Rc<Cell<bool>>,
+    clipboard: Option<Clipboard>,
+    /// The list of keyboard shortcuts, shown over everything else while it's open.
+    help: Option<Centered<PanelBackground<Frame<FlexLayout>>>>,
 }
 
+/// The keyboard shortcuts and what they do, as listed by the help panel.
+const SHORTCUTS: &[(&str, &str)] = &[
+    ("Ctrl+S", "Save"),
+    ("Ctrl+Z", "Undo"),
+    ("Ctrl+Y", "Redo"),
+    ("Tab", "Next face"),
+    ("Left/Right", "Previous/next glyph"),
+    ("Up/Down", "Glyph above/below"),
+    ("Home/End", "First/last glyph"),
+    ("Delete", "Clear the face, or the selected cells"),
+    ("Ctrl+C", "Copy the face, or the selected cells"),
+    ("Ctrl+Shift+C", "Copy the voxel"),
+    ("Ctrl+V", "Paste"),
+    ("F1", "Voxel mode"),
+    ("F2", "Model mode"),
+    ("F3", "Palette mode"),
+    ("F4", "Remap mode"),
+    ("F5", "Weapon mode"),
+    ("PgUp/PgDn", "Page of glyphs, or slice in model mode"),
+    ("Right drag", "Filled rectangle with Rect"),
+    ("Right click", "Deselect with Select"),
+    ("+/-", "Zoom in/out"),
+    ("?", "Show/hide this help"),
+    ("Escape", "Quit"),
+];
+
 impl Editor {
     pub fn new(ctx: &mut Context) -> Fallible<Self> {
-        let voxels: BTreeMap<IStr, Voxel3> = try_load("voxels.json")?;
-        let models: BTreeMap<IStr, Model> = try_load("models.json")?;
-        let recent: Recent = try_load(".recent.json")?;
+        let mut corrupt_files = Vec::new();
+        let voxels: BTreeMap<IStr, Voxel3> = load_store("voxels.json", &mut corrupt_files)?;
+        let models: BTreeMap<IStr, Model> = load_store("models.json", &mut corrupt_files)?;
+        let palette: Palette = load_store(color::PALETTE_PATH, &mut corrupt_files)?;
+        let weapons: WeaponModels = load_store(WEAPONS_PATH, &mut corrupt_files)?;
+        // Padded out the way they're painted, so that ragged rows don't count as an edit
+        let weapons = weapon_models(weapon_slices(&weapons));
+        let recent: Recent = load_store(".recent.json", &mut corrupt_files)?;
+        let settings: Settings = load_store(".settings.json", &mut corrupt_files)?;
         let font = KataFont::load(ctx)?;
-
-        Ok(Self {
-            mode: EditorMode::restore(&recent, &voxels, &models, &font),
+        let strings = StringTable::load(STRINGS_PATH)?;
+        let dialogs = DialogHandle::new();
+        let can_save = bind(false);
+        let save_clicked = Rc::new(Cell::new(false));
+
+        let editor = Self {
+            tabs: Modal::new(
+                EditorMode::restore(
+                    &recent,
+                    &voxels,
+                    &models,
+                    &palette,
+                    &weapons,
+                    &settings,
+                    font.charset_width(),
+                    font.charset_width() * font.charset_height(),
+                ),
+                dialogs.clone(),
+            ),
+            dialogs,
+            quit_choice: Rc::new(Cell::new(None)),
+            import_choice: Rc::new(Cell::new(None)),
+            vox_import_choice: Rc::new(Cell::new(false)),
+            import_path: bind(String::new()),
+            corrupt_files,
+            backup_choice: Rc::new(Cell::new(None)),
             ui_context: UiContext::new(KataFontBatch::new(
                 font,
                 Image::solid(ctx, 1, graphics::Color::WHITE)?,
-                4.0,
-            )),
+                step_scaling(settings.scaling, 0),
+            ))
+            .with_strings(strings),
 
             voxels,
             models,
+            palette,
+            weapons,
+            edited_models: BTreeMap::new(),
             recent,
+            settings,
 
             mouse_wheel_scroll: 0.0,
             held_buttons: HashMap::new(),
+            clicks: ClickTracker::default(),
+            notifications: Notifications::default(),
+            last_saved: None,
+            saving: None,
+            save_button: Button::new("Save", {
+                let save_clicked = Rc::clone(&save_clicked);
+                move || save_clicked.set(true)
+            })
+            .with_enabled(can_save.clone()),
+            can_save,
+            save_clicked,
+            clipboard: None,
+            help: None,
+        };
+
+        if !editor.corrupt_files.is_empty() {
+            editor.open_corrupt_files_dialog();
+        }
+
+        Ok(editor)
+    }
+
+    fn voxel_mode(&self) -> Option<&VoxelMode> {
+        self.tabs.inner().elements().find_map(|mode| match mode {
+            EditorMode::Voxel(voxel_mode) => Some(voxel_mode),
+            _ => None,
         })
     }
 
+    fn voxel_mode_mut(&mut self) -> Option<&mut VoxelMode> {
+        self.tabs
+            .inner_mut()
+            .elements_mut()
+            .find_map(|mode| match mode {
+                EditorMode::Voxel(voxel_mode) => Some(voxel_mode),
+                _ => None,
+            })
+    }
+
+    fn model_mode(&self) -> Option<&ModelMode> {
+        self.tabs.inner().elements().find_map(|mode| match mode {
+            EditorMode::Model(model_mode) => Some(model_mode),
+            _ => None,
+        })
+    }
+
+    fn palette_mode(&self) -> Option<&PaletteMode> {
+        self.tabs.inner().elements().find_map(|mode| match mode {
+            EditorMode::Palette(palette_mode) => Some(palette_mode),
+            _ => None,
+        })
+    }
+
+    fn weapon_mode(&self) -> Option<&WeaponMode> {
+        self.tabs.inner().elements().find_map(|mode| match mode {
+            EditorMode::Weapon(weapon_mode) => Some(weapon_mode),
+            _ => None,
+        })
+    }
+
+    /// The voxels with any edits made so far.
+    fn current_voxels(&self) -> BTreeMap<IStr, Voxel3> {
+        self.voxel_mode()
+            .map(VoxelMode::voxels)
+            .unwrap_or_else(|| self.voxels.clone())
+    }
+
+    /// The models with any edits made so far.
+    fn current_models(&self) -> BTreeMap<IStr, Model> {
+        let mut models = self.models.clone();
+        models.extend(
+            self.edited_models
+                .iter()
+                .map(|(name, model)| (name.clone(), model.clone())),
+        );
+
+        if let Some(model_mode) = self.model_mode() {
+            if let Some(name) = &model_mode.name {
+                models.insert(name.clone(), model_mode.current_model());
+            }
+        }
+
+        models
+    }
+
+    /// The named colors with any edits made so far.
+    fn current_palette(&self) -> Palette {
+        self.palette_mode()
+            .map(|palette_mode| Palette {
+                entries: palette_mode.actions.entries.get(),
+            })
+            .unwrap_or_else(|| self.palette.clone())
+    }
+
+    /// The weapon models with any edits made so far.
+    fn current_weapons(&self) -> WeaponModels {
+        self.weapon_mode()
+            .map(|weapon_mode| weapon_mode.actions.models())
+            .unwrap_or_else(|| self.weapons.clone())
+    }
+
+    /// The settings with any changes made so far.
+    fn current_settings(&self) -> Settings {
+        match self.voxel_mode() {
+            Some(voxel_mode) => Settings {
+                palette: voxel_mode.palette.get(),
+                ..self.settings.clone()
+            },
+            None => self.settings.clone(),
+        }
+    }
+
+    /// Whether anything was edited since the last load or save.
+    fn is_dirty(&self) -> bool {
+        self.current_voxels() != self.voxels
+            || self.current_models() != self.models
+            || self.current_palette() != self.palette
+            || self.current_weapons() != self.weapons
+            || self.current_settings() != self.settings
+    }
+
+    /// The voxels, models, palette, weapons, recently used state and settings as they are now,
+    /// to be written back to disk.
+    fn save_files(&self) -> SaveFiles {
+        SaveFiles {
+            voxels: self.current_voxels(),
+            models: self.current_models(),
+            palette: self.current_palette(),
+            weapons: self.current_weapons(),
+            recent: self.recent.clone(),
+            settings: self.current_settings(),
+        }
+    }
+
+    /// Starts saving everything on another thread, with the progress shown in the status bar.
+    /// Quits once it's done when `quit` is set, unless it fails.
+    fn save(&mut self, quit: bool) {
+        if let Some(saving) = &mut self.saving {
+            saving.quit |= quit;
+            return;
+        }
+
+        if !self.corrupt_files.is_empty() {
+            self.notify(
+                NotifyLevel::Warning,
+                &tr!(self.ui_context.strings, "editor.save_blocked"),
+            );
+            self.open_corrupt_files_dialog();
+            return;
+        }
+
+        let files = self.save_files();
+        let receiver = spawn_save(files.clone(), PathBuf::new());
+        self.saving = Some(PendingSave::new(files, receiver, quit));
+    }
+
+    /// Picks up how far the save got, and shows how it went once it's finished.
+    fn poll_save(&mut self, ctx: &mut Context) {
+        let result = match self.saving.as_mut().and_then(PendingSave::poll) {
+            Some(result) => result,
+            None => return,
+        };
+
+        let saving = self.saving.take().unwrap();
+
+        match result {
+            Ok(()) => {
+                let SaveFiles {
+                    voxels,
+                    models,
+                    palette,
+                    weapons,
+                    settings,
+                    ..
+                } = saving.files;
+
+                // Only what was saved; anything edited since is still unsaved
+                self.edited_models
+                    .retain(|name, model| models.get(name) != Some(model));
+                self.voxels = voxels;
+                self.models = models;
+                self.palette = palette;
+                self.weapons = weapons;
+                self.settings = settings;
+                self.last_saved = Some(Local::now());
+                self.notify(
+                    NotifyLevel::Success,
+                    &tr!(
+                        self.ui_context.strings,
+                        "editor.save_ok",
+                        self.voxels.len(),
+                        self.models.len()
+                    ),
+                );
+
+                if saving.quit {
+                    event::quit(ctx);
+                }
+            }
+
+            // Stays open when saving to quit, so the error can be read
+            Err(e) => {
+                warn!("Failed to save: {}", e);
+                self.notify(
+                    NotifyLevel::Error,
+                    &tr!(self.ui_context.strings, "editor.save_failed", e),
+                );
+            }
+        }
+    }
+
+    /// Starts looking through every model for the selected voxel. Results show up in voxel mode
+    /// as they're found.
+    fn find_usages(&mut self) {
+        let name = match self.voxel_mode().and_then(VoxelMode::selected_name) {
+            Some(name) => name,
+            None => return,
+        };
+
+        let models = self.current_models();
+        self.notify(
+            NotifyLevel::Info,
+            &tr!(
+                self.ui_context.strings,
+                "editor.usages_searching",
+                &*name,
+                models.len()
+            ),
+        );
+
+        let search = spawn_usage_search(name.clone(), models);
+        if let Some(voxel_mode) = self.voxel_mode_mut() {
+            voxel_mode.start_usage_search(name, search);
+        }
+    }
+
+    /// Switches to model mode with the model called `name`, looking at slice `z`. The model that
+    /// was open before keeps its edits.
+    fn open_model(&mut self, name: &IStr, z: usize) {
+        let model = match self.current_models().remove(name) {
+            Some(model) => model,
+            None => {
+                self.notify(
+                    NotifyLevel::Warning,
+                    &tr!(self.ui_context.strings, "editor.model_missing", &**name),
+                );
+                return;
+            }
+        };
+
+        let open = self.model_mode().and_then(|model_mode| {
+            model_mode
+                .name
+                .clone()
+                .map(|name| (name, model_mode.current_model()))
+        });
+
+        if let Some((open_name, open_model)) = open {
+            self.edited_models.insert(open_name, open_model);
+        }
+
+        let voxels = self.current_voxels();
+        let tool = self.recent.tool;
+
+        for mode in self.tabs.inner_mut().elements_mut() {
+            if let EditorMode::Model(model_mode) = mode {
+                *model_mode =
+                    ModelMode::new(Some(name.clone()), Some(model.clone()), &voxels, tool);
+                model_mode.slice.set(z as i32);
+            }
+        }
+
+        self.tabs.inner_mut().select(EditorModeName::Model.index());
+        self.ui_context.relayout = true;
+    }
+
+    /// Turns the model being edited into tiles and writes them where the game can stamp them
+    /// into the world. Voxels are matched to tiles by `tile_mapping.json`.
+    fn export_stamp(&mut self) {
+        let (name, model) = match self.model_to_export() {
+            Some(named) => named,
+            None => {
+                self.notify(
+                    NotifyLevel::Warning,
+                    &tr!(self.ui_context.strings, "editor.export_no_model"),
+                );
+                return;
+            }
+        };
+
+        let result = try_load("tile_mapping.json").and_then(|mapping: BTreeMap<IStr, TileType>| {
+            let (stamp, unmapped) = stamp::from_model(&model, &mapping);
+            write_json(stamp::STAMP_PATH, &stamp)?;
+            Ok(unmapped)
+        });
+
+        match result {
+            Ok(unmapped) if unmapped.is_empty() => self.notify(
+                NotifyLevel::Success,
+                &tr!(
+                    self.ui_context.strings,
+                    "editor.export_ok",
+                    &*name,
+                    stamp::STAMP_PATH
+                ),
+            ),
+
+            Ok(unmapped) => {
+                let unmapped: Vec<&str> = unmapped.iter().map(|name| &**name).collect();
+                self.notify(
+                    NotifyLevel::Warning,
+                    &tr!(
+                        self.ui_context.strings,
+                        "editor.export_unmapped",
+                        &*name,
+                        unmapped.join(", ")
+                    ),
+                );
+            }
+
+            Err(e) => {
+                warn!("Failed to export {}: {}", &*name, e);
+                self.notify(
+                    NotifyLevel::Error,
+                    &tr!(self.ui_context.strings, "editor.export_failed", e),
+                );
+            }
+        }
+    }
+
+    /// Writes the model being edited next to the editor's files as a MagicaVoxel model, colored
+    /// by its voxels.
+    fn export_vox(&mut self) {
+        let (name, model) = match self.model_to_export() {
+            Some(named_model) => named_model,
+            None => {
+                self.notify(
+                    NotifyLevel::Warning,
+                    &tr!(self.ui_context.strings, "editor.export_no_model"),
+                );
+                return;
+            }
+        };
+
+        let path = format!("{}.vox", &*name);
+        let colors = vox::model_colors(&model, &self.current_voxels());
+
+        match vox::export(colors.view(), &path) {
+            Ok(()) => self.notify(
+                NotifyLevel::Success,
+                &tr!(self.ui_context.strings, "editor.export_ok", &*name, path),
+            ),
+            Err(e) => {
+                warn!("Failed to export {}: {}", &*name, e);
+                self.notify(
+                    NotifyLevel::Error,
+                    &tr!(self.ui_context.strings, "editor.export_failed", e),
+                );
+            }
+        }
+    }
+
+    /// Writes the open model to `<name>.png` as a sheet of its slices, drawn at the current zoom.
+    fn export_sheet(&mut self) {
+        let (name, model) = match self.model_to_export() {
+            Some(named_model) => named_model,
+            None => {
+                self.notify(
+                    NotifyLevel::Warning,
+                    &tr!(self.ui_context.strings, "editor.export_no_model"),
+                );
+                return;
+            }
+        };
+
+        let path = format!("{}.png", &*name);
+        let scale = self.ui_context.batch.scaling().round().max(1.0) as u32;
+        let glyphs = self.ui_context.batch.font().glyphs();
+
+        match sheet::export(&model, &self.current_voxels(), glyphs, scale, &path) {
+            Ok(()) => self.notify(
+                NotifyLevel::Success,
+                &tr!(self.ui_context.strings, "editor.export_ok", &*name, path),
+            ),
+            Err(e) => {
+                warn!("Failed to export {}: {}", &*name, e);
+                self.notify(
+                    NotifyLevel::Error,
+                    &tr!(self.ui_context.strings, "editor.export_failed", e),
+                );
+            }
+        }
+    }
+
+    /// The open model and its name, if it has one.
+    fn model_to_export(&self) -> Option<(IStr, Model)> {
+        let model_mode = self.model_mode()?;
+        let name = model_mode.name.clone()?;
+        Some((name, model_mode.current_model()))
+    }
+
+    /// Asks for the path of a MagicaVoxel model to import. The answer is carried out by
+    /// `update`.
+    fn open_vox_import_dialog(&self) {
+        let choose = |import| {
+            let vox_import_choice = Rc::clone(&self.vox_import_choice);
+            let dialogs = self.dialogs.clone();
+            move || {
+                vox_import_choice.set(import);
+                dialogs.close();
+            }
+        };
+
+        let submit = choose(true);
+
+        self.dialogs.open(dialog(
+            "Import .vox",
+            vec![
+                FlexElement::fixed(Box::new(KataText::from_str("Path to a .vox model:"))),
+                FlexElement::fixed(Box::new(
+                    TextInput::new(self.import_path.clone())
+                        .with_focus()
+                        .with_on_submit(move |_| submit()),
+                )),
+            ],
+            vec![
+                Button::new("Import", choose(true)),
+                Button::new("Cancel", choose(false)),
+            ],
+        ));
+    }
+
+    /// Adds the MagicaVoxel model at the path typed into the import dialog as a new model, and
+    /// opens it. Its colors become voxels, reusing any that are already there.
+    fn import_vox(&mut self) {
+        let path = self.import_path.get();
+
+        let (model, imported) = match vox::import(&path) {
+            Ok(imported) => imported,
+            Err(e) => {
+                warn!("Failed to import {}: {}", path, e);
+                self.notify(
+                    NotifyLevel::Error,
+                    &tr!(self.ui_context.strings, "editor.import_failed", e),
+                );
+                return;
+            }
+        };
+
+        let voxel_mode = match self.voxel_mode() {
+            Some(voxel_mode) => voxel_mode,
+            None => return,
+        };
+
+        // Voxels with a name that's taken by a different voxel get a new one
+        let mut renamed = BTreeMap::new();
+        let mut added = 0;
+        for (name, voxel) in imported {
+            let existing = voxel_mode
+                .actions
+                .entries
+                .borrow()
+                .iter()
+                .find(|(other, _)| *other == name)
+                .map(|(_, other)| *other == voxel);
+
+            let new_name = match existing {
+                Some(true) => continue,
+                Some(false) => unique_name(&voxel_mode.actions.entries.borrow(), &name),
+                None => name.clone(),
+            };
+
+            let index = voxel_mode.actions.entries.borrow().len();
+            voxel_mode.actions.insert(index, new_name.clone(), voxel);
+            renamed.insert(name, new_name);
+            added += 1;
+        }
+
+        let model = Model {
+            voxels: model.voxels.map(|name| {
+                name.as_ref()
+                    .map(|name| renamed.get(name).unwrap_or(name).clone())
+            }),
+            ..model
+        };
+
+        let stem = Path::new(&path).file_stem().map_or_else(
+            || String::from("vox"),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        let models: Vec<_> = self.current_models().into_iter().collect();
+        let name = unique_name(&models, &stem);
+
+        self.edited_models.insert(name.clone(), model);
+        self.open_model(&name, 0);
+        self.notify(
+            NotifyLevel::Success,
+            &tr!(
+                self.ui_context.strings,
+                "editor.vox_import_ok",
+                &*name,
+                added
+            ),
+        );
+    }
+
+    /// Asks for the path of an image and what to import it as. The answer is carried out by
+    /// `update`.
+    fn open_import_dialog(&self) {
+        let choose = |target| {
+            let import_choice = Rc::clone(&self.import_choice);
+            let dialogs = self.dialogs.clone();
+            move || {
+                import_choice.set(target);
+                dialogs.close();
+            }
+        };
+
+        let submit = choose(Some(ImportTarget::Slice));
+
+        self.dialogs.open(dialog(
+            "Import image",
+            vec![
+                FlexElement::fixed(Box::new(KataText::from_str("Path to a PNG:"))),
+                FlexElement::fixed(Box::new(
+                    TextInput::new(self.import_path.clone())
+                        .with_focus()
+                        .with_on_submit(move |_| submit()),
+                )),
+            ],
+            vec![
+                Button::new("As slice", choose(Some(ImportTarget::Slice))),
+                Button::new("As text art", choose(Some(ImportTarget::Text))),
+                Button::new("Cancel", choose(None)),
+            ],
+        ));
+    }
+
+    /// Loads the image at the path typed into the import dialog and turns it into `target`.
+    fn import_image(&mut self, target: ImportTarget) {
+        let path = self.import_path.get();
+
+        let image = match load_image(&path) {
+            Ok(image) => image,
+            Err(e) => {
+                warn!("Failed to import {}: {}", path, e);
+                self.notify(
+                    NotifyLevel::Error,
+                    &tr!(
+                        self.ui_context.strings,
+                        "editor.image_import_failed",
+                        path,
+                        e
+                    ),
+                );
+                return;
+            }
+        };
+
+        match target {
+            ImportTarget::Slice => self.import_slice(&image),
+            ImportTarget::Text => self.import_text(&path, &image),
+        }
+    }
+
+    /// Fills the cells under the opaque pixels of `image` on the active slice with the brush.
+    /// Whatever doesn't fit in the model is left out.
+    fn import_slice(&mut self, image: &RgbaImage) {
+        let strings = &self.ui_context.strings;
+        let (level, message) = match self.model_mode() {
+            Some(ModelMode { name: None, .. }) | None => {
+                (NotifyLevel::Warning, tr!(strings, "editor.import_no_model"))
+            }
+
+            Some(ModelMode { brush, .. }) if brush.get().is_none() => {
+                (NotifyLevel::Warning, tr!(strings, "editor.import_no_brush"))
+            }
+
+            Some(model_mode) => {
+                let changed = model_mode.import_slice(image);
+                let (w, h, _) = model_mode.model.get().dim();
+
+                if image.width() as usize > w || image.height() as usize > h {
+                    (
+                        NotifyLevel::Warning,
+                        tr!(
+                            strings,
+                            "editor.slice_import_cropped",
+                            changed,
+                            image.width(),
+                            image.height(),
+                            w,
+                            h
+                        ),
+                    )
+                } else {
+                    (
+                        NotifyLevel::Success,
+                        tr!(strings, "editor.slice_import_ok", changed),
+                    )
+                }
+            }
+        };
+
+        self.notify(level, &message);
+    }
+
+    /// Turns `image` into half-block art, writes it next to the image at `path` and shows it.
+    fn import_text(&mut self, path: &str, image: &RgbaImage) {
+        let rows = image_to_text(image);
+        let art_path = Path::new(path).with_extension("json");
+
+        if let Err(e) = write_json(&art_path, &rows) {
+            warn!("Failed to write {}: {}", art_path.display(), e);
+            self.notify(
+                NotifyLevel::Error,
+                &tr!(self.ui_context.strings, "editor.art_write_failed", e),
+            );
+            return;
+        }
+
+        let dialogs = self.dialogs.clone();
+        self.dialogs.open(dialog(
+            "Imported art",
+            vec![FlexElement::fixed(Box::new(KataText::from_rows(rows)))],
+            vec![Button::new("OK", move || dialogs.close())],
+        ));
+
+        self.notify(
+            NotifyLevel::Success,
+            &tr!(
+                self.ui_context.strings,
+                "editor.art_write_ok",
+                art_path.display()
+            ),
+        );
+    }
+
+    fn notify(&mut self, level: NotifyLevel, message: &str) {
+        self.notifications.notify(level, message);
+    }
+
+    /// Says which files couldn't be read and asks whether to move them out of the way before
+    /// saving replaces them. The answer is carried out by `update`.
+    fn open_corrupt_files_dialog(&self) {
+        let choose = |choice| {
+            let backup_choice = Rc::clone(&self.backup_choice);
+            let dialogs = self.dialogs.clone();
+            move || {
+                backup_choice.set(Some(choice));
+                dialogs.close();
+            }
+        };
+
+        let mut body: Vec<FlexElement> = vec![FlexElement::fixed(Box::new(KataText::from_str(
+            "These files couldn't be read and were left empty. Saving will overwrite them.",
+        )))];
+        body.extend(self.corrupt_files.iter().map(|(path, error)| {
+            FlexElement::fixed(Box::new(KataText::from_str(&format!(
+                "{}: {}",
+                path.display(),
+                error
+            ))))
+        }));
+
+        self.dialogs.open(dialog(
+            "Unreadable files",
+            body,
+            vec![
+                Button::new("Back up", choose(true)),
+                Button::new("Overwrite", choose(false)),
+            ],
+        ));
+    }
+
+    /// Moves the files that couldn't be read out of the way, or forgets about them so that
+    /// saving can overwrite them.
+    fn resolve_corrupt_files(&mut self, back_up: bool) {
+        if !back_up {
+            self.corrupt_files.clear();
+            return;
+        }
+
+        let mut failed = Vec::new();
+        for (path, error) in self.corrupt_files.drain(..) {
+            match back_up_file(&path) {
+                Ok(backup_path) => {
+                    info!("Moved {} to {}", path.display(), backup_path.display())
+                }
+                Err(e) => {
+                    warn!("{}", e);
+                    failed.push((path, error));
+                }
+            }
+        }
+
+        if failed.is_empty() {
+            self.notify(
+                NotifyLevel::Success,
+                &tr!(self.ui_context.strings, "editor.backup_ok"),
+            );
+        } else {
+            self.notify(
+                NotifyLevel::Error,
+                &tr!(
+                    self.ui_context.strings,
+                    "editor.backup_failed",
+                    failed.len()
+                ),
+            );
+            self.corrupt_files = failed;
+        }
+    }
+
+    /// Asks whether to save before quitting. The answer is carried out by `update`.
+    fn open_quit_dialog(&self) {
+        let choose = |choice| {
+            let quit_choice = Rc::clone(&self.quit_choice);
+            let dialogs = self.dialogs.clone();
+            move || {
+                quit_choice.set(choice);
+                dialogs.close();
+            }
+        };
+
+        self.dialogs.open(dialog(
+            "Unsaved changes",
+            vec![FlexElement::fixed(Box::new(KataText::from_str(
+                "Save your changes before quitting?",
+            )))],
+            vec![
+                Button::new("Save", choose(Some(QuitChoice::Save))),
+                Button::new("Discard", choose(Some(QuitChoice::Discard))),
+                Button::new("Cancel", choose(None)),
+            ],
+        ));
+    }
+
+    /// The mode, whether there are unsaved changes and the last message, cut to `width` cells.
+    fn status_text(&self, width: u32, now: Instant) -> KataText {
+        let mut text = KataText::from_voxels(Vec::new());
+
+        if let Some(mode) = self.tabs.inner().active_element() {
+            text.push_span(
+                &format!(" {} ", mode.name().title()),
+                color::BLACK,
+                Some(color::GRAY),
+            );
+
+            match mode {
+                EditorMode::Model(model_mode) => {
+                    text.push_span(&format!(" {}", model_mode.status()), color::WHITE, None);
+                }
+                EditorMode::Weapon(weapon_mode) => {
+                    text.push_span(&format!(" {}", weapon_mode.status()), color::WHITE, None);
+                }
+                _ => {}
+            }
+        }
+
+        if self.is_dirty() {
+            text.push_span(
+                &format!(" {}", tr!(self.ui_context.strings, "editor.status_unsaved")),
+                color::YELLOW,
+                None,
+            );
+        } else if let Some(last_saved) = self.last_saved {
+            text.push_span(
+                &format!(
+                    " {}",
+                    tr!(
+                        self.ui_context.strings,
+                        "editor.status_saved_at",
+                        last_saved.format("%H:%M")
+                    )
+                ),
+                color::GRAY,
+                None,
+            );
+        }
+
+        if let Some((message, color)) = self.notifications.visible(now) {
+            text.push_span("  ", color::WHITE, None);
+            text.push_span(message, color, None);
+        }
+
+        truncate_line(&mut text.voxels, width as usize);
+        text
+    }
+
+    fn toggle_help(&mut self) {
+        self.help = match self.help {
+            Some(_) => None,
+            None => Some(help_panel()),
+        };
+    }
+
+    /// Handles the shortcuts that work in any mode, then those of the active mode.
+    fn handle_shortcut(&mut self, ctx: &Context, keycode: KeyCode, mods: KeyMods) -> bool {
+        let mode = match keycode {
+            KeyCode::F1 => EditorModeName::Voxel,
+            KeyCode::F2 => EditorModeName::Model,
+            KeyCode::F3 => EditorModeName::Palette,
+            KeyCode::F4 => EditorModeName::Remap,
+            KeyCode::F5 => EditorModeName::Weapon,
+
+            KeyCode::Equals | KeyCode::Plus | KeyCode::NumpadAdd => {
+                self.zoom(ctx, 1);
+                return true;
+            }
+
+            KeyCode::Minus | KeyCode::NumpadSubtract => {
+                self.zoom(ctx, -1);
+                return true;
+            }
+
+            KeyCode::C if mods.contains(KeyMods::CTRL) => {
+                self.copy(mods.contains(KeyMods::SHIFT));
+                return true;
+            }
+
+            KeyCode::V if mods.contains(KeyMods::CTRL) => {
+                self.paste();
+                return true;
+            }
+
+            _ => {
+                return match self.tabs.inner_mut().active_element_mut() {
+                    Some(EditorMode::Voxel(voxel_mode)) => {
+                        voxel_mode.handle_shortcut(keycode, mods)
+                    }
+                    Some(EditorMode::Model(model_mode)) => {
+                        model_mode.handle_shortcut(keycode, mods)
+                    }
+                    Some(EditorMode::Remap(remap_mode)) => {
+                        remap_mode.handle_shortcut(keycode, mods)
+                    }
+                    Some(EditorMode::Weapon(weapon_mode)) => {
+                        weapon_mode.handle_shortcut(keycode, mods)
+                    }
+                    Some(EditorMode::Palette(_)) | None => false,
+                }
+            }
+        };
+
+        self.tabs.inner_mut().select(mode.index());
+        true
+    }
+
+    /// Copies the active face of the selected voxel, or all of it if `whole` is set. In model mode
+    /// the selected cells of the slice are copied instead.
+    fn copy(&mut self, whole: bool) {
+        let copied = match self.tabs.inner().active_element() {
+            Some(EditorMode::Voxel(voxel_mode)) if whole => {
+                voxel_mode.selected_voxel().map(Clipboard::Voxel)
+            }
+            Some(EditorMode::Voxel(voxel_mode)) => Some(Clipboard::Face(voxel_mode.active_face())),
+            Some(EditorMode::Model(model_mode)) => {
+                model_mode.selection.copy().map(Clipboard::Cells)
+            }
+            _ => None,
+        };
+
+        match copied {
+            Some(copied) => self.clipboard = Some(copied),
+            None => self.notify(
+                NotifyLevel::Warning,
+                &tr!(self.ui_context.strings, "editor.copy_nothing"),
+            ),
+        }
+    }
+
+    /// Pastes the clipboard onto the active face, the selected voxel or the model slice, depending
+    /// on what it holds.
+    fn paste(&mut self) {
+        let pasted = match (self.tabs.inner().active_element(), &self.clipboard) {
+            (Some(EditorMode::Voxel(voxel_mode)), Some(Clipboard::Face(face))) => {
+                voxel_mode.paste_face(face.clone())
+            }
+            (Some(EditorMode::Voxel(voxel_mode)), Some(Clipboard::Voxel(voxel))) => {
+                voxel_mode.paste_voxel(voxel.clone())
+            }
+            (Some(EditorMode::Model(model_mode)), Some(Clipboard::Cells(cells))) => {
+                model_mode.selection.paste(cells)
+            }
+            _ => false,
+        };
+
+        if !pasted {
+            let message = match &self.clipboard {
+                None => tr!(self.ui_context.strings, "editor.paste_empty"),
+                Some(_) => tr!(self.ui_context.strings, "editor.paste_mismatch"),
+            };
+            self.notify(NotifyLevel::Warning, &message);
+        }
+    }
+
+    /// Zooms the UI in by `steps`, or out if it's negative, and remembers the new zoom level.
+    fn zoom(&mut self, ctx: &Context, steps: i32) {
+        let old_tile_size = self.ui_context.batch.tile_width();
+        let scaling = step_scaling(self.ui_context.batch.scaling(), steps);
+
+        // Don't zoom in so far that not even one cell fits on the screen
+        let screen_size = graphics::drawable_size(ctx);
+        let font = self.ui_context.batch.font();
+        let scaling = scaling.min(
+            (screen_size.0 / font.char_width() as f32)
+                .min(screen_size.1 / font.char_height() as f32)
+                .max(MIN_SCALING),
+        );
+
+        if scaling == self.ui_context.batch.scaling() {
+            return;
+        }
+
+        self.ui_context.batch.set_scaling(scaling);
+        self.ui_context.relayout = true;
+
+        // Keep drags going from the same place on screen
+        let ratio = old_tile_size / self.ui_context.batch.tile_width();
+        let rescale = |p: mint::Point2<i32>| {
+            mint::Point2::from([(p.x as f32 * ratio) as i32, (p.y as f32 * ratio) as i32])
+        };
+
+        for held in self.held_buttons.values_mut() {
+            held.start_pos = rescale(held.start_pos);
+            held.pos = rescale(held.pos);
+        }
+
+        // The zoom level is kept right away, without saving any other edits to the settings
+        self.settings.scaling = scaling;
+        if self
+            .corrupt_files
+            .iter()
+            .any(|(path, _)| path == Path::new(".settings.json"))
+        {
+            debug!("Not saving the zoom level over the unreadable settings");
+        } else if let Err(e) = write_json(".settings.json", &self.settings) {
+            warn!("Failed to save settings: {}", e);
+        }
+    }
+
     fn layout_size(&self, ctx: &Context) -> Size {
         let screen_size = graphics::drawable_size(ctx);
         Size::new(
-            (screen_size.0 / self.ui_context.batch.tile_width()) as u32,
-            (screen_size.1 / self.ui_context.batch.tile_height()) as u32,
+            ((screen_size.0 / self.ui_context.batch.tile_width()) as u32).max(1),
+            ((screen_size.1 / self.ui_context.batch.tile_height()) as u32).max(1),
+        )
+    }
+
+    /// Where the modes go: everything above the status bar.
+    fn layout_rect(&self, ctx: &Context) -> SRect {
+        let layout_size = self.layout_size(ctx);
+        SRect::new(
+            0,
+            0,
+            layout_size.width,
+            layout_size.height.saturating_sub(1).max(1),
         )
     }
 
-    fn layout_rect(&self, ctx: &Context) -> IRect {
+    fn status_rect(&self, ctx: &Context) -> SRect {
         let layout_size = self.layout_size(ctx);
-        IRect::new(0, 0, layout_size.width, layout_size.height)
+        SRect::new(0, layout_size.height as i32 - 1, layout_size.width, 1)
+    }
+
+    /// Where the save button goes, which is the right end of the status bar.
+    fn save_button_rect(&mut self, ctx: &Context) -> SRect {
+        let status_rect = self.status_rect(ctx);
+        let size = self
+            .save_button
+            .layout(BoxConstraints::new(Size::ZERO, status_rect.size()));
+
+        SRect::new(
+            status_rect.right() - size.width as i32,
+            status_rect.y,
+            size.width,
+            size.height,
+        )
     }
 }
 
 impl EventHandler<ggez::GameError> for Editor {
     fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) {
+        if keyboard::active_mods(ctx).contains(KeyMods::CTRL) {
+            if y != 0.0 {
+                self.zoom(ctx, y.signum() as i32);
+            }
+
+            return;
+        }
+
         self.mouse_wheel_scroll += y;
 
         while self.mouse_wheel_scroll >= 1.0 {
@@ -93,8 +1191,7 @@ impl EventHandler<ggez::GameError> for Editor {
             };
             let layout_rect = self.layout_rect(ctx);
             let _ = self
-                .mode
-                .layout()
+                .tabs
                 .handle_event(&mut self.ui_context, event, layout_rect);
         }
 
@@ -106,8 +1203,7 @@ impl EventHandler<ggez::GameError> for Editor {
             };
             let layout_rect = self.layout_rect(ctx);
             let _ = self
-                .mode
-                .layout()
+                .tabs
                 .handle_event(&mut self.ui_context, event, layout_rect);
         }
     }
@@ -119,7 +1215,7 @@ impl EventHandler<ggez::GameError> for Editor {
         _x: f32,
         _y: f32,
     ) {
-        let pos = dbg!(self.ui_context.mouse_pos(ctx));
+        let pos = self.ui_context.mouse_pos(ctx);
         self.held_buttons.insert(
             button,
             HeldButton {
@@ -128,8 +1224,18 @@ impl EventHandler<ggez::GameError> for Editor {
             },
         );
 
+        let save_button_rect = self.save_button_rect(ctx);
+        let _ = self.save_button.handle_event(
+            &mut self.ui_context,
+            Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDown { button },
+            },
+            save_button_rect,
+        );
+
         let layout_rect = self.layout_rect(ctx);
-        let _ = self.mode.layout().handle_event(
+        let _ = self.tabs.handle_event(
             &mut self.ui_context,
             Event::Mouse {
                 pos,
@@ -137,6 +1243,20 @@ impl EventHandler<ggez::GameError> for Editor {
             },
             layout_rect,
         );
+
+        if self
+            .clicks
+            .click(&self.ui_context.config, button, pos, Instant::now())
+        {
+            let _ = self.tabs.handle_event(
+                &mut self.ui_context,
+                Event::Mouse {
+                    pos,
+                    e: MouseEvent::ButtonDoubleClick { button },
+                },
+                layout_rect,
+            );
+        }
     }
 
     fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, _x: f32, _y: f32) {
@@ -144,7 +1264,7 @@ impl EventHandler<ggez::GameError> for Editor {
 
         let pos = self.ui_context.mouse_pos(ctx);
         let layout_rect = self.layout_rect(ctx);
-        let _ = self.mode.layout().handle_event(
+        let _ = self.tabs.handle_event(
             &mut self.ui_context,
             Event::Mouse {
                 pos,
@@ -158,11 +1278,20 @@ impl EventHandler<ggez::GameError> for Editor {
         let pos = self.ui_context.mouse_pos(ctx);
         let layout_rect = self.layout_rect(ctx);
 
+        let _ = self.tabs.handle_event(
+            &mut self.ui_context,
+            Event::Mouse {
+                pos,
+                e: MouseEvent::Move,
+            },
+            layout_rect,
+        );
+
         for (&button, held) in self.held_buttons.iter_mut() {
             if pos != held.pos {
                 held.pos = pos;
 
-                let _ = self.mode.layout().handle_event(
+                let _ = self.tabs.handle_event(
                     &mut self.ui_context,
                     Event::Mouse {
                         pos,
@@ -177,20 +1306,230 @@ impl EventHandler<ggez::GameError> for Editor {
         }
     }
 
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        if keycode == KeyCode::S && keymods.contains(KeyMods::CTRL) {
+            self.save(false);
+            return;
+        }
+
+        let layout_rect = self.layout_rect(ctx);
+        let result = self.tabs.handle_event(
+            &mut self.ui_context,
+            Event::KeyDown {
+                keycode,
+                mods: keymods,
+            },
+            layout_rect,
+        );
+
+        // Shortcuts only get keys nothing else used, e.g. to close a dialog
+        if result.is_err() {
+            return;
+        }
+
+        if keycode == KeyCode::Escape {
+            if self.help.is_some() {
+                self.help = None;
+            } else if !self.quit_event(ctx) {
+                event::quit(ctx);
+            }
+        } else if !self.ui_context.typing {
+            self.handle_shortcut(ctx, keycode, keymods);
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        let layout_rect = self.layout_rect(ctx);
+        let result = self.tabs.handle_event(
+            &mut self.ui_context,
+            Event::Text { ch: character },
+            layout_rect,
+        );
+
+        if character == '?' && result.is_ok() && !self.ui_context.typing {
+            self.toggle_help();
+        }
+    }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if self.is_dirty() {
+            self.open_quit_dialog();
+            true
+        } else {
+            false
+        }
+    }
+
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if self.ui_context.relayout {
+        let layout_size = self.layout_rect(ctx).size();
+        if self
+            .ui_context
+            .relayout_if_needed(&mut self.tabs, layout_size)
+        {
             debug!("Relayout");
-            self.ui_context.relayout = false;
-            let layout_size = self.layout_size(ctx);
-            self.mode
-                .layout()
-                .layout(BoxConstraints::exact(layout_size));
         }
 
-        match &mut self.mode {
-            EditorMode::Voxel(voxel_mode) => {}
+        match self.quit_choice.take() {
+            Some(QuitChoice::Save) => self.save(true),
+            Some(QuitChoice::Discard) => event::quit(ctx),
+            None => {}
+        }
+
+        if self.save_clicked.replace(false) {
+            self.save(false);
+        }
+
+        self.poll_save(ctx);
+        self.can_save.set(self.saving.is_none() && self.is_dirty());
+
+        let brush = self.voxel_mode().and_then(VoxelMode::selected_name);
+        let mut export = false;
+        let mut vox_export = false;
+        let mut sheet_export = false;
+        let mut vox_import = false;
+        let mut import = false;
+        let mut find_usages = false;
+        let mut searched = None;
+        let mut open_usage = None;
+        let mut remapped = None;
+
+        match self.tabs.inner_mut().active_element_mut() {
+            Some(EditorMode::Voxel(voxel_mode)) => {
+                if let Some(name) = voxel_mode.selected_name() {
+                    self.recent.voxel = Some(name);
+                }
+
+                self.recent.voxels = voxel_mode.recent_names();
+                self.recent.face = voxel_mode.actions.active_face.get();
+
+                find_usages = voxel_mode.find_usages_requested.replace(false);
+                searched = voxel_mode.poll_usages();
+                open_usage = voxel_mode.open_usage.take();
+            }
+
+            Some(EditorMode::Model(model_mode)) => {
+                if let Some(name) = &model_mode.name {
+                    self.recent.model = Some(name.clone());
+
+                    if self.recent.models.first() != Some(name) {
+                        self.recent.models.retain(|other| other != name);
+                        self.recent.models.insert(0, name.clone());
+                        self.recent.models.truncate(MAX_RECENT_MODELS);
+                    }
+                }
+
+                self.recent.tool = model_mode.tool.get();
+                self.recent.slice = model_mode.slice.get();
+
+                if model_mode.brush.get() != brush {
+                    model_mode.brush.set(brush);
+                }
+
+                export = model_mode.export_requested.replace(false);
+                vox_export = model_mode.vox_export_requested.replace(false);
+                sheet_export = model_mode.sheet_export_requested.replace(false);
+                import = model_mode.import_requested.replace(false);
+                vox_import = model_mode.vox_import_requested.replace(false);
+            }
+
+            Some(EditorMode::Palette(palette_mode)) => {
+                if let Some(name) = palette_mode.actions.selected_name() {
+                    self.recent.palette = Some(name);
+                }
+            }
+
+            Some(EditorMode::Remap(remap_mode)) => {
+                remapped = remap_mode.actions.message.take();
+            }
+
+            Some(EditorMode::Weapon(weapon_mode)) => {
+                if let Some(name) = weapon_mode.actions.selected_name() {
+                    self.recent.weapon = Some(name);
+                }
+
+                self.recent.tool = weapon_mode.tool.get();
+
+                if weapon_mode.brush.get() != brush {
+                    weapon_mode.brush.set(brush);
+                }
+
+                if weapon_mode.actions.switched.replace(false) {
+                    self.ui_context.relayout = true;
+                }
+            }
+
+            None => {}
+        }
+
+        if let Some(mode) = self.tabs.inner().active_element() {
+            self.recent.mode = mode.name();
+        }
+
+        if let Some((level, message)) = remapped {
+            self.notify(level, &message);
+        }
+
+        if export {
+            self.export_stamp();
+        }
+
+        if vox_export {
+            self.export_vox();
+        }
+
+        if sheet_export {
+            self.export_sheet();
+        }
+
+        if import {
+            self.open_import_dialog();
+        }
+
+        if vox_import {
+            self.open_vox_import_dialog();
+        }
+
+        if find_usages {
+            self.find_usages();
+        }
+
+        if let Some((name, found)) = searched {
+            match found {
+                0 => self.notify(
+                    NotifyLevel::Warning,
+                    &tr!(self.ui_context.strings, "editor.usages_none", &*name),
+                ),
+                1 => self.notify(
+                    NotifyLevel::Success,
+                    &tr!(self.ui_context.strings, "editor.usages_one", &*name),
+                ),
+                _ => self.notify(
+                    NotifyLevel::Success,
+                    &tr!(self.ui_context.strings, "editor.usages_many", &*name, found),
+                ),
+            }
+        }
+
+        if let Some(usage) = open_usage {
+            self.open_model(&usage.model, usage.first.2);
+        }
+
+        if let Some(target) = self.import_choice.take() {
+            self.import_image(target);
+        }
+
+        if self.vox_import_choice.replace(false) {
+            self.import_vox();
+        }
 
-            EditorMode::Model(model_mode) => {}
+        if let Some(back_up) = self.backup_choice.take() {
+            self.resolve_corrupt_files(back_up);
         }
 
         Ok(())
@@ -200,17 +1539,62 @@ impl EventHandler<ggez::GameError> for Editor {
         graphics::clear(ctx, graphics::Color::BLACK);
 
         self.ui_context.batch.clear();
+        self.ui_context.frame += 1;
+        self.ui_context.typing = false;
 
         let layout_rect = self.layout_rect(ctx);
         let _ = self
-            .mode
-            .layout()
+            .tabs
             .handle_event(&mut self.ui_context, Event::Draw, layout_rect);
+        self.ui_context
+            .draw_overlays(layout_rect.clip_to_unsigned());
+
+        let status_rect = self.status_rect(ctx);
+        let save_button_rect = self.save_button_rect(ctx);
+        let status_rect = SRect::new(
+            status_rect.x,
+            status_rect.y,
+            (save_button_rect.x - status_rect.x).max(0) as u32,
+            status_rect.h,
+        );
+        let mut status = self.status_text(status_rect.w, Instant::now());
+        status.layout(BoxConstraints::exact(status_rect.size()));
+        let _ = status.handle_event(&mut self.ui_context, Event::Draw, status_rect);
+        let _ = self
+            .save_button
+            .handle_event(&mut self.ui_context, Event::Draw, save_button_rect);
+
+        if let Some(saving) = &mut self.saving {
+            let width = status_rect.w.min(SAVE_PROGRESS_WIDTH);
+            let bar_rect = SRect::new(
+                status_rect.right() - width as i32,
+                status_rect.y,
+                width,
+                status_rect.h,
+            );
+            saving.bar.layout(BoxConstraints::exact(bar_rect.size()));
+            let _ = saving
+                .bar
+                .handle_event(&mut self.ui_context, Event::Draw, bar_rect);
+        }
 
-        match &mut self.mode {
-            EditorMode::Voxel(voxel_mode) => {}
+        if let Some(help) = &mut self.help {
+            help.layout(BoxConstraints::exact(layout_rect.size()));
+            let _ = help.handle_event(&mut self.ui_context, Event::Draw, layout_rect);
+        }
 
-            EditorMode::Model(model_mode) => {}
+        match self.tabs.inner_mut().active_element_mut() {
+            Some(EditorMode::Voxel(voxel_mode)) => {}
+
+            Some(EditorMode::Model(model_mode)) => {}
+
+            Some(EditorMode::Palette(_)) => {}
+
+            Some(EditorMode::Remap(_)) => {}
+
+            Some(EditorMode::Weapon(_)) => {}
+
+            None => {}
         }
 
         graphics::draw(ctx, &self.ui_context.batch, DrawParam::default())?;
@@ -224,10 +1608,122 @@ impl EventHandler<ggez::GameError> for Editor {
     }
 }
 
+/// How a notification is colored in the status bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum NotifyLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl NotifyLevel {
+    fn color(self) -> Color {
+        match self {
+            Self::Info => color::WHITE,
+            Self::Success => color::GREEN,
+            Self::Warning => color::YELLOW,
+            Self::Error => color::RED,
+        }
+    }
+}
+
+/// How long a notification stays in the status bar, including fading out.
+const NOTIFICATION_DURATION: Duration = Duration::from_secs(4);
+/// How long a notification takes to fade out at the end.
+const NOTIFICATION_FADE: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+struct Notification {
+    level: NotifyLevel,
+    message: String,
+    posted: Instant,
+}
+
+/// The message shown in the status bar, which replaces the previous one and expires on its own.
+#[derive(Clone, Debug, Default)]
+struct Notifications {
+    current: Option<Notification>,
+}
+
+impl Notifications {
+    fn notify(&mut self, level: NotifyLevel, message: &str) {
+        self.notify_at(level, message, Instant::now());
+    }
+
+    fn notify_at(&mut self, level: NotifyLevel, message: &str, now: Instant) {
+        self.current = Some(Notification {
+            level,
+            message: String::from(message),
+            posted: now,
+        });
+    }
+
+    /// The message and its color at `now`, dimmed while it fades out, or `None` once it's gone.
+    fn visible(&self, now: Instant) -> Option<(&str, Color)> {
+        let notification = self.current.as_ref()?;
+        let remaining = NOTIFICATION_DURATION
+            .checked_sub(now.saturating_duration_since(notification.posted))
+            .filter(|remaining| *remaining > Duration::from_secs(0))?;
+
+        let fade = (remaining.as_secs_f32() / NOTIFICATION_FADE.as_secs_f32()).min(1.0);
+        Some((
+            &notification.message,
+            notification.level.color().dimmed(fade),
+        ))
+    }
+}
+
+/// Dotted row of the font, the closest it has to an ellipsis.
+const ELLIPSIS: u16 = 0x2D2;
+
+/// Cuts `voxels` down to `width`, ending with an ellipsis if anything was left out.
+fn truncate_line(voxels: &mut Vec<Voxel2>, width: usize) {
+    if voxels.len() <= width {
+        return;
+    }
+
+    voxels.truncate(width);
+    if let Some(last) = voxels.last_mut() {
+        last.char_offset = ELLIPSIS;
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 struct HeldButton {
-    start_pos: mint::Point2<u32>,
-    pos: mint::Point2<u32>,
+    start_pos: mint::Point2<i32>,
+    pos: mint::Point2<i32>,
+}
+
+/// Editor preferences, which are kept in `.settings.json` next to the voxels and models.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+struct Settings {
+    /// Colors added to the color picker on top of the named ones.
+    palette: Vec<Color>,
+    /// How many screen pixels each font pixel takes up.
+    scaling: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            palette: Vec::new(),
+            scaling: DEFAULT_SCALING,
+        }
+    }
+}
+
+const DEFAULT_SCALING: f32 = 4.0;
+const MIN_SCALING: f32 = 1.0;
+const MAX_SCALING: f32 = 8.0;
+const SCALING_STEP: f32 = 0.5;
+
+/// Zooms `scaling` in by `steps`, or out if it's negative, staying on a whole step within the
+/// allowed range.
+fn step_scaling(scaling: f32, steps: i32) -> f32 {
+    let scaling = (scaling / SCALING_STEP).round() * SCALING_STEP + steps as f32 * SCALING_STEP;
+    scaling.max(MIN_SCALING).min(MAX_SCALING)
 }
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
@@ -235,31 +1731,46 @@ struct Recent {
     voxel: Option<IStr>,
     model: Option<IStr>,
     mode: EditorModeName,
+    #[serde(default)]
+    tool: ModelTool,
+    /// The last voxels selected, most recent first, as shown by the recent strip.
+    #[serde(default)]
+    voxels: Vec<IStr>,
+    /// The last models opened, most recent first.
+    #[serde(default)]
+    models: Vec<IStr>,
+    /// The face being edited in voxel mode.
+    #[serde(default)]
+    face: VoxelFace,
+    /// The slice being looked at in `model`.
+    #[serde(default)]
+    slice: i32,
+    /// The entry last selected in palette mode.
+    #[serde(default)]
+    palette: Option<IStr>,
+    /// The model last selected in weapon mode.
+    #[serde(default)]
+    weapon: Option<IStr>,
 }
 
-fn try_load<T, P>(path: P) -> Fallible<T>
-where
-    T: DeserializeOwned + Default,
-    P: AsRef<Path>,
-{
-    let path = path.as_ref();
-    if path.is_file() {
-        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
-    } else {
-        Ok(T::default())
-    }
-}
+const MAX_RECENT_MODELS: usize = 10;
 
 enum EditorMode {
     Voxel(VoxelMode),
     Model(ModelMode),
+    Palette(PaletteMode),
+    Remap(RemapMode),
+    Weapon(WeaponMode),
 }
 
 impl EditorMode {
-    fn layout(&mut self) -> &mut dyn Element {
+    fn element(&mut self) -> &mut dyn Element {
         match self {
             EditorMode::Voxel(v) => &mut v.layout,
             EditorMode::Model(m) => &mut m.layout,
+            EditorMode::Palette(p) => &mut p.layout,
+            EditorMode::Remap(r) => &mut r.layout,
+            EditorMode::Weapon(w) => &mut w.layout,
         }
     }
 
@@ -267,29 +1778,85 @@ impl EditorMode {
         match self {
             EditorMode::Voxel(_) => EditorModeName::Voxel,
             EditorMode::Model(_) => EditorModeName::Model,
+            EditorMode::Palette(_) => EditorModeName::Palette,
+            EditorMode::Remap(_) => EditorModeName::Remap,
+            EditorMode::Weapon(_) => EditorModeName::Weapon,
         }
     }
 
+    /// Builds every mode as a tab, showing the one that was last used.
     fn restore(
         recent: &Recent,
         voxels: &BTreeMap<IStr, Voxel3>,
         models: &BTreeMap<IStr, Model>,
-        font: &KataFont,
-    ) -> Self {
-        match recent.mode {
-            EditorModeName::Voxel => EditorMode::Voxel(VoxelMode::new(
-                recent.voxel.as_ref().and_then(|v| voxels.get(v)).cloned(),
-                font,
-            )),
-            EditorModeName::Model => EditorMode::Model(ModelMode::new(
-                recent
-                    .model
-                    .as_ref()
-                    .and_then(|m| models.get(m))
-                    .cloned()
-                    .map(EditableModel::from),
-            )),
-        }
+        palette: &Palette,
+        weapons: &WeaponModels,
+        settings: &Settings,
+        charset_width: u16,
+        glyph_count: u16,
+    ) -> Tabs<EditorMode> {
+        // Edited in palette mode and offered by the color picker in voxel mode
+        let named_colors = bind(palette.entries.clone());
+        let voxel_mode = VoxelMode::new(
+            voxels,
+            models,
+            settings,
+            &named_colors,
+            recent,
+            charset_width,
+            glyph_count,
+        );
+        // Remaps the voxels through voxel mode's undo stack
+        let remap_mode = RemapMode::new(voxel_mode.actions.clone(), glyph_count);
+
+        Tabs::new(bind(recent.mode.index()))
+            .with_tab(EditorModeName::Voxel.title(), EditorMode::Voxel(voxel_mode))
+            .with_tab(
+                EditorModeName::Model.title(),
+                EditorMode::Model({
+                    // Fall back to the first model when the last one is gone
+                    let name = recent
+                        .model
+                        .as_ref()
+                        .filter(|&name| models.contains_key(name))
+                        .or_else(|| models.keys().next())
+                        .cloned();
+                    let model = name.as_ref().map(|name| models[name].clone());
+                    let same_model = name == recent.model;
+                    let model_mode = ModelMode::new(name, model, voxels, recent.tool);
+
+                    if same_model {
+                        model_mode.slice.set(recent.slice);
+                        model_mode.step_slice(0);
+                    }
+
+                    model_mode
+                }),
+            )
+            .with_tab(
+                EditorModeName::Palette.title(),
+                EditorMode::Palette(PaletteMode::new(named_colors, recent.palette.as_ref())),
+            )
+            .with_tab(EditorModeName::Remap.title(), EditorMode::Remap(remap_mode))
+            .with_tab(
+                EditorModeName::Weapon.title(),
+                EditorMode::Weapon(WeaponMode::new(
+                    weapons,
+                    voxels,
+                    recent.weapon.as_ref(),
+                    recent.tool,
+                )),
+            )
+    }
+}
+
+impl Element for EditorMode {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.element().layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        self.element().handle_event(ctx, event, bounds)
     }
 }
 
@@ -297,233 +1864,207 @@ impl EditorMode {
 enum EditorModeName {
     Voxel,
     Model,
+    Palette,
+    Remap,
+    Weapon,
 }
 
-impl Default for EditorModeName {
-    fn default() -> Self {
-        EditorModeName::Voxel
+impl EditorModeName {
+    /// The position of the mode's tab.
+    fn index(self) -> usize {
+        match self {
+            EditorModeName::Voxel => 0,
+            EditorModeName::Model => 1,
+            EditorModeName::Palette => 2,
+            EditorModeName::Remap => 3,
+            EditorModeName::Weapon => 4,
+        }
     }
-}
 
-struct VoxelMode {
-    layout: FlexLayout,
-}
-
-impl VoxelMode {
-    fn new(voxel: Option<Voxel3>, font: &KataFont) -> Self {
-        // Bindings
-        let voxel = Binding::new(voxel.unwrap_or_else(Default::default));
-        let active_face = Binding::new(VoxelFace::X);
-
-        let charset_width = font.charset_width();
-
-        // Layout
-        let font_display = List::from_vec(
-            (0..font.charset_height())
-                .map(|y| {
-                    ListElement::new(Box::new(
-                        KataText::from_voxels(
-                            (0..font.charset_width())
-                                .map(|x| Voxel2::new(y * font.charset_width() + x))
-                                .collect(),
-                        )
-                        .with_events({
-                            let voxel = voxel.clone();
-                            let active_face = active_face.clone();
-                            move |_self, _ctx, e, bounds| {
-                                match e.cull(bounds) {
-                                    Some(Event::Mouse {
-                                        pos,
-                                        e:
-                                            MouseEvent::ButtonDown {
-                                                button: MouseButton::Left,
-                                            },
-                                    }) => {
-                                        let x = pos.x - bounds.x;
-                                        let char_offset = u16::from(y * charset_width) + x as u16;
-
-                                        let mut new_voxel = voxel.get();
-                                        new_voxel[active_face.get()].char_offset = char_offset;
-                                        voxel.set(new_voxel);
-
-                                        dbg!(char_offset);
-
-                                        return Err(Stop);
-                                    }
-
-                                    _ => {}
-                                }
-
-                                Ok(Continue)
-                            }
-                        }),
-                    ))
-                })
-                .collect(),
-        );
-
-        let face_display = |char_offset: u8, face: VoxelFace| {
-            let voxel = voxel.clone();
-            let active_face = active_face.clone();
-
-            Box::new(FlexLayout::vertical(vec![
-                FlexElement::fixed(Box::new(Placeholder::new(
-                    Voxel2::new(char_offset.into()),
-                    |c| dbg!(dbg!(c).constrain(Size::new(1, 1))),
-                ))),
-                FlexElement::fixed(Box::new(
-                    VoxelDisplay::new(flo_binding::computed(move || voxel.get()[face].clone()))
-                        .with_events(move |_self, _ctx, e, bounds| {
-                            match e.cull(bounds) {
-                                Some(Event::Mouse {
-                                    e:
-                                        MouseEvent::ButtonDown {
-                                            button: MouseButton::Left,
-                                        },
-                                    ..
-                                }) => active_face.set(face),
-
-                                _ => {}
-                            }
-
-                            Ok(Continue)
-                        }),
-                )),
-            ]))
-        };
-
-        let voxel_info = FlexLayout::vertical(vec![
-            FlexElement::flex(Box::new(Filling::blank()), 1),
-            FlexElement::fixed(Box::new(FlexLayout::horizontal(vec![
-                FlexElement::flex(Box::new(Filling::blank()), 1),
-                FlexElement::fixed(face_display(b'X', VoxelFace::X)),
-                FlexElement::fixed(face_display(b'Y', VoxelFace::Y)),
-                FlexElement::fixed(face_display(b'Z', VoxelFace::Z)),
-                FlexElement::flex(Box::new(Filling::blank()), 1),
-            ]))),
-            FlexElement::flex(Box::new(Filling::blank()), 1),
-        ]);
-
-        let middle_pane = FlexLayout::vertical(vec![
-            FlexElement::flex(Box::new(Centered::new(voxel_info)), 1),
-            FlexElement::flex(placeholder(b'c', color::GREEN, |c| c.max), 1),
-        ]);
-
-        let voxel_list = List::from_vec(
-            (1..=30)
-                .map(|i| ListElement::new(Box::new(KataText::from_str(&format!("Voxel {}", i)))))
-                .collect(),
-        );
-
-        Self {
-            layout: FlexLayout::horizontal(vec![
-                FlexElement::fixed(Box::new(font_display)),
-                FlexElement::fixed(divider()),
-                FlexElement::flex(Box::new(middle_pane), 1),
-                FlexElement::fixed(divider()),
-                FlexElement::flex(Box::new(voxel_list), 1),
-            ]),
+    fn title(self) -> &'static str {
+        match self {
+            EditorModeName::Voxel => "Voxel",
+            EditorModeName::Model => "Model",
+            EditorModeName::Palette => "Palette",
+            EditorModeName::Remap => "Remap",
+            EditorModeName::Weapon => "Weapon",
         }
     }
 }
 
-fn placeholder<N, F>(char_offset: N, color: Color, size_fn: F) -> Box<dyn Element>
-where
-    N: Into<u16>,
-    F: Fn(BoxConstraints) -> Size + 'static,
-{
-    Box::new(Placeholder::new(
-        Voxel2::new(char_offset.into()).background(Some(color)),
-        size_fn,
-    ))
+/// Whatever was last copied in the editor.
+#[derive(Clone, Debug)]
+enum Clipboard {
+    Face(Voxel2),
+    Voxel(Voxel3),
+    /// Cells of a model slice, by their position within the selection they were copied from.
+    Cells(Array2<Option<IStr>>),
 }
 
-fn divider() -> Box<dyn Element> {
-    placeholder(0x266u16, color::BLACK, |c| Size::new(1, c.max.height))
+/// What the import dialog turns an image into.
+#[derive(Clone, Copy, Debug)]
+enum ImportTarget {
+    /// The active slice of the model being edited.
+    Slice,
+    /// Half-block art for the UI.
+    Text,
 }
 
-struct ModelMode {
-    layout: StackedLayout,
-    current: Arc<Mutex<Option<Model>>>,
+/// How the quit dialog was answered, other than by cancelling.
+#[derive(Clone, Copy, Debug)]
+enum QuitChoice {
+    Save,
+    Discard,
 }
 
-impl ModelMode {
-    fn new(current_model: Option<EditableModel>) -> Self {
-        todo!()
+impl Default for EditorModeName {
+    fn default() -> Self {
+        EditorModeName::Voxel
     }
 }
 
-impl From<EditableModel> for Model {
-    fn from(mut eo: EditableModel) -> Self {
-        if eo.voxels.is_empty() {
-            return Self {
-                voxels: Array3::from_shape_simple_fn((0, 0, 0), || unreachable!()),
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ndarray::Array3;
+
+    #[test]
+    fn test_scrolling_skips_relayout() {
+        let voxels: BTreeMap<IStr, Voxel3> = (0..200)
+            .map(|i| (IStr::new(&format!("voxel{:03}", i)), Voxel3::default()))
+            .collect();
+        let mut tabs = Modal::new(
+            EditorMode::restore(
+                &Recent::default(),
+                &voxels,
+                &BTreeMap::new(),
+                &Palette::default(),
+                &WeaponModels::default(),
+                &Settings::default(),
+                32,
+                1024,
+            ),
+            DialogHandle::new(),
+        );
+        let size = Size::new(120, 60);
+        let bounds = SRect::new(0, 0, size.width, size.height);
+        let mut ctx = UiContext::new(TextGridBatch::new(size));
+        let lines = |tabs: &mut Modal<Tabs<EditorMode>>| {
+            let mut ctx = UiContext::new(TextGridBatch::new(size));
+            let _ = tabs.handle_event(&mut ctx, Event::Draw, bounds);
+            ctx.batch.lines()
+        };
+
+        // Settle into the first frame, the way the editor's update and draw go
+        assert!(ctx.relayout_if_needed(&mut tabs, size));
+        let _ = tabs.handle_event(&mut ctx, Event::Draw, bounds);
+        ctx.relayout_if_needed(&mut tabs, size);
+        let relayouts = ctx.relayouts;
+
+        let (y, line) = lines(&mut tabs)
+            .into_iter()
+            .enumerate()
+            .find(|(_, line)| line.contains("voxel000"))
+            .expect("The voxel list isn't showing");
+        let pos = mint::Point2::from([line.find("voxel000").unwrap() as i32, y as i32]);
+
+        for _ in 0..100 {
+            let wheel = Event::Mouse {
+                pos,
+                e: MouseEvent::WheelDown,
             };
+            let _ = tabs.handle_event(&mut ctx, wheel, bounds);
+            ctx.relayout_if_needed(&mut tabs, size);
+            let _ = tabs.handle_event(&mut ctx, Event::Draw, bounds);
         }
 
-        let mut keys = eo.voxels.keys();
-        let first = keys.by_ref().next().unwrap();
+        assert_eq!(ctx.relayouts, relayouts);
+        // It did scroll
+        assert!(!lines(&mut tabs)
+            .iter()
+            .any(|line| line.contains("voxel000")));
+    }
 
-        let mut min_x = first.coords.x;
-        let mut min_y = first.coords.y;
-        let mut min_z = first.coords.z;
+    #[test]
+    fn test_step_scaling() {
+        assert_eq!(step_scaling(4.0, 1), 4.5);
+        assert_eq!(step_scaling(4.0, -2), 3.0);
+        assert_eq!(step_scaling(1.0, -1), MIN_SCALING);
+        assert_eq!(step_scaling(7.5, 3), MAX_SCALING);
+        assert_eq!(step_scaling(2.3, 0), 2.5);
+        assert_eq!(step_scaling(0.0, 0), MIN_SCALING);
+    }
 
-        let mut max_x = first.coords.x;
-        let mut max_y = first.coords.y;
-        let mut max_z = first.coords.z;
+    /// A 3x2x2 model with the letters "abc" over "de" and an empty cell on the first slice, and
+    /// nothing on the second.
+    pub(super) fn letters() -> Array3<Option<IStr>> {
+        let mut model = Array3::from_elem((3, 2, 2), None);
+        for (i, name) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            model[(i % 3, i / 3, 0)] = Some(IStr::new(name));
+        }
 
-        for pos in keys {
-            min_x = min_x.min(pos.coords.x);
-            min_y = min_y.min(pos.coords.y);
-            min_z = min_z.min(pos.coords.z);
+        model
+    }
 
-            max_x = max_x.max(pos.coords.x);
-            max_y = max_y.max(pos.coords.y);
-            max_z = max_z.max(pos.coords.z);
-        }
+    pub(super) fn slice_names(model: &Array3<Option<IStr>>, z: usize) -> Vec<String> {
+        let (w, h, _) = model.dim();
+        (0..h)
+            .map(|y| {
+                (0..w)
+                    .map(|x| model[(x, y, z)].as_ref().map_or(".", |name| &**name))
+                    .collect()
+            })
+            .collect()
+    }
 
-        let w = (max_x - min_x) as usize;
-        let h = (max_y - min_y) as usize;
-        let d = (max_z - min_z) as usize;
+    #[test]
+    fn test_notifications() {
+        let start = Instant::now();
+        let mut notifications = Notifications::default();
+        assert_eq!(notifications.visible(start), None);
+
+        notifications.notify_at(NotifyLevel::Error, "Failed", start);
+        assert_eq!(notifications.visible(start), Some(("Failed", color::RED)));
+        assert_eq!(
+            notifications.visible(start + NOTIFICATION_DURATION - NOTIFICATION_FADE / 2),
+            Some(("Failed", color::RED.dimmed(0.5)))
+        );
+        assert_eq!(notifications.visible(start + NOTIFICATION_DURATION), None);
 
-        let voxels = Array3::from_shape_fn((w, h, d), |(x, y, z)| {
-            eo.voxels.remove(&Point3::new(
-                (x as i16) - min_x,
-                (y as i16) - min_y,
-                (z as i16) - min_z,
-            ))
-        });
+        notifications.notify_at(NotifyLevel::Success, "Saved", start + NOTIFICATION_DURATION);
+        assert_eq!(
+            notifications.visible(start + NOTIFICATION_DURATION),
+            Some(("Saved", color::GREEN))
+        );
+    }
+
+    #[test]
+    fn test_truncate_line() {
+        let text = |s| KataText::from_str(s).voxels;
+        let offsets = |voxels: &[Voxel2]| voxels.iter().map(|v| v.char_offset).collect::<Vec<_>>();
 
-        assert!(eo.voxels.is_empty());
+        let mut voxels = text("Saved");
+        truncate_line(&mut voxels, 5);
+        assert_eq!(voxels, text("Saved"));
 
-        Self { voxels }
+        truncate_line(&mut voxels, 3);
+        assert_eq!(
+            offsets(&voxels),
+            vec![u16::from(b'S'), u16::from(b'a'), ELLIPSIS]
+        );
+
+        truncate_line(&mut voxels, 0);
+        assert!(voxels.is_empty());
     }
-}
 
-#[derive(Clone, Debug)]
-struct EditableModel {
-    voxels: HashMap<Point3<i16>, IStr>,
-}
+    #[test]
+    fn test_old_recent() {
+        let recent: Recent =
+            serde_json::from_str(r#"{"voxel": "wall", "model": null, "mode": "Model"}"#).unwrap();
 
-impl From<Model> for EditableModel {
-    fn from(mut o: Model) -> Self {
-        Self {
-            voxels: o
-                .voxels
-                .indexed_iter_mut()
-                .filter_map(|((x, y, z), v)| {
-                    v.take().map(|v| {
-                        (
-                            Point3::new(
-                                x.try_into().unwrap(),
-                                y.try_into().unwrap(),
-                                z.try_into().unwrap(),
-                            ),
-                            v,
-                        )
-                    })
-                })
-                .collect(),
-        }
+        assert_eq!(recent.voxel, Some(IStr::new("wall")));
+        assert!(recent.voxels.is_empty());
+        assert_eq!(recent.face, VoxelFace::X);
+        assert_eq!(recent.slice, 0);
     }
 }
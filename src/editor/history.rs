@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use internship::IStr;
+use ndarray::{Array2, Array3};
+
+use crate::rendering::voxel::Voxel3;
+
+/// How many edits can be undone.
+const MAX_UNDO: usize = 256;
+
+/// An edit to the voxel entries, which can be undone by applying its inverse.
+#[derive(Clone, Debug)]
+pub(super) enum EditorAction {
+    SetVoxel {
+        index: usize,
+        old: Voxel3,
+        new: Voxel3,
+    },
+    Insert {
+        index: usize,
+        name: IStr,
+        voxel: Voxel3,
+    },
+    Remove {
+        index: usize,
+        name: IStr,
+        voxel: Voxel3,
+    },
+    Rename {
+        index: usize,
+        old: IStr,
+        new: IStr,
+    },
+    Move {
+        from: usize,
+        to: usize,
+    },
+    /// Several edits that are undone together, in order.
+    Compound(Vec<EditorAction>),
+}
+
+impl EditorAction {
+    pub(super) fn inverse(&self) -> Self {
+        match self.clone() {
+            EditorAction::SetVoxel { index, old, new } => EditorAction::SetVoxel {
+                index,
+                old: new,
+                new: old,
+            },
+            EditorAction::Insert { index, name, voxel } => {
+                EditorAction::Remove { index, name, voxel }
+            }
+            EditorAction::Remove { index, name, voxel } => {
+                EditorAction::Insert { index, name, voxel }
+            }
+            EditorAction::Rename { index, old, new } => EditorAction::Rename {
+                index,
+                old: new,
+                new: old,
+            },
+            EditorAction::Move { from, to } => EditorAction::Move { from: to, to: from },
+            EditorAction::Compound(actions) => {
+                EditorAction::Compound(actions.iter().rev().map(Self::inverse).collect())
+            }
+        }
+    }
+
+    /// The entry to load once the action is done, if it leaves one worth looking at.
+    pub(super) fn focus(&self) -> Option<usize> {
+        match self {
+            EditorAction::SetVoxel { index, .. }
+            | EditorAction::Insert { index, .. }
+            | EditorAction::Remove { index, .. }
+            | EditorAction::Rename { index, .. } => Some(*index),
+            EditorAction::Move { to, .. } => Some(*to),
+            EditorAction::Compound(actions) => actions.iter().rev().find_map(Self::focus),
+        }
+    }
+}
+
+/// The edits that can be undone, and those that have been undone and can be redone.
+pub(super) struct History<A> {
+    pub(super) done: VecDeque<A>,
+    pub(super) undone: Vec<A>,
+}
+
+impl<A> History<A> {
+    /// Remembers an edit that was just made, which can't be followed by redoing older ones.
+    pub(super) fn push(&mut self, action: A) {
+        self.done.push_back(action);
+        if self.done.len() > MAX_UNDO {
+            self.done.pop_front();
+        }
+
+        self.undone.clear();
+    }
+}
+
+impl<A> Default for History<A> {
+    fn default() -> Self {
+        Self {
+            done: VecDeque::new(),
+            undone: Vec::new(),
+        }
+    }
+}
+
+/// One cell of a model changing from one voxel to another.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct CellChange {
+    pub(super) pos: (usize, usize, usize),
+    pub(super) old: Option<IStr>,
+    pub(super) new: Option<IStr>,
+}
+
+/// The cells changed by one use of a tool, which are undone together.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(super) struct ModelEdit {
+    pub(super) cells: Vec<CellChange>,
+}
+
+impl ModelEdit {
+    pub(super) fn inverse(&self) -> Self {
+        Self {
+            cells: self
+                .cells
+                .iter()
+                .rev()
+                .map(|change| CellChange {
+                    pos: change.pos,
+                    old: change.new.clone(),
+                    new: change.old.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub(super) fn apply_to(&self, model: &mut Array3<Option<IStr>>) {
+        for change in &self.cells {
+            model[change.pos] = change.new.clone();
+        }
+    }
+
+    /// Sets `cells` of slice `z` to `value`, remembering the ones that changed.
+    pub(super) fn set_cells<I>(
+        &mut self,
+        model: &mut Array3<Option<IStr>>,
+        cells: I,
+        z: usize,
+        value: &Option<IStr>,
+    ) where
+        I: IntoIterator<Item = (usize, usize)>,
+    {
+        self.set_each(
+            model,
+            cells.into_iter().map(|cell| (cell, value.clone())),
+            z,
+        );
+    }
+
+    /// Sets each of `cells` of slice `z` to its own value, remembering the ones that changed.
+    /// Cells outside the model are skipped.
+    fn set_each<I>(&mut self, model: &mut Array3<Option<IStr>>, cells: I, z: usize)
+    where
+        I: IntoIterator<Item = ((usize, usize), Option<IStr>)>,
+    {
+        for ((x, y), value) in cells {
+            let pos = (x, y, z);
+            match model.get_mut(pos) {
+                Some(cell) if *cell != value => {
+                    self.cells.push(CellChange {
+                        pos,
+                        old: cell.clone(),
+                        new: value.clone(),
+                    });
+                    *cell = value;
+                }
+
+                _ => {}
+            }
+        }
+    }
+
+    /// Puts `cells` on slice `z` with their top left corner at `(x, y)`, empty ones included.
+    pub(super) fn place(
+        &mut self,
+        model: &mut Array3<Option<IStr>>,
+        cells: &Array2<Option<IStr>>,
+        (x, y): (usize, usize),
+        z: usize,
+    ) {
+        self.set_each(
+            model,
+            cells
+                .indexed_iter()
+                .map(|((cx, cy), value)| ((x + cx, y + cy), value.clone())),
+            z,
+        );
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use failure::{format_err, Fallible};
+use flo_binding::{bind, Bound, MutableBound};
+use image::{Rgba, RgbaImage};
+use internship::IStr;
+use log::warn;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    editor::{Recent, Settings},
+    rendering::{
+        color::{self, Color, Palette},
+        voxel::{Model, Voxel2, Voxel3},
+    },
+    ui::*,
+    util::{try_load_lenient, write_json},
+    weapons::{WeaponModels, WEAPONS_PATH},
+};
+
+/// A bordered dialog with `title`, with `body` stacked on top of a row of `buttons`.
+/// Loads one of the editor's files. If it isn't valid JSON it's added to `corrupt` and the
+/// default is used instead, so the editor can still start and offer to back it up.
+/// How many cells of the status bar the save progress takes up, left of the save button.
+pub(super) const SAVE_PROGRESS_WIDTH: u32 = 16;
+
+/// Everything the editor writes back to disk, as it was when the save started.
+#[derive(Clone)]
+pub(super) struct SaveFiles {
+    pub(super) voxels: BTreeMap<IStr, Voxel3>,
+    pub(super) models: BTreeMap<IStr, Model>,
+    pub(super) palette: Palette,
+    pub(super) weapons: WeaponModels,
+    pub(super) recent: Recent,
+    pub(super) settings: Settings,
+}
+
+impl SaveFiles {
+    /// How many files `write` writes.
+    const COUNT: u32 = 6;
+
+    /// Writes each file into `dir`, sending `Ok` after each one. Stops at the first that fails,
+    /// sending its error.
+    pub(super) fn write(&self, dir: &Path, sender: &Sender<Fallible<()>>) {
+        let writes: Vec<Box<dyn Fn() -> Fallible<()> + '_>> = vec![
+            Box::new(|| write_json(dir.join("voxels.json"), &self.voxels)),
+            Box::new(|| write_json(dir.join("models.json"), &self.models)),
+            Box::new(|| write_json(dir.join(color::PALETTE_PATH), &self.palette)),
+            Box::new(|| write_json(dir.join(WEAPONS_PATH), &self.weapons)),
+            Box::new(|| write_json(dir.join(".recent.json"), &self.recent)),
+            Box::new(|| write_json(dir.join(".settings.json"), &self.settings)),
+        ];
+
+        for write in &writes {
+            let result = write();
+            let failed = result.is_err();
+
+            if sender.send(result).is_err() || failed {
+                break;
+            }
+        }
+    }
+}
+
+/// Writes `files` into `dir` on another thread, so that big models don't hold up the UI. The
+/// channel gets a result for each file, see `SaveFiles::write`, and is closed once it's done.
+pub(super) fn spawn_save(files: SaveFiles, dir: PathBuf) -> Receiver<Fallible<()>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || files.write(&dir, &sender));
+    receiver
+}
+
+/// A save started by `spawn_save`, with a bar showing how many of the files are written.
+pub(super) struct PendingSave {
+    pub(super) files: SaveFiles,
+    receiver: Receiver<Fallible<()>>,
+    pub(super) bar: ProgressBar,
+    /// Whether to quit once it's done, when it was started from the quit dialog.
+    pub(super) quit: bool,
+}
+
+impl PendingSave {
+    pub(super) fn new(files: SaveFiles, receiver: Receiver<Fallible<()>>, quit: bool) -> Self {
+        Self {
+            files,
+            receiver,
+            bar: ProgressBar::new(bind((0, SaveFiles::COUNT))).with_percentage(),
+            quit,
+        }
+    }
+
+    /// Advances the bar by the files written since the last poll. Returns how it went once
+    /// every file was written or one failed.
+    pub(super) fn poll(&mut self) -> Option<Fallible<()>> {
+        let (mut done, total) = self.bar.progress.get();
+
+        let result = loop {
+            match self.receiver.try_recv() {
+                Ok(Ok(())) => done += 1,
+                Ok(Err(e)) => break Some(Err(e)),
+                Err(TryRecvError::Empty) => break None,
+                Err(TryRecvError::Disconnected) if done < total => {
+                    break Some(Err(format_err!("Saving stopped after {} files", done)))
+                }
+                Err(TryRecvError::Disconnected) => break Some(Ok(())),
+            }
+        };
+
+        self.bar.progress.set((done, total));
+        result
+    }
+}
+
+pub(super) fn load_store<T>(path: &str, corrupt: &mut Vec<(PathBuf, String)>) -> Fallible<T>
+where
+    T: DeserializeOwned + Default,
+{
+    let (value, error) = try_load_lenient(path)?;
+
+    if let Some(e) = error {
+        warn!("{} isn't valid, starting without it: {}", path, e);
+        corrupt.push((PathBuf::from(path), e.to_string()));
+    }
+
+    Ok(value)
+}
+
+/// Images bigger than this either way are refused, as they'd be too big to edit anyway.
+const MAX_IMPORT_SIZE: u32 = 128;
+/// Pixels at least this opaque are imported, and the rest are left empty.
+const OPAQUE_ALPHA: u8 = 128;
+const UPPER_HALF_BLOCK: u16 = 0xDF;
+const LOWER_HALF_BLOCK: u16 = 0xDC;
+
+pub(super) fn load_image(path: &str) -> Fallible<RgbaImage> {
+    // Checked before decoding so that huge images are refused quickly
+    let (width, height) = image::image_dimensions(path)?;
+    if width > MAX_IMPORT_SIZE || height > MAX_IMPORT_SIZE {
+        return Err(format_err!(
+            "the image is {}x{}, but at most {}x{} can be imported",
+            width,
+            height,
+            MAX_IMPORT_SIZE,
+            MAX_IMPORT_SIZE
+        ));
+    }
+
+    Ok(image::open(path)?.to_rgba8())
+}
+
+/// The color of the pixel at `(x, y)` as the nearest named color, or `None` if it's transparent or
+/// outside the image.
+fn pixel_color(image: &RgbaImage, x: u32, y: u32) -> Option<Color> {
+    if x >= image.width() || y >= image.height() {
+        return None;
+    }
+
+    let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+    if a >= OPAQUE_ALPHA {
+        Some(color::nearest(Color::new(r, g, b)))
+    } else {
+        None
+    }
+}
+
+pub(super) fn opaque_pixels(image: &RgbaImage) -> Vec<(usize, usize)> {
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[3] >= OPAQUE_ALPHA)
+        .map(|(x, y, _)| (x as usize, y as usize))
+        .collect()
+}
+
+/// Turns `image` into rows of half-block glyphs, each showing two pixels one above the other.
+pub(super) fn image_to_text(image: &RgbaImage) -> Vec<Vec<Voxel2>> {
+    (0..image.height())
+        .step_by(2)
+        .map(|y| {
+            (0..image.width())
+                .map(
+                    |x| match (pixel_color(image, x, y), pixel_color(image, x, y + 1)) {
+                        (Some(top), bottom) => Voxel2::new(UPPER_HALF_BLOCK)
+                            .foreground(top)
+                            .background(bottom),
+                        (None, Some(bottom)) => Voxel2::new(LOWER_HALF_BLOCK).foreground(bottom),
+                        (None, None) => Voxel2::new(u16::from(b' ')),
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::{back_up_file, try_load};
+    use na::Point3;
+    use ndarray::Array3;
+    use std::fs;
+
+    #[test]
+    fn test_image_import() {
+        let clear = Rgba([0, 0, 0, 0]);
+        let mut image = RgbaImage::from_pixel(2, 3, clear);
+        image.put_pixel(0, 0, Rgba([250, 10, 20, 255]));
+        image.put_pixel(0, 1, Rgba([10, 10, 240, 200]));
+        image.put_pixel(1, 1, Rgba([240, 240, 240, 255]));
+        image.put_pixel(1, 2, Rgba([0, 255, 0, 100]));
+
+        assert_eq!(opaque_pixels(&image), [(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(pixel_color(&image, 0, 0), Some(color::RED));
+        assert_eq!(pixel_color(&image, 1, 2), None);
+        assert_eq!(pixel_color(&image, 2, 0), None);
+
+        let rows = image_to_text(&image);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0],
+            [
+                Voxel2::new(UPPER_HALF_BLOCK)
+                    .foreground(color::RED)
+                    .background(Some(color::BLUE)),
+                Voxel2::new(LOWER_HALF_BLOCK).foreground(color::WHITE),
+            ]
+        );
+        assert_eq!(
+            rows[1],
+            [Voxel2::new(u16::from(b' ')), Voxel2::new(u16::from(b' '))]
+        );
+    }
+
+    #[test]
+    fn test_write_json() {
+        let path = std::env::temp_dir().join("katakomb_test_write_json.json");
+        let voxels: BTreeMap<IStr, Voxel3> = vec![(IStr::new("wall"), Voxel3::default())]
+            .into_iter()
+            .collect();
+
+        write_json(&path, &voxels).unwrap();
+        let loaded: BTreeMap<IStr, Voxel3> = try_load(&path).unwrap();
+        assert_eq!(loaded, voxels);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn empty_save_files() -> SaveFiles {
+        SaveFiles {
+            voxels: BTreeMap::new(),
+            models: BTreeMap::new(),
+            palette: Palette::default(),
+            weapons: WeaponModels::default(),
+            recent: Recent::default(),
+            settings: Settings::default(),
+        }
+    }
+
+    #[test]
+    fn test_save_progress() {
+        let (sender, receiver) = mpsc::channel();
+        let mut saving = PendingSave::new(empty_save_files(), receiver, false);
+        assert!(saving.poll().is_none());
+        assert_eq!(saving.bar.progress.get(), (0, SaveFiles::COUNT));
+
+        sender.send(Ok(())).unwrap();
+        assert!(saving.poll().is_none());
+        assert_eq!(saving.bar.progress.get(), (1, SaveFiles::COUNT));
+
+        for _ in 1..SaveFiles::COUNT {
+            sender.send(Ok(())).unwrap();
+        }
+        drop(sender);
+        assert!(saving.poll().unwrap().is_ok());
+        assert_eq!(
+            saving.bar.progress.get(),
+            (SaveFiles::COUNT, SaveFiles::COUNT)
+        );
+
+        // A failed file ends the save there
+        let (sender, receiver) = mpsc::channel();
+        let mut saving = PendingSave::new(empty_save_files(), receiver, false);
+        sender.send(Ok(())).unwrap();
+        sender.send(Err(format_err!("disk full"))).unwrap();
+        assert!(saving.poll().unwrap().is_err());
+        assert_eq!(saving.bar.progress.get(), (1, SaveFiles::COUNT));
+    }
+
+    #[test]
+    fn test_spawn_save() {
+        let dir = std::env::temp_dir().join("katakomb_test_spawn_save");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut files = empty_save_files();
+        files.models.insert(
+            IStr::new("crate"),
+            Model::new(Array3::from_elem((1, 1, 1), Some(IStr::new("wall")))),
+        );
+
+        let receiver = spawn_save(files.clone(), dir.clone());
+        let mut saving = PendingSave::new(files, receiver, false);
+        let result = loop {
+            if let Some(result) = saving.poll() {
+                break result;
+            }
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(
+            saving.bar.progress.get(),
+            (SaveFiles::COUNT, SaveFiles::COUNT)
+        );
+        let models: BTreeMap<IStr, Model> = try_load(dir.join("models.json")).unwrap();
+        assert_eq!(models, saving.files.models);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_models_json_round_trip() {
+        let path = std::env::temp_dir().join("katakomb_test_models_round_trip.json");
+        let mut voxels = Array3::from_elem((2, 3, 1), None);
+        voxels[(1, 2, 0)] = Some(IStr::new("wall"));
+
+        let plain = Model::new(voxels.clone());
+        let mut metadata = BTreeMap::new();
+        metadata.insert(String::from("author"), String::from("someone"));
+        let pivoted = Model::new(voxels)
+            .with_pivot(Point3::new(0.0, 0.5, 0.0))
+            .with_metadata(metadata);
+        let models: BTreeMap<IStr, Model> = vec![
+            (IStr::new("plain"), plain.clone()),
+            (IStr::new("pivoted"), pivoted),
+        ]
+        .into_iter()
+        .collect();
+
+        write_json(&path, &models).unwrap();
+        let loaded: BTreeMap<IStr, Model> = try_load(&path).unwrap();
+        assert_eq!(loaded, models);
+
+        // Models saved before they had a pivot turn around their middle
+        fs::write(
+            &path,
+            serde_json::to_string(&serde_json::json!({ "plain": { "voxels": &plain.voxels } }))
+                .unwrap(),
+        )
+        .unwrap();
+        let loaded: BTreeMap<IStr, Model> = try_load(&path).unwrap();
+        assert_eq!(loaded[&IStr::new("plain")], plain);
+        assert_eq!(plain.pivot, Point3::new(0.5, 1.0, 0.0));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_json_is_kept_for_backup() {
+        let dir = std::env::temp_dir().join("katakomb_test_corrupt_json");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("models.json");
+        fs::write(&path, r#"{ "wall": [1, 2"#).unwrap();
+
+        assert!(try_load::<BTreeMap<IStr, Model>, _>(&path)
+            .unwrap_err()
+            .to_string()
+            .contains("models.json"));
+
+        let mut corrupt = Vec::new();
+        let models: BTreeMap<IStr, Model> =
+            load_store(path.to_str().unwrap(), &mut corrupt).unwrap();
+        assert!(models.is_empty());
+        assert_eq!(corrupt.len(), 1);
+        assert_eq!(corrupt[0].0, path);
+
+        // A missing file isn't corrupt, just empty
+        let missing: BTreeMap<IStr, Model> =
+            load_store(dir.join("voxels.json").to_str().unwrap(), &mut corrupt).unwrap();
+        assert!(missing.is_empty());
+        assert_eq!(corrupt.len(), 1);
+
+        let backup_path = back_up_file(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(&backup_path).unwrap(),
+            r#"{ "wall": [1, 2"#
+        );
+        assert!(back_up_file(&path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
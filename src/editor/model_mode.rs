@@ -0,0 +1,1003 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::{
+    event::{KeyCode, KeyMods},
+    input::mouse::MouseButton,
+    mint,
+};
+use image::RgbaImage;
+use internship::IStr;
+use na::{Point3, Vector3};
+use ndarray::Array3;
+
+use crate::{
+    editor::{
+        history::{History, ModelEdit},
+        io::opaque_pixels,
+        panels::{button_row, VoxelPreview},
+        tools::{
+            flood_fill, line_cells, move_cells, rect_cells, MirrorAxis, ModelSelection, ModelTool,
+            Selection, Stroke, MODEL_TOOLS,
+        },
+    },
+    geometry::rect::SRect,
+    rendering::{
+        color,
+        voxel::{Model, Voxel2, Voxel3, VoxelFace},
+    },
+    ui::*,
+};
+
+pub(super) struct ModelMode {
+    pub(super) layout: FlexLayout,
+    pub(super) name: Option<IStr>,
+    pub(super) current: Arc<Mutex<Option<EditableModel>>>,
+    pub(super) model: Binding<Array3<Option<IStr>>>,
+    /// Where the model turns around and is placed from along each axis, in half voxels so that
+    /// it can sit between two.
+    pub(super) pivot: [Binding<i32>; 3],
+    /// Kept as it was loaded, since there's nowhere to edit it yet.
+    pub(super) metadata: BTreeMap<String, String>,
+    /// The z index of the slice being looked at.
+    pub(super) slice: Binding<i32>,
+    pub(super) tool: Binding<ModelTool>,
+    /// The voxel that gets painted, which is the one selected in voxel mode.
+    pub(super) brush: Binding<Option<IStr>>,
+    pub(super) history: Rc<RefCell<History<ModelEdit>>>,
+    pub(super) selection: ModelSelection,
+    /// Set by the export button, for the editor to carry out on the next update.
+    pub(super) export_requested: Rc<Cell<bool>>,
+    /// Set by the .vox export button, for the editor to carry out on the next update.
+    pub(super) vox_export_requested: Rc<Cell<bool>>,
+    /// Set by the PNG export button, for the editor to carry out on the next update.
+    pub(super) sheet_export_requested: Rc<Cell<bool>>,
+    /// Set by the import button, for the editor to open its dialog on the next update.
+    pub(super) import_requested: Rc<Cell<bool>>,
+    /// Set by the .vox import button, for the editor to open its dialog on the next update.
+    pub(super) vox_import_requested: Rc<Cell<bool>>,
+    /// Redraws the preview after the model changes.
+    pub(super) refresh_preview: Rc<dyn Fn()>,
+}
+
+impl ModelMode {
+    pub(super) fn new(
+        name: Option<IStr>,
+        current_model: Option<Model>,
+        voxels: &BTreeMap<IStr, Voxel3>,
+        tool: ModelTool,
+    ) -> Self {
+        let model = bind(
+            current_model
+                .as_ref()
+                .map_or_else(|| Array3::from_elem((0, 0, 0), None), |m| m.voxels.clone()),
+        );
+        let (width, height, depth) = model.get().dim();
+        let (width, height, depth) = (width as i32, height as i32, depth as i32);
+        let initial_pivot = current_model
+            .as_ref()
+            .map_or_else(Point3::origin, |m| m.pivot);
+        let pivot = [
+            bind((initial_pivot.x * 2.0).round() as i32),
+            bind((initial_pivot.y * 2.0).round() as i32),
+            bind((initial_pivot.z * 2.0).round() as i32),
+        ];
+        let metadata = current_model
+            .as_ref()
+            .map(|m| m.metadata.clone())
+            .unwrap_or_default();
+        let slice = bind(0);
+        let tool = bind(tool);
+        let brush = bind(None);
+        let history = Rc::new(RefCell::new(History::default()));
+        let voxels = Rc::new(voxels.clone());
+
+        let preview_voxels = bind(Array3::from_elem((0, 0, 0), None));
+        let refresh_preview: Rc<dyn Fn()> = Rc::new({
+            let model = model.clone();
+            let voxels = Rc::clone(&voxels);
+            let preview_voxels = preview_voxels.clone();
+            move || {
+                // Voxels missing from the map are left out of the preview
+                preview_voxels.set(
+                    model
+                        .get()
+                        .map(|name| name.as_ref().and_then(|name| voxels.get(name)).cloned()),
+                )
+            }
+        });
+        refresh_preview();
+
+        let tool_buttons = MODEL_TOOLS
+            .iter()
+            .map(|&t| {
+                let tool = tool.clone();
+                Button::new(t.title(), move || tool.set(t))
+            })
+            .collect();
+
+        let export_requested = Rc::new(Cell::new(false));
+        let export_button = Button::new("Export as stamp", {
+            let export_requested = Rc::clone(&export_requested);
+            move || export_requested.set(true)
+        });
+
+        let vox_export_requested = Rc::new(Cell::new(false));
+        let vox_export_button = Button::new("Export as .vox", {
+            let vox_export_requested = Rc::clone(&vox_export_requested);
+            move || vox_export_requested.set(true)
+        });
+
+        let sheet_export_requested = Rc::new(Cell::new(false));
+        let sheet_export_button = Button::new("Export as PNG", {
+            let sheet_export_requested = Rc::clone(&sheet_export_requested);
+            move || sheet_export_requested.set(true)
+        });
+
+        let import_requested = Rc::new(Cell::new(false));
+        let import_button = Button::new("Import image", {
+            let import_requested = Rc::clone(&import_requested);
+            move || import_requested.set(true)
+        });
+
+        let vox_import_requested = Rc::new(Cell::new(false));
+        let vox_import_button = Button::new("Import .vox", {
+            let vox_import_requested = Rc::clone(&vox_import_requested);
+            move || vox_import_requested.set(true)
+        });
+
+        let selection = ModelSelection {
+            selection: bind(None),
+            model: model.clone(),
+            slice: slice.clone(),
+            brush: brush.clone(),
+            history: Rc::clone(&history),
+            refresh_preview: Rc::clone(&refresh_preview),
+        };
+        let selection_button = |title: &str, action: fn(&ModelSelection)| {
+            let selection = selection.clone();
+            Button::new(title, move || action(&selection))
+        };
+        let selection_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Selection "))),
+            FlexElement::fixed(Box::new(button_row(vec![
+                selection_button("Delete", |s| {
+                    s.delete();
+                }),
+                selection_button("Fill", |s| {
+                    s.fill();
+                }),
+                selection_button("Mirror H", |s| {
+                    s.mirror(MirrorAxis::Horizontal);
+                }),
+                selection_button("Mirror V", |s| {
+                    s.mirror(MirrorAxis::Vertical);
+                }),
+                selection_button("Deselect", ModelSelection::clear),
+            ]))),
+        ]);
+
+        let slice_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Slice "))),
+            FlexElement::fixed(Box::new(Stepper::new(slice.clone(), 0, (depth - 1).max(0)))),
+            FlexElement::fixed(Box::new(Padding::new(button_row(tool_buttons), 0, 0, 0, 2))),
+            FlexElement::fixed(Box::new(Padding::new(export_button, 0, 0, 0, 1))),
+            FlexElement::fixed(Box::new(Padding::new(vox_export_button, 0, 0, 0, 1))),
+            FlexElement::fixed(Box::new(Padding::new(sheet_export_button, 0, 0, 0, 1))),
+            FlexElement::fixed(Box::new(Padding::new(import_button, 0, 0, 0, 1))),
+            FlexElement::fixed(Box::new(Padding::new(vox_import_button, 0, 0, 0, 1))),
+        ]);
+
+        let pivot_stepper = |half_voxels: &Binding<i32>, side: i32| {
+            FlexElement::fixed(Box::new(Padding::new(
+                Stepper::new(half_voxels.clone(), 0, ((side - 1) * 2).max(0)),
+                0,
+                1,
+                0,
+                0,
+            )))
+        };
+        let pivot_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Pivot (half voxels) "))),
+            pivot_stepper(&pivot[0], width),
+            pivot_stepper(&pivot[1], height),
+            pivot_stepper(&pivot[2], depth),
+        ]);
+        let preview_pivot = flo_binding::computed({
+            let pivot = pivot.clone();
+            move || pivot_of(&pivot)
+        });
+
+        let slice_view = ModelSlice::new(
+            model.clone(),
+            slice.clone(),
+            tool.clone(),
+            brush.clone(),
+            Rc::clone(&voxels),
+        )
+        .with_selection(selection.selection.clone())
+        .with_on_edit({
+            let history = Rc::clone(&history);
+            let refresh_preview = Rc::clone(&refresh_preview);
+            move |edit| {
+                history.borrow_mut().push(edit);
+                refresh_preview();
+            }
+        });
+
+        Self {
+            layout: FlexLayout::vertical(vec![
+                FlexElement::fixed(Box::new(slice_row)),
+                FlexElement::fixed(Box::new(pivot_row)),
+                FlexElement::fixed(Box::new(selection_row)),
+                FlexElement::flex(
+                    Box::new(FlexLayout::horizontal(vec![
+                        FlexElement::flex(Box::new(Frame::new(slice_view).with_title("Slice")), 1),
+                        FlexElement::flex(
+                            Box::new(
+                                Frame::new(
+                                    VoxelPreview::new(preview_voxels).with_pivot(preview_pivot),
+                                )
+                                .with_title("Preview"),
+                            ),
+                            1,
+                        ),
+                    ])),
+                    1,
+                ),
+            ]),
+            name,
+            current: Arc::new(Mutex::new(current_model.map(EditableModel::from))),
+            model,
+            pivot,
+            metadata,
+            slice,
+            tool,
+            brush,
+            history,
+            selection,
+            export_requested,
+            vox_export_requested,
+            sheet_export_requested,
+            import_requested,
+            vox_import_requested,
+            refresh_preview,
+        }
+    }
+
+    fn depth(&self) -> i32 {
+        self.model.get().dim().2 as i32
+    }
+
+    /// The model as it's been edited, with its pivot and metadata.
+    pub(super) fn current_model(&self) -> Model {
+        Model::new(self.model.get())
+            .with_pivot(pivot_of(&self.pivot))
+            .with_metadata(self.metadata.clone())
+    }
+
+    /// Moves `steps` slices back, or forward if it's negative, staying within the model.
+    pub(super) fn step_slice(&self, steps: i32) {
+        let slice = (self.slice.get() + steps).min(self.depth() - 1).max(0);
+        self.slice.set(slice);
+    }
+
+    /// Where in the model the slice is and what the painter does, e.g. "Slice 2 / 5  Fill", along
+    /// with the size of the selection if there is one.
+    pub(super) fn status(&self) -> String {
+        let status = format!(
+            "Slice {} / {}  {}",
+            self.slice.get() + 1,
+            self.depth(),
+            self.tool.get().title()
+        );
+
+        match self.selection.selection.get() {
+            Some(selection) => format!(
+                "{}  {}x{} selected",
+                status,
+                selection.width(),
+                selection.height()
+            ),
+            None => status,
+        }
+    }
+
+    /// Undoes the last edit to the model, returning whether there was one.
+    pub(super) fn undo(&self) -> bool {
+        let edit = self.history.borrow_mut().done.pop_back();
+
+        match edit {
+            Some(edit) => {
+                self.perform(&edit.inverse());
+                self.history.borrow_mut().undone.push(edit);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone edit to the model, returning whether there was one.
+    pub(super) fn redo(&self) -> bool {
+        let edit = self.history.borrow_mut().undone.pop();
+
+        match edit {
+            Some(edit) => {
+                self.perform(&edit);
+                self.history.borrow_mut().done.push_back(edit);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Fills the cells under the opaque pixels of `image` on the active slice with the brush, as
+    /// one edit. Returns how many cells changed.
+    pub(super) fn import_slice(&self, image: &RgbaImage) -> usize {
+        let mut model = self.model.get();
+        let mut edit = ModelEdit::default();
+        edit.set_cells(
+            &mut model,
+            opaque_pixels(image),
+            self.slice.get() as usize,
+            &self.brush.get(),
+        );
+
+        let changed = edit.cells.len();
+        if changed > 0 {
+            self.model.set(model);
+            self.history.borrow_mut().push(edit);
+            (self.refresh_preview)();
+        }
+
+        changed
+    }
+
+    pub(super) fn perform(&self, edit: &ModelEdit) {
+        let mut model = self.model.get();
+        edit.apply_to(&mut model);
+        self.model.set(model);
+        (self.refresh_preview)();
+    }
+
+    /// Handles the keyboard shortcuts of the mode, returning whether `keycode` was one.
+    pub(super) fn handle_shortcut(&mut self, keycode: KeyCode, mods: KeyMods) -> bool {
+        let ctrl = mods.contains(KeyMods::CTRL);
+
+        match keycode {
+            KeyCode::Z if ctrl && mods.contains(KeyMods::SHIFT) => self.redo(),
+            KeyCode::Z if ctrl => self.undo(),
+            KeyCode::Y if ctrl => self.redo(),
+
+            KeyCode::PageUp => {
+                self.step_slice(-1);
+                true
+            }
+
+            KeyCode::PageDown => {
+                self.step_slice(1);
+                true
+            }
+
+            KeyCode::Delete => self.selection.delete(),
+
+            _ => false,
+        }
+    }
+}
+
+/// The pivot set by steppers in half voxels.
+fn pivot_of(half_voxels: &[Binding<i32>; 3]) -> Point3<f32> {
+    let [x, y, z] = half_voxels;
+    Point3::new(x.get(), y.get(), z.get()).map(|half| half as f32 / 2.0)
+}
+
+/// How much the slices next to the active one are darkened.
+const GHOST_DIM: f32 = 0.3;
+
+/// One z slice of a model, seen from the front, with the slices in front of and behind it shown
+/// as dimmed ghosts wherever the slice itself is empty. Dragging over it uses the current tool.
+pub(super) struct ModelSlice {
+    pub(super) model: Binding<Array3<Option<IStr>>>,
+    pub(super) slice: Binding<i32>,
+    pub(super) tool: Binding<ModelTool>,
+    pub(super) brush: Binding<Option<IStr>>,
+    pub(super) voxels: Rc<BTreeMap<IStr, Voxel3>>,
+    pub(super) selection: Binding<Option<Selection>>,
+    stroke: Option<Stroke>,
+    on_edit: Option<Box<dyn FnMut(ModelEdit)>>,
+}
+
+impl ModelSlice {
+    pub(super) fn new(
+        model: Binding<Array3<Option<IStr>>>,
+        slice: Binding<i32>,
+        tool: Binding<ModelTool>,
+        brush: Binding<Option<IStr>>,
+        voxels: Rc<BTreeMap<IStr, Voxel3>>,
+    ) -> Self {
+        Self {
+            model,
+            slice,
+            tool,
+            brush,
+            voxels,
+            selection: bind(None),
+            stroke: None,
+            on_edit: None,
+        }
+    }
+
+    /// Shares the selection made with the select tool through `selection`.
+    pub(super) fn with_selection(self, selection: Binding<Option<Selection>>) -> Self {
+        Self { selection, ..self }
+    }
+
+    /// Calls `on_edit` with each finished edit, which has already been made to the model.
+    pub(super) fn with_on_edit<F>(self, on_edit: F) -> Self
+    where
+        F: FnMut(ModelEdit) + 'static,
+    {
+        Self {
+            on_edit: Some(Box::new(on_edit)),
+            ..self
+        }
+    }
+
+    /// What to draw at `(x, y)`, in drawing order: the ghosts first and the active slice last.
+    fn layers(&self, model: &Array3<Option<IStr>>, x: usize, y: usize) -> Vec<Voxel2> {
+        let slice = self.slice.get();
+        let face_at = |z: i32| {
+            if z < 0 {
+                return None;
+            }
+
+            model
+                .get((x, y, z as usize))
+                .and_then(Option::as_ref)
+                .and_then(|name| self.voxels.get(name))
+                .map(|voxel| voxel[VoxelFace::Z].clone())
+        };
+
+        if let Some(face) = face_at(slice) {
+            return vec![face];
+        }
+
+        [slice - 1, slice + 1]
+            .iter()
+            .filter_map(|&z| face_at(z))
+            .map(|face| Voxel2 {
+                foreground: face.foreground.dimmed(GHOST_DIM),
+                background: face.background.map(|c| c.dimmed(GHOST_DIM)),
+                ..face
+            })
+            .collect()
+    }
+
+    /// The cell under `pos`, clamped to the model so that drags can go past its edges.
+    fn cell_at(&self, bounds: SRect, pos: mint::Point2<i32>) -> Option<(usize, usize)> {
+        let (w, h, _) = self.model.get().dim();
+        if w == 0 || h == 0 {
+            return None;
+        }
+
+        Some((
+            ((pos.x - bounds.x).max(0) as usize).min(w - 1),
+            ((pos.y - bounds.y).max(0) as usize).min(h - 1),
+        ))
+    }
+
+    /// What the current tool puts down: the brush, or nothing when erasing.
+    fn value(&self) -> Option<IStr> {
+        match self.tool.get() {
+            ModelTool::Erase => None,
+            _ => self.brush.get(),
+        }
+    }
+
+    /// The cells a line or rectangle stroke covers, or none for the other tools.
+    fn shape(&self, stroke: &Stroke) -> Vec<(usize, usize)> {
+        match self.tool.get() {
+            ModelTool::Line => line_cells(stroke.start, stroke.end),
+            ModelTool::Rect => rect_cells(
+                stroke.start,
+                stroke.end,
+                stroke.button == MouseButton::Right,
+            ),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Changes `cells` of the active slice to `value`, adding the changes to `edit`.
+    fn paint<I>(&self, edit: &mut ModelEdit, cells: I, value: &Option<IStr>)
+    where
+        I: IntoIterator<Item = (usize, usize)>,
+    {
+        let z = self.slice.get();
+        if z < 0 {
+            return;
+        }
+
+        let mut model = self.model.get();
+        let changed = edit.cells.len();
+        edit.set_cells(&mut model, cells, z as usize, value);
+
+        if edit.cells.len() != changed {
+            self.model.set(model);
+        }
+    }
+
+    fn finish(&mut self, edit: ModelEdit) {
+        if !edit.cells.is_empty() {
+            if let Some(on_edit) = &mut self.on_edit {
+                on_edit(edit);
+            }
+        }
+    }
+
+    fn press(&mut self, cell: (usize, usize), button: MouseButton) {
+        let value = self.value();
+
+        match self.tool.get() {
+            ModelTool::Select if button == MouseButton::Right => self.selection.set(None),
+
+            ModelTool::Select => {
+                let lifted = self.selection.get().filter(|s| s.contains(cell));
+                if lifted.is_none() {
+                    self.selection.set(Some(Selection::new(cell, cell)));
+                }
+
+                self.stroke = Some(Stroke {
+                    button,
+                    start: cell,
+                    end: cell,
+                    edit: ModelEdit::default(),
+                    lifted,
+                });
+            }
+
+            ModelTool::Fill => {
+                let z = self.slice.get();
+                if z >= 0 {
+                    let cells = flood_fill(&self.model.get(), cell, z as usize);
+                    let mut edit = ModelEdit::default();
+                    self.paint(&mut edit, cells, &value);
+                    self.finish(edit);
+                }
+            }
+
+            tool => {
+                let mut edit = ModelEdit::default();
+                if let ModelTool::Paint | ModelTool::Erase = tool {
+                    self.paint(&mut edit, vec![cell], &value);
+                }
+
+                self.stroke = Some(Stroke {
+                    button,
+                    start: cell,
+                    end: cell,
+                    edit,
+                    lifted: None,
+                });
+            }
+        }
+    }
+
+    pub(super) fn drag(&mut self, cell: (usize, usize)) {
+        if let Some(mut stroke) = self.stroke.take() {
+            if let ModelTool::Paint | ModelTool::Erase = self.tool.get() {
+                // Paint every cell in between so that fast drags don't leave gaps
+                let value = self.value();
+                self.paint(&mut stroke.edit, line_cells(stroke.end, cell), &value);
+            }
+
+            stroke.end = cell;
+            if self.tool.get() == ModelTool::Select && stroke.lifted.is_none() {
+                self.selection.set(Some(Selection::new(stroke.start, cell)));
+            }
+
+            self.stroke = Some(stroke);
+        }
+    }
+
+    /// Where the lifted selection of `stroke` has been dragged to, if there is one.
+    fn moved_selection(&self, stroke: &Stroke) -> Option<Selection> {
+        let (w, h, _) = self.model.get().dim();
+        stroke
+            .lifted
+            .map(|lifted| lifted.moved(stroke.start, stroke.end, (w, h)))
+    }
+
+    fn release(&mut self) {
+        if let Some(mut stroke) = self.stroke.take() {
+            if let (Some(from), Some(to)) = (stroke.lifted, self.moved_selection(&stroke)) {
+                let z = self.slice.get();
+                if from != to && z >= 0 {
+                    let mut model = self.model.get();
+                    move_cells(&mut stroke.edit, &mut model, from, to, z as usize);
+                    self.model.set(model);
+                    self.selection.set(Some(to));
+                }
+
+                self.finish(stroke.edit);
+                return;
+            }
+
+            let value = self.value();
+            let cells = self.shape(&stroke);
+            self.paint(&mut stroke.edit, cells, &value);
+            self.finish(stroke.edit);
+        }
+    }
+}
+
+impl Element for ModelSlice {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        let (w, h, _) = self.model.get().dim();
+        constraints.constrain(Size::new(w as u32, h as u32))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDown { button },
+            } if bounds.contains(pos)
+                && (button == MouseButton::Left || button == MouseButton::Right) =>
+            {
+                if let Some(cell) = self.cell_at(bounds, pos) {
+                    self.press(cell, button);
+                    return Err(Stop);
+                }
+            }
+
+            Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDrag { button, .. },
+            } if self.stroke.as_ref().map(|s| s.button) == Some(button) => {
+                if let Some(cell) = self.cell_at(bounds, pos) {
+                    self.drag(cell);
+                }
+
+                return Err(Stop);
+            }
+
+            // Finish wherever the button is released
+            Event::Mouse {
+                e: MouseEvent::ButtonUp { button },
+                ..
+            } if self.stroke.as_ref().map(|s| s.button) == Some(button) => {
+                self.release();
+                return Err(Stop);
+            }
+
+            Event::Draw if event.cull(bounds).is_some() => {
+                let model = self.model.get();
+                let (w, h, _) = model.dim();
+
+                for y in 0..h.min(bounds.h as usize) {
+                    for x in 0..w.min(bounds.w as usize) {
+                        for voxel in self.layers(&model, x, y) {
+                            ctx.draw(&voxel, [bounds.x + x as i32, bounds.y + y as i32]);
+                        }
+                    }
+                }
+
+                // Show where a line or rectangle will go before it's released
+                if let Some(stroke) = &self.stroke {
+                    let face = self
+                        .value()
+                        .and_then(|name| self.voxels.get(&name).map(|v| v[VoxelFace::Z].clone()))
+                        .unwrap_or_else(|| Voxel2::new(0).background(Some(color::GRAY)));
+
+                    for (x, y) in self.shape(stroke) {
+                        if x < bounds.w as usize && y < bounds.h as usize {
+                            ctx.draw(&face, [bounds.x + x as i32, bounds.y + y as i32]);
+                        }
+                    }
+                }
+
+                // The selection is drawn inverted, carrying its cells along while it's moved
+                let moving = self
+                    .stroke
+                    .as_ref()
+                    .and_then(|stroke| stroke.lifted.zip(self.moved_selection(stroke)));
+                let overlay =
+                    moving.or_else(|| self.selection.get().map(|selection| (selection, selection)));
+
+                if let Some((from, to)) = overlay {
+                    for (x, y) in to.cells() {
+                        if x >= w.min(bounds.w as usize) || y >= h.min(bounds.h as usize) {
+                            continue;
+                        }
+
+                        let source = (x - to.left + from.left, y - to.top + from.top);
+                        let face = self
+                            .layers(&model, source.0, source.1)
+                            .pop()
+                            .unwrap_or_default();
+                        ctx.draw(&face.inverted(), [bounds.x + x as i32, bounds.y + y as i32]);
+                    }
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+impl From<EditableModel> for Model {
+    fn from(mut eo: EditableModel) -> Self {
+        if eo.voxels.is_empty() {
+            return Self::new(Array3::from_elem((0, 0, 0), None))
+                .with_pivot(eo.pivot)
+                .with_metadata(eo.metadata);
+        }
+
+        let mut keys = eo.voxels.keys();
+        let first = keys.by_ref().next().unwrap();
+
+        let mut min_x = first.coords.x;
+        let mut min_y = first.coords.y;
+        let mut min_z = first.coords.z;
+
+        let mut max_x = first.coords.x;
+        let mut max_y = first.coords.y;
+        let mut max_z = first.coords.z;
+
+        for pos in keys {
+            min_x = min_x.min(pos.coords.x);
+            min_y = min_y.min(pos.coords.y);
+            min_z = min_z.min(pos.coords.z);
+
+            max_x = max_x.max(pos.coords.x);
+            max_y = max_y.max(pos.coords.y);
+            max_z = max_z.max(pos.coords.z);
+        }
+
+        let w = (max_x - min_x) as usize + 1;
+        let h = (max_y - min_y) as usize + 1;
+        let d = (max_z - min_z) as usize + 1;
+
+        let voxels = Array3::from_shape_fn((w, h, d), |(x, y, z)| {
+            eo.voxels.remove(&Point3::new(
+                (x as i16) + min_x,
+                (y as i16) + min_y,
+                (z as i16) + min_z,
+            ))
+        });
+
+        assert!(eo.voxels.is_empty());
+
+        // The pivot stays with the voxels as the model is cropped to them
+        let min = Vector3::new(min_x, min_y, min_z).map(f32::from);
+        Self::new(voxels)
+            .with_pivot(eo.pivot - min)
+            .with_metadata(eo.metadata)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(super) struct EditableModel {
+    pub(super) voxels: HashMap<Point3<i16>, IStr>,
+    pub(super) pivot: Point3<f32>,
+    metadata: BTreeMap<String, String>,
+}
+
+impl From<Model> for EditableModel {
+    fn from(mut o: Model) -> Self {
+        Self {
+            pivot: o.pivot,
+            metadata: std::mem::take(&mut o.metadata),
+            voxels: o
+                .voxels
+                .indexed_iter_mut()
+                .filter_map(|((x, y, z), v)| {
+                    v.take().map(|v| {
+                        (
+                            Point3::new(
+                                x.try_into().unwrap(),
+                                y.try_into().unwrap(),
+                                z.try_into().unwrap(),
+                            ),
+                            v,
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::test::{letters, slice_names};
+
+    #[test]
+    fn test_model_slice_layers() {
+        let mut voxel = Voxel3::default();
+        voxel[VoxelFace::Z].char_offset = 7;
+        voxel[VoxelFace::Z].background = Some(color::WHITE);
+        let voxels = Rc::new(vec![(IStr::new("a"), voxel)].into_iter().collect());
+
+        let mut model = Array3::from_elem((1, 1, 3), None);
+        model[(0, 0, 0)] = Some(IStr::new("a"));
+        model[(0, 0, 2)] = Some(IStr::new("a"));
+        model[(0, 0, 1)] = None;
+
+        let slice_view = ModelSlice::new(
+            bind(model.clone()),
+            bind(1),
+            bind(ModelTool::Paint),
+            bind(None),
+            voxels,
+        );
+        let ghosts = slice_view.layers(&model, 0, 0);
+        assert_eq!(ghosts.len(), 2);
+        assert_eq!(ghosts[0].char_offset, 7);
+        assert_eq!(ghosts[0].background, Some(color::WHITE.dimmed(GHOST_DIM)));
+
+        slice_view.slice.set(0);
+        let layers = slice_view.layers(&model, 0, 0);
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].background, Some(color::WHITE));
+
+        slice_view.slice.set(2);
+        assert_eq!(slice_view.layers(&model, 0, 0).len(), 1);
+    }
+
+    #[test]
+    fn test_model_slice_painting() {
+        let edits = Rc::new(RefCell::new(Vec::new()));
+        let model = bind(Array3::from_elem((4, 4, 1), None));
+        let tool = bind(ModelTool::Line);
+        let mut slice_view = ModelSlice::new(
+            model.clone(),
+            bind(0),
+            tool.clone(),
+            bind(Some(IStr::new("a"))),
+            Rc::new(BTreeMap::new()),
+        )
+        .with_on_edit({
+            let edits = Rc::clone(&edits);
+            move |edit| edits.borrow_mut().push(edit)
+        });
+
+        let bounds = SRect::new(1, 1, 4, 4);
+        let mut ctx = UiContext::new(TextGridBatch::new(Size::new(5, 5)));
+        let mut mouse = |slice_view: &mut ModelSlice, x, y, e| {
+            let event = Event::Mouse {
+                pos: mint::Point2::from([x, y]),
+                e,
+            };
+            let _ = slice_view.handle_event(&mut ctx, event, bounds);
+        };
+
+        let left = MouseButton::Left;
+        mouse(
+            &mut slice_view,
+            1,
+            1,
+            MouseEvent::ButtonDown { button: left },
+        );
+        let start_pos = mint::Point2::from([1, 1]);
+        mouse(
+            &mut slice_view,
+            9,
+            9,
+            MouseEvent::ButtonDrag {
+                button: left,
+                start_pos,
+            },
+        );
+        assert!(model.get().iter().all(Option::is_none));
+
+        mouse(&mut slice_view, 9, 9, MouseEvent::ButtonUp { button: left });
+        assert_eq!(edits.borrow().len(), 1);
+        assert_eq!(edits.borrow()[0].cells.len(), 4);
+        assert!((0..4).all(|i| model.get()[(i, i, 0)].is_some()));
+
+        tool.set(ModelTool::Fill);
+        mouse(
+            &mut slice_view,
+            4,
+            1,
+            MouseEvent::ButtonDown { button: left },
+        );
+        assert_eq!(edits.borrow().len(), 2);
+        assert_eq!(edits.borrow()[1].cells.len(), 6);
+    }
+
+    #[test]
+    fn test_model_slice_select() {
+        let edits = Rc::new(RefCell::new(Vec::new()));
+        let model = bind(letters());
+        let selection = bind(None);
+        let mut slice_view = ModelSlice::new(
+            model.clone(),
+            bind(0),
+            bind(ModelTool::Select),
+            bind(None),
+            Rc::new(BTreeMap::new()),
+        )
+        .with_selection(selection.clone())
+        .with_on_edit({
+            let edits = Rc::clone(&edits);
+            move |edit| edits.borrow_mut().push(edit)
+        });
+
+        let bounds = SRect::new(0, 0, 3, 2);
+        let mut ctx = UiContext::new(TextGridBatch::new(Size::new(3, 2)));
+        let left = MouseButton::Left;
+        let mut drag = |slice_view: &mut ModelSlice, from: [i32; 2], to: [i32; 2]| {
+            let start_pos = mint::Point2::from(from);
+            for &(pos, e) in &[
+                (from, MouseEvent::ButtonDown { button: left }),
+                (
+                    to,
+                    MouseEvent::ButtonDrag {
+                        button: left,
+                        start_pos,
+                    },
+                ),
+                (to, MouseEvent::ButtonUp { button: left }),
+            ] {
+                let event = Event::Mouse {
+                    pos: mint::Point2::from(pos),
+                    e,
+                };
+                let _ = slice_view.handle_event(&mut ctx, event, bounds);
+            }
+        };
+
+        drag(&mut slice_view, [1, 1], [0, 0]);
+        assert_eq!(selection.get(), Some(Selection::new((0, 0), (1, 1))));
+        assert!(edits.borrow().is_empty());
+
+        // Dragging from inside moves it, as one edit
+        drag(&mut slice_view, [0, 0], [1, 0]);
+        assert_eq!(selection.get(), Some(Selection::new((1, 0), (2, 1))));
+        assert_eq!(slice_names(&model.get(), 0), [".ab", ".de"]);
+        assert_eq!(edits.borrow().len(), 1);
+
+        let _ = slice_view.handle_event(
+            &mut ctx,
+            Event::Mouse {
+                pos: mint::Point2::from([0, 0]),
+                e: MouseEvent::ButtonDown {
+                    button: MouseButton::Right,
+                },
+            },
+            bounds,
+        );
+        assert_eq!(selection.get(), None);
+    }
+
+    #[test]
+    fn test_editable_model_conversion() {
+        let mut voxels = Array3::from_elem((3, 2, 2), None);
+        voxels[(1, 0, 1)] = Some(IStr::new("wall"));
+        voxels[(2, 1, 1)] = Some(IStr::new("floor"));
+        let model = Model::new(voxels).with_pivot(Point3::new(2.0, 0.5, 1.0));
+
+        // Cropped to the voxels, keeping the pivot where it was among them
+        let cropped = Model::from(EditableModel::from(model));
+        assert_eq!(cropped.voxels.dim(), (2, 2, 1));
+        assert_eq!(cropped.voxels[(0, 0, 0)], Some(IStr::new("wall")));
+        assert_eq!(cropped.voxels[(1, 1, 0)], Some(IStr::new("floor")));
+        assert_eq!(cropped.pivot, Point3::new(1.0, 0.5, 0.0));
+
+        assert_eq!(Model::from(EditableModel::from(cropped.clone())), cropped);
+    }
+}
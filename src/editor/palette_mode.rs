@@ -0,0 +1,320 @@
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use internship::IStr;
+
+use crate::{
+    editor::{
+        panels::{button_row, divider, BoundList},
+        voxel_mode::unique_name,
+    },
+    rendering::{
+        color::{self, Color},
+        voxel::Voxel2,
+    },
+    ui::*,
+};
+
+/// Edits the project's named colors, which the color picker in voxel mode offers first.
+pub(super) struct PaletteMode {
+    pub(super) layout: FlexLayout,
+    pub(super) actions: PaletteEntries,
+}
+
+impl PaletteMode {
+    pub(super) fn new(entries: Binding<Vec<(IStr, Color)>>, current: Option<&IStr>) -> Self {
+        let selected = bind(
+            current.and_then(|current| entries.get().iter().position(|(name, _)| name == current)),
+        );
+
+        let actions = PaletteEntries {
+            entries: entries.clone(),
+            selected: selected.clone(),
+            name: bind(String::new()),
+            red: bind(255),
+            green: bind(255),
+            blue: bind(255),
+        };
+        actions.load();
+
+        let list = SelectableList::from_vec(Vec::new(), selected).with_on_select({
+            let actions = actions.clone();
+            move |_| actions.load()
+        });
+
+        let action_button = |label: &str, action: fn(&PaletteEntries)| {
+            let actions = actions.clone();
+            Button::new(label, move || action(&actions))
+        };
+
+        let list_pane = FlexLayout::vertical(vec![
+            FlexElement::flex(Box::new(BoundList::new(list, entries, palette_row)), 1),
+            FlexElement::fixed(Box::new(button_row(vec![
+                action_button("New", PaletteEntries::create),
+                action_button("Delete", PaletteEntries::delete),
+            ]))),
+        ]);
+
+        let channel = |label: &str, value: &Binding<i32>| {
+            let store = |actions: &PaletteEntries| {
+                let actions = actions.clone();
+                move |_| actions.store_color()
+            };
+
+            FlexElement::fixed(Box::new(FlexLayout::horizontal(vec![
+                FlexElement::fixed(Box::new(KataText::from_str(label))),
+                FlexElement::flex(
+                    Box::new(Slider::new(value.clone(), 0, 255).with_on_change(store(&actions))),
+                    1,
+                ),
+                FlexElement::fixed(Box::new(
+                    Stepper::new(value.clone(), 0, 255).with_on_change(store(&actions)),
+                )),
+            ])))
+        };
+
+        let preview = VoxelDisplay::new(flo_binding::computed({
+            let (red, green, blue) = (
+                actions.red.clone(),
+                actions.green.clone(),
+                actions.blue.clone(),
+            );
+            move || {
+                Voxel2::new(0).background(Some(Color::new(
+                    red.get() as u8,
+                    green.get() as u8,
+                    blue.get() as u8,
+                )))
+            }
+        }))
+        .with_magnification(PALETTE_PREVIEW_SIZE);
+
+        let name_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Name "))),
+            FlexElement::flex(
+                Box::new(TextInput::new(actions.name.clone()).with_on_submit({
+                    let actions = actions.clone();
+                    move |name: String| actions.rename(&name)
+                })),
+                1,
+            ),
+        ]);
+
+        let hex = bind(String::new());
+        let import = {
+            let actions = actions.clone();
+            let hex = hex.clone();
+            move || {
+                actions.import_hex(&hex.get());
+                hex.set(String::new());
+            }
+        };
+
+        let hex_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Hex "))),
+            FlexElement::flex(
+                Box::new(TextInput::new(hex).with_on_submit({
+                    let import = import.clone();
+                    move |_| import()
+                })),
+                1,
+            ),
+            FlexElement::fixed(Box::new(Padding::new(
+                Button::new("Import", import),
+                0,
+                0,
+                0,
+                1,
+            ))),
+        ]);
+
+        let color_pane = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(name_row)),
+            channel("R ", &actions.red),
+            channel("G ", &actions.green),
+            channel("B ", &actions.blue),
+            FlexElement::fixed(Box::new(Padding::new(preview, 1, 0, 1, 0))),
+            FlexElement::fixed(Box::new(hex_row)),
+        ]);
+
+        Self {
+            layout: FlexLayout::horizontal(vec![
+                FlexElement::flex(Box::new(Frame::new(list_pane).with_title("Palette")), 1),
+                FlexElement::fixed(divider()),
+                FlexElement::flex(Box::new(Frame::new(color_pane).with_title("Color")), 1),
+            ]),
+            actions,
+        }
+    }
+}
+
+/// The named colors with the selected one loaded into a name field and sliders, which write
+/// back to it as they're changed.
+#[derive(Clone)]
+pub(super) struct PaletteEntries {
+    pub(super) entries: Binding<Vec<(IStr, Color)>>,
+    pub(super) selected: Binding<Option<usize>>,
+    pub(super) name: Binding<String>,
+    red: Binding<i32>,
+    green: Binding<i32>,
+    blue: Binding<i32>,
+}
+
+impl PaletteEntries {
+    pub(super) fn selected_entry(&self) -> Option<(IStr, Color)> {
+        self.selected
+            .get()
+            .and_then(|index| self.entries.get().get(index).cloned())
+    }
+
+    pub(super) fn selected_name(&self) -> Option<IStr> {
+        self.selected_entry().map(|(name, _)| name)
+    }
+
+    fn mixed(&self) -> Color {
+        Color::new(
+            self.red.get() as u8,
+            self.green.get() as u8,
+            self.blue.get() as u8,
+        )
+    }
+
+    /// Shows the selected entry in the name field and the sliders.
+    pub(super) fn load(&self) {
+        if let Some((name, color)) = self.selected_entry() {
+            let (r, g, b) = color.rgb();
+            self.name.set(String::from(&*name));
+            self.red.set(i32::from(r));
+            self.green.set(i32::from(g));
+            self.blue.set(i32::from(b));
+        }
+    }
+
+    /// Sets the color of the selected entry to the one mixed by the sliders.
+    fn store_color(&self) {
+        if let Some(index) = self.selected.get() {
+            let mut entries = self.entries.get();
+
+            if let Some(entry) = entries.get_mut(index) {
+                entry.1 = self.mixed();
+                self.entries.set(entries);
+            }
+        }
+    }
+
+    /// Renames the selected entry, unless `name` is empty or taken, in which case the name field
+    /// goes back to the old name.
+    pub(super) fn rename(&self, name: &str) {
+        let name = name.trim();
+        let index = match self.selected.get() {
+            Some(index) => index,
+            None => return,
+        };
+
+        let mut entries = self.entries.get();
+        let taken = entries
+            .iter()
+            .enumerate()
+            .any(|(i, (other, _))| i != index && &**other == name);
+
+        if !name.is_empty() && !taken && index < entries.len() {
+            entries[index].0 = IStr::new(name);
+            self.entries.set(entries);
+        }
+
+        self.load();
+    }
+
+    pub(super) fn create(&self) {
+        self.push(self.mixed());
+    }
+
+    pub(super) fn delete(&self) {
+        if let Some(index) = self.selected.get() {
+            let mut entries = self.entries.get();
+
+            if index < entries.len() {
+                entries.remove(index);
+                let selected = if entries.is_empty() {
+                    None
+                } else {
+                    Some(index.min(entries.len() - 1))
+                };
+
+                self.entries.set(entries);
+                self.selected.set(selected);
+                self.load();
+            }
+        }
+    }
+
+    /// Adds an entry for each `#RRGGBB` color in `text`, which can be separated by spaces or
+    /// commas. Returns how many there were.
+    fn import_hex(&self, text: &str) -> usize {
+        let colors: Vec<Color> = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter_map(Color::from_hex)
+            .collect();
+
+        for &color in &colors {
+            self.push(color);
+        }
+
+        colors.len()
+    }
+
+    /// Adds `color` under a new name at the end and selects it.
+    pub(super) fn push(&self, color: Color) {
+        let mut entries = self.entries.get();
+        let name = unique_name(&entries, "color");
+        entries.push((name, color));
+
+        self.selected.set(Some(entries.len() - 1));
+        self.entries.set(entries);
+        self.load();
+    }
+}
+
+/// How many cells either way the palette mode's preview swatch takes up.
+const PALETTE_PREVIEW_SIZE: u32 = 4;
+
+fn palette_row(entry: &(IStr, Color)) -> Box<dyn Row> {
+    let (name, color) = entry.clone();
+    let mut text = KataText::from_voxels(vec![Voxel2::new(0).background(Some(color))]);
+    text.push_span(&format!(" {}", &*name), color::WHITE, None);
+
+    Box::new(Tooltip::new(text, move |_, _| {
+        Some(format!("{} {}", &*name, color.to_hex()))
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_palette_entries() {
+        let moss = (IStr::new("moss"), Color::new(40, 90, 30));
+        let mode = PaletteMode::new(bind(vec![moss.clone()]), Some(&IStr::new("moss")));
+        let actions = &mode.actions;
+
+        assert_eq!(actions.selected.get(), Some(0));
+        assert_eq!(actions.name.get(), "moss");
+        assert_eq!(actions.green.get(), 90);
+
+        actions.red.set(50);
+        actions.store_color();
+        assert_eq!(actions.entries.get()[0].1, Color::new(50, 90, 30));
+
+        assert_eq!(actions.import_hex("#102030, nope 405060"), 2);
+        assert_eq!(actions.selected_name(), Some(IStr::new("color 2")));
+        assert_eq!(actions.red.get(), 0x40);
+
+        actions.rename("moss");
+        assert_eq!(actions.name.get(), "color 2");
+        actions.rename(" lichen ");
+        assert_eq!(actions.entries.get()[2].0, IStr::new("lichen"));
+
+        actions.delete();
+        assert_eq!(actions.entries.get().len(), 2);
+        assert_eq!(actions.selected_name(), Some(IStr::new("color")));
+    }
+}
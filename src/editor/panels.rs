@@ -0,0 +1,1077 @@
+use std::{cell::RefCell, f32::consts::PI, rc::Rc};
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::{input::mouse::MouseButton, mint};
+use internship::IStr;
+use na::{Point3, Rotation3, Vector3};
+use ndarray::Array3;
+
+use crate::{
+    editor::{history::EditorAction, voxel_mode::VoxelEntries, SHORTCUTS},
+    geometry::rect::SRect,
+    rendering::{
+        self,
+        color::{self, Color},
+        voxel::{Model, Voxel2, Voxel3, VoxelFace},
+    },
+    ui::*,
+};
+
+/// A `SelectableList` of the voxel names, which is rebuilt whenever entries are added, renamed
+/// or removed. The list has to start out with a row for each entry.
+pub(super) struct VoxelList {
+    list: SelectableList,
+    pub(super) entries: Rc<RefCell<Vec<(IStr, Voxel3)>>>,
+    shown: Vec<IStr>,
+}
+
+impl VoxelList {
+    pub(super) fn new(list: SelectableList, entries: Rc<RefCell<Vec<(IStr, Voxel3)>>>) -> Self {
+        let mut it = Self {
+            list,
+            entries,
+            shown: Vec::new(),
+        };
+
+        it.shown = it.names();
+        it
+    }
+
+    fn names(&self) -> Vec<IStr> {
+        self.entries
+            .borrow()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    fn is_stale(&self) -> bool {
+        self.shown != self.names()
+    }
+
+    fn rebuild(&mut self) {
+        let names = self.names();
+        self.list.set_rows(names.iter().map(voxel_row).collect());
+        self.shown = names;
+    }
+}
+
+impl Element for VoxelList {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        if self.is_stale() {
+            self.rebuild();
+        }
+
+        self.list.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            if self.is_stale() {
+                ctx.relayout = true;
+            }
+        }
+
+        self.list.handle_event(ctx, event, bounds)
+    }
+}
+
+/// A list with a row for each of `items`, rebuilt whenever they change, e.g. the results of a
+/// usage search while it's running.
+pub(super) struct BoundList<T> {
+    list: SelectableList,
+    items: Binding<Vec<T>>,
+    row: fn(&T) -> Box<dyn Row>,
+    shown: Vec<T>,
+}
+
+impl<T: Clone + PartialEq + Send + 'static> BoundList<T> {
+    pub(super) fn new(
+        list: SelectableList,
+        items: Binding<Vec<T>>,
+        row: fn(&T) -> Box<dyn Row>,
+    ) -> Self {
+        Self {
+            list,
+            items,
+            row,
+            shown: Vec::new(),
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.shown != self.items.get()
+    }
+
+    fn rebuild(&mut self) {
+        let items = self.items.get();
+        self.list.set_rows(items.iter().map(self.row).collect());
+        self.shown = items;
+    }
+}
+
+impl<T: Clone + PartialEq + Send + 'static> Element for BoundList<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        if self.is_stale() {
+            self.rebuild();
+        }
+
+        self.list.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            if self.is_stale() {
+                ctx.relayout = true;
+            }
+        }
+
+        self.list.handle_event(ctx, event, bounds)
+    }
+}
+
+/// How many times as big the glyph browser shows the hovered glyph.
+pub(super) const GLYPH_MAGNIFICATION: u32 = 4;
+/// How wide the frame scrubber is, which fits its label and a dozen or so frames.
+const FRAME_SCRUBBER_WIDTH: u32 = 24;
+
+/// Reads a glyph index typed in hex, with or without a leading `0x`.
+pub(super) fn parse_glyph_index(text: &str, glyph_count: u16) -> Option<u16> {
+    let text = text.trim();
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+
+    u16::from_str_radix(digits, 16)
+        .ok()
+        .filter(|&glyph| glyph < glyph_count)
+}
+
+/// The index of the hovered glyph, or of the active face's glyph when nothing is hovered.
+pub(super) struct GlyphIndex {
+    pub(super) hovered: Binding<Option<usize>>,
+    pub(super) active: Binding<i32>,
+}
+
+impl Element for GlyphIndex {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        constraints.constrain(Size::new(5, 2))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            let (label, glyph) = match self.hovered.get() {
+                Some(glyph) => ("Hover", glyph),
+                None => ("Face", self.active.get() as usize),
+            };
+
+            for (y, line) in [String::from(label), format!("0x{:03X}", glyph)]
+                .iter()
+                .enumerate()
+                .take(bounds.h as usize)
+            {
+                let text = KataText::from_str(line);
+
+                for (x, voxel) in text.voxels.iter().take(bounds.w as usize).enumerate() {
+                    ctx.draw(voxel, [bounds.x + x as i32, bounds.y + y as i32]);
+                }
+            }
+        }
+
+        Ok(Continue)
+    }
+}
+
+/// A cell for each frame of the voxel's animation, with the one shown marked. Clicking a cell
+/// edits that frame. While the animation plays it moves the shown frame along with the UI.
+pub(super) struct FrameScrubber {
+    pub(super) actions: VoxelEntries,
+}
+
+impl FrameScrubber {
+    /// The label, and the cells that come after it.
+    fn line(&self) -> (String, String) {
+        let count = self.actions.voxel.get().frame_count();
+        let active = self.actions.active_frame.get().min(count - 1);
+        let cells = (0..count)
+            .map(|i| if i == active { '#' } else { '-' })
+            .collect();
+
+        (format!("Frame {}/{} ", active + 1, count), cells)
+    }
+}
+
+impl Element for FrameScrubber {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        constraints.constrain(Size::new(FRAME_SCRUBBER_WIDTH, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event.cull(bounds) {
+            Some(Event::Mouse {
+                pos,
+                e:
+                    MouseEvent::ButtonDown {
+                        button: MouseButton::Left,
+                    },
+            }) => {
+                let (label, cells) = self.line();
+                let x = (pos.x - bounds.x) as usize;
+
+                if x >= label.len() && x < label.len() + cells.len() {
+                    self.actions.show_frame(x - label.len());
+                }
+
+                return Err(Stop);
+            }
+
+            Some(Event::Draw) => {
+                if self.actions.playing.get() {
+                    let frame = self.actions.voxel.get().frame_at(ctx.frame);
+                    if frame != self.actions.active_frame.get() {
+                        self.actions.active_frame.set(frame);
+                    }
+                }
+
+                let (label, cells) = self.line();
+                let text = KataText::from_str(&(label + &cells));
+                for (x, voxel) in text.voxels.iter().take(bounds.w as usize).enumerate() {
+                    ctx.draw(voxel, [bounds.x + x as i32, bounds.y]);
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+/// The selected voxel, on the left, and another voxel to compare it with, on the right.
+#[derive(Clone)]
+pub(super) struct VoxelComparison {
+    pub(super) actions: VoxelEntries,
+    /// The index of the voxel on the right.
+    pub(super) right: Binding<usize>,
+}
+
+impl VoxelComparison {
+    pub(super) fn left(&self) -> Voxel3 {
+        self.actions.voxel.get()
+    }
+
+    /// The voxel on the right with its name, read afresh since undoing can change it too.
+    pub(super) fn right(&self) -> Option<(IStr, Voxel3)> {
+        self.actions.entries.borrow().get(self.right.get()).cloned()
+    }
+
+    /// Moves the right side on by `steps` voxels, or back if it's negative, skipping the one on
+    /// the left.
+    pub(super) fn step(&self, steps: i32) {
+        let count = self.actions.entries.borrow().len() as i32;
+        let selected = self.actions.selected_voxel.get();
+        let mut index = self.right.get() as i32;
+
+        loop {
+            index = (index + steps).rem_euclid(count.max(1));
+            if count < 2 || Some(index as usize) != selected {
+                break;
+            }
+        }
+
+        self.right.set(index as usize);
+    }
+
+    /// Copies `face` of the voxel on the left over to the one on the right, as an edit that can
+    /// be undone.
+    pub(super) fn copy_face(&self, face: VoxelFace) {
+        let index = self.right.get();
+        let old = match self.right() {
+            Some((_, old)) => old,
+            None => return,
+        };
+
+        let mut new = old.clone();
+        new[face] = self.left()[face].clone();
+        if new != old {
+            self.actions
+                .apply(EditorAction::SetVoxel { index, old, new });
+        }
+    }
+}
+
+/// How far apart the glyphs of a face comparison are.
+const COMPARISON_GAP: u32 = 1;
+
+/// One face of both voxels of a comparison side by side, framed in yellow if they differ.
+pub(super) struct FaceComparison {
+    comparison: VoxelComparison,
+    pub(super) face: VoxelFace,
+    pub(super) left: Binding<Voxel2>,
+    pub(super) right: Binding<Voxel2>,
+    pub(super) frame: Frame<FlexLayout>,
+}
+
+impl FaceComparison {
+    pub(super) fn new(comparison: VoxelComparison, face: VoxelFace) -> Self {
+        let left = bind(Voxel2::default());
+        let right = bind(Voxel2::default());
+        let title = match face {
+            VoxelFace::X => "X",
+            VoxelFace::Y => "Y",
+            VoxelFace::Z => "Z",
+        };
+
+        let frame = Frame::new(FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(
+                VoxelDisplay::new(left.clone()).with_magnification(GLYPH_MAGNIFICATION),
+            )),
+            FlexElement::fixed(Box::new(Padding::new(
+                VoxelDisplay::new(right.clone()).with_magnification(GLYPH_MAGNIFICATION),
+                0,
+                0,
+                0,
+                COMPARISON_GAP,
+            ))),
+        ]))
+        .with_title(title);
+
+        Self {
+            comparison,
+            face,
+            left,
+            right,
+            frame,
+        }
+    }
+}
+
+impl Element for FaceComparison {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.frame.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            let left = self.comparison.left()[self.face].clone();
+            let right = self
+                .comparison
+                .right()
+                .map(|(_, voxel)| voxel[self.face].clone())
+                .unwrap_or_default();
+
+            self.frame.set_border_color(if left.diff(&right).any() {
+                color::YELLOW
+            } else {
+                color::WHITE
+            });
+
+            if self.left.get() != left {
+                self.left.set(left);
+            }
+            if self.right.get() != right {
+                self.right.set(right);
+            }
+        }
+
+        self.frame.handle_event(ctx, event, bounds)
+    }
+}
+
+/// The names of the compared voxels, and how many of their faces differ.
+pub(super) struct ComparedNames {
+    pub(super) comparison: VoxelComparison,
+}
+
+impl ComparedNames {
+    pub(super) fn lines(&self) -> [String; 2] {
+        let actions = &self.comparison.actions;
+        let left = actions.selected_voxel.get().and_then(|index| {
+            actions
+                .entries
+                .borrow()
+                .get(index)
+                .map(|(name, _)| name.clone())
+        });
+        let right = self.comparison.right();
+        let name = |name: Option<&IStr>| {
+            name.map_or_else(|| String::from("?"), |name| String::from(&**name))
+        };
+
+        let diff = right
+            .as_ref()
+            .map(|(_, voxel)| self.comparison.left().diff(voxel));
+        let differing = [VoxelFace::X, VoxelFace::Y, VoxelFace::Z]
+            .iter()
+            .filter(|&&face| diff.map_or(false, |diff| diff.face(face).any()))
+            .count();
+
+        [
+            format!(
+                "{} vs {}",
+                name(left.as_ref()),
+                name(right.as_ref().map(|(name, _)| name))
+            ),
+            format!("{} of 3 faces differ", differing),
+        ]
+    }
+}
+
+impl Element for ComparedNames {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        // As wide as the faces below it, each framed and followed by a column of padding
+        let width = 3 * (2 * GLYPH_MAGNIFICATION + COMPARISON_GAP + 3);
+        constraints.constrain(Size::new(width, 2))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            for (y, line) in self.lines().iter().enumerate().take(bounds.h as usize) {
+                let text = KataText::from_str(line);
+
+                for (x, voxel) in text.voxels.iter().take(bounds.w as usize).enumerate() {
+                    ctx.draw(voxel, [bounds.x + x as i32, bounds.y + y as i32]);
+                }
+            }
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub(super) fn help_panel() -> Centered<PanelBackground<Frame<FlexLayout>>> {
+    let key_width = SHORTCUTS
+        .iter()
+        .map(|(key, _)| key.len())
+        .max()
+        .unwrap_or(0);
+
+    let rows = SHORTCUTS
+        .iter()
+        .map(|(key, action)| {
+            let mut text = KataText::from_voxels(Vec::new());
+            text.push_span(&format!("{:1$} ", key, key_width), color::YELLOW, None);
+            text.push_span(action, color::WHITE, None);
+            FlexElement::fixed(Box::new(text))
+        })
+        .collect();
+
+    Centered::new(PanelBackground::new(
+        Frame::new(FlexLayout::vertical(rows)).with_title("Shortcuts"),
+    ))
+}
+
+pub(super) fn dialog(
+    title: &str,
+    mut body: Vec<FlexElement>,
+    buttons: Vec<Button>,
+) -> impl Element {
+    body.push(FlexElement::fixed(Box::new(button_row(buttons))));
+    PanelBackground::new(Frame::new(FlexLayout::vertical(body)).with_title(title))
+}
+
+pub(super) fn button_row(buttons: Vec<Button>) -> FlexLayout {
+    FlexLayout::horizontal(
+        buttons
+            .into_iter()
+            .map(|button| FlexElement::fixed(Box::new(Padding::new(button, 0, 1, 0, 0))))
+            .collect(),
+    )
+}
+
+/// Swatches of the palette's colors, the built-in ones and custom ones, with sliders to mix new
+/// colors. Left clicking a color uses it
+/// as the foreground of the active face and right clicking as the background.
+pub(super) fn color_picker(
+    voxel: &Binding<Voxel3>,
+    set_voxel: &Rc<dyn Fn(Voxel3)>,
+    active_face: &Binding<VoxelFace>,
+    active_frame: &Binding<usize>,
+    named_colors: &Binding<Vec<(IStr, Color)>>,
+    palette: &Binding<Vec<Color>>,
+) -> FlexLayout {
+    let red = bind(255);
+    let green = bind(255);
+    let blue = bind(255);
+    let mixed = {
+        let (red, green, blue) = (red.clone(), green.clone(), blue.clone());
+        move || Color::new(red.get() as u8, green.get() as u8, blue.get() as u8)
+    };
+
+    // Sets the foreground of the active face with the left button and the background with the
+    // right one
+    let set_color: Rc<dyn Fn(Option<Color>, MouseButton)> = Rc::new({
+        let voxel = voxel.clone();
+        let set_voxel = Rc::clone(set_voxel);
+        let active_face = active_face.clone();
+        let active_frame = active_frame.clone();
+        move |color: Option<Color>, button: MouseButton| {
+            let mut new_voxel = voxel.get();
+            let face = &mut new_voxel.frame_mut(active_frame.get())[active_face.get()];
+
+            match (button, color) {
+                (MouseButton::Left, Some(color)) => face.foreground = color,
+                (MouseButton::Right, background) => face.background = background,
+                _ => return,
+            }
+
+            set_voxel(new_voxel);
+        }
+    });
+
+    let swatches = ColorPalette::new(
+        {
+            let named_colors = named_colors.clone();
+            let palette = palette.clone();
+            flo_binding::computed(move || {
+                named_colors
+                    .get()
+                    .into_iter()
+                    .map(|(_, color)| color)
+                    .chain(color::NAMED.iter().map(|&(_, color)| color))
+                    .chain(palette.get())
+                    .collect()
+            })
+        },
+        {
+            let set_color = Rc::clone(&set_color);
+            let (red, green, blue) = (red.clone(), green.clone(), blue.clone());
+            move |color: Color, button| {
+                // Also load it into the sliders to make variations of it
+                let (r, g, b) = color.rgb();
+                red.set(i32::from(r));
+                green.set(i32::from(g));
+                blue.set(i32::from(b));
+
+                set_color(Some(color), button);
+            }
+        },
+    );
+
+    let channel = |label: &str, value: &Binding<i32>| {
+        FlexElement::fixed(Box::new(FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str(label))),
+            FlexElement::flex(Box::new(Slider::new(value.clone(), 0, 255)), 1),
+            FlexElement::fixed(Box::new(Stepper::new(value.clone(), 0, 255))),
+        ])))
+    };
+
+    let preview = VoxelDisplay::new(flo_binding::computed({
+        let mixed = mixed.clone();
+        move || Voxel2::new(0).background(Some(mixed()))
+    }));
+
+    let use_mixed = |label: &str, button: MouseButton| {
+        let set_color = Rc::clone(&set_color);
+        let mixed = mixed.clone();
+        Button::new(label, move || set_color(Some(mixed()), button))
+    };
+
+    let buttons = button_row(vec![
+        use_mixed("Fg", MouseButton::Left),
+        use_mixed("Bg", MouseButton::Right),
+        Button::new("No bg", {
+            let set_color = Rc::clone(&set_color);
+            move || set_color(None, MouseButton::Right)
+        }),
+        Button::new("Add", {
+            let named_colors = named_colors.clone();
+            let palette = palette.clone();
+            let mixed = mixed.clone();
+            move || {
+                let color = mixed();
+                let mut colors = palette.get();
+
+                let named = color::NAMED.iter().any(|&(_, named)| named == color)
+                    || named_colors.get().iter().any(|&(_, named)| named == color);
+                if !named && !colors.contains(&color) {
+                    colors.push(color);
+                    palette.set(colors);
+                }
+            }
+        }),
+    ]);
+
+    FlexLayout::vertical(vec![
+        FlexElement::flex(Box::new(ScrollView::new(swatches)), 1),
+        channel("R ", &red),
+        channel("G ", &green),
+        channel("B ", &blue),
+        FlexElement::fixed(Box::new(FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(Padding::new(preview, 0, 1, 0, 0))),
+            FlexElement::fixed(Box::new(buttons)),
+        ]))),
+    ])
+}
+
+/// Swatches of `colors`, wrapped to the available width, that call `on_pick` with the color and
+/// the button it was clicked with.
+struct ColorPalette<B> {
+    colors: B,
+    on_pick: Box<dyn FnMut(Color, MouseButton)>,
+    pub(super) width: u32,
+    shown_len: usize,
+}
+
+impl<B: Bound<Vec<Color>>> ColorPalette<B> {
+    pub(super) fn new<F>(colors: B, on_pick: F) -> Self
+    where
+        F: FnMut(Color, MouseButton) + 'static,
+    {
+        Self {
+            colors,
+            on_pick: Box::new(on_pick),
+            width: 1,
+            shown_len: 0,
+        }
+    }
+
+    pub(super) fn index_at(&self, bounds: SRect, pos: mint::Point2<i32>) -> Option<usize> {
+        if bounds.contains(pos) {
+            let (x, y) = ((pos.x - bounds.x) as u32, (pos.y - bounds.y) as u32);
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl<B: Bound<Vec<Color>>> Element for ColorPalette<B> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        let len = self.colors.get().len();
+        self.shown_len = len;
+        self.width = if constraints.is_bounded(LayoutDirection::Horizontal) {
+            constraints.max.width.max(1)
+        } else {
+            len.max(1) as u32
+        };
+
+        let rows = (len as u32 + self.width - 1) / self.width;
+        constraints.constrain(Size::new(self.width, rows))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let colors = self.colors.get();
+
+        match event.cull(bounds) {
+            Some(Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDown { button },
+            }) => {
+                if let Some(&color) = self.index_at(bounds, pos).and_then(|i| colors.get(i)) {
+                    (self.on_pick)(color, button);
+                    return Err(Stop);
+                }
+            }
+
+            Some(Event::Draw) => {
+                if colors.len() != self.shown_len {
+                    ctx.relayout = true;
+                }
+
+                for (i, &color) in colors.iter().enumerate() {
+                    let i = i as u32;
+                    ctx.draw(
+                        &Voxel2::new(0).background(Some(color)),
+                        [
+                            bounds.x + (i % self.width) as i32,
+                            bounds.y + (i / self.width) as i32,
+                        ],
+                    );
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+/// How far the preview turns on each Draw pass while it isn't being dragged.
+const PREVIEW_SPIN: f32 = 0.01;
+
+/// A rotating 3D view of some voxels, drawn with a glyph per voxel the way the world is. Each
+/// voxel shows the face that points the most towards the camera. Dragging with the left button
+/// orbits the camera.
+pub(super) struct VoxelPreview<B> {
+    pub(super) voxels: B,
+    /// What the camera orbits around, or the middle of the voxels if there's nothing set.
+    pub(super) pivot: Option<Box<dyn Bound<Point3<f32>>>>,
+    yaw: f32,
+    pitch: f32,
+    pub(super) drag: Option<mint::Point2<i32>>,
+    last_frame: Option<u64>,
+}
+
+impl<B: Bound<Array3<Option<Voxel3>>>> VoxelPreview<B> {
+    pub(super) fn new(voxels: B) -> Self {
+        Self {
+            voxels,
+            pivot: None,
+            yaw: 0.0,
+            pitch: -0.5,
+            drag: None,
+            last_frame: None,
+        }
+    }
+
+    pub(super) fn with_pivot<P>(mut self, pivot: P) -> Self
+    where
+        P: Bound<Point3<f32>> + 'static,
+    {
+        self.pivot = Some(Box::new(pivot));
+        self
+    }
+
+    /// Projects the voxels onto a grid of `size`, keeping the nearest one in each cell.
+    fn render(&self, size: Size) -> Vec<(mint::Point2<u32>, Voxel2)> {
+        let voxels = self.voxels.get();
+        let (w, h, d) = voxels.dim();
+        if voxels.is_empty() || size.width == 0 || size.height == 0 {
+            return Vec::new();
+        }
+
+        let center = self
+            .pivot
+            .as_ref()
+            .map_or_else(|| Model::center((w, h, d)), |pivot| pivot.get());
+
+        // Far enough away to fit the whole thing from the pivot, and past the near plane
+        let corner = |side: usize, pivot: f32| pivot.max(side as f32 - 1.0 - pivot) + 0.5;
+        let radius = Vector3::new(
+            corner(w, center.x),
+            corner(h, center.y),
+            corner(d, center.z),
+        )
+        .norm();
+        let distance = radius * 1.5 + 1.0;
+
+        let rotation = Rotation3::from_euler_angles(self.pitch, self.yaw, 0.0);
+        let eye = center + rotation.transform_vector(&Vector3::new(0.0, 0.0, distance));
+        let model_view_projection =
+            rendering::util::view_projection(&eye, &center, size.width as f32 / size.height as f32);
+
+        let mut cells: Vec<Option<(f32, Voxel2)>> = vec![None; (size.width * size.height) as usize];
+
+        for ((x, y, z), voxel) in voxels.indexed_iter() {
+            let voxel = match voxel {
+                Some(voxel) => voxel,
+                None => continue,
+            };
+
+            let pos = Point3::new(x as f32, y as f32, z as f32);
+            let screen_pos =
+                match Point3::from_homogeneous(model_view_projection * pos.to_homogeneous()) {
+                    Some(screen_pos) if screen_pos.z >= -1.0 && screen_pos.z <= 1.0 => screen_pos,
+                    _ => continue,
+                };
+
+            let cell_x = (screen_pos.x + 1.0) / 2.0 * size.width as f32;
+            let cell_y = (1.0 - screen_pos.y) / 2.0 * size.height as f32;
+            if cell_x < 0.0 || cell_y < 0.0 {
+                continue;
+            }
+
+            let (cell_x, cell_y) = (cell_x as u32, cell_y as u32);
+            if cell_x >= size.width || cell_y >= size.height {
+                continue;
+            }
+
+            let view = pos - eye;
+            let face = if view.x.abs() >= view.y.abs() && view.x.abs() >= view.z.abs() {
+                VoxelFace::X
+            } else if view.y.abs() >= view.z.abs() {
+                VoxelFace::Y
+            } else {
+                VoxelFace::Z
+            };
+
+            let cell = &mut cells[(cell_y * size.width + cell_x) as usize];
+            if cell
+                .as_ref()
+                .map_or(true, |&(depth, _)| screen_pos.z < depth)
+            {
+                *cell = Some((screen_pos.z, voxel[face].clone()));
+            }
+        }
+
+        cells
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, cell)| {
+                cell.map(|(_, voxel)| {
+                    let i = i as u32;
+                    (mint::Point2::from([i % size.width, i / size.width]), voxel)
+                })
+            })
+            .collect()
+    }
+}
+
+impl<B: Bound<Array3<Option<Voxel3>>>> Element for VoxelPreview<B> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        constraints.max
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event.cull(bounds) {
+            Some(Event::Mouse { pos, e }) => match e {
+                MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                } => {
+                    self.drag = Some(pos);
+                    return Err(Stop);
+                }
+
+                MouseEvent::ButtonDrag {
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    if let Some(last) = self.drag {
+                        self.yaw -= (pos.x as f32 - last.x as f32) * 0.1;
+                        self.pitch = (self.pitch - (pos.y as f32 - last.y as f32) * 0.1)
+                            .max(-1.5)
+                            .min(1.5);
+                        self.drag = Some(pos);
+                    }
+
+                    return Err(Stop);
+                }
+
+                _ => {}
+            },
+
+            Some(Event::Draw) => {
+                if self.drag.is_none() {
+                    if let Some(last_frame) = self.last_frame {
+                        let frames = ctx.frame.saturating_sub(last_frame);
+                        self.yaw = (self.yaw + frames as f32 * PREVIEW_SPIN) % (2.0 * PI);
+                    }
+                }
+
+                self.last_frame = Some(ctx.frame);
+
+                for (pos, voxel) in self.render(bounds.size()) {
+                    ctx.draw(&voxel, [bounds.x + pos.x as i32, bounds.y + pos.y as i32]);
+                }
+            }
+
+            _ => {}
+        }
+
+        // Stop dragging wherever the button is released
+        if let Event::Mouse {
+            e: MouseEvent::ButtonUp {
+                button: MouseButton::Left,
+            },
+            ..
+        } = event
+        {
+            self.drag = None;
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub(super) const MAX_RECENT_VOXELS: usize = 10;
+
+/// A horizontal strip with the X face of each recently selected voxel, most recent first.
+/// Clicking one loads it again.
+pub(super) struct RecentStrip {
+    list: List,
+    pub(super) recent: Binding<Vec<usize>>,
+    shown: Option<Vec<(usize, IStr)>>,
+    pub(super) entries: Rc<RefCell<Vec<(IStr, Voxel3)>>>,
+    pub(super) load_voxel: Rc<dyn Fn(usize)>,
+}
+
+impl RecentStrip {
+    pub(super) fn new(
+        recent: Binding<Vec<usize>>,
+        entries: Rc<RefCell<Vec<(IStr, Voxel3)>>>,
+        load_voxel: Rc<dyn Fn(usize)>,
+    ) -> Self {
+        Self {
+            list: List::new().with_direction(LayoutDirection::Horizontal),
+            recent,
+            shown: None,
+            entries,
+            load_voxel,
+        }
+    }
+
+    /// The recent entries along with their names, so that renaming one also rebuilds the strip.
+    pub(super) fn current(&self) -> Vec<(usize, IStr)> {
+        let entries = self.entries.borrow();
+
+        self.recent
+            .get()
+            .into_iter()
+            .filter_map(|index| entries.get(index).map(|(name, _)| (index, name.clone())))
+            .collect()
+    }
+
+    fn is_stale(&self) -> bool {
+        self.shown.as_ref() != Some(&self.current())
+    }
+
+    fn rebuild(&mut self) {
+        let recent = self.current();
+        let entries = self.entries.borrow();
+
+        let elements: Vec<ListElement> = recent
+            .iter()
+            .map(|(index, name)| {
+                let index = *index;
+                let voxel = &entries[index].1;
+                let name = name.clone();
+                let load_voxel = Rc::clone(&self.load_voxel);
+
+                ListElement::new(Box::new(
+                    Tooltip::new(
+                        KataText::from_voxels(vec![voxel[VoxelFace::X].clone()]),
+                        move |_, _| Some(String::from(&*name)),
+                    )
+                    .with_events(move |_self, _ctx, e, bounds| {
+                        match e.cull(bounds) {
+                            Some(Event::Mouse {
+                                e:
+                                    MouseEvent::ButtonDown {
+                                        button: MouseButton::Left,
+                                    },
+                                ..
+                            }) => {
+                                load_voxel(index);
+                                Err(Stop)
+                            }
+
+                            _ => Ok(Continue),
+                        }
+                    }),
+                ))
+            })
+            .collect();
+
+        drop(entries);
+        *self.list.elements_mut() = elements;
+        self.shown = Some(recent);
+    }
+}
+
+impl Element for RecentStrip {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        if self.is_stale() {
+            self.rebuild();
+        }
+
+        let size = self.list.layout(constraints);
+        constraints.constrain(Size::new(constraints.max.width, size.height.max(1)))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            if self.is_stale() {
+                ctx.relayout = true;
+            }
+        }
+
+        self.list.handle_event(ctx, event, bounds)
+    }
+}
+
+fn placeholder<N, F>(char_offset: N, color: Color, size_fn: F) -> Box<dyn Element>
+where
+    N: Into<u16>,
+    F: Fn(BoxConstraints) -> Size + 'static,
+{
+    Box::new(Placeholder::new(
+        Voxel2::new(char_offset.into()).background(Some(color)),
+        size_fn,
+    ))
+}
+
+pub(super) fn divider() -> Box<dyn Element> {
+    placeholder(0x266u16, color::BLACK, |c| Size::new(1, c.max.height))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_glyph_index() {
+        assert_eq!(parse_glyph_index("df", 1024), Some(0xDF));
+        assert_eq!(parse_glyph_index(" 0x2C3 ", 1024), Some(0x2C3));
+        assert_eq!(parse_glyph_index("0X10", 1024), Some(0x10));
+        assert_eq!(parse_glyph_index("400", 1024), None);
+        assert_eq!(parse_glyph_index("xyz", 1024), None);
+        assert_eq!(parse_glyph_index("", 1024), None);
+    }
+
+    #[test]
+    fn test_color_palette() {
+        let picked = Rc::new(RefCell::new(None));
+        let colors: Vec<Color> = color::NAMED.iter().map(|&(_, color)| color).collect();
+        let mut palette = ColorPalette::new(bind(colors), {
+            let picked = Rc::clone(&picked);
+            move |color, button| *picked.borrow_mut() = Some((color, button))
+        });
+
+        let size = palette.layout(BoxConstraints::new(Size::ZERO, Size::new(4, 10)));
+        assert_eq!(size, Size::new(4, 3));
+
+        let bounds = SRect::new(1, 1, 4, 3);
+        let mut ctx = UiContext::new(TextGridBatch::new(Size::new(5, 4)));
+        let mut click = |x, y| {
+            let event = Event::Mouse {
+                pos: mint::Point2::from([x, y]),
+                e: MouseEvent::ButtonDown {
+                    button: MouseButton::Right,
+                },
+            };
+            palette.handle_event(&mut ctx, event, bounds).is_err()
+        };
+
+        assert!(click(2, 3));
+        assert_eq!(*picked.borrow(), Some((color::CYAN, MouseButton::Right)));
+
+        // Past the last color
+        *picked.borrow_mut() = None;
+        assert!(!click(4, 3));
+        assert_eq!(*picked.borrow(), None);
+    }
+
+    #[test]
+    fn test_voxel_preview() {
+        let voxel = Voxel3::new(Voxel2::new(1), Voxel2::new(2), Voxel2::new(3));
+        let mut preview = VoxelPreview::new(bind(Array3::from_elem((1, 1, 1), Some(voxel))));
+        let center = mint::Point2::from([2, 2]);
+
+        // Looking along z from the front
+        preview.pitch = 0.0;
+        assert_eq!(
+            preview.render(Size::new(5, 5)),
+            vec![(center, Voxel2::new(3))]
+        );
+
+        // From the side
+        preview.yaw = PI / 2.0;
+        assert_eq!(
+            preview.render(Size::new(5, 5)),
+            vec![(center, Voxel2::new(1))]
+        );
+
+        // From above
+        preview.yaw = 0.0;
+        preview.pitch = -1.5;
+        assert_eq!(
+            preview.render(Size::new(5, 5)),
+            vec![(center, Voxel2::new(2))]
+        );
+
+        assert!(preview.render(Size::new(0, 5)).is_empty());
+    }
+}
@@ -0,0 +1,443 @@
+use std::{cell::RefCell, rc::Rc};
+
+use failure::{format_err, Fallible};
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::event::{KeyCode, KeyMods};
+use internship::IStr;
+use log::warn;
+
+use crate::{
+    editor::{
+        history::EditorAction,
+        panels::{button_row, divider, parse_glyph_index, BoundList},
+        voxel_mode::VoxelEntries,
+        NotifyLevel,
+    },
+    rendering::{
+        color,
+        voxel::{Voxel3, VoxelFace},
+    },
+    ui::*,
+    util::try_load,
+};
+
+/// Where the remap mode loads its rules from by default.
+const REMAP_PATH: &str = "remap.json";
+
+/// Moves the faces using one glyph onto another, e.g. after the font has been reorganized.
+#[derive(Clone, Debug, PartialEq)]
+struct RemapRule {
+    pub(super) old: u16,
+    pub(super) new: u16,
+    /// How many faces use `old`, as of the last dry run or remap.
+    faces: Option<usize>,
+}
+
+/// The edits that carry out `rules` on `entries`, one per voxel that changes, and how many
+/// faces each rule touches. The first rule for a glyph wins, and every face is remapped from
+/// its original glyph, so rules can swap glyphs.
+fn remap_glyphs(
+    entries: &[(IStr, Voxel3)],
+    rules: &[RemapRule],
+) -> (Vec<EditorAction>, Vec<usize>) {
+    let mut faces = vec![0; rules.len()];
+    let mut edits = Vec::new();
+
+    for (index, (_, old)) in entries.iter().enumerate() {
+        let mut new = old.clone();
+
+        for frame in 0..new.frame_count() {
+            for &face in &[VoxelFace::X, VoxelFace::Y, VoxelFace::Z] {
+                let char_offset = &mut new.frame_mut(frame)[face].char_offset;
+
+                if let Some(rule) = rules.iter().position(|rule| rule.old == *char_offset) {
+                    *char_offset = rules[rule].new;
+                    faces[rule] += 1;
+                }
+            }
+        }
+
+        if new != *old {
+            edits.push(EditorAction::SetVoxel {
+                index,
+                old: old.clone(),
+                new,
+            });
+        }
+    }
+
+    (edits, faces)
+}
+
+/// Reads remap rules as pairs of hex glyph indices, e.g. `[["0x2B0", "0x2C0"]]`.
+fn load_remap_rules(path: &str, glyph_count: u16) -> Fallible<Vec<RemapRule>> {
+    let pairs: Vec<(String, String)> = try_load(path)?;
+
+    pairs
+        .iter()
+        .map(|(old, new)| {
+            let parse = |text: &str| {
+                parse_glyph_index(text, glyph_count)
+                    .ok_or_else(|| format_err!("\"{}\" isn't a glyph index", text))
+            };
+
+            Ok(RemapRule {
+                old: parse(old)?,
+                new: parse(new)?,
+                faces: None,
+            })
+        })
+        .collect()
+}
+
+pub(super) struct RemapMode {
+    pub(super) layout: FlexLayout,
+    pub(super) actions: GlyphRemap,
+}
+
+impl RemapMode {
+    pub(super) fn new(voxels: VoxelEntries, glyph_count: u16) -> Self {
+        let rules = bind(Vec::new());
+        let selected = bind(None);
+
+        let actions = GlyphRemap {
+            rules: rules.clone(),
+            selected: selected.clone(),
+            old: bind(String::new()),
+            new: bind(String::new()),
+            path: bind(String::from(REMAP_PATH)),
+            voxels,
+            glyph_count,
+            message: Rc::new(RefCell::new(None)),
+        };
+
+        let action_button = |label: &str, action: fn(&GlyphRemap)| {
+            let actions = actions.clone();
+            Button::new(label, move || action(&actions))
+        };
+
+        let add = {
+            let actions = actions.clone();
+            move |_: String| actions.add()
+        };
+
+        let rule_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("0x"))),
+            FlexElement::fixed(Box::new(
+                TextInput::new(actions.old.clone())
+                    .with_width(4)
+                    .with_on_submit(add.clone()),
+            )),
+            FlexElement::fixed(Box::new(KataText::from_str(" -> 0x"))),
+            FlexElement::fixed(Box::new(
+                TextInput::new(actions.new.clone())
+                    .with_width(4)
+                    .with_on_submit(add),
+            )),
+        ]);
+
+        let rules_pane = FlexLayout::vertical(vec![
+            FlexElement::flex(
+                Box::new(BoundList::new(
+                    SelectableList::from_vec(Vec::new(), selected),
+                    rules,
+                    remap_row,
+                )),
+                1,
+            ),
+            FlexElement::fixed(Box::new(rule_row)),
+            FlexElement::fixed(Box::new(button_row(vec![
+                action_button("Add", GlyphRemap::add),
+                action_button("Remove", GlyphRemap::remove),
+            ]))),
+        ]);
+
+        let load = {
+            let actions = actions.clone();
+            move |_: String| actions.load()
+        };
+
+        let remap_pane = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(KataText::from_str(
+                "Moves every voxel face using an old glyph onto the new one. A dry run counts \
+                 the faces each rule would touch without changing them.",
+            ))),
+            FlexElement::fixed(Box::new(Padding::new(
+                FlexLayout::horizontal(vec![
+                    FlexElement::fixed(Box::new(KataText::from_str("Rules file "))),
+                    FlexElement::flex(
+                        Box::new(TextInput::new(actions.path.clone()).with_on_submit(load)),
+                        1,
+                    ),
+                ]),
+                1,
+                0,
+                0,
+                0,
+            ))),
+            FlexElement::fixed(Box::new(button_row(vec![action_button(
+                "Load",
+                GlyphRemap::load,
+            )]))),
+            FlexElement::fixed(Box::new(Padding::new(
+                button_row(vec![
+                    action_button("Dry run", GlyphRemap::dry_run),
+                    action_button("Remap", GlyphRemap::remap),
+                ]),
+                1,
+                0,
+                0,
+                0,
+            ))),
+        ]);
+
+        Self {
+            layout: FlexLayout::horizontal(vec![
+                FlexElement::flex(Box::new(Frame::new(rules_pane).with_title("Rules")), 1),
+                FlexElement::fixed(divider()),
+                FlexElement::flex(Box::new(Frame::new(remap_pane).with_title("Remap")), 1),
+            ]),
+            actions,
+        }
+    }
+
+    /// Handles the keyboard shortcuts of the mode, returning whether `keycode` was one.
+    pub(super) fn handle_shortcut(&mut self, keycode: KeyCode, mods: KeyMods) -> bool {
+        let ctrl = mods.contains(KeyMods::CTRL);
+
+        match keycode {
+            KeyCode::Z if ctrl && mods.contains(KeyMods::SHIFT) => self.actions.voxels.redo(),
+            KeyCode::Z if ctrl => self.actions.voxels.undo(),
+            KeyCode::Y if ctrl => self.actions.voxels.redo(),
+            _ => false,
+        }
+    }
+}
+
+/// The remap rules along with the fields for adding one, and the voxel entries they apply to.
+#[derive(Clone)]
+pub(super) struct GlyphRemap {
+    rules: Binding<Vec<RemapRule>>,
+    pub(super) selected: Binding<Option<usize>>,
+    pub(super) old: Binding<String>,
+    pub(super) new: Binding<String>,
+    path: Binding<String>,
+    pub(super) voxels: VoxelEntries,
+    pub(super) glyph_count: u16,
+    /// What the last action did, for the editor to show in the status bar on the next update.
+    pub(super) message: Rc<RefCell<Option<(NotifyLevel, String)>>>,
+}
+
+impl GlyphRemap {
+    pub(super) fn notify(&self, level: NotifyLevel, message: String) {
+        *self.message.borrow_mut() = Some((level, message));
+    }
+
+    /// Replaces the rules, forgetting the counts of the last dry run.
+    fn set_rules(&self, mut rules: Vec<RemapRule>) {
+        for rule in &mut rules {
+            rule.faces = None;
+        }
+
+        self.rules.set(rules);
+    }
+
+    /// Adds a rule from the old and new fields, replacing any other rule for the same glyph.
+    fn add(&self) {
+        let old = parse_glyph_index(&self.old.get(), self.glyph_count);
+        let new = parse_glyph_index(&self.new.get(), self.glyph_count);
+
+        let (old, new) = match (old, new) {
+            (Some(old), Some(new)) => (old, new),
+            _ => {
+                self.notify(
+                    NotifyLevel::Warning,
+                    format!("Glyphs go from 0x000 to 0x{:03X}", self.glyph_count - 1),
+                );
+                return;
+            }
+        };
+
+        let mut rules = self.rules.get();
+        let index = match rules.iter().position(|rule| rule.old == old) {
+            Some(index) => {
+                rules[index].new = new;
+                index
+            }
+
+            None => {
+                rules.push(RemapRule {
+                    old,
+                    new,
+                    faces: None,
+                });
+                rules.len() - 1
+            }
+        };
+
+        self.set_rules(rules);
+        self.selected.set(Some(index));
+        self.old.set(String::new());
+        self.new.set(String::new());
+    }
+
+    pub(super) fn remove(&self) {
+        if let Some(index) = self.selected.get() {
+            let mut rules = self.rules.get();
+
+            if index < rules.len() {
+                rules.remove(index);
+                self.set_rules(rules);
+            }
+        }
+    }
+
+    /// Replaces the rules with the ones in the file at the path field.
+    pub(super) fn load(&self) {
+        let path = self.path.get();
+
+        match load_remap_rules(&path, self.glyph_count) {
+            Ok(rules) => {
+                self.notify(
+                    NotifyLevel::Success,
+                    format!("Loaded {} rules from {}", rules.len(), path),
+                );
+                self.set_rules(rules);
+                self.selected.set(None);
+            }
+
+            Err(e) => {
+                warn!("Failed to load {}: {}", path, e);
+                self.notify(
+                    NotifyLevel::Error,
+                    format!("Failed to load {}: {}", path, e),
+                );
+            }
+        }
+    }
+
+    /// Works out the remap, showing how many faces each rule touches.
+    fn plan(&self) -> Vec<EditorAction> {
+        let mut rules = self.rules.get();
+        let (edits, faces) = remap_glyphs(&self.voxels.entries.borrow(), &rules);
+
+        for (rule, faces) in rules.iter_mut().zip(faces) {
+            rule.faces = Some(faces);
+        }
+
+        self.rules.set(rules);
+        edits
+    }
+
+    /// The number of faces the rules touch, as of the last plan.
+    fn planned_faces(&self) -> usize {
+        self.rules.get().iter().filter_map(|rule| rule.faces).sum()
+    }
+
+    fn dry_run(&self) {
+        let edits = self.plan();
+        self.notify(
+            NotifyLevel::Info,
+            format!(
+                "Remapping would change {} faces in {} voxels",
+                self.planned_faces(),
+                edits.len()
+            ),
+        );
+    }
+
+    /// Remaps every voxel at once, so that a single undo puts them all back.
+    fn remap(&self) {
+        let edits = self.plan();
+
+        if edits.is_empty() {
+            self.notify(
+                NotifyLevel::Warning,
+                String::from("No faces use the remapped glyphs"),
+            );
+            return;
+        }
+
+        let message = format!(
+            "Remapped {} faces in {} voxels",
+            self.planned_faces(),
+            edits.len()
+        );
+        self.voxels.apply(EditorAction::Compound(edits));
+        self.notify(NotifyLevel::Success, message);
+    }
+}
+
+fn remap_row(rule: &RemapRule) -> Box<dyn Row> {
+    let mut text = KataText::from_str(&format!("0x{:03X} -> 0x{:03X}", rule.old, rule.new));
+
+    if let Some(faces) = rule.faces {
+        text.push_span(&format!("  {} faces", faces), color::GRAY, None);
+    }
+
+    Box::new(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::voxel::Voxel2;
+    use std::fs;
+
+    #[test]
+    fn test_remap_glyphs() {
+        let voxel = |x, y, z| Voxel3::new(Voxel2::new(x), Voxel2::new(y), Voxel2::new(z));
+        let rule = |old, new| RemapRule {
+            old,
+            new,
+            faces: None,
+        };
+        let entries = vec![
+            (IStr::new("wall"), voxel(1, 2, 3)),
+            (IStr::new("floor"), voxel(3, 3, 3)),
+            (IStr::new("air"), voxel(0, 0, 0)),
+        ];
+
+        // Swaps 1 and 2, and the second rule for 3 is ignored
+        let rules = vec![rule(1, 2), rule(2, 1), rule(3, 4), rule(3, 5), rule(9, 0)];
+        let (edits, faces) = remap_glyphs(&entries, &rules);
+
+        assert_eq!(faces, vec![1, 1, 4, 0, 0]);
+        assert_eq!(edits.len(), 2);
+        match &edits[0] {
+            EditorAction::SetVoxel { index, old, new } => {
+                assert_eq!(*index, 0);
+                assert_eq!(*old, voxel(1, 2, 3));
+                assert_eq!(*new, voxel(2, 1, 4));
+            }
+            action => panic!("Unexpected {:?}", action),
+        }
+        match &edits[1] {
+            EditorAction::SetVoxel { index, new, .. } => {
+                assert_eq!(*index, 1);
+                assert_eq!(*new, voxel(4, 4, 4));
+            }
+            action => panic!("Unexpected {:?}", action),
+        }
+
+        let (edits, faces) = remap_glyphs(&entries, &[]);
+        assert!(edits.is_empty());
+        assert!(faces.is_empty());
+    }
+
+    #[test]
+    fn test_load_remap_rules() {
+        let path = std::env::temp_dir().join("katakomb_test_remap.json");
+        let path = path.to_str().unwrap();
+
+        fs::write(path, r#"[["0x2B0", "2C0"], ["DF", "0xDC"]]"#).unwrap();
+        let rules = load_remap_rules(path, 1024).unwrap();
+        let pairs: Vec<(u16, u16)> = rules.iter().map(|rule| (rule.old, rule.new)).collect();
+        assert_eq!(pairs, vec![(0x2B0, 0x2C0), (0xDF, 0xDC)]);
+
+        fs::write(path, r#"[["0x2B0", "0x400"]]"#).unwrap();
+        assert!(load_remap_rules(path, 1024).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+}
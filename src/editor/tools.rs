@@ -0,0 +1,493 @@
+use std::{cell::RefCell, rc::Rc};
+
+use flo_binding::{Binding, Bound, MutableBound};
+use ggez::input::mouse::MouseButton;
+use internship::IStr;
+use na::Point2;
+use ndarray::{Array2, Array3, Axis};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    editor::history::{History, ModelEdit},
+    geometry::util::calculate_bresenham_2d,
+};
+
+/// What dragging over the model slice does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(super) enum ModelTool {
+    Paint,
+    Erase,
+    /// Replaces the clicked cell and every cell joined to it with the same voxel.
+    Fill,
+    Line,
+    /// Outlines the dragged rectangle, or fills it when dragged with the right button.
+    Rect,
+    /// Selects the dragged rectangle, or moves the selection when dragged from inside it. A right
+    /// click clears it.
+    Select,
+}
+
+pub(super) const MODEL_TOOLS: [ModelTool; 6] = [
+    ModelTool::Paint,
+    ModelTool::Erase,
+    ModelTool::Fill,
+    ModelTool::Line,
+    ModelTool::Rect,
+    ModelTool::Select,
+];
+
+impl ModelTool {
+    pub(super) fn title(self) -> &'static str {
+        match self {
+            ModelTool::Paint => "Paint",
+            ModelTool::Erase => "Erase",
+            ModelTool::Fill => "Fill",
+            ModelTool::Line => "Line",
+            ModelTool::Rect => "Rect",
+            ModelTool::Select => "Select",
+        }
+    }
+}
+
+impl Default for ModelTool {
+    fn default() -> Self {
+        ModelTool::Paint
+    }
+}
+
+/// A rectangle of cells on a model slice, with both edges included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Selection {
+    pub(super) left: usize,
+    pub(super) top: usize,
+    pub(super) right: usize,
+    bottom: usize,
+}
+
+impl Selection {
+    /// The rectangle with opposite corners `start` and `end`.
+    pub(super) fn new(start: (usize, usize), end: (usize, usize)) -> Self {
+        Self {
+            left: start.0.min(end.0),
+            top: start.1.min(end.1),
+            right: start.0.max(end.0),
+            bottom: start.1.max(end.1),
+        }
+    }
+
+    pub(super) fn width(self) -> usize {
+        self.right - self.left + 1
+    }
+
+    pub(super) fn height(self) -> usize {
+        self.bottom - self.top + 1
+    }
+
+    pub(super) fn contains(self, (x, y): (usize, usize)) -> bool {
+        x >= self.left && x <= self.right && y >= self.top && y <= self.bottom
+    }
+
+    pub(super) fn cells(self) -> Vec<(usize, usize)> {
+        rect_cells((self.left, self.top), (self.right, self.bottom), true)
+    }
+
+    /// The selection moved as far as the drag from `from` to `to`, but no further than the edges
+    /// of a slice `size` cells big.
+    pub(super) fn moved(
+        self,
+        from: (usize, usize),
+        to: (usize, usize),
+        size: (usize, usize),
+    ) -> Self {
+        let shift = |start: usize, end: usize, low: usize, high: usize, side: usize| {
+            let offset = end as i64 - start as i64;
+            offset.max(-(low as i64)).min(side as i64 - 1 - high as i64)
+        };
+        let dx = shift(from.0, to.0, self.left, self.right, size.0);
+        let dy = shift(from.1, to.1, self.top, self.bottom, size.1);
+        let at = |pos: usize, offset: i64| (pos as i64 + offset) as usize;
+
+        Self {
+            left: at(self.left, dx),
+            top: at(self.top, dy),
+            right: at(self.right, dx),
+            bottom: at(self.bottom, dy),
+        }
+    }
+}
+
+/// Which way the selection gets flipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum MirrorAxis {
+    /// Left to right.
+    Horizontal,
+    /// Top to bottom.
+    Vertical,
+}
+
+/// The cells of `selection` on slice `z`, by their position within it.
+fn copy_cells(
+    model: &Array3<Option<IStr>>,
+    selection: Selection,
+    z: usize,
+) -> Array2<Option<IStr>> {
+    Array2::from_shape_fn((selection.width(), selection.height()), |(x, y)| {
+        model
+            .get((selection.left + x, selection.top + y, z))
+            .cloned()
+            .flatten()
+    })
+}
+
+fn mirrored(cells: &Array2<Option<IStr>>, axis: MirrorAxis) -> Array2<Option<IStr>> {
+    let mut view = cells.view();
+    view.invert_axis(match axis {
+        MirrorAxis::Horizontal => Axis(0),
+        MirrorAxis::Vertical => Axis(1),
+    });
+
+    view.to_owned()
+}
+
+/// Moves the cells of `from` on slice `z` over to `to`, leaving the cells they came from empty.
+pub(super) fn move_cells(
+    edit: &mut ModelEdit,
+    model: &mut Array3<Option<IStr>>,
+    from: Selection,
+    to: Selection,
+    z: usize,
+) {
+    let cells = copy_cells(model, from, z);
+    edit.set_cells(model, from.cells(), z, &None);
+    edit.place(model, &cells, (to.left, to.top), z);
+}
+
+/// The selection on the model slice, along with the actions on it. Each action is one edit, so
+/// that it's undone all at once.
+#[derive(Clone)]
+pub(super) struct ModelSelection {
+    pub(super) selection: Binding<Option<Selection>>,
+    pub(super) model: Binding<Array3<Option<IStr>>>,
+    pub(super) slice: Binding<i32>,
+    pub(super) brush: Binding<Option<IStr>>,
+    pub(super) history: Rc<RefCell<History<ModelEdit>>>,
+    pub(super) refresh_preview: Rc<dyn Fn()>,
+}
+
+impl ModelSelection {
+    /// Calls `edit_with` with the selection on the active slice, and keeps what it changed as
+    /// one edit. Returns whether there was a selection.
+    pub(super) fn edit<F>(&self, edit_with: F) -> bool
+    where
+        F: FnOnce(&mut ModelEdit, &mut Array3<Option<IStr>>, Selection, usize),
+    {
+        let (selection, z) = match (self.selection.get(), self.slice.get()) {
+            (Some(selection), z) if z >= 0 => (selection, z as usize),
+            _ => return false,
+        };
+
+        let mut model = self.model.get();
+        let mut edit = ModelEdit::default();
+        edit_with(&mut edit, &mut model, selection, z);
+
+        if !edit.cells.is_empty() {
+            self.model.set(model);
+            self.history.borrow_mut().push(edit);
+            (self.refresh_preview)();
+        }
+
+        true
+    }
+
+    pub(super) fn delete(&self) -> bool {
+        self.edit(|edit, model, selection, z| edit.set_cells(model, selection.cells(), z, &None))
+    }
+
+    /// Fills the selection with the brush.
+    pub(super) fn fill(&self) -> bool {
+        let brush = self.brush.get();
+        self.edit(|edit, model, selection, z| edit.set_cells(model, selection.cells(), z, &brush))
+    }
+
+    pub(super) fn mirror(&self, axis: MirrorAxis) -> bool {
+        self.edit(|edit, model, selection, z| {
+            let cells = mirrored(&copy_cells(model, selection, z), axis);
+            edit.place(model, &cells, (selection.left, selection.top), z);
+        })
+    }
+
+    /// The selected cells of the active slice, if anything is selected.
+    pub(super) fn copy(&self) -> Option<Array2<Option<IStr>>> {
+        match (self.selection.get(), self.slice.get()) {
+            (Some(selection), z) if z >= 0 => {
+                Some(copy_cells(&self.model.get(), selection, z as usize))
+            }
+            _ => None,
+        }
+    }
+
+    /// Pastes `cells` onto the active slice at the top left corner of the selection, or of the
+    /// slice if there isn't one, and selects what was pasted. Cells that would go past the edges
+    /// of the model are left out.
+    pub(super) fn paste(&self, cells: &Array2<Option<IStr>>) -> bool {
+        let (w, h, _) = self.model.get().dim();
+        let (cw, ch) = cells.dim();
+        let (x, y) = self
+            .selection
+            .get()
+            .map_or((0, 0), |selection| (selection.left, selection.top));
+        if self.slice.get() < 0 || x >= w || y >= h || cw == 0 || ch == 0 {
+            return false;
+        }
+
+        let pasted = Selection::new((x, y), ((x + cw - 1).min(w - 1), (y + ch - 1).min(h - 1)));
+        self.selection.set(Some(pasted));
+        self.edit(|edit, model, _, z| edit.place(model, cells, (x, y), z))
+    }
+
+    pub(super) fn clear(&self) {
+        self.selection.set(None);
+    }
+}
+
+/// The cells of slice `z` that are joined to `(x, y)` through their sides and hold the same voxel.
+pub(super) fn flood_fill(
+    model: &Array3<Option<IStr>>,
+    (x, y): (usize, usize),
+    z: usize,
+) -> Vec<(usize, usize)> {
+    let (w, h, d) = model.dim();
+    if x >= w || y >= h || z >= d {
+        return Vec::new();
+    }
+
+    let target = &model[(x, y, z)];
+    let mut seen = Array2::from_elem((w, h), false);
+    let mut open = vec![(x, y)];
+    let mut cells = Vec::new();
+    seen[(x, y)] = true;
+
+    while let Some((x, y)) = open.pop() {
+        cells.push((x, y));
+
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+
+        for &(nx, ny) in &neighbours {
+            if nx < w && ny < h && !seen[(nx, ny)] && &model[(nx, ny, z)] == target {
+                seen[(nx, ny)] = true;
+                open.push((nx, ny));
+            }
+        }
+    }
+
+    cells
+}
+
+/// The cells on a straight line from `start` to `end`, both included.
+pub(super) fn line_cells(start: (usize, usize), end: (usize, usize)) -> Vec<(usize, usize)> {
+    calculate_bresenham_2d(
+        Point2::new(start.0 as i32, start.1 as i32),
+        Point2::new(end.0 as i32, end.1 as i32),
+    )
+    .into_iter()
+    .map(|p| (p.x as usize, p.y as usize))
+    .collect()
+}
+
+/// The cells of the rectangle with corners `start` and `end`, or just its outline unless `filled`.
+pub(super) fn rect_cells(
+    start: (usize, usize),
+    end: (usize, usize),
+    filled: bool,
+) -> Vec<(usize, usize)> {
+    let (left, right) = (start.0.min(end.0), start.0.max(end.0));
+    let (top, bottom) = (start.1.min(end.1), start.1.max(end.1));
+
+    (top..=bottom)
+        .flat_map(|y| (left..=right).map(move |x| (x, y)))
+        .filter(|&(x, y)| filled || x == left || x == right || y == top || y == bottom)
+        .collect()
+}
+
+/// A drag over the model slice that hasn't been released yet.
+pub(super) struct Stroke {
+    pub(super) button: MouseButton,
+    pub(super) start: (usize, usize),
+    pub(super) end: (usize, usize),
+    /// What's been painted so far, for the tools that paint while dragging.
+    pub(super) edit: ModelEdit,
+    /// The selection being moved, when the select tool was pressed inside it.
+    pub(super) lifted: Option<Selection>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::editor::test::{letters, slice_names};
+    use flo_binding::bind;
+
+    #[test]
+    fn test_model_tools() {
+        let a = Some(IStr::new("a"));
+        let mut model = Array3::from_elem((4, 3, 1), None);
+        model[(1, 0, 0)] = a.clone();
+        model[(1, 1, 0)] = a.clone();
+        model[(1, 2, 0)] = a.clone();
+
+        let mut filled = flood_fill(&model, (0, 1), 0);
+        filled.sort();
+        assert_eq!(filled, [(0, 0), (0, 1), (0, 2)]);
+        assert_eq!(flood_fill(&model, (1, 0), 0).len(), 3);
+        assert!(flood_fill(&model, (4, 0), 0).is_empty());
+
+        assert_eq!(line_cells((0, 0), (3, 1)).len(), 4);
+        assert_eq!(rect_cells((2, 2), (0, 0), true).len(), 9);
+        assert_eq!(rect_cells((0, 0), (2, 2), false).len(), 8);
+
+        let mut edit = ModelEdit::default();
+        let original = model.clone();
+        edit.set_cells(&mut model, vec![(0, 0), (1, 0), (9, 9)], 0, &a);
+        assert_eq!(edit.cells.len(), 1);
+        assert_eq!(model[(0, 0, 0)], a);
+
+        edit.inverse().apply_to(&mut model);
+        assert_eq!(model, original);
+    }
+
+    #[test]
+    fn test_selection_geometry() {
+        let selection = Selection::new((3, 1), (1, 2));
+        assert_eq!(
+            selection,
+            Selection {
+                left: 1,
+                top: 1,
+                right: 3,
+                bottom: 2,
+            }
+        );
+        assert_eq!((selection.width(), selection.height()), (3, 2));
+        assert_eq!(selection.cells().len(), 6);
+        assert!(selection.contains((1, 1)) && selection.contains((3, 2)));
+        assert!(!selection.contains((0, 1)) && !selection.contains((2, 3)));
+
+        // Moved as far as the drag, but not past the edges of the slice
+        assert_eq!(
+            selection.moved((2, 2), (3, 1), (5, 4)),
+            Selection::new((2, 0), (4, 1))
+        );
+        assert_eq!(
+            selection.moved((2, 2), (0, 0), (5, 4)),
+            Selection::new((0, 0), (2, 1))
+        );
+        assert_eq!(
+            selection.moved((1, 1), (9, 9), (5, 4)),
+            Selection::new((2, 2), (4, 3))
+        );
+    }
+
+    #[test]
+    fn test_mirror_transforms() {
+        let model = letters();
+        let all = Selection::new((0, 0), (2, 1));
+        let cells = copy_cells(&model, all, 0);
+        assert_eq!(cells.dim(), (3, 2));
+        assert_eq!(cells[(1, 1)], Some(IStr::new("e")));
+
+        let mirror = |axis| {
+            let mut model = model.clone();
+            let mut edit = ModelEdit::default();
+            edit.place(&mut model, &mirrored(&cells, axis), (0, 0), 0);
+            (slice_names(&model, 0), edit)
+        };
+
+        let (names, edit) = mirror(MirrorAxis::Horizontal);
+        assert_eq!(names, ["cba", ".ed"]);
+        // The middle column stays where it is
+        assert_eq!(edit.cells.len(), 4);
+
+        let (names, edit) = mirror(MirrorAxis::Vertical);
+        assert_eq!(names, ["de.", "abc"]);
+        assert_eq!(edit.cells.len(), 6);
+
+        // Mirroring twice gets back to where it started
+        let twice = mirrored(
+            &mirrored(&cells, MirrorAxis::Vertical),
+            MirrorAxis::Vertical,
+        );
+        assert_eq!(twice, cells);
+
+        let mut moved = model.clone();
+        move_cells(
+            &mut ModelEdit::default(),
+            &mut moved,
+            Selection::new((0, 0), (1, 0)),
+            Selection::new((1, 1), (2, 1)),
+            0,
+        );
+        assert_eq!(slice_names(&moved, 0), ["..c", "dab"]);
+    }
+
+    fn test_selection(model: Array3<Option<IStr>>) -> ModelSelection {
+        ModelSelection {
+            selection: bind(None),
+            model: bind(model),
+            slice: bind(0),
+            brush: bind(Some(IStr::new("x"))),
+            history: Rc::new(RefCell::new(History::default())),
+            refresh_preview: Rc::new(|| {}),
+        }
+    }
+
+    #[test]
+    fn test_selection_actions() {
+        let actions = test_selection(letters());
+        assert!(!actions.delete());
+        assert_eq!(actions.copy(), None);
+
+        actions.selection.set(Some(Selection::new((1, 0), (2, 1))));
+        assert!(actions.mirror(MirrorAxis::Horizontal));
+        assert_eq!(slice_names(&actions.model.get(), 0), ["acb", "d.e"]);
+        assert!(actions.fill());
+        assert_eq!(slice_names(&actions.model.get(), 0), ["axx", "dxx"]);
+        assert!(actions.delete());
+        assert_eq!(slice_names(&actions.model.get(), 0), ["a..", "d.."]);
+
+        // Each action is undone all at once
+        let history = actions.history.borrow();
+        assert_eq!(history.done.len(), 3);
+        let mut model = actions.model.get();
+        for edit in history.done.iter().rev() {
+            edit.inverse().apply_to(&mut model);
+        }
+        assert_eq!(model, letters());
+    }
+
+    #[test]
+    fn test_selection_paste() {
+        let actions = test_selection(letters());
+        actions.selection.set(Some(Selection::new((0, 0), (1, 1))));
+        let copied = actions.copy().unwrap();
+
+        // Onto the next slice, and clipped at the edge of the model
+        actions.slice.set(1);
+        actions.selection.set(Some(Selection::new((2, 1), (2, 1))));
+        assert!(actions.paste(&copied));
+        assert_eq!(slice_names(&actions.model.get(), 1), ["...", "..a"]);
+        assert_eq!(
+            actions.selection.get(),
+            Some(Selection::new((2, 1), (2, 1)))
+        );
+
+        // Into another model, with nothing selected
+        let other = test_selection(Array3::from_elem((2, 2, 1), None));
+        assert!(other.paste(&copied));
+        assert_eq!(slice_names(&other.model.get(), 0), ["ab", "de"]);
+        assert_eq!(other.history.borrow().done.len(), 1);
+    }
+}
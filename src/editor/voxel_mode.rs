@@ -0,0 +1,1510 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::{
+    event::{KeyCode, KeyMods},
+    input::mouse::MouseButton,
+};
+use internship::IStr;
+use ndarray::Array3;
+
+use crate::{
+    editor::{
+        history::{EditorAction, History},
+        panels::{
+            button_row, color_picker, dialog, divider, parse_glyph_index, BoundList, ComparedNames,
+            FaceComparison, FrameScrubber, GlyphIndex, RecentStrip, VoxelComparison, VoxelList,
+            VoxelPreview, GLYPH_MAGNIFICATION, MAX_RECENT_VOXELS,
+        },
+        Recent, Settings,
+    },
+    rendering::{
+        color::{self, Color},
+        voxel::{Model, Voxel2, Voxel3, VoxelFace},
+    },
+    ui::*,
+};
+
+pub(super) struct VoxelMode {
+    pub(super) layout: Modal<FlexLayout>,
+    pub(super) actions: VoxelEntries,
+    pub(super) charset_width: u16,
+    pub(super) glyph_count: u16,
+    /// Colors added to the color picker on top of the named ones.
+    pub(super) palette: Binding<Vec<Color>>,
+    /// Pages of glyphs to move the selection by, carried out by the glyph browser when it's drawn.
+    glyph_pages: Rc<Cell<i32>>,
+    /// Set by the find usages button, for the editor to start the search on the next update.
+    pub(super) find_usages_requested: Rc<Cell<bool>>,
+    /// The voxel being searched for and where the results come in, while a search is running.
+    usage_search: Option<(IStr, Receiver<Usage>)>,
+    usages: Binding<Vec<Usage>>,
+    selected_usage: Binding<Option<usize>>,
+    /// The usage last clicked, for the editor to open on the next update.
+    pub(super) open_usage: Rc<RefCell<Option<Usage>>>,
+}
+
+impl VoxelMode {
+    pub(super) fn new(
+        voxels: &BTreeMap<IStr, Voxel3>,
+        models: &BTreeMap<IStr, Model>,
+        settings: &Settings,
+        named_colors: &Binding<Vec<(IStr, Color)>>,
+        recent: &Recent,
+        charset_width: u16,
+        glyph_count: u16,
+    ) -> Self {
+        let entries: Vec<(IStr, Voxel3)> = voxels
+            .iter()
+            .map(|(name, voxel)| (name.clone(), voxel.clone()))
+            .collect();
+
+        // Bindings
+        let position = |name: &IStr| entries.iter().position(|(other, _)| other == name);
+        let selected_voxel = Binding::new(recent.voxel.as_ref().and_then(position));
+        let voxel = Binding::new(
+            selected_voxel
+                .get()
+                .map(|i| entries[i].1.clone())
+                .unwrap_or_else(Default::default),
+        );
+        let active_face = Binding::new(recent.face);
+        let active_frame = bind(0);
+        let selected_glyph = Binding::new(Some(usize::from(voxel.get()[recent.face].char_offset)));
+        let active_char_offset = Binding::new(i32::from(voxel.get()[recent.face].char_offset));
+
+        // Voxels deleted since are left out
+        let mut recent_indices: Vec<usize> = recent.voxels.iter().filter_map(position).collect();
+        if recent_indices.is_empty() {
+            recent_indices.extend(selected_voxel.get());
+        }
+        recent_indices.truncate(MAX_RECENT_VOXELS);
+        let recent_voxels = Binding::new(recent_indices);
+        let palette = Binding::new(settings.palette.clone());
+
+        let entries = Rc::new(RefCell::new(entries));
+
+        // Loads an entry into the bindings and moves it to the front of the recent voxels
+        let load_voxel: Rc<dyn Fn(usize)> = Rc::new({
+            let entries = Rc::clone(&entries);
+            let selected_voxel = selected_voxel.clone();
+            let voxel = voxel.clone();
+            let active_face = active_face.clone();
+            let active_frame = active_frame.clone();
+            let selected_glyph = selected_glyph.clone();
+            let active_char_offset = active_char_offset.clone();
+            let recent_voxels = recent_voxels.clone();
+            move |index| {
+                let new_voxel = entries.borrow()[index].1.clone();
+                let char_offset = new_voxel[active_face.get()].char_offset;
+                selected_voxel.set(Some(index));
+                active_frame.set(0);
+                selected_glyph.set(Some(usize::from(char_offset)));
+                active_char_offset.set(i32::from(char_offset));
+                voxel.set(new_voxel);
+
+                let mut recent = recent_voxels.get();
+                recent.retain(|&i| i != index);
+                recent.insert(0, index);
+                recent.truncate(MAX_RECENT_VOXELS);
+                recent_voxels.set(recent);
+            }
+        });
+
+        let dialogs = DialogHandle::new();
+        let actions = VoxelEntries {
+            entries: Rc::clone(&entries),
+            selected_voxel: selected_voxel.clone(),
+            recent_voxels: recent_voxels.clone(),
+            voxel: voxel.clone(),
+            active_face: active_face.clone(),
+            active_frame: active_frame.clone(),
+            playing: bind(false),
+            selected_glyph: selected_glyph.clone(),
+            active_char_offset: active_char_offset.clone(),
+            load_voxel: Rc::clone(&load_voxel),
+            history: Rc::new(RefCell::new(History::default())),
+            used_by: Rc::new(voxel_users(models)),
+            dialogs: dialogs.clone(),
+        };
+
+        // Shows an edited voxel and writes it back to the selected entry
+        let set_voxel: Rc<dyn Fn(Voxel3)> = Rc::new({
+            let actions = actions.clone();
+            move |new_voxel| actions.set_voxel(new_voxel)
+        });
+
+        // Layout
+        let hovered_glyph = bind(None);
+        let font_display = GridLayout::new(
+            Size::new(1, 1),
+            (0..glyph_count)
+                .map(|char_offset| {
+                    Box::new(Tooltip::new(
+                        KataText::from_voxels(vec![Voxel2::new(char_offset)]),
+                        move |_, _| Some(format!("0x{:03X}", char_offset)),
+                    )) as Box<dyn Element>
+                })
+                .collect(),
+        )
+        .with_selection(
+            selected_glyph.clone(),
+            Voxel2::new(0).background(Some(color::GRAY)),
+        )
+        .with_events({
+            let actions = actions.clone();
+            let hovered_glyph = hovered_glyph.clone();
+            move |grid, _ctx, e, bounds| {
+                if let Event::Mouse {
+                    pos,
+                    e: MouseEvent::Move,
+                } = e
+                {
+                    let hovered = grid.index_at(bounds, pos);
+                    if hovered_glyph.get() != hovered {
+                        hovered_glyph.set(hovered);
+                    }
+                }
+
+                match e.cull(bounds) {
+                    Some(Event::Mouse {
+                        pos,
+                        e:
+                            MouseEvent::ButtonDown {
+                                button: MouseButton::Left,
+                            },
+                    }) => {
+                        if let Some(index) = grid.index_at(bounds, pos) {
+                            actions.set_char_offset(index as u16);
+                            return Err(Stop);
+                        }
+                    }
+
+                    _ => {}
+                }
+
+                Ok(Continue)
+            }
+        });
+
+        // Keeps the selected glyph in view whenever it changes, and moves it by pages, which
+        // depends on how many rows are shown
+        let glyph_pages = Rc::new(Cell::new(0));
+        let glyph_grid = ScrollView::new(font_display).with_events({
+            let actions = actions.clone();
+            let selected_glyph = selected_glyph.clone();
+            let glyph_pages = Rc::clone(&glyph_pages);
+            let mut shown_glyph = None;
+            move |view, ctx, e, bounds| {
+                if let Event::Draw = e {
+                    let pages = glyph_pages.replace(0);
+                    if pages != 0 {
+                        let page = (bounds.h.max(1) * view.inner().columns()) as i32;
+                        let glyph = selected_glyph.get().unwrap_or(0) as i32 + pages * page;
+                        actions
+                            .set_char_offset(glyph.min(i32::from(glyph_count) - 1).max(0) as u16);
+                    }
+
+                    let glyph = selected_glyph.get();
+                    if glyph != shown_glyph {
+                        if let Some(glyph) = glyph {
+                            let span = view.inner().row_span(glyph);
+                            view.scroll_into_view(ctx, bounds, span);
+                        }
+
+                        shown_glyph = glyph;
+                    }
+                }
+
+                Ok(Continue)
+            }
+        });
+
+        let glyph_search = bind(String::new());
+        let glyph_search_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Go to 0x"))),
+            FlexElement::fixed(Box::new(
+                TextInput::new(glyph_search).with_width(4).with_on_submit({
+                    let actions = actions.clone();
+                    move |text: String| {
+                        if let Some(glyph) = parse_glyph_index(&text, glyph_count) {
+                            actions.set_char_offset(glyph);
+                        }
+                    }
+                }),
+            )),
+        ]);
+
+        // The hovered glyph, or the active face's when nothing is hovered, in the face's colors
+        let magnified_glyph = flo_binding::computed({
+            let hovered_glyph = hovered_glyph.clone();
+            let voxel = voxel.clone();
+            let active_face = active_face.clone();
+            let active_frame = active_frame.clone();
+            move || {
+                let face = voxel.get().frame(active_frame.get())[active_face.get()].clone();
+
+                match hovered_glyph.get() {
+                    Some(glyph) => Voxel2 {
+                        char_offset: glyph as u16,
+                        ..face
+                    },
+                    None => face,
+                }
+            }
+        });
+
+        let glyph_info = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(
+                VoxelDisplay::new(magnified_glyph).with_magnification(GLYPH_MAGNIFICATION),
+            )),
+            FlexElement::fixed(Box::new(Padding::new(
+                GlyphIndex {
+                    hovered: hovered_glyph,
+                    active: active_char_offset.clone(),
+                },
+                0,
+                0,
+                0,
+                1,
+            ))),
+        ]);
+
+        let glyph_browser = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(glyph_search_row)),
+            FlexElement::flex(Box::new(glyph_grid), 1),
+            FlexElement::fixed(Box::new(glyph_info)),
+        ]);
+
+        let face_display = |char_offset: u8, face: VoxelFace| {
+            let actions = actions.clone();
+            let face_voxel = voxel.clone();
+            let active_frame = active_frame.clone();
+
+            Box::new(FlexLayout::vertical(vec![
+                FlexElement::fixed(Box::new(Placeholder::new(
+                    Voxel2::new(char_offset.into()),
+                    |c| c.constrain(Size::new(1, 1)),
+                ))),
+                FlexElement::fixed(Box::new(
+                    VoxelDisplay::new(flo_binding::computed(move || {
+                        face_voxel.get().frame(active_frame.get())[face].clone()
+                    }))
+                    .with_events(move |_self, _ctx, e, bounds| {
+                        match e.cull(bounds) {
+                            Some(Event::Mouse {
+                                e:
+                                    MouseEvent::ButtonDown {
+                                        button: MouseButton::Left,
+                                    },
+                                ..
+                            }) => actions.select_face(face),
+
+                            _ => {}
+                        }
+
+                        Ok(Continue)
+                    }),
+                )),
+            ]))
+        };
+
+        // Steps through the glyphs of the active face one at a time
+        let char_offset_stepper = Stepper::new(
+            active_char_offset.clone(),
+            0,
+            i32::from(glyph_count.saturating_sub(1)),
+        )
+        .with_on_change({
+            let actions = actions.clone();
+            move |char_offset| actions.set_char_offset(char_offset as u16)
+        });
+
+        let action_button = |label: &str, action: fn(&VoxelEntries)| {
+            let actions = actions.clone();
+            Button::new(label, move || action(&actions))
+        };
+
+        let voxel_info = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(FlexLayout::horizontal(vec![
+                FlexElement::fixed(face_display(b'X', VoxelFace::X)),
+                FlexElement::fixed(face_display(b'Y', VoxelFace::Y)),
+                FlexElement::fixed(face_display(b'Z', VoxelFace::Z)),
+            ]))),
+            FlexElement::fixed(Box::new(char_offset_stepper)),
+            FlexElement::fixed(Box::new(Padding::new(
+                FrameScrubber {
+                    actions: actions.clone(),
+                },
+                1,
+                0,
+                0,
+                0,
+            ))),
+            FlexElement::fixed(Box::new(button_row(vec![
+                action_button("Add", VoxelEntries::add_frame),
+                action_button("Dup", VoxelEntries::duplicate_frame),
+                action_button("Del", VoxelEntries::remove_frame),
+                action_button("Play", VoxelEntries::toggle_playing),
+            ]))),
+        ]);
+
+        let middle_pane = FlexLayout::vertical(vec![
+            FlexElement::flex(
+                Box::new(PanelBackground::new(
+                    Frame::new(Centered::new(voxel_info)).with_title("Voxel"),
+                )),
+                1,
+            ),
+            FlexElement::flex(
+                Box::new(PanelBackground::new(
+                    Frame::new(VoxelPreview::new(flo_binding::computed({
+                        let voxel = voxel.clone();
+                        let active_frame = active_frame.clone();
+                        move || {
+                            let frame = voxel.get().frame(active_frame.get()).clone();
+                            Array3::from_elem((3, 3, 3), Some(frame))
+                        }
+                    })))
+                    .with_title("Preview"),
+                )),
+                1,
+            ),
+            FlexElement::flex(
+                Box::new(PanelBackground::new(
+                    Frame::new(color_picker(
+                        &voxel,
+                        &set_voxel,
+                        &active_face,
+                        &active_frame,
+                        named_colors,
+                        &palette,
+                    ))
+                    .with_title("Colors"),
+                )),
+                1,
+            ),
+        ]);
+
+        let voxel_list = SelectableList::from_vec(
+            entries
+                .borrow()
+                .iter()
+                .map(|(name, _)| voxel_row(name))
+                .collect(),
+            selected_voxel.clone(),
+        )
+        .with_on_select({
+            let load_voxel = Rc::clone(&load_voxel);
+            move |index| load_voxel(index)
+        })
+        .with_on_move({
+            let actions = actions.clone();
+            move |from, to| actions.apply(EditorAction::Move { from, to })
+        });
+
+        let find_usages_requested = Rc::new(Cell::new(false));
+        let find_usages_button = Button::new("Find usages", {
+            let find_usages_requested = Rc::clone(&find_usages_requested);
+            move || find_usages_requested.set(true)
+        });
+
+        let voxel_pane = FlexLayout::vertical(vec![
+            FlexElement::flex(Box::new(VoxelList::new(voxel_list, Rc::clone(&entries))), 1),
+            FlexElement::fixed(Box::new(button_row(vec![
+                action_button("New", VoxelEntries::create),
+                action_button("Rename", VoxelEntries::open_rename),
+            ]))),
+            FlexElement::fixed(Box::new(button_row(vec![
+                action_button("Duplicate", VoxelEntries::duplicate),
+                action_button("Delete", VoxelEntries::open_delete),
+            ]))),
+            FlexElement::fixed(Box::new(button_row(vec![
+                find_usages_button,
+                action_button("Compare", VoxelEntries::open_compare),
+            ]))),
+        ]);
+
+        let usages = bind(Vec::new());
+        let selected_usage = bind(None);
+        let open_usage = Rc::new(RefCell::new(None));
+        let usage_list = SelectableList::from_vec(Vec::new(), selected_usage.clone())
+            .with_on_select({
+                let usages = usages.clone();
+                let open_usage = Rc::clone(&open_usage);
+                move |index| {
+                    *open_usage.borrow_mut() = usages.get().get(index).cloned();
+                }
+            });
+
+        let recent_strip = RecentStrip::new(recent_voxels, Rc::clone(&entries), load_voxel);
+
+        Self {
+            layout: Modal::new(
+                FlexLayout::vertical(vec![
+                    FlexElement::flex(
+                        Box::new(FlexLayout::horizontal(vec![
+                            FlexElement::fixed(Box::new(glyph_browser)),
+                            FlexElement::fixed(divider()),
+                            FlexElement::flex(Box::new(middle_pane), 1),
+                            FlexElement::fixed(divider()),
+                            FlexElement::flex(
+                                Box::new(FlexLayout::vertical(vec![
+                                    FlexElement::flex(
+                                        Box::new(Frame::new(voxel_pane).with_title("Voxels")),
+                                        2,
+                                    ),
+                                    FlexElement::flex(
+                                        Box::new(
+                                            Frame::new(BoundList::new(
+                                                usage_list,
+                                                usages.clone(),
+                                                usage_row,
+                                            ))
+                                            .with_title("Usages"),
+                                        ),
+                                        1,
+                                    ),
+                                ])),
+                                1,
+                            ),
+                        ])),
+                        1,
+                    ),
+                    FlexElement::fixed(Box::new(Frame::new(recent_strip).with_title("Recent"))),
+                ]),
+                dialogs,
+            ),
+            actions,
+            palette,
+            charset_width,
+            glyph_count,
+            glyph_pages,
+            find_usages_requested,
+            usage_search: None,
+            usages,
+            selected_usage,
+            open_usage,
+        }
+    }
+
+    /// Clears the usages and shows the ones that come from `search` instead.
+    pub(super) fn start_usage_search(&mut self, name: IStr, search: Receiver<Usage>) {
+        self.usages.set(Vec::new());
+        self.selected_usage.set(None);
+        self.usage_search = Some((name, search));
+    }
+
+    /// Adds the usages found since the last call. Once the search is done, returns the voxel
+    /// that was searched for and how many models use it.
+    pub(super) fn poll_usages(&mut self) -> Option<(IStr, usize)> {
+        let (name, search) = self.usage_search.take()?;
+        let mut usages = self.usages.get();
+        let found = usages.len();
+
+        let done = loop {
+            match search.try_recv() {
+                Ok(usage) => usages.push(usage),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+
+        if usages.len() != found {
+            self.usages.set(usages);
+        }
+
+        if done {
+            Some((name, self.usages.get().len()))
+        } else {
+            self.usage_search = Some((name, search));
+            None
+        }
+    }
+
+    /// All voxels, including any edits made so far.
+    pub(super) fn voxels(&self) -> BTreeMap<IStr, Voxel3> {
+        self.actions.entries.borrow().iter().cloned().collect()
+    }
+
+    /// The names of the recently selected voxels, most recent first.
+    pub(super) fn recent_names(&self) -> Vec<IStr> {
+        let entries = self.actions.entries.borrow();
+
+        self.actions
+            .recent_voxels
+            .get()
+            .into_iter()
+            .filter_map(|index| entries.get(index))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    pub(super) fn selected_name(&self) -> Option<IStr> {
+        self.actions
+            .selected_voxel
+            .get()
+            .map(|index| self.actions.entries.borrow()[index].0.clone())
+    }
+
+    /// Handles the keyboard shortcuts of the mode, returning whether `keycode` was one.
+    pub(super) fn handle_shortcut(&mut self, keycode: KeyCode, mods: KeyMods) -> bool {
+        let ctrl = mods.contains(KeyMods::CTRL);
+
+        match keycode {
+            KeyCode::Z if ctrl && mods.contains(KeyMods::SHIFT) => self.actions.redo(),
+            KeyCode::Z if ctrl => self.actions.undo(),
+            KeyCode::Y if ctrl => self.actions.redo(),
+
+            KeyCode::Tab => {
+                self.actions.next_face();
+                true
+            }
+
+            KeyCode::Left => self.nudge_char_offset(-1),
+            KeyCode::Right => self.nudge_char_offset(1),
+            KeyCode::Up => self.nudge_char_offset(-i32::from(self.charset_width)),
+            KeyCode::Down => self.nudge_char_offset(i32::from(self.charset_width)),
+
+            KeyCode::Home => {
+                self.actions.set_char_offset(0);
+                true
+            }
+
+            KeyCode::End => {
+                self.actions
+                    .set_char_offset(self.glyph_count.saturating_sub(1));
+                true
+            }
+
+            KeyCode::PageUp => {
+                self.glyph_pages.set(self.glyph_pages.get() - 1);
+                true
+            }
+
+            KeyCode::PageDown => {
+                self.glyph_pages.set(self.glyph_pages.get() + 1);
+                true
+            }
+
+            KeyCode::Delete => {
+                self.actions.clear_face();
+                true
+            }
+
+            _ => false,
+        }
+    }
+
+    pub(super) fn selected_voxel(&self) -> Option<Voxel3> {
+        self.actions
+            .selected_voxel
+            .get()
+            .map(|_| self.actions.voxel.get())
+    }
+
+    pub(super) fn active_face(&self) -> Voxel2 {
+        self.actions.face(self.actions.active_face.get())
+    }
+
+    /// Puts `face` on the active face of the selected voxel, returning whether there was one.
+    pub(super) fn paste_face(&self, face: Voxel2) -> bool {
+        let mut voxel = match self.selected_voxel() {
+            Some(voxel) => voxel,
+            None => return false,
+        };
+
+        voxel.frame_mut(self.actions.active_frame.get())[self.actions.active_face.get()] = face;
+        self.paste_voxel(voxel)
+    }
+
+    /// Replaces the selected voxel with `voxel`, returning whether there was one.
+    pub(super) fn paste_voxel(&self, voxel: Voxel3) -> bool {
+        if self.actions.selected_voxel.get().is_none() {
+            return false;
+        }
+
+        self.actions.set_voxel(voxel);
+        self.actions.select_face(self.actions.active_face.get());
+        true
+    }
+
+    /// Moves the glyph of the active face by `delta` places in the font.
+    fn nudge_char_offset(&self, delta: i32) -> bool {
+        let char_offset = self.active_face().char_offset;
+        let char_offset = (i32::from(char_offset) + delta)
+            .max(0)
+            .min(i32::from(self.glyph_count) - 1);
+
+        self.actions.set_char_offset(char_offset as u16);
+        true
+    }
+}
+
+/// The voxel entries along with the bindings showing them, and the actions that edit them.
+/// Edits go through `apply` so that they can be undone.
+#[derive(Clone)]
+pub(super) struct VoxelEntries {
+    pub(super) entries: Rc<RefCell<Vec<(IStr, Voxel3)>>>,
+    pub(super) selected_voxel: Binding<Option<usize>>,
+    recent_voxels: Binding<Vec<usize>>,
+    pub(super) voxel: Binding<Voxel3>,
+    pub(super) active_face: Binding<VoxelFace>,
+    /// The frame of the voxel's animation being edited.
+    pub(super) active_frame: Binding<usize>,
+    /// Whether the voxel's animation is playing rather than showing the frame being edited.
+    pub(super) playing: Binding<bool>,
+    selected_glyph: Binding<Option<usize>>,
+    active_char_offset: Binding<i32>,
+    pub(super) load_voxel: Rc<dyn Fn(usize)>,
+    pub(super) history: Rc<RefCell<History<EditorAction>>>,
+    /// The models using each voxel, which are left alone since the editor can't change them yet.
+    used_by: Rc<HashMap<IStr, Vec<IStr>>>,
+    pub(super) dialogs: DialogHandle,
+}
+
+impl VoxelEntries {
+    /// Does `action`, remembering it so that it can be undone.
+    pub(super) fn apply(&self, action: EditorAction) {
+        self.perform(&action);
+
+        match &action {
+            // The list has already moved its rows and selection along
+            EditorAction::Move { .. } => (),
+            EditorAction::SetVoxel { index, new, .. } => {
+                if self.selected_voxel.get() == Some(*index) {
+                    self.voxel.set(new.clone());
+                }
+            }
+            _ => self.load_focus(&action),
+        }
+
+        self.history.borrow_mut().push(action);
+    }
+
+    /// Undoes the last edit, returning whether there was one.
+    pub(super) fn undo(&self) -> bool {
+        let action = self.history.borrow_mut().done.pop_back();
+
+        match action {
+            Some(action) => {
+                let inverse = action.inverse();
+                self.perform(&inverse);
+                self.load_focus(&inverse);
+                self.history.borrow_mut().undone.push(action);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone edit, returning whether there was one.
+    pub(super) fn redo(&self) -> bool {
+        let action = self.history.borrow_mut().undone.pop();
+
+        match action {
+            Some(action) => {
+                self.perform(&action);
+                self.load_focus(&action);
+                self.history.borrow_mut().done.push_back(action);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Changes the entries and the recent voxels to match `action`, without loading anything.
+    pub(super) fn perform(&self, action: &EditorAction) {
+        match action {
+            EditorAction::SetVoxel { index, new, .. } => {
+                self.entries.borrow_mut()[*index].1 = new.clone();
+            }
+
+            EditorAction::Insert { index, name, voxel } => {
+                let index = *index;
+                self.entries
+                    .borrow_mut()
+                    .insert(index, (name.clone(), voxel.clone()));
+
+                self.recent_voxels.set(
+                    self.recent_voxels
+                        .get()
+                        .into_iter()
+                        .map(|i| if i >= index { i + 1 } else { i })
+                        .collect(),
+                );
+            }
+
+            EditorAction::Remove { index, .. } => {
+                let index = *index;
+                self.entries.borrow_mut().remove(index);
+
+                self.recent_voxels.set(
+                    self.recent_voxels
+                        .get()
+                        .into_iter()
+                        .filter(|&i| i != index)
+                        .map(|i| if i > index { i - 1 } else { i })
+                        .collect(),
+                );
+            }
+
+            EditorAction::Rename { index, new, .. } => {
+                self.entries.borrow_mut()[*index].0 = new.clone();
+            }
+
+            EditorAction::Move { from, to } => {
+                let (from, to) = (*from, *to);
+                let mut entries = self.entries.borrow_mut();
+                let entry = entries.remove(from);
+                entries.insert(to, entry);
+
+                self.recent_voxels.set(
+                    self.recent_voxels
+                        .get()
+                        .into_iter()
+                        .map(|i| moved_index(i, from, to))
+                        .collect(),
+                );
+            }
+
+            EditorAction::Compound(actions) => {
+                for action in actions {
+                    self.perform(action);
+                }
+            }
+        }
+    }
+
+    /// Loads the entry `action` left in focus, or clears the selection if there are none left.
+    fn load_focus(&self, action: &EditorAction) {
+        let len = self.entries.borrow().len();
+
+        match action.focus() {
+            Some(_) if len == 0 => {
+                self.selected_voxel.set(None);
+                self.voxel.set(Voxel3::default());
+            }
+
+            Some(index) => (self.load_voxel)(index.min(len - 1)),
+            None => (),
+        }
+    }
+
+    /// Adds an entry at `index` and loads it.
+    pub(super) fn insert(&self, index: usize, name: IStr, voxel: Voxel3) {
+        self.apply(EditorAction::Insert { index, name, voxel });
+    }
+
+    /// Removes the entry at `index` and loads the one that takes its place, if any.
+    pub(super) fn remove(&self, index: usize) {
+        let (name, voxel) = self.entries.borrow()[index].clone();
+        self.apply(EditorAction::Remove { index, name, voxel });
+    }
+
+    /// Gives the entry at `index` a new name, or says why it can't have it.
+    pub(super) fn rename(&self, index: usize, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        let old = self.entries.borrow()[index].0.clone();
+
+        if name.is_empty() {
+            return Err(String::from("The name can't be empty"));
+        }
+
+        if self
+            .entries
+            .borrow()
+            .iter()
+            .enumerate()
+            .any(|(i, (other, _))| i != index && &**other == name)
+        {
+            return Err(format!("\"{}\" is already taken", name));
+        }
+
+        if &*old != name {
+            self.apply(EditorAction::Rename {
+                index,
+                old,
+                new: IStr::new(name),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Shows an edited voxel, writing it back to the selected entry if there is one.
+    fn set_voxel(&self, voxel: Voxel3) {
+        match self.selected_voxel.get() {
+            Some(index) => {
+                let old = self.entries.borrow()[index].1.clone();
+
+                if old != voxel {
+                    self.apply(EditorAction::SetVoxel {
+                        index,
+                        old,
+                        new: voxel,
+                    });
+                }
+            }
+
+            None => self.voxel.set(voxel),
+        }
+    }
+
+    /// The face `face` of the frame being edited.
+    pub(super) fn face(&self, face: VoxelFace) -> Voxel2 {
+        self.voxel.get().frame(self.active_frame.get())[face].clone()
+    }
+
+    /// Puts the glyph at `char_offset` on the active face.
+    fn set_char_offset(&self, char_offset: u16) {
+        let mut voxel = self.voxel.get();
+        voxel.frame_mut(self.active_frame.get())[self.active_face.get()].char_offset = char_offset;
+        self.set_voxel(voxel);
+
+        self.selected_glyph.set(Some(usize::from(char_offset)));
+        self.active_char_offset.set(i32::from(char_offset));
+    }
+
+    /// Makes `face` the active one and selects its glyph.
+    fn select_face(&self, face: VoxelFace) {
+        let char_offset = self.face(face).char_offset;
+        self.active_face.set(face);
+        self.selected_glyph.set(Some(usize::from(char_offset)));
+        self.active_char_offset.set(i32::from(char_offset));
+    }
+
+    /// Moves on to the face after the active one, going back to the first after the last.
+    fn next_face(&self) {
+        let next = match self.active_face.get() {
+            VoxelFace::X => VoxelFace::Y,
+            VoxelFace::Y => VoxelFace::Z,
+            VoxelFace::Z => VoxelFace::X,
+        };
+
+        self.select_face(next);
+    }
+
+    /// Resets the active face to an empty glyph with default colors.
+    fn clear_face(&self) {
+        let mut voxel = self.voxel.get();
+        voxel.frame_mut(self.active_frame.get())[self.active_face.get()] = Voxel2::default();
+        self.set_voxel(voxel);
+        self.select_face(self.active_face.get());
+    }
+
+    /// Adds a blank frame after the one being edited and starts editing it.
+    fn add_frame(&self) {
+        let mut voxel = self.voxel.get();
+        let index = self.active_frame.get().min(voxel.frame_count() - 1) + 1;
+        voxel.insert_frame(index, &Voxel3::default());
+        self.set_voxel(voxel);
+        self.show_frame(index);
+    }
+
+    /// Adds a copy of the frame being edited after it and starts editing the copy.
+    fn duplicate_frame(&self) {
+        let mut voxel = self.voxel.get();
+        let index = self.active_frame.get().min(voxel.frame_count() - 1);
+        voxel.duplicate_frame(index);
+        self.set_voxel(voxel);
+        self.show_frame(index + 1);
+    }
+
+    /// Takes away the frame being edited, unless it's the only one.
+    fn remove_frame(&self) {
+        let mut voxel = self.voxel.get();
+        let index = self.active_frame.get().min(voxel.frame_count() - 1);
+        if voxel.remove_frame(index) {
+            let last = voxel.frame_count() - 1;
+            self.set_voxel(voxel);
+            self.show_frame(index.min(last));
+        }
+    }
+
+    /// Stops playing and edits the frame at `index`.
+    pub(super) fn show_frame(&self, index: usize) {
+        self.playing.set(false);
+        self.active_frame.set(index);
+        self.select_face(self.active_face.get());
+    }
+
+    /// Starts or stops playing the animation, going back to editing whichever frame it stopped
+    /// on.
+    fn toggle_playing(&self) {
+        if self.playing.get() {
+            self.show_frame(self.active_frame.get());
+        } else {
+            self.playing.set(true);
+        }
+    }
+
+    /// The models that would break if the entry at `index` was renamed or deleted.
+    fn users(&self, index: usize) -> Vec<IStr> {
+        self.used_by
+            .get(&self.entries.borrow()[index].0)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub(super) fn create(&self) {
+        let name = unique_name(&self.entries.borrow(), "voxel");
+        let index = self.entries.borrow().len();
+        self.insert(index, name, Voxel3::default());
+    }
+
+    fn duplicate(&self) {
+        if let Some(index) = self.selected_voxel.get() {
+            let (name, voxel) = self.entries.borrow()[index].clone();
+            let name = unique_name(&self.entries.borrow(), &format!("{} copy", &*name));
+            self.insert(index + 1, name, voxel);
+        }
+    }
+
+    fn open_rename(&self) {
+        if let Some(index) = self.selected_voxel.get() {
+            if !self.is_in_use(index, "rename") {
+                let name = String::from(&*self.entries.borrow()[index].0);
+                self.open_rename_dialog(index, name, None);
+            }
+        }
+    }
+
+    fn open_rename_dialog(&self, index: usize, name: String, error: Option<String>) {
+        let text = bind(name);
+        let submit = {
+            let this = self.clone();
+            move |name: String| match this.rename(index, &name) {
+                Ok(()) => this.dialogs.close(),
+                Err(e) => this.open_rename_dialog(index, name, Some(e)),
+            }
+        };
+
+        let mut body = vec![FlexElement::fixed(Box::new(
+            TextInput::new(text.clone())
+                .with_focus()
+                .with_on_submit(submit.clone()),
+        ))];
+
+        if let Some(error) = error {
+            body.push(FlexElement::fixed(Box::new(KataText::from_colored_str(
+                &error,
+                color::RED,
+            ))));
+        }
+
+        let dialogs = self.dialogs.clone();
+        self.dialogs.open(dialog(
+            "Rename",
+            body,
+            vec![
+                Button::new("OK", move || submit(text.get())),
+                Button::new("Cancel", move || dialogs.close()),
+            ],
+        ));
+    }
+
+    fn open_delete(&self) {
+        if let Some(index) = self.selected_voxel.get() {
+            if self.is_in_use(index, "delete") {
+                return;
+            }
+
+            let name = self.entries.borrow()[index].0.clone();
+            let this = self.clone();
+            let dialogs = self.dialogs.clone();
+
+            self.dialogs.open(dialog(
+                "Delete",
+                vec![FlexElement::fixed(Box::new(KataText::from_str(&format!(
+                    "Delete \"{}\"?",
+                    &*name
+                ))))],
+                vec![
+                    Button::new("Delete", move || {
+                        this.remove(index);
+                        this.dialogs.close();
+                    }),
+                    Button::new("Cancel", move || dialogs.close()),
+                ],
+            ));
+        }
+    }
+
+    /// Opens a dialog comparing the selected voxel with another one, starting with the one
+    /// selected before it.
+    fn open_compare(&self) {
+        let selected = match self.selected_voxel.get() {
+            Some(selected) => selected,
+            None => return,
+        };
+
+        let count = self.entries.borrow().len();
+        if count < 2 {
+            return;
+        }
+
+        let right = self
+            .recent_voxels
+            .get()
+            .into_iter()
+            .find(|&index| index != selected && index < count)
+            .unwrap_or((selected + 1) % count);
+        let comparison = VoxelComparison {
+            actions: self.clone(),
+            right: bind(right),
+        };
+
+        let faces = [VoxelFace::X, VoxelFace::Y, VoxelFace::Z]
+            .iter()
+            .map(|&face| {
+                let comparison = comparison.clone();
+                FlexElement::fixed(Box::new(Padding::new(
+                    FlexLayout::vertical(vec![
+                        FlexElement::fixed(Box::new(FaceComparison::new(comparison.clone(), face))),
+                        FlexElement::fixed(Box::new(Button::new("Copy ->", move || {
+                            comparison.copy_face(face)
+                        }))),
+                    ]),
+                    0,
+                    1,
+                    0,
+                    0,
+                )))
+            })
+            .collect();
+
+        let step_button = |label: &str, steps: i32| {
+            let comparison = comparison.clone();
+            Button::new(label, move || comparison.step(steps))
+        };
+        let dialogs = self.dialogs.clone();
+
+        self.dialogs.open(dialog(
+            "Compare",
+            vec![
+                FlexElement::fixed(Box::new(ComparedNames {
+                    comparison: comparison.clone(),
+                })),
+                FlexElement::fixed(Box::new(FlexLayout::horizontal(faces))),
+            ],
+            vec![
+                step_button("< Prev", -1),
+                step_button("Next >", 1),
+                Button::new("Close", move || dialogs.close()),
+            ],
+        ));
+    }
+
+    /// Whether models use the entry at `index`, in which case a dialog says which ones instead
+    /// of letting it be renamed or deleted.
+    fn is_in_use(&self, index: usize, action: &str) -> bool {
+        let users = self.users(index);
+        if users.is_empty() {
+            return false;
+        }
+
+        let users: Vec<&str> = users.iter().map(|name| &**name).collect();
+        let dialogs = self.dialogs.clone();
+
+        self.dialogs.open(dialog(
+            "In use",
+            vec![FlexElement::fixed(Box::new(KataText::from_str(&format!(
+                "Can't {} \"{}\", it's used by {}",
+                action,
+                &*self.entries.borrow()[index].0,
+                users.join(", ")
+            ))))],
+            vec![Button::new("OK", move || dialogs.close())],
+        ));
+
+        true
+    }
+}
+
+/// The names of the voxels each model uses.
+fn voxel_users(models: &BTreeMap<IStr, Model>) -> HashMap<IStr, Vec<IStr>> {
+    let mut users: HashMap<IStr, Vec<IStr>> = HashMap::new();
+
+    for (model_name, model) in models {
+        for voxel_name in model.voxels.iter().flatten() {
+            let model_names = users.entry(voxel_name.clone()).or_default();
+            if !model_names.contains(model_name) {
+                model_names.push(model_name.clone());
+            }
+        }
+    }
+
+    users
+}
+
+/// Where a model uses a voxel, as found by a usage search.
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Usage {
+    pub(super) model: IStr,
+    /// How many cells hold the voxel.
+    pub(super) count: usize,
+    /// The first of those cells, going through the slices front to back.
+    pub(super) first: (usize, usize, usize),
+}
+
+/// How `model_name` uses `voxel`, or `None` if it doesn't.
+fn find_usage(voxel: &IStr, model_name: &IStr, model: &Model) -> Option<Usage> {
+    let cells: Vec<(usize, usize, usize)> = model
+        .voxels
+        .indexed_iter()
+        .filter(|(_, cell)| cell.as_ref() == Some(voxel))
+        .map(|(pos, _)| pos)
+        .collect();
+
+    let first = cells.iter().copied().min_by_key(|&(x, y, z)| (z, y, x))?;
+
+    Some(Usage {
+        model: model_name.clone(),
+        count: cells.len(),
+        first,
+    })
+}
+
+/// Looks through `models` for `voxel` on another thread, sending the usage in each model as
+/// soon as it's found so that hundreds of models don't hold up the UI. The channel is closed
+/// once every model was looked at.
+pub(super) fn spawn_usage_search(voxel: IStr, models: BTreeMap<IStr, Model>) -> Receiver<Usage> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        for (name, model) in &models {
+            if let Some(usage) = find_usage(&voxel, name, model) {
+                // Nobody is listening anymore when another search was started
+                if sender.send(usage).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+/// `base`, or `base` followed by the lowest number that makes it unique among `entries`.
+pub(super) fn unique_name<T>(entries: &[(IStr, T)], base: &str) -> IStr {
+    let taken = |name: &str| entries.iter().any(|(other, _)| &**other == name);
+
+    if !taken(base) {
+        return IStr::new(base);
+    }
+
+    (2..)
+        .map(|i| format!("{} {}", base, i))
+        .find(|name| !taken(name))
+        .map(|name| IStr::new(&name))
+        .unwrap()
+}
+
+pub(super) fn voxel_row(name: &IStr) -> Box<dyn Row> {
+    let name = name.clone();
+
+    Box::new(Tooltip::new(KataText::from_str(&name), move |_, _| {
+        Some(String::from(&*name))
+    }))
+}
+
+fn usage_row(usage: &Usage) -> Box<dyn Row> {
+    let (x, y, z) = usage.first;
+    let text = format!("{}: {} at {}, {}, {}", &*usage.model, usage.count, x, y, z);
+
+    Box::new(Tooltip::new(KataText::from_str(&text), move |_, _| {
+        Some(text.clone())
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_voxel_mode_small_layout() {
+        let voxels: BTreeMap<IStr, Voxel3> = (0..3)
+            .map(|i| (IStr::new(&format!("voxel{}", i)), Voxel3::default()))
+            .collect();
+
+        let mut mode = VoxelMode::new(
+            &voxels,
+            &BTreeMap::new(),
+            &Settings::default(),
+            &bind(Vec::new()),
+            &Recent {
+                voxel: Some(IStr::new("voxel1")),
+                ..Recent::default()
+            },
+            32,
+            1024,
+        );
+
+        for &(width, height) in &[(80, 40), (5, 5), (1, 1), (0, 0), (80, 40)] {
+            let size = Size::new(width, height);
+            assert_eq!(mode.layout.layout(BoxConstraints::exact(size)), size);
+        }
+    }
+
+    #[test]
+    fn test_voxel_mode_paste() {
+        let voxels: BTreeMap<IStr, Voxel3> = (0..2)
+            .map(|i| (IStr::new(&format!("voxel{}", i)), Voxel3::default()))
+            .collect();
+
+        let mode = VoxelMode::new(
+            &voxels,
+            &BTreeMap::new(),
+            &Settings::default(),
+            &bind(Vec::new()),
+            &Recent {
+                voxel: Some(IStr::new("voxel1")),
+                ..Recent::default()
+            },
+            32,
+            1024,
+        );
+
+        assert!(mode.paste_face(Voxel2::new(12)));
+        assert_eq!(mode.active_face().char_offset, 12);
+        assert_eq!(mode.actions.active_char_offset.get(), 12);
+        assert_eq!(
+            mode.voxels()[&IStr::new("voxel1")][VoxelFace::X].char_offset,
+            12
+        );
+        assert_eq!(mode.voxels()[&IStr::new("voxel0")], Voxel3::default());
+
+        assert!(mode.paste_voxel(Voxel3::default()));
+        assert_eq!(mode.active_face().char_offset, 0);
+
+        assert!(mode.actions.undo());
+        assert_eq!(mode.selected_voxel().unwrap()[VoxelFace::X].char_offset, 12);
+    }
+
+    fn test_entries(names: &[&str]) -> VoxelEntries {
+        let selected_voxel: Binding<Option<usize>> = bind(None);
+
+        VoxelEntries {
+            entries: Rc::new(RefCell::new(
+                names
+                    .iter()
+                    .map(|&name| (IStr::new(name), Voxel3::default()))
+                    .collect(),
+            )),
+            selected_voxel: selected_voxel.clone(),
+            recent_voxels: bind(Vec::new()),
+            voxel: bind(Voxel3::default()),
+            active_face: bind(VoxelFace::X),
+            active_frame: bind(0),
+            playing: bind(false),
+            selected_glyph: bind(None),
+            active_char_offset: bind(0),
+            load_voxel: Rc::new(move |index: usize| selected_voxel.set(Some(index))),
+            history: Rc::new(RefCell::new(History::default())),
+            used_by: Rc::new(HashMap::new()),
+            dialogs: DialogHandle::new(),
+        }
+    }
+
+    fn entry_names(entries: &VoxelEntries) -> Vec<String> {
+        entries
+            .entries
+            .borrow()
+            .iter()
+            .map(|(name, _)| String::from(&**name))
+            .collect()
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let entries = test_entries(&["voxel", "voxel 2", "wall"]);
+        let entries = entries.entries.borrow();
+
+        assert_eq!(&*unique_name(&entries, "floor"), "floor");
+        assert_eq!(&*unique_name(&entries, "wall"), "wall 2");
+        assert_eq!(&*unique_name(&entries, "voxel"), "voxel 3");
+    }
+
+    #[test]
+    fn test_voxel_entries() {
+        let entries = test_entries(&["a", "b"]);
+        entries.selected_voxel.set(Some(0));
+        entries.recent_voxels.set(vec![1, 0]);
+
+        entries.duplicate();
+        assert_eq!(entry_names(&entries), ["a", "a copy", "b"]);
+        assert_eq!(entries.selected_voxel.get(), Some(1));
+        assert_eq!(entries.recent_voxels.get(), vec![2, 0]);
+
+        entries.create();
+        assert_eq!(entry_names(&entries), ["a", "a copy", "b", "voxel"]);
+        assert_eq!(entries.selected_voxel.get(), Some(3));
+
+        assert!(entries.rename(3, " ").is_err());
+        assert!(entries.rename(3, "b").is_err());
+        assert!(entries.rename(3, " c ").is_ok());
+        assert_eq!(entry_names(&entries), ["a", "a copy", "b", "c"]);
+
+        entries.remove(0);
+        assert_eq!(entry_names(&entries), ["a copy", "b", "c"]);
+        assert_eq!(entries.recent_voxels.get(), vec![1]);
+        assert_eq!(entries.selected_voxel.get(), Some(0));
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let entries = test_entries(&["a", "b", "c"]);
+        entries.selected_voxel.set(Some(0));
+
+        entries.set_char_offset(5);
+        entries.next_face();
+        entries.set_char_offset(7);
+        entries.remove(1);
+        entries.apply(EditorAction::Move { from: 1, to: 0 });
+        assert!(entries.rename(0, "d").is_ok());
+        assert_eq!(entry_names(&entries), ["d", "a"]);
+
+        for _ in 0..3 {
+            assert!(entries.undo());
+        }
+
+        assert_eq!(entry_names(&entries), ["a", "b", "c"]);
+        assert_eq!(entries.selected_voxel.get(), Some(1));
+
+        assert!(entries.undo());
+        assert_eq!(entries.entries.borrow()[0].1[VoxelFace::Y].char_offset, 0);
+        assert!(entries.undo());
+        assert_eq!(entries.entries.borrow()[0].1, Voxel3::default());
+        assert!(!entries.undo());
+
+        assert!(entries.redo());
+        assert!(entries.redo());
+        assert_eq!(entries.entries.borrow()[0].1[VoxelFace::X].char_offset, 5);
+        assert_eq!(entries.entries.borrow()[0].1[VoxelFace::Y].char_offset, 7);
+
+        entries.clear_face();
+        assert!(!entries.redo());
+        assert_eq!(entries.entries.borrow()[0].1[VoxelFace::Y].char_offset, 0);
+    }
+
+    #[test]
+    fn test_voxel_frames() {
+        let entries = test_entries(&["a"]);
+        entries.selected_voxel.set(Some(0));
+        entries.set_char_offset(5);
+
+        entries.duplicate_frame();
+        entries.set_char_offset(6);
+        entries.add_frame();
+        assert_eq!(entries.active_frame.get(), 2);
+        assert_eq!(entries.active_char_offset.get(), 0);
+
+        let offsets = |entries: &VoxelEntries| {
+            let voxel = entries.entries.borrow()[0].1.clone();
+            (0..voxel.frame_count())
+                .map(|i| voxel.frame(i)[VoxelFace::X].char_offset)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(offsets(&entries), vec![5, 6, 0]);
+
+        entries.show_frame(0);
+        entries.remove_frame();
+        assert_eq!(offsets(&entries), vec![6, 0]);
+        assert_eq!(entries.active_char_offset.get(), 6);
+
+        // Frames are edited like anything else, so they come back on undo
+        assert!(entries.undo());
+        assert_eq!(offsets(&entries), vec![5, 6, 0]);
+
+        entries.toggle_playing();
+        assert!(entries.playing.get());
+        entries.show_frame(1);
+        assert!(!entries.playing.get());
+    }
+
+    #[test]
+    fn test_voxel_comparison() {
+        let entries = test_entries(&["a", "b", "c"]);
+        entries.selected_voxel.set(Some(0));
+        entries.set_char_offset(5);
+        let comparison = VoxelComparison {
+            actions: entries.clone(),
+            right: bind(1),
+        };
+        let names = ComparedNames {
+            comparison: comparison.clone(),
+        };
+        assert_eq!(names.lines(), ["a vs b", "1 of 3 faces differ"]);
+
+        comparison.copy_face(VoxelFace::X);
+        assert_eq!(entries.entries.borrow()[1].1[VoxelFace::X].char_offset, 5);
+        assert_eq!(names.lines()[1], "0 of 3 faces differ");
+        // Copying a face that's the same already changes nothing
+        comparison.copy_face(VoxelFace::Y);
+        assert_eq!(entries.history.borrow().done.len(), 2);
+
+        assert!(entries.undo());
+        assert_eq!(entries.entries.borrow()[1].1, Voxel3::default());
+
+        // Going round skips the voxel on the left
+        entries.selected_voxel.set(Some(0));
+        comparison.step(1);
+        assert_eq!(comparison.right.get(), 2);
+        comparison.step(1);
+        assert_eq!(comparison.right.get(), 1);
+        comparison.step(-1);
+        assert_eq!(comparison.right.get(), 2);
+    }
+
+    #[test]
+    fn test_voxel_users() {
+        let mut voxels = Array3::from_elem((2, 1, 1), None);
+        voxels[(0, 0, 0)] = Some(IStr::new("wall"));
+        voxels[(1, 0, 0)] = Some(IStr::new("wall"));
+        let models = vec![(IStr::new("room"), Model::new(voxels))]
+            .into_iter()
+            .collect();
+
+        let users = voxel_users(&models);
+        assert_eq!(users[&IStr::new("wall")], vec![IStr::new("room")]);
+        assert!(!users.contains_key(&IStr::new("floor")));
+    }
+
+    #[test]
+    fn test_usage_search() {
+        let wall = IStr::new("wall");
+        let mut voxels = Array3::from_elem((3, 2, 2), None);
+        voxels[(2, 0, 0)] = Some(IStr::new("floor"));
+        voxels[(0, 1, 1)] = Some(wall.clone());
+        voxels[(2, 1, 0)] = Some(wall.clone());
+        voxels[(1, 1, 0)] = Some(wall.clone());
+        let room = Model::new(voxels);
+        let empty = Model::new(Array3::from_elem((1, 1, 1), None));
+
+        assert_eq!(
+            find_usage(&wall, &IStr::new("room"), &room),
+            Some(Usage {
+                model: IStr::new("room"),
+                count: 3,
+                first: (1, 1, 0),
+            })
+        );
+        assert_eq!(find_usage(&wall, &IStr::new("empty"), &empty), None);
+
+        let models = vec![(IStr::new("room"), room), (IStr::new("empty"), empty)]
+            .into_iter()
+            .collect();
+        let usages: Vec<Usage> = spawn_usage_search(wall, models).iter().collect();
+        assert_eq!(usages.len(), 1);
+        assert_eq!(usages[0].model, IStr::new("room"));
+    }
+}
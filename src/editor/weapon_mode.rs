@@ -0,0 +1,486 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeMap,
+    f32::consts::PI,
+    rc::Rc,
+};
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::event::{KeyCode, KeyMods};
+use internship::IStr;
+use ndarray::Array3;
+
+use crate::{
+    editor::{
+        history::{History, ModelEdit},
+        model_mode::ModelSlice,
+        panels::{button_row, divider, BoundList},
+        tools::{ModelTool, MODEL_TOOLS},
+        voxel_mode::unique_name,
+    },
+    rendering::{
+        color,
+        drawable::Drawable,
+        tile::TileType,
+        voxel::{Voxel2, Voxel3, VoxelRotation},
+    },
+    ui::*,
+    weapons::{WeaponCell, WeaponModels},
+};
+
+/// How big a new weapon model is, which is as long and as tall as the rifle.
+const NEW_WEAPON_SIZE: (usize, usize) = (11, 3);
+
+/// The weapon models, painted flat the way they're held in the game with the muzzle on the left.
+/// They're painted with the voxel selected in voxel mode, and the tiles already in them are shown
+/// as they look in the game.
+pub(super) struct WeaponMode {
+    pub(super) layout: FlexLayout,
+    pub(super) actions: WeaponEntries,
+    pub(super) tool: Binding<ModelTool>,
+    /// The voxel that gets painted, which is the one selected in voxel mode.
+    pub(super) brush: Binding<Option<IStr>>,
+}
+
+impl WeaponMode {
+    pub(super) fn new(
+        weapons: &WeaponModels,
+        voxels: &BTreeMap<IStr, Voxel3>,
+        current: Option<&IStr>,
+        tool: ModelTool,
+    ) -> Self {
+        let slices = weapon_slices(weapons);
+        let voxels = Rc::new(weapon_voxels(voxels, &slices));
+        let entries: Vec<_> = slices.into_iter().collect();
+
+        // Fall back to the first model when the last one is gone
+        let selected = current
+            .and_then(|current| entries.iter().position(|(name, _)| name == current))
+            .or_else(|| if entries.is_empty() { None } else { Some(0) });
+
+        let actions = WeaponEntries {
+            entries: bind(entries),
+            selected: bind(selected),
+            name: bind(String::new()),
+            model: bind(Array3::from_elem((0, 0, 1), None)),
+            history: Rc::new(RefCell::new(History::default())),
+            switched: Rc::new(Cell::new(false)),
+        };
+        actions.load();
+
+        let list = SelectableList::from_vec(Vec::new(), actions.selected.clone()).with_on_select({
+            let actions = actions.clone();
+            move |_| actions.load()
+        });
+
+        let action_button = |label: &str, action: fn(&WeaponEntries)| {
+            let actions = actions.clone();
+            Button::new(label, move || action(&actions))
+        };
+
+        let list_pane = FlexLayout::vertical(vec![
+            FlexElement::flex(
+                Box::new(BoundList::new(list, actions.entries.clone(), weapon_row)),
+                1,
+            ),
+            FlexElement::fixed(Box::new(button_row(vec![
+                action_button("New", WeaponEntries::create),
+                action_button("Delete", WeaponEntries::delete),
+            ]))),
+        ]);
+
+        let name_row = FlexLayout::horizontal(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("Name "))),
+            FlexElement::flex(
+                Box::new(TextInput::new(actions.name.clone()).with_on_submit({
+                    let actions = actions.clone();
+                    move |name: String| actions.rename(&name)
+                })),
+                1,
+            ),
+        ]);
+
+        let tool = bind(tool);
+        let brush = bind(None);
+        let tool_buttons = MODEL_TOOLS
+            .iter()
+            .map(|&t| {
+                let tool = tool.clone();
+                Button::new(t.title(), move || tool.set(t))
+            })
+            .collect();
+
+        let slice_view = ModelSlice::new(
+            actions.model.clone(),
+            bind(0),
+            tool.clone(),
+            brush.clone(),
+            voxels,
+        )
+        .with_on_edit({
+            let actions = actions.clone();
+            move |edit| {
+                actions.history.borrow_mut().push(edit);
+                actions.store();
+            }
+        });
+
+        let weapon_pane = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(name_row)),
+            FlexElement::fixed(Box::new(button_row(tool_buttons))),
+            FlexElement::flex(Box::new(Padding::new(slice_view, 1, 0, 0, 0)), 1),
+        ]);
+
+        Self {
+            layout: FlexLayout::horizontal(vec![
+                FlexElement::flex(Box::new(Frame::new(list_pane).with_title("Weapons")), 1),
+                FlexElement::fixed(divider()),
+                FlexElement::flex(Box::new(Frame::new(weapon_pane).with_title("Weapon")), 3),
+            ]),
+            actions,
+            tool,
+            brush,
+        }
+    }
+
+    /// How big the weapon is and what the painter does, e.g. "11x3  Pencil".
+    pub(super) fn status(&self) -> String {
+        let (width, height, _) = self.actions.model.get().dim();
+        format!("{}x{}  {}", width, height, self.tool.get().title())
+    }
+
+    /// Handles the keyboard shortcuts of the mode, returning whether `keycode` was one.
+    pub(super) fn handle_shortcut(&mut self, keycode: KeyCode, mods: KeyMods) -> bool {
+        let ctrl = mods.contains(KeyMods::CTRL);
+
+        match keycode {
+            KeyCode::Z if ctrl && mods.contains(KeyMods::SHIFT) => self.actions.redo(),
+            KeyCode::Z if ctrl => self.actions.undo(),
+            KeyCode::Y if ctrl => self.actions.redo(),
+            _ => false,
+        }
+    }
+}
+
+/// The weapon models with the selected one loaded into a name field and the slice, which write
+/// back to it as they're changed.
+#[derive(Clone)]
+pub(super) struct WeaponEntries {
+    pub(super) entries: Binding<Vec<(IStr, Array3<Option<IStr>>)>>,
+    pub(super) selected: Binding<Option<usize>>,
+    pub(super) name: Binding<String>,
+    pub(super) model: Binding<Array3<Option<IStr>>>,
+    /// The edits made to the selected model, which start over when another one is loaded.
+    pub(super) history: Rc<RefCell<History<ModelEdit>>>,
+    /// Set when another model is loaded, for the editor to lay the slice out again on the next
+    /// update.
+    pub(super) switched: Rc<Cell<bool>>,
+}
+
+impl WeaponEntries {
+    pub(super) fn selected_entry(&self) -> Option<(IStr, Array3<Option<IStr>>)> {
+        self.selected
+            .get()
+            .and_then(|index| self.entries.get().get(index).cloned())
+    }
+
+    pub(super) fn selected_name(&self) -> Option<IStr> {
+        self.selected_entry().map(|(name, _)| name)
+    }
+
+    /// Shows the selected model in the name field and the slice.
+    pub(super) fn load(&self) {
+        let (name, model) = self
+            .selected_entry()
+            .unwrap_or_else(|| (IStr::new(""), Array3::from_elem((0, 0, 1), None)));
+
+        self.name.set(String::from(&*name));
+        self.model.set(model);
+        *self.history.borrow_mut() = History::default();
+        self.switched.set(true);
+    }
+
+    /// Writes the slice back to the selected model.
+    fn store(&self) {
+        if let Some(index) = self.selected.get() {
+            let mut entries = self.entries.get();
+
+            if let Some(entry) = entries.get_mut(index) {
+                entry.1 = self.model.get();
+                self.entries.set(entries);
+            }
+        }
+    }
+
+    /// Renames the selected model, unless `name` is empty or taken, in which case the name field
+    /// goes back to the old name.
+    pub(super) fn rename(&self, name: &str) {
+        let name = name.trim();
+        let index = match self.selected.get() {
+            Some(index) => index,
+            None => return,
+        };
+
+        let mut entries = self.entries.get();
+        let taken = entries
+            .iter()
+            .enumerate()
+            .any(|(i, (other, _))| i != index && &**other == name);
+
+        if !name.is_empty() && !taken && index < entries.len() {
+            entries[index].0 = IStr::new(name);
+            self.entries.set(entries);
+        }
+
+        // Unlike loading, this keeps the edits to the model that can be undone
+        if let Some(name) = self.selected_name() {
+            self.name.set(String::from(&*name));
+        }
+    }
+
+    /// Adds a blank model under a new name at the end and selects it.
+    pub(super) fn create(&self) {
+        let mut entries = self.entries.get();
+        let name = unique_name(&entries, "weapon");
+        let (width, height) = NEW_WEAPON_SIZE;
+        entries.push((name, Array3::from_elem((width, height, 1), None)));
+
+        self.selected.set(Some(entries.len() - 1));
+        self.entries.set(entries);
+        self.load();
+    }
+
+    pub(super) fn delete(&self) {
+        if let Some(index) = self.selected.get() {
+            let mut entries = self.entries.get();
+
+            if index < entries.len() {
+                entries.remove(index);
+                let selected = if entries.is_empty() {
+                    None
+                } else {
+                    Some(index.min(entries.len() - 1))
+                };
+
+                self.entries.set(entries);
+                self.selected.set(selected);
+                self.load();
+            }
+        }
+    }
+
+    /// Undoes the last edit to the selected model, returning whether there was one.
+    pub(super) fn undo(&self) -> bool {
+        let edit = self.history.borrow_mut().done.pop_back();
+
+        match edit {
+            Some(edit) => {
+                self.perform(&edit.inverse());
+                self.history.borrow_mut().undone.push(edit);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Redoes the last undone edit to the selected model, returning whether there was one.
+    pub(super) fn redo(&self) -> bool {
+        let edit = self.history.borrow_mut().undone.pop();
+
+        match edit {
+            Some(edit) => {
+                self.perform(&edit);
+                self.history.borrow_mut().done.push_back(edit);
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    pub(super) fn perform(&self, edit: &ModelEdit) {
+        let mut model = self.model.get();
+        edit.apply_to(&mut model);
+        self.model.set(model);
+        self.store();
+    }
+
+    /// The models as they're kept in `weapons.json`.
+    pub(super) fn models(&self) -> WeaponModels {
+        weapon_models(self.entries.get())
+    }
+}
+
+/// The weapon models as slices to paint, one voxel deep and with air left empty.
+pub(super) fn weapon_slices(weapons: &WeaponModels) -> BTreeMap<IStr, Array3<Option<IStr>>> {
+    weapons
+        .models
+        .iter()
+        .map(|(name, rows)| {
+            let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+            let slice = Array3::from_shape_fn((width, rows.len(), 1), |(x, y, _)| {
+                rows[y].get(x).and_then(WeaponCell::name)
+            });
+
+            (name.clone(), slice)
+        })
+        .collect()
+}
+
+/// The painted slices as they're kept in `weapons.json`, with the tiles written by name.
+pub(super) fn weapon_models<I>(slices: I) -> WeaponModels
+where
+    I: IntoIterator<Item = (IStr, Array3<Option<IStr>>)>,
+{
+    let models = slices
+        .into_iter()
+        .map(|(name, slice)| {
+            let (width, height, _) = slice.dim();
+            let rows: Vec<Vec<WeaponCell>> = (0..height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| WeaponCell::from_name(slice[[x, y, 0]].as_ref()))
+                        .collect()
+                })
+                .collect();
+
+            (name, rows)
+        })
+        .collect();
+
+    WeaponModels { models }
+}
+
+/// `voxels` along with a voxel for each tile the weapon models are made of, so that the slice can
+/// show them.
+fn weapon_voxels(
+    voxels: &BTreeMap<IStr, Voxel3>,
+    slices: &BTreeMap<IStr, Array3<Option<IStr>>>,
+) -> BTreeMap<IStr, Voxel3> {
+    let mut voxels = voxels.clone();
+
+    for name in slices.values().flatten().flatten() {
+        if let WeaponCell::Tile(tile_type) = WeaponCell::from_name(Some(name)) {
+            voxels
+                .entry(name.clone())
+                .or_insert_with(|| tile_voxel(tile_type));
+        }
+    }
+
+    voxels
+}
+
+/// A voxel that looks like `tile_type` does when it's held, from every side.
+fn tile_voxel(tile_type: TileType) -> Voxel3 {
+    let rotation = match (tile_type.rotation() / (PI / 2.0)).round() as i32 {
+        1 => VoxelRotation::Rotation90,
+        2 => VoxelRotation::Rotation180,
+        3 => VoxelRotation::Rotation270,
+        _ => VoxelRotation::None,
+    };
+
+    let face = Voxel2::new(tile_type.char_index())
+        .foreground(tile_type.get_color().into())
+        .rotation(rotation);
+    Voxel3::new(face.clone(), face.clone(), face)
+}
+
+fn weapon_row(entry: &(IStr, Array3<Option<IStr>>)) -> Box<dyn Row> {
+    let (name, slice) = entry;
+    let (width, height, _) = slice.dim();
+    let mut text = KataText::from_str(name);
+    text.push_span(&format!("  {}x{}", width, height), color::GRAY, None);
+
+    Box::new(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::voxel::VoxelFace;
+
+    #[test]
+    fn test_weapon_slices() {
+        let weapons: WeaponModels =
+            serde_json::from_str(r#"{ "pistol": [["Barrel", "slide"], ["Grip"]] }"#).unwrap();
+        let slices = weapon_slices(&weapons);
+        let pistol = &slices[&IStr::new("pistol")];
+
+        assert_eq!(pistol.dim(), (2, 2, 1));
+        assert_eq!(pistol[[0, 0, 0]], Some(IStr::new("Barrel")));
+        assert_eq!(pistol[[1, 0, 0]], Some(IStr::new("slide")));
+        assert_eq!(pistol[[1, 1, 0]], None);
+
+        // The short row is padded with air on the way back
+        let padded = weapon_models(slices.clone());
+        assert_eq!(
+            padded.models[&IStr::new("pistol")][1],
+            vec![
+                WeaponCell::Tile(TileType::Grip),
+                WeaponCell::Tile(TileType::Air)
+            ]
+        );
+        assert_eq!(weapon_models(weapon_slices(&padded)), padded);
+
+        // Only the tiles get voxels made up for them
+        let voxels = weapon_voxels(&BTreeMap::new(), &slices);
+        assert_eq!(voxels.len(), 2);
+        assert_eq!(
+            voxels[&IStr::new("Barrel")][VoxelFace::Z].char_offset,
+            TileType::Barrel.char_index()
+        );
+        assert_eq!(
+            tile_voxel(TileType::RecLower)[VoxelFace::X].rotation,
+            VoxelRotation::Rotation90
+        );
+    }
+
+    #[test]
+    fn test_weapon_entries() {
+        let mode = WeaponMode::new(
+            &WeaponModels::default(),
+            &BTreeMap::new(),
+            Some(&IStr::new("gone")),
+            ModelTool::default(),
+        );
+        let actions = &mode.actions;
+
+        assert_eq!(actions.selected_name(), Some(IStr::new("rifle")));
+        assert_eq!(actions.model.get().dim(), (11, 3, 1));
+        assert_eq!(actions.models(), WeaponModels::default());
+
+        actions.create();
+        assert_eq!(actions.name.get(), "weapon");
+        assert!(actions.switched.replace(false));
+        assert!(actions.model.get().iter().all(Option::is_none));
+
+        // Edits are written back to the model and can be undone
+        let mut model = actions.model.get();
+        let mut edit = ModelEdit::default();
+        edit.set_cells(&mut model, vec![(0, 1)], 0, &Some(IStr::new("barrel")));
+        actions.model.set(model);
+        actions.history.borrow_mut().push(edit);
+        actions.store();
+        assert_eq!(
+            actions.models().models[&IStr::new("weapon")][1][0],
+            WeaponCell::Voxel(IStr::new("barrel"))
+        );
+
+        actions.rename("rifle");
+        assert_eq!(actions.name.get(), "weapon");
+        actions.rename(" pistol ");
+        assert_eq!(actions.selected_name(), Some(IStr::new("pistol")));
+
+        assert!(actions.undo());
+        assert_eq!(
+            actions.models().models[&IStr::new("pistol")][1][0],
+            WeaponCell::Tile(TileType::Air)
+        );
+        assert!(!actions.undo());
+
+        actions.delete();
+        assert_eq!(actions.entries.get().len(), 1);
+        assert_eq!(actions.selected_name(), Some(IStr::new("rifle")));
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::VecDeque;
+
+use na::{Point3, Vector3};
+
+/// How many updates a floating text stays up for.
+pub const FLOATING_TEXT_TICS: u64 = 30;
+/// The most floating texts shown at once. The oldest are dropped early to make room, so holding
+/// the trigger down can't pile them up.
+pub const MAX_FLOATING_TEXTS: usize = 24;
+/// How far a floating text rises by the time it's gone.
+const RISE_HEIGHT: f32 = 1.0;
+/// How far apart the characters of a floating text are drawn, in tiles.
+pub const CHAR_SPACING: f32 = 0.35;
+/// How big the characters of a floating text are drawn, compared to a tile.
+pub const CHAR_SIZE: f32 = 0.5;
+
+/// A few characters drawn in the world rather than on the HUD, like the damage a shot did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloatingText {
+    pub text: String,
+    /// Where it started, in the middle of the text.
+    pub pos: Point3<f32>,
+    /// The update it appeared on.
+    pub born: u64,
+}
+
+impl FloatingText {
+    /// How far through its life it is on update `now`, from 0 to 1.
+    fn progress(&self, now: u64) -> f32 {
+        (now.saturating_sub(self.born) as f32 / FLOATING_TEXT_TICS as f32).min(1.0)
+    }
+
+    /// Where it is on update `now`, having risen since it appeared.
+    pub fn pos_at(&self, now: u64) -> Point3<f32> {
+        self.pos + Vector3::y() * RISE_HEIGHT * self.progress(now)
+    }
+
+    /// How opaque it is on update `now`, fading out as it rises.
+    pub fn alpha_at(&self, now: u64) -> f32 {
+        1.0 - self.progress(now)
+    }
+}
+
+/// The floating texts in the world, oldest first.
+#[derive(Clone, Debug, Default)]
+pub struct FloatingTexts {
+    texts: VecDeque<FloatingText>,
+}
+
+impl FloatingTexts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows `text` at `pos` from update `now`.
+    pub fn push(&mut self, text: String, pos: Point3<f32>, now: u64) {
+        if self.texts.len() == MAX_FLOATING_TEXTS {
+            self.texts.pop_front();
+        }
+
+        self.texts.push_back(FloatingText {
+            text,
+            pos,
+            born: now,
+        });
+    }
+
+    /// Drops the texts that have faded out by update `now`.
+    pub fn expire(&mut self, now: u64) {
+        self.texts
+            .retain(|text| now < text.born + FLOATING_TEXT_TICS);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FloatingText> {
+        self.texts.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rise_and_fade() {
+        let text = FloatingText {
+            text: String::from("10"),
+            pos: Point3::new(1.0, 2.0, 3.0),
+            born: 100,
+        };
+
+        assert_eq!(text.pos_at(100), text.pos);
+        assert_eq!(text.alpha_at(100), 1.0);
+        assert_eq!(
+            text.pos_at(100 + FLOATING_TEXT_TICS / 2),
+            Point3::new(1.0, 2.0 + RISE_HEIGHT / 2.0, 3.0)
+        );
+        assert_eq!(text.alpha_at(100 + FLOATING_TEXT_TICS / 2), 0.5);
+        // And stays where it got to
+        assert_eq!(text.alpha_at(100 + FLOATING_TEXT_TICS * 2), 0.0);
+        assert_eq!(
+            text.pos_at(100 + FLOATING_TEXT_TICS * 2),
+            Point3::new(1.0, 2.0 + RISE_HEIGHT, 3.0)
+        );
+    }
+
+    #[test]
+    fn test_expire() {
+        let mut texts = FloatingTexts::new();
+        texts.push(String::from("a"), Point3::origin(), 0);
+        texts.push(String::from("b"), Point3::origin(), 5);
+
+        texts.expire(FLOATING_TEXT_TICS - 1);
+        assert_eq!(texts.iter().count(), 2);
+        texts.expire(FLOATING_TEXT_TICS);
+        assert_eq!(texts.iter().next().unwrap().text, "b");
+        texts.expire(FLOATING_TEXT_TICS + 5);
+        assert_eq!(texts.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_capped() {
+        let mut texts = FloatingTexts::new();
+        for i in 0..MAX_FLOATING_TEXTS + 5 {
+            texts.push(i.to_string(), Point3::origin(), 0);
+        }
+
+        assert_eq!(texts.iter().count(), MAX_FLOATING_TEXTS);
+        assert_eq!(texts.iter().next().unwrap().text, "5");
+    }
+}
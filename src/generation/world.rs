@@ -1,12 +1,19 @@
+use ggez::graphics::Color;
+use log::warn;
 use na::*;
 use ndarray::prelude::*;
-use noise::{NoiseFn, OpenSimplex, Perlin, Value};
+use noise::{NoiseFn, OpenSimplex, Perlin, Seedable, Value};
 use rand::prelude::*;
 
 use crate::{
     constants::*,
     rendering::tile::{Tile, *},
+    settings::Settings,
     util::*,
+    world::{
+        util::{any_neighbour_is, path_distances, Adjacency},
+        World,
+    },
 };
 
 pub struct ChunkGenPackage {
@@ -18,40 +25,77 @@ pub struct ChunkGenPackage {
     // pub worley_weight: Value,
     pub value: Value,
     pub value_weight: Value,
+    pub noise_scale: f64,
+    pub noise_weight_scale: f64,
+    /// How many tiles a chunk is along each side.
+    pub chunk_size: usize,
+}
+
+impl ChunkGenPackage {
+    /// Seeds all of the noise from `seed`, so the same seed always generates the same chunks.
+    pub fn from_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        Self {
+            simplex: OpenSimplex::new().set_seed(rng.gen()),
+            simplex_weight: Value::new().set_seed(rng.gen()),
+            perlin: Perlin::new().set_seed(rng.gen()),
+            perlin_weight: Value::new().set_seed(rng.gen()),
+            // worley: Worley::new().set_seed(rng.gen()),
+            // worley_weight: Value::new().set_seed(rng.gen()),
+            value: Value::new().set_seed(rng.gen()),
+            value_weight: Value::new().set_seed(rng.gen()),
+            noise_scale: NOISE_SCALE,
+            noise_weight_scale: NOISE_WEIGHT_SCALE,
+            chunk_size: CHUNK_SIZE,
+        }
+    }
+
+    pub fn with_noise_scales(self, noise_scale: f64, noise_weight_scale: f64) -> Self {
+        Self {
+            noise_scale,
+            noise_weight_scale,
+            ..self
+        }
+    }
+
+    pub fn with_chunk_size(self, chunk_size: usize) -> Self {
+        Self { chunk_size, ..self }
+    }
 }
 
 pub fn gen_tile(gen_package: &ChunkGenPackage, x: usize, y: usize, z: usize) -> Tile {
     let simplex_raw = gen_package
         .simplex
         .get([
-            x as f64 * NOISE_SCALE,
-            y as f64 * NOISE_SCALE, // * 0.05,
-            z as f64 * NOISE_SCALE,
+            x as f64 * gen_package.noise_scale,
+            y as f64 * gen_package.noise_scale, // * 0.05,
+            z as f64 * gen_package.noise_scale,
         ])
         .abs();
     let simplex_raw_weight = gen_package
         .simplex_weight
         .get([
-            x as f64 * NOISE_WEIGHT_SCALE,
-            y as f64 * NOISE_WEIGHT_SCALE,
-            z as f64 * NOISE_WEIGHT_SCALE,
+            x as f64 * gen_package.noise_weight_scale,
+            y as f64 * gen_package.noise_weight_scale,
+            z as f64 * gen_package.noise_weight_scale,
         ])
         .abs();
 
     let perlin_raw = gen_package
         .perlin
         .get([
-            x as f64 * NOISE_SCALE,
-            y as f64 * NOISE_SCALE, // * 0.05,
-            z as f64 * NOISE_SCALE,
+            x as f64 * gen_package.noise_scale,
+            y as f64 * gen_package.noise_scale, // * 0.05,
+            z as f64 * gen_package.noise_scale,
         ])
         .abs();
     let perlin_raw_weight = gen_package
         .perlin_weight
         .get([
-            x as f64 * NOISE_WEIGHT_SCALE,
-            y as f64 * NOISE_WEIGHT_SCALE,
-            z as f64 * NOISE_WEIGHT_SCALE,
+            x as f64 * gen_package.noise_weight_scale,
+            y as f64 * gen_package.noise_weight_scale,
+            z as f64 * gen_package.noise_weight_scale,
         ])
         .abs();
 
@@ -69,17 +113,17 @@ pub fn gen_tile(gen_package: &ChunkGenPackage, x: usize, y: usize, z: usize) ->
     let value_raw = gen_package
         .value
         .get([
-            x as f64 * NOISE_SCALE,
-            y as f64 * NOISE_SCALE, // * 0.05,
-            z as f64 * NOISE_SCALE,
+            x as f64 * gen_package.noise_scale,
+            y as f64 * gen_package.noise_scale, // * 0.05,
+            z as f64 * gen_package.noise_scale,
         ])
         .abs();
     let value_raw_weight = gen_package
         .value_weight
         .get([
-            x as f64 * NOISE_WEIGHT_SCALE,
-            y as f64 * NOISE_WEIGHT_SCALE,
-            z as f64 * NOISE_WEIGHT_SCALE,
+            x as f64 * gen_package.noise_weight_scale,
+            y as f64 * gen_package.noise_weight_scale,
+            z as f64 * gen_package.noise_weight_scale,
         ])
         .abs();
 
@@ -102,8 +146,8 @@ pub fn gen_tile(gen_package: &ChunkGenPackage, x: usize, y: usize, z: usize) ->
     //             .abs(),
     //     );
 
-    let cave_threshold =
-        ((y as f64 - (CHUNK_SIZE / 2) as f64).abs() / (CHUNK_SIZE / 2) as f64).max(0.0) + 0.15;
+    let half_size = (gen_package.chunk_size / 2) as f64;
+    let cave_threshold = ((y as f64 - half_size).abs() / half_size).max(0.0) + 0.15;
 
     Tile {
         pos: Point3::new(x as f32, y as f32, z as f32),
@@ -132,7 +176,8 @@ pub fn gen_tile(gen_package: &ChunkGenPackage, x: usize, y: usize, z: usize) ->
 }
 
 pub fn generate_chunk(offset: Point3<i32>, gen_package: &ChunkGenPackage) -> Array3<Tile> {
-    let mut chunk = Array3::from_shape_fn((CHUNK_SIZE, CHUNK_SIZE, CHUNK_SIZE), |(x, y, z)| {
+    let size = gen_package.chunk_size;
+    let mut chunk = Array3::from_shape_fn((size, size, size), |(x, y, z)| {
         gen_tile(gen_package, x, y, z)
     });
 
@@ -160,3 +205,387 @@ pub fn generate_chunk(offset: Point3<i32>, gen_package: &ChunkGenPackage) -> Arr
 
     chunk
 }
+
+/// Generates a chunk from `seed` with the noise and size in `settings`, decorates it and lights
+/// it from whatever in it gives off light. The same seed and settings always make the same caves
+/// and lights.
+pub fn generate_world(seed: u64, settings: &Settings) -> World {
+    let package = ChunkGenPackage::from_seed(seed)
+        .with_noise_scales(settings.noise_scale, settings.noise_weight_scale)
+        .with_chunk_size(settings.chunk_size);
+    let mut tiles = generate_chunk(Point3::origin(), &package);
+    let mut rng = StdRng::seed_from_u64(seed);
+    decorate(&mut tiles, &mut rng);
+    place_doors(&mut tiles, &mut rng);
+    // Where the player starts
+    let spawn = Point3::new(
+        package.chunk_size / 2,
+        package.chunk_size / 2,
+        package.chunk_size / 2,
+    );
+    if place_objective(&mut tiles, spawn).is_none() {
+        warn!("No floor can be reached from the start, so there's no artifact to find");
+    }
+    let lights = emitted_lights(&tiles);
+
+    World::new(tiles, lights)
+}
+
+/// Scatters things that give off light through the caves: mushrooms and candles on the floor,
+/// crystals in the walls and pools of lava in the floors of the deepest caves. Each goes in about
+/// one in `LIGHT_RARITY` of the places it could.
+pub fn decorate<R>(tiles: &mut Array3<Tile>, rng: &mut R)
+where
+    R: Rng,
+{
+    let height = tiles.dim().1;
+    let mut decorations = Vec::new();
+
+    for ((x, y, z), tile) in tiles.indexed_iter() {
+        let below = y.checked_sub(1).map(|y| tiles[[x, y, z]].tile_type);
+        let above = tiles.get([x, y + 1, z]).map(|tile| tile.tile_type);
+        let solid = tile.tile_type.collides();
+
+        let decoration =
+            if tile.tile_type == TileType::Air && below.map_or(false, |below| below.collides()) {
+                if rng.gen_range(0, 4) == 0 {
+                    TileType::Candle
+                } else {
+                    TileType::Mushroom
+                }
+            } else if solid && above == Some(TileType::Air) && y < height / 4 {
+                TileType::Lava
+            } else if solid
+                && any_neighbour_is(tiles.view(), Point3::new(x, y, z), Adjacency::Face6, |t| {
+                    t.tile_type == TileType::Air
+                })
+            {
+                TileType::Crystal
+            } else {
+                continue;
+            };
+
+        if rng.gen_range(0, LIGHT_RARITY) == 0 {
+            decorations.push(([x, y, z], decoration));
+        }
+    }
+
+    for (index, tile_type) in decorations {
+        tiles[index].tile_type = tile_type;
+    }
+}
+
+/// Hangs closed doors in about one in `DOOR_RARITY` of the doorways through the caves. A doorway
+/// is an empty tile with rock under and over it and on both sides of it along x or z, opening
+/// out to empty tiles along the other.
+pub fn place_doors<R>(tiles: &mut Array3<Tile>, rng: &mut R)
+where
+    R: Rng,
+{
+    let (width, height, depth) = tiles.dim();
+    let mut doors = Vec::new();
+
+    // Lava and crystals count as neither, so a door is never hung over lava
+    let rock = |x: usize, y: usize, z: usize| {
+        let tile_type = tiles[[x, y, z]].tile_type;
+        tile_type.collides() && tile_type.emission().is_none()
+    };
+    let open = |x: usize, y: usize, z: usize| tiles[[x, y, z]].tile_type == TileType::Air;
+
+    for x in 1..width.saturating_sub(1) {
+        for y in 1..height.saturating_sub(1) {
+            for z in 1..depth.saturating_sub(1) {
+                let doorway = open(x, y, z)
+                    && rock(x, y - 1, z)
+                    && rock(x, y + 1, z)
+                    && ((rock(x - 1, y, z)
+                        && rock(x + 1, y, z)
+                        && open(x, y, z - 1)
+                        && open(x, y, z + 1))
+                        || (rock(x, y, z - 1)
+                            && rock(x, y, z + 1)
+                            && open(x - 1, y, z)
+                            && open(x + 1, y, z)));
+
+                if doorway && rng.gen_range(0, DOOR_RARITY) == 0 {
+                    doors.push([x, y, z]);
+                }
+            }
+        }
+    }
+
+    for index in doors {
+        tiles[index].tile_type = TileType::DoorClosed;
+    }
+}
+
+/// Where a run's goals go, found by `objective_spots`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObjectiveSpots {
+    /// The floor nearest to the start, for the player to bring the artifact back to.
+    pub extraction: Point3<usize>,
+    /// The floor furthest from the start through the caves.
+    pub artifact: Point3<usize>,
+}
+
+/// Finds where the artifact and the way out go, going by how far each empty tile with floor
+/// under it is from `start` through the caves rather than in a straight line. `None` if fewer
+/// than two such tiles can be reached from `start`.
+pub fn objective_spots(tiles: &Array3<Tile>, start: Point3<usize>) -> Option<ObjectiveSpots> {
+    let floors: Vec<_> = path_distances(tiles.view(), start, Adjacency::Face6, |tile| {
+        !tile.tile_type.collides()
+    })
+    .into_iter()
+    .filter(|&(p, _)| {
+        tiles[[p.x, p.y, p.z]].tile_type == TileType::Air
+            && p.y
+                .checked_sub(1)
+                .map_or(false, |y| tiles[[p.x, y, p.z]].tile_type.collides())
+    })
+    .collect();
+
+    // Nearest first, so the first and last are the nearest and furthest
+    match (floors.first(), floors.last()) {
+        (Some(&(extraction, _)), Some(&(artifact, _))) if extraction != artifact => {
+            Some(ObjectiveSpots {
+                extraction,
+                artifact,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Puts the artifact and the way out where `objective_spots` says.
+pub fn place_objective(tiles: &mut Array3<Tile>, start: Point3<usize>) -> Option<ObjectiveSpots> {
+    let spots = objective_spots(tiles, start)?;
+
+    let ObjectiveSpots {
+        extraction,
+        artifact,
+    } = spots;
+    tiles[[extraction.x, extraction.y, extraction.z]].tile_type = TileType::Extraction;
+    tiles[[artifact.x, artifact.y, artifact.z]].tile_type = TileType::Artifact;
+
+    Some(spots)
+}
+
+/// Places for pickups to be left: about one in `PICKUP_RARITY` of the empty tiles with floor
+/// under them and room to stand over them.
+pub fn pickup_spots<R>(tiles: &Array3<Tile>, rng: &mut R) -> Vec<Point3<usize>>
+where
+    R: Rng,
+{
+    tiles
+        .indexed_iter()
+        .filter(|&((x, y, z), tile)| {
+            let floor = y
+                .checked_sub(1)
+                .map_or(false, |y| tiles[[x, y, z]].tile_type.collides());
+            let headroom = tiles
+                .get([x, y + 1, z])
+                .map_or(false, |tile| !tile.tile_type.collides());
+
+            tile.tile_type == TileType::Air && floor && headroom
+        })
+        .filter(|_| rng.gen_range(0, PICKUP_RARITY) == 0)
+        .map(|((x, y, z), _)| Point3::new(x, y, z))
+        .collect()
+}
+
+/// A light for every tile that gives off light, in its emission's color.
+pub fn emitted_lights(tiles: &Array3<Tile>) -> Vec<(Point3<usize>, Color)> {
+    tiles
+        .indexed_iter()
+        .filter_map(|((x, y, z), tile)| {
+            let (color, _) = tile.tile_type.emission()?;
+            Some((Point3::new(x, y, z), color))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, path::Path};
+
+    use super::*;
+    use crate::world::debug::slices_to_string;
+
+    /// Small enough to keep the files readable, and big enough for a few caves.
+    const GOLDEN_CHUNK_SIZE: usize = 32;
+    const GOLDEN_Y_LEVELS: &[usize] = &[4, 12, 16, 20, 28];
+
+    /// Compares chunks from a few fixed seeds with the slices in `tests/golden`, so that changes
+    /// to generation show up as diffs there. A missing file fails like a different one does. Set
+    /// `KATAKOMB_BLESS` to write them afresh after a change that's meant to alter what gets
+    /// generated, and commit them with it.
+    #[test]
+    fn test_generation_matches_golden_files() {
+        let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+        let bless = env::var_os("KATAKOMB_BLESS").is_some();
+
+        for &seed in &[0, 1, 2] {
+            let package = ChunkGenPackage::from_seed(seed).with_chunk_size(GOLDEN_CHUNK_SIZE);
+            let tiles = generate_chunk(Point3::origin(), &package);
+            let dump = slices_to_string(tiles.view(), GOLDEN_Y_LEVELS);
+            let path = golden_dir.join(format!("seed_{}.txt", seed));
+
+            if bless {
+                fs::create_dir_all(&golden_dir).unwrap();
+                fs::write(&path, &dump).unwrap();
+                println!("Wrote {}", path.display());
+                continue;
+            }
+
+            let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+                panic!(
+                    "Failed to read {}: {}\nRerun with KATAKOMB_BLESS=1 to write it",
+                    path.display(),
+                    e
+                )
+            });
+            if let Some((line, (got, want))) = dump
+                .lines()
+                .zip(expected.lines())
+                .enumerate()
+                .find(|(_, (got, want))| got != want)
+            {
+                panic!(
+                    "Seed {} differs from {} on line {}:\n  got  {}\n  want {}\n\
+                     Rerun with KATAKOMB_BLESS=1 if that's intended",
+                    seed,
+                    path.display(),
+                    line + 1,
+                    got,
+                    want
+                );
+            }
+            assert_eq!(
+                dump.lines().count(),
+                expected.lines().count(),
+                "Seed {} has a different number of lines than {}",
+                seed,
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_doors_go_in_doorways() {
+        // A corridor along z at x = 4 through solid rock, with the end tiles left as rock
+        let (width, depth) = (9, 256);
+        let mut tiles = tiles_from_fn((width, 3, depth), |(x, y, z)| {
+            if x == 4 && y == 1 && z > 0 && z < depth - 1 {
+                TileType::Air
+            } else {
+                TileType::Rock0
+            }
+        });
+        // Lava under one end of it, where no door should go
+        for z in 1..8 {
+            tiles[[4, 0, z]].tile_type = TileType::Lava;
+        }
+
+        place_doors(&mut tiles, &mut StdRng::seed_from_u64(0));
+
+        let doors: Vec<_> = tiles
+            .indexed_iter()
+            .filter(|(_, tile)| tile.tile_type == TileType::DoorClosed)
+            .map(|(index, _)| index)
+            .collect();
+        assert!(!doors.is_empty());
+        for &(x, y, z) in &doors {
+            assert_eq!((x, y), (4, 1));
+            // Neither at the ends, which don't open out both ways, nor over the lava
+            assert!(z >= 8 && z < depth - 2, "door at {}", z);
+        }
+    }
+
+    #[test]
+    fn test_objective_spots() {
+        // Two rooms side by side, on a rock floor at y = 0, joined by a tunnel round the bottom.
+        // The far corner of the second room is nearer the start in a straight line than the
+        // tunnel is, but it's the furthest to walk to.
+        #[rustfmt::skip]
+        let map = [
+            "#######",
+            "#..#..#",
+            "#..#..#",
+            "#.###.#",
+            "#.....#",
+            "#######",
+        ];
+        let tiles = tiles_from_fn((7, 2, map.len()), |(x, y, z)| {
+            if y == 1 && map[z].as_bytes()[x] == b'.' {
+                TileType::Air
+            } else {
+                TileType::Rock0
+            }
+        });
+
+        let spots = objective_spots(&tiles, Point3::new(1, 1, 1)).unwrap();
+        assert_eq!(spots.extraction, Point3::new(1, 1, 1));
+        assert_eq!(spots.artifact, Point3::new(4, 1, 1));
+
+        // Nothing to stand on, or only the one place
+        assert_eq!(objective_spots(&tiles, Point3::new(0, 1, 0)), None);
+        let mut cell = tiles.clone();
+        cell.iter_mut()
+            .for_each(|tile| tile.tile_type = TileType::Rock0);
+        cell[[1, 1, 1]].tile_type = TileType::Air;
+        assert_eq!(objective_spots(&cell, Point3::new(1, 1, 1)), None);
+
+        let mut placed = tiles.clone();
+        assert_eq!(
+            place_objective(&mut placed, Point3::new(1, 1, 1)),
+            Some(spots)
+        );
+        assert_eq!(placed[[1, 1, 1]].tile_type, TileType::Extraction);
+        assert_eq!(placed[[4, 1, 1]].tile_type, TileType::Artifact);
+    }
+
+    #[test]
+    fn test_pickup_spots() {
+        let settings = Settings {
+            chunk_size: 32,
+            ..Settings::default()
+        };
+        let world = generate_world(0, &settings);
+        let tiles = world.tiles();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let spots = pickup_spots(tiles, &mut rng);
+        for spot in &spots {
+            assert_eq!(tiles[[spot.x, spot.y, spot.z]].tile_type, TileType::Air);
+            assert!(tiles[[spot.x, spot.y - 1, spot.z]].tile_type.collides());
+            assert!(!tiles[[spot.x, spot.y + 1, spot.z]].tile_type.collides());
+        }
+
+        assert_eq!(pickup_spots(tiles, &mut StdRng::seed_from_u64(0)), spots);
+    }
+
+    #[test]
+    fn test_world_is_lit_by_its_decorations() {
+        let world = generate_world(0, &Settings::default());
+        let tiles = world.tiles();
+        assert!(!world.lights().is_empty());
+
+        for &(pos, color) in world.lights() {
+            let tile_type = tiles[[pos.x, pos.y, pos.z]].tile_type;
+            assert_eq!(tile_type.emission().map(|(color, _)| color), Some(color));
+
+            // Standing on the floor, or with nothing standing on them
+            match tile_type {
+                TileType::Candle | TileType::Mushroom => {
+                    assert!(tiles[[pos.x, pos.y - 1, pos.z]].tile_type.collides())
+                }
+                TileType::Lava => {
+                    assert!(!tiles[[pos.x, pos.y + 1, pos.z]].tile_type.collides())
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(emitted_lights(tiles).len(), world.lights().len());
+    }
+}
@@ -1,2 +1,4 @@
+pub mod aabb;
+pub mod frustum;
 pub mod rect;
 pub mod util;
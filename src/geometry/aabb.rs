@@ -0,0 +1,256 @@
+use std::ops::Range;
+
+use na::{Point3, Vector3};
+
+use crate::geometry::util::floor_point;
+
+/// An axis-aligned box of tiles. `min` is included and `max` isn't, like a range, so the box
+/// is empty when `max` isn't past `min` on every axis.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct IAabb3 {
+    pub min: Point3<i32>,
+    pub max: Point3<i32>,
+}
+
+impl IAabb3 {
+    pub fn new(min: Point3<i32>, max: Point3<i32>) -> Self {
+        Self { min, max }
+    }
+
+    /// The box covering the indices of an array with dimensions `dim`.
+    pub fn from_dim(dim: (usize, usize, usize)) -> Self {
+        Self::new(
+            Point3::origin(),
+            Point3::new(dim.0 as i32, dim.1 as i32, dim.2 as i32),
+        )
+    }
+
+    /// The box of tiles at most `radius` away from `center` along each axis, both ends included.
+    pub fn from_center_radius(center: Point3<i32>, radius: i32) -> Self {
+        Self::new(
+            center - Vector3::repeat(radius),
+            center + Vector3::repeat(radius + 1),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.max[i] <= self.min[i])
+    }
+
+    /// How many tiles the box spans along each axis.
+    pub fn size(&self) -> Vector3<i32> {
+        (self.max - self.min).map(|d| d.max(0))
+    }
+
+    pub fn contains(&self, point: Point3<i32>) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] < self.max[i])
+    }
+
+    /// The tiles in both boxes, or `None` if there aren't any.
+    pub fn intersect(&self, other: &Self) -> Option<Self> {
+        let clamped = self.clamp_to(other);
+
+        if clamped.is_empty() {
+            None
+        } else {
+            Some(clamped)
+        }
+    }
+
+    /// The smallest box containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.min.inf(&other.min), self.max.sup(&other.max))
+    }
+
+    /// The box grown by `amount` on every side.
+    pub fn outset(&self, amount: i32) -> Self {
+        Self::new(
+            self.min - Vector3::repeat(amount),
+            self.max + Vector3::repeat(amount),
+        )
+    }
+
+    /// Cuts the box down to fit inside `bounds`. If they don't overlap, this leaves it empty but
+    /// still inside `bounds`, so its ranges can be used to slice an array.
+    pub fn clamp_to(&self, bounds: &Self) -> Self {
+        let min = self.min.sup(&bounds.min).inf(&bounds.max);
+        let max = self.max.inf(&bounds.max).sup(&min);
+        Self::new(min, max)
+    }
+
+    /// The index ranges along each axis, e.g. for slicing an array. Anything below zero is
+    /// left out.
+    pub fn ranges(&self) -> [Range<usize>; 3] {
+        let range = |i: usize| {
+            let start = self.min[i].max(0);
+            start as usize..self.max[i].max(start) as usize
+        };
+
+        [range(0), range(1), range(2)]
+    }
+
+    /// Every tile in the box, with z changing fastest, then y, then x.
+    pub fn iter_cells(&self) -> impl Iterator<Item = Point3<i32>> {
+        let (min, max) = (self.min, self.max.sup(&self.min));
+
+        (min.x..max.x).flat_map(move |x| {
+            (min.y..max.y).flat_map(move |y| (min.z..max.z).map(move |z| Point3::new(x, y, z)))
+        })
+    }
+}
+
+/// An axis-aligned box in world space, e.g. for an entity to collide with. Like `IAabb3`, `min`
+/// is included and `max` isn't.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct FAabb3 {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl FAabb3 {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// The box reaching `half_extents` from `center` along each axis.
+    pub fn from_center_half_extents(center: Point3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self::new(center - half_extents, center + half_extents)
+    }
+
+    pub fn contains(&self, point: Point3<f32>) -> bool {
+        (0..3).all(|i| point[i] >= self.min[i] && point[i] < self.max[i])
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        (0..3).all(|i| self.min[i] < other.max[i] && other.min[i] < self.max[i])
+    }
+
+    pub fn translated(&self, offset: Vector3<f32>) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        na::center(&self.min, &self.max)
+    }
+
+    /// How far `point` is from the nearest point in the box, which is 0 inside it.
+    pub fn distance_to(&self, point: Point3<f32>) -> f32 {
+        let nearest = Point3::from(point.coords.sup(&self.min.coords).inf(&self.max.coords));
+        na::distance(&point, &nearest)
+    }
+
+    /// The tiles the box overlaps.
+    pub fn cells(&self) -> IAabb3 {
+        IAabb3::new(floor_point(self.min), self.max.map(|c| c.ceil() as i32))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_contains_edges() {
+        let aabb = IAabb3::new(Point3::new(1, 2, 3), Point3::new(4, 5, 6));
+
+        // The min corner is in, the max corner is out
+        assert!(aabb.contains(Point3::new(1, 2, 3)));
+        assert!(aabb.contains(Point3::new(3, 4, 5)));
+        assert!(!aabb.contains(Point3::new(4, 4, 5)));
+        assert!(!aabb.contains(Point3::new(3, 5, 5)));
+        assert!(!aabb.contains(Point3::new(3, 4, 6)));
+        assert!(!aabb.contains(Point3::new(0, 2, 3)));
+        assert_eq!(aabb.iter_cells().count(), 27);
+        assert_eq!(aabb.size(), Vector3::new(3, 3, 3));
+
+        let chunk = IAabb3::from_dim((16, 16, 16));
+        assert!(chunk.contains(Point3::new(0, 0, 0)));
+        assert!(chunk.contains(Point3::new(15, 15, 15)));
+        assert!(!chunk.contains(Point3::new(16, 0, 0)));
+        assert!(!chunk.contains(Point3::new(-1, 0, 0)));
+    }
+
+    #[test]
+    fn test_from_center_radius() {
+        let aabb = IAabb3::from_center_radius(Point3::new(0, 5, -5), 2);
+        assert_eq!(aabb.min, Point3::new(-2, 3, -7));
+        assert_eq!(aabb.max, Point3::new(3, 8, -2));
+
+        // Both ends are included
+        assert!(aabb.contains(Point3::new(2, 7, -3)));
+        assert!(aabb.contains(Point3::new(-2, 3, -7)));
+        assert_eq!(aabb.iter_cells().count(), 125);
+
+        let single = IAabb3::from_center_radius(Point3::new(1, 1, 1), 0);
+        assert_eq!(
+            single.iter_cells().collect::<Vec<_>>(),
+            vec![Point3::new(1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_intersect_and_clamp() {
+        let chunk = IAabb3::from_dim((8, 8, 8));
+        let light = IAabb3::from_center_radius(Point3::new(1, 6, 4), 3);
+
+        let clamped = light.clamp_to(&chunk);
+        assert_eq!(
+            clamped,
+            IAabb3::new(Point3::new(0, 3, 1), Point3::new(5, 8, 8))
+        );
+        assert_eq!(light.intersect(&chunk), Some(clamped));
+        assert_eq!(clamped.ranges(), [0..5, 3..8, 1..8]);
+
+        // Boxes that only touch don't intersect, and clamping leaves them empty
+        let beside = IAabb3::new(Point3::new(8, 0, 0), Point3::new(10, 8, 8));
+        assert_eq!(beside.intersect(&chunk), None);
+        assert!(beside.clamp_to(&chunk).is_empty());
+        assert_eq!(beside.clamp_to(&chunk).iter_cells().count(), 0);
+        assert_eq!(beside.clamp_to(&chunk).size(), Vector3::new(0, 8, 8));
+
+        let inverted = IAabb3::new(Point3::new(2, 2, 2), Point3::new(1, 3, 3));
+        assert!(inverted.is_empty());
+        assert_eq!(inverted.iter_cells().count(), 0);
+        assert_eq!(inverted.ranges(), [2..2, 2..3, 2..3]);
+    }
+
+    #[test]
+    fn test_union_and_outset() {
+        let a = IAabb3::new(Point3::new(0, 0, 0), Point3::new(2, 2, 2));
+        let b = IAabb3::new(Point3::new(3, -1, 1), Point3::new(4, 1, 2));
+
+        assert_eq!(
+            a.union(&b),
+            IAabb3::new(Point3::new(0, -1, 0), Point3::new(4, 2, 2))
+        );
+        assert_eq!(a.union(&a), a);
+        assert_eq!(
+            a.outset(1),
+            IAabb3::new(Point3::new(-1, -1, -1), Point3::new(3, 3, 3))
+        );
+        assert_eq!(a.outset(1).intersect(&b), None);
+        assert!(a.outset(2).intersect(&b).is_some());
+    }
+
+    #[test]
+    fn test_faabb() {
+        let aabb =
+            FAabb3::from_center_half_extents(Point3::new(1.5, 1.0, 0.0), Vector3::repeat(0.5));
+        assert!(aabb.contains(Point3::new(1.0, 0.5, -0.5)));
+        assert!(!aabb.contains(Point3::new(2.0, 1.0, 0.0)));
+
+        assert!(aabb.intersects(&aabb.translated(Vector3::new(0.9, 0.0, 0.0))));
+        assert!(!aabb.intersects(&aabb.translated(Vector3::new(1.0, 0.0, 0.0))));
+
+        assert_eq!(
+            aabb.cells(),
+            IAabb3::new(Point3::new(1, 0, -1), Point3::new(2, 2, 1))
+        );
+
+        assert_eq!(aabb.center(), Point3::new(1.5, 1.0, 0.0));
+        assert_eq!(aabb.distance_to(Point3::new(1.2, 0.8, 0.1)), 0.0);
+        assert!((aabb.distance_to(Point3::new(3.0, 1.0, 0.0)) - 1.0).abs() < 1e-6);
+        assert!((aabb.distance_to(Point3::new(2.0, 2.5, 0.0)) - 1.0).abs() < 1e-6);
+        assert!((aabb.distance_to(Point3::new(3.0, 2.5, 0.5)) - 2f32.sqrt()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,163 @@
+use na::{Matrix4, Point3, RowVector4, Vector3};
+
+use crate::geometry::aabb::IAabb3;
+
+/// A plane facing along `normal`, which is unit length. Points on the side it faces have a
+/// positive distance.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    fn from_row(row: RowVector4<f32>) -> Self {
+        let normal = Vector3::new(row[0], row[1], row[2]);
+        let length = normal.norm();
+
+        Self {
+            normal: normal / length,
+            d: row[3] / length,
+        }
+    }
+
+    /// The signed distance from the plane to `point`.
+    pub fn distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(&point.coords) + self.d
+    }
+}
+
+/// The volume a camera can see, bounded by six planes facing inwards.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far, in that order.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a model-view-projection matrix, so the frustum is in model
+    /// space. Expects clip space z to run from -1 to 1 like `Perspective3`.
+    pub fn from_mvp(mvp: Matrix4<f32>) -> Self {
+        let row = |i| mvp.row(i).into_owned();
+        let w = row(3);
+
+        Self {
+            planes: [
+                Plane::from_row(w + row(0)),
+                Plane::from_row(w - row(0)),
+                Plane::from_row(w + row(1)),
+                Plane::from_row(w - row(1)),
+                Plane::from_row(w + row(2)),
+                Plane::from_row(w - row(2)),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.distance(center) >= -radius)
+    }
+
+    /// Whether any of the tiles in `aabb` might be visible. This only rejects boxes entirely
+    /// behind one of the planes, so a box just outside a corner of the frustum can still pass.
+    pub fn intersects_aabb(&self, aabb: &IAabb3) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+
+        let min = aabb.min.map(|c| c as f32);
+        let max = aabb.max.map(|c| c as f32);
+
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Point3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+
+            plane.distance(corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use na::{Isometry3, Perspective3};
+
+    use super::*;
+
+    /// A camera at the origin looking along +z with a 90 degree field of view either way, so a
+    /// point is inside when it's further along z than it is along x or y.
+    fn frustum() -> Frustum {
+        let view = Isometry3::look_at_rh(
+            &Point3::origin(),
+            &Point3::new(0.0, 0.0, 1.0),
+            &Vector3::y(),
+        );
+        let projection = Perspective3::new(1.0, std::f32::consts::FRAC_PI_2, 1.0, 100.0);
+
+        Frustum::from_mvp(projection.as_matrix() * view.to_homogeneous())
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let frustum = frustum();
+
+        assert!(frustum.contains_point(Point3::new(0.0, 0.0, 10.0)));
+        assert!(frustum.contains_point(Point3::new(9.0, -9.0, 10.0)));
+        assert!(!frustum.contains_point(Point3::new(11.0, 0.0, 10.0)));
+        assert!(!frustum.contains_point(Point3::new(0.0, -11.0, 10.0)));
+
+        // Behind the eye, closer than the near plane and past the far plane
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, 0.5)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, 101.0)));
+    }
+
+    #[test]
+    fn test_plane_distance() {
+        let frustum = frustum();
+        let near = frustum.planes[4];
+        let far = frustum.planes[5];
+
+        assert!((near.distance(Point3::new(0.0, 0.0, 3.0)) - 2.0).abs() < 1e-4);
+        assert!((far.distance(Point3::new(0.0, 0.0, 90.0)) - 10.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_intersects_sphere() {
+        let frustum = frustum();
+
+        assert!(frustum.intersects_sphere(Point3::new(11.0, 0.0, 10.0), 2.0));
+        assert!(!frustum.intersects_sphere(Point3::new(11.0, 0.0, 10.0), 0.1));
+        assert!(frustum.intersects_sphere(Point3::new(0.0, 0.0, -0.5), 2.0));
+        assert!(!frustum.intersects_sphere(Point3::new(0.0, 0.0, -5.0), 2.0));
+    }
+
+    #[test]
+    fn test_intersects_aabb() {
+        let frustum = frustum();
+
+        let ahead = IAabb3::from_center_radius(Point3::new(0, 0, 10), 1);
+        assert!(frustum.intersects_aabb(&ahead));
+
+        // Around the eye, poking through the near plane
+        let around = IAabb3::new(Point3::new(-1, -1, -1), Point3::new(1, 1, 5));
+        assert!(frustum.intersects_aabb(&around));
+
+        let behind = IAabb3::from_center_radius(Point3::new(0, 0, -5), 1);
+        assert!(!frustum.intersects_aabb(&behind));
+
+        let beside = IAabb3::new(Point3::new(20, 0, 5), Point3::new(21, 1, 6));
+        assert!(!frustum.intersects_aabb(&beside));
+
+        let empty = IAabb3::new(Point3::new(0, 0, 10), Point3::new(0, 1, 11));
+        assert!(!frustum.intersects_aabb(&empty));
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::Range;
+use std::{convert::TryFrom, fmt, ops::Range};
 
 pub use ggez::{graphics::Rect as FRect, mint};
 
@@ -41,9 +41,9 @@ impl IRect {
         self.x
     }
 
-    /// Returns the right edge of the `Rect`
-    pub fn right(&self) -> u32 {
-        self.x + self.w
+    /// Returns the right edge of the `Rect`, saturating at `u32::MAX`
+    pub const fn right(&self) -> u32 {
+        self.x.saturating_add(self.w)
     }
 
     /// Returns the top edge of the `Rect`
@@ -51,9 +51,14 @@ impl IRect {
         self.y
     }
 
-    /// Returns the bottom edge of the `Rect`
-    pub fn bottom(&self) -> u32 {
-        self.y + self.h
+    /// Returns the bottom edge of the `Rect`, saturating at `u32::MAX`
+    pub const fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.h)
+    }
+
+    /// Checks whether the `Rect` has no area
+    pub const fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
     }
 
     /// Checks whether the `Rect` contains a `Point`
@@ -76,6 +81,68 @@ impl IRect {
             && self.bottom() > other.top()
     }
 
+    /// Checks whether all of another `Rect` lies inside the `Rect`
+    pub fn contains_rect(&self, other: &Self) -> bool {
+        other.left() >= self.left()
+            && other.right() <= self.right()
+            && other.top() >= self.top()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// Returns the part of the `Rect` that lies inside another, or `None` if they don't overlap
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let left = self.left().max(other.left());
+        let top = self.top().max(other.top());
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        if left < right && top < bottom {
+            Some(Self::new(left, top, right - left, bottom - top))
+        } else {
+            None
+        }
+    }
+
+    /// Shrinks the `Rect` by `amount` on every side, down to nothing in the middle
+    pub fn inset(self, amount: u32) -> Self {
+        let dx = amount.min(self.w / 2);
+        let dy = amount.min(self.h / 2);
+
+        Self::new(
+            self.x + dx,
+            self.y + dy,
+            self.w.saturating_sub(amount.saturating_mul(2)),
+            self.h.saturating_sub(amount.saturating_mul(2)),
+        )
+    }
+
+    /// Grows the `Rect` by `amount` on every side, stopping at zero and `u32::MAX`
+    pub fn outset(self, amount: u32) -> Self {
+        let x = self.x.saturating_sub(amount);
+        let y = self.y.saturating_sub(amount);
+
+        Self::new(
+            x,
+            y,
+            self.right().saturating_add(amount) - x,
+            self.bottom().saturating_add(amount) - y,
+        )
+    }
+
+    /// Returns the `Rect` moved by an offset of (x, y), or `None` if its far edges would overflow
+    pub fn checked_translate<V>(self, offset: V) -> Option<Self>
+    where
+        V: Into<mint::Vector2<u32>>,
+    {
+        let offset = offset.into();
+        let x = self.x.checked_add(offset.x)?;
+        let y = self.y.checked_add(offset.y)?;
+        x.checked_add(self.w)?;
+        y.checked_add(self.h)?;
+
+        Some(Self::new(x, y, self.w, self.h))
+    }
+
     /// Translates the `Rect` by an offset of (x, y)
     pub fn translate<V>(&mut self, offset: V)
     where
@@ -153,44 +220,602 @@ impl IRect {
         self.dir_start(direction)..self.dir_end(direction)
     }
 
+    /// Every point in the rect, a row at a time.
     pub fn points(self) -> Points {
-        Points { rect: self, i: 0 }
+        Points::new(self, false)
+    }
+
+    /// Every point in the rect, a column at a time.
+    pub fn points_colmajor(self) -> Points {
+        Points::new(self, true)
+    }
+
+    /// Each row's y coordinate along with the points in it.
+    pub fn rows(self) -> Lines {
+        self.lines(LayoutDirection::Vertical)
+    }
+
+    /// Each column's x coordinate along with the points in it.
+    pub fn cols(self) -> Lines {
+        self.lines(LayoutDirection::Horizontal)
+    }
+
+    /// The one cell thick slices of the rect along `direction`, i.e. rows when vertical and
+    /// columns when horizontal.
+    pub fn lines(self, direction: LayoutDirection) -> Lines {
+        Lines {
+            rect: self,
+            direction,
+            range: self.dir(direction),
+        }
     }
 }
 
 pub struct Points {
     rect: IRect,
-    i: usize,
+    col_major: bool,
+    /// How many points there are in each row, or each column if `col_major`.
+    stride: u32,
+    major: u32,
+    minor: u32,
+    remaining: usize,
+}
+
+impl Points {
+    fn new(rect: IRect, col_major: bool) -> Self {
+        Self {
+            rect,
+            col_major,
+            stride: if col_major { rect.h } else { rect.w },
+            major: 0,
+            minor: 0,
+            // Done in usize so a huge rect can't overflow u32
+            remaining: (rect.w as usize).saturating_mul(rect.h as usize),
+        }
+    }
 }
 
 impl Iterator for Points {
     type Item = mint::Point2<u32>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if (self.rect.w * self.rect.h) as usize == self.i {
-            None
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let (dx, dy) = if self.col_major {
+            (self.major, self.minor)
         } else {
-            let pos = mint::Point2::from([
-                self.rect.x + self.i as u32 % self.rect.w,
-                self.rect.y + self.i as u32 / self.rect.w,
-            ]);
-            self.i += 1;
-            Some(pos)
+            (self.minor, self.major)
+        };
+
+        self.remaining -= 1;
+        self.minor += 1;
+        if self.minor == self.stride {
+            self.minor = 0;
+            self.major += 1;
         }
+
+        Some(mint::Point2::from([self.rect.x + dx, self.rect.y + dy]))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let n = (self.rect.w * self.rect.h) as usize - self.i;
-        (n, Some(n))
+        (self.remaining, Some(self.remaining))
     }
 }
 
 impl ExactSizeIterator for Points {}
 
+pub struct Lines {
+    rect: IRect,
+    direction: LayoutDirection,
+    range: Range<u32>,
+}
+
+impl Iterator for Lines {
+    type Item = (u32, Points);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.range.next()?;
+        let start = pos - self.rect.dir_start(self.direction);
+        let line = self.rect.slice_dir(self.direction, start..(start + 1));
+
+        Some((pos, line.points()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Lines {}
+
+/// A rect whose origin can be negative, e.g. for content scrolled partly off the top or left of
+/// its viewport. It has the same methods as `IRect`, with the edges saturating at `i32::MIN` and
+/// `i32::MAX`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct SRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// Adds `length` to `start`, saturating at `i32::MAX`.
+fn saturating_end(start: i32, length: u32) -> i32 {
+    (i64::from(start) + i64::from(length)).min(i64::from(i32::MAX)) as i32
+}
+
+/// The length from `start` to `end`, which is zero if `end` comes first.
+fn span(start: i32, end: i32) -> u32 {
+    (i64::from(end) - i64::from(start)).max(0) as u32
+}
+
+impl SRect {
+    /// Create a new `Rect`.
+    pub const fn new(x: i32, y: i32, w: u32, h: u32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    /// Create a new `Rect` with all values zero.
+    pub const fn zero() -> Self {
+        Self::new(0, 0, 0, 0)
+    }
+
+    /// Gets the `Rect`'s x and y coordinates as a `Point2`.
+    pub const fn point(&self) -> mint::Point2<i32> {
+        mint::Point2 {
+            x: self.x,
+            y: self.y,
+        }
+    }
+
+    /// Returns the left edge of the `Rect`
+    pub const fn left(&self) -> i32 {
+        self.x
+    }
+
+    /// Returns the right edge of the `Rect`, saturating at `i32::MAX`
+    pub fn right(&self) -> i32 {
+        saturating_end(self.x, self.w)
+    }
+
+    /// Returns the top edge of the `Rect`
+    pub const fn top(&self) -> i32 {
+        self.y
+    }
+
+    /// Returns the bottom edge of the `Rect`, saturating at `i32::MAX`
+    pub fn bottom(&self) -> i32 {
+        saturating_end(self.y, self.h)
+    }
+
+    /// Checks whether the `Rect` has no area
+    pub const fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+
+    /// Checks whether the `Rect` contains a `Point`
+    pub fn contains<P>(&self, point: P) -> bool
+    where
+        P: Into<mint::Point2<i32>>,
+    {
+        let point = point.into();
+        point.x >= self.left()
+            && point.x < self.right()
+            && point.y < self.bottom()
+            && point.y >= self.top()
+    }
+
+    /// Checks whether all of another `Rect` lies inside the `Rect`
+    pub fn contains_rect(&self, other: &Self) -> bool {
+        other.left() >= self.left()
+            && other.right() <= self.right()
+            && other.top() >= self.top()
+            && other.bottom() <= self.bottom()
+    }
+
+    /// Checks whether the `Rect` overlaps another `Rect`
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.left() < other.right()
+            && self.right() > other.left()
+            && self.top() < other.bottom()
+            && self.bottom() > other.top()
+    }
+
+    /// Returns the part of the `Rect` that lies inside another, or `None` if they don't overlap
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let left = self.left().max(other.left());
+        let top = self.top().max(other.top());
+        let right = self.right().min(other.right());
+        let bottom = self.bottom().min(other.bottom());
+
+        if left < right && top < bottom {
+            Some(Self::new(left, top, span(left, right), span(top, bottom)))
+        } else {
+            None
+        }
+    }
+
+    /// Shrinks the `Rect` by `amount` on every side, down to nothing in the middle
+    pub fn inset(self, amount: u32) -> Self {
+        let dx = amount.min(self.w / 2);
+        let dy = amount.min(self.h / 2);
+
+        Self::new(
+            saturating_end(self.x, dx),
+            saturating_end(self.y, dy),
+            self.w.saturating_sub(amount.saturating_mul(2)),
+            self.h.saturating_sub(amount.saturating_mul(2)),
+        )
+    }
+
+    /// Grows the `Rect` by `amount` on every side, stopping at `i32::MIN` and `i32::MAX`
+    pub fn outset(self, amount: u32) -> Self {
+        let start =
+            |start: i32| (i64::from(start) - i64::from(amount)).max(i64::from(i32::MIN)) as i32;
+        let x = start(self.x);
+        let y = start(self.y);
+
+        Self::new(
+            x,
+            y,
+            span(x, saturating_end(self.right(), amount)),
+            span(y, saturating_end(self.bottom(), amount)),
+        )
+    }
+
+    /// Returns the `Rect` moved by an offset of (x, y), or `None` if its edges would overflow
+    pub fn checked_translate<V>(self, offset: V) -> Option<Self>
+    where
+        V: Into<mint::Vector2<i32>>,
+    {
+        let offset = offset.into();
+        let x = self.x.checked_add(offset.x)?;
+        let y = self.y.checked_add(offset.y)?;
+        let fits =
+            |start: i32, length: u32| i64::from(start) + i64::from(length) <= i64::from(i32::MAX);
+
+        if fits(x, self.w) && fits(y, self.h) {
+            Some(Self::new(x, y, self.w, self.h))
+        } else {
+            None
+        }
+    }
+
+    /// Translates the `Rect` by an offset of (x, y)
+    pub fn translate<V>(&mut self, offset: V)
+    where
+        V: Into<mint::Vector2<i32>>,
+    {
+        let offset = offset.into();
+        self.x += offset.x;
+        self.y += offset.y;
+    }
+
+    /// Moves the `Rect`'s origin to (x, y)
+    pub fn move_to<P>(&mut self, destination: P)
+    where
+        P: Into<mint::Point2<i32>>,
+    {
+        let destination = destination.into();
+        self.x = destination.x;
+        self.y = destination.y;
+    }
+
+    /// Returns a new `Rect` that includes all points of these two `Rect`s.
+    pub fn combine_with(self, other: Self) -> Self {
+        let x = i32::min(self.x, other.x);
+        let y = i32::min(self.y, other.y);
+        let w = span(x, i32::max(self.right(), other.right()));
+        let h = span(y, i32::max(self.bottom(), other.bottom()));
+        Self { x, y, w, h }
+    }
+
+    pub fn size(self) -> Size {
+        Size::new(self.w, self.h)
+    }
+
+    pub fn slice_dir(self, direction: LayoutDirection, range: Range<u32>) -> Self {
+        let mut r = self;
+
+        match direction {
+            LayoutDirection::Horizontal => {
+                r.x = saturating_end(r.x, range.start);
+                r.w = range.end - range.start;
+            }
+            LayoutDirection::Vertical => {
+                r.y = saturating_end(r.y, range.start);
+                r.h = range.end - range.start;
+            }
+        }
+
+        r
+    }
+
+    pub fn dir_start(self, direction: LayoutDirection) -> i32 {
+        match direction {
+            LayoutDirection::Horizontal => self.left(),
+            LayoutDirection::Vertical => self.top(),
+        }
+    }
+
+    pub fn dir_end(self, direction: LayoutDirection) -> i32 {
+        match direction {
+            LayoutDirection::Horizontal => self.right(),
+            LayoutDirection::Vertical => self.bottom(),
+        }
+    }
+
+    pub fn dir(self, direction: LayoutDirection) -> Range<i32> {
+        self.dir_start(direction)..self.dir_end(direction)
+    }
+
+    /// Every point in the rect, a row at a time.
+    pub fn points(self) -> SPoints {
+        SPoints::new(self, IRect::new(0, 0, self.w, self.h).points())
+    }
+
+    /// Every point in the rect, a column at a time.
+    pub fn points_colmajor(self) -> SPoints {
+        SPoints::new(self, IRect::new(0, 0, self.w, self.h).points_colmajor())
+    }
+
+    /// Each row's y coordinate along with the points in it.
+    pub fn rows(self) -> SLines {
+        self.lines(LayoutDirection::Vertical)
+    }
+
+    /// Each column's x coordinate along with the points in it.
+    pub fn cols(self) -> SLines {
+        self.lines(LayoutDirection::Horizontal)
+    }
+
+    /// The one cell thick slices of the rect along `direction`, i.e. rows when vertical and
+    /// columns when horizontal.
+    pub fn lines(self, direction: LayoutDirection) -> SLines {
+        SLines {
+            rect: self,
+            direction,
+            range: 0..self.size()[direction],
+        }
+    }
+
+    /// Returns the part of the `Rect` at non-negative coordinates, which is empty if there is
+    /// none.
+    pub fn clip_to_unsigned(self) -> IRect {
+        let x = self.x.max(0);
+        let y = self.y.max(0);
+
+        IRect::new(
+            x as u32,
+            y as u32,
+            span(x, self.right()),
+            span(y, self.bottom()),
+        )
+    }
+}
+
+/// The points of a `SRect`, which are those of a rect of the same size at zero moved to its
+/// origin.
+pub struct SPoints {
+    origin: mint::Point2<i32>,
+    points: Points,
+}
+
+impl SPoints {
+    fn new(rect: SRect, points: Points) -> Self {
+        Self {
+            origin: rect.point(),
+            points,
+        }
+    }
+}
+
+impl Iterator for SPoints {
+    type Item = mint::Point2<i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let p = self.points.next()?;
+
+        Some(mint::Point2::from([
+            saturating_end(self.origin.x, p.x),
+            saturating_end(self.origin.y, p.y),
+        ]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.points.size_hint()
+    }
+}
+
+impl ExactSizeIterator for SPoints {}
+
+pub struct SLines {
+    rect: SRect,
+    direction: LayoutDirection,
+    /// What's left of the lines, counted from the start of the rect.
+    range: Range<u32>,
+}
+
+impl Iterator for SLines {
+    type Item = (i32, SPoints);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.range.next()?;
+        let line = self.rect.slice_dir(self.direction, start..(start + 1));
+
+        Some((line.dir_start(self.direction), line.points()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+}
+
+impl ExactSizeIterator for SLines {}
+
+/// Converts the coordinates as they are, saturating at `i32::MAX`.
+impl From<IRect> for SRect {
+    fn from(rect: IRect) -> Self {
+        let coordinate = |c: u32| c.min(i32::MAX as u32) as i32;
+        Self::new(coordinate(rect.x), coordinate(rect.y), rect.w, rect.h)
+    }
+}
+
+/// Fails on a `SRect` with a negative origin. Use `SRect::clip_to_unsigned` to cut that part
+/// off instead.
+impl TryFrom<SRect> for IRect {
+    type Error = NegativeOrigin;
+
+    fn try_from(rect: SRect) -> Result<Self, Self::Error> {
+        if rect.x < 0 || rect.y < 0 {
+            Err(NegativeOrigin(rect))
+        } else {
+            Ok(Self::new(rect.x as u32, rect.y as u32, rect.w, rect.h))
+        }
+    }
+}
+
+/// A `SRect` that starts above or to the left of zero, so it can't be an `IRect`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct NegativeOrigin(pub SRect);
+
+impl fmt::Display for NegativeOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} starts at a negative coordinate", self.0)
+    }
+}
+
+impl std::error::Error for NegativeOrigin {}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn test_intersection() {
+        let rect = IRect::new(2, 3, 4, 5);
+        assert_eq!(
+            rect.intersection(&IRect::new(4, 0, 10, 5)),
+            Some(IRect::new(4, 3, 2, 2))
+        );
+        assert_eq!(rect.intersection(&rect), Some(rect));
+        assert_eq!(rect.intersection(&IRect::new(0, 0, 100, 100)), Some(rect));
+
+        // Touching edges and empty rects don't overlap
+        assert_eq!(rect.intersection(&IRect::new(6, 3, 1, 1)), None);
+        assert_eq!(rect.intersection(&IRect::new(3, 4, 0, 0)), None);
+        assert_eq!(IRect::zero().intersection(&IRect::zero()), None);
+
+        let max = IRect::new(u32::MAX - 1, u32::MAX - 1, 10, 10);
+        assert_eq!(max.right(), u32::MAX);
+        assert_eq!(
+            max.intersection(&IRect::new(0, 0, u32::MAX, u32::MAX)),
+            Some(IRect::new(u32::MAX - 1, u32::MAX - 1, 1, 1))
+        );
+    }
+
+    #[test]
+    fn test_contains_rect() {
+        let rect = IRect::new(2, 3, 4, 5);
+        assert!(rect.contains_rect(&rect));
+        assert!(rect.contains_rect(&IRect::new(3, 4, 2, 2)));
+        assert!(rect.contains_rect(&IRect::new(6, 8, 0, 0)));
+        assert!(!rect.contains_rect(&IRect::new(3, 4, 4, 1)));
+        assert!(!rect.contains_rect(&IRect::new(1, 3, 1, 1)));
+        assert!(
+            IRect::new(0, 0, u32::MAX, u32::MAX).contains_rect(&IRect::new(
+                u32::MAX,
+                u32::MAX,
+                0,
+                0
+            ))
+        );
+    }
+
+    #[test]
+    fn test_inset_outset() {
+        let rect = IRect::new(2, 3, 4, 5);
+        assert_eq!(rect.inset(1), IRect::new(3, 4, 2, 3));
+        assert_eq!(rect.inset(2), IRect::new(4, 5, 0, 1));
+        assert_eq!(rect.inset(u32::MAX), IRect::new(4, 5, 0, 0));
+        assert_eq!(IRect::zero().inset(1), IRect::zero());
+
+        assert_eq!(rect.outset(1), IRect::new(1, 2, 6, 7));
+        assert_eq!(rect.outset(5), IRect::new(0, 0, 11, 13));
+        assert_eq!(
+            IRect::new(1, 1, u32::MAX - 1, 1).outset(2),
+            IRect::new(0, 0, u32::MAX, 5)
+        );
+    }
+
+    #[test]
+    fn test_checked_translate() {
+        let rect = IRect::new(2, 3, 4, 5);
+        assert_eq!(rect.checked_translate([1, 2]), Some(IRect::new(3, 5, 4, 5)));
+        assert_eq!(rect.checked_translate([u32::MAX - 6, 0]), None);
+        assert_eq!(
+            rect.checked_translate([u32::MAX - 6 - 1, 0]),
+            Some(IRect::new(u32::MAX - 5, 3, 4, 5))
+        );
+        assert_eq!(
+            IRect::zero().checked_translate([u32::MAX, u32::MAX]),
+            Some(IRect::new(u32::MAX, u32::MAX, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_srect() {
+        let rect = SRect::new(-2, -3, 4, 5);
+        assert_eq!((rect.right(), rect.bottom()), (2, 2));
+        assert!(rect.contains([-2, -3]));
+        assert!(!rect.contains([2, 0]));
+        assert_eq!(
+            rect.intersection(&SRect::new(0, 0, 10, 10)),
+            Some(SRect::new(0, 0, 2, 2))
+        );
+        assert_eq!(rect.intersection(&SRect::new(2, 0, 1, 1)), None);
+        assert_eq!(rect.inset(1), SRect::new(-1, -2, 2, 3));
+        assert_eq!(rect.outset(1), SRect::new(-3, -4, 6, 7));
+        assert_eq!(
+            rect.combine_with(SRect::new(1, 1, 3, 3)),
+            SRect::new(-2, -3, 6, 7)
+        );
+
+        let max = SRect::new(i32::MAX - 1, i32::MIN, u32::MAX, 1);
+        assert_eq!(max.right(), i32::MAX);
+        assert_eq!(max.checked_translate([0, 0]), None);
+        assert_eq!(max.outset(1).left(), i32::MAX - 2);
+        assert_eq!(max.outset(1).top(), i32::MIN);
+        assert_eq!(
+            SRect::new(0, 0, 1, 1).checked_translate([-5, i32::MAX - 1]),
+            Some(SRect::new(-5, i32::MAX - 1, 1, 1))
+        );
+    }
+
+    #[test]
+    fn test_srect_conversions() {
+        assert_eq!(SRect::from(IRect::new(1, 2, 3, 4)), SRect::new(1, 2, 3, 4));
+        assert_eq!(
+            SRect::from(IRect::new(u32::MAX, 0, 1, 1)),
+            SRect::new(i32::MAX, 0, 1, 1)
+        );
+
+        assert_eq!(
+            IRect::try_from(SRect::new(1, 2, 3, 4)),
+            Ok(IRect::new(1, 2, 3, 4))
+        );
+        let above = SRect::new(1, -2, 3, 4);
+        assert_eq!(IRect::try_from(above), Err(NegativeOrigin(above)));
+
+        assert_eq!(above.clip_to_unsigned(), IRect::new(1, 0, 3, 2));
+        assert_eq!(
+            SRect::new(-5, -5, 2, 2).clip_to_unsigned(),
+            IRect::new(0, 0, 0, 0)
+        );
+    }
+
     #[test]
     fn test_points() {
         assert_points(
@@ -202,10 +827,106 @@ mod test {
         assert_points(IRect::new(10, 10, 1, 1), &[[10, 10]]);
     }
 
+    #[test]
+    fn test_points_colmajor() {
+        assert_eq!(
+            IRect::new(0, 0, 2, 3).points_colmajor().collect::<Vec<_>>(),
+            points(&[[0, 0], [0, 1], [0, 2], [1, 0], [1, 1], [1, 2]])
+        );
+        assert_eq!(IRect::new(5, 5, 0, 3).points_colmajor().count(), 0);
+        assert_eq!(
+            IRect::new(20, 50, 3, 1)
+                .points_colmajor()
+                .collect::<Vec<_>>(),
+            points(&[[20, 50], [21, 50], [22, 50]])
+        );
+    }
+
+    #[test]
+    fn test_points_size_hint() {
+        let mut it = IRect::new(3, 4, 2, 3).points();
+        assert_eq!(it.len(), 6);
+        it.next();
+        it.next();
+        assert_eq!(it.len(), 4);
+        assert_eq!(it.count(), 4);
+
+        // w * h overflows u32, but not usize
+        let huge = IRect::new(0, 0, 1 << 20, 1 << 20);
+        assert_eq!(huge.points().len(), 1 << 40);
+        assert_eq!(
+            huge.points().nth((1 << 20) + 2),
+            Some(mint::Point2::from([2, 1]))
+        );
+    }
+
+    #[test]
+    fn test_rows_and_cols() {
+        let rect = IRect::new(1, 2, 2, 3);
+
+        let rows: Vec<_> = rect.rows().map(|(y, row)| (y, row.collect())).collect();
+        assert_eq!(
+            rows,
+            vec![
+                (2, points(&[[1, 2], [2, 2]])),
+                (3, points(&[[1, 3], [2, 3]])),
+                (4, points(&[[1, 4], [2, 4]])),
+            ]
+        );
+
+        let cols: Vec<_> = rect.cols().map(|(x, col)| (x, col.collect())).collect();
+        assert_eq!(
+            cols,
+            vec![
+                (1, points(&[[1, 2], [1, 3], [1, 4]])),
+                (2, points(&[[2, 2], [2, 3], [2, 4]])),
+            ]
+        );
+
+        assert_eq!(rect.rows().len(), 3);
+        assert_eq!(rect.lines(LayoutDirection::Horizontal).len(), 2);
+
+        // An empty rect still has rows if it has height, but they're empty
+        let flat = IRect::new(0, 0, 0, 2);
+        assert_eq!(flat.rows().len(), 2);
+        assert!(flat.rows().all(|(_, mut row)| row.next().is_none()));
+        assert_eq!(flat.cols().len(), 0);
+    }
+
+    #[test]
+    fn test_srect_lines() {
+        let rect = SRect::new(-1, -2, 2, 3);
+
+        assert_eq!(
+            rect.points().collect::<Vec<_>>(),
+            vec![[-1, -2], [0, -2], [-1, -1], [0, -1], [-1, 0], [0, 0]]
+                .into_iter()
+                .map(mint::Point2::from)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            rect.points_colmajor().nth(1),
+            Some(mint::Point2::from([-1, -1]))
+        );
+
+        let rows: Vec<_> = rect.rows().map(|(y, row)| (y, row.count())).collect();
+        assert_eq!(rows, vec![(-2, 2), (-1, 2), (0, 2)]);
+        let cols: Vec<_> = rect.cols().map(|(x, _)| x).collect();
+        assert_eq!(cols, vec![-1, 0]);
+
+        assert_eq!(
+            rect.slice_dir(LayoutDirection::Vertical, 1..3),
+            SRect::new(-1, -1, 2, 2)
+        );
+        assert_eq!(rect.dir(LayoutDirection::Horizontal), -1..1);
+    }
+
+    fn points(points: &[[u32; 2]]) -> Vec<mint::Point2<u32>> {
+        points.iter().cloned().map(mint::Point2::from).collect()
+    }
+
     fn assert_points(rect: IRect, expected: &[[u32; 2]]) {
         let actual: Vec<_> = rect.points().collect();
-        let expected: Vec<mint::Point2<u32>> =
-            expected.iter().cloned().map(mint::Point2::from).collect();
-        assert_eq!(actual, expected);
+        assert_eq!(actual, points(expected));
     }
 }
@@ -1,98 +1,128 @@
+use lazy_static::lazy_static;
 use na::*;
-use std::convert::TryInto;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryInto,
+    sync::{Arc, RwLock},
+};
 
 use crate::util::*;
 
-pub fn calculate_bresenham(p1: Point3<i32>, p2: Point3<i32>) -> Vec<Point3<i32>> {
-    let mut line = Vec::new();
-
-    let mut p = Point3::new(p1.x, p1.y, p1.z);
-
-    let dx = p2.x - p1.x;
-    let dy = p2.y - p1.y;
-    let dz = p2.z - p1.z;
-    let x_inc = if dx < 0 { -1 } else { 1 };
-    let l = dx.abs();
-    let y_inc = if dy < 0 { -1 } else { 1 };
-    let m = dy.abs();
-    let z_inc = if dz < 0 { -1 } else { 1 };
-    let n = dz.abs();
-    let dx2 = l << 1;
-    let dy2 = m << 1;
-    let dz2 = n << 1;
-
-    if l >= m && l >= n {
-        let mut err_1 = dy2 - l;
-        let mut err_2 = dz2 - l;
-        for _i in 0..l {
-            line.push(p.clone());
-            if err_1 > 0 {
-                p.y += y_inc;
-                err_1 -= dx2;
-            }
-            if err_2 > 0 {
-                p.z += z_inc;
-                err_2 -= dx2;
-            }
-            err_1 += dy2;
-            err_2 += dz2;
-            p.x += x_inc;
+/// The points on a line from `start` to `end`, both included, worked out one at a time. Where a
+/// line passes exactly between two points it always takes the lower one, so swapping the ends
+/// gives the same points in reverse.
+#[derive(Clone, Debug)]
+pub struct BresenhamIter {
+    p: Point3<i32>,
+    inc: Vector3<i32>,
+    /// Twice the distance to cover along each axis.
+    delta2: Vector3<i32>,
+    /// The axis that's stepped along for every point, and the other two.
+    major: usize,
+    minor: [usize; 2],
+    err: [i32; 2],
+    remaining: usize,
+}
+
+impl BresenhamIter {
+    pub fn new(start: Point3<i32>, end: Point3<i32>) -> Self {
+        let d = end - start;
+        let inc = d.map(|d| if d < 0 { -1 } else { 1 });
+        let abs = d.abs();
+
+        let (major, minor) = if abs.x >= abs.y && abs.x >= abs.z {
+            (0, [1, 2])
+        } else if abs.y >= abs.x && abs.y >= abs.z {
+            (1, [0, 2])
+        } else {
+            (2, [1, 0])
+        };
+
+        let delta2 = abs * 2;
+
+        Self {
+            p: start,
+            inc,
+            delta2,
+            major,
+            minor,
+            err: [delta2[minor[0]] - abs[major], delta2[minor[1]] - abs[major]],
+            remaining: abs[major] as usize + 1,
         }
-    } else if m >= l && m >= n {
-        let mut err_1 = dx2 - m;
-        let mut err_2 = dz2 - m;
-        for _i in 0..m {
-            line.push(p.clone());
-            if err_1 > 0 {
-                p.x += x_inc;
-                err_1 -= dy2;
-            }
-            if err_2 > 0 {
-                p.z += z_inc;
-                err_2 -= dy2;
+    }
+
+    fn step(&mut self) {
+        for (err, &axis) in self.err.iter_mut().zip(self.minor.iter()) {
+            if *err > 0 || (*err == 0 && self.inc[axis] < 0) {
+                self.p[axis] += self.inc[axis];
+                *err -= self.delta2[self.major];
             }
-            err_1 += dx2;
-            err_2 += dz2;
-            p.y += y_inc;
+
+            *err += self.delta2[axis];
         }
-    } else {
-        let mut err_1 = dy2 - n;
-        let mut err_2 = dx2 - n;
-        for _i in 0..n {
-            line.push(p.clone());
-            if err_1 > 0 {
-                p.y += y_inc;
-                err_1 -= dz2;
-            }
-            if err_2 > 0 {
-                p.x += x_inc;
-                err_2 -= dz2;
-            }
-            err_1 += dy2;
-            err_2 += dx2;
-            p.z += z_inc;
+
+        self.p[self.major] += self.inc[self.major];
+    }
+}
+
+impl Iterator for BresenhamIter {
+    type Item = Point3<i32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let point = self.p;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            self.step();
         }
+
+        Some(point)
     }
-    line.push(p.clone());
 
-    line
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
 
-pub fn calculate_sphere_surface(radius: usize) -> Vec<Point3<f32>> {
-    let mut points = Vec::new();
+impl ExactSizeIterator for BresenhamIter {}
+
+pub fn calculate_bresenham(p1: Point3<i32>, p2: Point3<i32>) -> Vec<Point3<i32>> {
+    BresenhamIter::new(p1, p2).collect()
+}
 
-    let origin = Point3::origin();
+/// Like `calculate_bresenham`, but for a line on a plane, e.g. in the UI.
+pub fn calculate_bresenham_2d(p1: Point2<i32>, p2: Point2<i32>) -> Vec<Point2<i32>> {
+    BresenhamIter::new(Point3::new(p1.x, p1.y, 0), Point3::new(p2.x, p2.y, 0))
+        .map(|p| Point2::new(p.x, p.y))
+        .collect()
+}
+
+/// Whether the point at offset (x, y, z) from the center lies in the sphere of `radius`, i.e.
+/// its distance rounds down to at most `radius`. This compares squared distances, so there's no
+/// rounding error.
+fn in_sphere(x: i32, y: i32, z: i32, radius: i32) -> bool {
+    x * x + y * y + z * z < (radius + 1) * (radius + 1)
+}
 
+/// The points of `calculate_sphere` with a neighbour outside of it, including diagonal ones.
+/// Nothing can get from the inside of the sphere to the outside without going through one.
+pub fn calculate_sphere_surface(radius: usize) -> Vec<Point3<f32>> {
     let radius: i32 = radius.try_into().unwrap();
+    let mut points = Vec::new();
 
-    for x in -radius..radius {
-        for y in -radius..radius {
-            for z in -radius..radius {
-                let point = Point3::new(x as f32, y as f32, z as f32);
+    for x in -radius..=radius {
+        for y in -radius..=radius {
+            for z in -radius..=radius {
+                let on_surface = in_sphere(x, y, z, radius)
+                    && NEIGHBOURS
+                        .iter()
+                        .any(|(dx, dy, dz)| !in_sphere(x + dx, y + dy, z + dz, radius));
 
-                //DISGOSDANG
-                if euclidean_distance_squared(origin, point).sqrt().floor() as i32 == radius {
-                    points.push(point);
+                if on_surface {
+                    points.push(Point3::new(x as f32, y as f32, z as f32));
                 }
             }
         }
@@ -104,16 +134,11 @@ pub fn calculate_sphere_surface(radius: usize) -> Vec<Point3<f32>> {
 pub fn calculate_sphere(radius: i32) -> Vec<Point3<f32>> {
     let mut points = Vec::new();
 
-    let origin = Point3::origin();
-
     for x in -radius..=radius {
         for y in -radius..=radius {
             for z in -radius..=radius {
-                let point = Point3::new(x as f32, y as f32, z as f32);
-
-                //DISGOSDANG
-                if euclidean_distance_squared(origin, point).sqrt().floor() as i32 <= radius {
-                    points.push(point);
+                if in_sphere(x, y, z, radius) {
+                    points.push(Point3::new(x as f32, y as f32, z as f32));
                 }
             }
         }
@@ -122,14 +147,111 @@ pub fn calculate_sphere(radius: i32) -> Vec<Point3<f32>> {
     points
 }
 
-pub fn euclidean_distance_squared(a: Point3<f32>, b: Point3<f32>) -> f32 {
-    let x_diff = a.x - b.x;
-    let y_diff = a.y - b.y;
-    let z_diff = a.z - b.z;
+/// The offsets to the 26 points around a point.
+const NEIGHBOURS: [(i32, i32, i32); 26] = [
+    (-1, -1, -1),
+    (-1, -1, 0),
+    (-1, -1, 1),
+    (-1, 0, -1),
+    (-1, 0, 0),
+    (-1, 0, 1),
+    (-1, 1, -1),
+    (-1, 1, 0),
+    (-1, 1, 1),
+    (0, -1, -1),
+    (0, -1, 0),
+    (0, -1, 1),
+    (0, 0, -1),
+    (0, 0, 1),
+    (0, 1, -1),
+    (0, 1, 0),
+    (0, 1, 1),
+    (1, -1, -1),
+    (1, -1, 0),
+    (1, -1, 1),
+    (1, 0, -1),
+    (1, 0, 0),
+    (1, 0, 1),
+    (1, 1, -1),
+    (1, 1, 0),
+    (1, 1, 1),
+];
+
+type SphereCache = RwLock<HashMap<usize, Arc<Vec<Point3<f32>>>>>;
+
+lazy_static! {
+    static ref SPHERE_SURFACES: SphereCache = RwLock::new(HashMap::new());
+    static ref SPHERES: SphereCache = RwLock::new(HashMap::new());
+}
+
+/// The points of spheres around the origin, worked out once for each radius and shared after.
+pub struct SpherePoints;
+
+impl SpherePoints {
+    /// The points of `calculate_sphere_surface`.
+    pub fn surface(radius: usize) -> Arc<Vec<Point3<f32>>> {
+        Self::cached(&SPHERE_SURFACES, radius, calculate_sphere_surface)
+    }
+
+    /// The points of `calculate_sphere`.
+    pub fn solid(radius: usize) -> Arc<Vec<Point3<f32>>> {
+        Self::cached(&SPHERES, radius, |radius| {
+            calculate_sphere(radius.try_into().unwrap())
+        })
+    }
 
-    let result = x_diff * x_diff + y_diff * y_diff + z_diff * z_diff;
+    fn cached<F>(cache: &SphereCache, radius: usize, calculate: F) -> Arc<Vec<Point3<f32>>>
+    where
+        F: FnOnce(usize) -> Vec<Point3<f32>>,
+    {
+        if let Some(points) = cache.read().unwrap().get(&radius) {
+            return Arc::clone(points);
+        }
 
-    result
+        Arc::clone(
+            cache
+                .write()
+                .unwrap()
+                .entry(radius)
+                .or_insert_with(|| Arc::new(calculate(radius))),
+        )
+    }
+}
+
+pub fn distance_squared(a: Point3<f32>, b: Point3<f32>) -> f32 {
+    (a - b).norm_squared()
+}
+
+pub fn distance(a: Point3<f32>, b: Point3<f32>) -> f32 {
+    distance_squared(a, b).sqrt()
+}
+
+pub fn manhattan_distance(a: Point3<i32>, b: Point3<i32>) -> i32 {
+    (a - b).abs().sum()
+}
+
+/// The tile a world position falls in. Rounds towards negative infinity, so -0.5 is in tile -1
+/// rather than tile 0.
+pub fn floor_point(pos: Point3<f32>) -> Point3<i32> {
+    pos.map(|c| c.floor() as i32)
+}
+
+/// Converts a tile position to an array index. Negative coordinates are clamped to 0, so check
+/// them first or use `try_to_usize_point` if they can happen.
+pub fn to_usize_point(pos: Point3<i32>) -> Point3<usize> {
+    pos.map(|c| c.max(0) as usize)
+}
+
+/// Converts a tile position to an index into an array with dimensions `dim`, or `None` if it's
+/// outside the array.
+pub fn try_to_usize_point(pos: Point3<i32>, dim: (usize, usize, usize)) -> Option<Point3<usize>> {
+    let dim = [dim.0, dim.1, dim.2];
+
+    if (0..3).all(|i| pos[i] >= 0 && (pos[i] as usize) < dim[i]) {
+        Some(to_usize_point(pos))
+    } else {
+        None
+    }
 }
 
 pub fn get_cube_points(pos: Point3<f32>) -> Vec<Point3<f32>> {
@@ -144,3 +266,163 @@ pub fn get_cube_points(pos: Point3<f32>) -> Vec<Point3<f32>> {
         Point3::new(pos.x + 0.9, pos.y + 0.9, pos.z + 0.9),
     ]
 }
+
+#[cfg(test)]
+mod test {
+    use rand::prelude::*;
+
+    use super::*;
+
+    fn random_point(rng: &mut StdRng) -> Point3<i32> {
+        Point3::new(
+            rng.gen_range(-20, 20),
+            rng.gen_range(-20, 20),
+            rng.gen_range(-20, 20),
+        )
+    }
+
+    #[test]
+    fn test_sphere_surface_is_watertight() {
+        for radius in 0..12 {
+            let solid: HashSet<(i32, i32, i32)> = SpherePoints::solid(radius)
+                .iter()
+                .map(|p| (p.x as i32, p.y as i32, p.z as i32))
+                .collect();
+            let surface: HashSet<(i32, i32, i32)> = SpherePoints::surface(radius)
+                .iter()
+                .map(|p| (p.x as i32, p.y as i32, p.z as i32))
+                .collect();
+
+            let r = radius as i32;
+            assert!(surface.is_subset(&solid));
+            assert!(surface.contains(&(r, 0, 0)) && surface.contains(&(0, 0, -r)));
+
+            // Every neighbour of a point inside the shell is in the sphere
+            for &(x, y, z) in solid.difference(&surface) {
+                for (dx, dy, dz) in NEIGHBOURS.iter() {
+                    assert!(
+                        solid.contains(&(x + dx, y + dy, z + dz)),
+                        "radius {}: ({}, {}, {}) leaks",
+                        radius,
+                        x,
+                        y,
+                        z
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sphere_points_are_cached() {
+        assert!(Arc::ptr_eq(
+            &SpherePoints::surface(5),
+            &SpherePoints::surface(5)
+        ));
+        assert_eq!(*SpherePoints::solid(2), calculate_sphere(2));
+        assert_eq!(SpherePoints::solid(0).len(), 1);
+    }
+
+    #[test]
+    fn test_bresenham() {
+        assert_eq!(
+            calculate_bresenham(Point3::new(0, 0, 0), Point3::new(3, 1, 0)),
+            vec![
+                Point3::new(0, 0, 0),
+                Point3::new(1, 0, 0),
+                Point3::new(2, 1, 0),
+                Point3::new(3, 1, 0),
+            ]
+        );
+
+        let p = Point3::new(4, -2, 7);
+        assert_eq!(calculate_bresenham(p, p), vec![p]);
+    }
+
+    #[test]
+    fn test_bresenham_properties() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..1000 {
+            let (start, end) = (random_point(&mut rng), random_point(&mut rng));
+            let line: Vec<_> = BresenhamIter::new(start, end).collect();
+            let d = (end - start).abs();
+
+            assert_eq!(line, calculate_bresenham(start, end));
+            assert_eq!(line.len(), d.max() as usize + 1);
+            assert_eq!(BresenhamIter::new(start, end).len(), line.len());
+            assert_eq!(line.first(), Some(&start));
+            assert_eq!(line.last(), Some(&end));
+
+            // Neighbouring points touch
+            for pair in line.windows(2) {
+                assert_eq!((pair[1] - pair[0]).abs().max(), 1);
+            }
+
+            let mut reversed = calculate_bresenham(end, start);
+            reversed.reverse();
+            assert_eq!(line, reversed, "{} to {}", start, end);
+        }
+    }
+
+    #[test]
+    fn test_bresenham_2d() {
+        assert_eq!(
+            calculate_bresenham_2d(Point2::new(2, 1), Point2::new(-1, 0)),
+            vec![
+                Point2::new(2, 1),
+                Point2::new(1, 1),
+                Point2::new(0, 0),
+                Point2::new(-1, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_floor_point_negative() {
+        assert_eq!(
+            floor_point(Point3::new(-0.5, 0.5, -1.0)),
+            Point3::new(-1, 0, -1)
+        );
+        assert_eq!(
+            floor_point(Point3::new(-0.0, -1.001, 2.999)),
+            Point3::new(0, -2, 2)
+        );
+
+        // A position just below the array is out of it, not in the first tile
+        let below = floor_point(Point3::new(3.0, -0.25, 3.0));
+        assert_eq!(try_to_usize_point(below, (8, 8, 8)), None);
+        assert_eq!(to_usize_point(below), Point3::new(3, 0, 3));
+    }
+
+    #[test]
+    fn test_try_to_usize_point() {
+        let dim = (4, 5, 6);
+        assert_eq!(
+            try_to_usize_point(Point3::new(0, 0, 0), dim),
+            Some(Point3::new(0, 0, 0))
+        );
+        assert_eq!(
+            try_to_usize_point(Point3::new(3, 4, 5), dim),
+            Some(Point3::new(3, 4, 5))
+        );
+        assert_eq!(try_to_usize_point(Point3::new(4, 4, 5), dim), None);
+        assert_eq!(try_to_usize_point(Point3::new(3, 5, 5), dim), None);
+        assert_eq!(try_to_usize_point(Point3::new(3, 4, 6), dim), None);
+        assert_eq!(try_to_usize_point(Point3::new(0, -1, 0), dim), None);
+    }
+
+    #[test]
+    fn test_distances() {
+        let a = Point3::new(1.0, 2.0, 3.0);
+        let b = Point3::new(4.0, -2.0, 3.0);
+        assert_eq!(distance_squared(a, b), 25.0);
+        assert_eq!(distance(a, b), 5.0);
+        assert_eq!(distance(a, a), 0.0);
+
+        assert_eq!(
+            manhattan_distance(Point3::new(1, -2, 3), Point3::new(-1, 2, 3)),
+            6
+        );
+    }
+}
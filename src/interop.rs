@@ -0,0 +1,2 @@
+pub mod sheet;
+pub mod vox;
@@ -0,0 +1,249 @@
+use std::{collections::BTreeMap, path::Path};
+
+use failure::{format_err, Fallible};
+use image::{ImageFormat, Rgba, RgbaImage};
+use internship::IStr;
+
+use crate::rendering::{
+    font::GlyphSheet,
+    voxel::{Model, Voxel2, Voxel3, VoxelMirror, VoxelRotation},
+};
+
+/// The color of the line between slices.
+const SEPARATOR: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+/// Sheets bigger than this either way are refused, being more than most viewers will open.
+const MAX_SHEET_SIZE: u64 = 16384;
+
+const TRANSPARENT: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+/// Draws each z slice of `model` side by side, one pixel apart, with every cell drawn as the
+/// z face of its voxel and each glyph pixel `scale` pixels across. Empty cells and voxels
+/// missing from `voxels` are left transparent.
+pub fn render(
+    model: &Model,
+    voxels: &BTreeMap<IStr, Voxel3>,
+    glyphs: &GlyphSheet,
+    scale: u32,
+) -> Fallible<RgbaImage> {
+    let (width, height, depth) = model.voxels.dim();
+    if width == 0 || height == 0 || depth == 0 || scale == 0 {
+        return Err(format_err!(
+            "Can't draw {}x{}x{} cells at scale {}",
+            width,
+            height,
+            depth,
+            scale
+        ));
+    }
+
+    // Worked out wide first, so that a large scale can't overflow
+    let cell_width = u64::from(glyphs.char_width()) * u64::from(scale);
+    let cell_height = u64::from(glyphs.char_height()) * u64::from(scale);
+    let sheet_width = (cell_width * width as u64 + 1) * depth as u64 - 1;
+    let sheet_height = cell_height * height as u64;
+    if sheet_width > MAX_SHEET_SIZE || sheet_height > MAX_SHEET_SIZE {
+        return Err(format_err!(
+            "The sheet would be {}x{} pixels, but at most {}x{} can be written",
+            sheet_width,
+            sheet_height,
+            MAX_SHEET_SIZE,
+            MAX_SHEET_SIZE
+        ));
+    }
+
+    let (cell_width, cell_height) = (cell_width as u32, cell_height as u32);
+    let (sheet_width, sheet_height) = (sheet_width as u32, sheet_height as u32);
+    let slice_width = cell_width * width as u32;
+
+    let mut sheet = RgbaImage::from_pixel(sheet_width, sheet_height, TRANSPARENT);
+
+    for z in 1..depth as u32 {
+        let x = z * (slice_width + 1) - 1;
+        for y in 0..sheet_height {
+            sheet.put_pixel(x, y, SEPARATOR);
+        }
+    }
+
+    for ((x, y, z), name) in model.voxels.indexed_iter() {
+        let voxel = match name.as_ref().and_then(|name| voxels.get(name)) {
+            Some(voxel) => &voxel.z,
+            None => continue,
+        };
+
+        let left = z as u32 * (slice_width + 1) + x as u32 * cell_width;
+        let top = y as u32 * cell_height;
+
+        for v in 0..cell_height {
+            for u in 0..cell_width {
+                let pixel = glyph_pixel(voxel, glyphs, u / scale, v / scale);
+                sheet.put_pixel(left + u, top + v, pixel);
+            }
+        }
+    }
+
+    Ok(sheet)
+}
+
+/// Draws `model` as with `render` and writes it to `path` as a PNG.
+pub fn export<P>(
+    model: &Model,
+    voxels: &BTreeMap<IStr, Voxel3>,
+    glyphs: &GlyphSheet,
+    scale: u32,
+    path: P,
+) -> Fallible<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let sheet = render(model, voxels, glyphs, scale)?;
+    sheet
+        .save_with_format(path, ImageFormat::Png)
+        .map_err(|e| format_err!("Failed to write {}: {}", path.display(), e))
+}
+
+/// The color of `voxel` at `(x, y)` within its glyph: the glyph's pixel tinted by the foreground,
+/// over the background if there is one.
+fn glyph_pixel(voxel: &Voxel2, glyphs: &GlyphSheet, x: u32, y: u32) -> Rgba<u8> {
+    let (x, y) = glyph_source(voxel, glyphs, x, y);
+    let Rgba([r, g, b, a]) = glyphs.pixel(voxel.char_offset, x, y);
+
+    let (fg_r, fg_g, fg_b) = voxel.foreground.rgb();
+    let tint = |c: u8, fg: u8| (u32::from(c) * u32::from(fg) / 255) as u8;
+    let fg = [tint(r, fg_r), tint(g, fg_g), tint(b, fg_b)];
+
+    match voxel.background {
+        Some(background) => {
+            let (bg_r, bg_g, bg_b) = background.rgb();
+            let blend = |fg: u8, bg: u8| {
+                ((u32::from(fg) * u32::from(a) + u32::from(bg) * u32::from(255 - a)) / 255) as u8
+            };
+            Rgba([
+                blend(fg[0], bg_r),
+                blend(fg[1], bg_g),
+                blend(fg[2], bg_b),
+                255,
+            ])
+        }
+        None => Rgba([fg[0], fg[1], fg[2], a]),
+    }
+}
+
+/// Where in the glyph `(x, y)` of the drawn voxel comes from, undoing its rotation, which is in
+/// quarter turns clockwise, and then its mirroring.
+fn glyph_source(voxel: &Voxel2, glyphs: &GlyphSheet, x: u32, y: u32) -> (u32, u32) {
+    // Rotations assume square glyphs, as the font's are
+    let last_x = u32::from(glyphs.char_width()) - 1;
+    let last_y = u32::from(glyphs.char_height()) - 1;
+
+    let (x, y) = match voxel.rotation {
+        VoxelRotation::None => (x, y),
+        VoxelRotation::Rotation90 => (y, last_x - x),
+        VoxelRotation::Rotation180 => (last_x - x, last_y - y),
+        VoxelRotation::Rotation270 => (last_y - y, x),
+    };
+
+    match voxel.mirror {
+        VoxelMirror::None => (x, y),
+        VoxelMirror::MirrorX => (last_x - x, y),
+        VoxelMirror::MirrorY => (x, last_y - y),
+        VoxelMirror::MirrorBoth => (last_x - x, last_y - y),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::color::Color;
+    use ndarray::Array3;
+
+    const RED: Color = Color::new(255, 0, 0);
+    const BLUE: Color = Color::new(0, 0, 255);
+
+    /// Two glyphs: 0 is blank, and 1 has its left half filled in.
+    fn glyphs() -> GlyphSheet {
+        let pixels = RgbaImage::from_fn(16, 8, |x, _| {
+            if x >= 8 && x < 12 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                TRANSPARENT
+            }
+        });
+        GlyphSheet::new(pixels, 8, 8).unwrap()
+    }
+
+    fn voxels() -> BTreeMap<IStr, Voxel3> {
+        let half = Voxel2::new(1).foreground(RED);
+        let face = |voxel: Voxel2| Voxel3::new(Voxel2::default(), Voxel2::default(), voxel);
+
+        vec![
+            (IStr::new("half"), face(half.clone())),
+            (
+                IStr::new("mirrored"),
+                face(half.mirror(VoxelMirror::MirrorX)),
+            ),
+            (
+                IStr::new("blank"),
+                face(Voxel2::new(0).background(Some(BLUE))),
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    fn model() -> Model {
+        let mut voxels = Array3::from_elem((2, 2, 2), None);
+        voxels[[0, 0, 0]] = Some(IStr::new("half"));
+        voxels[[1, 0, 0]] = Some(IStr::new("missing"));
+        voxels[[0, 1, 1]] = Some(IStr::new("mirrored"));
+        voxels[[1, 1, 1]] = Some(IStr::new("blank"));
+        Model::new(voxels)
+    }
+
+    #[test]
+    fn test_render() {
+        let sheet = render(&model(), &voxels(), &glyphs(), 2).unwrap();
+
+        // Two slices of two 16 pixel cells, with a line between them
+        assert_eq!(sheet.dimensions(), (65, 32));
+
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        assert_eq!(*sheet.get_pixel(0, 0), red);
+        assert_eq!(*sheet.get_pixel(7, 15), red);
+        assert_eq!(*sheet.get_pixel(8, 0), TRANSPARENT);
+        assert_eq!(*sheet.get_pixel(16, 0), TRANSPARENT);
+        assert_eq!(*sheet.get_pixel(0, 16), TRANSPARENT);
+
+        for y in 0..32 {
+            assert_eq!(*sheet.get_pixel(32, y), SEPARATOR);
+        }
+
+        // The mirrored glyph in the second slice has its right half filled in instead
+        assert_eq!(*sheet.get_pixel(33, 16), TRANSPARENT);
+        assert_eq!(*sheet.get_pixel(33 + 15, 16), red);
+        assert_eq!(*sheet.get_pixel(33 + 16, 16), blue);
+        assert_eq!(*sheet.get_pixel(64, 31), blue);
+    }
+
+    #[test]
+    fn test_render_refuses() {
+        assert!(render(&model(), &voxels(), &glyphs(), 0).is_err());
+        assert!(render(&model(), &voxels(), &glyphs(), 1 << 20).is_err());
+
+        let empty = Model::new(Array3::from_elem((0, 2, 2), None));
+        assert!(render(&empty, &voxels(), &glyphs(), 1).is_err());
+    }
+
+    #[test]
+    fn test_export() {
+        let path = std::env::temp_dir().join("katakomb_test_sheet.png");
+        export(&model(), &voxels(), &glyphs(), 1, &path).unwrap();
+
+        let written = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(written, render(&model(), &voxels(), &glyphs(), 1).unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,548 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+use failure::{format_err, Fallible};
+use internship::IStr;
+use log::warn;
+use ndarray::prelude::*;
+
+use crate::rendering::{
+    color::Color,
+    drawable::Drawable,
+    tile::Tile,
+    voxel::{Model, Voxel2, Voxel3, VoxelFace},
+};
+
+/// Where the game exports the world for a look in MagicaVoxel.
+pub const CHUNK_EXPORT_PATH: &str = "chunk.vox";
+
+/// The most cells a .vox model can have along any side.
+pub const MAX_VOX_SIZE: usize = 256;
+
+/// The version of the format MagicaVoxel writes, and so what it expects to read.
+const VOX_VERSION: u32 = 150;
+
+/// A .vox palette has 256 entries, but color index 0 means an empty cell.
+const MAX_PALETTE_LEN: usize = 255;
+
+/// The glyph imported voxels are drawn with, a solid block.
+pub const FULL_BLOCK: u16 = 0xDB;
+
+pub type Rgb = (u8, u8, u8);
+
+/// The color of every tile that isn't see-through, for exporting a chunk.
+pub fn tile_colors(tiles: &Array3<Tile>) -> Array3<Option<Rgb>> {
+    tiles.map(|tile| {
+        if tile.tile_type.is_transparent() {
+            None
+        } else {
+            Some(tile.tile_type.get_color().to_rgb())
+        }
+    })
+}
+
+/// The foreground color of the top face of each voxel in `model`. Voxels missing from `voxels`
+/// are left out.
+pub fn model_colors(model: &Model, voxels: &BTreeMap<IStr, Voxel3>) -> Array3<Option<Rgb>> {
+    model.voxels.map(|name| {
+        name.as_ref()
+            .and_then(|name| voxels.get(name))
+            .map(|voxel| voxel[VoxelFace::Y].foreground.rgb())
+    })
+}
+
+/// Writes `colors` to `path` as a MagicaVoxel model, with `None` cells left empty. Models
+/// larger than `MAX_VOX_SIZE` along any side are turned away rather than cut up.
+///
+/// MagicaVoxel's z is up where ours is y, so those two axes are swapped on the way out.
+pub fn export<P>(colors: ArrayView3<Option<Rgb>>, path: P) -> Fallible<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let bytes = encode(colors)?;
+    fs::write(path, bytes).map_err(|e| format_err!("Failed to write {}: {}", path.display(), e))
+}
+
+fn encode(colors: ArrayView3<Option<Rgb>>) -> Fallible<Vec<u8>> {
+    let (width, height, depth) = colors.dim();
+
+    if [width, height, depth]
+        .iter()
+        .any(|&side| side == 0 || side > MAX_VOX_SIZE)
+    {
+        return Err(format_err!(
+            "Can't export {}x{}x{} cells, .vox models have to be 1 to {} along each side",
+            width,
+            height,
+            depth,
+            MAX_VOX_SIZE
+        ));
+    }
+
+    let (palette, shift) = quantize(colors.iter().flatten().copied());
+    // Palette entry i is color index i + 1
+    let indices: BTreeMap<Rgb, u8> = palette
+        .iter()
+        .enumerate()
+        .map(|(i, &color)| (color, i as u8 + 1))
+        .collect();
+
+    let mut size = Vec::new();
+    for &side in &[width, depth, height] {
+        push_u32(&mut size, side as u32);
+    }
+
+    let filled: Vec<_> = colors
+        .indexed_iter()
+        .filter_map(|((x, y, z), color)| color.map(|color| ([x, z, y], color)))
+        .collect();
+
+    let mut xyzi = Vec::with_capacity(4 + filled.len() * 4);
+    push_u32(&mut xyzi, filled.len() as u32);
+    for ([x, y, z], color) in filled {
+        let index = indices[&reduce(color, shift)];
+        xyzi.extend_from_slice(&[x as u8, y as u8, z as u8, index]);
+    }
+
+    let mut rgba = Vec::with_capacity(256 * 4);
+    for i in 0..256 {
+        let (r, g, b) = palette.get(i).copied().unwrap_or((0, 0, 0));
+        rgba.extend_from_slice(&[r, g, b, 255]);
+    }
+
+    let mut children = Vec::new();
+    push_chunk(&mut children, b"SIZE", &size, &[]);
+    push_chunk(&mut children, b"XYZI", &xyzi, &[]);
+    push_chunk(&mut children, b"RGBA", &rgba, &[]);
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"VOX ");
+    push_u32(&mut bytes, VOX_VERSION);
+    push_chunk(&mut bytes, b"MAIN", &[], &children);
+
+    Ok(bytes)
+}
+
+/// Up to `MAX_PALETTE_LEN` colors covering all of `colors`, and how many low bits were
+/// dropped from each channel to get there.
+fn quantize<I>(colors: I) -> (Vec<Rgb>, u32)
+where
+    I: Iterator<Item = Rgb>,
+{
+    let distinct: BTreeSet<Rgb> = colors.collect();
+
+    // Dropping 6 bits leaves at most 64 colors, so this always stops
+    (0..8)
+        .map(|shift| {
+            let reduced: BTreeSet<Rgb> = distinct.iter().map(|&c| reduce(c, shift)).collect();
+            (reduced.into_iter().collect::<Vec<_>>(), shift)
+        })
+        .find(|(palette, _)| palette.len() <= MAX_PALETTE_LEN)
+        .unwrap()
+}
+
+/// `color` with the low `shift` bits of each channel dropped, rounded to the middle of what
+/// they covered.
+fn reduce((r, g, b): Rgb, shift: u32) -> Rgb {
+    if shift == 0 {
+        return (r, g, b);
+    }
+
+    let half = 1u8 << (shift - 1);
+    let channel = |c: u8| ((c >> shift) << shift) | half;
+    (channel(r), channel(g), channel(b))
+}
+
+fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_chunk(bytes: &mut Vec<u8>, id: &[u8; 4], content: &[u8], children: &[u8]) {
+    bytes.extend_from_slice(id);
+    push_u32(bytes, content.len() as u32);
+    push_u32(bytes, children.len() as u32);
+    bytes.extend_from_slice(content);
+    bytes.extend_from_slice(children);
+}
+
+/// Reads the MagicaVoxel model at `path` into a model, with a voxel for each color it uses.
+/// The voxels are solid blocks named after the file and their palette index. Only the first
+/// model in a file with several is read.
+///
+/// The file is read as it goes, so only the model itself is ever held in memory.
+pub fn import<P>(path: P) -> Fallible<(Model, BTreeMap<IStr, Voxel3>)>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let name = path.file_stem().map_or_else(
+        || String::from("vox"),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+
+    let file =
+        File::open(path).map_err(|e| format_err!("Failed to open {}: {}", path.display(), e))?;
+
+    decode(BufReader::new(file), &name)
+        .map_err(|e| format_err!("{} isn't a .vox model: {}", path.display(), e))
+}
+
+fn decode<R>(mut reader: R, name: &str) -> Fallible<(Model, BTreeMap<IStr, Voxel3>)>
+where
+    R: Read,
+{
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"VOX " {
+        return Err(format_err!("it doesn't start with \"VOX \""));
+    }
+
+    let version = read_u32(&mut reader)?;
+    if version != VOX_VERSION {
+        warn!(
+            "Reading .vox version {} as if it were {}",
+            version, VOX_VERSION
+        );
+    }
+
+    let main = read_chunk_header(&mut reader)?;
+    if &main.id != b"MAIN" {
+        return Err(format_err!("the first chunk isn't MAIN"));
+    }
+    skip(&mut reader, main.content_len)?;
+
+    let mut size = None;
+    let mut cells: Option<Array3<Option<u8>>> = None;
+    let mut palette = None;
+    let mut remaining = main.children_len;
+
+    while remaining > 0 {
+        let chunk = read_chunk_header(&mut reader)?;
+        remaining = remaining
+            .checked_sub(12 + chunk.content_len + chunk.children_len)
+            .ok_or_else(|| format_err!("a chunk runs past the end of MAIN"))?;
+
+        match &chunk.id {
+            b"SIZE" if size.is_none() => {
+                if chunk.content_len != 12 {
+                    return Err(format_err!("SIZE has {} bytes", chunk.content_len));
+                }
+
+                let dim = [
+                    read_u32(&mut reader)? as usize,
+                    read_u32(&mut reader)? as usize,
+                    read_u32(&mut reader)? as usize,
+                ];
+                if dim.iter().any(|&side| side == 0 || side > MAX_VOX_SIZE) {
+                    return Err(format_err!("the model is {:?} cells", dim));
+                }
+
+                size = Some(dim);
+            }
+
+            b"XYZI" if cells.is_none() => {
+                let [width, depth, height] =
+                    size.ok_or_else(|| format_err!("XYZI comes before SIZE"))?;
+
+                let count = read_u32(&mut reader)? as u64;
+                if chunk.content_len != 4 + count * 4 {
+                    return Err(format_err!(
+                        "XYZI has {} bytes for {} voxels",
+                        chunk.content_len,
+                        count
+                    ));
+                }
+
+                // Their z is our y
+                let mut model_cells = Array3::from_elem((width, height, depth), None);
+                for _ in 0..count {
+                    let mut voxel = [0; 4];
+                    reader.read_exact(&mut voxel)?;
+                    let [x, z, y, index] = voxel;
+
+                    let cell = model_cells
+                        .get_mut([usize::from(x), usize::from(y), usize::from(z)])
+                        .ok_or_else(|| format_err!("there's a voxel outside the model"))?;
+                    // Index 0 is empty
+                    if index != 0 {
+                        *cell = Some(index);
+                    }
+                }
+
+                cells = Some(model_cells);
+            }
+
+            b"RGBA" if palette.is_none() => {
+                if chunk.content_len != 256 * 4 {
+                    return Err(format_err!("RGBA has {} bytes", chunk.content_len));
+                }
+
+                let mut rgba = [0; 256 * 4];
+                reader.read_exact(&mut rgba)?;
+                palette = Some(rgba);
+            }
+
+            b"SIZE" | b"XYZI" => {
+                warn!("Only reading the first model in the file");
+                skip(&mut reader, chunk.content_len)?;
+            }
+
+            _ => skip(&mut reader, chunk.content_len)?,
+        }
+
+        skip(&mut reader, chunk.children_len)?;
+    }
+
+    let cells = cells.ok_or_else(|| format_err!("there's no model in it"))?;
+
+    // Color index i is palette entry i - 1. Files without a palette use MagicaVoxel's
+    // default one, which isn't kept here, so they come out in shades of grey instead.
+    let color_of = |index: u8| match &palette {
+        Some(rgba) => {
+            let entry = usize::from(index - 1) * 4;
+            Color::new(rgba[entry], rgba[entry + 1], rgba[entry + 2])
+        }
+        None => Color::new(index, index, index),
+    };
+
+    let used: BTreeSet<u8> = cells.iter().flatten().copied().collect();
+    let names: BTreeMap<u8, IStr> = used
+        .iter()
+        .map(|&index| (index, IStr::new(&format!("{} {}", name, index))))
+        .collect();
+
+    let voxels = names
+        .iter()
+        .map(|(&index, name)| {
+            let face = Voxel2::new(FULL_BLOCK).foreground(color_of(index));
+            (name.clone(), Voxel3::new(face.clone(), face.clone(), face))
+        })
+        .collect();
+
+    let model = Model::new(cells.map(|index| index.map(|index| names[&index].clone())));
+
+    Ok((model, voxels))
+}
+
+struct ChunkHeader {
+    id: [u8; 4],
+    content_len: u64,
+    children_len: u64,
+}
+
+fn read_chunk_header<R: Read>(reader: &mut R) -> io::Result<ChunkHeader> {
+    let mut id = [0; 4];
+    reader.read_exact(&mut id)?;
+
+    Ok(ChunkHeader {
+        id,
+        content_len: u64::from(read_u32(reader)?),
+        children_len: u64::from(read_u32(reader)?),
+    })
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut word = [0; 4];
+    reader.read_exact(&mut word)?;
+    Ok(u32::from_le_bytes(word))
+}
+
+/// Reads past `len` bytes, failing if the file ends first.
+fn skip<R: Read>(reader: &mut R, len: u64) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len), &mut io::sink())?;
+
+    if skipped < len {
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the file ends partway through a chunk",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        let mut word = [0; 4];
+        word.copy_from_slice(&bytes[at..at + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    /// The id, content and children of the chunk at `at`, and where the next one starts.
+    fn read_chunk(bytes: &[u8], at: usize) -> (&[u8], &[u8], &[u8], usize) {
+        let content_len = u32_at(bytes, at + 4) as usize;
+        let children_len = u32_at(bytes, at + 8) as usize;
+        let content = at + 12;
+        let children = content + content_len;
+        let end = children + children_len;
+
+        (
+            &bytes[at..at + 4],
+            &bytes[content..children],
+            &bytes[children..end],
+            end,
+        )
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut colors = Array3::from_elem((3, 2, 4), None);
+        colors[[0, 0, 0]] = Some((255, 0, 0));
+        colors[[2, 1, 3]] = Some((0, 0, 255));
+        colors[[1, 0, 3]] = Some((255, 0, 0));
+
+        let path = std::env::temp_dir().join("katakomb_test_export.vox");
+        export(colors.view(), &path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(&bytes[0..4], b"VOX ");
+        assert_eq!(u32_at(&bytes, 4), VOX_VERSION);
+
+        let (id, content, main, end) = read_chunk(&bytes, 8);
+        assert_eq!(id, b"MAIN");
+        assert!(content.is_empty());
+        assert_eq!(end, bytes.len());
+
+        let (id, size, _, next) = read_chunk(main, 0);
+        assert_eq!(id, b"SIZE");
+        // y and z swapped
+        assert_eq!(
+            [u32_at(size, 0), u32_at(size, 4), u32_at(size, 8)],
+            [3, 4, 2]
+        );
+
+        let (id, xyzi, _, next) = read_chunk(main, next);
+        assert_eq!(id, b"XYZI");
+        assert_eq!(u32_at(xyzi, 0), 3);
+        assert_eq!(xyzi.len(), 4 + 3 * 4);
+        assert!(xyzi[4..].chunks(4).any(|v| v == [2, 3, 1, 1]));
+
+        let (id, rgba, _, next) = read_chunk(main, next);
+        assert_eq!(id, b"RGBA");
+        assert_eq!(rgba.len(), 256 * 4);
+        assert_eq!(&rgba[0..8], &[0, 0, 255, 255, 255, 0, 0, 255]);
+        assert_eq!(next, main.len());
+    }
+
+    #[test]
+    fn test_quantize() {
+        let few = vec![(1, 2, 3), (1, 2, 3), (200, 0, 0)];
+        assert_eq!(quantize(few.into_iter()), (vec![(1, 2, 3), (200, 0, 0)], 0));
+
+        let many = (0..=255).flat_map(|r| (0..4).map(move |g| (r, g * 60, 0)));
+        let (palette, shift) = quantize(many.clone());
+        assert!(palette.len() <= MAX_PALETTE_LEN);
+        assert!(shift > 0);
+        assert!(many.map(|c| reduce(c, shift)).all(|c| palette.contains(&c)));
+    }
+
+    #[test]
+    fn test_rejects_oversized() {
+        let too_wide = Array3::from_elem((MAX_VOX_SIZE + 1, 1, 1), None);
+        let error = encode(too_wide.view()).unwrap_err().to_string();
+        assert!(error.contains("257x1x1"), "{}", error);
+
+        assert!(encode(Array3::from_elem((0, 1, 1), None).view()).is_err());
+        assert!(encode(Array3::from_elem((MAX_VOX_SIZE, 1, 1), None).view()).is_ok());
+    }
+
+    /// A 2x1x3 model with two voxels: one of palette entry 0 at (0, 0, 0) and one of entry 1
+    /// at (1, 0, 2), in their coordinates.
+    fn fixture() -> Vec<u8> {
+        let mut bytes = vec![
+            b'V', b'O', b'X', b' ', 150, 0, 0, 0, //
+            b'M', b'A', b'I', b'N', 0, 0, 0, 0, 0x3C, 0x04, 0, 0, //
+            b'S', b'I', b'Z', b'E', 12, 0, 0, 0, 0, 0, 0, 0, //
+            2, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0, //
+            b'X', b'Y', b'Z', b'I', 12, 0, 0, 0, 0, 0, 0, 0, //
+            2, 0, 0, 0, 0, 0, 0, 1, 1, 2, 0, 2, //
+            b'R', b'G', b'B', b'A', 0, 4, 0, 0, 0, 0, 0, 0, //
+            255, 0, 0, 255, 0, 128, 255, 255,
+        ];
+        bytes.resize(bytes.len() + 254 * 4, 0);
+        bytes
+    }
+
+    #[test]
+    fn test_import_fixture() {
+        let (model, voxels) = decode(&fixture()[..], "test").unwrap();
+
+        // Their y is our z
+        assert_eq!(model.voxels.dim(), (2, 1, 3));
+        assert_eq!(model.voxels[[0, 0, 0]], Some(IStr::new("test 1")));
+        assert_eq!(model.voxels[[1, 0, 2]], Some(IStr::new("test 2")));
+        assert_eq!(model.voxels.iter().flatten().count(), 2);
+
+        assert_eq!(voxels.len(), 2);
+        let face = &voxels[&IStr::new("test 2")][VoxelFace::Z];
+        assert_eq!(face.char_offset, FULL_BLOCK);
+        assert_eq!(face.foreground, Color::new(0, 128, 255));
+    }
+
+    #[test]
+    fn test_import_skips_unknown_chunks() {
+        let mut bytes = fixture();
+        // A made up chunk with a child between SIZE and XYZI
+        let unknown = [
+            b'n', b'T', b'R', b'N', 2, 0, 0, 0, 12, 0, 0, 0, 7, 7, //
+            b'n', b'S', b'H', b'P', 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        bytes.splice(44..44, unknown.iter().copied());
+        let main_children = 0x43C + unknown.len() as u32;
+        bytes[16..20].copy_from_slice(&main_children.to_le_bytes());
+
+        let (model, voxels) = decode(&bytes[..], "test").unwrap();
+        assert_eq!(model.voxels.iter().flatten().count(), 2);
+        assert_eq!(voxels.len(), 2);
+    }
+
+    #[test]
+    fn test_import_round_trip() {
+        let mut colors = Array3::from_elem((4, 3, 2), None);
+        colors[[3, 2, 1]] = Some((10, 20, 30));
+        colors[[0, 1, 0]] = Some((200, 100, 0));
+
+        let (model, voxels) = decode(&encode(colors.view()).unwrap()[..], "trip").unwrap();
+        assert_eq!(model.voxels.dim(), (4, 3, 2));
+        assert_eq!(model_colors(&model, &voxels), colors);
+    }
+
+    #[test]
+    fn test_import_malformed() {
+        let fixture = fixture();
+        let check = |bytes: &[u8]| decode(bytes, "bad").map(|_| ());
+
+        assert!(check(b"PNG!").is_err());
+        assert!(check(&fixture[..60]).is_err());
+
+        // A voxel outside SIZE
+        let mut outside = fixture.clone();
+        outside[64] = 5;
+        assert!(check(&outside).is_err());
+
+        // XYZI says it has more voxels than it does
+        let mut short = fixture.clone();
+        short[56] = 9;
+        assert!(check(&short).is_err());
+
+        // No XYZI at all
+        let mut no_model = fixture.clone();
+        no_model[44..48].copy_from_slice(b"XXXX");
+        assert!(check(&no_model).is_err());
+    }
+
+    #[test]
+    fn test_import_missing_file() {
+        let error = import("/nonexistent/katakomb.vox").unwrap_err().to_string();
+        assert!(error.contains("katakomb.vox"), "{}", error);
+    }
+}
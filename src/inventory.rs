@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use ggez::graphics::Color;
+use serde::{Deserialize, Serialize};
+
+use crate::{strings::StringTable, tr};
+
+/// Something the player can carry more than one of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Flare,
+    Ammo,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 2] = [ItemKind::Flare, ItemKind::Ammo];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemKind::Flare => "flare",
+            ItemKind::Ammo => "ammo",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// Its name as the player sees it.
+    pub fn display_name(self, strings: &StringTable) -> String {
+        match self {
+            ItemKind::Flare => tr!(strings, "item.flare"),
+            ItemKind::Ammo => tr!(strings, "item.ammo"),
+        }
+    }
+
+    /// The most of it the player can carry.
+    pub fn max_carried(self) -> u32 {
+        match self {
+            ItemKind::Flare => 8,
+            ItemKind::Ammo => 120,
+        }
+    }
+
+    /// The glyph and color it's drawn with while it's lying in the world.
+    pub fn icon(self) -> (u16, Color) {
+        match self {
+            ItemKind::Flare => (0x2F, Color::new(1.0, 0.25, 0.2, 1.0)),
+            ItemKind::Ammo => (0xF0, Color::new(0.8, 0.65, 0.3, 1.0)),
+        }
+    }
+}
+
+/// How many of each item the player is carrying.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Inventory {
+    counts: BTreeMap<ItemKind, u32>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn count(&self, kind: ItemKind) -> u32 {
+        self.counts.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// How many more of `kind` there's room for.
+    pub fn room_for(&self, kind: ItemKind) -> u32 {
+        kind.max_carried().saturating_sub(self.count(kind))
+    }
+
+    /// Adds as many of `amount` as there's room for, returning how many that was.
+    pub fn add(&mut self, kind: ItemKind, amount: u32) -> u32 {
+        let added = amount.min(self.room_for(kind));
+        if added > 0 {
+            *self.counts.entry(kind).or_insert(0) += added;
+        }
+
+        added
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_add_up_to_max() {
+        let mut inventory = Inventory::new();
+        let max = ItemKind::Flare.max_carried();
+
+        assert_eq!(inventory.add(ItemKind::Flare, max - 1), max - 1);
+        assert_eq!(inventory.add(ItemKind::Flare, 3), 1);
+        assert_eq!(inventory.count(ItemKind::Flare), max);
+        assert_eq!(inventory.room_for(ItemKind::Flare), 0);
+        assert_eq!(inventory.add(ItemKind::Flare, 1), 0);
+
+        // Room for one kind says nothing about the others
+        assert_eq!(inventory.add(ItemKind::Ammo, 0), 0);
+        assert_eq!(inventory.count(ItemKind::Ammo), 0);
+        assert_eq!(inventory.add(ItemKind::Ammo, 20), 20);
+        assert_eq!(inventory.count(ItemKind::Ammo), 20);
+    }
+
+    #[test]
+    fn test_names() {
+        for &kind in &ItemKind::ALL {
+            assert_eq!(ItemKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(ItemKind::from_name("grenade"), None);
+    }
+}
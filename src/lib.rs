@@ -1,7 +1,12 @@
 pub mod constants;
 pub mod generation;
 pub mod geometry;
+pub mod interop;
+pub mod inventory;
 pub mod rendering;
+pub mod run;
+pub mod settings;
+pub mod strings;
 pub mod ui;
 pub mod util;
 pub mod world;
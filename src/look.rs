@@ -0,0 +1,97 @@
+use std::f32::consts::FRAC_PI_2;
+
+use na::{Point2, Vector2};
+
+use crate::settings::InputConfig;
+
+/// How far the view can tilt up or down, just short of straight up or down so that it doesn't
+/// flip over.
+pub const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Eases the mouse moving `raw` pixels this update towards how far it moved the last, which was
+/// eased to `last`, as much as `config` says.
+pub fn smooth_look_delta(
+    last: Vector2<f32>,
+    raw: Vector2<f32>,
+    config: &InputConfig,
+) -> Vector2<f32> {
+    last * config.smoothing + raw * (1.0 - config.smoothing)
+}
+
+/// Where the player faces after the mouse moves `delta` pixels from `facing`, as yaw and pitch.
+/// The pitch stops at `MAX_PITCH` either way.
+pub fn apply_look_delta(
+    facing: Point2<f32>,
+    delta: Vector2<f32>,
+    config: &InputConfig,
+) -> Point2<f32> {
+    let pitch_sign = if config.invert_y { -1.0 } else { 1.0 };
+    let yaw = facing.x - delta.x * config.sensitivity_x;
+    let pitch = facing.y + delta.y * config.sensitivity_y * pitch_sign;
+
+    Point2::new(yaw, pitch.max(-MAX_PITCH).min(MAX_PITCH))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_look_delta() {
+        let config = InputConfig {
+            sensitivity_x: 0.125,
+            sensitivity_y: 0.25,
+            ..InputConfig::default()
+        };
+
+        // Moving right turns to a lower yaw, and moving down looks down
+        let facing = apply_look_delta(Point2::origin(), Vector2::new(8.0, 2.0), &config);
+        assert_eq!(facing, Point2::new(-1.0, 0.5));
+
+        let inverted = InputConfig {
+            invert_y: true,
+            ..config.clone()
+        };
+        let facing = apply_look_delta(Point2::origin(), Vector2::new(8.0, 2.0), &inverted);
+        assert_eq!(facing, Point2::new(-1.0, -0.5));
+    }
+
+    #[test]
+    fn test_pitch_clamped() {
+        let config = InputConfig::default();
+
+        let down = apply_look_delta(Point2::new(1.0, 1.5), Vector2::new(0.0, 1000.0), &config);
+        assert_eq!(down, Point2::new(1.0, MAX_PITCH));
+
+        // Pushing past the clamp doesn't build up, so moving back responds straight away
+        let back = apply_look_delta(down, Vector2::new(0.0, -100.0), &config);
+        assert_eq!(back.y, MAX_PITCH - 100.0 * config.sensitivity_y);
+
+        let inverted = InputConfig {
+            invert_y: true,
+            ..config
+        };
+        let up = apply_look_delta(Point2::origin(), Vector2::new(0.0, 1000.0), &inverted);
+        assert_eq!(up.y, -MAX_PITCH);
+    }
+
+    #[test]
+    fn test_smoothing() {
+        let raw = Vector2::new(10.0, -4.0);
+
+        let config = InputConfig::default();
+        assert_eq!(smooth_look_delta(Vector2::new(3.0, 3.0), raw, &config), raw);
+
+        let config = InputConfig {
+            smoothing: 0.5,
+            ..InputConfig::default()
+        };
+        let mut last = Vector2::zeros();
+        let mut eased = Vec::new();
+        for _ in 0..3 {
+            last = smooth_look_delta(last, raw, &config);
+            eased.push(last.x);
+        }
+        assert_eq!(eased, vec![5.0, 7.5, 8.75]);
+    }
+}
@@ -1,76 +1,163 @@
 use std::{
-    cmp::Ordering,
-    collections::BTreeSet,
     env,
-    f32::consts::{FRAC_PI_4, PI},
+    f32::consts::PI,
     fs::File,
     io::BufReader,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    thread,
     time::Duration,
     time::Instant,
 };
 
-use failure::Fallible;
-use float_ord::FloatOrd;
+use failure::{format_err, Fallible};
 use ggez::{
     // audio::{SoundData, Source, SoundSource},
-    conf::WindowMode,
-    event::{self, EventHandler, KeyCode},
+    conf::{WindowMode, WindowSetup},
+    event::{self, EventHandler, KeyCode, KeyMods},
 
     graphics::{self, spritebatch::SpriteBatch, Color, DrawParam, FilterMode, Image},
-    input::{keyboard, mouse},
+    input::mouse,
     timer,
     Context,
     ContextBuilder,
     GameResult,
 };
-use log::info;
-use na::{
-    Isometry3, Matrix4, Perspective3, Point2, Point3, Rotation3, Unit, UnitVector3, Vector2,
-    Vector3,
-};
-use ndarray::arr2;
+use internship::IStr;
+use log::{debug, info, warn};
+use na::{Isometry3, Matrix4, Point2, Point3, Rotation3, Unit, Vector2, Vector3};
 use ndarray::prelude::*;
-use noise::{OpenSimplex, Perlin, Seedable, Value, Worley};
 use rand::prelude::*;
-use rayon::prelude::*;
-use rodio::{OutputStream, Source};
 use specs::prelude::*;
 use structopt::StructOpt;
 
 use crate::{
-    components::{position::*, velocity::*},
+    audio::Audio,
+    components::{
+        collider::*,
+        hit_flash::*,
+        pickup::*,
+        position::*,
+        save::{entities_path, load_entities, register_components, EntitiesFile},
+        velocity::*,
+    },
+    console::{Console, GameCtx},
     constants::*,
+    floating_text::{FloatingTexts, CHAR_SIZE, CHAR_SPACING},
     generation::world::*,
-    geometry::util::*,
-    rendering::{drawable::Drawable, font::*, light::*, tile::*},
-    systems::physics_system::*,
+    geometry::{frustum::Frustum, rect::IRect, util::*},
+    interop::vox,
+    inventory::Inventory,
+    look::{apply_look_delta, smooth_look_delta},
+    menu::{GameState, Menu, MenuChoice},
+    metrics::{FrameLimiter, FrameMetrics, MetricsReport, Phase},
+    rendering::{
+        color::PaletteRemaps,
+        drawable::Drawable,
+        font::*,
+        fov::*,
+        light::*,
+        lighting::*,
+        minimap::{draw_minimap, minimap, player_arrow, MINIMAP_RADIUS},
+        shake::CameraShake,
+        tile::*,
+        util::depth_scale,
+    },
+    replay::{Input, InputFrame, Key, Recorder, Replay, ReplayHeader},
+    run::{GameObjective, Run},
+    settings::{InputConfig, Settings, SettingsOverrides, SETTINGS_PATH},
+    status::{draw_status_icons, tint_light, StatusEffects, StatusKind, LAVA_BURN_UPDATES},
+    strings::{StringTable, STRINGS_PATH},
+    systems::{physics_system::*, pickup_system::*, spatial_hash_system::*},
+    toast::{draw_toasts, Toasts},
+    ui::{BoxConstraints, Element, KataText, UiContext},
     util::*,
-    world::util::*,
+    weapons::{Weapons, WEAPONS_PATH},
+    world::{
+        dirty::DirtyConsumer,
+        explored::Explored,
+        regions::SealedRegions,
+        save::{quicksave_path, PlayerSave, SaveFile},
+        sound::OcclusionCache,
+        stamp::{self, TileStamp, STAMP_PATH},
+        util::*,
+        DecalType,
+    },
 };
 
 mod audio;
+mod bench;
 mod components;
+mod console;
 mod constants;
 mod editor;
+mod floating_text;
 mod generation;
 mod geometry;
+mod interop;
+mod inventory;
+mod look;
+mod menu;
+mod metrics;
 mod rendering;
+mod replay;
+mod run;
+mod settings;
+mod status;
+mod strings;
 mod systems;
+mod toast;
 pub mod ui;
 mod util;
+mod weapons;
 mod world;
 
 #[derive(StructOpt)]
 struct Opts {
     #[structopt(subcommand)]
     mode: Option<Mode>,
+    /// The folder in saves/ to load the world from if it's there, and to quicksave to.
+    #[structopt(long, default_value = "default")]
+    world: String,
+    /// Saves the input of every update to this file, to be played back with --replay. The world
+    /// is generated afresh rather than loaded, so that it can be made again.
+    #[structopt(long, parse(from_os_str), conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Plays back input saved with --record, in the same world, instead of reading the keyboard
+    /// and mouse until it runs out.
+    #[structopt(long, parse(from_os_str))]
+    replay: Option<PathBuf>,
+    #[structopt(flatten)]
+    settings: SettingsOverrides,
 }
 
 #[derive(StructOpt)]
 enum Mode {
     Main,
     Editor,
+    /// Runs the lighting and FOV passes on a generated chunk without a window, and prints how
+    /// long they took. Fails if any of them panicked.
+    Bench {
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+        #[structopt(long, default_value = "100")]
+        iterations: usize,
+        /// Plays this recording through instead, in its own world, ignoring the seed and
+        /// iterations.
+        #[structopt(long, parse(from_os_str))]
+        replay: Option<PathBuf>,
+    },
+    /// Generates a chunk without a window and prints horizontal slices of it as text, one
+    /// character per tile, for comparing what generation makes.
+    DumpSlices {
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+        /// The heights to dump, which can be given more than once. Every height if none are.
+        #[structopt(long = "y")]
+        y_levels: Vec<usize>,
+        /// Writes each slice to its own file in this folder instead of printing them.
+        #[structopt(long, parse(from_os_str))]
+        out_dir: Option<PathBuf>,
+    },
 }
 
 impl Default for Mode {
@@ -100,35 +187,110 @@ fn main() -> Fallible<()> {
         .level_for("winit", log::LevelFilter::Info)
         .level_for("gilrs", log::LevelFilter::Warn)
         .level_for("ggez", log::LevelFilter::Info)
+        // Frame timings, once a second. Turn this off to silence them.
+        .level_for("katakomb::metrics", log::LevelFilter::Debug)
         .chain(std::io::stdout())
         .apply()?;
 
+    let settings = Settings::load_or_create(SETTINGS_PATH)?.with_overrides(&opts.settings);
+    let mode = opts.mode.unwrap_or_default();
+
+    // These need neither a window nor a GPU, so they can run anywhere
+    match mode {
+        Mode::Bench {
+            seed,
+            iterations,
+            ref replay,
+        } => {
+            return match replay {
+                Some(path) => bench::run_replay(&settings, &Replay::read(path)?),
+                None => bench::run(&settings, seed, iterations),
+            }
+        }
+        Mode::DumpSlices {
+            seed,
+            ref y_levels,
+            ref out_dir,
+        } => return world::debug::dump_slices(&settings, seed, y_levels, out_dir.as_deref()),
+        _ => {}
+    }
+
     let mut cb = ContextBuilder::new("Katakomb", "CodeBunny");
+    let mut resource_paths = vec![String::from("the default resources folder")];
 
     if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = PathBuf::from(manifest_dir);
         path.push("resources");
         info!("Adding cargo resource path: '{:?}'", path);
+        resource_paths.push(path.display().to_string());
         cb = cb.add_resource_path(path);
     }
 
     let (mut ctx, mut event_loop) = cb
-        .window_mode(WindowMode::default().dimensions(WINDOW_WIDTH, WINDOW_HEIGHT))
+        .window_mode(
+            WindowMode::default().dimensions(settings.window_width, settings.window_height),
+        )
+        .window_setup(WindowSetup::default().vsync(settings.vsync))
         .build()
-        .expect("Could not create ggez context!");
+        .map_err(|e| {
+            format_err!(
+                "Failed to create the window, with resources from {}: {}",
+                resource_paths.join(" and "),
+                e
+            )
+        })?;
 
-    match opts.mode.unwrap_or_default() {
+    match mode {
         Mode::Main => {
-            let mut handler = Katakomb::new(&mut ctx)?;
+            let (seed, input, settings) =
+                start_input(opts.record.as_deref(), opts.replay.as_deref(), settings)?;
+            let mut handler = Katakomb::new(&mut ctx, settings, &opts.world, seed, input)?;
             event::run(ctx, event_loop, handler);
         }
         Mode::Editor => {
             let mut handler = editor::Editor::new(&mut ctx)?;
             event::run(ctx, event_loop, handler);
         }
+        Mode::Bench { .. } | Mode::DumpSlices { .. } => {
+            unreachable!("Headless modes run without a context")
+        }
     }
 }
 
+/// Picks the world's seed and where input comes from, as `--record` and `--replay` say. A replay
+/// brings its own seed and the settings it was recorded with.
+fn start_input(
+    record: Option<&Path>,
+    replay: Option<&Path>,
+    settings: Settings,
+) -> Fallible<(u64, Input, Settings)> {
+    if let Some(path) = replay {
+        let replay = Replay::read(path)?;
+        info!(
+            "Replaying {} updates from {}",
+            replay.frames.len(),
+            path.display()
+        );
+        let settings = replay.header.apply_to(settings);
+        return Ok((
+            replay.header.seed,
+            Input::Replaying(replay.frames.into_iter()),
+            settings,
+        ));
+    }
+
+    let seed = thread_rng().gen();
+    let input = match record {
+        Some(path) => {
+            info!("Recording to {}", path.display());
+            Input::Recording(Recorder::create(path, &ReplayHeader::new(seed, &settings))?)
+        }
+        None => Input::Live,
+    };
+
+    Ok((seed, input, settings))
+}
+
 struct Entity {
     pos: Point3<f32>,
     vel: Vector3<f32>,
@@ -137,19 +299,205 @@ struct Entity {
 
 struct Player {
     entity: Entity,
+    /// How far the mouse moved last update, after smoothing.
+    look_delta: Vector2<f32>,
 
     crouching: bool,
 
+    /// What the player is standing on, if anything.
+    ground: Option<Ground>,
+    /// Flying through rock instead of falling, from the console.
+    noclip: bool,
+    /// The highest the player has been since they were last on the ground.
+    fall_top: Option<f32>,
+
+    health: f32,
+    status: StatusEffects,
+    /// Whether the interact key was held last update, so holding it only interacts once.
+    interact_held: bool,
+
     equipped_item: Item,
 }
 
+/// What happened in a player's step that the game reacts to.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct StepEvents {
+    fired: bool,
+    /// How far the shot travels before echoing off the walls around, when one was fired.
+    echoes: Vec<f32>,
+    /// How many tiles the player fell, if they landed.
+    landed: Option<f32>,
+    /// The interact key was pressed.
+    interacted: bool,
+}
+
 impl Player {
+    /// A player in the middle of the world, holding a glowstick.
+    fn new(settings: &Settings) -> Self {
+        Self {
+            entity: Entity {
+                pos: Point3::new(
+                    (settings.chunk_size / 2) as f32,
+                    (settings.chunk_size / 2) as f32,
+                    (settings.chunk_size / 2) as f32,
+                ),
+                vel: Vector3::new(0.0, 0.0, 0.0),
+                facing: Point2::origin(),
+            },
+            look_delta: Vector2::zeros(),
+            equipped_item: Item::glowstick(),
+            crouching: false,
+            ground: None,
+            noclip: false,
+            fall_top: None,
+            health: PLAYER_MAX_HEALTH,
+            status: StatusEffects::new(),
+            interact_held: false,
+        }
+    }
+
+    /// Turns, moves and uses the equipped item for one update, as `input` says, turning as
+    /// `look` says. Lights the item gives off are added to `light_sources`.
+    fn step(
+        &mut self,
+        tiles: &Array3<Tile>,
+        input: &InputFrame,
+        look: &InputConfig,
+        light_sources: &mut Vec<(Point3<usize>, Color)>,
+    ) -> StepEvents {
+        let mut events = StepEvents::default();
+
+        self.look_delta = smooth_look_delta(self.look_delta, input.mouse_delta.into(), look);
+        self.entity.facing = apply_look_delta(self.entity.facing, self.look_delta, look);
+
+        // The player's own light takes on the color of whatever's happening to them
+        let item_lights = light_sources.len();
+        self.equipped_item
+            .update(world_pos_to_index(self.entity.pos), light_sources);
+        if let Some(tint) = self.status.light_tint() {
+            for (_, color) in &mut light_sources[item_lights..] {
+                *color = tint_light(*color, tint);
+            }
+        }
+        self.update_equipped();
+
+        let movement_rotation =
+            Rotation3::from_axis_angle(&Vector3::y_axis(), self.entity.facing.x);
+
+        if input.left_button {
+            if let Some(echoes) = self.equipped_item.primary_use(
+                tiles.view(),
+                clamp_eye(self.entity.pos, tiles),
+                world_pos_to_index(self.entity.pos),
+                self.status.sway_multiplier(),
+                light_sources,
+            ) {
+                events.fired = true;
+                events.echoes = echoes;
+            }
+        }
+
+        if input.right_button {
+            self.equipped_item
+                .secondary_use(world_pos_to_index(self.entity.pos), light_sources);
+        }
+
+        let acceleration = 0.01 * self.status.movement_multiplier();
+        if input.held(Key::Left) {
+            self.entity.vel.x += acceleration;
+        }
+        if input.held(Key::Right) {
+            self.entity.vel.x -= acceleration;
+        }
+        if input.held(Key::Forward) {
+            self.entity.vel.z += acceleration;
+        }
+        if input.held(Key::Back) {
+            self.entity.vel.z -= acceleration;
+        }
+
+        self.ground = probe_ground(
+            tiles.view(),
+            self.entity.pos,
+            PLAYER_HALF_WIDTH,
+            GROUND_EPSILON,
+        )
+        .filter(|ground| ground.distance < GROUND_EPSILON);
+
+        let on_lava = self
+            .ground
+            .as_ref()
+            .map_or(false, |ground| ground.tile_type == Some(TileType::Lava));
+        if on_lava && !self.noclip {
+            self.status.apply(StatusKind::Burning, LAVA_BURN_UPDATES);
+        }
+        self.health = (self.health - self.status.tick()).max(0.0);
+
+        let height = self.entity.pos.y;
+        if self.noclip {
+            self.fall_top = None;
+        } else if self.ground.is_some() {
+            events.landed = self.fall_top.take().map(|top| top - height);
+        } else {
+            self.fall_top = Some(self.fall_top.map_or(height, |top| top.max(height)));
+        }
+
+        if self.noclip {
+            if input.held(Key::Jump) {
+                self.entity.vel.y += 0.01;
+            }
+            if input.held(Key::Crouch) {
+                self.entity.vel.y -= 0.01;
+            }
+        } else if self.ground.is_some() {
+            if input.held(Key::Jump) {
+                self.entity.vel.y += 0.3;
+            }
+        } else {
+            self.entity.vel.y -= 0.01;
+        }
+        self.crouching = input.held(Key::Crouch) && !self.noclip;
+
+        events.interacted = input.held(Key::Interact) && !self.interact_held;
+        self.interact_held = input.held(Key::Interact);
+
+        if !self.noclip {
+            clip_velocity(self.entity.pos, &mut self.entity.vel, tiles);
+        }
+
+        let vel_normalised = Unit::new_and_get(self.entity.vel);
+        if vel_normalised.1 > 1.0 {
+            self.entity.vel = vel_normalised.0.into_inner();
+        }
+
+        let movement_offset = movement_rotation.transform_vector(&self.entity.vel);
+
+        let new_pos = self.entity.pos + movement_offset;
+
+        if self.noclip {
+            // Still kept inside the world, eyes and all, as there's nothing outside it to index
+            let (width, height, depth) = tiles.dim();
+            self.entity.pos = Point3::new(
+                new_pos.x.max(0.0).min(width as f32 - 1.0),
+                new_pos.y.max(0.0).min(height as f32 - 2.0),
+                new_pos.z.max(0.0).min(depth as f32 - 1.0),
+            );
+        } else if !collides_at(new_pos, tiles) {
+            self.entity.pos = new_pos;
+        }
+
+        self.entity.vel *= 0.9;
+
+        events
+    }
+
     pub fn draw_equipped(
         &self,
         font: &KataFont,
         mvp: Matrix4<f32>,
         rotation: Rotation3<f32>,
         mut item_sprite_batch: &mut SpriteBatch,
+        (screen_width, screen_height): (f32, f32),
     ) {
         match &self.equipped_item {
             Item::Weapon {
@@ -169,6 +517,7 @@ impl Player {
                     *ads,
                     *gun_recoil,
                     *gun_rotation,
+                    (screen_width, screen_height),
                 );
             }
             Item::Glowstick {
@@ -183,10 +532,7 @@ impl Player {
                     0.25
                 };
 
-                let screen_dest = [
-                    WINDOW_WIDTH / 2.0,
-                    WINDOW_HEIGHT,
-                ];
+                let screen_dest = [screen_width / 2.0, screen_height];
 
                 let dp = DrawParam::new()
                     .src(tile_type.get_char_offset(&font))
@@ -229,6 +575,8 @@ const GLOWSTICK_TIMER: usize = 10000;
 
 enum Item {
     Weapon {
+        /// What the model is called in `weapons.json`.
+        name: IStr,
         gun_model: Array2<TileType>,
         gun_timer: u8,
 
@@ -243,6 +591,28 @@ enum Item {
 }
 
 impl Item {
+    /// A glowstick that hasn't been cracked yet.
+    pub fn glowstick() -> Self {
+        Self::Glowstick {
+            cracked: false,
+            light_timer: GLOWSTICK_TIMER,
+        }
+    }
+
+    /// The weapon with the model called `name`, ready to fire, if there is one.
+    pub fn weapon(weapons: &Weapons, name: &str) -> Option<Self> {
+        let gun_model = weapons.get(name)?.clone();
+
+        Some(Self::Weapon {
+            name: IStr::new(name),
+            gun_model,
+            gun_timer: 0,
+            ads: 0.0,
+            gun_recoil: 0.0,
+            gun_rotation: Point2::origin(),
+        })
+    }
+
     pub fn update(&mut self, pos: Point3<usize>, lights: &mut Vec<(Point3<usize>, Color)>) {
         match self {
             Self::Weapon {
@@ -265,9 +635,10 @@ impl Item {
                 ref mut light_timer,
             } => {
                 if *cracked {
+                    let (color, _) = TileType::Glowstick.emission().unwrap();
                     lights.push((
                         pos,
-                        scale_color(Color::GREEN, *light_timer as f32 / GLOWSTICK_TIMER as f32),
+                        scale_color(color, *light_timer as f32 / GLOWSTICK_TIMER as f32),
                     ));
                     if *light_timer > 0 {
                         *light_timer -= 1;
@@ -276,8 +647,18 @@ impl Item {
             }
         }
     }
-    
-    pub fn primary_use(&mut self, pos: Point3<usize>, lights: &mut Vec<(Point3<usize>, Color)>) {
+
+    /// Fires or throws the item from `eye`. If a shot was fired, returns how far it travels
+    /// before echoing off the walls in each direction, see `echo_distances`. The aim is thrown
+    /// off more the higher `sway` is.
+    pub fn primary_use(
+        &mut self,
+        tiles: ArrayView3<Tile>,
+        eye: Point3<f32>,
+        pos: Point3<usize>,
+        sway: f32,
+        lights: &mut Vec<(Point3<usize>, Color)>,
+    ) -> Option<Vec<f32>> {
         println!("primary item use");
         match self {
             Self::Weapon {
@@ -288,85 +669,27 @@ impl Item {
             } => {
                 println!("gun timer: {}", *gun_timer);
                 if *gun_timer == 0 {
-                    lights.push((pos, Color::YELLOW));
+                    lights.push((pos, rendering::color::WARM_LIGHT.into()));
                     *gun_recoil = (*gun_recoil + 0.2).min(1.0);
-                    gun_rotation.x = (gun_rotation.x + (thread_rng().gen::<f32>() - 0.5) * 0.05)
+                    gun_rotation.x = (gun_rotation.x
+                        + (thread_rng().gen::<f32>() - 0.5) * 0.05 * sway)
                         .min(1.0)
                         .max(-1.0);
                     gun_rotation.y = (gun_rotation.y + 0.05).min(1.0);
 
-                    // // dbg!(std::env::current_dir().unwrap().to_str().unwrap());
-                    // let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-                    // // let device = rodio::default_output_device().unwrap();
-                    // let file = File::open(r"resources/gunshot.wav").unwrap();
-                    // let source = rodio::Decoder::new(BufReader::new(file)).unwrap();
-
-                    // let mut echo_distances = Vec::new();
-
-                    // for cube_point in get_cube_points(Point3::new(-0.5, -0.5, -0.5)) {
-                    //     let ray_target = self.player.pos + (cube_point.coords * MAX_SOUND_RANGE * 2.0);
-
-                    //     if is_in_array(self.tile_array.view(), world_pos_to_index(ray_target)) {
-                    //         let ray_hit = try_bresenham_hitscan(
-                    //             self.tile_array.view(),
-                    //             world_pos_to_int(self.player.pos),
-                    //             world_pos_to_int(ray_target),
-                    //         );
-
-                    //         if ray_hit != world_pos_to_int(ray_target) {
-                    //             // //TODO mess with this
-                    //             let hit_distance = euclidean_distance_squared(
-                    //                 self.player.pos,
-                    //                 Point3::new(ray_hit.x as f32, ray_hit.y as f32, ray_hit.z as f32),
-                    //             )
-                    //             .sqrt();
-                    //             let hit_distance_ratio = hit_distance / (MAX_SOUND_RANGE * 2.0);
-                    //             let hit_distance_ratio_squared = hit_distance * hit_distance;
-                    //             echo_distances.push(hit_distance_ratio);
-                    //             // let mut source = Source::from_data(ctx, self.player_gun_sound.clone()).unwrap();
-                    //             // source.set_pitch(0.5 + 0.5 * (1.0 - hit_distance_ratio));
-                    //             // source.set_fade_in(Duration::from_millis((hit_distance_ratio_squared) as u64));
-                    //             // //source.set_volume(1.0 - (hit_distance_ratio * 0.5));
-                    //             // self.sound_queue.push((update_time + (hit_distance_ratio * 0.5) as f64, source));
-                    //             // //TODO take average of hit distances and use that to change the non-ray sound's pitch
-                    //         }
-                    //     }
-                    // }
-
-                    // echo_distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
-                    // let min_echo_distance = echo_distances.first().unwrap();
-                    // // let med_echo_distance = echo_distances[echo_distances.len() /2 ];
-                    // let max_echo_distance = echo_distances.last().unwrap();
-
-                    // //warning: using more than 2 reverbs leads to very unpleasant results :<
-                    // stream_handle
-                    //     .play_raw(
-                    //         source
-                    //             .convert_samples::<i16>()
-                    //             .buffered()
-                    //             .reverb(
-                    //                 Duration::from_millis((min_echo_distance * 1000.0) as u64),
-                    //                 0.5 - min_echo_distance * 0.5,
-                    //             )
-                    //             // .reverb(
-                    //             //     Duration::from_millis((med_echo_distance * 750.0) as u64),
-                    //             //     0.5 - med_echo_distance * 0.5,
-                    //             // )
-                    //             .reverb(
-                    //                 Duration::from_millis((max_echo_distance * 1250.0) as u64),
-                    //                 0.25 - max_echo_distance * 0.25,
-                    //             )
-                    //             .convert_samples(),
-                    //     )
-                    //     .unwrap();
+                    // How the shot echoes off the walls around
+                    let echoes = echo_distances(tiles, eye, MAX_SOUND_RANGE as usize);
 
                     *gun_timer = 12;
+                    return Some(echoes);
                 }
             }
             Self::Glowstick { .. } => {
                 //TODO: throw
             }
         }
+
+        None
     }
 
     pub fn secondary_use(&mut self, pos: Point3<usize>, lights: &mut Vec<(Point3<usize>, Color)>) {
@@ -387,152 +710,992 @@ impl Item {
 }
 
 struct Katakomb {
-    // blank_texture: Image,
+    /// Stretched over the screen to dim the world while paused.
+    blank_texture: Image,
     // lighting_sphere: Vec<Point3<f32>>,
+    settings: Settings,
+    state: GameState,
+    menu: Menu,
+    console: Console,
     font: KataFont,
-    tile_array: Array3<Tile>,
-    draw_tiles: BTreeSet<DrawTile>,
+    world: world::World,
+    /// The things in the world that aren't tiles, like pickups.
+    ecs: specs::World,
+    spatial_hash: SpatialHashSystem,
+    /// What the world was generated from, unless it was loaded from a save.
+    seed: Option<u64>,
+    /// Where F5 saves the world and F9 loads it from.
+    save_path: PathBuf,
+    /// Where lighting hears about changes to the world.
+    lighting_dirty: DirtyConsumer,
+    /// Which static lights the player is sealed off from.
+    light_regions: SealedRegions,
+    draw_tiles: Vec<VisibleCell>,
+    fov_cache: FovCache,
+    /// Every cell the player has seen, for the minimap.
+    explored: Explored,
+    shadowcast_scratch: ShadowcastScratch,
+    /// The colors swapped in the palette modes other than the normal one.
+    palette_remaps: PaletteRemaps,
+    /// The models the player's weapon can have.
+    weapons: Weapons,
+
+    metrics: FrameMetrics,
+    frame_limiter: FrameLimiter,
+    show_metrics: bool,
+    /// Where the HUD and the menus are drawn, along with their text.
+    ui_context: UiContext<KataFontBatch>,
+    show_minimap: bool,
+    /// Drawn smaller than the HUD, so that the whole minimap fits on the screen.
+    minimap_batch: KataFontBatch,
 
     player: Player,
-
-    nuke_lighting: bool,
-
-    lights: Vec<(Point3<usize>, Color)>,
+    /// Moves the view when the player fires or lands hard.
+    shake: CameraShake,
+    toasts: Toasts,
+    /// The damage numbers over whatever was just shot.
+    floating_texts: FloatingTexts,
+    /// Saved with the player, and put back by quickloads.
+    run: Run,
+    /// Where each update's keys and mouse movement come from.
+    input: Input,
+
+    /// What the world was last lit with, so that it's only lit again once something changes.
+    lighting_cache: LightingCache,
 
     current_tic: u64,
 
     mouse_pos: ggez::mint::Point2<f32>,
+    /// Whether moving the mouse back to the middle of the window failed last frame, so that
+    /// it's only logged once.
+    mouse_recenter_failed: bool,
+    /// Where the gunshots are heard, unless there's no sound device.
+    audio: Option<Audio>,
+    /// How muffled each entity that made a sound lately is, by entity id.
+    occlusion: OcclusionCache<u32>,
     // lights: Vec<Light>,
     // light_noise: OpenSimplex,
-    // player_gun_sound: SoundData,
-    // sound_queue: Vec<(f64, Source)>,
 }
 
 impl Katakomb {
-    pub fn new(ctx: &mut Context) -> Fallible<Self> {
+    /// Starts on the main menu in a world generated from `seed`, from which the quicksave for
+    /// `world_name` can be continued instead. While recording or replaying there's no menu, and
+    /// the generated world is played straight away so that it's the same every time.
+    pub fn new(
+        ctx: &mut Context,
+        settings: Settings,
+        world_name: &str,
+        seed: u64,
+        input: Input,
+    ) -> Fallible<Self> {
         // Load/create resources such as images here.
         // let noise = OpenSimplex::new().set_seed(thread_rng().gen::<u32>());
         // let meta_noise = OpenSimplex::new().set_seed(thread_rng().gen::<u32>());
 
-        ggez::input::mouse::set_cursor_grabbed(ctx, true);
-
-        let chunk_gen_package = ChunkGenPackage {
-            simplex: OpenSimplex::new().set_seed(thread_rng().gen::<u32>()),
-            simplex_weight: Value::new().set_seed(thread_rng().gen::<u32>()),
-            perlin: Perlin::new().set_seed(thread_rng().gen::<u32>()),
-            perlin_weight: Value::new().set_seed(thread_rng().gen::<u32>()),
-            // worley: Worley::new().set_seed(thread_rng().gen::<u32>()),
-            // worley_weight: Value::new().set_seed(thread_rng().gen::<u32>()),
-            value: Value::new().set_seed(thread_rng().gen::<u32>()),
-            value_weight: Value::new().set_seed(thread_rng().gen::<u32>()),
-        };
-
         graphics::set_default_filter(ctx, FilterMode::Nearest);
 
         use crate::rendering::tile::TileType::*;
 
-        let tile_array = generate_chunk(Point3::new(0, 0, 0), &chunk_gen_package);
-
-        let lights: Vec<_> = tile_array
-            .iter()
-            .filter(|tile| {
-                thread_rng().gen_range(0, 5000) == 0
-                    && world::util::any_neighbour_is(
-                        tile_array.view(),
-                        Point3::new(
-                            tile.pos.x.floor() as i32,
-                            tile.pos.y.floor() as i32,
-                            tile.pos.z.floor() as i32,
-                        ),
-                        |t| t.tile_type.is_transparent(),
-                    )
-                    && world::util::any_neighbour_is(
-                        tile_array.view(),
-                        Point3::new(
-                            tile.pos.x.floor() as i32,
-                            tile.pos.y.floor() as i32,
-                            tile.pos.z.floor() as i32,
-                        ),
-                        |t| t.tile_type.collides(),
-                    )
-            })
-            .map(|tile| {
-                (
-                    Point3::new(
-                        tile.pos.x.floor() as usize,
-                        tile.pos.y.floor() as usize,
-                        tile.pos.z.floor() as usize,
-                    ),
-                    Color {
-                        r: thread_rng().gen_range(0.0, 1.0),
-                        g: thread_rng().gen_range(0.0, 1.0),
-                        b: thread_rng().gen_range(0.0, 1.0),
-                        a: 1.0,
-                    },
-                )
-            })
-            .collect();
+        let save_path = quicksave_path(world_name)?;
+
+        let mut world = generate_world(seed, &settings);
+        let lighting_dirty = world.register_dirty_consumer();
+        let light_regions = SealedRegions::new(&mut world);
+        let explored = Explored::new(world.tiles().dim());
 
-        Ok(Self {
-            // blank_texture: Image::solid(ctx, 1, graphics::Color::WHITE).unwrap(),
+        let mut ecs = specs::World::new();
+        register_components(&mut ecs);
+        ecs.register::<HitFlashComponent>();
+        ecs.insert(Collector::new(Point3::origin(), Inventory::new()));
+        let spatial_hash = SpatialHashSystem::new(&mut ecs);
+        let spots = pickup_spots(world.tiles(), &mut StdRng::seed_from_u64(seed));
+        spawn_pickups(&mut ecs, &spots, &mut StdRng::seed_from_u64(seed));
+
+        // Read and decoded once, and shared by the HUD, the minimap and the world
+        let font = KataFont::load(ctx)?;
+        let blank_texture = Image::solid(ctx, 1, graphics::Color::WHITE)?;
+
+        let mut game = Self {
             // lighting_sphere: calculate_sphere_surface(LIGHT_RANGE),
-            font: KataFont::load(ctx)?,
-            tile_array,
-            draw_tiles: BTreeSet::new(),
-            player: Player {
-                entity: Entity{
-                    pos: Point3::new(
-                        (CHUNK_SIZE / 2) as f32,
-                        (CHUNK_SIZE / 2) as f32,
-                        (CHUNK_SIZE / 2) as f32,
-                    ),
-                    vel: Vector3::new(0.0, 0.0, 0.0),
-                    facing: Point2::origin(),
-                },
-                equipped_item:
-                Item::Glowstick {
-                    cracked: false,
-                    light_timer: GLOWSTICK_TIMER,
-                },
-                // Item::Weapon {
-                //     gun_recoil: 0.0,
-                //     gun_rotation: Point2::origin(),
-                //     gun_model: arr2(&[
-                //         [
-                //             Air, Air, FrontSight, Air, Air, Air, Air, RearSight, Air, Air, Air,
-                //         ],
-                //         [
-                //             BarrelEnd, BarrelEnd, GasBlock, Barrel, Barrel, RecLower, RecLower,
-                //             RecLower, Air, StockUpper, StockUpper,
-                //         ],
-                //         [
-                //             Air, Air, Air, Air, Air, Air, Magazine, Grip, Stock, Stock, Stock,
-                //         ],
-                //     ]),
-                //     gun_timer: 0,
-                //     ads: 0.0,
-                // },
-                crouching: false,
-            },
-            nuke_lighting: false,
-            lights,
+            world,
+            ecs,
+            spatial_hash,
+            seed: Some(seed),
+            save_path,
+            lighting_dirty,
+            light_regions,
+            draw_tiles: Vec::new(),
+            fov_cache: FovCache::default(),
+            explored,
+            shadowcast_scratch: ShadowcastScratch::new(MAX_CAST_RANGE),
+            palette_remaps: PaletteRemaps::load(),
+            weapons: Weapons::load(),
+            metrics: FrameMetrics::new(Instant::now()),
+            frame_limiter: FrameLimiter::new(settings.frame_budget(), Instant::now()),
+            show_metrics: false,
+            ui_context: UiContext::new(KataFontBatch::new(
+                font.clone(),
+                blank_texture.clone(),
+                2.0,
+            ))
+            .with_strings(StringTable::load(STRINGS_PATH)?),
+            show_minimap: true,
+            minimap_batch: KataFontBatch::new(font.clone(), blank_texture.clone(), 1.0),
+            state: GameState::MainMenu,
+            menu: Menu::new(),
+            console: Console::new(),
+            player: Player::new(&settings),
+            shake: CameraShake::new(),
+            toasts: Toasts::new(),
+            floating_texts: FloatingTexts::new(),
+            run: Run::new(),
+            input,
+            lighting_cache: LightingCache::default(),
             current_tic: 0,
-            mouse_pos: [
-                WINDOW_WIDTH / 2.0,
-                WINDOW_HEIGHT / 2.0,
-            ]
-            .into()
+            mouse_pos: [settings.window_width / 2.0, settings.window_height / 2.0].into(),
+            mouse_recenter_failed: false,
+            settings,
+            audio: Audio::new()
+                .map_err(|e| warn!("Playing without sound: {}", e))
+                .ok(),
+            occlusion: OcclusionCache::new(),
+            // Last, as the batches above take clones of them
+            font,
+            blank_texture,
             // lights: Vec::new(),
             // light_noise: OpenSimplex::new(),
-            // player_gun_sound: SoundData::new(ctx, r"/gunshot.wav").unwrap(),
-            // sound_queue: Vec::new(),
+        };
+
+        let (_, sealed) = game
+            .light_regions
+            .visible_lights(game.world.lights(), world_pos_to_index(game.eye()));
+        info!(
+            "{} of {} lights start out sealed off from the player",
+            sealed,
+            game.world.lights().len()
+        );
+
+        if game.input.is_live() {
+            game.open_menu();
+        } else {
+            game.play(ctx);
+        }
+
+        Ok(game)
+    }
+
+    fn player_save(&self) -> PlayerSave {
+        let entity = &self.player.entity;
+
+        PlayerSave {
+            pos: [entity.pos.x, entity.pos.y, entity.pos.z],
+            facing: [entity.facing.x, entity.facing.y],
+            inventory: self.ecs.read_resource::<Collector>().inventory.clone(),
+            run: self.run.clone(),
+        }
+    }
+
+    /// Puts the player back where they were saved, standing still, carrying what they were then
+    /// and as far through the run.
+    fn restore_player(&mut self, player: PlayerSave) {
+        let entity = &mut self.player.entity;
+        entity.pos = Point3::from(player.pos);
+        entity.vel = Vector3::zeros();
+        entity.facing = Point2::from(player.facing);
+
+        self.player.ground = None;
+        self.player.health = PLAYER_MAX_HEALTH;
+        self.player.status.clear();
+        self.run = player.run;
+
+        let mut collector = self.ecs.write_resource::<Collector>();
+        collector.inventory = player.inventory;
+        collector.collected.clear();
+    }
+
+    fn quicksave(&self) -> Fallible<()> {
+        SaveFile::new(&self.world, self.player_save()).write(&self.save_path)?;
+        EntitiesFile::new(&self.ecs).write(entities_path(&self.save_path))?;
+        info!("Saved to {}", self.save_path.display());
+
+        Ok(())
+    }
+
+    /// Replaces the world, the player and the entities with the quicksave. If it can't be read
+    /// nothing is changed.
+    fn quickload(&mut self, ctx: &mut Context) -> Fallible<()> {
+        let (world, player) = SaveFile::read(&self.save_path)?.into_world();
+        let entities = EntitiesFile::read(entities_path(&self.save_path))?;
+
+        self.world = world;
+        self.seed = None;
+        self.lighting_dirty = self.world.register_dirty_consumer();
+        self.light_regions = SealedRegions::new(&mut self.world);
+        self.lighting_cache.nuke();
+        self.draw_tiles.clear();
+        self.fov_cache = FovCache::default();
+        self.explored = Explored::new(self.world.tiles().dim());
+        self.restore_player(player);
+
+        // The ids of the entities that are deleted get used again by the loaded ones
+        self.ecs.delete_all();
+        self.ecs.maintain();
+        load_entities(&mut self.ecs, &entities.entities);
+        self.occlusion = OcclusionCache::new();
+
+        // Capture the mouse again, as if the game had just started
+        self.capture_mouse(ctx);
+
+        info!("Loaded {}", self.save_path.display());
+
+        Ok(())
+    }
+
+    /// Where the player sees from, kept out of the ceiling when there's no room to stand.
+    fn eye(&self) -> Point3<f32> {
+        clamp_eye(self.player.entity.pos, self.world.tiles())
+    }
+
+    /// The last empty cell in front of the player before whatever they're looking at, or the
+    /// furthest one within reach if there's nothing there.
+    fn aim_point(&self) -> Point3<i32> {
+        let eye = self.eye();
+        let rotation = Rotation3::from_euler_angles(
+            self.player.entity.facing.y,
+            self.player.entity.facing.x,
+            0.0,
+        );
+        let facing = rotation.transform_vector(&Vector3::new(0.0, 0.0, 1.0));
+
+        let mut aim = world_pos_to_index(eye);
+        let mut distance = 0.0;
+
+        while distance < self.settings.stamp_range {
+            let pos = eye + facing * distance;
+            if collides_at(pos, self.world.tiles()) {
+                break;
+            }
+
+            aim = world_pos_to_index(pos);
+            distance += 0.5;
+        }
+
+        Point3::new(aim.x as i32, aim.y as i32, aim.z as i32)
+    }
+
+    /// The way the player is looking, leaving out the camera shake.
+    fn look_dir(&self) -> Unit<Vector3<f32>> {
+        let rotation = Rotation3::from_euler_angles(
+            self.player.entity.facing.y,
+            self.player.entity.facing.x,
+            0.0,
+        );
+
+        Unit::new_normalize(rotation.transform_vector(&Vector3::new(0.0, 0.0, 1.0)))
+    }
+
+    /// The door or lever the player is looking at, if there's one within reach and nothing
+    /// solid in the way.
+    fn interact_target(&self) -> Option<Point3<i32>> {
+        raycast_until(
+            self.world.tiles().view(),
+            self.eye(),
+            self.look_dir(),
+            INTERACT_RANGE,
+            |tile| tile.tile_type.is_interactable() || !tile.tile_type.is_transparent(),
+        )
+        .map(|hit| hit.cell)
+        .filter(|&cell| {
+            self.world
+                .tile(cell)
+                .map_or(false, |tile| tile.tile_type.is_interactable())
         })
     }
+
+    /// Works out what a shot fired where the player is looking hits. Whatever it hits flashes and
+    /// shows the damage it took, though nothing has any health to take it from yet.
+    fn shoot(&mut self) {
+        let (eye, dir) = (self.eye(), self.look_dir());
+        let hit = hitscan(
+            self.world.tiles().view(),
+            &self.ecs.read_resource::<SpatialHash>(),
+            &self.ecs.read_storage::<ColliderComponent>(),
+            eye,
+            dir,
+            WEAPON_RANGE,
+        );
+
+        match hit {
+            Some(ShotHit::Entity { entity, t }) => {
+                let pos = self
+                    .ecs
+                    .read_storage::<PositionComponent>()
+                    .get(entity)
+                    .map(|pos| pos.value);
+                let tiles = self.world.tiles().view();
+
+                // Whatever's partly behind something only takes a graze
+                let cover = pos.map_or(0.0, |pos| {
+                    cover_fraction(tiles, eye, world_pos_to_index(pos))
+                });
+                let damage = ((1.0 - cover) * WEAPON_DAMAGE as f32).round().max(1.0) as u32;
+                debug!(
+                    "Shot entity {} from {:.1} away, {:.0}% in cover",
+                    entity.id(),
+                    t,
+                    cover * 100.0
+                );
+
+                self.floating_texts.push(
+                    damage.to_string(),
+                    eye + dir.into_inner() * t,
+                    self.current_tic,
+                );
+                let mut flashes = self.ecs.write_storage::<HitFlashComponent>();
+                if let Err(e) = flashes.insert(entity, HitFlashComponent::new(self.current_tic)) {
+                    warn!("Failed to flash entity {}: {}", entity.id(), e);
+                }
+
+                if let (Some(audio), Some(pos)) = (&self.audio, pos) {
+                    let attenuation = self.occlusion.attenuation(
+                        tiles,
+                        entity.id(),
+                        eye,
+                        pos,
+                        MAX_SOUND_RANGE,
+                        self.current_tic,
+                    );
+                    if let Err(e) = audio.play_scream(attenuation) {
+                        warn!("Failed to play a scream: {}", e);
+                    }
+                }
+            }
+            Some(ShotHit::Tile(hit)) => {
+                debug!("Shot the tile at {} from {:.1} away", hit.cell, hit.t);
+
+                let is_rock = self
+                    .world
+                    .tile(hit.cell)
+                    .map_or(false, |tile| tile.tile_type.is_rock());
+                if is_rock {
+                    self.world.add_decal(hit.cell, DecalType::BulletPock);
+                }
+            }
+            None => debug!("Shot at nothing"),
+        }
+    }
+
+    /// Draws in the pickups near the player and shows what they picked up.
+    fn collect_pickups(&mut self) {
+        self.ecs.write_resource::<Collector>().pos = self.player.entity.pos;
+        PickupSystem.run_now(&self.ecs);
+        self.ecs.maintain();
+
+        let collected = std::mem::take(&mut self.ecs.write_resource::<Collector>().collected);
+        let strings = &self.ui_context.strings;
+        for (item, amount) in collected {
+            let text = tr!(strings, "hud.picked_up", item.display_name(strings), amount);
+            self.toasts.push(text, self.current_tic);
+        }
+        self.toasts.expire(self.current_tic);
+    }
+
+    /// Adds `glyph` in `color` to `batch` at `pos` in the world, facing the camera and drawn like
+    /// a tile `size` times over.
+    fn add_billboard(
+        &self,
+        batch: &mut SpriteBatch,
+        model_view_projection: Matrix4<f32>,
+        (screen_width, screen_height): (f32, f32),
+        pos: Point3<f32>,
+        (glyph, color): (u16, Color),
+        size: f32,
+    ) {
+        // Tiles are drawn at their corner rather than their middle
+        let drawn_at = pos - Vector3::repeat(0.5);
+        let screen_pos =
+            match Point3::from_homogeneous(model_view_projection * drawn_at.to_homogeneous()) {
+                Some(screen_pos) if screen_pos.z >= -1.0 && screen_pos.z <= 1.0 => screen_pos,
+                _ => return,
+            };
+        let scale = depth_scale(screen_pos.z) * PI * 10.0 * size;
+
+        batch.add(
+            DrawParam::new()
+                .src(self.font.get_src_rect(glyph))
+                .dest([
+                    screen_pos.x * screen_width / 2.0 + screen_width / 2.0,
+                    -screen_pos.y * screen_height / 2.0 + screen_height / 2.0,
+                ])
+                .scale([scale, scale])
+                .color(color)
+                .offset([0.5, 0.5]),
+        );
+    }
+
+    /// Adds the pickups in sight to `batch`, drawn like tiles and lit by the tile they're in.
+    fn draw_pickups(
+        &self,
+        batch: &mut SpriteBatch,
+        model_view_projection: Matrix4<f32>,
+        screen_size: (f32, f32),
+    ) {
+        let eye = self.eye();
+        let tiles = self.world.tiles();
+        let entities = self.ecs.entities();
+        let positions = self.ecs.read_storage::<PositionComponent>();
+        let pickups = self.ecs.read_storage::<PickupComponent>();
+
+        for (entity, pos, pickup) in (&entities, &positions, &pickups).join() {
+            // Only bobbing while it's lying there
+            let pos = match pickup.flight {
+                Some(_) => pos.value,
+                None => pos.value + bob_offset(self.current_tic, entity.id()),
+            };
+            if !line_of_sight(tiles.view(), eye, pos).clear {
+                continue;
+            }
+
+            let (glyph, item_color) = pickup.item.icon();
+            let illumination =
+                try_get_tile_at(pos, tiles).map_or(Color::BLACK, |tile| tile.illumination_color);
+            let sprite = (glyph, shade(item_color, illumination));
+            self.add_billboard(batch, model_view_projection, screen_size, pos, sprite, 1.0);
+        }
+    }
+
+    /// Adds the things that can be shot to `batch`, lit like pickups, or white for a moment after
+    /// they've been hit.
+    fn draw_targets(
+        &self,
+        batch: &mut SpriteBatch,
+        model_view_projection: Matrix4<f32>,
+        screen_size: (f32, f32),
+    ) {
+        let eye = self.eye();
+        let tiles = self.world.tiles();
+        let positions = self.ecs.read_storage::<PositionComponent>();
+        let colliders = self.ecs.read_storage::<ColliderComponent>();
+        let flashes = self.ecs.read_storage::<HitFlashComponent>();
+
+        for (pos, collider, flash) in (&positions, &colliders, flashes.maybe()).join() {
+            let pos = pos.value;
+            if !line_of_sight(tiles.view(), eye, pos).clear {
+                continue;
+            }
+
+            let color = match flash {
+                Some(flash) if flash.is_flashing(self.current_tic) => Color::WHITE,
+                _ => {
+                    let illumination = try_get_tile_at(pos, tiles)
+                        .map_or(Color::BLACK, |tile| tile.illumination_color);
+                    shade(Color::new(0.8, 0.3, 0.25, 1.0), illumination)
+                }
+            };
+            let size = collider.radius * 2.0;
+            self.add_billboard(
+                batch,
+                model_view_projection,
+                screen_size,
+                pos,
+                (0x09, color),
+                size,
+            );
+        }
+    }
+
+    /// Adds the floating texts to `batch`, with each character drawn at its own place in the world
+    /// along a line across the view.
+    fn draw_floating_texts(
+        &self,
+        batch: &mut SpriteBatch,
+        model_view_projection: Matrix4<f32>,
+        rotation: Rotation3<f32>,
+        screen_size: (f32, f32),
+    ) {
+        // To the right on the screen, which there isn't a way to when looking straight up or down
+        let right = rotation
+            .transform_vector(&Vector3::z())
+            .cross(&Vector3::y());
+        let right = match right.try_normalize(1.0e-3) {
+            Some(right) => right * CHAR_SPACING,
+            None => return,
+        };
+
+        for text in self.floating_texts.iter() {
+            let voxels = KataText::from_str(&text.text).voxels;
+            let start = text.pos_at(self.current_tic) - right * (voxels.len() as f32 - 1.0) / 2.0;
+            let alpha = text.alpha_at(self.current_tic);
+
+            for (i, voxel) in voxels.iter().enumerate() {
+                let mut color: Color = voxel.foreground.into();
+                color.a = alpha;
+                let pos = start + right * i as f32;
+                let sprite = (voxel.char_offset, color);
+                self.add_billboard(
+                    batch,
+                    model_view_projection,
+                    screen_size,
+                    pos,
+                    sprite,
+                    CHAR_SIZE,
+                );
+            }
+        }
+    }
+
+    /// The player's view rotation and the matrix the world is drawn with.
+    fn camera(&self) -> (Rotation3<f32>, Matrix4<f32>) {
+        // Our object is translated along the x axis.
+        let model = Isometry3::new(Vector3::x(), na::zero());
+
+        // Our camera looks toward the point (1.0, 0.0, 0.0).
+        // It is located at (0.0, 0.0, 1.0).
+        let eye = self.eye();
+
+        let facing = self.player.entity.facing + self.shake.offset();
+        let rotation = Rotation3::from_euler_angles(facing.y, facing.x, 0.0);
+
+        let rotation_offset = rotation.transform_point(&Point3::new(0.0, 0.0, 1.0));
+
+        let target = eye + rotation_offset.coords;
+        // let target = Point3::new(0.0, 0.0, 0.0);
+        let view_projection =
+            rendering::util::view_projection(&eye, &target, self.settings.aspect_ratio());
+
+        // Combine everything.
+        (rotation, view_projection * model.to_homogeneous())
+    }
+
+    /// The lines of the metrics HUD: the frame rate settings, then the timings in `report`.
+    fn hud_lines(&self, report: &MetricsReport) -> Vec<String> {
+        let strings = &self.ui_context.strings;
+        let vsync = if self.settings.vsync {
+            tr!(strings, "hud.on")
+        } else {
+            tr!(strings, "hud.off")
+        };
+        let max_fps = self
+            .settings
+            .max_fps
+            .map_or_else(|| tr!(strings, "hud.unlimited"), |fps| fps.to_string());
+
+        let mut lines = vec![tr!(strings, "hud.frame_limits", vsync, max_fps)];
+        lines.extend(report.lines());
+        lines
+    }
+
+    /// Writes the model last exported from the editor into the world where the player is aiming.
+    fn place_stamp(&mut self) -> Fallible<()> {
+        let stamp: TileStamp = serde_json::from_reader(BufReader::new(File::open(STAMP_PATH)?))?;
+        let written = stamp::apply(&mut self.world, self.aim_point(), &stamp);
+        info!("Stamped {} tiles", written);
+
+        Ok(())
+    }
+
+    /// Puts the weapon with the next model in the player's hand, or the default one if they
+    /// aren't holding a weapon.
+    fn cycle_weapon(&mut self) {
+        let current = match &self.player.equipped_item {
+            Item::Weapon { name, .. } => Some(&**name),
+            _ => None,
+        };
+
+        let weapon = self
+            .weapons
+            .next(current)
+            .and_then(|name| Some((name, Item::weapon(&self.weapons, name)?)));
+
+        match weapon {
+            Some((name, weapon)) => {
+                info!("Holding {}", &**name);
+                self.player.equipped_item = weapon;
+            }
+            None => warn!("There are no weapons in {}", WEAPONS_PATH),
+        }
+    }
+
+    /// Grabs the mouse and moves it to the middle of the window, so that looking around starts
+    /// from there.
+    fn capture_mouse(&mut self, ctx: &mut Context) {
+        if let Err(e) = mouse::set_cursor_grabbed(ctx, true) {
+            warn!("Failed to grab the mouse: {}", e);
+        }
+        let (screen_width, screen_height) = graphics::drawable_size(ctx);
+        self.mouse_recenter_failed =
+            mouse::set_position(ctx, [screen_width / 2.0, screen_height / 2.0]).is_err();
+    }
+
+    /// Opens the menu that goes with the current state, if it has one.
+    fn open_menu(&mut self) {
+        let strings = &self.ui_context.strings;
+
+        match self.state {
+            GameState::MainMenu => {
+                let can_continue = self.input.is_live() && self.save_path.is_file();
+                self.menu.open_main(strings, can_continue);
+            }
+            GameState::Paused => self.menu.open_pause(strings),
+            GameState::Finished => {
+                if let GameObjective::Escaped(results) = &self.run.objective {
+                    self.menu.open_results(strings, &results.lines(strings));
+                }
+            }
+            GameState::Playing | GameState::Console => self.menu.close(),
+        }
+    }
+
+    fn play(&mut self, ctx: &mut Context) {
+        self.state = GameState::Playing;
+        self.menu.close();
+        self.capture_mouse(ctx);
+    }
+
+    /// Stops the world where it is and lets go of the mouse.
+    fn pause(&mut self, ctx: &mut Context) {
+        self.state = GameState::Paused;
+        self.open_menu();
+        release_mouse(ctx);
+    }
+
+    /// Stops the world for good and shows how the run went.
+    fn finish(&mut self, ctx: &mut Context) {
+        self.state = GameState::Finished;
+        self.open_menu();
+        release_mouse(ctx);
+    }
+
+    /// Stops the world like pausing does, but to type into the console instead.
+    fn open_console(&mut self, ctx: &mut Context) {
+        self.state = GameState::Console;
+        release_mouse(ctx);
+    }
+
+    /// Runs the line entered into the console, if there is one.
+    fn run_console(&mut self) {
+        let mut settings = self.settings.clone();
+        let mut game = GameCtx {
+            world: &mut self.world,
+            player: &mut self.player,
+            settings: &mut settings,
+            ecs: &mut self.ecs,
+            weapons: &self.weapons,
+            seed: self.seed,
+            relight: false,
+        };
+
+        self.console.run_submitted(&mut game);
+
+        if game.relight {
+            self.lighting_cache.nuke();
+        }
+        if settings != self.settings {
+            self.use_settings(settings);
+        }
+    }
+
+    fn choose(&mut self, ctx: &mut Context, choice: MenuChoice) {
+        match choice {
+            // The world made at the start hasn't been played in yet
+            MenuChoice::NewWorld | MenuChoice::Resume => self.play(ctx),
+
+            MenuChoice::Continue => match self.quickload(ctx) {
+                Ok(()) => self.play(ctx),
+                Err(e) => warn!("Failed to load, staying on the menu: {}", e),
+            },
+
+            MenuChoice::Settings => self
+                .menu
+                .open_settings(&self.ui_context.strings, &self.settings),
+
+            MenuChoice::SaveSettings => {
+                self.apply_settings();
+                self.open_menu();
+            }
+
+            MenuChoice::Back => self.open_menu(),
+
+            MenuChoice::Quit => event::quit(ctx),
+        }
+    }
+
+    /// Uses what was picked on the settings screen from now on, and writes it to the settings
+    /// file. Only what the screen changes is written, so that command line options aren't kept.
+    fn apply_settings(&mut self) {
+        self.use_settings(self.menu.apply_settings(&self.settings));
+
+        let result = Settings::load_or_create(SETTINGS_PATH)
+            .and_then(|saved| write_json(SETTINGS_PATH, &self.menu.apply_settings(&saved)));
+        match result {
+            Ok(()) => info!("Saved the settings to {}", SETTINGS_PATH),
+            Err(e) => warn!("Failed to save the settings: {}", e),
+        }
+    }
+
+    /// Switches to `settings`, redoing whatever was worked out from the old ones.
+    fn use_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+        self.frame_limiter = FrameLimiter::new(self.settings.frame_budget(), Instant::now());
+        self.fov_cache = FovCache::default();
+        self.lighting_cache.nuke();
+    }
+
+    /// The whole window, in cells of the HUD's font.
+    fn menu_rect(&self, ctx: &Context) -> IRect {
+        let (screen_width, screen_height) = graphics::drawable_size(ctx);
+        IRect::new(
+            0,
+            0,
+            ((screen_width / self.ui_context.batch.tile_width()) as u32).max(1),
+            ((screen_height / self.ui_context.batch.tile_height()) as u32).max(1),
+        )
+    }
+
+    /// The top half of the window, where the console goes.
+    fn console_rect(&self, ctx: &Context) -> IRect {
+        let rect = self.menu_rect(ctx);
+        IRect::new(rect.x, rect.y, rect.w, (rect.h / 2).max(1))
+    }
+
+    /// Passes a mouse event at the mouse's position to the menu.
+    fn menu_mouse_event(&mut self, ctx: &Context, e: ui::MouseEvent) {
+        let event = ui::Event::Mouse {
+            pos: self.ui_context.mouse_pos(ctx),
+            e,
+        };
+        let bounds = self.menu_rect(ctx);
+        self.menu.handle_event(&mut self.ui_context, event, bounds);
+    }
+
+    /// Draws the explored cells around the player in the top right corner of the window.
+    fn draw_minimap(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let center = world_pos_to_index(self.player.entity.pos);
+        let map = minimap(
+            self.world.tiles().view(),
+            &self.explored,
+            [center.x, center.y, center.z],
+            MINIMAP_RADIUS,
+        );
+
+        let (screen_width, _) = graphics::drawable_size(ctx);
+        let columns = (screen_width / self.minimap_batch.tile_width()) as u32;
+        let side = map.dim().0 as u32;
+
+        self.minimap_batch.clear();
+        draw_minimap(
+            &mut self.minimap_batch,
+            map.view(),
+            &player_arrow(self.player.entity.facing.x),
+            [columns.saturating_sub(side + 1), 1].into(),
+        );
+
+        ggez::graphics::draw(ctx, &self.minimap_batch, DrawParam::default())
+    }
+
+    /// Draws the HUD, if it's shown, and the menu, if one is open.
+    fn draw_ui(&mut self, ctx: &mut Context) -> GameResult<()> {
+        self.ui_context.batch.clear();
+        self.ui_context.frame += 1;
+
+        if self.show_metrics {
+            if let Some(report) = self.metrics.last_report() {
+                for (y, line) in self.hud_lines(report).iter().enumerate() {
+                    for (x, voxel) in KataText::from_str(line).voxels.iter().enumerate() {
+                        self.ui_context.batch.add(voxel, [x as u32, y as u32]);
+                    }
+                }
+            }
+        }
+
+        // Along the bottom of the window, where the console and the minimap won't cover them
+        let bounds = self.menu_rect(ctx);
+        draw_status_icons(
+            &mut self.ui_context.batch,
+            &self.player.status,
+            [1, bounds.h.saturating_sub(2)].into(),
+        );
+        // Over the status icons, so the newest is nearest to them
+        draw_toasts(
+            &mut self.ui_context.batch,
+            &self.toasts,
+            [1, bounds.h.saturating_sub(3 + self.toasts.len() as u32)].into(),
+        );
+
+        // Along the top, in the middle
+        if let Some(objective) = self.run.hud_text(&self.ui_context.strings) {
+            let text = KataText::from_str(&objective);
+            let x = bounds.w.saturating_sub(text.voxels.len() as u32) / 2;
+            for (i, voxel) in text.voxels.iter().enumerate() {
+                self.ui_context.batch.add(voxel, [x + i as u32, 0]);
+            }
+        }
+
+        if self.state == GameState::Console {
+            let console_rect = self.console_rect(ctx);
+            self.console
+                .layout(BoxConstraints::exact(console_rect.size()));
+            let _ = self.console.handle_event(
+                &mut self.ui_context,
+                ui::Event::Draw,
+                console_rect.into(),
+            );
+        }
+
+        self.menu
+            .handle_event(&mut self.ui_context, ui::Event::Draw, bounds);
+        self.ui_context.draw_overlays(bounds);
+
+        ggez::graphics::draw(ctx, &self.ui_context.batch, DrawParam::default())
+    }
 }
 
 impl EventHandler<ggez::GameError> for Katakomb {
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        keymods: KeyMods,
+        _repeat: bool,
+    ) {
+        // Everything but closing it is typed into the console while it's open
+        if self.state == GameState::Console {
+            match keycode {
+                KeyCode::Escape | KeyCode::Grave => self.play(ctx),
+                _ => {
+                    let event = ui::Event::KeyDown {
+                        keycode,
+                        mods: keymods,
+                    };
+                    let bounds = self.console_rect(ctx);
+                    let _ = self
+                        .console
+                        .handle_event(&mut self.ui_context, event, bounds.into());
+                    self.run_console();
+                }
+            }
+
+            return;
+        }
+
+        match keycode {
+            KeyCode::Escape => match self.state {
+                GameState::Playing => self.pause(ctx),
+                _ if self.menu.is_settings_open() => self.open_menu(),
+                GameState::Paused | GameState::Console => self.play(ctx),
+                GameState::MainMenu | GameState::Finished => event::quit(ctx),
+            },
+
+            KeyCode::F3 => self.show_metrics = !self.show_metrics,
+            KeyCode::M => self.show_minimap = !self.show_minimap,
+
+            // Everything else changes the world, which stays as it is outside of the game
+            _ if self.state != GameState::Playing => {}
+
+            KeyCode::Grave if !self.input.is_live() => {
+                warn!("The console can't be used while recording or replaying");
+            }
+            KeyCode::Grave => self.open_console(ctx),
+
+            // Debug key for trying out models from the editor in the world
+            KeyCode::P if !self.input.is_live() => {
+                warn!("Stamps can't be placed while recording or replaying");
+            }
+            KeyCode::P => {
+                if let Err(e) = self.place_stamp() {
+                    warn!("Failed to place stamp: {}", e);
+                }
+            }
+
+            // Debug key for trying out the weapon models from the editor
+            KeyCode::G if !self.input.is_live() => {
+                warn!("Weapons can't be switched while recording or replaying");
+            }
+            KeyCode::G => self.cycle_weapon(),
+
+            // Debug key for looking at the world in MagicaVoxel
+            KeyCode::V => {
+                let colors = vox::tile_colors(self.world.tiles());
+                match vox::export(colors.view(), vox::CHUNK_EXPORT_PATH) {
+                    Ok(()) => info!("Exported the world to {}", vox::CHUNK_EXPORT_PATH),
+                    Err(e) => warn!("Failed to export the world: {}", e),
+                }
+            }
+
+            KeyCode::F5 => {
+                if let Err(e) = self.quicksave() {
+                    warn!("Failed to save: {}", e);
+                }
+            }
+
+            KeyCode::F9 if !self.input.is_live() => {
+                warn!("Saves can't be loaded while recording or replaying");
+            }
+            KeyCode::F9 => {
+                if let Err(e) = self.quickload(ctx) {
+                    warn!("Failed to load, carrying on: {}", e);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        // The key that opens the console comes through as text as well
+        if self.state == GameState::Console && character != '`' {
+            let bounds = self.console_rect(ctx);
+            let _ = self.console.handle_event(
+                &mut self.ui_context,
+                ui::Event::Text { ch: character },
+                bounds.into(),
+            );
+        }
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: mouse::MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
+        if self.menu.is_open() {
+            self.menu_mouse_event(ctx, ui::MouseEvent::ButtonDown { button });
+        }
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: mouse::MouseButton,
+        _x: f32,
+        _y: f32,
+    ) {
+        if self.menu.is_open() {
+            self.menu_mouse_event(ctx, ui::MouseEvent::ButtonUp { button });
+        }
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, _x: f32, _y: f32, _dx: f32, _dy: f32) {
+        if self.menu.is_open() {
+            self.menu_mouse_event(ctx, ui::MouseEvent::Move);
+        }
+    }
+
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        let start_t = Instant::now();
+        if let Some(choice) = self.menu.take_choice() {
+            self.choose(ctx, choice);
+        }
+
+        // Nothing moves outside of the game, not even the input of a replay
+        if self.state != GameState::Playing {
+            if let Some(wait) = self.frame_limiter.wait(Instant::now()) {
+                thread::sleep(wait);
+            }
+
+            return Ok(());
+        }
 
         // Update code here...
         // self.physics_system.run_now(&self.ecs_world);
@@ -544,143 +1707,96 @@ impl EventHandler<ggez::GameError> for Katakomb {
 
         // let old_mouse_pos = self.mouse_pos;
         // self.mouse_pos = mouse::position(ctx);
-        mouse::set_position(ctx, screen_center).unwrap();
+        // Some platforms refuse while the window doesn't have focus, so just skip a frame
+        match mouse::set_position(ctx, screen_center) {
+            Ok(()) => self.mouse_recenter_failed = false,
+            Err(e) => {
+                if !self.mouse_recenter_failed {
+                    warn!("Failed to recenter the mouse: {}", e);
+                }
+                self.mouse_recenter_failed = true;
+            }
+        }
 
         // let mouse_delta: Point2<f32> =
         //     Point2::new(old_mouse_pos.x - self.mouse_pos.x, old_mouse_pos.y - self.mouse_pos.y).into();
 
         // self.mouse_pos = mouse::position(ctx);
 
-        let mouse_delta = mouse::delta(&ctx);
+        let input = self.input.next_frame(ctx);
 
         let mut light_sources = Vec::new();
 
-        self.player.entity.facing = self.player.entity.facing
-            + Vector2::new(mouse_delta.x * -0.0025, mouse_delta.y * 0.0025);
-
         let mut muzzle_flash = false;
 
         let update_time = timer::duration_to_f64(timer::time_since_start(ctx));
 
-        self.player.equipped_item.update(
-            world_pos_to_index(self.player.entity.pos),
+        let events = self.player.step(
+            self.world.tiles(),
+            &input,
+            &self.settings.input,
             &mut light_sources,
         );
-        self.player.update_equipped();
 
-        let movement_rotation =
-            Rotation3::from_axis_angle(&Vector3::y_axis(), self.player.entity.facing.x);
+        // Only the view shakes, so it doesn't matter that it's different when replayed
+        let config = &self.settings.player;
+        if events.fired {
+            self.shake.fire(config, &mut thread_rng());
 
-        if mouse::button_pressed(ctx, mouse::MouseButton::Left) {
-            self.player.equipped_item.primary_use(
-                world_pos_to_index(self.player.entity.pos),
-                &mut light_sources,
-            );
+            if let Some(audio) = &self.audio {
+                if let Err(e) = audio.play_gunshot(&events.echoes, MAX_SOUND_RANGE) {
+                    warn!("Failed to play a gunshot: {}", e);
+                }
+            }
         }
-
-        // if keyboard::is_key_pressed(ctx, KeyCode::Left) {
-        //     self.player.facing.x += 0.025;
-        // }
-        // if keyboard::is_key_pressed(ctx, KeyCode::Right) {
-        //     self.player.facing.x -= 0.025;
-        // }
-        // if keyboard::is_key_pressed(ctx, KeyCode::Up) {
-        //     self.player.facing.y -= 0.025;
-        // }
-        // if keyboard::is_key_pressed(ctx, KeyCode::Down) {
-        //     self.player.facing.y += 0.025;
-        // }
-
-        if mouse::button_pressed(ctx, mouse::MouseButton::Right) {
-            self.player.equipped_item.secondary_use(
-                world_pos_to_index(self.player.entity.pos),
-                &mut light_sources,
-            );
+        if let Some(height) = events.landed {
+            self.shake.land(config, height);
         }
+        self.shake.step(config, &mut thread_rng());
+        self.run.update(timer::delta(ctx), events.fired);
 
-        if keyboard::is_key_pressed(ctx, KeyCode::A) {
-            self.player.entity.vel.x += 0.01;
-        }
-        if keyboard::is_key_pressed(ctx, KeyCode::D) {
-            self.player.entity.vel.x -= 0.01;
-        }
-        if keyboard::is_key_pressed(ctx, KeyCode::W) {
-            self.player.entity.vel.z += 0.01;
+        // Where everything is now, for shots and pickups to find
+        self.spatial_hash.run_now(&self.ecs);
+        if events.fired {
+            self.shoot();
         }
-        if keyboard::is_key_pressed(ctx, KeyCode::S) {
-            self.player.entity.vel.z -= 0.01;
-        }
-
-        if get_tile_at(
-            self.player.entity.pos + Point3::new(0.0f32, -0.1f32, 0.0f32).coords,
-            &self.tile_array,
-        )
-        .tile_type
-        .collides()
-        {
-            if keyboard::is_key_pressed(ctx, KeyCode::Space) {
-                self.player.entity.vel.y += 0.3;
+        self.floating_texts.expire(self.current_tic);
+        self.occlusion.expire(self.current_tic);
+
+        // Opening a door marks it dirty, which relights the world below
+        if events.interacted {
+            if let Some(target) = self.interact_target() {
+                if !self.run.pick_up(&mut self.world, target) {
+                    self.world.interact(target);
+                }
             }
-        } else {
-            self.player.entity.vel.y -= 0.01;
-        }
-        if keyboard::is_key_pressed(ctx, KeyCode::LControl) {
-            self.player.crouching = true;
-        } else {
-            self.player.crouching = false;
         }
 
-        if get_tile_at(
-            self.player.entity.pos + Point3::new(self.player.entity.vel.x, 0.0f32, 0.0f32).coords,
-            &self.tile_array,
-        )
-        .tile_type
-        .collides()
-        {
-            self.player.entity.vel.x = 0.0;
-        }
-        if get_tile_at(
-            self.player.entity.pos + Point3::new(0.0f32, self.player.entity.vel.y, 0.0f32).coords,
-            &self.tile_array,
-        )
-        .tile_type
-        .collides()
-        {
-            self.player.entity.vel.y = 0.0;
-        }
-        if get_tile_at(
-            self.player.entity.pos + Point3::new(0.0f32, 0.0f32, self.player.entity.vel.z).coords,
-            &self.tile_array,
-        )
-        .tile_type
-        .collides()
-        {
-            self.player.entity.vel.z = 0.0;
-        }
+        self.collect_pickups();
 
-        let vel_normalised = Unit::new_and_get(self.player.entity.vel);
-        if vel_normalised.1 > 1.0 {
-            self.player.entity.vel = vel_normalised.0.into_inner();
+        if self
+            .run
+            .try_extract(&self.world, self.player.entity.pos, &self.explored)
+        {
+            self.finish(ctx);
         }
 
-        let movement_offset = movement_rotation.transform_vector(&self.player.entity.vel);
-
-        let new_pos = self.player.entity.pos + movement_offset;
-
-        if !get_tile_at(new_pos, &self.tile_array).tile_type.collides() {
-            self.player.entity.pos = new_pos;
+        if input.held(Key::NukeLighting) {
+            self.lighting_cache.nuke();
         }
 
-        self.player.entity.vel *= 0.9;
-
-        if keyboard::is_key_pressed(ctx, KeyCode::N) {
-            self.nuke_lighting = true;
+        // Tiles changing can block or let through light from anywhere, so it's all lit again
+        if !self
+            .world
+            .take_dirty_regions(self.lighting_dirty)
+            .is_empty()
+        {
+            self.lighting_cache.nuke();
         }
 
         self.draw_tiles.clear();
 
         //let tile_points = self.tile_draw_points;
-        let camera_pos = self.player.entity.pos;
 
         // let tile_array = &self.tile_array;
         // let zip_iter = ndarray::Zip::indexed(tile_array);
@@ -691,16 +1807,12 @@ impl EventHandler<ggez::GameError> for Katakomb {
         //     camera_pos.z.floor() as i32,
         // );
 
-        let usize_camera_pos = Point3::new(
-            camera_pos.x.floor() as usize,
-            camera_pos.y.floor() as usize + 1,
-            camera_pos.z.floor() as usize,
-        );
+        let usize_camera_pos = world_pos_to_index(self.eye());
 
         // light_sources.push((usize_camera_pos, Color::GREEN));
 
         if muzzle_flash {
-            light_sources.push((usize_camera_pos, Color::YELLOW));
+            light_sources.push((usize_camera_pos, rendering::color::WARM_LIGHT.into()));
         }
 
         // light_sources.extend(
@@ -718,98 +1830,63 @@ impl EventHandler<ggez::GameError> for Katakomb {
         //     ].iter()
         // );
 
-        light_sources.extend(self.lights.iter().cloned());
-
-        //TODO: remove the necessity for this by having each light keep track of affected tiles
-        //and have light add/remove illumination as necessary
-        self.tile_array
-            .par_iter_mut()
-            .for_each(|tile| tile.illumination_color = Color::BLACK);
-
-        for light in light_sources.iter() {
-            let light_pos: &Point3<usize> = &light.0.into();
-            let light_color = light.1;
-
-            if is_in_array(self.tile_array.view(), world_pos_to_index(camera_pos)) {
-                let mut octs =
-                    split_shadowcast_octants(self.tile_array.view_mut(), *light_pos, LIGHT_RANGE);
-
-                octs.iter_mut().for_each(|o| {
-                    shadowcast_octant(
-                        o.0.view_mut(),
-                        o.1,
-                        LIGHT_RANGE,
-                        LightShape::Sphere,
-                        Point3::new(light.0.x as f32, light.0.y as f32, light.0.z as f32),
-                        |t, (x, y, z)| {
-                            t.illumination_color = combine_light_colors(
-                                scale_color(
-                                    light_color,
-                                    1.0 - (EUCLIDEAN_DISTANCE_LOOKUP[[x, y, z]]
-                                        / LIGHT_RANGE as f32)
-                                        .min(1.0),
-                                ),
-                                t.illumination_color,
-                            );
-                        },
-                    )
-                });
-            }
+        // Static lights sealed off from the player can't light anything they could see
+        self.light_regions.refresh(&mut self.world);
+        let (static_lights, sealed_lights) = self
+            .light_regions
+            .visible_lights(self.world.lights(), usize_camera_pos);
+        light_sources.extend(static_lights);
+        for (_, color) in &mut light_sources {
+            *color = self
+                .palette_remaps
+                .remap(self.settings.palette_mode, *color);
         }
-        self.draw_tiles.clear();
 
-        let dt = &mut self.draw_tiles;
+        let lighting_start = Instant::now();
 
-        let mut fov_octs = split_shadowcast_octants(
-            self.tile_array.view_mut(),
-            usize_camera_pos,
-            PLAYER_SIGHT_RANGE,
+        self.lighting_cache.relight(
+            self.world.tiles_mut(),
+            &light_sources,
+            self.settings.light_range,
+            &mut self.shadowcast_scratch,
         );
 
-        let player_rotation = Rotation3::from_euler_angles(
-            self.player.entity.facing.y,
-            self.player.entity.facing.x,
-            0.0,
-        );
+        self.metrics
+            .record(Phase::Lighting, lighting_start.elapsed());
+
+        let fov_start = Instant::now();
+
+        self.draw_tiles.clear();
 
-        let fov_facing = Unit::new_normalize(
-            player_rotation
-                .transform_point(&Point3::new(0.0, 0.0, 1.0))
-                .coords,
+        let frustum = Frustum::from_mvp(self.camera().1);
+
+        // Only cast again if the player has moved to another cell or the world has changed
+        let visible = self.fov_cache.visible(
+            &mut self.world,
+            usize_camera_pos,
+            self.settings.sight_range,
+            &mut self.shadowcast_scratch,
         );
+        self.explored.mark_visible(visible);
+        let tiles = self.world.tiles();
 
-        fov_octs.iter_mut().for_each(|o| {
-            shadowcast_octant(
-                o.0.view_mut(),
-                o.1,
-                PLAYER_SIGHT_RANGE,
-                LightShape::Sphere,
-                // LightShape::Cone {
-                //    facing: fov_facing,
-                //    width_angle: FRAC_PI_4,
-                // },
-                camera_pos,
-                |t, (x, y, z)| {
-                    if !t.tile_type.is_transparent() && t.illuminated() {
-                        dt.insert(DrawTile {
-                            tile: t.clone(),
-                            dist_from_eye: EUCLIDEAN_DISTANCE_LOOKUP[[x, y, z]],
-                        });
-                    }
-                },
-            )
-        });
+        // Glyphs are drawn centered on the tile's position and can spill past it
+        self.draw_tiles.extend(visible.iter().filter(|visible| {
+            let t = &tiles[visible.index()];
 
-        let frame_time = Instant::now().duration_since(start_t).as_micros() as f64 / 1000.0;
+            !t.tile_type.is_transparent()
+                && t.illuminated()
+                && frustum.intersects_sphere(t.pos, 1.0)
+        }));
 
-        println!("Draw tiles len: {}", self.draw_tiles.len());
-        println!("Light sources len: {}", light_sources.len());
-        println!("Frame time: {} ms", frame_time);
-        println!("FPS: {}", 1000.0 / frame_time);
+        self.metrics.record(Phase::Fov, fov_start.elapsed());
+        self.metrics
+            .set_counts(self.draw_tiles.len(), light_sources.len(), sealed_lights);
+        self.metrics.end_frame(Instant::now());
 
         // self.draw_tiles.sort_unstable_by(|a, b| {
-        //     euclidean_distance_squared(b.pos, camera_pos)
-        //         .partial_cmp(&euclidean_distance_squared(a.pos, camera_pos))
+        //     distance_squared(b.pos, camera_pos)
+        //         .partial_cmp(&distance_squared(a.pos, camera_pos))
         //         .unwrap_or(Ordering::Equal)
         // });
 
@@ -822,56 +1899,39 @@ impl EventHandler<ggez::GameError> for Katakomb {
 
         self.current_tic += 1;
 
+        if let Some(wait) = self.frame_limiter.wait(Instant::now()) {
+            thread::sleep(wait);
+        }
+
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx, graphics::Color::BLACK);
-
-        // Our object is translated along the x axis.
-        let model = Isometry3::new(Vector3::x(), na::zero());
-
-        // Our camera looks toward the point (1.0, 0.0, 0.0).
-        // It is located at (0.0, 0.0, 1.0).
-        let eye = self.player.entity.pos + Point3::new(0.0, 1.0, 0.0).coords; //Point3::new(0.0, 0.0, 1.0);
-
-        let rotation = Rotation3::from_euler_angles(
-            self.player.entity.facing.y,
-            self.player.entity.facing.x,
-            0.0,
-        );
+        let draw_start = Instant::now();
 
-        let rotation_offset = rotation.transform_point(&Point3::new(0.0, 0.0, 1.0));
-
-        let target = Point3::new(
-            self.player.entity.pos.x + rotation_offset.x,
-            self.player.entity.pos.y + rotation_offset.y + 1.0,
-            self.player.entity.pos.z + rotation_offset.z,
-        );
-        // let target = Point3::new(0.0, 0.0, 0.0);
-        let view = Isometry3::look_at_rh(&eye, &target, &Vector3::y());
-
-        // A perspective projection.
-        let projection = Perspective3::new(16.0 / 9.0, 3.14 / 2.0, 1.0, 1000.0);
+        graphics::clear(ctx, graphics::Color::BLACK);
 
-        // The combination of the model with the view is still an isometry.
-        let model_view = view * model;
+        if self.state == GameState::MainMenu {
+            self.draw_ui(ctx)?;
+            return graphics::present(ctx);
+        }
 
-        // Convert everything to a `Matrix4` so that they can be combined.
-        let mat_model_view = model_view.to_homogeneous();
-
-        // Combine everything.
-        let model_view_projection = projection.as_matrix() * mat_model_view;
+        let (rotation, model_view_projection) = self.camera();
+        let (screen_width, screen_height) =
+            (self.settings.window_width, self.settings.window_height);
 
         let mut sprite_batch = SpriteBatch::new(self.font.texture().clone());
 
-        for tile in self.draw_tiles.iter() {
-            let tile = &tile.tile;
+        for draw_tile in self.draw_tiles.iter() {
+            let tile = &self.world.tiles()[draw_tile.index()];
             if let Some(screen_pos) =
                 Point3::from_homogeneous(model_view_projection * tile.pos.to_homogeneous())
             {
                 if screen_pos.z >= -1.0 && screen_pos.z <= 1.0 {
-                    let tile_color = tile.tile_type.get_color();
+                    let palette_mode = self.settings.palette_mode;
+                    let tile_color = self
+                        .palette_remaps
+                        .remap(palette_mode, tile.tile_type.get_color());
                     let illumination_color = tile.illumination_color;
                     // let color = tile.illumination_color;
                     let color = average_colors(tile_color, illumination_color);
@@ -879,11 +1939,11 @@ impl EventHandler<ggez::GameError> for Katakomb {
                     // tile.illumination;
                     // let color_darkness =
                     //     (1.0 - screen_pos.z.min(1.0).max(0.0)) * 0.25 + tile.illumination * 0.75;
-                    let color_back_darkness = color_darkness * 0.75;
+                    let color_back_darkness = color_darkness * palette_mode.backing_brightness();
 
                     let screen_dest = [
-                        screen_pos.x * WINDOW_WIDTH / 2.0 + WINDOW_WIDTH / 2.0,
-                        -screen_pos.y * WINDOW_HEIGHT / 2.0 + WINDOW_HEIGHT / 2.0, //We need to negate this, as 2d screen space is inverse of normalised device coords
+                        screen_pos.x * screen_width / 2.0 + screen_width / 2.0,
+                        -screen_pos.y * screen_height / 2.0 + screen_height / 2.0, //We need to negate this, as 2d screen space is inverse of normalised device coords
                     ];
 
                     let color_value = 1.0; //color_value(&color).sqrt();
@@ -894,8 +1954,8 @@ impl EventHandler<ggez::GameError> for Katakomb {
                                 .src(self.font.get_src_rect(0x2CF))
                                 .dest(screen_dest)
                                 .scale([
-                                    (1.0 - screen_pos.z) * PI * 10.0,
-                                    (1.0 - screen_pos.z) * PI * 10.0,
+                                    depth_scale(screen_pos.z) * PI * 10.0,
+                                    depth_scale(screen_pos.z) * PI * 10.0,
                                 ])
                                 .color(graphics::Color {
                                     r: color.r * color_back_darkness,
@@ -912,8 +1972,8 @@ impl EventHandler<ggez::GameError> for Katakomb {
                             .src(tile.tile_type.get_char_offset(&self.font))
                             .dest(screen_dest)
                             .scale([
-                                (1.0 - screen_pos.z) * PI * 10.0,
-                                (1.0 - screen_pos.z) * PI * 10.0,
+                                depth_scale(screen_pos.z) * PI * 10.0,
+                                depth_scale(screen_pos.z) * PI * 10.0,
                             ])
                             .color(graphics::Color {
                                 r: color.r * color_darkness,
@@ -923,9 +1983,49 @@ impl EventHandler<ggez::GameError> for Katakomb {
                             })
                             .offset([0.5, 0.5]), // ..DrawParam::default()
                     );
+
+                    let decal = self
+                        .world
+                        .metadata(draw_tile.cell.map(i32::from))
+                        .and_then(|metadata| metadata.decal);
+                    if let Some(decal) = decal {
+                        let decal_darkness = color_darkness * DECAL_SHADE;
+                        sprite_batch.add(
+                            DrawParam::new()
+                                .src(self.font.get_src_rect(decal.glyph()))
+                                .dest(screen_dest)
+                                .scale([
+                                    depth_scale(screen_pos.z) * PI * 10.0,
+                                    depth_scale(screen_pos.z) * PI * 10.0,
+                                ])
+                                .color(graphics::Color {
+                                    r: color.r * decal_darkness,
+                                    g: color.g * decal_darkness,
+                                    b: color.b * decal_darkness,
+                                    a: color_value,
+                                })
+                                .offset([0.5, 0.5]),
+                        );
+                    }
                 }
             }
         }
+        self.draw_pickups(
+            &mut sprite_batch,
+            model_view_projection,
+            (screen_width, screen_height),
+        );
+        self.draw_targets(
+            &mut sprite_batch,
+            model_view_projection,
+            (screen_width, screen_height),
+        );
+        self.draw_floating_texts(
+            &mut sprite_batch,
+            model_view_projection,
+            rotation,
+            (screen_width, screen_height),
+        );
         ggez::graphics::draw(ctx, &sprite_batch, DrawParam::default())?;
 
         let mut item_sprite_batch = SpriteBatch::new(self.font.texture().clone());
@@ -935,374 +2035,38 @@ impl EventHandler<ggez::GameError> for Katakomb {
             model_view_projection,
             rotation,
             &mut item_sprite_batch,
+            (screen_width, screen_height),
         );
 
         ggez::graphics::draw(ctx, &item_sprite_batch, DrawParam::default())?;
 
-        graphics::present(ctx)
-    }
-}
-
-struct DrawTile {
-    tile: Tile,
-    dist_from_eye: f32,
-}
-
-impl Eq for DrawTile {}
-
-impl PartialEq for DrawTile {
-    fn eq(&self, other: &Self) -> bool {
-        self.dist_from_eye == other.dist_from_eye && self.tile.pos == other.tile.pos
-    }
-}
-
-impl PartialOrd for DrawTile {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl Ord for DrawTile {
-    fn cmp(&self, other: &Self) -> Ordering {
-        FloatOrd(self.dist_from_eye)
-            .cmp(&FloatOrd(other.dist_from_eye))
-            .then_with(|| FloatOrd(self.tile.pos.x).cmp(&FloatOrd(other.tile.pos.x)))
-            .then_with(|| FloatOrd(self.tile.pos.y).cmp(&FloatOrd(other.tile.pos.y)))
-            .then_with(|| FloatOrd(self.tile.pos.z).cmp(&FloatOrd(other.tile.pos.z)))
-            .reverse()
-    }
-}
-
-fn shadowcast_octant<F>(
-    mut slice: ArrayViewMut3<Tile>,
-    (x_sign, y_sign, z_sign): (bool, bool, bool),
-    cast_range: usize,
-    shape: LightShape,
-    source_pos: Point3<f32>,
-    mut f: F,
-) where
-    F: FnMut(&mut Tile, (usize, usize, usize)),
-{
-    if !slice.is_empty() {
-        if !x_sign {
-            slice.invert_axis(Axis(0));
-        }
-        if !y_sign {
-            slice.invert_axis(Axis(1));
-        }
-        if !z_sign {
-            slice.invert_axis(Axis(2));
-        }
-
-        for i in 0..3 {
-            let permuted_slice = slice
-                .view_mut()
-                .permuted_axes((i, (i + 1) % 3, (i + 2) % 3));
-
-            scan_recursive_shadowcast(permuted_slice, cast_range, shape, source_pos, &mut f);
-            // iterate_recursive_shadowcast(permuted_slice, 0.0, FRAC_PI_4, 0.0, FRAC_PI_4, 0);
-
-            // let pslice_width = permuted_slice.dim().0;
-            // let pslice_height = permuted_slice.dim().1;
-
-            // for (z, mut sub_slice) in permuted_slice.axis_iter_mut(Axis(2)).enumerate() {
-            //     for ((x, y), tile) in sub_slice.slice_mut(s![..z.min(pslice_width), ..z.min(pslice_height)]).indexed_iter_mut() {
-            //         // tile.illumination = 1.0 - ((x + y + z) as f32 / total_len as f32);
-            //         tile.illumination = 1.0 / z as f32;
-            //     }
-            // }
+        if self.show_minimap {
+            self.draw_minimap(ctx)?;
         }
-    }
-}
 
-#[derive(Clone, Copy, Debug)]
-pub enum LightShape {
-    Sphere,
-    Cone {
-        facing: UnitVector3<f32>,
-        width_angle: f32,
-    },
-}
-
-impl LightShape {
-    fn contains(&self, pos: Point3<f32>) -> bool {
-        match self {
-            Self::Sphere => true,
-            Self::Cone {
-                facing,
-                width_angle,
-            } => {
-                if let Some(unit) = Unit::try_new(pos.coords, 1.0) {
-                    facing.into_inner().angle(&unit.into_inner()) < *width_angle
-                } else {
-                    true
-                }
-            }
+        // The world as it was when the game was paused or the run ended, dimmed under the menu
+        if self.state == GameState::Paused || self.state == GameState::Finished {
+            ggez::graphics::draw(
+                ctx,
+                &self.blank_texture,
+                DrawParam::new()
+                    .scale([screen_width, screen_height])
+                    .color(Color::new(0.0, 0.0, 0.0, 0.6)),
+            )?;
         }
-    }
-}
-
-#[derive(Debug)]
-struct Shadowcast {
-    left_angle: f32,
-    right_angle: f32,
-    top_angle: f32,
-    bottom_angle: f32,
-    z: usize,
-}
-
-fn scan_recursive_shadowcast<F>(
-    mut slice: ArrayViewMut3<Tile>,
-    cast_range: usize,
-    shape: LightShape,
-    source_pos: Point3<f32>,
-    mut f: F,
-) where
-    F: FnMut(&mut Tile, (usize, usize, usize)),
-{
-    let mut frontier = Vec::new();
-
-    frontier.push(Shadowcast {
-        left_angle: 0.0,
-        right_angle: FRAC_PI_4,
-        top_angle: 0.0,
-        bottom_angle: FRAC_PI_4,
-        z: 0,
-    });
-
-    let (slice_width, slice_height, slice_depth) = slice.dim();
-
-    while let Some(current) = frontier.pop() {
-        let left = ((current.z + 1) as f32 * current.left_angle.tan()).floor() as usize;
-        let right = (((current.z + 1) as f32 * current.right_angle.tan()).ceil() as usize)
-            .min(slice_width.saturating_sub(1));
-        let top = ((current.z + 1) as f32 * current.top_angle.tan()).floor() as usize;
-        let bottom = (((current.z + 1) as f32 * current.bottom_angle.tan()).ceil() as usize)
-            .min(slice_height.saturating_sub(1));
-
-        let mut last_top = None;
-
-        'y_loop: for y in top..=bottom {
-            let mut last_left = None;
-
-            for x in left..=right {
-                let dist_from_center = EUCLIDEAN_DISTANCE_LOOKUP[[x, y, current.z]];
-                let outside_range = dist_from_center >= cast_range as f32;
-
-                if outside_range {
-                    if current.z < slice_depth - 1 {
-                        // At the end of each row, we check if there's any clear tiles
-                        if let Some(last_left) = last_left {
-                            frontier.push(Shadowcast {
-                                left_angle: ATAN_CASTING_LOOKUP[[last_left, current.z]],
-                                //(last_left as f32 / (current.z + 1) as f32).atan(),
-                                right_angle: ATAN_CASTING_LOOKUP[[x, current.z]],
-                                top_angle: ATAN_CASTING_LOOKUP[[y, current.z]],
-                                //(y as f32 / (current.z + 1) as f32).atan(),
-                                bottom_angle: ATAN_CASTING_LOOKUP[[y + 1usize, current.z]],
-                                //((y + 1usize) as f32 / (current.z + 1) as f32).atan(),
-                                z: current.z + 1,
-                            });
-                        }
-
-                        if let Some(last_top) = last_top.take() {
-                            frontier.push(Shadowcast {
-                                left_angle: current.left_angle,
-                                right_angle: current.right_angle,
-                                top_angle: ATAN_CASTING_LOOKUP[[last_top, current.z]],
-                                //(last_top as f32 / (current.z + 1) as f32).atan(),
-                                bottom_angle: ATAN_CASTING_LOOKUP[[y, current.z]],
-
-                                z: current.z + 1,
-                            });
-                        }
-                    }
-
-                    continue 'y_loop;
-                }
-
-                let tile = &mut slice[[x, y, current.z]];
 
-                let in_shape = shape.contains(Point3::from(tile.pos - source_pos));
+        self.draw_ui(ctx)?;
 
-                if in_shape {
-                    f(tile, (x, y, current.z));
-                }
-
-                // If we're on the last layer, we don't worry about bookkeeping for recursion
-                if current.z < slice_depth - 1 {
-                    if tile.tile_type.is_transparent() && in_shape {
-                        last_left = Some(last_left.unwrap_or(x));
-                    } else {
-                        let tile_top_angle = ATAN_CASTING_LOOKUP[[y, current.z]];
-                        //(y as f32 / (current.z + 1) as f32).atan();
-                        let tile_bottom_angle = ATAN_CASTING_LOOKUP[[y + 1usize, current.z]];
-                        //((y + 1usize) as f32 / (current.z + 1) as f32).atan();
-                        let tile_left_angle = ATAN_CASTING_LOOKUP[[x, current.z]];
-                        //(x as f32 / (current.z + 1) as f32).atan();
-
-                        if let Some(last_top) = last_top.take() {
-                            frontier.push(Shadowcast {
-                                left_angle: current.left_angle,
-                                right_angle: current.right_angle,
-                                top_angle: ATAN_CASTING_LOOKUP[[last_top, current.z]],
-                                //(last_top as f32 / (current.z + 1) as f32).atan(),
-                                bottom_angle: tile_top_angle,
-
-                                z: current.z + 1,
-                            });
-                        }
-
-                        if let Some(last_left) = last_left.take() {
-                            frontier.push(Shadowcast {
-                                left_angle: ATAN_CASTING_LOOKUP[[last_left, current.z]],
-                                //(last_left as f32 / (current.z + 1) as f32).atan(),
-                                right_angle: tile_left_angle,
-                                top_angle: tile_top_angle,
-                                bottom_angle: tile_bottom_angle,
-                                z: current.z + 1,
-                            });
-                        }
-                    }
-                }
-            }
+        self.metrics.record(Phase::Draw, draw_start.elapsed());
 
-            // At the end of each row, we check if there's any clear tiles
-            if let Some(last_left) = last_left {
-                if current.z < slice_depth - 1 {
-                    if last_left == left {
-                        // The whole row is clear
-                        last_top = Some(last_top.unwrap_or(y));
-                    } else {
-                        frontier.push(Shadowcast {
-                            left_angle: ATAN_CASTING_LOOKUP[[last_left, current.z]],
-                            //(last_left as f32 / (current.z + 1) as f32).atan(),
-                            right_angle: current.right_angle,
-                            top_angle: ATAN_CASTING_LOOKUP[[y, current.z]],
-                            //(y as f32 / (current.z + 1) as f32).atan(),
-                            bottom_angle: ATAN_CASTING_LOOKUP[[y + 1usize, current.z]],
-                            //((y + 1usize) as f32 / (current.z + 1) as f32).atan(),
-                            z: current.z + 1,
-                        });
-                    }
-                }
-            }
-        }
-
-        // At the end of each scan, we check if there's any clear rows
-        if let Some(last_top) = last_top {
-            if current.z < slice_depth - 1 {
-                if last_top == top {
-                    // The whole scan is clear
-                    frontier.push(Shadowcast {
-                        left_angle: current.left_angle,
-                        right_angle: current.right_angle,
-                        top_angle: current.top_angle,
-                        bottom_angle: current.bottom_angle,
-
-                        z: current.z + 1,
-                    });
-                } else {
-                    frontier.push(Shadowcast {
-                        left_angle: current.left_angle,
-                        right_angle: current.right_angle,
-                        top_angle: ATAN_CASTING_LOOKUP[[last_top, current.z]],
-                        //(last_top as f32 / (current.z + 1) as f32).atan(),
-                        bottom_angle: current.top_angle,
-
-                        z: current.z + 1,
-                    });
-                }
-            }
-        }
+        graphics::present(ctx)
     }
 }
 
-fn split_shadowcast_octants<'a>(
-    mut tile_array: ArrayViewMut3<'a, Tile>,
-    origin: Point3<usize>,
-    cast_range: usize,
-) -> [(ArrayViewMut3<'a, Tile>, (bool, bool, bool)); 8] {
-    let (tiles_width, tiles_height, tiles_depth) = tile_array.dim();
-
-    let light_left = origin.x.saturating_sub(cast_range);
-    let light_right = (origin.x + cast_range).min(tiles_width);
-    let light_bottom = origin.y.saturating_sub(cast_range);
-    let light_top = (origin.y + cast_range).min(tiles_height);
-    let light_back = origin.z.saturating_sub(cast_range);
-    let light_front = (origin.z + cast_range).min(tiles_depth);
-
-    let light_cube = tile_array.slice_move(s![
-        light_left..light_right,
-        light_bottom..light_top,
-        light_back..light_front,
-    ]);
-
-    let mid_x = origin.x - light_left;
-    let mid_y = origin.y - light_bottom;
-    let mid_z = origin.z - light_back;
-
-    let (bx, tx) = light_cube.split_at(Axis(0), mid_x);
-
-    let (bxby, bxty) = bx.split_at(Axis(1), mid_y);
-    let (txby, txty) = tx.split_at(Axis(1), mid_y);
-
-    let (bxbybz, bxbytz) = bxby.split_at(Axis(2), mid_z);
-    let (bxtybz, bxtytz) = bxty.split_at(Axis(2), mid_z);
-    let (txbybz, txbytz) = txby.split_at(Axis(2), mid_z);
-    let (txtybz, txtytz) = txty.split_at(Axis(2), mid_z);
-
-    let octs = [
-        (bxbybz, (false, false, false)),
-        (bxbytz, (false, false, true)),
-        (bxtybz, (false, true, false)),
-        (bxtytz, (false, true, true)),
-        (txbybz, (true, false, false)),
-        (txbytz, (true, false, true)),
-        (txtybz, (true, true, false)),
-        (txtytz, (true, true, true)),
-    ];
-
-    octs
-}
-
-// fn iterate_recursive_shadowcast(mut slice: ArrayViewMut3<Tile>, top_angle: f32, bottom_angle: f32, left_angle: f32, right_angle: f32, z: usize) {
-//     let slice_width = slice.dim().0;
-//     let slice_height = slice.dim().1;
-
-//     let left = ((z + 1) as f32 * left_angle.tan()).floor() as usize;
-//     let right = ((z + 1) as f32 * right_angle.tan()).ceil() as usize;
-//     let top = ((z + 1) as f32 * top_angle.tan()).floor() as usize;
-//     let bottom = ((z + 1) as f32 * bottom_angle.tan()).ceil() as usize;
-
-//     // dbg!(left, right, top, bottom);
-
-//     if !slice.is_empty() {
-//         let (mut selected, mut remainder) = slice.split_at(Axis(2), 1);
-
-//         for ((x, y), tile) in selected.slice_mut(s![left..=right.min(slice_width.saturating_sub(1)), top..=bottom.min(slice_height.saturating_sub(1)), 0]).indexed_iter_mut() {
-//             tile.illumination = 1.0 / z as f32;
-
-//             if tile.tile_type.is_transparent() {
-//                 iterate_recursive_shadowcast(
-//                     remainder.view_mut(),
-//                     (y as f32 / (z + 1) as f32).atan(),
-//                     ((y + 1usize) as f32 / (z + 1) as f32).atan(),
-//                     (x as f32 / (z + 1) as f32).atan(),
-//                     ((x + 1usize) as f32 / (z + 1) as f32).atan(),
-//                     z + 1
-//                 );
-//             }
-//         }
-//     }
-// }
-
-fn combine_light_colors(a: Color, b: Color) -> Color {
-    Color {
-        r: a.r.max(b.r).min(1.0),
-        g: a.g.max(b.g).min(1.0),
-        b: a.b.max(b.b).min(1.0),
-        a: 1.0,
+/// Lets the mouse leave the window, e.g. to click on a menu.
+fn release_mouse(ctx: &mut Context) {
+    if let Err(e) = mouse::set_cursor_grabbed(ctx, false) {
+        warn!("Failed to let go of the mouse: {}", e);
     }
 }
 
@@ -1315,13 +2079,17 @@ fn average_colors(a: Color, b: Color) -> Color {
     }
 }
 
-fn scale_color(color: Color, alpha: f32) -> Color {
-    Color {
-        r: color.r * alpha,
-        g: color.g * alpha,
-        b: color.b * alpha,
-        a: 1.0,
-    }
+/// `color` lit by `illumination`, the way tiles are drawn.
+fn shade(color: Color, illumination: Color) -> Color {
+    let color = average_colors(color, illumination);
+    let darkness = color_max(&color);
+
+    Color::new(
+        color.r * darkness,
+        color.g * darkness,
+        color.b * darkness,
+        1.0,
+    )
 }
 
 fn color_value(color: &Color) -> f32 {
@@ -0,0 +1,462 @@
+use std::{cell::Cell, rc::Rc};
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+
+use crate::{
+    constants::{MAX_CAST_RANGE, MAX_LOOK_SMOOTHING, MOUSE_SENSITIVITY},
+    geometry::rect::IRect,
+    rendering::color::PaletteMode,
+    settings::{InputConfig, Settings},
+    strings::StringTable,
+    tr,
+    ui::*,
+};
+
+/// The highest frame limit the settings screen offers.
+const MAX_FPS_LIMIT: i32 = 240;
+/// How much each step of the mouse speed sliders changes the sensitivity by.
+const SENSITIVITY_STEP: f32 = MOUSE_SENSITIVITY / 10.0;
+/// The fastest the mouse speed sliders go, in steps.
+const MAX_SENSITIVITY_STEPS: i32 = 40;
+/// How much each step of the smoothing slider changes it by.
+const SMOOTHING_STEP: f32 = 0.1;
+
+/// Which screen the game is on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameState {
+    MainMenu,
+    Playing,
+    /// The world is kept as it was, drawn dimmed under the pause menu.
+    Paused,
+    /// The world is kept as it was while commands are typed into the console.
+    Console,
+    /// The run is over, and its results are shown over the world where it ended.
+    Finished,
+}
+
+/// What a menu button asked for, to be carried out on the next update.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuChoice {
+    NewWorld,
+    Continue,
+    Resume,
+    Settings,
+    /// Keep what was changed on the settings screen.
+    SaveSettings,
+    /// Back from the settings screen to the menu it was opened from.
+    Back,
+    Quit,
+}
+
+type Screen = Centered<PanelBackground<Frame<FlexLayout>>>;
+
+/// The main menu, the pause menu and the settings screen they share. Only one is open at a time.
+pub struct Menu {
+    screen: Option<Screen>,
+    settings_open: bool,
+    /// The size `screen` was last laid out for, if it has been since it was opened.
+    laid_out: Option<Size>,
+    choice: Rc<Cell<Option<MenuChoice>>>,
+
+    light_range: Binding<i32>,
+    sight_range: Binding<i32>,
+    /// 0 for no limit.
+    max_fps: Binding<i32>,
+    /// The index in `PaletteMode::ALL`.
+    palette_mode: Binding<usize>,
+    /// The mouse settings in steps of their sliders.
+    sensitivity_x: Binding<i32>,
+    sensitivity_y: Binding<i32>,
+    smoothing: Binding<i32>,
+    /// 1 for inverted.
+    invert_y: Binding<usize>,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self {
+            screen: None,
+            settings_open: false,
+            laid_out: None,
+            choice: Rc::new(Cell::new(None)),
+            light_range: bind(1),
+            sight_range: bind(1),
+            max_fps: bind(0),
+            palette_mode: bind(0),
+            sensitivity_x: bind(0),
+            sensitivity_y: bind(0),
+            smoothing: bind(0),
+            invert_y: bind(0),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.screen.is_some()
+    }
+
+    /// Whether the settings screen is the one that's open.
+    pub fn is_settings_open(&self) -> bool {
+        self.screen.is_some() && self.settings_open
+    }
+
+    /// The choice made since the last call, if there was one.
+    pub fn take_choice(&self) -> Option<MenuChoice> {
+        self.choice.take()
+    }
+
+    /// The menu shown before playing. Continue is only offered if there's a save to load.
+    pub fn open_main(&mut self, strings: &StringTable, can_continue: bool) {
+        self.open(
+            tr!(strings, "menu.title"),
+            vec![
+                menu_row(self.button(tr!(strings, "menu.new_world"), MenuChoice::NewWorld)),
+                menu_row(
+                    self.button(tr!(strings, "menu.continue"), MenuChoice::Continue)
+                        .with_enabled(bind(can_continue)),
+                ),
+                menu_row(self.button(tr!(strings, "menu.settings"), MenuChoice::Settings)),
+                menu_row(self.button(tr!(strings, "menu.quit"), MenuChoice::Quit)),
+            ],
+        );
+    }
+
+    pub fn open_pause(&mut self, strings: &StringTable) {
+        self.open(
+            tr!(strings, "menu.paused"),
+            vec![
+                menu_row(self.button(tr!(strings, "menu.resume"), MenuChoice::Resume)),
+                menu_row(self.button(tr!(strings, "menu.settings"), MenuChoice::Settings)),
+                menu_row(self.button(tr!(strings, "menu.quit"), MenuChoice::Quit)),
+            ],
+        );
+    }
+
+    /// The results of a run, one of `lines` to a row, with nothing left to do but quit.
+    pub fn open_results(&mut self, strings: &StringTable, lines: &[String]) {
+        let mut rows: Vec<_> = lines
+            .iter()
+            .map(|line| menu_row(KataText::from_str(line)))
+            .collect();
+        rows.push(menu_row(
+            self.button(tr!(strings, "menu.quit"), MenuChoice::Quit),
+        ));
+
+        self.open(tr!(strings, "results.title"), rows);
+    }
+
+    /// The settings that can be changed while playing, starting from `settings`.
+    pub fn open_settings(&mut self, strings: &StringTable, settings: &Settings) {
+        self.light_range.set(settings.light_range as i32);
+        self.sight_range.set(settings.sight_range as i32);
+        self.max_fps.set(settings.max_fps.unwrap_or(0) as i32);
+        self.palette_mode.set(
+            PaletteMode::ALL
+                .iter()
+                .position(|&mode| mode == settings.palette_mode)
+                .unwrap_or(0),
+        );
+        let input = &settings.input;
+        self.sensitivity_x
+            .set(to_steps(input.sensitivity_x, SENSITIVITY_STEP));
+        self.sensitivity_y
+            .set(to_steps(input.sensitivity_y, SENSITIVITY_STEP));
+        self.smoothing
+            .set(to_steps(input.smoothing, SMOOTHING_STEP));
+        self.invert_y.set(input.invert_y as usize);
+
+        let labels = [
+            tr!(strings, "menu.light_range"),
+            tr!(strings, "menu.sight_range"),
+            tr!(strings, "menu.max_fps"),
+            tr!(strings, "menu.palette"),
+            tr!(strings, "menu.look_x"),
+            tr!(strings, "menu.look_y"),
+            tr!(strings, "menu.smoothing"),
+            tr!(strings, "menu.invert_y"),
+        ];
+        let label_width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+        let row = |label: &str, field: Box<dyn Element>| {
+            menu_row(FlexLayout::horizontal(vec![
+                FlexElement::fixed(Box::new(KataText::from_str(&format!(
+                    "{:1$} ",
+                    label, label_width
+                )))),
+                FlexElement::fixed(field),
+            ]))
+        };
+        let palette_names: Vec<_> = PaletteMode::ALL
+            .iter()
+            .map(|mode| mode.display_name(strings))
+            .collect();
+        let on_off = [tr!(strings, "menu.off"), tr!(strings, "menu.on")];
+        let max_smoothing = to_steps(MAX_LOOK_SMOOTHING, SMOOTHING_STEP);
+
+        let max_range = MAX_CAST_RANGE as i32;
+        self.open(
+            tr!(strings, "menu.settings"),
+            vec![
+                row(
+                    &labels[0],
+                    Box::new(Stepper::new(self.light_range.clone(), 1, max_range)),
+                ),
+                row(
+                    &labels[1],
+                    Box::new(Stepper::new(self.sight_range.clone(), 1, max_range)),
+                ),
+                row(
+                    &labels[2],
+                    Box::new(Stepper::new(self.max_fps.clone(), 0, MAX_FPS_LIMIT).with_step(10)),
+                ),
+                row(
+                    &labels[3],
+                    Box::new(Picker::new(&palette_names, self.palette_mode.clone())),
+                ),
+                row(
+                    &labels[4],
+                    Box::new(Slider::new(
+                        self.sensitivity_x.clone(),
+                        1,
+                        MAX_SENSITIVITY_STEPS,
+                    )),
+                ),
+                row(
+                    &labels[5],
+                    Box::new(Slider::new(
+                        self.sensitivity_y.clone(),
+                        1,
+                        MAX_SENSITIVITY_STEPS,
+                    )),
+                ),
+                row(
+                    &labels[6],
+                    Box::new(Slider::new(self.smoothing.clone(), 0, max_smoothing)),
+                ),
+                row(
+                    &labels[7],
+                    Box::new(Picker::new(&on_off, self.invert_y.clone())),
+                ),
+                menu_row(FlexLayout::horizontal(vec![
+                    FlexElement::fixed(Box::new(Padding::new(
+                        self.button(tr!(strings, "menu.save"), MenuChoice::SaveSettings),
+                        0,
+                        1,
+                        0,
+                        0,
+                    ))),
+                    FlexElement::fixed(Box::new(
+                        self.button(tr!(strings, "menu.back"), MenuChoice::Back),
+                    )),
+                ])),
+            ],
+        );
+        self.settings_open = true;
+    }
+
+    /// `settings` with the values from the settings screen.
+    pub fn apply_settings(&self, settings: &Settings) -> Settings {
+        Settings {
+            light_range: self.light_range.get().max(1) as usize,
+            sight_range: self.sight_range.get().max(1) as usize,
+            max_fps: Some(self.max_fps.get().max(0) as u32),
+            palette_mode: PaletteMode::ALL
+                .get(self.palette_mode.get())
+                .copied()
+                .unwrap_or_default(),
+            input: InputConfig {
+                sensitivity_x: from_steps(
+                    settings.input.sensitivity_x,
+                    self.sensitivity_x.get(),
+                    SENSITIVITY_STEP,
+                ),
+                sensitivity_y: from_steps(
+                    settings.input.sensitivity_y,
+                    self.sensitivity_y.get(),
+                    SENSITIVITY_STEP,
+                ),
+                smoothing: from_steps(
+                    settings.input.smoothing,
+                    self.smoothing.get(),
+                    SMOOTHING_STEP,
+                ),
+                invert_y: self.invert_y.get() == 1,
+            },
+            ..settings.clone()
+        }
+        .sanitized()
+    }
+
+    pub fn close(&mut self) {
+        self.screen = None;
+        self.settings_open = false;
+        self.laid_out = None;
+    }
+
+    /// Passes `event` to the open screen, laying it out to fill `bounds` first if it hasn't
+    /// been yet.
+    pub fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) {
+        let screen = match &mut self.screen {
+            Some(screen) => screen,
+            None => return,
+        };
+
+        if self.laid_out != Some(bounds.size()) {
+            screen.layout(BoxConstraints::exact(bounds.size()));
+            self.laid_out = Some(bounds.size());
+        }
+
+        let _ = screen.handle_event(ctx, event, bounds.into());
+    }
+
+    fn open(&mut self, title: String, rows: Vec<FlexElement>) {
+        self.screen = Some(Centered::new(PanelBackground::new(
+            Frame::new(FlexLayout::vertical(rows)).with_title(&title),
+        )));
+        self.settings_open = false;
+        self.laid_out = None;
+    }
+
+    fn button(&self, label: String, choice: MenuChoice) -> Button {
+        let chosen = Rc::clone(&self.choice);
+        Button::new(&label, move || chosen.set(Some(choice)))
+    }
+}
+
+/// How many steps of `step` it takes to get nearest to `value`.
+fn to_steps(value: f32, step: f32) -> i32 {
+    (value / step).round() as i32
+}
+
+/// `value` if it's what a slider at `steps` of `step` was set from, or else where the slider
+/// was moved to. Settings from the file that fall between steps are kept until they're changed.
+fn from_steps(value: f32, steps: i32, step: f32) -> f32 {
+    if to_steps(value, step) == steps {
+        value
+    } else {
+        steps as f32 * step
+    }
+}
+
+/// A row of a menu, with a blank line under it.
+fn menu_row<T: Element + 'static>(element: T) -> FlexElement {
+    FlexElement::fixed(Box::new(Padding::new(element, 0, 0, 1, 0)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ggez::{input::mouse::MouseButton, mint};
+
+    const SIZE: Size = Size {
+        width: 40,
+        height: 24,
+    };
+
+    /// Draws the open screen and clicks the middle of `label`, returning what was chosen.
+    fn click(menu: &mut Menu, label: &str) -> Option<MenuChoice> {
+        let bounds = IRect::new(0, 0, SIZE.width, SIZE.height);
+        let mut ctx = UiContext::new(TextGridBatch::new(SIZE));
+        menu.handle_event(&mut ctx, Event::Draw, bounds);
+
+        let lines = ctx.batch.lines();
+        let (y, x) = lines
+            .iter()
+            .enumerate()
+            .find_map(|(y, line)| line.find(label).map(|x| (y, x)))
+            .unwrap_or_else(|| panic!("No {:?} in {:#?}", label, lines));
+
+        let pos = mint::Point2::from([(x + label.len() / 2) as i32, y as i32]);
+        menu.handle_event(
+            &mut ctx,
+            Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                },
+            },
+            bounds,
+        );
+
+        menu.take_choice()
+    }
+
+    #[test]
+    fn test_main_menu() {
+        // Without a table every label is its key
+        let strings = StringTable::default();
+        let mut menu = Menu::new();
+
+        menu.open_main(&strings, false);
+        assert!(menu.is_open());
+        assert_eq!(click(&mut menu, "menu.continue"), None);
+        assert_eq!(
+            click(&mut menu, "menu.new_world"),
+            Some(MenuChoice::NewWorld)
+        );
+
+        menu.open_main(&strings, true);
+        assert_eq!(
+            click(&mut menu, "menu.continue"),
+            Some(MenuChoice::Continue)
+        );
+
+        menu.close();
+        assert!(!menu.is_open());
+        assert_eq!(menu.take_choice(), None);
+    }
+
+    #[test]
+    fn test_results() {
+        let strings = StringTable::default();
+        let mut menu = Menu::new();
+
+        menu.open_results(&strings, &[String::from("Shots fired: 12")]);
+        assert_eq!(click(&mut menu, "Shots fired: 12"), None);
+        assert_eq!(click(&mut menu, "menu.quit"), Some(MenuChoice::Quit));
+    }
+
+    #[test]
+    fn test_settings() {
+        // Short enough for the palettes to fit
+        let strings: StringTable = serde_json::from_str(
+            r#"{ "palette.normal": "N", "palette.deuteranopia": "D", "palette.high_contrast": "HC" }"#,
+        )
+        .unwrap();
+        let settings = Settings {
+            light_range: 10,
+            max_fps: None,
+            ..Settings::default()
+        };
+        let mut menu = Menu::new();
+
+        menu.open_pause(&strings);
+        assert!(!menu.is_settings_open());
+        menu.open_settings(&strings, &settings);
+        assert!(menu.is_settings_open());
+        assert_eq!(menu.apply_settings(&settings), settings);
+
+        menu.light_range.set(12);
+        menu.max_fps.set(60);
+        let applied = menu.apply_settings(&settings);
+        assert_eq!(applied.light_range, 12);
+        assert_eq!(applied.max_fps, Some(60));
+        assert_eq!(applied.sight_range, settings.sight_range);
+        assert_eq!(applied.palette_mode, PaletteMode::Normal);
+
+        assert_eq!(click(&mut menu, "HC"), None);
+        assert_eq!(
+            menu.apply_settings(&settings).palette_mode,
+            PaletteMode::HighContrast
+        );
+
+        menu.sensitivity_y.set(20);
+        menu.smoothing.set(3);
+        assert_eq!(click(&mut menu, "menu.on"), None);
+        let input = menu.apply_settings(&settings).input;
+        assert_eq!(input.sensitivity_x, settings.input.sensitivity_x);
+        assert_eq!(input.sensitivity_y, 20.0 * SENSITIVITY_STEP);
+        assert_eq!(input.smoothing, 3.0 * SMOOTHING_STEP);
+        assert!(input.invert_y);
+
+        assert_eq!(click(&mut menu, "menu.back"), Some(MenuChoice::Back));
+    }
+}
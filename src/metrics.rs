@@ -0,0 +1,277 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+/// How often the timings are averaged, logged and shown on the HUD.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The parts of a frame that are timed separately.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Lighting,
+    Fov,
+    Draw,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 3] = [Phase::Lighting, Phase::Fov, Phase::Draw];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Lighting => "lighting",
+            Phase::Fov => "fov",
+            Phase::Draw => "draw",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// How long one phase took over a report interval, in milliseconds.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct PhaseStats {
+    pub mean: f32,
+    pub p95: f32,
+    pub max: f32,
+}
+
+impl PhaseStats {
+    /// Sorts `samples` on the way.
+    pub fn from_samples(samples: &mut [Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        samples.sort_unstable();
+
+        let ms = |d: Duration| d.as_secs_f32() * 1000.0;
+        let total: Duration = samples.iter().sum();
+        // Nearest rank, so with fewer than 20 samples this is the slowest one
+        let p95_rank = (samples.len() * 95 + 99) / 100;
+
+        Self {
+            mean: ms(total) / samples.len() as f32,
+            p95: ms(samples[p95_rank - 1]),
+            max: ms(samples[samples.len() - 1]),
+        }
+    }
+}
+
+/// The timings and counts averaged over one report interval.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MetricsReport {
+    pub fps: f32,
+    pub phases: [PhaseStats; 3],
+    pub draw_tiles: usize,
+    pub lights: usize,
+    /// Static lights left out because they're sealed off from the player.
+    pub sealed_lights: usize,
+}
+
+impl MetricsReport {
+    pub fn phase(&self, phase: Phase) -> &PhaseStats {
+        &self.phases[phase.index()]
+    }
+
+    /// One line for the frame rate and counts and then one per phase, for the HUD.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{:.0} fps, {} tiles, {} lights, {} sealed off",
+            self.fps, self.draw_tiles, self.lights, self.sealed_lights
+        )];
+
+        lines.extend(Phase::ALL.iter().map(|&phase| {
+            let stats = self.phase(phase);
+            format!(
+                "{}: {:.2} ms avg, {:.2} p95, {:.2} max",
+                phase.name(),
+                stats.mean,
+                stats.p95,
+                stats.max
+            )
+        }));
+
+        lines
+    }
+}
+
+/// Collects frame timings and reports them once every `REPORT_INTERVAL`, instead of printing
+/// them every frame.
+pub struct FrameMetrics {
+    started: Instant,
+    frames: usize,
+    samples: [Vec<Duration>; 3],
+    draw_tiles: usize,
+    lights: usize,
+    sealed_lights: usize,
+    last_report: Option<MetricsReport>,
+}
+
+impl FrameMetrics {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            frames: 0,
+            samples: Default::default(),
+            draw_tiles: 0,
+            lights: 0,
+            sealed_lights: 0,
+            last_report: None,
+        }
+    }
+
+    pub fn record(&mut self, phase: Phase, duration: Duration) {
+        self.samples[phase.index()].push(duration);
+    }
+
+    /// Sets the counts for the current frame. Only the latest ones are reported.
+    pub fn set_counts(&mut self, draw_tiles: usize, lights: usize, sealed_lights: usize) {
+        self.draw_tiles = draw_tiles;
+        self.lights = lights;
+        self.sealed_lights = sealed_lights;
+    }
+
+    /// Counts a frame, and once `REPORT_INTERVAL` has passed since the last report logs a new
+    /// one to the `katakomb::metrics` target and returns it.
+    pub fn end_frame(&mut self, now: Instant) -> Option<&MetricsReport> {
+        self.frames += 1;
+
+        let elapsed = now.saturating_duration_since(self.started);
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+
+        let mut phases = [PhaseStats::default(); 3];
+        for (stats, samples) in phases.iter_mut().zip(self.samples.iter_mut()) {
+            *stats = PhaseStats::from_samples(samples);
+            samples.clear();
+        }
+
+        let report = MetricsReport {
+            fps: self.frames as f32 / elapsed.as_secs_f32(),
+            phases,
+            draw_tiles: self.draw_tiles,
+            lights: self.lights,
+            sealed_lights: self.sealed_lights,
+        };
+
+        debug!("{}", report.lines().join("; "));
+
+        self.started = now;
+        self.frames = 0;
+        self.last_report = Some(report);
+        self.last_report.as_ref()
+    }
+
+    /// The most recent report, if a whole interval has passed yet.
+    pub fn last_report(&self) -> Option<&MetricsReport> {
+        self.last_report.as_ref()
+    }
+}
+
+/// Holds frames back so that there are no more than a set number of them a second.
+pub struct FrameLimiter {
+    budget: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// `budget` is how long each frame should take at least, or `None` for no limit.
+    pub fn new(budget: Option<Duration>, now: Instant) -> Self {
+        Self {
+            budget,
+            next_frame: now,
+        }
+    }
+
+    /// How long to wait at `now` before starting the next frame. A frame that ran over doesn't
+    /// make the ones after it shorter to catch up.
+    pub fn wait(&mut self, now: Instant) -> Option<Duration> {
+        let budget = self.budget?;
+
+        self.next_frame = (self.next_frame + budget).max(now);
+        let wait = self.next_frame - now;
+
+        if wait > Duration::from_secs(0) {
+            Some(wait)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_phase_stats() {
+        let mut samples: Vec<_> = (1..=100).rev().map(ms).collect();
+        let stats = PhaseStats::from_samples(&mut samples);
+
+        assert!((stats.mean - 50.5).abs() < 1e-3);
+        assert!((stats.p95 - 95.0).abs() < 1e-3);
+        assert!((stats.max - 100.0).abs() < 1e-3);
+
+        let mut few = vec![ms(3), ms(1), ms(2)];
+        assert!((PhaseStats::from_samples(&mut few).p95 - 3.0).abs() < 1e-3);
+
+        assert_eq!(PhaseStats::from_samples(&mut []), PhaseStats::default());
+    }
+
+    #[test]
+    fn test_reports_once_per_interval() {
+        let start = Instant::now();
+        let mut metrics = FrameMetrics::new(start);
+
+        for frame in 1..=9 {
+            metrics.record(Phase::Lighting, ms(4));
+            metrics.record(Phase::Fov, ms(2));
+            metrics.set_counts(frame * 10, 3, frame);
+            assert!(metrics.end_frame(start + ms(frame as u64 * 100)).is_none());
+        }
+        assert!(metrics.last_report().is_none());
+
+        metrics.record(Phase::Lighting, ms(14));
+        let report = metrics.end_frame(start + ms(1000)).unwrap().clone();
+
+        assert!((report.fps - 10.0).abs() < 1e-3);
+        assert!((report.phase(Phase::Lighting).mean - 5.0).abs() < 1e-3);
+        assert!((report.phase(Phase::Lighting).max - 14.0).abs() < 1e-3);
+        assert!((report.phase(Phase::Fov).mean - 2.0).abs() < 1e-3);
+        assert_eq!(*report.phase(Phase::Draw), PhaseStats::default());
+        assert_eq!(report.draw_tiles, 90);
+        assert_eq!(report.sealed_lights, 9);
+        assert_eq!(report.lines().len(), 1 + Phase::ALL.len());
+
+        // Starts over from the report
+        assert!(metrics.end_frame(start + ms(1500)).is_none());
+        assert_eq!(metrics.last_report(), Some(&report));
+        let next = metrics.end_frame(start + ms(2000)).unwrap();
+        assert!((next.fps - 2.0).abs() < 1e-3);
+        assert_eq!(next.phase(Phase::Lighting).mean, 0.0);
+    }
+
+    #[test]
+    fn test_frame_limiter() {
+        let start = Instant::now();
+
+        let mut unlimited = FrameLimiter::new(None, start);
+        assert_eq!(unlimited.wait(start), None);
+
+        let mut limiter = FrameLimiter::new(Some(ms(20)), start);
+        assert_eq!(limiter.wait(start + ms(5)), Some(ms(15)));
+        // The next frame is due 20ms after the last one was let through
+        assert_eq!(limiter.wait(start + ms(30)), Some(ms(10)));
+
+        // A slow frame starts the schedule over rather than being made up for
+        assert_eq!(limiter.wait(start + ms(100)), None);
+        assert_eq!(limiter.wait(start + ms(110)), Some(ms(10)));
+    }
+}
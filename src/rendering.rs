@@ -1,7 +1,11 @@
 pub mod color;
 pub mod drawable;
 pub mod font;
+pub mod fov;
 pub mod light;
+pub mod lighting;
+pub mod minimap;
+pub mod shake;
 pub mod tile;
 pub mod util;
 pub mod voxel;
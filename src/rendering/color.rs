@@ -1,7 +1,14 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use failure::{format_err, Fallible};
 use ggez::graphics::Color as GGColor;
+use internship::IStr;
+use log::warn;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+use crate::{strings::StringTable, tr};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct Color {
     r: u8,
     g: u8,
@@ -12,6 +19,41 @@ impl Color {
     pub const fn new(r: u8, g: u8, b: u8) -> Self {
         Self { r, g, b }
     }
+
+    pub const fn rgb(self) -> (u8, u8, u8) {
+        (self.r, self.g, self.b)
+    }
+
+    /// Parses a color written as `#RRGGBB`, with or without the `#`.
+    pub fn from_hex(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let digits = s.strip_prefix('#').unwrap_or(s);
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap();
+        Some(Self::new(channel(0), channel(2), channel(4)))
+    }
+
+    /// Writes the color as `#RRGGBB`.
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+
+    /// Scales each channel by `factor`, e.g. by 0.4 to draw something as disabled.
+    pub fn dimmed(self, factor: f32) -> Self {
+        let scale = |c: u8| (c as f32 * factor).round() as u8;
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+/// Rounds each channel to the nearest step, dropping the alpha.
+impl From<GGColor> for Color {
+    fn from(c: GGColor) -> Self {
+        let channel = |c: f32| (c.max(0.0).min(1.0) * u8::max_value() as f32).round() as u8;
+        Self::new(channel(c.r), channel(c.g), channel(c.b))
+    }
 }
 
 impl From<Color> for GGColor {
@@ -34,3 +76,266 @@ pub const BLACK: Color = Color::new(0, 0, 0);
 pub const RED: Color = Color::new(255, 0, 0);
 pub const GREEN: Color = Color::new(0, 255, 0);
 pub const BLUE: Color = Color::new(0, 0, 255);
+pub const YELLOW: Color = Color::new(255, 255, 0);
+pub const CYAN: Color = Color::new(0, 255, 255);
+pub const MAGENTA: Color = Color::new(255, 0, 255);
+
+/// White light at a few color temperatures, from candlelight to daylight.
+pub const WARM_LIGHT: Color = Color::new(255, 180, 107);
+pub const NEUTRAL_LIGHT: Color = Color::new(255, 228, 206);
+pub const COOL_LIGHT: Color = Color::new(204, 219, 255);
+
+/// The named colors above with their lowercase names, e.g. `"light_gray"`.
+pub const NAMED: [(&str, Color); 11] = [
+    ("white", WHITE),
+    ("light_gray", LIGHT_GRAY),
+    ("gray", GRAY),
+    ("dark_gray", DARK_GRAY),
+    ("black", BLACK),
+    ("red", RED),
+    ("green", GREEN),
+    ("blue", BLUE),
+    ("yellow", YELLOW),
+    ("cyan", CYAN),
+    ("magenta", MAGENTA),
+];
+
+/// Looks up one of the named colors above by its lowercase name, e.g. `"light_gray"`.
+pub fn by_name(name: &str) -> Option<Color> {
+    NAMED
+        .iter()
+        .find(|&&(color_name, _)| color_name == name)
+        .map(|&(_, color)| color)
+}
+
+/// The named color closest to `color`, by squared distance between the channels.
+pub fn nearest(color: Color) -> Color {
+    let distance = |other: Color| {
+        let channel = |a: u8, b: u8| (i32::from(a) - i32::from(b)).pow(2);
+        channel(color.r, other.r) + channel(color.g, other.g) + channel(color.b, other.b)
+    };
+
+    NAMED
+        .iter()
+        .map(|&(_, named)| named)
+        .min_by_key(|&named| distance(named))
+        .unwrap()
+}
+
+pub const PALETTE_PATH: &str = "palette.json";
+
+/// Named colors made for the project, kept in `palette.json` and edited in the editor. Names it
+/// doesn't have fall back to the built-in colors above.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Palette {
+    pub entries: Vec<(IStr, Color)>,
+}
+
+impl Palette {
+    /// The color called `name` in the palette, or else the built-in one.
+    pub fn get(&self, name: &str) -> Option<Color> {
+        self.entries
+            .iter()
+            .find(|(entry_name, _)| &**entry_name == name)
+            .map(|&(_, color)| color)
+            .or_else(|| by_name(name))
+    }
+}
+
+pub const PALETTE_MODES_PATH: &str = "resources/palette_modes.json";
+
+/// Which colors the world is drawn with, for players who find the usual ones hard to tell apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteMode {
+    Normal,
+    /// Nothing told apart by red against green alone.
+    Deuteranopia,
+    /// Brighter colors, and solid tiles backed brighter so their outlines stand out.
+    HighContrast,
+}
+
+impl Default for PaletteMode {
+    fn default() -> Self {
+        PaletteMode::Normal
+    }
+}
+
+impl PaletteMode {
+    pub const ALL: [PaletteMode; 3] = [
+        PaletteMode::Normal,
+        PaletteMode::Deuteranopia,
+        PaletteMode::HighContrast,
+    ];
+
+    /// Its name as the player sees it.
+    pub fn display_name(self, strings: &StringTable) -> String {
+        match self {
+            PaletteMode::Normal => tr!(strings, "palette.normal"),
+            PaletteMode::Deuteranopia => tr!(strings, "palette.deuteranopia"),
+            PaletteMode::HighContrast => tr!(strings, "palette.high_contrast"),
+        }
+    }
+
+    /// How bright the square behind a solid tile is drawn, compared to the tile's glyph.
+    pub fn backing_brightness(self) -> f32 {
+        match self {
+            PaletteMode::HighContrast => 1.5,
+            _ => 0.75,
+        }
+    }
+}
+
+/// The colors each `PaletteMode` swaps for others, kept in `palette_modes.json` as `#RRGGBB`
+/// pairs so that they can be tuned without rebuilding. Colors that aren't listed, and every
+/// color in modes that aren't, are drawn as they are.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PaletteRemaps {
+    remaps: HashMap<PaletteMode, HashMap<Color, Color>>,
+}
+
+impl PaletteRemaps {
+    /// Loads `palette_modes.json`, or no remaps at all when it's missing or can't be read.
+    pub fn load() -> Self {
+        match Self::load_from(PALETTE_MODES_PATH) {
+            Ok(remaps) => remaps,
+            Err(e) => {
+                warn!("Failed to load {}: {}", PALETTE_MODES_PATH, e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Loads the remaps at `path`, which are empty if the file doesn't exist.
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Fallible<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Self::from_json(&json),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Parses the remaps, failing on any color that isn't written as `#RRGGBB`.
+    pub fn from_json(json: &str) -> Fallible<Self> {
+        let raw: HashMap<PaletteMode, HashMap<String, String>> = serde_json::from_str(json)?;
+        let parse =
+            |hex: &str| Color::from_hex(hex).ok_or_else(|| format_err!("{:?} isn't a color", hex));
+
+        let mut remaps = HashMap::new();
+        for (mode, pairs) in raw {
+            let pairs = pairs
+                .iter()
+                .map(|(from, to)| Ok((parse(from)?, parse(to)?)))
+                .collect::<Fallible<_>>()?;
+            remaps.insert(mode, pairs);
+        }
+
+        Ok(Self { remaps })
+    }
+
+    /// `color` as it's drawn in `mode`. Its alpha is kept.
+    pub fn remap(&self, mode: PaletteMode, color: GGColor) -> GGColor {
+        match self
+            .remaps
+            .get(&mode)
+            .and_then(|pairs| pairs.get(&Color::from(color)))
+        {
+            Some(&remapped) => GGColor {
+                a: color.a,
+                ..remapped.into()
+            },
+            None => color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dimmed() {
+        assert_eq!(WHITE.dimmed(0.4), Color::new(102, 102, 102));
+        assert_eq!(RED.dimmed(0.5), Color::new(128, 0, 0));
+        assert_eq!(GRAY.dimmed(0.0), BLACK);
+        assert_eq!(WHITE.dimmed(2.0), WHITE);
+    }
+
+    #[test]
+    fn test_by_name() {
+        assert_eq!(by_name("light_gray"), Some(LIGHT_GRAY));
+        assert_eq!(by_name("magenta"), Some(MAGENTA));
+        assert_eq!(by_name("Magenta"), None);
+        assert_eq!(by_name(""), None);
+    }
+
+    #[test]
+    fn test_nearest() {
+        assert_eq!(nearest(RED), RED);
+        assert_eq!(nearest(Color::new(250, 10, 20)), RED);
+        assert_eq!(nearest(Color::new(120, 130, 125)), GRAY);
+        assert_eq!(nearest(Color::new(10, 10, 10)), BLACK);
+        assert_eq!(nearest(Color::new(200, 200, 40)), YELLOW);
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(Color::from_hex("#FF8000"), Some(Color::new(255, 128, 0)));
+        assert_eq!(Color::from_hex(" 0a0b0c "), Some(Color::new(10, 11, 12)));
+        assert_eq!(Color::from_hex("#FFF"), None);
+        assert_eq!(Color::from_hex("#+F8000"), None);
+        assert_eq!(Color::from_hex("#FF80ÿ"), None);
+        assert_eq!(Color::new(255, 128, 0).to_hex(), "#FF8000");
+    }
+
+    #[test]
+    fn test_palette() {
+        let palette = Palette {
+            entries: vec![
+                (IStr::new("moss"), Color::new(40, 90, 30)),
+                (IStr::new("red"), Color::new(200, 0, 0)),
+            ],
+        };
+
+        assert_eq!(palette.get("moss"), Some(Color::new(40, 90, 30)));
+        assert_eq!(palette.get("red"), Some(Color::new(200, 0, 0)));
+        assert_eq!(palette.get("blue"), Some(BLUE));
+        assert_eq!(palette.get("rust"), None);
+    }
+
+    #[test]
+    fn test_palette_remaps() {
+        let remaps = PaletteRemaps::from_json(
+            r##"{ "deuteranopia": { "#00FF00": "#3399FF", "#FF0000": "#FFB000" } }"##,
+        )
+        .unwrap();
+        let green = GGColor::new(0.0, 1.0, 0.0, 0.5);
+
+        assert_eq!(
+            remaps.remap(PaletteMode::Deuteranopia, green),
+            GGColor::new(0.2, 0.6, 1.0, 0.5)
+        );
+        assert_eq!(remaps.remap(PaletteMode::Normal, green), green);
+        assert_eq!(remaps.remap(PaletteMode::HighContrast, green), green);
+        // Only exact matches are swapped
+        let nearly = GGColor::new(0.0, 0.9, 0.0, 1.0);
+        assert_eq!(remaps.remap(PaletteMode::Deuteranopia, nearly), nearly);
+
+        assert!(PaletteRemaps::from_json(r#"{ "normal": { "#00FF00": "green" } }"#).is_err());
+        assert!(PaletteRemaps::from_json(r#"{ "sepia": {} }"#).is_err());
+    }
+
+    #[test]
+    fn test_shipped_palette_modes() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let remaps = PaletteRemaps::load_from(root.join(PALETTE_MODES_PATH)).unwrap();
+
+        // Glowsticks shouldn't be told apart from lava by green against red
+        let glowstick = GGColor::new(0.2, 1.0, 0.3, 1.0);
+        assert_ne!(
+            remaps.remap(PaletteMode::Deuteranopia, glowstick),
+            glowstick
+        );
+    }
+}
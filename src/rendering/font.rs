@@ -1,15 +1,23 @@
-use failure::Fallible;
+use std::{convert::TryInto, io::Read, rc::Rc};
+
+use failure::{ensure, format_err, Fallible};
 use ggez::{
+    filesystem,
     graphics::{spritebatch::SpriteBatch, BlendMode, DrawParam, Drawable, FilterMode, Image, Rect},
     mint, Context, GameResult,
 };
+use image::{Rgba, RgbaImage};
 
 use crate::rendering::voxel::Voxel2;
 
+/// The glyph sheet, in the resources folder.
+pub const FONT_PATH: &str = "/master8x8.png";
+
+/// Cloning it shares the texture and the pixels instead of loading them again.
+#[derive(Clone)]
 pub struct KataFont {
     texture: Image,
-    char_width: u8,
-    char_height: u8,
+    glyphs: Rc<GlyphSheet>,
 }
 
 impl KataFont {
@@ -17,40 +25,44 @@ impl KataFont {
         &self.texture
     }
 
-    pub fn load(ctx: &mut Context) -> Fallible<Self> {
-        let texture = Image::new(ctx, "/master8x8.png")?;
-
-        let char_width: u8 = 8;
-        let char_height: u8 = 8;
-
-        assert_eq!(
-            texture.width() % u16::from(char_width),
-            0,
-            "Font width {} is not multiple of char width {}",
-            texture.width(),
-            char_width,
-        );
+    /// The same pixels as the texture, for drawing glyphs without the GPU.
+    pub fn glyphs(&self) -> &GlyphSheet {
+        &self.glyphs
+    }
 
-        assert_eq!(
-            texture.height() % u16::from(char_height),
-            0,
-            "Font height {} is not multiple of char height {}",
-            texture.height(),
-            char_height,
-        );
+    pub fn load(ctx: &mut Context) -> Fallible<Self> {
+        // Decoded here rather than by ggez so that the pixels can be kept
+        let mut bytes = Vec::new();
+        filesystem::open(ctx, FONT_PATH)
+            .and_then(|mut file| Ok(file.read_to_end(&mut bytes)?))
+            .map_err(|e| {
+                format_err!(
+                    "Failed to load the font {} from {} or any other resource path: {}",
+                    FONT_PATH,
+                    filesystem::resources_dir(ctx).display(),
+                    e
+                )
+            })?;
+        let pixels = image::load_from_memory(&bytes)
+            .map_err(|e| format_err!("Failed to decode the font {}: {}", FONT_PATH, e))?
+            .to_rgba8();
+
+        let glyphs = GlyphSheet::new(pixels, 8, 8)?;
+        let (width, height) = glyphs.pixels.dimensions();
+        let texture =
+            Image::from_rgba8(ctx, width.try_into()?, height.try_into()?, &glyphs.pixels)?;
 
         Ok(Self {
             texture,
-            char_width,
-            char_height,
+            glyphs: Rc::new(glyphs),
         })
     }
 
     pub fn get_src_rect(&self, index: u16) -> Rect {
         let font_width = self.texture.width();
         let font_height = self.texture.height();
-        let float_char_width = self.char_width as f32 / font_width as f32;
-        let float_char_height = self.char_height as f32 / font_height as f32;
+        let float_char_width = self.char_width() as f32 / font_width as f32;
+        let float_char_height = self.char_height() as f32 / font_height as f32;
 
         let charset_width = self.charset_width();
 
@@ -66,19 +78,64 @@ impl KataFont {
     }
 
     pub fn char_width(&self) -> u8 {
-        self.char_width
+        self.glyphs.char_width
     }
 
     pub fn char_height(&self) -> u8 {
-        self.char_height
+        self.glyphs.char_height
     }
 
     pub fn charset_width(&self) -> u16 {
-        self.texture.width() / u16::from(self.char_width)
+        self.texture.width() / u16::from(self.char_width())
     }
 
     pub fn charset_height(&self) -> u16 {
-        self.texture.height() / u16::from(self.char_height)
+        self.texture.height() / u16::from(self.char_height())
+    }
+}
+
+/// The font's pixels, laid out in a grid of glyphs like the texture.
+#[derive(Clone, Debug)]
+pub struct GlyphSheet {
+    pixels: RgbaImage,
+    char_width: u8,
+    char_height: u8,
+}
+
+impl GlyphSheet {
+    pub fn new(pixels: RgbaImage, char_width: u8, char_height: u8) -> Fallible<Self> {
+        check_glyph_grid(pixels.width(), pixels.height(), char_width, char_height)?;
+
+        Ok(Self {
+            pixels,
+            char_width,
+            char_height,
+        })
+    }
+
+    pub fn char_width(&self) -> u8 {
+        self.char_width
+    }
+
+    pub fn char_height(&self) -> u8 {
+        self.char_height
+    }
+
+    /// The pixel at `(x, y)` within glyph `index`, or transparent if there's no such glyph.
+    pub fn pixel(&self, index: u16, x: u32, y: u32) -> Rgba<u8> {
+        let char_width = u32::from(self.char_width);
+        let char_height = u32::from(self.char_height);
+        let columns = self.pixels.width() / char_width;
+        let rows = self.pixels.height() / char_height;
+
+        let (column, row) = (u32::from(index) % columns, u32::from(index) / columns);
+        if row >= rows || x >= char_width || y >= char_height {
+            return Rgba([0, 0, 0, 0]);
+        }
+
+        *self
+            .pixels
+            .get_pixel(column * char_width + x, row * char_height + y)
     }
 }
 
@@ -116,6 +173,10 @@ impl KataFontBatch {
         self.scaling
     }
 
+    pub fn font(&self) -> &KataFont {
+        &self.font
+    }
+
     pub fn tile_width(&self) -> f32 {
         self.scaling * self.font.char_width() as f32
     }
@@ -128,16 +189,27 @@ impl KataFontBatch {
     where
         P: Into<mint::Point2<u32>>,
     {
+        self.add_magnified(voxel, dest, 1);
+    }
+
+    /// Adds `voxel` drawn `magnification` times as big, covering that many tiles either way from
+    /// `dest`.
+    pub fn add_magnified<P>(&mut self, voxel: &Voxel2, dest: P, magnification: u32)
+    where
+        P: Into<mint::Point2<u32>>,
+    {
+        let scaling = self.scaling * magnification as f32;
         let mirror_scale = voxel.mirror.into_scale();
         let dest = dest.into();
+        // Rotated and mirrored about the middle of the tiles, so they stay where they're put
+        let half = magnification as f32 / 2.0;
         let dest = mint::Point2::from([
-            dest.x as f32 * self.tile_width(),
-            dest.y as f32 * self.tile_height(),
+            (dest.x as f32 + half) * self.tile_width(),
+            (dest.y as f32 + half) * self.tile_height(),
         ]);
 
-        let scale =
-            mint::Vector2::from([mirror_scale.x * self.scaling, mirror_scale.y * self.scaling]);
-        let offset = mint::Point2::from([0.0, 0.0]);
+        let scale = mint::Vector2::from([mirror_scale.x * scaling, mirror_scale.y * scaling]);
+        let offset = mint::Point2::from([0.5, 0.5]);
 
         self.fg_batch.add(
             DrawParam::new()
@@ -202,3 +274,35 @@ impl Drawable for KataFontBatch {
         fg_mode
     }
 }
+
+/// Checks that a font of `width` by `height` pixels splits evenly into glyphs.
+fn check_glyph_grid(width: u32, height: u32, char_width: u8, char_height: u8) -> Fallible<()> {
+    ensure!(
+        width % u32::from(char_width) == 0,
+        "Font width {} is not a multiple of char width {}",
+        width,
+        char_width
+    );
+    ensure!(
+        height % u32::from(char_height) == 0,
+        "Font height {} is not a multiple of char height {}",
+        height,
+        char_height
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_glyph_grid() {
+        assert!(check_glyph_grid(128, 64, 8, 8).is_ok());
+
+        let error = check_glyph_grid(130, 64, 8, 8).unwrap_err().to_string();
+        assert!(error.contains("width 130"));
+        assert!(check_glyph_grid(128, 60, 8, 8).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use std::cmp::Ordering;
+
+use float_ord::FloatOrd;
+use log::trace;
+use na::Point3;
+
+use crate::{rendering::lighting::*, world::World};
+
+/// A tile the eye can see. These sort furthest first, so nearer tiles are drawn over them.
+#[derive(Copy, Clone, Debug)]
+pub struct VisibleCell {
+    pub cell: Point3<u16>,
+    pub dist_from_eye: f32,
+}
+
+impl VisibleCell {
+    pub fn index(&self) -> [usize; 3] {
+        [
+            usize::from(self.cell.x),
+            usize::from(self.cell.y),
+            usize::from(self.cell.z),
+        ]
+    }
+
+    fn cell_key(&self) -> (u16, u16, u16) {
+        (self.cell.x, self.cell.y, self.cell.z)
+    }
+}
+
+impl Eq for VisibleCell {}
+
+impl PartialEq for VisibleCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl PartialOrd for VisibleCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for VisibleCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        FloatOrd(other.dist_from_eye)
+            .cmp(&FloatOrd(self.dist_from_eye))
+            .then_with(|| self.cell_key().cmp(&other.cell_key()))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct FovKey {
+    eye_cell: Point3<usize>,
+    revision: u64,
+    range: usize,
+}
+
+/// Remembers what the eye could see last time, so that it only has to be cast again once the
+/// eye moves to another cell, the sight range changes or a tile is changed. Looking around
+/// doesn't change what can be seen, only which of it is on screen.
+#[derive(Default)]
+pub struct FovCache {
+    key: Option<FovKey>,
+    cells: Vec<VisibleCell>,
+    casts: u64,
+}
+
+impl FovCache {
+    /// Every cell that can be seen from `eye_cell` out to `range`, once each and furthest
+    /// first. This includes air and unlit tiles, which can't be drawn but can be lit later.
+    pub fn visible(
+        &mut self,
+        world: &mut World,
+        eye_cell: Point3<usize>,
+        range: usize,
+        scratch: &mut ShadowcastScratch,
+    ) -> &[VisibleCell] {
+        let key = FovKey {
+            eye_cell,
+            revision: world.revision(),
+            range,
+        };
+
+        if self.key != Some(key) {
+            self.cast(world, key, scratch);
+        }
+
+        &self.cells
+    }
+
+    /// How many times the cache has had to cast, rather than reusing the last one.
+    pub fn casts(&self) -> u64 {
+        self.casts
+    }
+
+    fn cast(&mut self, world: &mut World, key: FovKey, scratch: &mut ShadowcastScratch) {
+        self.cells = cast_fov(
+            world.tiles_mut().view_mut(),
+            key.eye_cell,
+            key.eye_cell.map(|c| c as f32),
+            key.range,
+            LightShape::Sphere,
+            scratch,
+            |t, dist_from_eye| VisibleCell {
+                cell: t.pos.map(|c| c as u16),
+                dist_from_eye,
+            },
+        );
+        let cells = &mut self.cells;
+
+        // The scans overlap, so the same tile can turn up more than once, always at the same
+        // distance, which sorting puts next to each other.
+        let visited = cells.len();
+        cells.sort_unstable();
+        cells.dedup_by_key(|visible| visible.cell);
+        trace!("{} cells were seen more than once", visited - cells.len());
+
+        self.key = Some(key);
+        self.casts += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{constants::*, rendering::tile::*};
+
+    fn world() -> World {
+        let tiles = tiles_from_fn((16, 16, 16), |(_, y, _)| {
+            if y == 0 {
+                TileType::Rock0
+            } else {
+                TileType::Air
+            }
+        });
+
+        World::new(tiles, Vec::new())
+    }
+
+    #[test]
+    fn test_visible_sorted_and_unique() {
+        let mut world = world();
+        let mut cache = FovCache::default();
+        let mut scratch = ShadowcastScratch::new(MAX_CAST_RANGE);
+
+        let visible = cache.visible(&mut world, Point3::new(8, 4, 8), 6, &mut scratch);
+
+        assert!(visible.windows(2).all(|w| w[0] < w[1]));
+        assert!(visible.iter().any(|v| v.cell == Point3::new(8, 0, 8)));
+        assert_eq!(visible.last().map(|v| v.cell), Some(Point3::new(8, 4, 8)));
+
+        let mut cells: Vec<_> = visible.iter().map(|v| v.cell_key()).collect();
+        cells.sort();
+        cells.dedup();
+        assert_eq!(cells.len(), visible.len());
+    }
+
+    #[test]
+    fn test_recasts_when_key_changes() {
+        let mut world = world();
+        let mut cache = FovCache::default();
+        let mut scratch = ShadowcastScratch::new(MAX_CAST_RANGE);
+        let eye = Point3::new(8, 4, 8);
+
+        let first = cache.visible(&mut world, eye, 6, &mut scratch).to_vec();
+        cache.visible(&mut world, eye, 6, &mut scratch);
+        assert_eq!(cache.casts(), 1);
+
+        // Editing a tile, even one out of sight
+        assert!(world.set_tile(Point3::new(0, 15, 0), TileType::Rock0));
+        assert_eq!(cache.visible(&mut world, eye, 6, &mut scratch), &first[..]);
+        assert_eq!(cache.casts(), 2);
+
+        cache.visible(&mut world, Point3::new(9, 4, 8), 6, &mut scratch);
+        assert_eq!(cache.casts(), 3);
+
+        cache.visible(&mut world, Point3::new(9, 4, 8), 8, &mut scratch);
+        cache.visible(&mut world, Point3::new(9, 4, 8), 8, &mut scratch);
+        assert_eq!(cache.casts(), 4);
+    }
+
+    #[test]
+    fn test_edit_in_sight_changes_visible() {
+        let mut world = world();
+        let mut cache = FovCache::default();
+        let mut scratch = ShadowcastScratch::new(MAX_CAST_RANGE);
+        let eye = Point3::new(8, 4, 8);
+
+        let before = cache.visible(&mut world, eye, 6, &mut scratch).to_vec();
+        assert!(before.iter().any(|v| v.cell == Point3::new(8, 0, 8)));
+
+        // Seal the eye in
+        for x in 7..=9 {
+            for y in 3..=5 {
+                for z in 7..=9 {
+                    if (x, y, z) != (8, 4, 8) {
+                        world.set_tile(Point3::new(x, y, z), TileType::Rock0);
+                    }
+                }
+            }
+        }
+
+        let after = cache.visible(&mut world, eye, 6, &mut scratch);
+        assert!(after.len() < before.len());
+        assert!(!after.iter().any(|v| v.cell == Point3::new(8, 0, 8)));
+    }
+}
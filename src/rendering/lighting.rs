@@ -0,0 +1,691 @@
+use std::f32::consts::FRAC_PI_4;
+
+use ggez::graphics::Color;
+use na::*;
+use ndarray::prelude::*;
+use rayon::prelude::*;
+
+use crate::{
+    constants::*,
+    geometry::aabb::IAabb3,
+    rendering::{drawable::Drawable, tile::*},
+    util::*,
+};
+
+/// Somewhere to keep the scans still to be done while shadowcasting, so that it doesn't have to
+/// be allocated again for every light. There's one for each octant, since they're cast at the
+/// same time.
+#[derive(Debug, Default)]
+pub struct ShadowcastScratch {
+    octants: [OctantScratch; 8],
+}
+
+impl ShadowcastScratch {
+    /// Reserves enough for a fairly broken up octant of `range` without growing.
+    pub fn new(range: usize) -> Self {
+        let mut scratch = Self::default();
+        for octant in &mut scratch.octants {
+            octant.frontier.reserve(range * range);
+        }
+
+        scratch
+    }
+
+    /// How many scans each octant can hold without growing.
+    pub fn capacity(&self) -> usize {
+        self.octants
+            .iter()
+            .map(|octant| octant.frontier.capacity())
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+/// The scans still to be done in one octant.
+#[derive(Debug, Default)]
+pub struct OctantScratch {
+    frontier: Vec<Shadowcast>,
+}
+
+/// Resets the illumination of every tile and then lights them from each of `lights`, out to
+/// `range` tiles away, or less for a light in a tile whose emission doesn't reach as far. Lights
+/// outside of `tiles` are skipped.
+pub fn compute_lighting(
+    tiles: &mut Array3<Tile>,
+    lights: &[(Point3<usize>, Color)],
+    range: usize,
+    scratch: &mut ShadowcastScratch,
+) {
+    assert!(range <= MAX_CAST_RANGE, "Light range {} is too far", range);
+
+    //TODO: remove the necessity for this by having each light keep track of affected tiles
+    //and have light add/remove illumination as necessary
+    tiles
+        .par_iter_mut()
+        .for_each(|tile| tile.illumination_color = Color::BLACK);
+
+    for &(light_pos, light_color) in lights {
+        if !is_in_array(tiles.view(), light_pos) {
+            continue;
+        }
+
+        let range = emission_range(&tiles[[light_pos.x, light_pos.y, light_pos.z]], range);
+        cast_light(tiles.view_mut(), light_pos, light_color, range, scratch);
+    }
+}
+
+/// What the tiles were last lit with, so that lighting them again can be skipped while neither
+/// the lights nor the tiles have changed.
+#[derive(Debug, Default)]
+pub struct LightingCache {
+    lit_with: Option<(Vec<(Point3<usize>, Color)>, usize)>,
+}
+
+impl LightingCache {
+    /// Makes the next `relight` light the tiles whatever they were lit with, e.g. because some of
+    /// them changed.
+    pub fn nuke(&mut self) {
+        self.lit_with = None;
+    }
+
+    /// Lights `tiles` like `compute_lighting` does, unless they were last lit with the same
+    /// `lights` and `range` and haven't been nuked since. Returns whether they were lit.
+    pub fn relight(
+        &mut self,
+        tiles: &mut Array3<Tile>,
+        lights: &[(Point3<usize>, Color)],
+        range: usize,
+        scratch: &mut ShadowcastScratch,
+    ) -> bool {
+        if let Some((lit_lights, lit_range)) = &self.lit_with {
+            if lit_lights.as_slice() == lights && *lit_range == range {
+                return false;
+            }
+        }
+
+        compute_lighting(tiles, lights, range, scratch);
+        self.lit_with = Some((lights.to_vec(), range));
+        true
+    }
+}
+
+/// How far a light in `tile` reaches, which is less than `range` if the tile gives off light
+/// that doesn't reach as far.
+fn emission_range(tile: &Tile, range: usize) -> usize {
+    tile.tile_type
+        .emission()
+        .map_or(range, |(_, emission_range)| emission_range.min(range))
+}
+
+/// Lights `tiles` from a light at `light_pos`. The octants don't share any tiles, so they're lit
+/// at the same time.
+fn cast_light(
+    tiles: ArrayViewMut3<Tile>,
+    light_pos: Point3<usize>,
+    light_color: Color,
+    range: usize,
+    scratch: &mut ShadowcastScratch,
+) {
+    let mut octs = split_shadowcast_octants(tiles, light_pos, range);
+
+    octs.par_iter_mut()
+        .zip(scratch.octants.par_iter_mut())
+        .for_each(|(o, scratch)| {
+            shadowcast_octant(
+                o.0.view_mut(),
+                o.1,
+                range,
+                LightShape::Sphere,
+                light_pos.map(|c| c as f32),
+                scratch,
+                |t, (x, y, z)| {
+                    t.illumination_color = combine_light_colors(
+                        scale_color(
+                            light_color,
+                            1.0 - (EUCLIDEAN_DISTANCE_LOOKUP[[x, y, z]] / range as f32).min(1.0),
+                        ),
+                        t.illumination_color,
+                    );
+                },
+            )
+        });
+}
+
+/// Casts out to `range` tiles away from an eye at `eye`, which is in `eye_cell`, calling `f`
+/// with every tile it can see and how far away that is, and gives back what `f` returned for
+/// each. The octants are cast at the same time, each into a list of its own, and the lists are
+/// put together afterwards. The scans within an octant overlap, so `f` can see the same tile
+/// more than once.
+pub fn cast_fov<T, F>(
+    tiles: ArrayViewMut3<Tile>,
+    eye_cell: Point3<usize>,
+    eye: Point3<f32>,
+    range: usize,
+    shape: LightShape,
+    scratch: &mut ShadowcastScratch,
+    f: F,
+) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&Tile, f32) -> T + Sync,
+{
+    assert!(range <= MAX_CAST_RANGE, "Sight range {} is too far", range);
+
+    let mut octs = split_shadowcast_octants(tiles, eye_cell, range);
+
+    let seen: Vec<Vec<T>> = octs
+        .par_iter_mut()
+        .zip(scratch.octants.par_iter_mut())
+        .map(|(o, scratch)| {
+            let mut seen = Vec::new();
+            shadowcast_octant(
+                o.0.view_mut(),
+                o.1,
+                range,
+                shape,
+                eye,
+                scratch,
+                |t, (x, y, z)| seen.push(f(t, EUCLIDEAN_DISTANCE_LOOKUP[[x, y, z]])),
+            );
+            seen
+        })
+        .collect();
+
+    seen.into_iter().flatten().collect()
+}
+
+pub fn shadowcast_octant<F>(
+    mut slice: ArrayViewMut3<Tile>,
+    (x_sign, y_sign, z_sign): (bool, bool, bool),
+    cast_range: usize,
+    shape: LightShape,
+    source_pos: Point3<f32>,
+    scratch: &mut OctantScratch,
+    mut f: F,
+) where
+    F: FnMut(&mut Tile, (usize, usize, usize)),
+{
+    if !slice.is_empty() {
+        if !x_sign {
+            slice.invert_axis(Axis(0));
+        }
+        if !y_sign {
+            slice.invert_axis(Axis(1));
+        }
+        if !z_sign {
+            slice.invert_axis(Axis(2));
+        }
+
+        for i in 0..3 {
+            let permuted_slice = slice
+                .view_mut()
+                .permuted_axes((i, (i + 1) % 3, (i + 2) % 3));
+
+            scan_recursive_shadowcast(
+                permuted_slice,
+                cast_range,
+                shape,
+                source_pos,
+                &mut scratch.frontier,
+                &mut f,
+            );
+            // iterate_recursive_shadowcast(permuted_slice, 0.0, FRAC_PI_4, 0.0, FRAC_PI_4, 0);
+
+            // let pslice_width = permuted_slice.dim().0;
+            // let pslice_height = permuted_slice.dim().1;
+
+            // for (z, mut sub_slice) in permuted_slice.axis_iter_mut(Axis(2)).enumerate() {
+            //     for ((x, y), tile) in sub_slice.slice_mut(s![..z.min(pslice_width), ..z.min(pslice_height)]).indexed_iter_mut() {
+            //         // tile.illumination = 1.0 - ((x + y + z) as f32 / total_len as f32);
+            //         tile.illumination = 1.0 / z as f32;
+            //     }
+            // }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum LightShape {
+    Sphere,
+    Cone {
+        facing: UnitVector3<f32>,
+        width_angle: f32,
+    },
+}
+
+impl LightShape {
+    pub fn contains(&self, pos: Point3<f32>) -> bool {
+        match self {
+            Self::Sphere => true,
+            Self::Cone {
+                facing,
+                width_angle,
+            } => {
+                if let Some(unit) = Unit::try_new(pos.coords, 1.0) {
+                    facing.into_inner().angle(&unit.into_inner()) < *width_angle
+                } else {
+                    true
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shadowcast {
+    left_angle: f32,
+    right_angle: f32,
+    top_angle: f32,
+    bottom_angle: f32,
+    z: usize,
+}
+
+fn scan_recursive_shadowcast<F>(
+    mut slice: ArrayViewMut3<Tile>,
+    cast_range: usize,
+    shape: LightShape,
+    source_pos: Point3<f32>,
+    frontier: &mut Vec<Shadowcast>,
+    mut f: F,
+) where
+    F: FnMut(&mut Tile, (usize, usize, usize)),
+{
+    frontier.clear();
+
+    frontier.push(Shadowcast {
+        left_angle: 0.0,
+        right_angle: FRAC_PI_4,
+        top_angle: 0.0,
+        bottom_angle: FRAC_PI_4,
+        z: 0,
+    });
+
+    let (slice_width, slice_height, slice_depth) = slice.dim();
+
+    while let Some(current) = frontier.pop() {
+        let left = ((current.z + 1) as f32 * current.left_angle.tan()).floor() as usize;
+        let right = (((current.z + 1) as f32 * current.right_angle.tan()).ceil() as usize)
+            .min(slice_width.saturating_sub(1));
+        let top = ((current.z + 1) as f32 * current.top_angle.tan()).floor() as usize;
+        let bottom = (((current.z + 1) as f32 * current.bottom_angle.tan()).ceil() as usize)
+            .min(slice_height.saturating_sub(1));
+
+        let mut last_top = None;
+
+        'y_loop: for y in top..=bottom {
+            let mut last_left = None;
+
+            for x in left..=right {
+                let dist_from_center = EUCLIDEAN_DISTANCE_LOOKUP[[x, y, current.z]];
+                let outside_range = dist_from_center >= cast_range as f32;
+
+                if outside_range {
+                    if current.z < slice_depth - 1 {
+                        // At the end of each row, we check if there's any clear tiles
+                        if let Some(last_left) = last_left {
+                            frontier.push(Shadowcast {
+                                left_angle: ATAN_CASTING_LOOKUP[[last_left, current.z]],
+                                //(last_left as f32 / (current.z + 1) as f32).atan(),
+                                right_angle: ATAN_CASTING_LOOKUP[[x, current.z]],
+                                top_angle: ATAN_CASTING_LOOKUP[[y, current.z]],
+                                //(y as f32 / (current.z + 1) as f32).atan(),
+                                bottom_angle: ATAN_CASTING_LOOKUP[[y + 1usize, current.z]],
+                                //((y + 1usize) as f32 / (current.z + 1) as f32).atan(),
+                                z: current.z + 1,
+                            });
+                        }
+
+                        if let Some(last_top) = last_top.take() {
+                            frontier.push(Shadowcast {
+                                left_angle: current.left_angle,
+                                right_angle: current.right_angle,
+                                top_angle: ATAN_CASTING_LOOKUP[[last_top, current.z]],
+                                //(last_top as f32 / (current.z + 1) as f32).atan(),
+                                bottom_angle: ATAN_CASTING_LOOKUP[[y, current.z]],
+
+                                z: current.z + 1,
+                            });
+                        }
+                    }
+
+                    continue 'y_loop;
+                }
+
+                let tile = &mut slice[[x, y, current.z]];
+
+                let in_shape = shape.contains(Point3::from(tile.pos - source_pos));
+
+                if in_shape {
+                    f(tile, (x, y, current.z));
+                }
+
+                // If we're on the last layer, we don't worry about bookkeeping for recursion
+                if current.z < slice_depth - 1 {
+                    if tile.tile_type.is_transparent() && in_shape {
+                        last_left = Some(last_left.unwrap_or(x));
+                    } else {
+                        let tile_top_angle = ATAN_CASTING_LOOKUP[[y, current.z]];
+                        //(y as f32 / (current.z + 1) as f32).atan();
+                        let tile_bottom_angle = ATAN_CASTING_LOOKUP[[y + 1usize, current.z]];
+                        //((y + 1usize) as f32 / (current.z + 1) as f32).atan();
+                        let tile_left_angle = ATAN_CASTING_LOOKUP[[x, current.z]];
+                        //(x as f32 / (current.z + 1) as f32).atan();
+
+                        if let Some(last_top) = last_top.take() {
+                            frontier.push(Shadowcast {
+                                left_angle: current.left_angle,
+                                right_angle: current.right_angle,
+                                top_angle: ATAN_CASTING_LOOKUP[[last_top, current.z]],
+                                //(last_top as f32 / (current.z + 1) as f32).atan(),
+                                bottom_angle: tile_top_angle,
+
+                                z: current.z + 1,
+                            });
+                        }
+
+                        if let Some(last_left) = last_left.take() {
+                            frontier.push(Shadowcast {
+                                left_angle: ATAN_CASTING_LOOKUP[[last_left, current.z]],
+                                //(last_left as f32 / (current.z + 1) as f32).atan(),
+                                right_angle: tile_left_angle,
+                                top_angle: tile_top_angle,
+                                bottom_angle: tile_bottom_angle,
+                                z: current.z + 1,
+                            });
+                        }
+                    }
+                }
+            }
+
+            // At the end of each row, we check if there's any clear tiles
+            if let Some(last_left) = last_left {
+                if current.z < slice_depth - 1 {
+                    if last_left == left {
+                        // The whole row is clear
+                        last_top = Some(last_top.unwrap_or(y));
+                    } else {
+                        frontier.push(Shadowcast {
+                            left_angle: ATAN_CASTING_LOOKUP[[last_left, current.z]],
+                            //(last_left as f32 / (current.z + 1) as f32).atan(),
+                            right_angle: current.right_angle,
+                            top_angle: ATAN_CASTING_LOOKUP[[y, current.z]],
+                            //(y as f32 / (current.z + 1) as f32).atan(),
+                            bottom_angle: ATAN_CASTING_LOOKUP[[y + 1usize, current.z]],
+                            //((y + 1usize) as f32 / (current.z + 1) as f32).atan(),
+                            z: current.z + 1,
+                        });
+                    }
+                }
+            }
+        }
+
+        // At the end of each scan, we check if there's any clear rows
+        if let Some(last_top) = last_top {
+            if current.z < slice_depth - 1 {
+                if last_top == top {
+                    // The whole scan is clear
+                    frontier.push(Shadowcast {
+                        left_angle: current.left_angle,
+                        right_angle: current.right_angle,
+                        top_angle: current.top_angle,
+                        bottom_angle: current.bottom_angle,
+
+                        z: current.z + 1,
+                    });
+                } else {
+                    frontier.push(Shadowcast {
+                        left_angle: current.left_angle,
+                        right_angle: current.right_angle,
+                        top_angle: ATAN_CASTING_LOOKUP[[last_top, current.z]],
+                        //(last_top as f32 / (current.z + 1) as f32).atan(),
+                        bottom_angle: current.top_angle,
+
+                        z: current.z + 1,
+                    });
+                }
+            }
+        }
+    }
+}
+
+pub fn split_shadowcast_octants<'a>(
+    mut tile_array: ArrayViewMut3<'a, Tile>,
+    origin: Point3<usize>,
+    cast_range: usize,
+) -> [(ArrayViewMut3<'a, Tile>, (bool, bool, bool)); 8] {
+    let [xs, ys, zs] = IAabb3::from_center_radius(
+        Point3::new(origin.x as i32, origin.y as i32, origin.z as i32),
+        cast_range as i32,
+    )
+    .clamp_to(&IAabb3::from_dim(tile_array.dim()))
+    .ranges();
+
+    let mid_x = origin.x - xs.start;
+    let mid_y = origin.y - ys.start;
+    let mid_z = origin.z - zs.start;
+
+    let light_cube = tile_array.slice_move(s![xs, ys, zs]);
+
+    let (bx, tx) = light_cube.split_at(Axis(0), mid_x);
+
+    let (bxby, bxty) = bx.split_at(Axis(1), mid_y);
+    let (txby, txty) = tx.split_at(Axis(1), mid_y);
+
+    let (bxbybz, bxbytz) = bxby.split_at(Axis(2), mid_z);
+    let (bxtybz, bxtytz) = bxty.split_at(Axis(2), mid_z);
+    let (txbybz, txbytz) = txby.split_at(Axis(2), mid_z);
+    let (txtybz, txtytz) = txty.split_at(Axis(2), mid_z);
+
+    let octs = [
+        (bxbybz, (false, false, false)),
+        (bxbytz, (false, false, true)),
+        (bxtybz, (false, true, false)),
+        (bxtytz, (false, true, true)),
+        (txbybz, (true, false, false)),
+        (txbytz, (true, false, true)),
+        (txtybz, (true, true, false)),
+        (txtytz, (true, true, true)),
+    ];
+
+    octs
+}
+
+// fn iterate_recursive_shadowcast(mut slice: ArrayViewMut3<Tile>, top_angle: f32, bottom_angle: f32, left_angle: f32, right_angle: f32, z: usize) {
+//     let slice_width = slice.dim().0;
+//     let slice_height = slice.dim().1;
+
+//     let left = ((z + 1) as f32 * left_angle.tan()).floor() as usize;
+//     let right = ((z + 1) as f32 * right_angle.tan()).ceil() as usize;
+//     let top = ((z + 1) as f32 * top_angle.tan()).floor() as usize;
+//     let bottom = ((z + 1) as f32 * bottom_angle.tan()).ceil() as usize;
+
+//     // dbg!(left, right, top, bottom);
+
+//     if !slice.is_empty() {
+//         let (mut selected, mut remainder) = slice.split_at(Axis(2), 1);
+
+//         for ((x, y), tile) in selected.slice_mut(s![left..=right.min(slice_width.saturating_sub(1)), top..=bottom.min(slice_height.saturating_sub(1)), 0]).indexed_iter_mut() {
+//             tile.illumination = 1.0 / z as f32;
+
+//             if tile.tile_type.is_transparent() {
+//                 iterate_recursive_shadowcast(
+//                     remainder.view_mut(),
+//                     (y as f32 / (z + 1) as f32).atan(),
+//                     ((y + 1usize) as f32 / (z + 1) as f32).atan(),
+//                     (x as f32 / (z + 1) as f32).atan(),
+//                     ((x + 1usize) as f32 / (z + 1) as f32).atan(),
+//                     z + 1
+//                 );
+//             }
+//         }
+//     }
+// }
+
+pub fn combine_light_colors(a: Color, b: Color) -> Color {
+    Color {
+        r: a.r.max(b.r).min(1.0),
+        g: a.g.max(b.g).min(1.0),
+        b: a.b.max(b.b).min(1.0),
+        a: 1.0,
+    }
+}
+
+pub fn scale_color(color: Color, alpha: f32) -> Color {
+    Color {
+        r: color.r * alpha,
+        g: color.g * alpha,
+        b: color.b * alpha,
+        a: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A room of air with a wall across it at x = 3.
+    fn walled_room() -> Array3<Tile> {
+        tiles_from_fn((9, 9, 9), |(x, _, _)| {
+            if x == 3 {
+                TileType::Rock0
+            } else {
+                TileType::Air
+            }
+        })
+    }
+
+    fn illumination(tiles: &Array3<Tile>) -> Vec<(f32, f32, f32)> {
+        tiles
+            .iter()
+            .map(|t| {
+                let c = t.illumination_color;
+                (c.r, c.g, c.b)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_wall_casts_shadow() {
+        let mut tiles = walled_room();
+        let mut scratch = ShadowcastScratch::new(LIGHT_RANGE);
+
+        compute_lighting(
+            &mut tiles,
+            &[(Point3::new(1, 4, 4), Color::WHITE)],
+            LIGHT_RANGE,
+            &mut scratch,
+        );
+
+        assert!(tiles[[2, 4, 4]].illuminated());
+        assert!(tiles[[3, 4, 4]].illuminated());
+        assert!(!tiles[[5, 4, 4]].illuminated());
+    }
+
+    #[test]
+    fn test_lighting_cache() {
+        let mut tiles = walled_room();
+        let mut scratch = ShadowcastScratch::new(LIGHT_RANGE);
+        let mut cache = LightingCache::default();
+        let lights = [(Point3::new(1, 4, 4), Color::WHITE)];
+
+        assert!(cache.relight(&mut tiles, &lights, LIGHT_RANGE, &mut scratch));
+        let lit = illumination(&tiles);
+        assert!(!cache.relight(&mut tiles, &lights, LIGHT_RANGE, &mut scratch));
+        assert_eq!(illumination(&tiles), lit);
+
+        // Moving a light, changing the range or nuking it all light the tiles again
+        let moved = [(Point3::new(6, 4, 4), Color::WHITE)];
+        assert!(cache.relight(&mut tiles, &moved, LIGHT_RANGE, &mut scratch));
+        assert!(!tiles[[1, 4, 4]].illuminated());
+        assert!(cache.relight(&mut tiles, &moved, LIGHT_RANGE - 1, &mut scratch));
+        cache.nuke();
+        assert!(cache.relight(&mut tiles, &moved, LIGHT_RANGE - 1, &mut scratch));
+    }
+
+    #[test]
+    fn test_emission_range() {
+        let light = Point3::new(5, 4, 4);
+        // Five tiles away, inside the light range but not the candle's
+        let corner = [8, 4, 8];
+        assert!(TileType::Candle.emission().unwrap().1 < LIGHT_RANGE);
+
+        let mut tiles = walled_room();
+        let mut scratch = ShadowcastScratch::default();
+        compute_lighting(
+            &mut tiles,
+            &[(light, Color::WHITE)],
+            LIGHT_RANGE,
+            &mut scratch,
+        );
+        assert!(tiles[corner].illuminated());
+
+        tiles[[light.x, light.y, light.z]].tile_type = TileType::Candle;
+        compute_lighting(
+            &mut tiles,
+            &[(light, Color::WHITE)],
+            LIGHT_RANGE,
+            &mut scratch,
+        );
+        assert!(!tiles[corner].illuminated());
+        assert!(tiles[[6, 4, 4]].illuminated());
+    }
+
+    #[test]
+    fn test_reused_scratch_matches_fresh() {
+        let lights = [
+            (Point3::new(1, 4, 4), Color::RED),
+            (Point3::new(6, 2, 7), Color::BLUE),
+            (Point3::new(20, 0, 0), Color::GREEN),
+        ];
+
+        let mut fresh = walled_room();
+        compute_lighting(
+            &mut fresh,
+            &lights,
+            LIGHT_RANGE,
+            &mut ShadowcastScratch::default(),
+        );
+
+        // Left over from lighting something else entirely
+        let mut scratch = ShadowcastScratch::new(LIGHT_RANGE);
+        compute_lighting(
+            &mut walled_room(),
+            &[(Point3::new(4, 4, 4), Color::WHITE)],
+            LIGHT_RANGE,
+            &mut scratch,
+        );
+
+        let mut reused = walled_room();
+        compute_lighting(&mut reused, &lights, LIGHT_RANGE, &mut scratch);
+
+        assert_eq!(illumination(&reused), illumination(&fresh));
+        assert!(illumination(&reused)
+            .iter()
+            .any(|&(r, g, b)| r + g + b > 0.0));
+    }
+
+    #[test]
+    fn test_fov_sees_every_octant() {
+        let mut tiles = tiles_from_fn((9, 9, 9), |_| TileType::Air);
+        let eye = Point3::new(4, 4, 4);
+
+        let seen = cast_fov(
+            tiles.view_mut(),
+            eye,
+            eye.map(|c| c as f32),
+            4,
+            LightShape::Sphere,
+            &mut ShadowcastScratch::new(4),
+            |t, _| t.pos.map(|c| c as usize),
+        );
+
+        // Nothing is in the way, so every tile well inside the range turns up, on all sides
+        let near: Vec<_> = tiles
+            .indexed_iter()
+            .map(|((x, y, z), _)| Point3::new(x, y, z))
+            .filter(|pos| (pos.map(|c| c as f32) - eye.map(|c| c as f32)).norm() <= 3.0)
+            .collect();
+        assert!(near.len() > 8);
+        assert!(near.iter().all(|pos| seen.contains(pos)));
+    }
+}
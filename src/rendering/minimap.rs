@@ -0,0 +1,239 @@
+use std::f32::consts::PI;
+
+use ggez::{graphics::Color as GGColor, mint};
+use ndarray::prelude::*;
+
+use crate::{
+    rendering::{
+        color::{self, Color},
+        drawable::Drawable,
+        tile::{Tile, TileType},
+        voxel::{Voxel2, VoxelRotation},
+    },
+    ui::VoxelBatch,
+    world::{debug::tile_char, explored::Explored},
+};
+
+/// How many cells the minimap shows either way from the player.
+pub const MINIMAP_RADIUS: usize = 24;
+
+/// The arrow the player is shown as, pointing up before it's turned.
+const ARROW: u16 = 0x1E;
+
+/// What the player knows of the slice of the world at `center`'s height, `radius` cells either
+/// way from it, indexed `[x, z]` like the world. Cells they haven't seen, including any outside
+/// the world, are `None`.
+pub fn minimap(
+    tiles: ArrayView3<Tile>,
+    explored: &Explored,
+    center: [usize; 3],
+    radius: usize,
+) -> Array2<Option<Voxel2>> {
+    let [center_x, y, center_z] = center;
+    let side = radius * 2 + 1;
+
+    Array2::from_shape_fn((side, side), |(column, row)| {
+        let x = (center_x + column).checked_sub(radius)?;
+        let z = (center_z + row).checked_sub(radius)?;
+        minimap_cell(tiles, explored, [x, y, z])
+    })
+}
+
+/// How the cell at `index` looks from above, taking in the cells over and under it: walls the
+/// player could climb onto are fainter than ones that reach over their head, and drops are
+/// fainter than floor.
+pub fn minimap_cell(
+    tiles: ArrayView3<Tile>,
+    explored: &Explored,
+    index: [usize; 3],
+) -> Option<Voxel2> {
+    if !explored.is_explored(index) {
+        return None;
+    }
+
+    let [x, y, z] = index;
+    let tile_type = tiles.get(index)?.tile_type;
+    // Anything unseen above or below is taken to be rock, as is the edge of the world
+    let solid = |y: Option<usize>| {
+        y.filter(|&y| explored.is_explored([x, y, z]))
+            .and_then(|y| tiles.get([x, y, z]))
+            .map_or(true, |tile| !tile.tile_type.is_transparent())
+    };
+
+    let (glyph, color) = if !tile_type.is_transparent() {
+        let color = tile_color(tile_type);
+        if solid(Some(y + 1)) {
+            ('#', color)
+        } else {
+            ('#', color.dimmed(0.5))
+        }
+    } else if tile_type != TileType::Air {
+        (tile_char(tile_type), tile_color(tile_type))
+    } else if solid(y.checked_sub(1)) {
+        ('.', color::GRAY)
+    } else {
+        ('.', color::LIGHT_GRAY.dimmed(0.5))
+    };
+
+    Some(Voxel2::new(glyph as u16).foreground(color))
+}
+
+/// The player as an arrow pointing the way they're facing, for a `yaw` like `Entity::facing.x`.
+pub fn player_arrow(yaw: f32) -> Voxel2 {
+    // A yaw of 0 faces +z, which is down the map, and turning faces +x, which is to the right
+    let quarter_turns = ((PI - yaw) / (PI / 2.0)).round().rem_euclid(4.0) as u8;
+    let rotation = match quarter_turns {
+        1 => VoxelRotation::Rotation90,
+        2 => VoxelRotation::Rotation180,
+        3 => VoxelRotation::Rotation270,
+        _ => VoxelRotation::None,
+    };
+
+    Voxel2::new(ARROW)
+        .foreground(color::YELLOW)
+        .rotation(rotation)
+}
+
+/// Draws `map` with its top left at `origin` over a black background, with `arrow` in the
+/// middle.
+pub fn draw_minimap(
+    batch: &mut dyn VoxelBatch,
+    map: ArrayView2<Option<Voxel2>>,
+    arrow: &Voxel2,
+    origin: mint::Point2<u32>,
+) {
+    let blank = Voxel2::new(0);
+    let background = Some(color::BLACK);
+
+    for ((column, row), cell) in map.indexed_iter() {
+        let voxel = cell.as_ref().unwrap_or(&blank).clone();
+        batch.add(
+            &voxel.background(background),
+            [origin.x + column as u32, origin.y + row as u32].into(),
+        );
+    }
+
+    let (columns, rows) = map.dim();
+    batch.add(
+        arrow,
+        [origin.x + columns as u32 / 2, origin.y + rows as u32 / 2].into(),
+    );
+}
+
+fn tile_color(tile_type: TileType) -> Color {
+    let GGColor { r, g, b, .. } = tile_type.get_color();
+    let channel = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+    Color::new(channel(r), channel(g), channel(b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        rendering::fov::VisibleCell,
+        ui::{Size, TextGridBatch},
+    };
+    use na::Point3;
+
+    /// A 4x3x3 world with rock along the bottom and at x = 0, a candle, a hole in the floor and
+    /// a ledge at x = 3 with nothing over it.
+    fn tiles() -> Array3<Tile> {
+        Array3::from_shape_fn((4, 3, 3), |(x, y, z)| Tile {
+            pos: Point3::new(x as f32, y as f32, z as f32),
+            illumination_color: GGColor::BLACK,
+            tile_type: match (x, y, z) {
+                (1, 0, 2) => TileType::Air,
+                (0, _, _) | (_, 0, _) => TileType::Rock0,
+                (3, 1, _) => TileType::Rock0,
+                (2, 1, 0) => TileType::Candle,
+                _ => TileType::Air,
+            },
+        })
+    }
+
+    fn explore(explored: &mut Explored, cells: &[(u16, u16, u16)]) {
+        let visible: Vec<_> = cells
+            .iter()
+            .map(|&(x, y, z)| VisibleCell {
+                cell: Point3::new(x, y, z),
+                dist_from_eye: 0.0,
+            })
+            .collect();
+        explored.mark_visible(&visible);
+    }
+
+    /// The map as a line for each z, with a space for each unknown cell.
+    fn map_lines(map: &Array2<Option<Voxel2>>) -> Vec<String> {
+        map.axis_iter(Axis(1))
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.as_ref().map_or(' ', |v| v.char_offset as u8 as char))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_minimap() {
+        let tiles = tiles();
+        let mut explored = Explored::new(tiles.dim());
+
+        // Nothing's been seen yet
+        let map = minimap(tiles.view(), &explored, [1, 1, 1], 1);
+        assert_eq!(map_lines(&map), vec!["   "; 3]);
+
+        let everything: Vec<_> = tiles
+            .indexed_iter()
+            .map(|((x, y, z), _)| (x as u16, y as u16, z as u16))
+            .collect();
+        explore(&mut explored, &everything);
+
+        // Centred next to the edge, so the left column is off the world
+        let map = minimap(tiles.view(), &explored, [0, 1, 1], 1);
+        assert_eq!(map_lines(&map), vec![" #.", " #.", " #."]);
+
+        let map = minimap(tiles.view(), &explored, [2, 1, 1], 1);
+        assert_eq!(map_lines(&map), vec![".i#", "..#", "..#"]);
+
+        let floor = map[[0, 1]].as_ref().unwrap().foreground;
+        let hole = map[[0, 2]].as_ref().unwrap().foreground;
+        assert_eq!(floor, color::GRAY);
+        assert_ne!(hole, floor);
+
+        // The ledge has air over it, while the wall at x = 0 reaches the top of the world
+        let ledge = map[[2, 1]].as_ref().unwrap().foreground;
+        let wall = minimap_cell(tiles.view(), &explored, [0, 1, 1])
+            .unwrap()
+            .foreground;
+        assert_eq!(ledge, wall.dimmed(0.5));
+    }
+
+    #[test]
+    fn test_player_arrow() {
+        let rotation = |yaw: f32| player_arrow(yaw).rotation;
+
+        assert_eq!(rotation(0.0), VoxelRotation::Rotation180);
+        assert_eq!(rotation(PI / 2.0), VoxelRotation::Rotation90);
+        assert_eq!(rotation(PI), VoxelRotation::None);
+        assert_eq!(rotation(-PI / 2.0), VoxelRotation::Rotation270);
+        // Close enough to a quarter turn, after a few laps
+        assert_eq!(rotation(4.0 * PI + 0.5), VoxelRotation::Rotation180);
+    }
+
+    #[test]
+    fn test_draw_minimap() {
+        let tiles = tiles();
+        let mut explored = Explored::new(tiles.dim());
+        explore(&mut explored, &[(2, 1, 0), (3, 1, 0)]);
+
+        let map = minimap(tiles.view(), &explored, [2, 1, 1], 1);
+        let mut batch = TextGridBatch::new(Size {
+            width: 5,
+            height: 4,
+        });
+        draw_minimap(&mut batch, map.view(), &player_arrow(PI), [1, 0].into());
+
+        // Unknown cells are blank but still cover whatever is under the map
+        assert_eq!(batch.lines(), vec![" .i# ", " .^. ", " ... ", "     "]);
+    }
+}
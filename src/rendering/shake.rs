@@ -0,0 +1,127 @@
+use na::Vector2;
+use rand::Rng;
+
+use crate::settings::PlayerConfig;
+
+/// Turns the view away from where the player is facing when they fire or land hard, dying away
+/// over the next few updates. Only the view moves, not where the player is aiming.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraShake {
+    /// The kick from recoil, as yaw and pitch like `Entity::facing`.
+    kick: Vector2<f32>,
+    /// The furthest the shake can move the view either way.
+    amplitude: f32,
+    /// Where the shake has moved the view to for this update.
+    jitter: Vector2<f32>,
+}
+
+impl CameraShake {
+    pub fn new() -> Self {
+        Self {
+            kick: Vector2::zeros(),
+            amplitude: 0.0,
+            jitter: Vector2::zeros(),
+        }
+    }
+
+    /// Kicks the view up and a little to one side for a shot.
+    pub fn fire<R>(&mut self, config: &PlayerConfig, rng: &mut R)
+    where
+        R: Rng,
+    {
+        if !config.screenshake {
+            return;
+        }
+
+        // Looking up is a lower pitch
+        self.kick += Vector2::new(
+            rng.gen_range(-1.0, 1.0) * config.recoil_yaw,
+            -config.recoil_pitch,
+        );
+        self.amplitude += config.fire_shake;
+    }
+
+    /// Shakes the view for landing after falling `height` tiles, if that's far enough.
+    pub fn land(&mut self, config: &PlayerConfig, height: f32) {
+        if !config.screenshake {
+            return;
+        }
+
+        self.amplitude += (height - config.hard_landing_height).max(0.0) * config.landing_shake;
+    }
+
+    /// Dies away by an update's worth and picks where the shake moves the view to next.
+    pub fn step<R>(&mut self, config: &PlayerConfig, rng: &mut R)
+    where
+        R: Rng,
+    {
+        self.kick *= config.shake_decay;
+        self.amplitude *= config.shake_decay;
+        self.jitter =
+            Vector2::new(rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)) * self.amplitude;
+    }
+
+    /// How far to turn the view from where the player is facing, as yaw and pitch.
+    pub fn offset(&self) -> Vector2<f32> {
+        self.kick + self.jitter
+    }
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_fire_kicks_up_and_dies_away() {
+        let config = PlayerConfig::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut shake = CameraShake::new();
+
+        shake.fire(&config, &mut rng);
+        shake.step(&config, &mut rng);
+        let kicked = shake.offset();
+        assert!(kicked.y < 0.0);
+        assert!(kicked.x.abs() <= config.recoil_yaw + config.fire_shake);
+
+        for _ in 0..100 {
+            shake.step(&config, &mut rng);
+        }
+        assert!(shake.offset().norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_landing() {
+        let config = PlayerConfig::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut shake = CameraShake::new();
+
+        shake.land(&config, config.hard_landing_height);
+        assert_eq!(shake, CameraShake::new());
+
+        shake.land(&config, config.hard_landing_height + 5.0);
+        shake.step(&config, &mut rng);
+        assert_ne!(shake.offset(), Vector2::zeros());
+    }
+
+    #[test]
+    fn test_turned_off() {
+        let config = PlayerConfig {
+            screenshake: false,
+            ..PlayerConfig::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut shake = CameraShake::new();
+
+        shake.fire(&config, &mut rng);
+        shake.land(&config, 100.0);
+        shake.step(&config, &mut rng);
+        assert_eq!(shake.offset(), Vector2::zeros());
+    }
+}
@@ -1,11 +1,13 @@
 use na::*;
+use ndarray::Array3;
 
-use crate::rendering::{drawable::*, font::*};
+use crate::rendering::{color, drawable::*, font::*};
 
 use ggez::graphics::{Color, Rect};
+use serde::{Deserialize, Serialize};
 
 //Using this as a stopgap for storing voxel information until it's split into its own struct/enum/whatever
-#[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize, Serialize)]
 pub enum TileType {
     Air,
     Rock0,
@@ -32,6 +34,16 @@ pub enum TileType {
     StockUpper,
     Grip,
     Glowstick,
+    Crystal,
+    Lava,
+    DoorClosed,
+    DoorOpen,
+    /// Opens or closes the door it's connected to through its `TileMetadata`.
+    Lever,
+    /// What the player is sent into the caves to find.
+    Artifact,
+    /// Marks where the player came in, which they have to get back to with the artifact.
+    Extraction,
 }
 
 impl TileType {
@@ -51,41 +63,100 @@ impl TileType {
             TileType::StockUpper => false,
             TileType::Stock => false,
             TileType::Glowstick => false,
+            TileType::Crystal => true,
+            TileType::Lava => true,
+            TileType::DoorClosed => true,
+            TileType::DoorOpen => false,
+            TileType::Lever => false,
+            TileType::Artifact => false,
+            TileType::Extraction => false,
             _ => todo!(),
         }
     }
+
+    /// The color and range of the light the tile gives off, if it gives off any.
+    pub fn emission(&self) -> Option<(Color, usize)> {
+        match self {
+            TileType::Candle => Some((color::WARM_LIGHT.into(), 5)),
+            TileType::Mushroom => Some((Color::new(0.55, 0.2, 0.8, 1.0), 4)),
+            TileType::Crystal => Some((color::COOL_LIGHT.into(), 7)),
+            TileType::Lava => Some((Color::new(1.0, 0.35, 0.05, 1.0), 8)),
+            TileType::Glowstick => Some((Color::new(0.2, 1.0, 0.3, 1.0), 6)),
+            TileType::Artifact => Some((Color::new(1.0, 0.85, 0.3, 1.0), 10)),
+            TileType::Extraction => Some((Color::new(0.3, 0.9, 0.6, 1.0), 3)),
+            _ => None,
+        }
+    }
+
+    /// Whether the player can do anything with it with the interact key.
+    pub fn is_interactable(&self) -> bool {
+        match self {
+            TileType::DoorClosed | TileType::DoorOpen | TileType::Lever | TileType::Artifact => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The glyph the tile is drawn with.
+    pub fn char_index(&self) -> u16 {
+        match self {
+            TileType::Air => 0,
+            TileType::Rock0 => 0x2B0,
+            TileType::Rock1 => 0x2B1,
+            TileType::Rock2 => 0x2B2,
+            TileType::Rock3 => 0x2B3,
+            TileType::Rock4 => 0x2B4,
+            TileType::Rock5 => 0x2B5,
+            TileType::Rock6 => 0x2B6,
+            TileType::Rock7 => 0x2B7,
+            TileType::Mushroom => 0x2E1,
+            TileType::Candle => 0x21A,
+            TileType::FrontSight => 0x211,
+            TileType::RearSight => 0x203,
+            TileType::GasBlock => 0x7C,
+            TileType::Barrel => 0x3A,
+            TileType::BarrelEnd => 0x2E9,
+            TileType::RecUpper => 0x2DD,
+            TileType::RecLower => 0x319,
+            TileType::RecLowerHalf => 0xDF,
+            TileType::RecLowerBack => 0x2C5,
+            TileType::Magazine => 0x1AB,
+            TileType::Stock => 0x319,
+            TileType::StockUpper => 0x2DD,
+            TileType::Grip => 0x283,
+            TileType::Glowstick => 0x2F,
+            TileType::Crystal => 0x04,
+            TileType::Lava => 0xF7,
+            TileType::DoorClosed => 0x2B,
+            TileType::DoorOpen => 0x27,
+            TileType::Lever => 0x21,
+            TileType::Artifact => 0x0F,
+            TileType::Extraction => 0x7F,
+        }
+    }
+
+    /// Whether it's one of the kinds of bare rock the caves are made of.
+    pub fn is_rock(&self) -> bool {
+        match self {
+            TileType::Rock0
+            | TileType::Rock1
+            | TileType::Rock2
+            | TileType::Rock3
+            | TileType::Rock4
+            | TileType::Rock5
+            | TileType::Rock6
+            | TileType::Rock7 => true,
+            _ => false,
+        }
+    }
 }
 
 impl Drawable for TileType {
     fn get_char_offset(&self, font: &KataFont) -> Rect {
-        match self {
-            TileType::Air => font.get_src_rect(0),
-            TileType::Rock0 => font.get_src_rect(0x2B0),
-            TileType::Rock1 => font.get_src_rect(0x2B1),
-            TileType::Rock2 => font.get_src_rect(0x2B2),
-            TileType::Rock3 => font.get_src_rect(0x2B3),
-            TileType::Rock4 => font.get_src_rect(0x2B4),
-            TileType::Rock5 => font.get_src_rect(0x2B5),
-            TileType::Rock6 => font.get_src_rect(0x2B6),
-            TileType::Rock7 => font.get_src_rect(0x2B7),
-            TileType::Mushroom => font.get_src_rect(0x2E1),
-            TileType::Candle => font.get_src_rect(0x21A),
-            TileType::FrontSight => font.get_src_rect(0x211),
-            TileType::RearSight => font.get_src_rect(0x203),
-            TileType::GasBlock => font.get_src_rect(0x7C),
-            TileType::Barrel => font.get_src_rect(0x3A),
-            TileType::BarrelEnd => font.get_src_rect(0x2E9),
-            TileType::RecUpper => font.get_src_rect(0x2DD),
-            TileType::RecLower => font.get_src_rect(0x319),
-            TileType::RecLowerHalf => font.get_src_rect(0xDF),
-            TileType::RecLowerBack => font.get_src_rect(0x2C5),
-            TileType::Magazine => font.get_src_rect(0x1AB),
-            TileType::Stock => font.get_src_rect(0x319),
-            TileType::StockUpper => font.get_src_rect(0x2DD),
-            TileType::Grip => font.get_src_rect(0x283),
-            TileType::Glowstick => font.get_src_rect(0x2F),
-        }
+        font.get_src_rect(self.char_index())
     }
+
     fn get_color(&self) -> Color {
         match self {
             TileType::Air => Color::new(0.0, 0.0, 0.0, 0.0),
@@ -102,6 +173,12 @@ impl Drawable for TileType {
             TileType::StockUpper => Color::new(0.75, 0.5, 0.25, 1.0),
             TileType::Stock => Color::new(0.75, 0.5, 0.25, 1.0),
             TileType::Glowstick => Color::GREEN,
+            TileType::Crystal => Color::new(0.6, 0.7, 1.0, 1.0),
+            TileType::Lava => Color::new(1.0, 0.4, 0.1, 1.0),
+            TileType::DoorClosed | TileType::DoorOpen => Color::new(0.55, 0.35, 0.15, 1.0),
+            TileType::Lever => Color::new(0.7, 0.7, 0.75, 1.0),
+            TileType::Artifact => Color::new(1.0, 0.85, 0.3, 1.0),
+            TileType::Extraction => Color::new(0.3, 0.9, 0.6, 1.0),
             _ => Color::new(0.25, 0.25, 0.25, 1.0),
         }
     }
@@ -132,15 +209,17 @@ impl Drawable for TileType {
             TileType::StockUpper => true,
             TileType::Grip => true,
             TileType::Glowstick => true,
+            TileType::Crystal => false,
+            TileType::Lava => false,
+            TileType::DoorClosed => false,
+            TileType::DoorOpen => true,
+            TileType::Lever => true,
+            TileType::Artifact => true,
+            TileType::Extraction => true,
         }
     }
     fn illuminates(&self) -> bool {
-        match self {
-            TileType::Mushroom => true,
-            TileType::Candle => true,
-            TileType::Glowstick => true,
-            _ => false,
-        }
+        self.emission().is_some()
     }
     fn rotation(&self) -> f32 {
         match self {
@@ -167,3 +246,68 @@ impl Tile {
             || self.illumination_color.b > 0.0
     }
 }
+
+/// Unlit tiles of `dim`, each at its index, of the type `f` gives for that index.
+pub fn tiles_from_fn<F>(dim: (usize, usize, usize), mut f: F) -> Array3<Tile>
+where
+    F: FnMut((usize, usize, usize)) -> TileType,
+{
+    Array3::from_shape_fn(dim, |(x, y, z)| Tile {
+        pos: Point3::new(x as f32, y as f32, z as f32),
+        illumination_color: Color::BLACK,
+        tile_type: f((x, y, z)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{constants::MAX_CAST_RANGE, rendering::lighting::scale_color};
+
+    const LIGHTS: [TileType; 7] = [
+        TileType::Candle,
+        TileType::Mushroom,
+        TileType::Crystal,
+        TileType::Lava,
+        TileType::Glowstick,
+        TileType::Artifact,
+        TileType::Extraction,
+    ];
+
+    #[test]
+    fn test_emissions_are_displayable() {
+        for &tile_type in &LIGHTS {
+            let (color, range) = tile_type.emission().unwrap();
+            assert!(tile_type.illuminates());
+            assert!(
+                (1..=MAX_CAST_RANGE).contains(&range),
+                "{:?} reaches {}",
+                tile_type,
+                range
+            );
+
+            // Lit from no distance at all up to the edge of the light, where it fades out
+            for distance in 0..=range {
+                let lit = scale_color(color, 1.0 - distance as f32 / range as f32);
+                let channels = [lit.r, lit.g, lit.b];
+                assert!(
+                    channels.iter().all(|c| (0.0..=1.0).contains(c)),
+                    "{:?} is {:?} {} tiles away",
+                    tile_type,
+                    channels,
+                    distance
+                );
+
+                let brightest = channels.iter().cloned().fold(0.0, f32::max);
+                if distance == 0 {
+                    assert!(brightest > 0.5, "{:?} is too dim", tile_type);
+                } else if distance == range {
+                    assert_eq!(brightest, 0.0);
+                }
+            }
+        }
+
+        assert_eq!(TileType::Air.emission(), None);
+        assert!(!TileType::Rock0.illuminates());
+    }
+}
@@ -4,10 +4,26 @@ use ndarray::prelude::*;
 use ggez::graphics::{spritebatch::SpriteBatch, Color, DrawParam};
 
 use crate::{
-    constants::*,
+    constants::NEAR_PLANE,
     rendering::{drawable::*, font::*, tile::*},
 };
 
+/// The camera the world is drawn with, looking from `eye` at `target` with y up. `aspect` is
+/// the width of the screen over its height.
+pub fn view_projection(eye: &Point3<f32>, target: &Point3<f32>, aspect: f32) -> Matrix4<f32> {
+    let view = Isometry3::look_at_rh(eye, target, &Vector3::y());
+    let projection = Perspective3::new(aspect, 3.14 / 2.0, NEAR_PLANE, 1000.0);
+
+    projection.as_matrix() * view.to_homogeneous()
+}
+
+/// How big to draw a glyph whose depth in normalized device coordinates is `ndc_z`, falling off
+/// with distance. It's the same as it was when the near plane was at 1, which glyph sizes are
+/// tuned for.
+pub fn depth_scale(ndc_z: f32) -> f32 {
+    (1.0 - ndc_z) / NEAR_PLANE
+}
+
 pub fn draw_player_weapon(
     weapon_sprite_batch: &mut SpriteBatch,
     font: &KataFont,
@@ -18,6 +34,7 @@ pub fn draw_player_weapon(
     player_ads: f32,
     player_gun_recoil: f32,
     player_gun_rotation: Point2<f32>,
+    (screen_width, screen_height): (f32, f32),
 ) {
     let player_gun_scale = 0.75;
 
@@ -44,19 +61,19 @@ pub fn draw_player_weapon(
                 if screen_pos.z >= -1.0 && screen_pos.z <= 1.0 {
                     let tile_type = &player_gun_model[[y, x]];
                     let color = tile_type.get_color();
-                    let color_darkness = (1.0 - screen_pos.z.min(1.0).max(0.0)).powf(1.1);
+                    let color_darkness = depth_scale(screen_pos.z.min(1.0)).min(1.0).powf(1.1);
 
                     let screen_dest = [
-                        screen_pos.x * WINDOW_WIDTH / 2.0 + WINDOW_WIDTH / 2.0,
-                        screen_pos.y * WINDOW_HEIGHT / 2.0 + WINDOW_HEIGHT / 2.0, //We need to negate this, as 2d screen space is inverse of normalised device coords
+                        screen_pos.x * screen_width / 2.0 + screen_width / 2.0,
+                        screen_pos.y * screen_height / 2.0 + screen_height / 2.0, //We need to negate this, as 2d screen space is inverse of normalised device coords
                     ];
 
                     let dp = DrawParam::new()
                         .src(tile_type.get_char_offset(&font))
                         .dest(screen_dest)
                         .scale([
-                            (1.0 - screen_pos.z) * 31.4 * player_gun_scale,
-                            (1.0 - screen_pos.z) * 31.4 * player_gun_scale,
+                            depth_scale(screen_pos.z) * 31.4 * player_gun_scale,
+                            depth_scale(screen_pos.z) * 31.4 * player_gun_scale,
                         ])
                         .color(Color {
                             r: color.r * color_darkness,
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     f32::consts::PI,
     ops::{Index, IndexMut},
 };
@@ -6,21 +7,127 @@ use std::{
 use ggez::mint;
 
 use internship::IStr;
+use na::Point3;
 use ndarray::Array3;
 use serde::{Deserialize, Serialize};
 
 use crate::rendering::color::{self, Color};
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+/// How many ticks each frame of an animated voxel is shown for, unless it says otherwise.
+pub const DEFAULT_TICKS_PER_FRAME: u32 = 8;
+
+fn default_ticks_per_frame() -> u32 {
+    DEFAULT_TICKS_PER_FRAME
+}
+
+fn is_default_ticks_per_frame(ticks: &u32) -> bool {
+    *ticks == DEFAULT_TICKS_PER_FRAME
+}
+
+/// A glyph for each face. Its own faces are the first frame of its animation, if it has one.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Voxel3 {
     pub x: Voxel2,
     pub y: Voxel2,
     pub z: Voxel2,
+    /// The frames after the first, of which only the faces are used. Left out when there are
+    /// none, so that voxels that don't animate are saved the same as before they could.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frames: Vec<Voxel3>,
+    #[serde(
+        default = "default_ticks_per_frame",
+        skip_serializing_if = "is_default_ticks_per_frame"
+    )]
+    pub ticks_per_frame: u32,
+}
+
+impl Default for Voxel3 {
+    fn default() -> Self {
+        Self::new(Voxel2::default(), Voxel2::default(), Voxel2::default())
+    }
 }
 
 impl Voxel3 {
     pub fn new(x: Voxel2, y: Voxel2, z: Voxel2) -> Self {
-        Self { x, y, z }
+        Self {
+            x,
+            y,
+            z,
+            frames: Vec::new(),
+            ticks_per_frame: DEFAULT_TICKS_PER_FRAME,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        1 + self.frames.len()
+    }
+
+    /// The frame at `index`, whose faces are the ones to draw. Past the last frame it's the
+    /// last one.
+    pub fn frame(&self, index: usize) -> &Voxel3 {
+        match index.min(self.frames.len()) {
+            0 => self,
+            index => &self.frames[index - 1],
+        }
+    }
+
+    /// Like `frame`, for changing its faces. Anything else about it should be left alone.
+    pub fn frame_mut(&mut self, index: usize) -> &mut Voxel3 {
+        match index.min(self.frames.len()) {
+            0 => self,
+            index => &mut self.frames[index - 1],
+        }
+    }
+
+    /// The frame shown `tick` ticks into its animation, which loops.
+    pub fn frame_at(&self, tick: u64) -> usize {
+        ((tick / u64::from(self.ticks_per_frame.max(1))) % self.frame_count() as u64) as usize
+    }
+
+    /// Adds a frame with the faces of `faces` before the one at `index`, or after the last.
+    pub fn insert_frame(&mut self, index: usize, faces: &Voxel3) {
+        let mut frames = self.frame_faces();
+        frames.insert(index.min(frames.len()), faces.faces_only());
+        self.set_frame_faces(frames);
+    }
+
+    /// Adds a copy of the frame at `index` after it.
+    pub fn duplicate_frame(&mut self, index: usize) {
+        let copy = self.frame(index).faces_only();
+        self.insert_frame(index.min(self.frame_count() - 1) + 1, &copy);
+    }
+
+    /// Takes away the frame at `index`, unless it's the only one. Returns whether it did.
+    pub fn remove_frame(&mut self, index: usize) -> bool {
+        let mut frames = self.frame_faces();
+        if frames.len() == 1 || index >= frames.len() {
+            return false;
+        }
+
+        frames.remove(index);
+        self.set_frame_faces(frames);
+        true
+    }
+
+    /// Just the faces, as a voxel that doesn't animate.
+    fn faces_only(&self) -> Voxel3 {
+        Voxel3::new(self.x.clone(), self.y.clone(), self.z.clone())
+    }
+
+    fn frame_faces(&self) -> Vec<Voxel3> {
+        (0..self.frame_count())
+            .map(|i| self.frame(i).faces_only())
+            .collect()
+    }
+
+    /// Makes the first of `frames` its own faces and the rest the frames after it.
+    fn set_frame_faces(&mut self, mut frames: Vec<Voxel3>) {
+        let rest = frames.split_off(1);
+        let first = frames.remove(0);
+        self.x = first.x;
+        self.y = first.y;
+        self.z = first.z;
+        self.frames = rest;
     }
 
     pub fn face(&self, face: VoxelFace) -> &Voxel2 {
@@ -38,6 +145,54 @@ impl Voxel3 {
             VoxelFace::Z => &mut self.z,
         }
     }
+
+    /// How each of its faces differs from those of `other`. Only the first frames are compared.
+    pub fn diff(&self, other: &Voxel3) -> FaceDiff {
+        FaceDiff {
+            x: self.x.diff(&other.x),
+            y: self.y.diff(&other.y),
+            z: self.z.diff(&other.z),
+        }
+    }
+}
+
+/// How the faces of two voxels differ, face by face.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaceDiff {
+    pub x: Voxel2Diff,
+    pub y: Voxel2Diff,
+    pub z: Voxel2Diff,
+}
+
+impl FaceDiff {
+    pub fn face(&self, face: VoxelFace) -> Voxel2Diff {
+        match face {
+            VoxelFace::X => self.x,
+            VoxelFace::Y => self.y,
+            VoxelFace::Z => self.z,
+        }
+    }
+
+    /// Whether the faces are all the same.
+    pub fn is_empty(&self) -> bool {
+        !(self.x.any() || self.y.any() || self.z.any())
+    }
+}
+
+/// Which parts of two glyphs differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Voxel2Diff {
+    pub char_offset: bool,
+    pub foreground: bool,
+    pub background: bool,
+    pub rotation: bool,
+    pub mirror: bool,
+}
+
+impl Voxel2Diff {
+    pub fn any(self) -> bool {
+        self.char_offset || self.foreground || self.background || self.rotation || self.mirror
+    }
 }
 
 impl Index<VoxelFace> for Voxel3 {
@@ -61,6 +216,12 @@ pub enum VoxelFace {
     Z,
 }
 
+impl Default for VoxelFace {
+    fn default() -> Self {
+        VoxelFace::X
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Voxel2 {
     pub char_offset: u16,
@@ -96,6 +257,25 @@ impl Voxel2 {
     pub fn mirror(self, mirror: VoxelMirror) -> Self {
         Self { mirror, ..self }
     }
+
+    pub fn diff(&self, other: &Voxel2) -> Voxel2Diff {
+        Voxel2Diff {
+            char_offset: self.char_offset != other.char_offset,
+            foreground: self.foreground != other.foreground,
+            background: self.background != other.background,
+            rotation: self.rotation != other.rotation,
+            mirror: self.mirror != other.mirror,
+        }
+    }
+
+    /// Swaps the foreground and background colors, treating a missing background as black.
+    pub fn inverted(self) -> Self {
+        Self {
+            foreground: self.background.unwrap_or(color::BLACK),
+            background: Some(self.foreground),
+            ..self
+        }
+    }
 }
 
 impl Default for Voxel2 {
@@ -104,9 +284,75 @@ impl Default for Voxel2 {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "ModelFile", into = "ModelFile")]
 pub struct Model {
     pub voxels: Array3<Option<IStr>>,
+    /// The point the model turns around and is placed by, in voxels from its first corner.
+    pub pivot: Point3<f32>,
+    /// Notes kept with the model, like who made it, by name.
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl Model {
+    /// A model of `voxels` that turns around its middle.
+    pub fn new(voxels: Array3<Option<IStr>>) -> Self {
+        Self {
+            pivot: Self::center(voxels.dim()),
+            voxels,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_pivot(mut self, pivot: Point3<f32>) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: BTreeMap<String, String>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// The middle of a model of size `dim`, which is between two voxels along even sides.
+    pub fn center(dim: (usize, usize, usize)) -> Point3<f32> {
+        let middle = |side: usize| side.saturating_sub(1) as f32 / 2.0;
+        Point3::new(middle(dim.0), middle(dim.1), middle(dim.2))
+    }
+}
+
+/// How a model is saved. Models saved before they had a pivot turn around their middle.
+#[derive(Deserialize, Serialize)]
+struct ModelFile {
+    voxels: Array3<Option<IStr>>,
+    #[serde(default)]
+    pivot: Option<[f32; 3]>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+}
+
+impl From<ModelFile> for Model {
+    fn from(file: ModelFile) -> Self {
+        let pivot = file
+            .pivot
+            .map_or_else(|| Self::center(file.voxels.dim()), Point3::from);
+
+        Self {
+            voxels: file.voxels,
+            pivot,
+            metadata: file.metadata,
+        }
+    }
+}
+
+impl From<Model> for ModelFile {
+    fn from(model: Model) -> Self {
+        Self {
+            voxels: model.voxels,
+            pivot: Some(model.pivot.coords.into()),
+            metadata: model.metadata,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -123,7 +369,7 @@ impl VoxelRotation {
             VoxelRotation::None => 0.0,
             VoxelRotation::Rotation90 => 0.5 * PI,
             VoxelRotation::Rotation180 => PI,
-            VoxelRotation::Rotation270 => 0.75 * PI,
+            VoxelRotation::Rotation270 => 1.5 * PI,
         }
     }
 }
@@ -147,3 +393,114 @@ impl VoxelMirror {
         .into()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(char_offset: u16) -> Voxel3 {
+        let face = Voxel2::new(char_offset);
+        Voxel3::new(face.clone(), face.clone(), face)
+    }
+
+    #[test]
+    fn test_single_frame_round_trip() {
+        // As voxels.json was written before voxels could animate
+        let old = r#"{"x":{"char_offset":1,"foreground":{"r":255,"g":255,"b":255},"background":null,"rotation":"None","mirror":"None"},"y":{"char_offset":2,"foreground":{"r":255,"g":255,"b":255},"background":null,"rotation":"None","mirror":"None"},"z":{"char_offset":3,"foreground":{"r":255,"g":255,"b":255},"background":null,"rotation":"None","mirror":"None"}}"#;
+
+        let voxel: Voxel3 = serde_json::from_str(old).unwrap();
+        assert_eq!(
+            voxel,
+            Voxel3::new(Voxel2::new(1), Voxel2::new(2), Voxel2::new(3))
+        );
+        assert_eq!(voxel.frame_count(), 1);
+        assert_eq!(voxel.ticks_per_frame, DEFAULT_TICKS_PER_FRAME);
+        assert_eq!(serde_json::to_string(&voxel).unwrap(), old);
+    }
+
+    #[test]
+    fn test_animated_round_trip() {
+        let mut voxel = frame(1);
+        voxel.insert_frame(1, &frame(2));
+        voxel.ticks_per_frame = 3;
+
+        let json = serde_json::to_string(&voxel).unwrap();
+        assert!(json.contains("frames") && json.contains("ticks_per_frame"));
+        assert_eq!(serde_json::from_str::<Voxel3>(&json).unwrap(), voxel);
+    }
+
+    #[test]
+    fn test_frames() {
+        let mut voxel = frame(1);
+        assert!(!voxel.remove_frame(0));
+
+        voxel.insert_frame(1, &frame(3));
+        voxel.insert_frame(1, &frame(2));
+        voxel.duplicate_frame(0);
+        let offsets = |voxel: &Voxel3| {
+            (0..voxel.frame_count())
+                .map(|i| voxel.frame(i).z.char_offset)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(offsets(&voxel), vec![1, 1, 2, 3]);
+        assert!(voxel.frames.iter().all(|frame| frame.frames.is_empty()));
+
+        // Taking the first frame away moves the next one into the voxel's own faces
+        assert!(voxel.remove_frame(0));
+        assert!(voxel.remove_frame(0));
+        assert_eq!(offsets(&voxel), vec![2, 3]);
+        assert_eq!(voxel.x.char_offset, 2);
+        assert!(!voxel.remove_frame(2));
+
+        voxel.frame_mut(1).y = Voxel2::new(9);
+        assert_eq!(voxel.frame(1).y.char_offset, 9);
+        assert_eq!(voxel.frame(5).y.char_offset, 9);
+
+        voxel.ticks_per_frame = 4;
+        let shown: Vec<_> = (0..17)
+            .step_by(4)
+            .map(|tick| voxel.frame_at(tick))
+            .collect();
+        assert_eq!(shown, vec![0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let voxel = frame(1);
+        assert!(voxel.diff(&voxel).is_empty());
+
+        let mut other = voxel.clone();
+        other.y = other
+            .y
+            .background(Some(color::RED))
+            .rotation(VoxelRotation::Rotation90);
+        other.z.char_offset = 2;
+        // Later frames are left out
+        other.insert_frame(1, &frame(5));
+
+        let diff = voxel.diff(&other);
+        assert!(!diff.is_empty());
+        assert!(!diff.face(VoxelFace::X).any());
+        assert_eq!(
+            diff.face(VoxelFace::Y),
+            Voxel2Diff {
+                background: true,
+                rotation: true,
+                ..Voxel2Diff::default()
+            }
+        );
+        assert_eq!(
+            diff.z,
+            Voxel2Diff {
+                char_offset: true,
+                ..Voxel2Diff::default()
+            }
+        );
+        assert_eq!(other.diff(&voxel), diff);
+
+        other.z.mirror = VoxelMirror::MirrorX;
+        other.x.foreground = color::GREEN;
+        let diff = voxel.diff(&other);
+        assert!(diff.z.mirror && diff.z.char_offset && diff.x.foreground);
+    }
+}
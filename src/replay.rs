@@ -0,0 +1,349 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    vec,
+};
+
+use failure::{format_err, Fallible};
+use ggez::{
+    event::KeyCode,
+    input::{keyboard, mouse},
+    Context,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Settings;
+
+/// Goes up whenever the replay format changes, so that older replays are turned away instead of
+/// playing out differently.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// The keys the game reads every update, as opposed to ones that do something once when pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Key {
+    Left,
+    Right,
+    Forward,
+    Back,
+    Jump,
+    Crouch,
+    /// Opens or closes a door, or pulls a lever.
+    Interact,
+    NukeLighting,
+}
+
+const KEY_BINDINGS: &[(KeyCode, Key)] = &[
+    (KeyCode::A, Key::Left),
+    (KeyCode::D, Key::Right),
+    (KeyCode::W, Key::Forward),
+    (KeyCode::S, Key::Back),
+    (KeyCode::Space, Key::Jump),
+    (KeyCode::LControl, Key::Crouch),
+    (KeyCode::E, Key::Interact),
+    (KeyCode::N, Key::NukeLighting),
+];
+
+/// What the player was doing during one update.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct InputFrame {
+    pub keys: Vec<Key>,
+    pub mouse_delta: [f32; 2],
+    pub left_button: bool,
+    pub right_button: bool,
+}
+
+impl InputFrame {
+    /// Reads the keyboard and mouse as they are now.
+    pub fn sample(ctx: &Context) -> Self {
+        let delta = mouse::delta(ctx);
+
+        Self {
+            keys: KEY_BINDINGS
+                .iter()
+                .filter(|(code, _)| keyboard::is_key_pressed(ctx, *code))
+                .map(|&(_, key)| key)
+                .collect(),
+            mouse_delta: [delta.x, delta.y],
+            left_button: mouse::button_pressed(ctx, mouse::MouseButton::Left),
+            right_button: mouse::button_pressed(ctx, mouse::MouseButton::Right),
+        }
+    }
+
+    pub fn held(&self, key: Key) -> bool {
+        self.keys.contains(&key)
+    }
+}
+
+/// The first line of a replay, with what's needed to make the same world again.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub seed: u64,
+    /// The settings the replay was recorded with. Only the ones that change how the game plays
+    /// out are used when it's played back.
+    pub settings: Settings,
+}
+
+/// Read on its own first, so a replay in another format gets a clear message rather than
+/// whatever it fails to parse on.
+#[derive(Deserialize)]
+struct ReplayVersion {
+    version: u32,
+}
+
+impl ReplayHeader {
+    pub fn new(seed: u64, settings: &Settings) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            seed,
+            settings: settings.clone(),
+        }
+    }
+
+    /// `settings` with everything that affects the world or the player taken from the replay,
+    /// leaving things like the window size alone.
+    pub fn apply_to(&self, settings: Settings) -> Settings {
+        let recorded = &self.settings;
+
+        Settings {
+            chunk_size: recorded.chunk_size,
+            light_range: recorded.light_range,
+            sight_range: recorded.sight_range,
+            noise_scale: recorded.noise_scale,
+            noise_weight_scale: recorded.noise_weight_scale,
+            input: recorded.input.clone(),
+            ..settings
+        }
+        .sanitized()
+    }
+}
+
+/// A recorded session: the header, then the input of every update in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub header: ReplayHeader,
+    pub frames: Vec<InputFrame>,
+}
+
+impl Replay {
+    /// Reads the replay at `path`, failing if it's in another format or any line is broken.
+    pub fn read<P>(path: P) -> Fallible<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read {}: {}", path.display(), e))?;
+        let mut lines = text.lines();
+
+        let first = lines
+            .next()
+            .ok_or_else(|| format_err!("{} is empty", path.display()))?;
+        let version: ReplayVersion = serde_json::from_str(first)
+            .map_err(|e| format_err!("{} isn't a replay: {}", path.display(), e))?;
+        if version.version != REPLAY_VERSION {
+            return Err(format_err!(
+                "{} is in version {} of the replay format, but only version {} can be played",
+                path.display(),
+                version.version,
+                REPLAY_VERSION
+            ));
+        }
+
+        let header = serde_json::from_str(first)
+            .map_err(|e| format_err!("{} has a broken header: {}", path.display(), e))?;
+        let frames = lines
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line).map_err(|e| {
+                    format_err!("{} is broken on line {}: {}", path.display(), i + 2, e)
+                })
+            })
+            .collect::<Fallible<_>>()?;
+
+        Ok(Self { header, frames })
+    }
+}
+
+/// Writes a replay a line at a time, so that everything up to a crash is kept.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    path: PathBuf,
+}
+
+impl Recorder {
+    pub fn create<P>(path: P, header: &ReplayHeader) -> Fallible<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let file = File::create(&path)
+            .map_err(|e| format_err!("Failed to create {}: {}", path.display(), e))?;
+
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+            path,
+        };
+        recorder.write_line(header)?;
+
+        Ok(recorder)
+    }
+
+    pub fn record(&mut self, frame: &InputFrame) -> Fallible<()> {
+        self.write_line(frame)
+    }
+
+    fn write_line<T>(&mut self, value: &T) -> Fallible<()>
+    where
+        T: Serialize,
+    {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Where each update's input comes from.
+pub enum Input {
+    Live,
+    /// From the keyboard and mouse, saving it to a replay as it goes.
+    Recording(Recorder),
+    /// From a replay, going back to the keyboard and mouse when it runs out.
+    Replaying(vec::IntoIter<InputFrame>),
+}
+
+impl Input {
+    /// Whether the world is free to change in ways a replay can't repeat, like loading a save.
+    pub fn is_live(&self) -> bool {
+        match self {
+            Input::Live => true,
+            Input::Recording(_) | Input::Replaying(_) => false,
+        }
+    }
+
+    /// The input for this update.
+    pub fn next_frame(&mut self, ctx: &Context) -> InputFrame {
+        match self {
+            Input::Live => InputFrame::sample(ctx),
+
+            Input::Recording(recorder) => {
+                let frame = InputFrame::sample(ctx);
+                if let Err(e) = recorder.record(&frame) {
+                    warn!(
+                        "Failed to write to {}, no longer recording: {}",
+                        recorder.path.display(),
+                        e
+                    );
+                    *self = Input::Live;
+                }
+
+                frame
+            }
+
+            Input::Replaying(frames) => match frames.next() {
+                Some(frame) => frame,
+                None => {
+                    info!("The replay is over, handing back to the keyboard and mouse");
+                    *self = Input::Live;
+                    InputFrame::sample(ctx)
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::settings::InputConfig;
+
+    fn frames() -> Vec<InputFrame> {
+        vec![
+            InputFrame::default(),
+            InputFrame {
+                keys: vec![Key::Forward, Key::Jump],
+                mouse_delta: [3.0, -1.5],
+                left_button: true,
+                right_button: false,
+            },
+        ]
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("katakomb_test_{}.replay", name))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_path("round_trip");
+        let header = ReplayHeader::new(42, &Settings::default());
+
+        let mut recorder = Recorder::create(&path, &header).unwrap();
+        for frame in &frames() {
+            recorder.record(frame).unwrap();
+        }
+        drop(recorder);
+
+        let replay = Replay::read(&path).unwrap();
+        assert_eq!(replay.header, header);
+        assert_eq!(replay.frames, frames());
+        assert!(replay.frames[1].held(Key::Jump));
+        assert!(!replay.frames[1].held(Key::Back));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_bad_replays() {
+        let path = temp_path("bad");
+        let header = ReplayHeader::new(42, &Settings::default());
+
+        let newer = ReplayHeader {
+            version: REPLAY_VERSION + 1,
+            ..header.clone()
+        };
+        Recorder::create(&path, &newer).unwrap();
+        let error = Replay::read(&path).unwrap_err().to_string();
+        assert!(error.contains("version"), "{}", error);
+
+        let mut recorder = Recorder::create(&path, &header).unwrap();
+        recorder.record(&InputFrame::default()).unwrap();
+        drop(recorder);
+        let mut text = fs::read_to_string(&path).unwrap();
+        text.push_str("{\"keys\": [\"Sideways\"]}\n");
+        fs::write(&path, text).unwrap();
+        let error = Replay::read(&path).unwrap_err().to_string();
+        assert!(error.contains("line 3"), "{}", error);
+
+        fs::write(&path, "").unwrap();
+        assert!(Replay::read(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        assert!(Replay::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_apply_to() {
+        let recorded = Settings {
+            chunk_size: 32,
+            light_range: 8,
+            window_width: 100.0,
+            input: InputConfig {
+                invert_y: true,
+                ..InputConfig::default()
+            },
+            ..Settings::default()
+        };
+        let header = ReplayHeader::new(7, &recorded);
+
+        let applied = header.apply_to(Settings::default());
+        assert_eq!(applied.chunk_size, 32);
+        assert_eq!(applied.light_range, 8);
+        assert!(applied.input.invert_y);
+        assert_eq!(applied.window_width, Settings::default().window_width);
+    }
+}
@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use na::Point3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geometry::util::floor_point,
+    rendering::tile::TileType,
+    strings::StringTable,
+    tr,
+    world::{explored::Explored, World},
+};
+
+/// What the player has to do next.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameObjective {
+    /// Find the artifact in the furthest part of the caves.
+    FindArtifact,
+    /// Bring the artifact back to the way out.
+    ReturnToEntrance,
+    /// Got out with the artifact, which ends the run.
+    Escaped(RunResults),
+}
+
+/// How a run went, for the results screen.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RunResults {
+    pub elapsed: Duration,
+    pub shots_fired: u32,
+    /// How many cells the player saw on the way.
+    pub tiles_explored: usize,
+}
+
+impl RunResults {
+    /// The lines of the results screen.
+    pub fn lines(&self, strings: &StringTable) -> Vec<String> {
+        let seconds = self.elapsed.as_secs();
+        let time = format!("{}:{:02}", seconds / 60, seconds % 60);
+
+        vec![
+            tr!(strings, "results.time", time),
+            tr!(strings, "results.shots", self.shots_fired),
+            tr!(strings, "results.explored", self.tiles_explored),
+        ]
+    }
+}
+
+/// One attempt at getting the artifact out of the caves, from the start of a world to escaping.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Run {
+    pub objective: GameObjective,
+    /// Time spent playing, not counting the menus.
+    pub elapsed: Duration,
+    pub shots_fired: u32,
+}
+
+impl Run {
+    pub fn new() -> Self {
+        Self {
+            objective: GameObjective::FindArtifact,
+            elapsed: Duration::from_secs(0),
+            shots_fired: 0,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        match self.objective {
+            GameObjective::Escaped(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Counts an update `delta` long, in which the player fired if `fired`. Nothing is counted
+    /// once the run is over.
+    pub fn update(&mut self, delta: Duration, fired: bool) {
+        if self.is_over() {
+            return;
+        }
+
+        self.elapsed += delta;
+        if fired {
+            self.shots_fired += 1;
+        }
+    }
+
+    /// Takes the artifact at `pos`, if that's where it is and it's what the player is looking
+    /// for. Its light goes with it. Returns whether it was taken.
+    pub fn pick_up(&mut self, world: &mut World, pos: Point3<i32>) -> bool {
+        let is_artifact = world
+            .tile(pos)
+            .map_or(false, |tile| tile.tile_type == TileType::Artifact);
+        if self.objective != GameObjective::FindArtifact || !is_artifact {
+            return false;
+        }
+
+        world.set_tile(pos, TileType::Air);
+        world.remove_light(Point3::new(pos.x as usize, pos.y as usize, pos.z as usize));
+        self.objective = GameObjective::ReturnToEntrance;
+
+        true
+    }
+
+    /// Ends the run if the player is carrying the artifact and standing with their feet at
+    /// `feet` on the way out. Returns whether it ended.
+    pub fn try_extract(&mut self, world: &World, feet: Point3<f32>, explored: &Explored) -> bool {
+        let on_extraction = world
+            .tile(floor_point(feet))
+            .map_or(false, |tile| tile.tile_type == TileType::Extraction);
+        if self.objective != GameObjective::ReturnToEntrance || !on_extraction {
+            return false;
+        }
+
+        self.objective = GameObjective::Escaped(RunResults {
+            elapsed: self.elapsed,
+            shots_fired: self.shots_fired,
+            tiles_explored: explored.count(),
+        });
+
+        true
+    }
+
+    /// What to tell the player to do on the HUD, if the run isn't over.
+    pub fn hud_text(&self, strings: &StringTable) -> Option<String> {
+        match self.objective {
+            GameObjective::FindArtifact => Some(tr!(strings, "run.find_artifact")),
+            GameObjective::ReturnToEntrance => Some(tr!(strings, "run.return")),
+            GameObjective::Escaped(_) => None,
+        }
+    }
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::{fov::VisibleCell, tile::tiles_from_fn};
+    use ggez::graphics::Color;
+
+    /// A row of air along x with the way out at one end and the artifact at the other.
+    fn world() -> World {
+        let tiles = tiles_from_fn((8, 1, 1), |(x, _, _)| match x {
+            0 => TileType::Extraction,
+            7 => TileType::Artifact,
+            _ => TileType::Air,
+        });
+
+        World::new(tiles, vec![(Point3::new(7, 0, 0), Color::WHITE)])
+    }
+
+    #[test]
+    fn test_full_run() {
+        let mut world = world();
+        let mut explored = Explored::new(world.tiles().dim());
+        explored.mark_visible(&[VisibleCell {
+            cell: Point3::new(3, 0, 0),
+            dist_from_eye: 0.0,
+        }]);
+        let mut run = Run::new();
+        let strings = StringTable::default();
+        assert_eq!(
+            run.hud_text(&strings),
+            Some(String::from("run.find_artifact"))
+        );
+
+        // The way out does nothing without the artifact
+        assert!(!run.try_extract(&world, Point3::new(0.5, 0.0, 0.5), &explored));
+        assert!(!run.pick_up(&mut world, Point3::new(6, 0, 0)));
+
+        run.update(Duration::from_secs(61), true);
+        assert!(run.pick_up(&mut world, Point3::new(7, 0, 0)));
+        assert_eq!(run.objective, GameObjective::ReturnToEntrance);
+        assert_eq!(
+            world.tile(Point3::new(7, 0, 0)).unwrap().tile_type,
+            TileType::Air
+        );
+        assert!(world.lights().is_empty());
+        assert_eq!(run.hud_text(&strings), Some(String::from("run.return")));
+
+        run.update(Duration::from_secs(1), false);
+        assert!(!run.try_extract(&world, Point3::new(1.5, 0.0, 0.5), &explored));
+        assert!(run.try_extract(&world, Point3::new(0.5, 0.0, 0.5), &explored));
+        let results = RunResults {
+            elapsed: Duration::from_secs(62),
+            shots_fired: 1,
+            tiles_explored: 1,
+        };
+        assert_eq!(run.objective, GameObjective::Escaped(results));
+        assert_eq!(run.hud_text(&strings), None);
+
+        // Stopped counting
+        run.update(Duration::from_secs(5), true);
+        assert_eq!(run.elapsed, Duration::from_secs(62));
+        assert_eq!(run.shots_fired, 1);
+        assert!(!run.try_extract(&world, Point3::new(0.5, 0.0, 0.5), &explored));
+    }
+
+    #[test]
+    fn test_results_lines() {
+        let strings: StringTable = serde_json::from_str(r#"{"results.time": "Time {}"}"#).unwrap();
+        let results = RunResults {
+            elapsed: Duration::from_millis(125_900),
+            shots_fired: 3,
+            tiles_explored: 40,
+        };
+
+        assert_eq!(
+            results.lines(&strings),
+            vec!["Time 2:05", "results.shots", "results.explored"]
+        );
+    }
+}
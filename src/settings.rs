@@ -0,0 +1,327 @@
+use std::{path::Path, time::Duration};
+
+use failure::Fallible;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use structopt::StructOpt;
+
+use crate::{
+    constants::*,
+    rendering::color::PaletteMode,
+    util::{try_load, write_json},
+};
+
+/// Where the game's settings are kept. It's written out with the defaults if it's missing.
+pub const SETTINGS_PATH: &str = "settings.json";
+
+/// Everything that can be tuned without rebuilding the game. Anything left out of the file
+/// keeps its default from `constants`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window_width: f32,
+    pub window_height: f32,
+    pub vsync: bool,
+    /// Frames per second to stop at, or `None` to draw as many as possible.
+    pub max_fps: Option<u32>,
+    /// How many tiles the world is along each side, up to `MAX_CHUNK_SIZE`.
+    pub chunk_size: usize,
+    /// How far lights reach, up to `MAX_CAST_RANGE`.
+    pub light_range: usize,
+    /// How far the player can see, up to `MAX_CAST_RANGE`.
+    pub sight_range: usize,
+    pub noise_scale: f64,
+    pub noise_weight_scale: f64,
+    pub max_sound_range: f32,
+    /// How far away stamps can be placed.
+    pub stamp_range: f32,
+    pub palette_mode: PaletteMode,
+    pub player: PlayerConfig,
+    pub input: InputConfig,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_width: WINDOW_WIDTH,
+            window_height: WINDOW_HEIGHT,
+            vsync: true,
+            max_fps: None,
+            chunk_size: CHUNK_SIZE,
+            light_range: LIGHT_RANGE,
+            sight_range: PLAYER_SIGHT_RANGE,
+            noise_scale: NOISE_SCALE,
+            noise_weight_scale: NOISE_WEIGHT_SCALE,
+            max_sound_range: MAX_SOUND_RANGE,
+            stamp_range: STAMP_RANGE,
+            palette_mode: PaletteMode::Normal,
+            player: PlayerConfig::default(),
+            input: InputConfig::default(),
+        }
+    }
+}
+
+/// How the camera reacts to what the player does. Angles are in radians.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PlayerConfig {
+    /// Whether firing and landing move the camera at all. The gun recoils either way.
+    pub screenshake: bool,
+    /// How far each shot kicks the view up.
+    pub recoil_pitch: f32,
+    /// The furthest each shot kicks the view to either side.
+    pub recoil_yaw: f32,
+    /// How much each shot shakes the view.
+    pub fire_shake: f32,
+    /// How many tiles the player can fall before landing shakes the view.
+    pub hard_landing_height: f32,
+    /// How much the view shakes for each tile fallen past `hard_landing_height`.
+    pub landing_shake: f32,
+    /// How much of the kick and shake is left after each update, from 0 to 1.
+    pub shake_decay: f32,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            screenshake: true,
+            recoil_pitch: 0.03,
+            recoil_yaw: 0.01,
+            fire_shake: 0.01,
+            hard_landing_height: 3.0,
+            landing_shake: 0.01,
+            shake_decay: 0.85,
+        }
+    }
+}
+
+/// How the mouse turns the view.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct InputConfig {
+    /// How far moving the mouse a pixel sideways turns the view, in radians.
+    pub sensitivity_x: f32,
+    /// How far moving the mouse a pixel up or down tilts the view, in radians.
+    pub sensitivity_y: f32,
+    /// How much of the last update's mouse movement carries over into the next, from 0 for
+    /// none up to `MAX_LOOK_SMOOTHING`. Evens out a jittery mouse at the cost of some lag.
+    pub smoothing: f32,
+    /// Moving the mouse up looks down instead.
+    pub invert_y: bool,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            sensitivity_x: MOUSE_SENSITIVITY,
+            sensitivity_y: MOUSE_SENSITIVITY,
+            smoothing: 0.0,
+            invert_y: false,
+        }
+    }
+}
+
+/// Settings given on the command line, which take priority over the file.
+#[derive(Debug, Default, StructOpt)]
+pub struct SettingsOverrides {
+    #[structopt(long)]
+    pub window_width: Option<f32>,
+    #[structopt(long)]
+    pub window_height: Option<f32>,
+    #[structopt(long, parse(try_from_str = parse_on_off))]
+    pub vsync: Option<bool>,
+    /// 0 for no limit.
+    #[structopt(long)]
+    pub max_fps: Option<u32>,
+    #[structopt(long)]
+    pub chunk_size: Option<usize>,
+    #[structopt(long)]
+    pub light_range: Option<usize>,
+    #[structopt(long)]
+    pub sight_range: Option<usize>,
+    /// Keeps the camera still when firing and landing.
+    #[structopt(long)]
+    pub no_screenshake: bool,
+    /// Moving the mouse up looks down instead.
+    #[structopt(long)]
+    pub invert_y: bool,
+}
+
+fn parse_on_off(s: &str) -> Result<bool, String> {
+    match s {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, not {:?}", s)),
+    }
+}
+
+impl Settings {
+    /// Loads the settings from `path`. If there's nothing there yet the defaults are written
+    /// out first, so there's a file to edit.
+    pub fn load_or_create<P>(path: P) -> Fallible<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        if !path.is_file() {
+            info!("Writing default settings to {:?}", path);
+            if let Err(e) = write_json(path, &Self::default()) {
+                warn!("Failed to write default settings: {}", e);
+            }
+        }
+
+        let settings: Self = try_load(path)?;
+        Ok(settings.sanitized())
+    }
+
+    pub fn with_overrides(mut self, overrides: &SettingsOverrides) -> Self {
+        self.window_width = overrides.window_width.unwrap_or(self.window_width);
+        self.window_height = overrides.window_height.unwrap_or(self.window_height);
+        self.vsync = overrides.vsync.unwrap_or(self.vsync);
+        self.max_fps = overrides.max_fps.map(Some).unwrap_or(self.max_fps);
+        self.chunk_size = overrides.chunk_size.unwrap_or(self.chunk_size);
+        self.light_range = overrides.light_range.unwrap_or(self.light_range);
+        self.sight_range = overrides.sight_range.unwrap_or(self.sight_range);
+        if overrides.no_screenshake {
+            self.player.screenshake = false;
+        }
+        if overrides.invert_y {
+            self.input.invert_y = true;
+        }
+
+        self.sanitized()
+    }
+
+    /// The width of the window over its height.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.window_width / self.window_height
+    }
+
+    /// How long a frame should take at most, if the frame rate is limited.
+    pub fn frame_budget(&self) -> Option<Duration> {
+        self.max_fps
+            .map(|fps| Duration::from_secs_f64(1.0 / f64::from(fps)))
+    }
+
+    /// Brings anything the game can't cope with back into range, with a warning.
+    pub fn sanitized(mut self) -> Self {
+        fn clamp(name: &str, value: usize, min: usize, max: usize) -> usize {
+            let clamped = value.max(min).min(max);
+            if clamped != value {
+                warn!("{} can't be {}, using {}", name, value, clamped);
+            }
+            clamped
+        }
+
+        self.chunk_size = clamp("chunk_size", self.chunk_size, 1, MAX_CHUNK_SIZE);
+        self.light_range = clamp("light_range", self.light_range, 1, MAX_CAST_RANGE);
+        self.sight_range = clamp("sight_range", self.sight_range, 1, MAX_CAST_RANGE);
+        self.window_width = self.window_width.max(1.0);
+        self.window_height = self.window_height.max(1.0);
+        // No limit is easier to ask for as 0 than as null
+        self.max_fps = self.max_fps.filter(|&fps| fps > 0);
+        self.player.shake_decay = self.player.shake_decay.max(0.0).min(1.0);
+        self.input.sensitivity_x = self.input.sensitivity_x.max(0.0);
+        self.input.sensitivity_y = self.input.sensitivity_y.max(0.0);
+        self.input.smoothing = self.input.smoothing.max(0.0).min(MAX_LOOK_SMOOTHING);
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_missing_fields_use_defaults() {
+        let settings: Settings =
+            serde_json::from_str(r#"{ "sight_range": 20, "palette_mode": "high_contrast" }"#)
+                .unwrap();
+
+        assert_eq!(
+            settings,
+            Settings {
+                sight_range: 20,
+                palette_mode: PaletteMode::HighContrast,
+                ..Settings::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_overrides_and_clamping() {
+        let overrides = SettingsOverrides {
+            window_height: Some(600.0),
+            light_range: Some(MAX_CAST_RANGE + 10),
+            chunk_size: Some(0),
+            ..SettingsOverrides::default()
+        };
+        let settings = Settings::default().with_overrides(&overrides);
+
+        assert_eq!(settings.window_width, WINDOW_WIDTH);
+        assert_eq!(settings.window_height, 600.0);
+        assert_eq!(settings.light_range, MAX_CAST_RANGE);
+        assert_eq!(settings.chunk_size, 1);
+        assert_eq!(settings.sight_range, PLAYER_SIGHT_RANGE);
+        assert!(settings.player.screenshake);
+        assert!(!settings.input.invert_y);
+
+        let still = SettingsOverrides {
+            no_screenshake: true,
+            invert_y: true,
+            ..SettingsOverrides::default()
+        };
+        let settings = settings.with_overrides(&still);
+        assert!(!settings.player.screenshake);
+        assert!(settings.input.invert_y);
+    }
+
+    #[test]
+    fn test_frame_rate_overrides() {
+        let settings = Settings {
+            max_fps: Some(30),
+            ..Settings::default()
+        };
+        assert_eq!(
+            settings.frame_budget(),
+            Some(Duration::from_secs_f64(1.0 / 30.0))
+        );
+
+        let unlimited = SettingsOverrides {
+            vsync: Some(false),
+            max_fps: Some(0),
+            ..SettingsOverrides::default()
+        };
+        let settings = settings.with_overrides(&unlimited);
+        assert!(!settings.vsync);
+        assert_eq!(settings.max_fps, None);
+        assert_eq!(settings.frame_budget(), None);
+
+        assert_eq!(parse_on_off("on"), Ok(true));
+        assert!(parse_on_off("yes").is_err());
+    }
+
+    #[test]
+    fn test_load_or_create() {
+        let path = std::env::temp_dir().join("katakomb_test_settings.json");
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(
+            Settings::load_or_create(&path).unwrap(),
+            Settings::default()
+        );
+        assert!(path.is_file());
+
+        fs::write(&path, r#"{ "sight_range": 1000 }"#).unwrap();
+        assert_eq!(
+            Settings::load_or_create(&path).unwrap().sight_range,
+            MAX_CAST_RANGE
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
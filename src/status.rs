@@ -0,0 +1,333 @@
+use ggez::{graphics::Color as GGColor, mint};
+
+use crate::{
+    rendering::{
+        color::{self, Color},
+        voxel::Voxel2,
+    },
+    ui::VoxelBatch,
+};
+
+/// How much health a stack of burning takes each update.
+pub const BURN_DAMAGE: f32 = 0.05;
+/// How many updates standing on lava sets the player burning for.
+pub const LAVA_BURN_UPDATES: u32 = 180;
+
+/// Something lasting that's happening to the player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusKind {
+    /// Hurts every update, more for each stack.
+    Burning,
+    /// Walks slower.
+    Slowed,
+    /// Aim sways further.
+    Dizzy,
+}
+
+/// What happens when an effect is applied while the player already has it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stacking {
+    /// The time left goes up to the new effect's, if that's longer.
+    Refresh,
+    /// The new effect's time is added to what's left.
+    Extend,
+    /// Another stack is added, up to `max`, and the time left is refreshed.
+    Stack { max: u32 },
+}
+
+impl StatusKind {
+    pub const ALL: [StatusKind; 3] = [StatusKind::Burning, StatusKind::Slowed, StatusKind::Dizzy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatusKind::Burning => "burning",
+            StatusKind::Slowed => "slowed",
+            StatusKind::Dizzy => "dizzy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    pub fn stacking(self) -> Stacking {
+        match self {
+            StatusKind::Burning => Stacking::Stack { max: 3 },
+            StatusKind::Slowed => Stacking::Refresh,
+            StatusKind::Dizzy => Stacking::Extend,
+        }
+    }
+
+    /// The icon the HUD shows while the effect lasts.
+    pub fn icon(self) -> Voxel2 {
+        let (glyph, color) = match self {
+            StatusKind::Burning => (0x0F, Color::new(255, 120, 20)),
+            StatusKind::Slowed => ('~' as u16, color::CYAN),
+            StatusKind::Dizzy => ('@' as u16, color::MAGENTA),
+        };
+
+        Voxel2::new(glyph).foreground(color)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub stacks: u32,
+    /// How many more updates it lasts for.
+    pub remaining: u32,
+    /// How many updates it lasted for when it was last applied, to show how much is left.
+    pub duration: u32,
+}
+
+impl StatusEffect {
+    /// How much faster or slower the player walks.
+    pub fn movement_multiplier(&self) -> f32 {
+        match self.kind {
+            StatusKind::Slowed => 0.5,
+            _ => 1.0,
+        }
+    }
+
+    /// How much health the effect takes each update.
+    pub fn damage_per_tick(&self) -> f32 {
+        match self.kind {
+            StatusKind::Burning => BURN_DAMAGE * self.stacks as f32,
+            _ => 0.0,
+        }
+    }
+
+    /// How much more the player's aim sways.
+    pub fn sway_multiplier(&self) -> f32 {
+        match self.kind {
+            StatusKind::Dizzy => 3.0,
+            _ => 1.0,
+        }
+    }
+
+    /// The color the effect tints the light the player is carrying, if it does.
+    pub fn light_tint(&self) -> Option<GGColor> {
+        match self.kind {
+            StatusKind::Burning => Some(GGColor::new(1.0, 0.4, 0.1, 1.0)),
+            _ => None,
+        }
+    }
+
+    /// How much of its time is left, from 1 when it was just applied down to 0.
+    pub fn fraction_left(&self) -> f32 {
+        if self.duration == 0 {
+            0.0
+        } else {
+            self.remaining as f32 / self.duration as f32
+        }
+    }
+}
+
+/// The effects the player has, at most one of each kind.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StatusEffects {
+    effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts `kind` for `updates` updates, or adds to it as its stacking says if it's already
+    /// going.
+    pub fn apply(&mut self, kind: StatusKind, updates: u32) {
+        if updates == 0 {
+            return;
+        }
+
+        let effect = match self.effects.iter_mut().find(|effect| effect.kind == kind) {
+            Some(effect) => effect,
+            None => {
+                self.effects.push(StatusEffect {
+                    kind,
+                    stacks: 1,
+                    remaining: updates,
+                    duration: updates,
+                });
+                return;
+            }
+        };
+
+        match kind.stacking() {
+            Stacking::Refresh => {
+                if updates > effect.remaining {
+                    effect.remaining = updates;
+                    effect.duration = updates;
+                }
+            }
+            Stacking::Extend => {
+                effect.remaining += updates;
+                effect.duration = effect.remaining;
+            }
+            Stacking::Stack { max } => {
+                effect.stacks = (effect.stacks + 1).min(max);
+                effect.remaining = effect.remaining.max(updates);
+                effect.duration = effect.remaining;
+            }
+        }
+    }
+
+    /// Counts down an update, dropping the effects that run out, and returns how much health
+    /// the effects took.
+    pub fn tick(&mut self) -> f32 {
+        let damage = self.effects.iter().map(StatusEffect::damage_per_tick).sum();
+
+        for effect in &mut self.effects {
+            effect.remaining -= 1;
+        }
+        self.effects.retain(|effect| effect.remaining > 0);
+
+        damage
+    }
+
+    pub fn get(&self, kind: StatusKind) -> Option<&StatusEffect> {
+        self.effects.iter().find(|effect| effect.kind == kind)
+    }
+
+    /// The effects in the order they started.
+    pub fn iter(&self) -> impl Iterator<Item = &StatusEffect> {
+        self.effects.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn movement_multiplier(&self) -> f32 {
+        self.effects
+            .iter()
+            .map(StatusEffect::movement_multiplier)
+            .product()
+    }
+
+    pub fn sway_multiplier(&self) -> f32 {
+        self.effects
+            .iter()
+            .map(StatusEffect::sway_multiplier)
+            .product()
+    }
+
+    /// The tint of the first effect that has one.
+    pub fn light_tint(&self) -> Option<GGColor> {
+        self.effects.iter().find_map(StatusEffect::light_tint)
+    }
+}
+
+/// Mixes `tint` into `light` halfway, keeping it as bright as it was.
+pub fn tint_light(light: GGColor, tint: GGColor) -> GGColor {
+    let brightness = light.r.max(light.g).max(light.b);
+    let mix = |l: f32, t: f32| (l + t * brightness) / 2.0;
+
+    GGColor::new(
+        mix(light.r, tint.r),
+        mix(light.g, tint.g),
+        mix(light.b, tint.b),
+        1.0,
+    )
+}
+
+/// Draws an icon for each effect in a row from `origin`, fading as it runs out.
+pub fn draw_status_icons(
+    batch: &mut dyn VoxelBatch,
+    effects: &StatusEffects,
+    origin: mint::Point2<u32>,
+) {
+    for (i, effect) in effects.iter().enumerate() {
+        let icon = effect.kind.icon();
+        let faded = icon.foreground.dimmed(0.25 + 0.75 * effect.fraction_left());
+
+        batch.add(
+            &icon.foreground(faded),
+            [origin.x + i as u32, origin.y].into(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{Size, TextGridBatch};
+
+    #[test]
+    fn test_refresh() {
+        let mut effects = StatusEffects::new();
+
+        effects.apply(StatusKind::Slowed, 10);
+        effects.tick();
+        effects.apply(StatusKind::Slowed, 5);
+        assert_eq!(effects.get(StatusKind::Slowed).unwrap().remaining, 9);
+
+        effects.apply(StatusKind::Slowed, 20);
+        let slowed = effects.get(StatusKind::Slowed).unwrap();
+        assert_eq!((slowed.stacks, slowed.remaining), (1, 20));
+        assert_eq!(slowed.fraction_left(), 1.0);
+        assert_eq!(effects.movement_multiplier(), 0.5);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut effects = StatusEffects::new();
+
+        effects.apply(StatusKind::Dizzy, 10);
+        effects.tick();
+        effects.apply(StatusKind::Dizzy, 5);
+        let dizzy = effects.get(StatusKind::Dizzy).unwrap();
+        assert_eq!((dizzy.stacks, dizzy.remaining), (1, 14));
+        assert_eq!(effects.sway_multiplier(), 3.0);
+    }
+
+    #[test]
+    fn test_stack() {
+        let mut effects = StatusEffects::new();
+
+        for _ in 0..5 {
+            effects.apply(StatusKind::Burning, 3);
+        }
+        let burning = effects.get(StatusKind::Burning).unwrap();
+        assert_eq!((burning.stacks, burning.remaining), (3, 3));
+        assert!(effects.light_tint().is_some());
+
+        // Burns on every update it lasts, then stops
+        let damage: f32 = (0..5).map(|_| effects.tick()).sum();
+        assert!((damage - BURN_DAMAGE * 9.0).abs() < 1e-6);
+        assert_eq!(effects.get(StatusKind::Burning), None);
+        assert_eq!(effects, StatusEffects::new());
+        assert_eq!(effects.movement_multiplier(), 1.0);
+        assert_eq!(effects.light_tint(), None);
+    }
+
+    #[test]
+    fn test_apply_nothing() {
+        let mut effects = StatusEffects::new();
+        effects.apply(StatusKind::Burning, 0);
+        assert_eq!(effects, StatusEffects::new());
+    }
+
+    #[test]
+    fn test_names() {
+        for &kind in &StatusKind::ALL {
+            assert_eq!(StatusKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(StatusKind::from_name("wet"), None);
+    }
+
+    #[test]
+    fn test_draw_status_icons() {
+        let mut effects = StatusEffects::new();
+        effects.apply(StatusKind::Dizzy, 4);
+        effects.apply(StatusKind::Slowed, 4);
+
+        let mut batch = TextGridBatch::new(Size {
+            width: 4,
+            height: 1,
+        });
+        draw_status_icons(&mut batch, &effects, [1, 0].into());
+        assert_eq!(batch.lines(), vec![" @~ "]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::{collections::BTreeMap, fmt::Display, path::Path};
+
+use failure::Fallible;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::util::try_load;
+
+/// The English text of the game and the editor.
+pub const STRINGS_PATH: &str = "lang/en.json";
+
+/// Text shown to the player, looked up by keys like `editor.save_ok` so that it can be
+/// translated. Placeholders are written `{}` for the next argument or `{0}` for a given one,
+/// and `{{` or `}}` for a brace.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct StringTable {
+    strings: BTreeMap<String, String>,
+}
+
+/// Looks up `key` in a `StringTable` and fills in its placeholders with the arguments, like
+/// `format!`. A missing key is shown as the key itself, so it's easy to spot.
+///
+/// ```ignore
+/// tr!(strings, "editor.save_ok", voxels.len(), models.len())
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($strings:expr, $key:literal $(, $arg:expr)* $(,)?) => {
+        $strings.format($key, &[$(&$arg as &dyn std::fmt::Display),*])
+    };
+}
+
+impl StringTable {
+    /// Loads the table at `path`. If it's missing every key is shown as itself.
+    pub fn load<P>(path: P) -> Fallible<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if !path.is_file() {
+            warn!("There are no strings at {}", path.display());
+        }
+
+        try_load(path)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(String::as_str)
+    }
+
+    /// The text for `key` with its placeholders filled in from `args`. Placeholders without an
+    /// argument are left as they are.
+    pub fn format(&self, key: &str, args: &[&dyn Display]) -> String {
+        let template = match self.get(key) {
+            Some(template) => template,
+            None => return key.to_string(),
+        };
+
+        let mut text = String::with_capacity(template.len());
+        let mut next_arg = 0;
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    while let Some(c) = chars.next() {
+                        if c == '}' {
+                            break;
+                        }
+                        placeholder.push(c);
+                    }
+
+                    let index = if placeholder.is_empty() {
+                        next_arg += 1;
+                        Some(next_arg - 1)
+                    } else {
+                        placeholder.parse().ok()
+                    };
+
+                    match index.and_then(|i| args.get(i)) {
+                        Some(arg) => text.push_str(&arg.to_string()),
+                        None => {
+                            text.push('{');
+                            text.push_str(&placeholder);
+                            text.push('}');
+                        }
+                    }
+                }
+                c => text.push(c),
+            }
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    fn table() -> StringTable {
+        serde_json::from_str(
+            r#"{
+                "greeting": "Hello, {}!",
+                "swapped": "{1} before {0}",
+                "braces": "{{{}}}"
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format() {
+        let strings = table();
+
+        assert_eq!(tr!(strings, "greeting", "cave"), "Hello, cave!");
+        assert_eq!(tr!(strings, "swapped", 1, 2), "2 before 1");
+        assert_eq!(tr!(strings, "braces", 3), "{3}");
+        assert_eq!(tr!(strings, "greeting"), "Hello, {}!");
+        assert_eq!(tr!(strings, "missing.key", 1), "missing.key");
+    }
+
+    /// The keys passed to `tr!` in `source`.
+    fn tr_keys(source: &str) -> Vec<&str> {
+        source
+            .split("tr!(")
+            .skip(1)
+            .filter_map(|call| {
+                let (_, rest) = call.split_at(call.find(',')?);
+                let rest = rest[1..].trim_start().strip_prefix('"')?;
+                Some(&rest[..rest.find('"')?])
+            })
+            .collect()
+    }
+
+    fn rust_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                rust_files(&path, files);
+            } else if path.extension().map_or(false, |ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_every_key_is_in_english() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let strings = StringTable::load(root.join(STRINGS_PATH)).unwrap();
+
+        let mut files = Vec::new();
+        rust_files(&root.join("src"), &mut files);
+
+        let mut missing = Vec::new();
+        // The tests here use keys of their own
+        for path in files.iter().filter(|path| !path.ends_with("strings.rs")) {
+            let source = fs::read_to_string(path).unwrap();
+            for key in tr_keys(&source) {
+                if strings.get(key).is_none() {
+                    missing.push(format!("{} in {}", key, path.display()));
+                }
+            }
+        }
+
+        assert!(
+            missing.is_empty(),
+            "Missing from {}: {:?}",
+            STRINGS_PATH,
+            missing
+        );
+    }
+
+    #[test]
+    fn test_tr_keys() {
+        let source = r#"
+            tr!(self.strings, "a.b", x);
+            tr!(strings,
+                "c.d")
+        "#;
+        assert_eq!(tr_keys(source), vec!["a.b", "c.d"]);
+    }
+}
@@ -1 +1,3 @@
 pub mod physics_system;
+pub mod pickup_system;
+pub mod spatial_hash_system;
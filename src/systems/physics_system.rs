@@ -1,19 +1,19 @@
 use crate::{
     components::{position::PositionComponent, velocity::VelocityComponent},
-    world::chunk::Chunk,
+    world::World,
 };
-use specs::{Read, ReadStorage, System, WriteStorage};
+use specs::{ReadExpect, ReadStorage, System, WriteStorage};
 
 pub struct PhysicsSystem;
 
 impl<'a> System<'a> for PhysicsSystem {
     type SystemData = (
-        Read<'a, Chunk>,
+        ReadExpect<'a, World>,
         ReadStorage<'a, VelocityComponent>,
         WriteStorage<'a, PositionComponent>,
     );
 
-    fn run(&mut self, (chunk, vel, mut pos): Self::SystemData) {
+    fn run(&mut self, (_world, vel, mut pos): Self::SystemData) {
         use specs::Join;
 
         for (vel, pos) in (&vel, &mut pos).join() {
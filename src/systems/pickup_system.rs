@@ -0,0 +1,251 @@
+use na::{Point3, Vector3};
+use specs::{Entities, Join, ReadExpect, System, WriteExpect, WriteStorage};
+
+use crate::{
+    components::{
+        pickup::{Flight, PickupComponent, PICKUP_FLIGHT_TICS},
+        position::PositionComponent,
+    },
+    constants::{PLAYER_EYE_HEIGHT, PLAYER_HALF_WIDTH},
+    geometry::aabb::FAabb3,
+    inventory::{Inventory, ItemKind},
+    systems::spatial_hash_system::SpatialHash,
+};
+
+/// How close to the player a pickup has to be to start flying to them.
+pub const PICKUP_RADIUS: f32 = 1.5;
+
+/// The player, as far as picking things up goes. `PickupSystem` needs it as a resource.
+pub struct Collector {
+    /// Where the player's feet are.
+    pub pos: Point3<f32>,
+    pub inventory: Inventory,
+    /// What's been picked up since this was last drained, in order, for the HUD to show.
+    pub collected: Vec<(ItemKind, u32)>,
+}
+
+impl Collector {
+    pub fn new(pos: Point3<f32>, inventory: Inventory) -> Self {
+        Self {
+            pos,
+            inventory,
+            collected: Vec::new(),
+        }
+    }
+
+    /// The space the player takes up, from their feet to their eyes.
+    pub fn aabb(&self) -> FAabb3 {
+        let half_width = Vector3::new(PLAYER_HALF_WIDTH, 0.0, PLAYER_HALF_WIDTH);
+        FAabb3::new(
+            self.pos - half_width,
+            self.pos + half_width + Vector3::y() * PLAYER_EYE_HEIGHT,
+        )
+    }
+}
+
+/// Draws pickups near the player in over a few updates and puts them in the player's inventory
+/// once they arrive. Only pickups there's room for are drawn in, and whatever doesn't fit after
+/// all, e.g. because another pickup arrived first, goes back to where it was lying. The pickups
+/// near the player are found through the `SpatialHash`, so it needs to be up to date.
+pub struct PickupSystem;
+
+impl<'a> System<'a> for PickupSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteExpect<'a, Collector>,
+        ReadExpect<'a, SpatialHash>,
+        WriteStorage<'a, PositionComponent>,
+        WriteStorage<'a, PickupComponent>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut collector, hash, mut positions, mut pickups): Self::SystemData,
+    ) {
+        let aabb = collector.aabb();
+        let target = aabb.center();
+
+        // Only the ones already flying need moving, so positions are only written when they
+        // change
+        for (entity, pickup) in (&entities, &mut pickups).join() {
+            let Flight { from, tics } = match pickup.flight {
+                Some(flight) => flight,
+                None => continue,
+            };
+            let pos = match positions.get_mut(entity) {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            let tics = tics + 1;
+            let progress = (tics as f32 / PICKUP_FLIGHT_TICS as f32).min(1.0);
+            pos.value = from + (target - from) * progress;
+
+            if tics < PICKUP_FLIGHT_TICS {
+                pickup.flight = Some(Flight { from, tics });
+                continue;
+            }
+
+            let added = collector.inventory.add(pickup.item, pickup.amount);
+            if added > 0 {
+                collector.collected.push((pickup.item, added));
+            }
+
+            pickup.amount -= added;
+            if pickup.amount == 0 {
+                entities
+                    .delete(entity)
+                    .expect("Joined a pickup that was already deleted");
+            } else {
+                pos.value = from;
+                pickup.flight = None;
+            }
+        }
+
+        // Anything within PICKUP_RADIUS of the player's box is within this of its middle
+        let reach = na::distance(&aabb.min, &aabb.max) / 2.0 + PICKUP_RADIUS;
+        for entity in hash.query_sphere(target, reach) {
+            let (pos, pickup) = match (positions.get(entity), pickups.get_mut(entity)) {
+                (Some(pos), Some(pickup)) => (pos.value, pickup),
+                _ => continue,
+            };
+
+            if pickup.flight.is_none()
+                && collector.inventory.room_for(pickup.item) > 0
+                && aabb.distance_to(pos) <= PICKUP_RADIUS
+            {
+                pickup.flight = Some(Flight { from: pos, tics: 0 });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        components::pickup::spawn_pickup, systems::spatial_hash_system::SpatialHashSystem,
+    };
+    use specs::{RunNow, World, WorldExt};
+
+    /// The world, and what keeps its spatial hash up to date.
+    fn ecs(inventory: Inventory) -> (World, SpatialHashSystem) {
+        let mut ecs = World::new();
+        ecs.register::<PositionComponent>();
+        ecs.register::<PickupComponent>();
+        ecs.insert(Collector::new(Point3::new(4.5, 1.0, 4.5), inventory));
+        let spatial = SpatialHashSystem::new(&mut ecs);
+
+        (ecs, spatial)
+    }
+
+    fn step(ecs: &mut World, spatial: &mut SpatialHashSystem, updates: u32) {
+        for _ in 0..updates {
+            spatial.run_now(ecs);
+            PickupSystem.run_now(ecs);
+            ecs.maintain();
+        }
+    }
+
+    fn pickup(ecs: &World, entity: specs::Entity) -> Option<(Point3<f32>, PickupComponent)> {
+        let positions = ecs.read_storage::<PositionComponent>();
+        let pickups = ecs.read_storage::<PickupComponent>();
+        Some((positions.get(entity)?.value, pickups.get(entity)?.clone()))
+    }
+
+    #[test]
+    fn test_flies_in_and_is_collected() {
+        let (mut ecs, mut spatial) = ecs(Inventory::new());
+        let near_pos = Point3::new(5.5, 1.5, 5.5);
+        let near = spawn_pickup(&mut ecs, near_pos, ItemKind::Ammo, 20);
+        let far_pos = Point3::new(9.5, 1.5, 9.5);
+        let far = spawn_pickup(&mut ecs, far_pos, ItemKind::Flare, 1);
+
+        // Only starts flying on the update it's noticed
+        step(&mut ecs, &mut spatial, 1);
+        let (pos, flying) = pickup(&ecs, near).unwrap();
+        assert_eq!(pos, near_pos);
+        assert!(flying.flight.is_some());
+
+        step(&mut ecs, &mut spatial, 1);
+        let target = ecs.read_resource::<Collector>().aabb().center();
+        let (pos, _) = pickup(&ecs, near).unwrap();
+        assert!(na::distance(&pos, &target) < na::distance(&near_pos, &target));
+
+        step(&mut ecs, &mut spatial, PICKUP_FLIGHT_TICS - 1);
+        assert_eq!(pickup(&ecs, near), None);
+        {
+            let collector = ecs.read_resource::<Collector>();
+            assert_eq!(collector.inventory.count(ItemKind::Ammo), 20);
+            assert_eq!(collector.collected, vec![(ItemKind::Ammo, 20)]);
+        }
+
+        // Out of reach the whole time
+        assert_eq!(
+            pickup(&ecs, far),
+            Some((far_pos, PickupComponent::new(ItemKind::Flare, 1)))
+        );
+    }
+
+    #[test]
+    fn test_several_in_one_update() {
+        let (mut ecs, mut spatial) = ecs(Inventory::new());
+        let spots = [
+            Point3::new(4.5, 1.5, 5.5),
+            Point3::new(5.5, 1.5, 4.5),
+            Point3::new(4.5, 2.5, 4.5),
+        ];
+        let entities: Vec<_> = spots
+            .iter()
+            .map(|&pos| spawn_pickup(&mut ecs, pos, ItemKind::Ammo, 10))
+            .collect();
+
+        step(&mut ecs, &mut spatial, PICKUP_FLIGHT_TICS + 1);
+
+        for &entity in &entities {
+            assert_eq!(pickup(&ecs, entity), None);
+        }
+        let collector = ecs.read_resource::<Collector>();
+        assert_eq!(collector.inventory.count(ItemKind::Ammo), 30);
+        assert_eq!(collector.collected, vec![(ItemKind::Ammo, 10); 3]);
+    }
+
+    #[test]
+    fn test_full_inventory() {
+        let mut inventory = Inventory::new();
+        let max = ItemKind::Flare.max_carried();
+        inventory.add(ItemKind::Flare, max - 1);
+        let (mut ecs, mut spatial) = ecs(inventory);
+
+        // Both set off while there's room for one more, and arrive together
+        let first_pos = Point3::new(4.5, 1.5, 5.5);
+        let first = spawn_pickup(&mut ecs, first_pos, ItemKind::Flare, 2);
+        let second_pos = Point3::new(5.5, 1.5, 4.5);
+        let second = spawn_pickup(&mut ecs, second_pos, ItemKind::Flare, 1);
+        step(&mut ecs, &mut spatial, PICKUP_FLIGHT_TICS + 1);
+
+        // The first takes the last of the room and goes back with what's left of it
+        assert_eq!(
+            pickup(&ecs, first),
+            Some((first_pos, PickupComponent::new(ItemKind::Flare, 1)))
+        );
+        assert_eq!(
+            pickup(&ecs, second),
+            Some((second_pos, PickupComponent::new(ItemKind::Flare, 1)))
+        );
+        {
+            let collector = ecs.read_resource::<Collector>();
+            assert_eq!(collector.inventory.count(ItemKind::Flare), max);
+            assert_eq!(collector.collected, vec![(ItemKind::Flare, 1)]);
+        }
+
+        // And they stay there while the player has no room
+        step(&mut ecs, &mut spatial, PICKUP_FLIGHT_TICS * 2);
+        assert_eq!(pickup(&ecs, second).unwrap().1.flight, None);
+
+        // Ammo still fits
+        let ammo = spawn_pickup(&mut ecs, first_pos, ItemKind::Ammo, 5);
+        step(&mut ecs, &mut spatial, PICKUP_FLIGHT_TICS + 1);
+        assert_eq!(pickup(&ecs, ammo), None);
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+
+use na::{Point3, Unit, Vector3};
+use ndarray::ArrayView3;
+use specs::{
+    shrev::ReaderId, storage::ComponentEvent, world::Index, Entities, Entity, Join, ReadStorage,
+    System, World, WorldExt, WriteExpect,
+};
+
+use crate::{
+    components::{collider::ColliderComponent, position::PositionComponent},
+    geometry::{
+        aabb::{FAabb3, IAabb3},
+        util::floor_point,
+    },
+    rendering::tile::Tile,
+    world::util::{raycast, RayHit},
+};
+
+/// How many tiles wide each cell of a `SpatialHash` is.
+pub const SPATIAL_CELL_SIZE: i32 = 4;
+/// The biggest collider `hitscan` can hit, so it knows how far from the ray to look.
+pub const MAX_COLLIDER_RADIUS: f32 = 2.0;
+
+/// Where every entity with a position is, bucketed into cells of `SPATIAL_CELL_SIZE` tiles so
+/// that finding the ones near a point doesn't mean looking at all of them. Kept up to date by
+/// `SpatialHashSystem`.
+#[derive(Debug, Default)]
+pub struct SpatialHash {
+    cells: HashMap<Point3<i32>, Vec<Entity>>,
+    /// Each entity and where it was when it was last put in, by its id.
+    entries: HashMap<Index, (Entity, Point3<f32>)>,
+}
+
+impl SpatialHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cell the tile at `tile` is in.
+    fn cell(tile: Point3<i32>) -> Point3<i32> {
+        tile.map(|c| c.div_euclid(SPATIAL_CELL_SIZE))
+    }
+
+    /// Where `entity` was when it was last put in.
+    pub fn position(&self, entity: Entity) -> Option<Point3<f32>> {
+        match self.entries.get(&entity.id()) {
+            Some(&(e, pos)) if e == entity => Some(pos),
+            _ => None,
+        }
+    }
+
+    /// Puts `entity` at `pos`, moving it if it's already in.
+    pub fn insert(&mut self, entity: Entity, pos: Point3<f32>) {
+        self.remove(entity.id());
+
+        let cell = Self::cell(floor_point(pos));
+        self.cells.entry(cell).or_insert_with(Vec::new).push(entity);
+        self.entries.insert(entity.id(), (entity, pos));
+    }
+
+    /// Takes out the entity with the id `id`, if there is one.
+    pub fn remove(&mut self, id: Index) {
+        let (entity, pos) = match self.entries.remove(&id) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let cell = Self::cell(floor_point(pos));
+        if let Some(entities) = self.cells.get_mut(&cell) {
+            entities.retain(|&e| e != entity);
+            if entities.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// The entities in the cells overlapping the tiles from `min` to `max`, both included, that
+    /// `keep` is true for, in no particular order.
+    fn query_cells<F>(&self, min: Point3<i32>, max: Point3<i32>, keep: F) -> Vec<Entity>
+    where
+        F: Fn(Point3<f32>) -> bool,
+    {
+        let cells = IAabb3::new(Self::cell(min), Self::cell(max) + Vector3::repeat(1));
+
+        cells
+            .iter_cells()
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|entity| keep(self.entries[&entity.id()].1))
+            .collect()
+    }
+
+    /// The entities in the tiles in `aabb`, in no particular order.
+    pub fn query_aabb(&self, aabb: &IAabb3) -> Vec<Entity> {
+        if aabb.is_empty() {
+            return Vec::new();
+        }
+
+        self.query_cells(aabb.min, aabb.max - Vector3::repeat(1), |pos| {
+            aabb.contains(floor_point(pos))
+        })
+    }
+
+    /// The entities at most `radius` from `center`, in no particular order.
+    pub fn query_sphere(&self, center: Point3<f32>, radius: f32) -> Vec<Entity> {
+        let reach = Vector3::repeat(radius);
+
+        self.query_cells(
+            floor_point(center - reach),
+            floor_point(center + reach),
+            |pos| na::distance(&pos, &center) <= radius,
+        )
+    }
+}
+
+/// Keeps the `SpatialHash` resource up to date, moving only the entities whose positions have
+/// changed since it last ran.
+pub struct SpatialHashSystem {
+    reader: ReaderId<ComponentEvent>,
+}
+
+impl SpatialHashSystem {
+    /// Starts listening for positions changing in `ecs`, and adds a `SpatialHash` to it with
+    /// the entities that are already there.
+    pub fn new(ecs: &mut World) -> Self {
+        let reader = ecs.write_storage::<PositionComponent>().register_reader();
+
+        let mut hash = SpatialHash::new();
+        for (entity, pos) in (&ecs.entities(), &ecs.read_storage::<PositionComponent>()).join() {
+            hash.insert(entity, pos.value);
+        }
+        ecs.insert(hash);
+
+        Self { reader }
+    }
+}
+
+impl<'a> System<'a> for SpatialHashSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, PositionComponent>,
+        WriteExpect<'a, SpatialHash>,
+    );
+
+    fn run(&mut self, (entities, positions, mut hash): Self::SystemData) {
+        for event in positions.channel().read(&mut self.reader) {
+            match *event {
+                ComponentEvent::Inserted(id) | ComponentEvent::Modified(id) => {
+                    let entity = entities.entity(id);
+                    match positions.get(entity) {
+                        Some(pos) => hash.insert(entity, pos.value),
+                        None => hash.remove(id),
+                    }
+                }
+                ComponentEvent::Removed(id) => hash.remove(id),
+            }
+        }
+    }
+}
+
+/// What a shot hit first, found by `hitscan`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShotHit {
+    Entity { entity: Entity, t: f32 },
+    Tile(RayHit),
+}
+
+/// How far along a ray it enters the sphere at `center`, or `None` if it misses.
+fn ray_sphere(
+    origin: Point3<f32>,
+    dir: &Unit<Vector3<f32>>,
+    center: Point3<f32>,
+    radius: f32,
+) -> Option<f32> {
+    let to_center = center - origin;
+    let along = to_center.dot(dir);
+    let miss_squared = to_center.norm_squared() - along * along;
+    if miss_squared > radius * radius {
+        return None;
+    }
+
+    let t = along - (radius * radius - miss_squared).sqrt();
+    if t >= 0.0 {
+        Some(t)
+    } else if along >= 0.0 {
+        // Starting inside it
+        Some(0.0)
+    } else {
+        None
+    }
+}
+
+/// Follows a shot from `origin` along `dir` to the first entity with a collider or solid tile it
+/// hits within `max_dist`. Entities are looked for first, through `hash`, so the ray through the
+/// tiles only has to go as far as the nearest one.
+pub fn hitscan(
+    tiles: ArrayView3<Tile>,
+    hash: &SpatialHash,
+    colliders: &ReadStorage<ColliderComponent>,
+    origin: Point3<f32>,
+    dir: Unit<Vector3<f32>>,
+    max_dist: f32,
+) -> Option<ShotHit> {
+    let end = origin + dir.into_inner() * max_dist;
+    let reach = Vector3::repeat(MAX_COLLIDER_RADIUS);
+    let around_ray = FAabb3::new(origin.inf(&end) - reach, origin.sup(&end) + reach).cells();
+
+    let nearest = hash
+        .query_aabb(&around_ray)
+        .into_iter()
+        .filter_map(|entity| {
+            let collider = colliders.get(entity)?;
+            let pos = hash.position(entity)?;
+            let t = ray_sphere(origin, &dir, pos, collider.radius)?;
+            Some((entity, t))
+        })
+        .filter(|&(_, t)| t <= max_dist)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    let tile_dist = nearest.map_or(max_dist, |(_, t)| t);
+    match raycast(tiles, origin, dir, tile_dist) {
+        Some(hit) => Some(ShotHit::Tile(hit)),
+        None => nearest.map(|(entity, t)| ShotHit::Entity { entity, t }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::tile::{tiles_from_fn, TileType};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use specs::{Builder, RunNow};
+
+    fn ecs() -> (World, SpatialHashSystem) {
+        let mut ecs = World::new();
+        ecs.register::<PositionComponent>();
+        ecs.register::<ColliderComponent>();
+        let system = SpatialHashSystem::new(&mut ecs);
+
+        (ecs, system)
+    }
+
+    fn spawn(ecs: &mut World, pos: Point3<f32>) -> Entity {
+        ecs.create_entity()
+            .with(PositionComponent { value: pos })
+            .build()
+    }
+
+    fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+        entities.sort_by_key(|entity| entity.id());
+        entities
+    }
+
+    #[test]
+    fn test_straddling_cells() {
+        let (mut ecs, mut system) = ecs();
+        // Either side of the boundary between two cells along x, and one below zero
+        let below = spawn(&mut ecs, Point3::new(3.99, 1.0, 1.0));
+        let above = spawn(&mut ecs, Point3::new(4.0, 1.0, 1.0));
+        let negative = spawn(&mut ecs, Point3::new(-0.01, 1.0, 1.0));
+        system.run_now(&ecs);
+        let hash = ecs.read_resource::<SpatialHash>();
+        assert_eq!(hash.entries.len(), 3);
+
+        // A sphere across the boundary finds both sides
+        assert_eq!(
+            sorted(hash.query_sphere(Point3::new(4.0, 1.0, 1.0), 0.5)),
+            vec![below, above]
+        );
+        assert_eq!(
+            hash.query_sphere(Point3::new(4.5, 1.0, 1.0), 0.5),
+            vec![above]
+        );
+
+        // A box ending at the boundary doesn't reach past it
+        let up_to = IAabb3::new(Point3::new(0, 0, 0), Point3::new(4, 2, 2));
+        assert_eq!(hash.query_aabb(&up_to), vec![below]);
+        let across = IAabb3::new(Point3::new(-1, 0, 0), Point3::new(5, 2, 2));
+        assert_eq!(
+            sorted(hash.query_aabb(&across)),
+            vec![below, above, negative]
+        );
+        assert_eq!(
+            hash.query_aabb(&IAabb3::new(Point3::new(2, 0, 0), Point3::new(2, 2, 2))),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_moved_and_deleted() {
+        let (mut ecs, mut system) = ecs();
+        let moving = spawn(&mut ecs, Point3::new(1.0, 1.0, 1.0));
+        let deleted = spawn(&mut ecs, Point3::new(2.0, 1.0, 1.0));
+        system.run_now(&ecs);
+
+        ecs.write_storage::<PositionComponent>()
+            .get_mut(moving)
+            .unwrap()
+            .value = Point3::new(9.0, 1.0, 1.0);
+        ecs.delete_entity(deleted).unwrap();
+        ecs.maintain();
+        system.run_now(&ecs);
+
+        let hash = ecs.read_resource::<SpatialHash>();
+        assert_eq!(hash.entries.len(), 1);
+        assert_eq!(hash.position(moving), Some(Point3::new(9.0, 1.0, 1.0)));
+        assert_eq!(hash.position(deleted), None);
+        assert_eq!(hash.query_sphere(Point3::new(1.0, 1.0, 1.0), 2.0), vec![]);
+        assert_eq!(
+            hash.query_sphere(Point3::new(9.0, 1.0, 1.0), 0.0),
+            vec![moving]
+        );
+    }
+
+    #[test]
+    fn test_queries_match_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (mut ecs, mut system) = ecs();
+        let random_point = |rng: &mut StdRng| {
+            Point3::new(
+                rng.gen_range(-20.0, 20.0),
+                rng.gen_range(-20.0, 20.0),
+                rng.gen_range(-20.0, 20.0),
+            )
+        };
+
+        for _ in 0..200 {
+            let pos = random_point(&mut rng);
+            spawn(&mut ecs, pos);
+        }
+        // Some of them move after they've been put in
+        system.run_now(&ecs);
+        {
+            let mut positions = ecs.write_storage::<PositionComponent>();
+            for pos in (&mut positions).join().step_by(3) {
+                pos.value = random_point(&mut rng);
+            }
+        }
+        system.run_now(&ecs);
+
+        let entities = ecs.entities();
+        let positions = ecs.read_storage::<PositionComponent>();
+        let hash = ecs.read_resource::<SpatialHash>();
+        let everything: Vec<_> = (&entities, &positions).join().collect();
+
+        for _ in 0..100 {
+            let center = random_point(&mut rng);
+            let radius = rng.gen_range(0.0, 12.0);
+            let expected: Vec<_> = everything
+                .iter()
+                .filter(|(_, pos)| na::distance(&pos.value, &center) <= radius)
+                .map(|&(entity, _)| entity)
+                .collect();
+            assert_eq!(sorted(hash.query_sphere(center, radius)), expected);
+
+            let aabb = IAabb3::new(
+                floor_point(center),
+                floor_point(center) + Vector3::new(rng.gen_range(0, 12), 5, rng.gen_range(0, 12)),
+            );
+            let expected: Vec<_> = everything
+                .iter()
+                .filter(|(_, pos)| aabb.contains(floor_point(pos.value)))
+                .map(|&(entity, _)| entity)
+                .collect();
+            assert_eq!(sorted(hash.query_aabb(&aabb)), expected);
+        }
+    }
+
+    #[test]
+    fn test_hitscan() {
+        let (mut ecs, mut system) = ecs();
+        // Open along x, with a wall at x = 8
+        let tiles = tiles_from_fn((12, 3, 3), |(x, _, _)| {
+            if x == 8 {
+                TileType::Rock0
+            } else {
+                TileType::Air
+            }
+        });
+        let target = ecs
+            .create_entity()
+            .with(PositionComponent {
+                value: Point3::new(5.5, 1.5, 1.5),
+            })
+            .with(ColliderComponent { radius: 0.5 })
+            .build();
+        // No collider, so shots go through it
+        spawn(&mut ecs, Point3::new(3.5, 1.5, 1.5));
+        system.run_now(&ecs);
+
+        let hash = ecs.read_resource::<SpatialHash>();
+        let colliders = ecs.read_storage::<ColliderComponent>();
+        let shoot = |origin: Point3<f32>, dir: Vector3<f32>| {
+            hitscan(
+                tiles.view(),
+                &hash,
+                &colliders,
+                origin,
+                Unit::new_normalize(dir),
+                20.0,
+            )
+        };
+
+        match shoot(Point3::new(1.5, 1.5, 1.5), Vector3::x()) {
+            Some(ShotHit::Entity { entity, t }) => {
+                assert_eq!(entity, target);
+                assert!((t - 3.5).abs() < 1e-4);
+            }
+            hit => panic!("Expected to hit the target, got {:?}", hit),
+        }
+        // Past it, or from behind the wall
+        match shoot(Point3::new(6.5, 1.5, 1.5), Vector3::x()) {
+            Some(ShotHit::Tile(hit)) => assert_eq!(hit.cell, Point3::new(8, 1, 1)),
+            hit => panic!("Expected to hit the wall, got {:?}", hit),
+        }
+        match shoot(Point3::new(10.5, 1.5, 1.5), -Vector3::x()) {
+            Some(ShotHit::Tile(hit)) => assert_eq!(hit.cell, Point3::new(8, 1, 1)),
+            hit => panic!("Expected to hit the wall, got {:?}", hit),
+        }
+        // Off to the side of it
+        match shoot(Point3::new(1.5, 1.5, 0.4), Vector3::x()) {
+            Some(ShotHit::Tile(hit)) => assert_eq!(hit.cell, Point3::new(8, 1, 0)),
+            hit => panic!("Expected to miss the target, got {:?}", hit),
+        }
+    }
+}
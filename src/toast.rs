@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+
+use ggez::mint;
+
+use crate::ui::{KataText, VoxelBatch};
+
+/// How many updates a toast stays up for.
+pub const TOAST_UPDATES: u64 = 180;
+/// The most toasts shown at once. Older ones are dropped early to make room.
+pub const MAX_TOASTS: usize = 4;
+
+/// Short messages shown on the HUD for a few seconds, e.g. for picking something up.
+#[derive(Clone, Debug, Default)]
+pub struct Toasts {
+    /// Each message and the update it goes away on, oldest first.
+    messages: VecDeque<(String, u64)>,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows `text` from update `now` for `TOAST_UPDATES` updates.
+    pub fn push(&mut self, text: String, now: u64) {
+        if self.messages.len() == MAX_TOASTS {
+            self.messages.pop_front();
+        }
+
+        self.messages.push_back((text, now + TOAST_UPDATES));
+    }
+
+    /// Drops the messages that have been up long enough by update `now`.
+    pub fn expire(&mut self, now: u64) {
+        self.messages.retain(|&(_, until)| until > now);
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// The messages, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.messages.iter().map(|(text, _)| text.as_str())
+    }
+}
+
+/// Draws a line for each toast down from `origin`, oldest first.
+pub fn draw_toasts(batch: &mut dyn VoxelBatch, toasts: &Toasts, origin: mint::Point2<u32>) {
+    for (y, text) in toasts.iter().enumerate() {
+        for (x, voxel) in KataText::from_str(text).voxels.iter().enumerate() {
+            batch.add(voxel, [origin.x + x as u32, origin.y + y as u32].into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{Size, TextGridBatch};
+
+    #[test]
+    fn test_expire() {
+        let mut toasts = Toasts::new();
+        toasts.push(String::from("a"), 0);
+        toasts.push(String::from("b"), 10);
+
+        toasts.expire(TOAST_UPDATES - 1);
+        assert_eq!(toasts.iter().collect::<Vec<_>>(), vec!["a", "b"]);
+        toasts.expire(TOAST_UPDATES);
+        assert_eq!(toasts.iter().collect::<Vec<_>>(), vec!["b"]);
+        toasts.expire(TOAST_UPDATES + 10);
+        assert_eq!(toasts.len(), 0);
+    }
+
+    #[test]
+    fn test_oldest_make_room() {
+        let mut toasts = Toasts::new();
+        for i in 0..MAX_TOASTS + 2 {
+            toasts.push(i.to_string(), 0);
+        }
+
+        assert_eq!(toasts.len(), MAX_TOASTS);
+        assert_eq!(toasts.iter().next(), Some("2"));
+    }
+
+    #[test]
+    fn test_draw_toasts() {
+        let mut toasts = Toasts::new();
+        toasts.push(String::from("hi"), 0);
+        toasts.push(String::from("there"), 0);
+
+        let mut batch = TextGridBatch::new(Size {
+            width: 7,
+            height: 3,
+        });
+        draw_toasts(&mut batch, &toasts, [1, 1].into());
+        assert_eq!(batch.lines(), vec!["       ", " hi    ", " there "]);
+    }
+}
@@ -1,22 +1,43 @@
+mod containers;
+mod controls;
+mod decorators;
+mod layout;
+mod list;
+mod scroll;
+mod text;
+
+pub use containers::{DialogHandle, Modal, Tabs};
+pub use controls::{Button, Picker, ProgressBar, Slider, Stepper, TextInput, VoxelDisplay};
+pub use decorators::{
+    Centered, Enabled, Frame, HandlerOrder, Padding, PanelBackground, Tooltip, WithEvents,
+};
+pub use layout::{
+    Filling, FlexElement, FlexLayout, GridLayout, Placeholder, StackedElement, StackedLayout,
+};
+pub use list::{moved_index, List, ListElement, Row, SelectableList};
+pub use scroll::{ScrollBar, ScrollView};
+pub use text::{KataText, WrapMode};
+
 use std::{
-    cmp::Ordering,
-    ops::{Deref, DerefMut, Index, IndexMut, Range},
+    collections::HashMap,
+    ops::{Index, IndexMut},
+    time::{Duration, Instant},
 };
 
-use flo_binding::{bind, Binding, Bound, MutableBound};
+use flo_binding::Binding;
 use ggez::{
-    input::mouse::{self, MouseButton},
+    input::{
+        keyboard::{KeyCode, KeyMods},
+        mouse::{self, MouseButton},
+    },
     mint, Context,
 };
-use log::trace;
+use log::{trace, warn};
 
 use crate::{
-    geometry::rect::IRect,
-    rendering::{
-        color::{self, Color},
-        font::KataFontBatch,
-        voxel::Voxel2,
-    },
+    geometry::rect::{IRect, SRect},
+    rendering::{color, font::KataFontBatch, voxel::Voxel2},
+    strings::StringTable,
 };
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -84,11 +105,11 @@ impl Default for Size {
 }
 
 pub trait PointExt {
-    fn dir(&self, direction: LayoutDirection) -> &u32;
+    fn dir(&self, direction: LayoutDirection) -> &i32;
 }
 
-impl PointExt for mint::Point2<u32> {
-    fn dir(&self, direction: LayoutDirection) -> &u32 {
+impl PointExt for mint::Point2<i32> {
+    fn dir(&self, direction: LayoutDirection) -> &i32 {
         match direction {
             LayoutDirection::Horizontal => &self.x,
             LayoutDirection::Vertical => &self.y,
@@ -144,1110 +165,653 @@ impl BoxConstraints {
     pub fn constrain(&self, size: Size) -> Size {
         size.min(self.max).max(self.min)
     }
-}
 
-pub struct UiContext {
-    pub relayout: bool,
-    pub batch: KataFontBatch,
-}
-
-impl UiContext {
-    pub fn new(batch: KataFontBatch) -> Self {
-        Self {
-            relayout: true,
-            batch,
-        }
-    }
-
-    pub fn mouse_pos(&self, ctx: &Context) -> mint::Point2<u32> {
-        let p = mouse::position(ctx);
-        mint::Point2::from([
-            (p.x / self.batch.tile_width()) as u32,
-            (p.y / self.batch.tile_height()) as u32,
-        ])
+    /// Whether the maximum size along `direction` is finite, i.e. not set to `u32::max_value()`
+    /// by a scrolling container.
+    pub fn is_bounded(&self, direction: LayoutDirection) -> bool {
+        self.max[direction] != u32::max_value()
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum Event {
-    Mouse {
-        pos: mint::Point2<u32>,
-        e: MouseEvent,
-    },
-
-    Draw,
-}
-
-impl Event {
-    pub fn cull(self, bounds: IRect) -> Option<Self> {
-        let keep = match self {
-            Event::Mouse { pos, e } => match e {
-                MouseEvent::ButtonDrag { start_pos, .. } => bounds.contains(start_pos),
-                _ => bounds.contains(pos),
-            },
-
-            Event::Draw => true,
-        };
-
-        if keep {
-            Some(self)
-        } else {
-            None
+/// Elements get a `UiContext` over `dyn VoxelBatch` so that they can be drawn into anything
+/// that implements it, like a `TextGridBatch` in tests. `batch` has to stay the last field for
+/// `&mut UiContext<KataFontBatch>` to coerce to that.
+pub struct UiContext<B: ?Sized = dyn VoxelBatch> {
+    pub relayout: bool,
+    /// How many times `relayout_if_needed` has laid the root out again.
+    pub relayouts: u64,
+    pub debug_overflow: DebugOverflow,
+    pub config: UiConfig,
+    /// Counts Draw passes for animations. Whoever sends the Draw event advances it.
+    pub frame: u64,
+    /// Whether a text input had focus during the last Draw pass, in which case keyboard
+    /// shortcuts should leave keys alone. Whoever sends the Draw event clears it first.
+    pub typing: bool,
+    /// The text elements and whoever builds them should show, in the player's language.
+    pub strings: StringTable,
+    overlays: Vec<Overlay>,
+    viewports: Vec<Viewport>,
+    dimmed: u32,
+    overflows: Vec<SRect>,
+    reported_overflows: Vec<SRect>,
+    pub batch: B,
+}
+
+/// Receives the voxels drawn through a `UiContext`, one cell at a time.
+pub trait VoxelBatch {
+    fn add(&mut self, voxel: &Voxel2, dest: mint::Point2<u32>);
+
+    /// Adds `voxel` drawn `magnification` times as big. Batches that can only draw whole cells
+    /// repeat it over the cells it would cover.
+    fn add_magnified(&mut self, voxel: &Voxel2, dest: mint::Point2<u32>, magnification: u32) {
+        for y in 0..magnification {
+            for x in 0..magnification {
+                self.add(voxel, mint::Point2::from([dest.x + x, dest.y + y]));
+            }
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum MouseEvent {
-    ButtonDown {
-        button: MouseButton,
-    },
-    ButtonUp {
-        button: MouseButton,
-    },
-    ButtonDrag {
-        button: MouseButton,
-        start_pos: mint::Point2<u32>,
-    },
-
-    WheelUp,
-    WheelDown,
-}
-
-pub struct Continue;
-pub struct Stop;
-pub type EventResult = Result<Continue, Stop>;
-
-pub trait Element {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size;
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult;
-}
-
-pub trait ElementExt: Element + Sized {
-    fn with_events<F>(self, handler: F) -> WithEvents<Self, F>
-    where
-        F: FnMut(&mut Self, &mut UiContext, Event, IRect) -> EventResult;
-}
-
-impl<T: Element + Sized> ElementExt for T {
-    fn with_events<F>(self, handler: F) -> WithEvents<Self, F>
-    where
-        F: FnMut(&mut Self, &mut UiContext, Event, IRect) -> EventResult,
-    {
-        WithEvents {
-            element: self,
-            handler,
-        }
+impl VoxelBatch for KataFontBatch {
+    fn add(&mut self, voxel: &Voxel2, dest: mint::Point2<u32>) {
+        KataFontBatch::add(self, voxel, dest)
     }
-}
-
-#[derive(Debug, Clone, Copy)]
-pub enum LayoutDirection {
-    Horizontal,
-    Vertical,
-}
 
-impl LayoutDirection {
-    pub fn other(self) -> Self {
-        match self {
-            LayoutDirection::Horizontal => LayoutDirection::Vertical,
-            LayoutDirection::Vertical => LayoutDirection::Horizontal,
-        }
+    fn add_magnified(&mut self, voxel: &Voxel2, dest: mint::Point2<u32>, magnification: u32) {
+        KataFontBatch::add_magnified(self, voxel, dest, magnification)
     }
 }
 
-pub struct List {
-    pub elements: Vec<ListElement>,
-    pub scrollbar: ScrollBar,
-    pub scrollbar_size: Option<Size>,
+/// Records what's drawn into a grid of cells so that tests can compare it against text.
+#[cfg(test)]
+pub struct TextGridBatch {
+    size: Size,
+    cells: Vec<Voxel2>,
 }
 
-impl List {
-    pub fn new() -> Self {
-        Self::from_vec(Vec::new())
-    }
-
-    pub fn from_vec(elements: Vec<ListElement>) -> Self {
+#[cfg(test)]
+impl TextGridBatch {
+    pub fn new(size: Size) -> Self {
         Self {
-            elements,
-            scrollbar_size: None,
-            scrollbar: ScrollBar::new(bind(0), bind(0), LayoutDirection::Vertical),
+            size,
+            cells: vec![Voxel2::new(0); (size.width * size.height) as usize],
         }
     }
-}
-
-impl Element for List {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("List relayout");
-
-        let elements_size = layout_list_elements(
-            &mut self.elements,
-            BoxConstraints::new(
-                Size::ZERO,
-                Size::new(constraints.max.width, u32::max_value()),
-            ),
-        );
-
-        let (width, scrollbar_size, scroll_pos, scroll_max) =
-            if elements_size.height > constraints.max.height {
-                trace!("List overflow");
-                // If the elements overflow, display the scrollbar
-                let scrollbar_size = self
-                    .scrollbar
-                    .layout(BoxConstraints::exact(Size::new(1, constraints.max.height)));
-
-                let elements_size = layout_list_elements(
-                    &mut self.elements,
-                    BoxConstraints::new(
-                        Size::ZERO,
-                        Size::new(
-                            constraints.max.width - scrollbar_size.width,
-                            u32::max_value(),
-                        ),
-                    ),
-                );
-
-                // Move up the list if we have room for elements from our current scroll offset
-                let mut fill_height = 0;
-                let mut scroll_max = (self.elements.len() - 1) as u32;
-                for element in self.elements.iter().rev() {
-                    fill_height += element.size.unwrap().height;
-
-                    if fill_height > constraints.max.height {
-                        break;
-                    }
-
-                    scroll_max -= 1;
-                }
 
-                (
-                    elements_size.width + 1,
-                    Some(scrollbar_size),
-                    self.scrollbar.scroll_pos.get().min(scroll_max),
-                    scroll_max,
-                )
-            } else {
-                (elements_size.width, None, 0, 0)
-            };
+    /// Lays `element` out to fill `size`, then draws it and any overlays it queued.
+    pub fn render(element: &mut dyn Element, size: Size) -> Self {
+        let bounds = IRect::new(0, 0, size.width, size.height);
+        let mut ctx = UiContext::new(Self::new(size));
 
-        self.scrollbar_size = scrollbar_size;
-        self.scrollbar.scroll_pos.set(scroll_pos);
-        self.scrollbar.scroll_max.set(scroll_max);
+        element.layout(BoxConstraints::exact(size));
+        let _ = element.handle_event(&mut ctx, Event::Draw, bounds.into());
+        ctx.draw_overlays(bounds);
 
-        Size::new(width, constraints.max.height)
+        ctx.batch
     }
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        match event.cull(bounds) {
-            Some(Event::Mouse { e, .. }) => match e {
-                MouseEvent::WheelUp => {
-                    self.scrollbar
-                        .scroll_pos
-                        .set(self.scrollbar.scroll_pos.get().saturating_sub(1));
-                    return Err(Stop);
-                }
-
-                MouseEvent::WheelDown => {
-                    self.scrollbar.scroll_pos.set(
-                        (self.scrollbar.scroll_pos.get() + 1).min(self.scrollbar.scroll_max.get()),
-                    );
-                    return Err(Stop);
-                }
+    pub fn cell(&self, x: u32, y: u32) -> &Voxel2 {
+        &self.cells[(y * self.size.width + x) as usize]
+    }
 
-                _ => {}
-            },
+    /// One line per row of cells. Blank cells with a background show up as `.`, and the
+    /// box-drawing and scrollbar glyphs as their closest ASCII lookalike.
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.size.height)
+            .map(|y| {
+                (0..self.size.width)
+                    .map(|x| Self::cell_char(self.cell(x, y)))
+                    .collect()
+            })
+            .collect()
+    }
 
-            _ => {}
+    fn cell_char(voxel: &Voxel2) -> char {
+        match voxel.char_offset {
+            0 | 0x20 if voxel.background.is_some() => '.',
+            0 => ' ',
+            c @ 0x20..=0x7E => c as u8 as char,
+            0x10 => '>',
+            0x11 => '<',
+            0x1E => '^',
+            0x1F => 'v',
+            0x260..=0x264 | 0x268..=0x26B => '+',
+            0x265 => '-',
+            0x266 => '|',
+            0x2EC => '#',
+            _ => '?',
         }
+    }
+}
 
-        if let Some(scrollbar_size) = self.scrollbar_size {
-            self.scrollbar.handle_event(
-                ctx,
-                event,
-                IRect::new(
-                    bounds.right() - scrollbar_size.width,
-                    bounds.y,
-                    scrollbar_size.width,
-                    scrollbar_size.height,
-                ),
-            )?;
+#[cfg(test)]
+impl VoxelBatch for TextGridBatch {
+    /// Like the font batch, a background stays until another one is drawn over it, while a
+    /// blank glyph doesn't hide what's under it.
+    fn add(&mut self, voxel: &Voxel2, dest: mint::Point2<u32>) {
+        if dest.x >= self.size.width || dest.y >= self.size.height {
+            return;
         }
 
-        let mut y = 0;
-
-        for element in self
-            .elements
-            .iter_mut()
-            .skip(self.scrollbar.scroll_pos.get() as usize)
-        {
-            if let Some(size) = element.size {
-                let bottom = y + size.height;
-
-                if bottom > bounds.bottom() {
-                    break;
-                }
-
-                element.element.handle_event(
-                    ctx,
-                    event,
-                    IRect::new(bounds.x, bounds.y + y, size.width, size.height),
-                )?;
+        let cell = &mut self.cells[(dest.y * self.size.width + dest.x) as usize];
+        let background = voxel.background.or(cell.background);
 
-                y = bottom;
-            }
+        if voxel.char_offset != 0 {
+            *cell = voxel.clone();
         }
 
-        Ok(Continue)
+        cell.background = background;
     }
 }
 
-fn layout_list_elements(elements: &mut [ListElement], constraints: BoxConstraints) -> Size {
-    let mut size = Size::new(0, 0);
+/// How much the foreground of disabled elements is dimmed by.
+const DIMMED_FACTOR: f32 = 0.4;
 
-    for element in elements {
-        let element_size = element.element.layout(constraints);
-
-        size.width = size.width.max(element_size.width);
-        size.height += element_size.height;
-
-        element.size = Some(element_size);
-    }
-
-    size
+/// What to do about elements that wanted more room than their parent gave them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DebugOverflow {
+    Off,
+    /// Log each overflowing element once, when it starts overflowing.
+    Log,
+    /// Log, and also fill the overflowing elements with red.
+    Highlight,
 }
 
-pub struct ListElement {
-    pub element: Box<dyn Element>,
-    size: Option<Size>,
+/// A region of the screen that drawing is clipped to.
+#[derive(Clone, Copy, Debug)]
+struct Viewport {
+    clip: SRect,
 }
 
-impl ListElement {
-    pub fn new(element: Box<dyn Element>) -> Self {
+impl<B: VoxelBatch> UiContext<B> {
+    pub fn new(batch: B) -> Self {
         Self {
-            element,
-            size: None,
+            relayout: true,
+            relayouts: 0,
+            debug_overflow: DebugOverflow::Off,
+            config: UiConfig::default(),
+            frame: 0,
+            typing: false,
+            strings: StringTable::default(),
+            overlays: Vec::new(),
+            viewports: Vec::new(),
+            dimmed: 0,
+            overflows: Vec::new(),
+            reported_overflows: Vec::new(),
+            batch,
         }
     }
-}
 
-impl From<Box<dyn Element>> for ListElement {
-    fn from(element: Box<dyn Element>) -> Self {
-        Self::new(element)
+    pub fn with_strings(self, strings: StringTable) -> Self {
+        Self { strings, ..self }
     }
 }
 
-pub struct Padding<T> {
-    inner: T,
-    top: u32,
-    right: u32,
-    bottom: u32,
-    left: u32,
-}
-
-impl<T: Element> Padding<T> {
-    pub fn new(inner: T, top: u32, right: u32, bottom: u32, left: u32) -> Self {
-        Self {
-            inner,
-            top,
-            right,
-            bottom,
-            left,
+impl<B: VoxelBatch + ?Sized> UiContext<B> {
+    /// Lays `root` out to fill `size` if anything asked for a relayout since it last was.
+    /// Returns whether it did.
+    pub fn relayout_if_needed(&mut self, root: &mut dyn Element, size: Size) -> bool {
+        if !self.relayout {
+            return false;
         }
-    }
-}
 
-impl<T: Element> Element for Padding<T> {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("Padding relayout");
+        trace!("Root relayout");
+        self.relayout = false;
+        self.relayouts += 1;
+        root.layout(BoxConstraints::exact(size));
 
-        self.inner
-            .layout(constraints.shrink(Size::new(self.right + self.left, self.top + self.bottom)))
+        true
     }
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        self.inner.handle_event(
-            ctx,
-            event,
-            IRect::new(
-                bounds.x + self.left,
-                bounds.y + self.top,
-                bounds.w.saturating_sub(self.left + self.right),
-                bounds.h.saturating_sub(self.top + self.bottom),
-            ),
-        )
+    /// Adds a voxel to the batch, clipping it to the current viewport and to the screen.
+    pub fn draw<P>(&mut self, voxel: &Voxel2, dest: P)
+    where
+        P: Into<mint::Point2<i32>>,
+    {
+        self.draw_magnified(voxel, dest, 1);
     }
-}
 
-pub struct Centered<T> {
-    inner: T,
-    inner_size: Option<Size>,
-}
+    /// Like `draw`, but `magnification` times as big. The voxel is left out unless all of the
+    /// cells it covers are within the viewport.
+    pub fn draw_magnified<P>(&mut self, voxel: &Voxel2, dest: P, magnification: u32)
+    where
+        P: Into<mint::Point2<i32>>,
+    {
+        let dest = dest.into();
+        let dimmed_voxel;
+        let voxel = if self.dimmed > 0 {
+            dimmed_voxel = voxel
+                .clone()
+                .foreground(voxel.foreground.dimmed(DIMMED_FACTOR));
+            &dimmed_voxel
+        } else {
+            voxel
+        };
 
-impl<T: Element> Centered<T> {
-    pub fn new(inner: T) -> Self {
-        Self {
-            inner,
-            inner_size: None,
+        let cells = SRect::new(dest.x, dest.y, magnification.max(1), magnification.max(1));
+        let visible = self
+            .viewports
+            .last()
+            .map_or(true, |viewport| viewport.clip.contains_rect(&cells));
+
+        if dest.x >= 0 && dest.y >= 0 && visible {
+            let dest = mint::Point2::from([dest.x as u32, dest.y as u32]);
+            self.batch.add_magnified(voxel, dest, magnification);
         }
     }
-}
-
-impl<T: Element> Element for Centered<T> {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("Centered relayout");
 
-        self.inner_size = dbg!(Some(
-            self.inner
-                .layout(BoxConstraints::new(Size::new(0, 0), constraints.max))
-        ));
-        constraints.max
-    }
+    /// Restricts drawing to the part of `clip` inside the current viewport until the matching
+    /// `pop_viewport`.
+    pub fn push_viewport(&mut self, clip: SRect) {
+        let clip = match self.viewports.last() {
+            Some(parent) => clip.intersection(&parent.clip).unwrap_or_default(),
+            None => clip,
+        };
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        let inner_size = self.inner_size.unwrap();
-
-        self.inner.handle_event(
-            ctx,
-            event,
-            IRect::new(
-                bounds.x + (bounds.w - inner_size.width) / 2,
-                bounds.y + (bounds.h - inner_size.height) / 2,
-                inner_size.width,
-                inner_size.height,
-            ),
-        )
+        self.viewports.push(Viewport { clip });
     }
-}
-
-pub struct ScrollBar {
-    pub scroll_pos: Binding<u32>,
-    pub scroll_max: Binding<u32>,
-    pub direction: LayoutDirection,
-}
 
-impl ScrollBar {
-    pub fn new(
-        scroll_pos: Binding<u32>,
-        scroll_max: Binding<u32>,
-        direction: LayoutDirection,
-    ) -> Self {
-        Self {
-            scroll_pos,
-            scroll_max,
-            direction,
-        }
+    pub fn pop_viewport(&mut self) {
+        self.viewports.pop();
     }
 
-    pub fn scroll_up(&mut self, ctx: &mut UiContext) {
-        self.scroll_to(ctx, self.scroll_pos.get().saturating_sub(1));
+    /// Dims the foreground of everything drawn until the matching `pop_dimmed`.
+    pub fn push_dimmed(&mut self) {
+        self.dimmed += 1;
     }
 
-    pub fn scroll_down(&mut self, ctx: &mut UiContext) {
-        self.scroll_to(ctx, self.scroll_pos.get() + 1);
+    pub fn pop_dimmed(&mut self) {
+        self.dimmed = self.dimmed.saturating_sub(1);
     }
 
-    pub fn scroll_to(&mut self, ctx: &mut UiContext, new_pos: u32) {
-        let old_pos = self.scroll_pos.get();
-        if old_pos != new_pos {
-            self.scroll_pos.set(new_pos.min(self.scroll_max.get()));
-            ctx.relayout = true;
-        }
+    /// The area that is currently visible, if drawing is clipped to a viewport.
+    pub fn visible_rect(&self) -> Option<SRect> {
+        self.viewports.last().map(|viewport| viewport.clip)
     }
 
-    fn caret_pos(&self, size: Size) -> u32 {
-        let scroll_r = self.scroll_pos.get() as f32 / self.scroll_max.get() as f32;
-        (scroll_r * (size.dir(self.direction).saturating_sub(3)) as f32).round() as u32
+    /// Queues voxels to be drawn on top of the layout once the Draw pass is done.
+    pub fn push_overlay(&mut self, overlay: Overlay) {
+        self.overlays.push(overlay);
     }
-}
 
-impl Element for ScrollBar {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("ScrollBar relayout");
+    /// Called during the Draw pass by containers for each child that overflowed in `bounds`.
+    pub fn report_overflow(&mut self, bounds: SRect) {
+        match self.debug_overflow {
+            DebugOverflow::Off => return,
+            DebugOverflow::Log => {}
+            DebugOverflow::Highlight => {
+                let red = Voxel2::new(0).background(Some(color::RED));
 
-        match self.direction {
-            LayoutDirection::Horizontal => {
-                Size::new(constraints.max.width, constraints.min.height.max(1))
-            }
-
-            LayoutDirection::Vertical => {
-                Size::new(constraints.min.width.max(1), constraints.max.height)
+                for p in bounds.points() {
+                    self.draw(&red, p);
+                }
             }
         }
+
+        self.overflows.push(bounds);
     }
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        let bar_bounds = match self.direction {
-            LayoutDirection::Horizontal => {
-                IRect::new(bounds.x + 1, bounds.y, bounds.w.saturating_sub(2), bounds.h)
-            }
-            LayoutDirection::Vertical => {
-                IRect::new(bounds.x, bounds.y + 1, bounds.w, bounds.h.saturating_sub(2))
+    /// Draws and clears the queued overlays, shifting each one so it stays inside `screen`.
+    /// This ends the Draw pass, so it's also where newly overflowing elements are logged.
+    pub fn draw_overlays(&mut self, screen: IRect) {
+        for bounds in self.overflows.iter() {
+            if !self.reported_overflows.contains(bounds) {
+                warn!("Element at {:?} doesn't fit into its constraints", bounds);
             }
-        };
-
-        match event.cull(bounds) {
-            Some(Event::Mouse { pos, e }) => match e {
-                MouseEvent::WheelUp => {
-                    self.scroll_up(ctx);
-                    Err(Stop)
-                }
-
-                MouseEvent::WheelDown => {
-                    self.scroll_down(ctx);
-                    Err(Stop)
-                }
-
-                MouseEvent::ButtonDown { button } if button == MouseButton::Left => {
-                    let scrollbar_pos =
-                        pos.dir(self.direction) - bounds.point().dir(self.direction);
-
-                    if scrollbar_pos == 0 {
-                        self.scroll_up(ctx);
-                    } else if scrollbar_pos == bounds.dir_end(self.direction) - 1 {
-                        self.scroll_down(ctx);
-                    } else if scrollbar_pos != self.caret_pos(bounds.size()) {
-                        let scroll_pos = ((scrollbar_pos - 1) as f32
-                            / (bar_bounds.size().dir(self.direction).saturating_sub(1)) as f32
-                            * self.scroll_max.get() as f32)
-                            .round() as u32;
-
-                        self.scroll_to(ctx, scroll_pos);
-                    }
-
-                    Err(Stop)
-                }
-
-                MouseEvent::ButtonDrag { button, start_pos }
-                    if button == MouseButton::Left && bar_bounds.contains(start_pos) =>
-                {
-                    let scroll_pos = (pos
-                        .dir(self.direction)
-                        .saturating_sub(*bar_bounds.point().dir(self.direction))
-                        as f32
-                        / (bar_bounds.size().dir(self.direction).saturating_sub(1)) as f32
-                        * self.scroll_max.get() as f32)
-                        .round() as u32;
-
-                    self.scroll_to(ctx, scroll_pos);
-
-                    Err(Stop)
-                }
-
-                _ => Ok(Continue),
-            },
-
-            Some(Event::Draw) => {
-                let caret = Voxel2::new(0x2EC).background(Some(color::GRAY));
-                let bg = Voxel2::new(0).background(Some(color::DARK_GRAY));
-
-                match self.direction {
-                    LayoutDirection::Horizontal => {
-                        let left_arrow = Voxel2::new(0x11).background(Some(color::GRAY));
-                        let right_arrow = Voxel2::new(0x10).background(Some(color::GRAY));
-
-                        let caret_x = bounds.left() + 1 + self.caret_pos(bounds.size());
-
-                        for y in bounds.top()..bounds.bottom() {
-                            ctx.batch.add(&left_arrow, [bounds.left(), y]);
-
-                            for x in (bounds.left() + 1)..caret_x {
-                                ctx.batch.add(&bg, [x, y]);
-                            }
-
-                            ctx.batch.add(&caret, [caret_x, y]);
-
-                            for x in (caret_x + 1)..(bounds.right() - 1) {
-                                ctx.batch.add(&bg, [x, y]);
-                            }
-
-                            ctx.batch.add(&right_arrow, [bounds.right() - 1, y]);
-                        }
-                    }
-
-                    LayoutDirection::Vertical => {
-                        let top_arrow = Voxel2::new(0x1E).background(Some(color::GRAY));
-                        let bottom_arrow = Voxel2::new(0x1F).background(Some(color::GRAY));
-
-                        let caret_y = bounds.top() + 1 + self.caret_pos(bounds.size());
-
-                        for x in bounds.left()..bounds.right() {
-                            ctx.batch.add(&top_arrow, [x, bounds.top()]);
+        }
 
-                            for y in (bounds.top() + 1)..caret_y {
-                                ctx.batch.add(&bg, [x, y]);
-                            }
+        self.reported_overflows = std::mem::take(&mut self.overflows);
 
-                            ctx.batch.add(&caret, [x, caret_y]);
+        for overlay in std::mem::take(&mut self.overlays) {
+            let width = (overlay.voxels.len() as u32).min(screen.w);
 
-                            for y in (caret_y + 1)..(bounds.bottom() - 1) {
-                                ctx.batch.add(&bg, [x, y]);
-                            }
+            if width == 0 || screen.h == 0 {
+                continue;
+            }
 
-                            ctx.batch.add(&bottom_arrow, [x, bounds.bottom() - 1]);
-                        }
-                    }
-                }
+            let x = (overlay.pos.x.max(0) as u32)
+                .min(screen.right() - width)
+                .max(screen.left());
+            let y = (overlay.pos.y.max(0) as u32)
+                .min(screen.bottom() - 1)
+                .max(screen.top());
 
-                Ok(Continue)
+            for (i, voxel) in overlay.voxels.iter().take(width as usize).enumerate() {
+                self.batch.add(voxel, mint::Point2::from([x + i as u32, y]));
             }
-
-            _ => Ok(Continue),
         }
     }
 }
 
-pub struct KataText {
-    pub voxels: Vec<Voxel2>,
+impl UiContext<KataFontBatch> {
+    pub fn mouse_pos(&self, ctx: &Context) -> mint::Point2<i32> {
+        let p = mouse::position(ctx);
+        mint::Point2::from([
+            (p.x / self.batch.tile_width()).floor() as i32,
+            (p.y / self.batch.tile_height()).floor() as i32,
+        ])
+    }
 }
 
-impl KataText {
-    pub fn from_voxels(voxels: Vec<Voxel2>) -> Self {
-        Self { voxels }
-    }
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    Mouse {
+        pos: mint::Point2<i32>,
+        e: MouseEvent,
+    },
 
-    pub fn from_colored_str(s: &str, color: Color) -> Self {
-        Self::from_voxels(
-            s.char_indices()
-                .map(|(i, c)| {
-                    Voxel2::new(if c.is_ascii() {
-                        u16::from(s.as_bytes()[i])
-                    } else {
-                        0x082D // Square
-                    })
-                    .foreground(color)
-                })
-                .collect(),
-        )
-    }
+    KeyDown {
+        keycode: KeyCode,
+        mods: KeyMods,
+    },
 
-    pub fn from_str(s: &str) -> Self {
-        Self::from_colored_str(s, color::WHITE)
-    }
-}
+    /// A character typed on the keyboard, after the keyboard layout has been applied.
+    Text {
+        ch: char,
+    },
 
-impl From<&str> for KataText {
-    fn from(s: &str) -> Self {
-        Self::from_str(s)
-    }
+    Draw,
 }
 
-impl Element for KataText {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("Text relayout");
+impl Event {
+    pub fn cull(self, bounds: SRect) -> Option<Self> {
+        let keep = match self {
+            Event::Mouse { pos, e } => match e {
+                MouseEvent::ButtonDrag { start_pos, .. } => bounds.contains(start_pos),
+                _ => bounds.contains(pos),
+            },
 
-        let n = self.voxels.len() as u32;
+            Event::KeyDown { .. } | Event::Text { .. } | Event::Draw => true,
+        };
 
-        if constraints.max.width == 0 {
-            Size::new(0, 0)
+        if keep {
+            Some(self)
         } else {
-            Size::new(
-                n.min(constraints.max.width).max(constraints.min.width),
-                n / constraints.max.width + if n % constraints.max.width > 0 { 1 } else { 0 },
-            )
-        }
-    }
-
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        match event {
-            Event::Draw if bounds.w > 0 => {
-                for (i, voxel) in self.voxels.iter().enumerate() {
-                    ctx.batch.add(
-                        voxel,
-                        [
-                            bounds.x + i as u32 % bounds.w,
-                            bounds.y + i as u32 / bounds.w,
-                        ],
-                    );
-                }
-                Ok(Continue)
-            }
-
-            _ => Ok(Continue),
+            None
         }
     }
 }
 
-pub struct StackedLayout {
-    elements: Vec<StackedElement>,
-    direction: LayoutDirection,
-    dividers: bool,
-}
-
-impl StackedLayout {
-    pub fn empty(direction: LayoutDirection) -> Self {
-        Self::from_vec(direction, Vec::new())
-    }
+#[derive(Clone, Copy, Debug)]
+pub enum MouseEvent {
+    ButtonDown {
+        button: MouseButton,
+    },
+    ButtonUp {
+        button: MouseButton,
+    },
+    ButtonDrag {
+        button: MouseButton,
+        start_pos: mint::Point2<i32>,
+    },
+    /// Sent after the `ButtonDown` of a click that quickly follows another one in about the
+    /// same place.
+    ButtonDoubleClick {
+        button: MouseButton,
+    },
 
-    pub fn from_vec(direction: LayoutDirection, elements: Vec<StackedElement>) -> Self {
-        Self {
-            elements,
-            direction,
-            dividers: false,
-        }
-    }
+    WheelUp,
+    WheelDown,
 
-    pub fn horizontal(elements: Vec<StackedElement>) -> Self {
-        Self::from_vec(LayoutDirection::Horizontal, elements)
-    }
+    Move,
+}
 
-    pub fn vertical(elements: Vec<StackedElement>) -> Self {
-        Self::from_vec(LayoutDirection::Vertical, elements)
-    }
+/// How close together in time and space two clicks have to be to make a double click.
+#[derive(Clone, Copy, Debug)]
+pub struct UiConfig {
+    pub double_click_time: Duration,
+    /// In cells, along either axis.
+    pub double_click_distance: u32,
+}
 
-    pub fn with_dividers(self) -> Self {
+impl Default for UiConfig {
+    fn default() -> Self {
         Self {
-            dividers: true,
-            ..self
+            double_click_time: Duration::from_millis(400),
+            double_click_distance: 1,
         }
     }
-
-    fn scan_sizes(&self) -> (u32, usize) {
-        let mut total_size = 0;
-        let mut free = 0;
-
-        for element in self.elements.iter() {
-            if let Some(size) = element.size {
-                total_size += size;
-            } else {
-                free += 1;
-            }
-        }
-
-        (total_size, free)
-    }
 }
 
-impl Element for StackedLayout {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("StackedLayout relayout");
-
-        let (total_size, free) = self.scan_sizes();
-        let n = self.elements.len() as u32;
-
-        let mut size_allowance = constraints.max[self.direction];
-        if self.dividers {
-            size_allowance = size_allowance.saturating_sub(n.saturating_sub(1));
-        }
-
-        let fullness = total_size.cmp(&size_allowance);
-
-        match (fullness, free) {
-            (Ordering::Equal, 0) => {
-                // We're all good
-                trace!("StackedLayout children-only relayout");
-
-                for element in self.elements.iter_mut() {
-                    element.element.layout(BoxConstraints::exact(
-                        constraints
-                            .max
-                            .with_dir(self.direction, element.size.unwrap()),
-                    ));
-                }
-            }
-
-            (Ordering::Greater, _) | (Ordering::Equal, _) => {
-                // Overfull - Full relayout
-                trace!("StackedLayout full relayout");
-                for (i, element) in self.elements.iter_mut().enumerate() {
-                    let s = spread(i as u32, size_allowance, n);
-
-                    element.size = Some(s);
-                    element.element.layout(BoxConstraints::exact(
-                        constraints.max.with_dir(self.direction, s),
-                    ));
-                }
-            }
+/// Remembers the last click of each button to tell which clicks complete a double click.
+#[derive(Debug, Default)]
+pub struct ClickTracker {
+    last_clicks: HashMap<MouseButton, (Instant, mint::Point2<i32>)>,
+}
 
-            (Ordering::Less, 0) => {
-                // Add to existing elements in equal parts
-                trace!("StackedLayout adding to existing elements");
-                for (i, element) in self.elements.iter_mut().enumerate() {
-                    let s =
-                        element.size.unwrap() + spread(i as u32, size_allowance - total_size, n);
-
-                    element.size = Some(s);
-                    element.element.layout(BoxConstraints::exact(
-                        constraints.max.with_dir(self.direction, s),
-                    ));
-                }
+impl ClickTracker {
+    /// Records a click and returns whether it completes a double click. The click after a
+    /// double click starts over instead of making another one.
+    pub fn click(
+        &mut self,
+        config: &UiConfig,
+        button: MouseButton,
+        pos: mint::Point2<i32>,
+        time: Instant,
+    ) -> bool {
+        let double_click = match self.last_clicks.get(&button) {
+            Some(&(last_time, last_pos)) => {
+                time.saturating_duration_since(last_time) <= config.double_click_time
+                    && distance(last_pos.x, pos.x) <= config.double_click_distance
+                    && distance(last_pos.y, pos.y) <= config.double_click_distance
             }
 
-            (Ordering::Less, _) => {
-                // Spread to free elements in equal parts
-                trace!("StackedLayout adding to new elements");
-                for (i, element) in self
-                    .elements
-                    .iter_mut()
-                    .filter(|e| e.size.is_none())
-                    .enumerate()
-                {
-                    let s = element.size.unwrap_or_else(|| {
-                        spread(i as u32, size_allowance - total_size, free as u32)
-                    });
-
-                    element.size = Some(s);
-
-                    element.element.layout(BoxConstraints::exact(
-                        constraints.max.with_dir(self.direction, s),
-                    ));
-                }
-            }
+            None => false,
         };
 
-        constraints.max
-    }
-
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        let mut offset = 0;
-
-        for (i, element) in self.elements.iter_mut().enumerate() {
-            if self.dividers && i > 0 {
-                let div_bounds = bounds.slice_dir(self.direction, offset..(offset + 1));
-
-                match event {
-                    Event::Draw => {
-                        let div_voxel = Voxel2::new(match self.direction {
-                            LayoutDirection::Horizontal => 0x266,
-                            LayoutDirection::Vertical => 0x265,
-                        });
-
-                        for p in div_bounds.points() {
-                            ctx.batch.add(&div_voxel, p);
-                        }
-                    }
-
-                    _ => {}
-                }
-
-                offset += 1;
-            }
-
-            let element_size = element.size.unwrap();
-            element.element.handle_event(
-                ctx,
-                event,
-                bounds.slice_dir(self.direction, offset..(offset + element_size)),
-            )?;
-            offset += element_size;
+        if double_click {
+            self.last_clicks.remove(&button);
+        } else {
+            self.last_clicks.insert(button, (time, pos));
         }
 
-        Ok(Continue)
+        double_click
     }
 }
 
-#[inline(always)]
-fn spread(i: u32, total: u32, n: u32) -> u32 {
-    total / n + if i < total % n { 1 } else { 0 }
+fn distance(a: i32, b: i32) -> u32 {
+    (i64::from(a) - i64::from(b)).abs() as u32
 }
 
-pub struct StackedElement {
-    element: Box<dyn Element>,
-    size: Option<u32>,
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    pub pos: mint::Point2<i32>,
+    pub voxels: Vec<Voxel2>,
 }
 
-impl StackedElement {
-    pub fn new(element: Box<dyn Element>) -> Self {
-        Self {
-            element,
-            size: None,
-        }
-    }
-}
+pub struct Continue;
+pub struct Stop;
+pub type EventResult = Result<Continue, Stop>;
 
-impl From<Box<dyn Element>> for StackedElement {
-    fn from(element: Box<dyn Element>) -> Self {
-        Self::new(element)
-    }
+pub trait Element {
+    /// Returns the size the element will take up, which should lie within `constraints`. An
+    /// element that can't fit may return more than `constraints.max`; its parent treats that as
+    /// an overflow and clamps it.
+    fn layout(&mut self, constraints: BoxConstraints) -> Size;
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult;
 }
 
-pub struct FlexLayout {
-    elements: Vec<FlexElement>,
-    direction: LayoutDirection,
-}
+pub trait ElementExt: Element + Sized {
+    /// Runs `handler` on each event before the element sees it.
+    fn with_events<F>(self, handler: F) -> WithEvents<Self, F>
+    where
+        F: FnMut(&mut Self, &mut UiContext, Event, SRect) -> EventResult;
 
-impl FlexLayout {
-    pub fn empty(direction: LayoutDirection) -> Self {
-        Self::from_vec(direction, Vec::new())
-    }
+    /// Runs `handler` on each event the element didn't stop.
+    fn with_events_after<F>(self, handler: F) -> WithEvents<Self, F>
+    where
+        F: FnMut(&mut Self, &mut UiContext, Event, SRect) -> EventResult;
 
-    pub fn from_vec(direction: LayoutDirection, elements: Vec<FlexElement>) -> Self {
-        Self {
-            elements,
-            direction,
-        }
+    /// Draws the element dimmed and ignores all of its other events while `enabled` is false.
+    fn with_enabled(self, enabled: Binding<bool>) -> Enabled<Self>;
+}
+
+impl<T: Element + Sized> ElementExt for T {
+    fn with_events<F>(self, handler: F) -> WithEvents<Self, F>
+    where
+        F: FnMut(&mut Self, &mut UiContext, Event, SRect) -> EventResult,
+    {
+        WithEvents::new(self, handler, HandlerOrder::Before)
     }
 
-    pub fn horizontal(elements: Vec<FlexElement>) -> Self {
-        Self::from_vec(LayoutDirection::Horizontal, elements)
+    fn with_events_after<F>(self, handler: F) -> WithEvents<Self, F>
+    where
+        F: FnMut(&mut Self, &mut UiContext, Event, SRect) -> EventResult,
+    {
+        WithEvents::new(self, handler, HandlerOrder::After)
     }
 
-    pub fn vertical(elements: Vec<FlexElement>) -> Self {
-        Self::from_vec(LayoutDirection::Vertical, elements)
+    fn with_enabled(self, enabled: Binding<bool>) -> Enabled<Self> {
+        Enabled::new(self, enabled)
     }
 }
 
-impl Element for FlexLayout {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        trace!("FlexLayout relayout");
+/// Lays out a child, clamping the size it reports to `constraints`. The second value is whether
+/// the child wanted more than `constraints.max`.
+fn layout_child(element: &mut dyn Element, constraints: BoxConstraints) -> (Size, bool) {
+    let size = element.layout(constraints);
+    let overflow = size.width > constraints.max.width || size.height > constraints.max.height;
 
-        let mut free = constraints.max;
-        let mut max_other = 0;
-
-        for fixed_element in self.elements.iter_mut().filter(|e| e.flex == 0) {
-            let element_size = fixed_element
-                .element
-                .layout(dbg!(BoxConstraints::new(Size::ZERO, free)));
-
-            free = dbg!(
-                free.shrink(Size::ZERO.with_dir(self.direction, element_size[self.direction]))
-            );
-            fixed_element.size = Some(element_size[self.direction]);
-            max_other = max_other.max(element_size[self.direction.other()]);
-        }
-
-        let total_flex: u32 = self.elements.iter().map(|e| e.flex).sum();
-
-        let mut start_flex = 0;
-        for flex_element in self.elements.iter_mut().filter(|e| e.flex > 0) {
-            let end_flex = start_flex + flex_element.flex;
-
-            let element_size = spread_flex(start_flex..end_flex, free[self.direction], total_flex);
-
-            flex_element.element.layout(BoxConstraints::exact(
-                free.with_dir(self.direction, element_size),
-            ));
-
-            flex_element.size = Some(element_size);
-            start_flex = end_flex;
-        }
-
-        if total_flex > 0 {
-            constraints.max
-        } else {
-            Size::default().with_dir(
-                self.direction,
-                constraints.max.dir(self.direction) - free.dir(self.direction),
-            )
-        }
-        .with_dir(self.direction.other(), max_other)
+    if overflow {
+        trace!("Overflow: {:?} doesn't fit {:?}", size, constraints);
     }
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        let mut offset = 0;
-
-        for element in self.elements.iter_mut() {
-            let element_size = element.size.unwrap();
-            element.element.handle_event(
-                ctx,
-                event,
-                bounds.slice_dir(self.direction, offset..(offset + element_size)),
-            )?;
-            offset += element_size;
-        }
-
-        Ok(Continue)
-    }
+    (constraints.constrain(size), overflow)
 }
 
-#[inline(always)]
-fn spread_flex(flex_range: Range<u32>, total: u32, total_flex: u32) -> u32 {
-    let flex = flex_range.end - flex_range.start;
-    let threshold = total % total_flex;
-
-    (total / total_flex * flex)
-        + if flex_range.start < threshold {
-            (threshold - flex_range.start).min(flex)
-        } else {
-            0
-        }
+/// Remembers how a child was last laid out so that its whole subtree can be skipped when it's
+/// given the same constraints again. The cache is invalidated when the child sets
+/// `UiContext::relayout` while handling an event.
+#[derive(Clone, Copy, Debug, Default)]
+struct LayoutCache {
+    constraints: Option<BoxConstraints>,
+    size: Size,
+    overflow: bool,
+    dirty: bool,
 }
 
-pub struct FlexElement {
-    pub element: Box<dyn Element>,
-    pub flex: u32,
-    size: Option<u32>,
-}
+impl LayoutCache {
+    fn layout(&mut self, element: &mut dyn Element, constraints: BoxConstraints) -> Size {
+        if self.dirty || self.constraints != Some(constraints) {
+            let (size, overflow) = layout_child(element, constraints);
 
-impl FlexElement {
-    pub fn flex(element: Box<dyn Element>, flex: u32) -> Self {
-        Self {
-            element,
-            flex,
-            size: None,
+            *self = Self {
+                constraints: Some(constraints),
+                size,
+                overflow,
+                dirty: false,
+            };
         }
-    }
 
-    pub fn fixed(element: Box<dyn Element>) -> Self {
-        Self {
-            element,
-            flex: 0,
-            size: None,
-        }
+        self.size
     }
-}
 
-pub struct VoxelDisplay<B> {
-    pub voxel: B,
-}
+    fn handle_event(
+        &mut self,
+        element: &mut dyn Element,
+        ctx: &mut UiContext,
+        event: Event,
+        bounds: SRect,
+    ) -> EventResult {
+        let relayout = std::mem::replace(&mut ctx.relayout, false);
+        let result = element.handle_event(ctx, event, bounds);
 
-impl<B: Bound<Voxel2>> VoxelDisplay<B> {
-    pub fn new(voxel: B) -> Self {
-        Self { voxel }
-    }
-}
+        if ctx.relayout {
+            self.mark_dirty();
+        }
 
-impl<B: Bound<Voxel2>> Element for VoxelDisplay<B> {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        Size::new(constraints.min.width.max(1), constraints.min.height.max(1))
-    }
+        ctx.relayout |= relayout;
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        match event {
-            Event::Draw => ctx.batch.add(&self.voxel.get(), bounds.point()),
-            _ => {}
+        if let (Event::Draw, true) = (event, self.overflow) {
+            ctx.report_overflow(bounds);
         }
 
-        Ok(Continue)
+        result
     }
-}
-
-pub struct Placeholder<F> {
-    voxel: Voxel2,
-    size_fn: F,
-}
 
-impl<F> Placeholder<F>
-where
-    F: Fn(BoxConstraints) -> Size,
-{
-    pub fn new(voxel: Voxel2, size_fn: F) -> Self {
-        Self { voxel, size_fn }
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
     }
 }
 
-impl<F> Element for Placeholder<F>
-where
-    F: Fn(BoxConstraints) -> Size,
-{
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        (self.size_fn)(constraints)
-    }
+#[derive(Debug, Clone, Copy)]
+pub enum LayoutDirection {
+    Horizontal,
+    Vertical,
+}
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        match event {
-            Event::Draw => {
-                for p in bounds.points() {
-                    ctx.batch.add(&self.voxel, p);
-                }
-            }
-            _ => {}
+impl LayoutDirection {
+    pub fn other(self) -> Self {
+        match self {
+            LayoutDirection::Horizontal => LayoutDirection::Vertical,
+            LayoutDirection::Vertical => LayoutDirection::Horizontal,
         }
-
-        Ok(Continue)
     }
 }
 
-pub struct Filling {
-    voxel: Voxel2,
-}
-
-impl Filling {
-    pub fn new(voxel: Voxel2) -> Self {
-        Self { voxel }
-    }
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    pub fn blank() -> Self {
-        Self::new(Voxel2::default())
+    /// Records the constraints it was last laid out with and fills them.
+    pub(super) struct ConstraintsProbe {
+        pub(super) constraints: Option<BoxConstraints>,
     }
-}
 
-impl Element for Filling {
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        constraints.max
+    impl ConstraintsProbe {
+        pub(super) fn new() -> Self {
+            Self { constraints: None }
+        }
     }
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        match event {
-            Event::Draw => {
-                for p in bounds.points() {
-                    ctx.batch.add(&self.voxel, p);
-                }
-            }
-            _ => {}
+    impl Element for ConstraintsProbe {
+        fn layout(&mut self, constraints: BoxConstraints) -> Size {
+            self.constraints = Some(constraints);
+            constraints.max
         }
 
-        Ok(Continue)
+        fn handle_event(
+            &mut self,
+            _ctx: &mut UiContext,
+            _event: Event,
+            _bounds: SRect,
+        ) -> EventResult {
+            Ok(Continue)
+        }
     }
-}
-
-pub struct WithEvents<T, F> {
-    element: T,
-    handler: F,
-}
-
-impl<T, F> Deref for WithEvents<T, F> {
-    type Target = T;
 
-    fn deref(&self) -> &Self::Target {
-        &self.element
-    }
-}
+    #[test]
+    fn test_click_tracker() {
+        let config = UiConfig::default();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let pos = |x, y| mint::Point2::from([x, y]);
 
-impl<T, F> DerefMut for WithEvents<T, F> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.element
-    }
-}
+        let mut clicks = ClickTracker::default();
+        assert!(!clicks.click(&config, MouseButton::Left, pos(5, 5), at(0)));
+        assert!(clicks.click(&config, MouseButton::Left, pos(6, 5), at(400)));
 
-impl<T, F> Element for WithEvents<T, F>
-where
-    T: Element,
-    F: FnMut(&mut T, &mut UiContext, Event, IRect) -> EventResult,
-{
-    fn layout(&mut self, constraints: BoxConstraints) -> Size {
-        self.element.layout(constraints)
-    }
+        // A third click starts over
+        assert!(!clicks.click(&config, MouseButton::Left, pos(6, 5), at(500)));
 
-    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: IRect) -> EventResult {
-        (self.handler)(&mut self.element, ctx, event, bounds)?;
-        self.element.handle_event(ctx, event, bounds)?;
+        // Two slow clicks are not a double click
+        let mut clicks = ClickTracker::default();
+        assert!(!clicks.click(&config, MouseButton::Left, pos(5, 5), at(0)));
+        assert!(!clicks.click(&config, MouseButton::Left, pos(5, 5), at(401)));
+        assert!(clicks.click(&config, MouseButton::Left, pos(5, 5), at(600)));
 
-        Ok(Continue)
+        // Neither are clicks too far apart or with different buttons
+        let mut clicks = ClickTracker::default();
+        assert!(!clicks.click(&config, MouseButton::Left, pos(5, 5), at(0)));
+        assert!(!clicks.click(&config, MouseButton::Left, pos(5, 7), at(100)));
+        assert!(!clicks.click(&config, MouseButton::Right, pos(5, 7), at(200)));
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
 
-    #[test]
-    fn test_spread() {
-        for n in 1..100 {
-            for total in 1..100 {
-                assert_eq!((0..n).map(|i| spread(i, total, n)).sum::<u32>(), total);
-            }
-        }
+    pub(super) fn sized_placeholder(size: Size) -> Placeholder<impl Fn(BoxConstraints) -> Size> {
+        Placeholder::new(Voxel2::new(0), move |c: BoxConstraints| c.constrain(size))
     }
 
-    #[test]
-    fn text_flex_spread() {
-        for total_flex in 1..10 {
-            for total in 1..100 {
-                for flex_1 in 0..total_flex {
-                    for flex_2 in flex_1..total_flex {
-                        let flex_range_1 = 0..flex_1;
-                        let flex_range_2 = flex_1..flex_2;
-                        let flex_range_3 = flex_2..total_flex;
-
-                        assert_eq!(
-                            spread_flex(flex_range_1, total, total_flex)
-                                + spread_flex(flex_range_2, total, total_flex)
-                                + spread_flex(flex_range_3, total, total_flex),
-                            total
-                        );
-                    }
-                }
-            }
-        }
+    pub(super) fn assert_render(element: &mut dyn Element, size: Size, expected: &[&str]) {
+        assert_eq!(TextGridBatch::render(element, size).lines(), expected);
     }
 }
@@ -0,0 +1,420 @@
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use flo_binding::{Binding, Bound, MutableBound};
+use ggez::input::{keyboard::KeyCode, mouse::MouseButton};
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::{color, voxel::Voxel2},
+    ui::{
+        decorators::centered_bounds, layout_child, BoxConstraints, Continue, Element, Event,
+        EventResult, KataText, MouseEvent, Size, Stop, UiContext,
+    },
+};
+
+/// Opens and closes the dialog of a `Modal`. It can be cloned into callbacks, e.g. a button
+/// that opens a dialog and the dialog's own buttons that close it again.
+#[derive(Clone, Default)]
+pub struct DialogHandle {
+    pending: Rc<RefCell<Option<Option<Box<dyn Element>>>>>,
+}
+
+impl DialogHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shows `dialog` in place of any dialog that's already open.
+    pub fn open<T: Element + 'static>(&self, dialog: T) {
+        *self.pending.borrow_mut() = Some(Some(Box::new(dialog)));
+    }
+
+    pub fn close(&self) {
+        *self.pending.borrow_mut() = Some(None);
+    }
+
+    fn take(&self) -> Option<Option<Box<dyn Element>>> {
+        self.pending.borrow_mut().take()
+    }
+}
+
+/// Shows a dialog in the middle of its child while one is open through its `DialogHandle`. The
+/// child is dimmed and gets no events until the dialog is closed, which Escape also does.
+pub struct Modal<T> {
+    inner: T,
+    handle: DialogHandle,
+    dialog: Option<Box<dyn Element>>,
+    dialog_size: Size,
+}
+
+impl<T: Element> Modal<T> {
+    pub fn new(inner: T, handle: DialogHandle) -> Self {
+        Self {
+            inner,
+            handle,
+            dialog: None,
+            dialog_size: Size::ZERO,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.dialog.is_some()
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Opens or closes the dialog as requested through the handle since the last event. A new
+    /// dialog is laid out straight away so it doesn't need to wait for a relayout.
+    fn apply_pending(&mut self, bounds: SRect) {
+        if let Some(dialog) = self.handle.take() {
+            self.dialog = dialog;
+            self.layout_dialog(bounds.size());
+        }
+    }
+
+    fn layout_dialog(&mut self, max: Size) {
+        if let Some(dialog) = &mut self.dialog {
+            let (size, _) = layout_child(&mut **dialog, BoxConstraints::new(Size::ZERO, max));
+            self.dialog_size = size;
+        }
+    }
+}
+
+impl<T: Element> Element for Modal<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Modal relayout");
+
+        let size = self.inner.layout(constraints);
+        self.layout_dialog(constraints.constrain(size));
+        size
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        self.apply_pending(bounds);
+
+        let result = match &mut self.dialog {
+            None => self.inner.handle_event(ctx, event, bounds),
+
+            Some(dialog) => {
+                if let Event::Draw = event {
+                    ctx.push_dimmed();
+                    let _ = self.inner.handle_event(ctx, event, bounds);
+                    ctx.pop_dimmed();
+                }
+
+                let dialog_bounds = centered_bounds(bounds, self.dialog_size);
+                let dialog_result = dialog.handle_event(ctx, event, dialog_bounds);
+
+                if let (
+                    Ok(Continue),
+                    Event::KeyDown {
+                        keycode: KeyCode::Escape,
+                        ..
+                    },
+                ) = (dialog_result, event)
+                {
+                    self.handle.close();
+                }
+
+                // Nothing gets past an open dialog
+                match event {
+                    Event::Draw => Ok(Continue),
+                    _ => Err(Stop),
+                }
+            }
+        };
+
+        self.apply_pending(bounds);
+        result
+    }
+}
+
+/// A header row of clickable labels above a body that shows one child at a time. Inactive
+/// children are still laid out, so switching tabs never needs a relayout, but they get no other
+/// events.
+pub struct Tabs<T> {
+    tabs: Vec<Tab<T>>,
+    active: Binding<usize>,
+}
+
+struct Tab<T> {
+    label: Vec<Voxel2>,
+    element: T,
+    overflow: bool,
+}
+
+impl<T: Element> Tabs<T> {
+    pub fn new(active: Binding<usize>) -> Self {
+        Self {
+            tabs: Vec::new(),
+            active,
+        }
+    }
+
+    pub fn with_tab(mut self, label: &str, element: T) -> Self {
+        self.tabs.push(Tab {
+            label: KataText::from_str(label).voxels,
+            element,
+            overflow: false,
+        });
+
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tabs.is_empty()
+    }
+
+    /// The index of the shown tab, or `None` if there are no tabs.
+    pub fn active(&self) -> Option<usize> {
+        if self.tabs.is_empty() {
+            None
+        } else {
+            Some(self.active.get().min(self.tabs.len() - 1))
+        }
+    }
+
+    /// Shows the tab at `index`.
+    pub fn select(&mut self, index: usize) {
+        self.active.set(index);
+    }
+
+    pub fn active_element(&self) -> Option<&T> {
+        self.active().map(|i| &self.tabs[i].element)
+    }
+
+    pub fn active_element_mut(&mut self) -> Option<&mut T> {
+        match self.active() {
+            Some(i) => Some(&mut self.tabs[i].element),
+            None => None,
+        }
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &T> {
+        self.tabs.iter().map(|tab| &tab.element)
+    }
+
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.tabs.iter_mut().map(|tab| &mut tab.element)
+    }
+
+    /// The columns of the header taken up by each label, which is padded by a space on either
+    /// side.
+    fn label_ranges(&self) -> impl Iterator<Item = Range<u32>> + '_ {
+        self.tabs.iter().scan(0, |start, tab| {
+            let range = *start..(*start + tab.label.len() as u32 + 2);
+            *start = range.end;
+            Some(range)
+        })
+    }
+
+    /// The tab whose label is `x` columns into the header.
+    fn tab_at(&self, x: u32) -> Option<usize> {
+        self.label_ranges().position(|range| range.contains(&x))
+    }
+
+    fn header_width(&self) -> u32 {
+        self.label_ranges()
+            .last()
+            .map(|range| range.end)
+            .unwrap_or(0)
+    }
+
+    fn body_bounds(bounds: SRect) -> SRect {
+        SRect::new(
+            bounds.x,
+            bounds.y + bounds.h.min(1) as i32,
+            bounds.w,
+            bounds.h.saturating_sub(1),
+        )
+    }
+
+    fn draw_header(&self, ctx: &mut UiContext, bounds: SRect) {
+        if bounds.h == 0 {
+            return;
+        }
+
+        let active = self.active();
+        let line = Voxel2::new(0x265);
+
+        for x in bounds.x..bounds.right() {
+            ctx.draw(&line, [x, bounds.y]);
+        }
+
+        for (i, (tab, range)) in self.tabs.iter().zip(self.label_ranges()).enumerate() {
+            let style = |voxel: Voxel2| {
+                if Some(i) == active {
+                    voxel.inverted()
+                } else {
+                    voxel.background(Some(color::GRAY))
+                }
+            };
+
+            let label = std::iter::once(Voxel2::new(0))
+                .chain(tab.label.iter().cloned())
+                .chain(std::iter::once(Voxel2::new(0)));
+
+            for (x, voxel) in range.zip(label) {
+                if x >= bounds.w {
+                    return;
+                }
+
+                ctx.draw(&style(voxel), [bounds.x + x as i32, bounds.y]);
+            }
+        }
+    }
+}
+
+impl<T: Element> Element for Tabs<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Tabs relayout");
+
+        let body_constraints = constraints.shrink(Size::new(0, 1));
+        let mut size = Size::new(self.header_width(), 0);
+
+        for tab in self.tabs.iter_mut() {
+            let (element_size, overflow) = layout_child(&mut tab.element, body_constraints);
+            tab.overflow = overflow;
+            size = size.max(element_size);
+        }
+
+        constraints.constrain(Size::new(size.width, size.height.saturating_add(1)))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event.cull(bounds) {
+            Some(Event::Mouse {
+                pos,
+                e:
+                    MouseEvent::ButtonDown {
+                        button: MouseButton::Left,
+                    },
+            }) if pos.y == bounds.y => {
+                if let Some(i) = self.tab_at((pos.x - bounds.x) as u32) {
+                    self.active.set(i);
+                }
+
+                return Err(Stop);
+            }
+
+            Some(Event::Draw) => self.draw_header(ctx, bounds),
+
+            _ => {}
+        }
+
+        let body_bounds = Self::body_bounds(bounds);
+
+        if let Some(i) = self.active() {
+            let tab = &mut self.tabs[i];
+            tab.element.handle_event(ctx, event, body_bounds)?;
+
+            if let (Event::Draw, true) = (event, tab.overflow) {
+                ctx.report_overflow(body_bounds);
+            }
+        }
+
+        Ok(Continue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{test::ConstraintsProbe, Button, TextGridBatch};
+    use flo_binding::bind;
+    use ggez::{input::keyboard::KeyMods, mint};
+
+    #[test]
+    fn test_modal() {
+        let clicks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let handle = DialogHandle::new();
+        let mut modal = Modal::new(
+            Button::new("Go", {
+                let clicks = std::rc::Rc::clone(&clicks);
+                move || clicks.set(clicks.get() + 1)
+            }),
+            handle.clone(),
+        );
+        let bounds = SRect::new(0, 0, 6, 3);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        modal.layout(BoxConstraints::exact(bounds.size()));
+
+        let click = Event::Mouse {
+            pos: mint::Point2::from([0, 0]),
+            e: MouseEvent::ButtonDown {
+                button: MouseButton::Left,
+            },
+        };
+
+        let _ = modal.handle_event(&mut ctx, click, bounds);
+        assert_eq!(clicks.get(), 1);
+
+        handle.open(KataText::from_str("Hi"));
+        assert!(modal.handle_event(&mut ctx, click, bounds).is_err());
+        assert!(modal.is_open());
+        assert_eq!(clicks.get(), 1);
+
+        let escape = Event::KeyDown {
+            keycode: KeyCode::Escape,
+            mods: KeyMods::NONE,
+        };
+        let _ = modal.handle_event(&mut ctx, escape, bounds);
+        assert!(!modal.is_open());
+
+        let _ = modal.handle_event(&mut ctx, click, bounds);
+        assert_eq!(clicks.get(), 2);
+    }
+
+    #[test]
+    fn test_tabs() {
+        let active = bind(5);
+        let mut tabs = Tabs::new(active.clone())
+            .with_tab("ab", ConstraintsProbe::new())
+            .with_tab("cde", ConstraintsProbe::new());
+
+        assert_eq!(tabs.active(), Some(1));
+        assert_eq!(tabs.header_width(), 9);
+        assert_eq!(
+            (0..10).map(|x| tabs.tab_at(x)).collect::<Vec<_>>(),
+            vec![
+                Some(0),
+                Some(0),
+                Some(0),
+                Some(0),
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(1),
+                Some(1),
+                None
+            ]
+        );
+
+        // Every tab gets laid out, whether it's shown or not
+        let size = tabs.layout(BoxConstraints::exact(Size::new(10, 5)));
+        assert_eq!(size, Size::new(10, 5));
+        assert!(tabs
+            .elements()
+            .all(|e| e.constraints == Some(BoxConstraints::exact(Size::new(10, 4)))));
+
+        active.set(0);
+        assert_eq!(tabs.active(), Some(0));
+
+        assert_eq!(Tabs::<ConstraintsProbe>::new(bind(0)).active(), None);
+        assert_eq!(
+            Tabs::<ConstraintsProbe>::body_bounds(SRect::new(1, 2, 3, 4)),
+            SRect::new(1, 3, 3, 3)
+        );
+    }
+}
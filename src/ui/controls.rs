@@ -0,0 +1,1053 @@
+use std::ops::Range;
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::input::{keyboard::KeyCode, mouse::MouseButton};
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::{color, voxel::Voxel2},
+    ui::{
+        decorators::centered_bounds, BoxConstraints, Continue, Element, Event, EventResult,
+        KataText, LayoutDirection, MouseEvent, Size, Stop, UiContext,
+    },
+};
+
+/// A one row tall bar that fills up as `progress` (done, total) advances. The bar is redrawn from
+/// the binding on every Draw, so updating the progress never needs a relayout.
+pub struct ProgressBar {
+    pub progress: Binding<(u32, u32)>,
+    fill: Voxel2,
+    empty: Voxel2,
+    show_percentage: bool,
+}
+
+impl ProgressBar {
+    pub fn new(progress: Binding<(u32, u32)>) -> Self {
+        Self {
+            progress,
+            fill: Voxel2::new(0).background(Some(color::WHITE)),
+            empty: Voxel2::new(0).background(Some(color::DARK_GRAY)),
+            show_percentage: false,
+        }
+    }
+
+    pub fn with_fill(self, fill: Voxel2) -> Self {
+        Self { fill, ..self }
+    }
+
+    pub fn with_percentage(self) -> Self {
+        Self {
+            show_percentage: true,
+            ..self
+        }
+    }
+
+    /// How many of `width` cells are filled.
+    fn filled(&self, width: u32) -> u32 {
+        let (done, total) = self.progress.get();
+
+        if total == 0 {
+            return 0;
+        }
+
+        (u64::from(done.min(total)) * u64::from(width) / u64::from(total)) as u32
+    }
+
+    fn percentage(&self) -> u32 {
+        self.filled(100)
+    }
+}
+
+impl Element for ProgressBar {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("ProgressBar relayout");
+
+        constraints.constrain(Size::new(constraints.max.width, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            let filled = self.filled(bounds.w);
+
+            for p in bounds.points() {
+                let voxel = if ((p.x - bounds.x) as u32) < filled {
+                    &self.fill
+                } else {
+                    &self.empty
+                };
+
+                ctx.draw(voxel, p);
+            }
+
+            if self.show_percentage {
+                let text = KataText::from_str(&format!("{}%", self.percentage()));
+                let text_bounds = centered_bounds(
+                    SRect::new(bounds.x, bounds.y, bounds.w, bounds.h.min(1)),
+                    Size::new(text.voxels.len() as u32, 1),
+                );
+
+                for (voxel, p) in text.voxels.iter().zip(text_bounds.points()) {
+                    // Keep the text readable on top of the filled part
+                    let voxel = if ((p.x - bounds.x) as u32) < filled {
+                        voxel.clone().inverted()
+                    } else {
+                        voxel.clone()
+                    };
+
+                    ctx.draw(&voxel, p);
+                }
+            }
+        }
+
+        Ok(Continue)
+    }
+}
+
+/// How many Draw events a `Stepper` arrow has to be held down for before it starts repeating,
+/// and how many it waits between repeats after that.
+const STEPPER_REPEAT_DELAY_DRAWS: u32 = 20;
+const STEPPER_REPEAT_INTERVAL_DRAWS: u32 = 3;
+
+/// A compact field for small integers, drawn as `◄ value ►`. The arrows step the value when
+/// clicked, and repeat while held down. The wheel steps it too while hovered.
+pub struct Stepper {
+    pub value: Binding<i32>,
+    min: i32,
+    max: i32,
+    step: i32,
+    held: Option<(i32, u32)>,
+    on_change: Option<Box<dyn FnMut(i32)>>,
+}
+
+impl Stepper {
+    pub fn new(value: Binding<i32>, min: i32, max: i32) -> Self {
+        assert!(min <= max);
+
+        Self {
+            value,
+            min,
+            max,
+            step: 1,
+            held: None,
+            on_change: None,
+        }
+    }
+
+    pub fn with_step(self, step: i32) -> Self {
+        Self { step, ..self }
+    }
+
+    /// Calls `on_change` with the new value whenever it's changed from the stepper itself.
+    pub fn with_on_change<F>(self, on_change: F) -> Self
+    where
+        F: FnMut(i32) + 'static,
+    {
+        Self {
+            on_change: Some(Box::new(on_change)),
+            ..self
+        }
+    }
+
+    /// Moves the value by `steps` steps, staying within the bounds.
+    pub fn step_by(&mut self, steps: i32) {
+        let old_value = self.value.get();
+        let new_value = old_value
+            .saturating_add(steps.saturating_mul(self.step))
+            .max(self.min)
+            .min(self.max);
+
+        if new_value != old_value {
+            self.value.set(new_value);
+
+            if let Some(on_change) = &mut self.on_change {
+                on_change(new_value);
+            }
+        }
+    }
+
+    /// Wide enough for any value between `min` and `max`.
+    fn value_width(&self) -> u32 {
+        self.min.to_string().len().max(self.max.to_string().len()) as u32
+    }
+
+    /// Which way the arrow `x` columns in steps the value, if there is one.
+    fn arrow_at(&self, x: u32) -> Option<i32> {
+        if x == 0 {
+            Some(-1)
+        } else if x == self.value_width() + 3 {
+            Some(1)
+        } else {
+            None
+        }
+    }
+}
+
+impl Element for Stepper {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Stepper relayout");
+
+        constraints.constrain(Size::new(self.value_width() + 4, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        // Let go of the arrow wherever the button is released
+        if let Event::Mouse {
+            e: MouseEvent::ButtonUp {
+                button: MouseButton::Left,
+            },
+            ..
+        } = event
+        {
+            self.held = None;
+        }
+
+        match event.cull(bounds) {
+            Some(Event::Mouse { pos, e }) => match e {
+                MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                } => {
+                    if let Some(direction) = self.arrow_at((pos.x - bounds.x) as u32) {
+                        self.step_by(direction);
+                        self.held = Some((direction, 0));
+                    }
+
+                    Err(Stop)
+                }
+
+                MouseEvent::WheelUp => {
+                    self.step_by(1);
+                    Err(Stop)
+                }
+
+                MouseEvent::WheelDown => {
+                    self.step_by(-1);
+                    Err(Stop)
+                }
+
+                _ => Ok(Continue),
+            },
+
+            Some(Event::Draw) => {
+                if let Some((direction, draws)) = &mut self.held {
+                    *draws += 1;
+
+                    let direction = *direction;
+                    let repeating = *draws >= STEPPER_REPEAT_DELAY_DRAWS
+                        && (*draws - STEPPER_REPEAT_DELAY_DRAWS) % STEPPER_REPEAT_INTERVAL_DRAWS
+                            == 0;
+
+                    if repeating {
+                        self.step_by(direction);
+                    }
+                }
+
+                let width = self.value_width() as usize;
+                let text = format!("{:>width$}", self.value.get(), width = width);
+                let voxels = std::iter::once(Voxel2::new(0x11))
+                    .chain(std::iter::once(Voxel2::new(0)))
+                    .chain(KataText::from_str(&text).voxels)
+                    .chain(std::iter::once(Voxel2::new(0)))
+                    .chain(std::iter::once(Voxel2::new(0x10)));
+
+                for (i, voxel) in voxels.take(bounds.w as usize).enumerate() {
+                    ctx.draw(&voxel, [bounds.x + i as i32, bounds.y]);
+                }
+
+                Ok(Continue)
+            }
+
+            _ => Ok(Continue),
+        }
+    }
+}
+
+/// A horizontal track with a handle that can be clicked or dragged along it to pick a value
+/// between `min` and `max`.
+pub struct Slider {
+    pub value: Binding<i32>,
+    min: i32,
+    max: i32,
+    enabled: Binding<bool>,
+    on_change: Option<Box<dyn FnMut(i32)>>,
+}
+
+impl Slider {
+    pub fn new(value: Binding<i32>, min: i32, max: i32) -> Self {
+        assert!(min <= max);
+
+        Self {
+            value,
+            min,
+            max,
+            enabled: bind(true),
+            on_change: None,
+        }
+    }
+
+    /// Draws the slider dimmed and leaves the value alone while `enabled` is false.
+    pub fn with_enabled(self, enabled: Binding<bool>) -> Self {
+        Self { enabled, ..self }
+    }
+
+    /// Calls `on_change` with the new value whenever it's changed from the slider itself.
+    pub fn with_on_change<F>(self, on_change: F) -> Self
+    where
+        F: FnMut(i32) + 'static,
+    {
+        Self {
+            on_change: Some(Box::new(on_change)),
+            ..self
+        }
+    }
+
+    fn set_value(&mut self, value: i32) {
+        let value = value.max(self.min).min(self.max);
+
+        if value != self.value.get() {
+            self.value.set(value);
+
+            if let Some(on_change) = &mut self.on_change {
+                on_change(value);
+            }
+        }
+    }
+
+    /// The value for the handle being `x` columns along a track that's `width` wide, rounded to
+    /// the nearest one.
+    fn value_at(&self, x: u32, width: u32) -> i32 {
+        if width <= 1 {
+            return self.min;
+        }
+
+        let range = i64::from(self.max) - i64::from(self.min);
+        let last = i64::from(width - 1);
+        let x = i64::from(x.min(width - 1));
+
+        (i64::from(self.min) + (x * range + last / 2) / last) as i32
+    }
+
+    /// Where the handle goes for the current value.
+    fn handle_x(&self, width: u32) -> u32 {
+        let range = i64::from(self.max) - i64::from(self.min);
+        if range == 0 || width == 0 {
+            return 0;
+        }
+
+        let offset = i64::from(self.value.get().max(self.min).min(self.max)) - i64::from(self.min);
+        ((offset * i64::from(width - 1) + range / 2) / range) as u32
+    }
+}
+
+impl Element for Slider {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Slider relayout");
+
+        let width = if constraints.is_bounded(LayoutDirection::Horizontal) {
+            constraints.max.width
+        } else {
+            16
+        };
+
+        constraints.constrain(Size::new(width, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event.cull(bounds) {
+            Some(Event::Mouse { .. }) if !self.enabled.get() => Ok(Continue),
+
+            Some(Event::Mouse { pos, e }) => match e {
+                MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                }
+                | MouseEvent::ButtonDrag {
+                    button: MouseButton::Left,
+                    ..
+                } => {
+                    let value = self.value_at((pos.x - bounds.x).max(0) as u32, bounds.w);
+                    self.set_value(value);
+                    Err(Stop)
+                }
+
+                MouseEvent::WheelUp => {
+                    self.set_value(self.value.get().saturating_add(1));
+                    Err(Stop)
+                }
+
+                MouseEvent::WheelDown => {
+                    self.set_value(self.value.get().saturating_sub(1));
+                    Err(Stop)
+                }
+
+                _ => Ok(Continue),
+            },
+
+            Some(Event::Draw) => {
+                let handle_x = self.handle_x(bounds.w);
+                let enabled = self.enabled.get();
+
+                if !enabled {
+                    ctx.push_dimmed();
+                }
+
+                for x in 0..bounds.w {
+                    let voxel = if x == handle_x {
+                        Voxel2::new(0x2EC)
+                    } else {
+                        Voxel2::new(0x265).foreground(color::GRAY)
+                    };
+
+                    ctx.draw(&voxel, [bounds.x + x as i32, bounds.y]);
+                }
+
+                if !enabled {
+                    ctx.pop_dimmed();
+                }
+
+                Ok(Continue)
+            }
+
+            _ => Ok(Continue),
+        }
+    }
+}
+
+/// A label that calls `on_click` when it's clicked.
+pub struct Button {
+    label: Vec<Voxel2>,
+    enabled: Binding<bool>,
+    on_click: Box<dyn FnMut()>,
+}
+
+impl Button {
+    pub fn new<F>(label: &str, on_click: F) -> Self
+    where
+        F: FnMut() + 'static,
+    {
+        Self {
+            label: KataText::from_str(label)
+                .voxels
+                .into_iter()
+                .map(|v| v.background(Some(color::GRAY)))
+                .collect(),
+            enabled: bind(true),
+            on_click: Box::new(on_click),
+        }
+    }
+
+    /// Draws the button dimmed and ignores clicks while `enabled` is false.
+    pub fn with_enabled(self, enabled: Binding<bool>) -> Self {
+        Self { enabled, ..self }
+    }
+}
+
+impl Element for Button {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Button relayout");
+
+        constraints.constrain(Size::new(self.label.len() as u32, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event.cull(bounds) {
+            Some(Event::Mouse {
+                e:
+                    MouseEvent::ButtonDown {
+                        button: MouseButton::Left,
+                    },
+                ..
+            }) if self.enabled.get() => {
+                (self.on_click)();
+                Err(Stop)
+            }
+
+            Some(Event::Draw) => {
+                let enabled = self.enabled.get();
+
+                if !enabled {
+                    ctx.push_dimmed();
+                }
+
+                for (i, voxel) in self.label.iter().take(bounds.w as usize).enumerate() {
+                    ctx.draw(voxel, [bounds.x + i as i32, bounds.y]);
+                }
+
+                if !enabled {
+                    ctx.pop_dimmed();
+                }
+
+                Ok(Continue)
+            }
+
+            _ => Ok(Continue),
+        }
+    }
+}
+
+/// A row of labels, one of which is picked at a time by clicking it. The picked one is drawn
+/// inverted, like the active tab of `Tabs`.
+pub struct Picker {
+    labels: Vec<Vec<Voxel2>>,
+    pub picked: Binding<usize>,
+}
+
+impl Picker {
+    pub fn new(labels: &[String], picked: Binding<usize>) -> Self {
+        Self {
+            labels: labels
+                .iter()
+                .map(|label| KataText::from_str(label).voxels)
+                .collect(),
+            picked,
+        }
+    }
+
+    /// The columns taken up by each label, which is padded by a space on either side.
+    fn label_ranges(&self) -> impl Iterator<Item = Range<u32>> + '_ {
+        self.labels.iter().scan(0, |start, label| {
+            let range = *start..(*start + label.len() as u32 + 2);
+            *start = range.end;
+            Some(range)
+        })
+    }
+
+    /// The label `x` columns in.
+    fn label_at(&self, x: u32) -> Option<usize> {
+        self.label_ranges().position(|range| range.contains(&x))
+    }
+}
+
+impl Element for Picker {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Picker relayout");
+
+        let width = self.label_ranges().last().map_or(0, |range| range.end);
+        constraints.constrain(Size::new(width, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event.cull(bounds) {
+            Some(Event::Mouse {
+                pos,
+                e:
+                    MouseEvent::ButtonDown {
+                        button: MouseButton::Left,
+                    },
+            }) => {
+                if let Some(i) = self.label_at((pos.x - bounds.x) as u32) {
+                    self.picked.set(i);
+                }
+
+                Err(Stop)
+            }
+
+            Some(Event::Draw) => {
+                let picked = self.picked.get();
+
+                for (i, (label, range)) in self.labels.iter().zip(self.label_ranges()).enumerate() {
+                    let style = |voxel: Voxel2| {
+                        if i == picked {
+                            voxel.inverted()
+                        } else {
+                            voxel.background(Some(color::GRAY))
+                        }
+                    };
+
+                    let padded = std::iter::once(Voxel2::new(0))
+                        .chain(label.iter().cloned())
+                        .chain(std::iter::once(Voxel2::new(0)));
+
+                    for (x, voxel) in range.zip(padded) {
+                        if x >= bounds.w {
+                            return Ok(Continue);
+                        }
+
+                        ctx.draw(&style(voxel), [bounds.x + x as i32, bounds.y]);
+                    }
+                }
+
+                Ok(Continue)
+            }
+
+            _ => Ok(Continue),
+        }
+    }
+}
+
+/// A single line of editable text. It takes typed characters while it has focus, which it gets
+/// by being clicked.
+pub struct TextInput {
+    pub text: Binding<String>,
+    width: u32,
+    cursor: usize,
+    scroll: usize,
+    focused: bool,
+    on_submit: Option<Box<dyn FnMut(String)>>,
+}
+
+impl TextInput {
+    pub fn new(text: Binding<String>) -> Self {
+        let cursor = text.get().len();
+
+        Self {
+            text,
+            width: 20,
+            cursor,
+            scroll: 0,
+            focused: false,
+            on_submit: None,
+        }
+    }
+
+    /// How many columns to ask for. Longer text scrolls to keep the cursor in view.
+    pub fn with_width(self, width: u32) -> Self {
+        Self { width, ..self }
+    }
+
+    /// Starts out with focus, e.g. as the only input of a dialog.
+    pub fn with_focus(self) -> Self {
+        Self {
+            focused: true,
+            ..self
+        }
+    }
+
+    /// Calls `on_submit` with the text when Enter is pressed.
+    pub fn with_on_submit<F>(self, on_submit: F) -> Self
+    where
+        F: FnMut(String) + 'static,
+    {
+        Self {
+            on_submit: Some(Box::new(on_submit)),
+            ..self
+        }
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Applies a key press, returning whether the input used it.
+    fn edit(&mut self, keycode: KeyCode) -> bool {
+        let mut text = self.text.get();
+        self.cursor = self.cursor.min(text.len());
+
+        match keycode {
+            KeyCode::Back if self.cursor > 0 => {
+                self.cursor -= 1;
+                text.remove(self.cursor);
+            }
+
+            KeyCode::Delete if self.cursor < text.len() => {
+                text.remove(self.cursor);
+            }
+
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(text.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = text.len(),
+
+            KeyCode::Return | KeyCode::NumpadEnter => {
+                if let Some(on_submit) = &mut self.on_submit {
+                    on_submit(text);
+                }
+
+                return true;
+            }
+
+            KeyCode::Back | KeyCode::Delete => {}
+            _ => return false,
+        }
+
+        self.text.set(text);
+        true
+    }
+
+    /// Only printable ASCII is accepted, since that's what the font has glyphs for.
+    fn insert(&mut self, c: char) {
+        if c.is_ascii_graphic() || c == ' ' {
+            let mut text = self.text.get();
+            self.cursor = self.cursor.min(text.len());
+            text.insert(self.cursor, c);
+            self.cursor += 1;
+            self.text.set(text);
+        }
+    }
+}
+
+impl Element for TextInput {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("TextInput relayout");
+
+        constraints.constrain(Size::new(self.width, 1))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDown { .. },
+            } => {
+                self.focused = bounds.contains(pos);
+
+                if self.focused {
+                    let x = (pos.x - bounds.x) as usize + self.scroll;
+                    self.cursor = x.min(self.text.get().len());
+                    return Err(Stop);
+                }
+            }
+
+            Event::KeyDown { keycode, .. } if self.focused => {
+                if self.edit(keycode) {
+                    return Err(Stop);
+                }
+            }
+
+            Event::Text { ch } if self.focused => {
+                self.insert(ch);
+                return Err(Stop);
+            }
+
+            Event::Draw => {
+                ctx.typing |= self.focused;
+
+                let text = self.text.get();
+                let width = bounds.w as usize;
+                self.cursor = self.cursor.min(text.len());
+
+                // Keep the cursor in view
+                if self.cursor < self.scroll {
+                    self.scroll = self.cursor;
+                } else if width > 0 && self.cursor >= self.scroll + width {
+                    self.scroll = self.cursor + 1 - width;
+                }
+
+                let voxels = KataText::from_str(&text).voxels;
+                let background = Voxel2::new(0).background(Some(color::LIGHT_GRAY));
+
+                for i in 0..width {
+                    let index = self.scroll + i;
+                    let voxel = voxels
+                        .get(index)
+                        .cloned()
+                        .unwrap_or_else(|| Voxel2::new(0))
+                        .background(background.background);
+
+                    let voxel = if self.focused && index == self.cursor {
+                        voxel.inverted()
+                    } else {
+                        voxel
+                    };
+
+                    ctx.draw(&voxel, [bounds.x + i as i32, bounds.y]);
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub struct VoxelDisplay<B> {
+    pub voxel: B,
+    magnification: u32,
+}
+
+impl<B: Bound<Voxel2>> VoxelDisplay<B> {
+    pub fn new(voxel: B) -> Self {
+        Self {
+            voxel,
+            magnification: 1,
+        }
+    }
+
+    /// Draws the voxel `magnification` times as big, taking up that many cells either way.
+    pub fn with_magnification(self, magnification: u32) -> Self {
+        Self {
+            magnification: magnification.max(1),
+            ..self
+        }
+    }
+}
+
+impl<B: Bound<Voxel2>> Element for VoxelDisplay<B> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        Size::new(
+            constraints.min.width.max(self.magnification),
+            constraints.min.height.max(self.magnification),
+        )
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Draw => {
+                ctx.draw_magnified(&self.voxel.get(), bounds.point(), self.magnification)
+            }
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{test::assert_render, ElementExt, TextGridBatch, DIMMED_FACTOR};
+    use ggez::{input::keyboard::KeyMods, mint};
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_stepper() {
+        let value = bind(8);
+        let mut stepper = Stepper::new(value.clone(), -5, 10).with_step(2);
+
+        assert_render(&mut stepper, Size::new(6, 1), &["<  8 >"]);
+
+        stepper.step_by(1);
+        assert_eq!(value.get(), 10);
+        stepper.step_by(-100);
+        assert_eq!(value.get(), -5);
+        assert_render(&mut stepper, Size::new(6, 1), &["< -5 >"]);
+
+        assert_eq!(stepper.arrow_at(0), Some(-1));
+        assert_eq!(stepper.arrow_at(2), None);
+        assert_eq!(stepper.arrow_at(5), Some(1));
+    }
+
+    #[test]
+    fn test_picker() {
+        let picked = bind(0);
+        let mut picker = Picker::new(&[String::from("ab"), String::from("cde")], picked.clone());
+
+        assert_render(&mut picker, Size::new(9, 1), &[" ab  cde "]);
+        assert_render(&mut picker, Size::new(6, 1), &[" ab  c"]);
+        assert_eq!(picker.label_at(3), Some(0));
+        assert_eq!(picker.label_at(4), Some(1));
+        assert_eq!(picker.label_at(9), None);
+
+        let bounds = SRect::new(1, 0, 9, 1);
+        let mut ctx = UiContext::new(TextGridBatch::new(Size::new(10, 1)));
+        let mut click = |picker: &mut Picker, x| {
+            picker.handle_event(
+                &mut ctx,
+                Event::Mouse {
+                    pos: mint::Point2::from([x, 0]),
+                    e: MouseEvent::ButtonDown {
+                        button: MouseButton::Left,
+                    },
+                },
+                bounds,
+            )
+        };
+
+        assert!(click(&mut picker, 6).is_err());
+        assert_eq!(picked.get(), 1);
+        // Clicks outside of it go on to whatever's under it
+        assert!(click(&mut picker, 0).is_ok());
+        assert_eq!(picked.get(), 1);
+    }
+
+    #[test]
+    fn test_slider() {
+        let value = bind(0);
+        let mut slider = Slider::new(value.clone(), 0, 255);
+
+        assert_eq!(slider.value_at(0, 6), 0);
+        assert_eq!(slider.value_at(5, 6), 255);
+        assert_eq!(slider.value_at(100, 6), 255);
+        assert_eq!(slider.value_at(2, 6), 102);
+        assert_render(&mut slider, Size::new(6, 1), &["#-----"]);
+
+        let bounds = SRect::new(2, 0, 6, 1);
+        let mut ctx = UiContext::new(TextGridBatch::new(Size::new(8, 1)));
+        let _ = slider.handle_event(
+            &mut ctx,
+            Event::Mouse {
+                pos: mint::Point2::from([7, 0]),
+                e: MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                },
+            },
+            bounds,
+        );
+        assert_eq!(value.get(), 255);
+        assert_render(&mut slider, Size::new(6, 1), &["-----#"]);
+
+        value.set(128);
+        assert_render(&mut slider, Size::new(6, 1), &["---#--"]);
+
+        // Disabled, it's dimmed and can't be dragged
+        let enabled = bind(false);
+        let mut slider = slider.with_enabled(enabled.clone());
+        let drag = Event::Mouse {
+            pos: mint::Point2::from([2, 0]),
+            e: MouseEvent::ButtonDrag {
+                button: MouseButton::Left,
+                start_pos: mint::Point2::from([7, 0]),
+            },
+        };
+        assert!(slider.handle_event(&mut ctx, drag, bounds).is_ok());
+        assert_eq!(value.get(), 128);
+
+        let grid = TextGridBatch::render(&mut slider, Size::new(6, 1));
+        assert_eq!(
+            grid.cell(3, 0).foreground,
+            color::WHITE.dimmed(DIMMED_FACTOR)
+        );
+
+        enabled.set(true);
+        assert!(slider.handle_event(&mut ctx, drag, bounds).is_err());
+        assert_eq!(value.get(), 0);
+    }
+
+    #[test]
+    fn test_button_enabled() {
+        let enabled = bind(false);
+        let clicks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut button = Button::new("ok", {
+            let clicks = clicks.clone();
+            move || clicks.set(clicks.get() + 1)
+        })
+        .with_enabled(enabled.clone());
+
+        let bounds = SRect::new(0, 0, 2, 1);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        let click = Event::Mouse {
+            pos: mint::Point2::from([1, 0]),
+            e: MouseEvent::ButtonDown {
+                button: MouseButton::Left,
+            },
+        };
+
+        assert!(button.handle_event(&mut ctx, click, bounds).is_ok());
+        assert_eq!(clicks.get(), 0);
+        let grid = TextGridBatch::render(&mut button, bounds.size());
+        assert_eq!(grid.lines(), vec!["ok"]);
+        assert_eq!(
+            grid.cell(0, 0).foreground,
+            color::WHITE.dimmed(DIMMED_FACTOR)
+        );
+
+        enabled.set(true);
+        assert!(button.handle_event(&mut ctx, click, bounds).is_err());
+        assert_eq!(clicks.get(), 1);
+        let grid = TextGridBatch::render(&mut button, bounds.size());
+        assert_eq!(grid.cell(0, 0).foreground, color::WHITE);
+    }
+
+    #[test]
+    fn test_text_input() {
+        let text = bind(String::from("ab"));
+        let submitted = std::rc::Rc::new(RefCell::new(None));
+        let mut input = TextInput::new(text.clone()).with_focus().with_on_submit({
+            let submitted = std::rc::Rc::clone(&submitted);
+            move |text| *submitted.borrow_mut() = Some(text)
+        });
+        let bounds = SRect::new(0, 0, 5, 1);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        let mut send = |input: &mut TextInput, event| input.handle_event(&mut ctx, event, bounds);
+        let key = |keycode| Event::KeyDown {
+            keycode,
+            mods: KeyMods::NONE,
+        };
+
+        assert!(send(&mut input, Event::Text { ch: 'c' }).is_err());
+        assert_eq!(text.get(), "abc");
+
+        let _ = send(&mut input, key(KeyCode::Left));
+        let _ = send(&mut input, key(KeyCode::Back));
+        assert_eq!(text.get(), "ac");
+
+        let _ = send(&mut input, key(KeyCode::Home));
+        let _ = send(&mut input, key(KeyCode::Delete));
+        let _ = send(&mut input, Event::Text { ch: '\u{e9}' });
+        assert_eq!(text.get(), "c");
+
+        let _ = send(&mut input, key(KeyCode::Return));
+        assert_eq!(submitted.borrow().as_deref(), Some("c"));
+
+        // Clicking elsewhere takes the focus away
+        let _ = send(
+            &mut input,
+            Event::Mouse {
+                pos: mint::Point2::from([10, 10]),
+                e: MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                },
+            },
+        );
+        assert!(!input.is_focused());
+        assert!(send(&mut input, Event::Text { ch: 'd' }).is_ok());
+        assert_eq!(text.get(), "c");
+    }
+
+    #[test]
+    fn test_stepper_repeat() {
+        let value = bind(0);
+        let mut stepper = Stepper::new(value.clone(), 0, 100);
+        let bounds = SRect::new(0, 0, 7, 1);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        let mut send = |stepper: &mut Stepper, e| {
+            let event = Event::Mouse {
+                pos: mint::Point2::from([6, 0]),
+                e,
+            };
+            let _ = stepper.handle_event(&mut ctx, event, bounds);
+        };
+
+        send(
+            &mut stepper,
+            MouseEvent::ButtonDown {
+                button: MouseButton::Left,
+            },
+        );
+        assert_eq!(value.get(), 1);
+
+        let mut draw = |stepper: &mut Stepper, n| {
+            let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+            for _ in 0..n {
+                let _ = stepper.handle_event(&mut ctx, Event::Draw, bounds);
+            }
+        };
+
+        // Nothing happens until the delay has passed
+        draw(&mut stepper, STEPPER_REPEAT_DELAY_DRAWS - 1);
+        assert_eq!(value.get(), 1);
+        draw(&mut stepper, 1);
+        assert_eq!(value.get(), 2);
+        draw(&mut stepper, STEPPER_REPEAT_INTERVAL_DRAWS);
+        assert_eq!(value.get(), 3);
+
+        send(
+            &mut stepper,
+            MouseEvent::ButtonUp {
+                button: MouseButton::Left,
+            },
+        );
+        draw(&mut stepper, STEPPER_REPEAT_DELAY_DRAWS * 2);
+        assert_eq!(value.get(), 3);
+    }
+
+    #[test]
+    fn test_progress_bar_filled() {
+        let filled = |done, total, width| ProgressBar::new(bind((done, total))).filled(width);
+
+        assert_eq!(filled(0, 10, 20), 0);
+        assert_eq!(filled(5, 10, 20), 10);
+        assert_eq!(filled(10, 10, 20), 20);
+        assert_eq!(filled(15, 10, 20), 20);
+        assert_eq!(filled(1, 3, 10), 3);
+        assert_eq!(filled(5, 0, 20), 0);
+        assert_eq!(filled(u32::max_value(), u32::max_value(), 80), 80);
+
+        let mut bar = ProgressBar::new(bind((1, 2)));
+        assert_eq!(bar.percentage(), 50);
+        assert_eq!(
+            bar.layout(BoxConstraints::new(Size::ZERO, Size::new(20, 5))),
+            Size::new(20, 1)
+        );
+    }
+}
@@ -0,0 +1,793 @@
+use std::ops::{Deref, DerefMut};
+
+use flo_binding::{Binding, Bound};
+use ggez::mint;
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::{
+        color::{self, Color},
+        voxel::Voxel2,
+    },
+    ui::{
+        layout_child, BoxConstraints, Continue, Element, Event, EventResult, KataText,
+        LayoutDirection, MouseEvent, Overlay, Row, Size, Stop, UiContext,
+    },
+};
+
+pub struct Padding<T> {
+    inner: T,
+    top: u32,
+    right: u32,
+    bottom: u32,
+    left: u32,
+    overflow: bool,
+}
+
+impl<T: Element> Padding<T> {
+    pub fn new(inner: T, top: u32, right: u32, bottom: u32, left: u32) -> Self {
+        Self {
+            inner,
+            top,
+            right,
+            bottom,
+            left,
+            overflow: false,
+        }
+    }
+
+    fn inner_bounds(&self, bounds: SRect) -> SRect {
+        SRect::new(
+            bounds.x + self.left as i32,
+            bounds.y + self.top as i32,
+            bounds.w.saturating_sub(self.left + self.right),
+            bounds.h.saturating_sub(self.top + self.bottom),
+        )
+    }
+}
+
+impl<T: Element> Element for Padding<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Padding relayout");
+
+        let insets = Size::new(self.right + self.left, self.top + self.bottom);
+        let (inner_size, overflow) = layout_child(&mut self.inner, constraints.shrink(insets));
+
+        self.overflow = overflow;
+        constraints.constrain(Size::new(
+            inner_size.width.saturating_add(insets.width),
+            inner_size.height.saturating_add(insets.height),
+        ))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let inner_bounds = self.inner_bounds(bounds);
+        self.inner.handle_event(ctx, event, inner_bounds)?;
+
+        if let (Event::Draw, true) = (event, self.overflow) {
+            ctx.report_overflow(inner_bounds);
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub struct Frame<T> {
+    inner: Padding<T>,
+    title: Vec<Voxel2>,
+    border_color: Color,
+}
+
+impl<T: Element> Frame<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: Padding::new(inner, 1, 1, 1, 1),
+            title: Vec::new(),
+            border_color: color::WHITE,
+        }
+    }
+
+    pub fn with_title(self, title: &str) -> Self {
+        Self {
+            title: KataText::from_str(title).voxels,
+            ..self
+        }
+    }
+
+    pub fn with_border_color(self, border_color: Color) -> Self {
+        Self {
+            border_color,
+            ..self
+        }
+    }
+
+    /// Changes the color of the border, e.g. to highlight what's in it.
+    pub fn set_border_color(&mut self, border_color: Color) {
+        self.border_color = border_color;
+    }
+
+    fn draw_border(&self, ctx: &mut UiContext, bounds: SRect) {
+        if bounds.w < 2 || bounds.h < 2 {
+            return;
+        }
+
+        let border = |char_offset| Voxel2::new(char_offset).foreground(self.border_color);
+
+        let (left, top) = (bounds.left(), bounds.top());
+        let (right, bottom) = (bounds.right() - 1, bounds.bottom() - 1);
+
+        ctx.draw(&border(0x264), [left, top]);
+        ctx.draw(&border(0x263), [right, top]);
+        ctx.draw(&border(0x261), [left, bottom]);
+        ctx.draw(&border(0x262), [right, bottom]);
+
+        let horizontal = border(0x265);
+        for x in (left + 1)..right {
+            ctx.draw(&horizontal, [x, top]);
+            ctx.draw(&horizontal, [x, bottom]);
+        }
+
+        let vertical = border(0x266);
+        for y in (top + 1)..bottom {
+            ctx.draw(&vertical, [left, y]);
+            ctx.draw(&vertical, [right, y]);
+        }
+
+        for (i, voxel) in self.title.iter().take((bounds.w - 2) as usize).enumerate() {
+            ctx.draw(voxel, [left + 1 + i as i32, top]);
+        }
+    }
+}
+
+impl<T: Element> Element for Frame<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Frame relayout");
+
+        self.inner.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            self.draw_border(ctx, bounds);
+        }
+
+        self.inner.handle_event(ctx, event, bounds)
+    }
+}
+
+/// Fills its bounds with a background and a box-drawn edge before drawing its child. Unlike
+/// `Frame` it has no title, and the child covers the edge unless it's inset.
+pub struct PanelBackground<T> {
+    inner: Padding<T>,
+    background: Voxel2,
+    border_color: Color,
+}
+
+impl<T: Element> PanelBackground<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner: Padding::new(inner, 0, 0, 0, 0),
+            background: Voxel2::new(0).background(Some(color::LIGHT_GRAY)),
+            border_color: color::WHITE,
+        }
+    }
+
+    /// Keeps the child off the edge.
+    pub fn inset(self) -> Self {
+        Self {
+            inner: Padding::new(self.inner.inner, 1, 1, 1, 1),
+            ..self
+        }
+    }
+
+    pub fn with_background(self, background: Voxel2) -> Self {
+        Self { background, ..self }
+    }
+
+    pub fn with_border_color(self, border_color: Color) -> Self {
+        Self {
+            border_color,
+            ..self
+        }
+    }
+
+    fn draw_background(&self, ctx: &mut UiContext, bounds: SRect) {
+        for p in bounds.points() {
+            let voxel = match panel_edge(bounds, p) {
+                Some(char_offset) => Voxel2::new(char_offset)
+                    .foreground(self.border_color)
+                    .background(self.background.background),
+                None => self.background.clone(),
+            };
+
+            ctx.draw(&voxel, p);
+        }
+    }
+}
+
+/// The box-drawing glyph for `p` if it lies on the edge of `bounds`. Bounds too thin to have an
+/// inside get no edge.
+fn panel_edge(bounds: SRect, p: mint::Point2<i32>) -> Option<u16> {
+    if bounds.w < 2 || bounds.h < 2 {
+        return None;
+    }
+
+    let left = p.x == bounds.left();
+    let right = p.x == bounds.right() - 1;
+    let top = p.y == bounds.top();
+    let bottom = p.y == bounds.bottom() - 1;
+
+    match (left, right, top, bottom) {
+        (true, _, true, _) => Some(0x264),
+        (_, true, true, _) => Some(0x263),
+        (true, _, _, true) => Some(0x261),
+        (_, true, _, true) => Some(0x262),
+        (_, _, true, _) | (_, _, _, true) => Some(0x265),
+        (true, _, _, _) | (_, true, _, _) => Some(0x266),
+        _ => None,
+    }
+}
+
+impl<T: Element> Element for PanelBackground<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.inner.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Draw = event {
+            self.draw_background(ctx, bounds);
+        }
+
+        self.inner.handle_event(ctx, event, bounds)
+    }
+}
+
+pub struct Centered<T> {
+    inner: T,
+    inner_size: Option<Size>,
+    overflow: bool,
+}
+
+impl<T: Element> Centered<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            inner_size: None,
+            overflow: false,
+        }
+    }
+}
+
+/// Places a rect of `size` in the middle of `bounds`, cutting it down if it doesn't fit.
+pub(super) fn centered_bounds(bounds: SRect, size: Size) -> SRect {
+    let size = size.min(bounds.size());
+
+    SRect::new(
+        bounds.x + ((bounds.w - size.width) / 2) as i32,
+        bounds.y + ((bounds.h - size.height) / 2) as i32,
+        size.width,
+        size.height,
+    )
+}
+
+impl<T: Element> Element for Centered<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Centered relayout");
+
+        let (inner_size, overflow) = layout_child(
+            &mut self.inner,
+            BoxConstraints::new(Size::new(0, 0), constraints.max),
+        );
+
+        self.inner_size = Some(inner_size);
+        self.overflow = overflow;
+
+        // Take up all of the available space along bounded axes, and only what the child needs
+        // along unbounded ones
+        let mut size = inner_size;
+        for &direction in &[LayoutDirection::Horizontal, LayoutDirection::Vertical] {
+            if constraints.is_bounded(direction) {
+                size[direction] = constraints.max[direction];
+            }
+        }
+
+        constraints.constrain(size)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let inner_bounds = centered_bounds(bounds, self.inner_size.unwrap());
+        self.inner.handle_event(ctx, event, inner_bounds)?;
+
+        if let (Event::Draw, true) = (event, self.overflow) {
+            ctx.report_overflow(inner_bounds);
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub struct Tooltip<T, F> {
+    inner: T,
+    text_fn: F,
+    delay: u32,
+    hover: Option<Hover>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Hover {
+    pos: mint::Point2<i32>,
+    draws: u32,
+}
+
+impl<T, F> Tooltip<T, F>
+where
+    T: Element,
+    F: FnMut(mint::Point2<i32>, SRect) -> Option<String>,
+{
+    /// Wraps `inner` so that resting the cursor over it shows the text returned by `text_fn`,
+    /// which gets the cursor position and the bounds of the element.
+    pub fn new(inner: T, text_fn: F) -> Self {
+        Self {
+            inner,
+            text_fn,
+            delay: 30,
+            hover: None,
+        }
+    }
+
+    /// Sets how many Draw events the cursor has to rest for before the tooltip shows.
+    pub fn with_delay(self, delay: u32) -> Self {
+        Self { delay, ..self }
+    }
+
+    fn update_hover(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) {
+        match event {
+            Event::Mouse {
+                pos,
+                e: MouseEvent::Move,
+            } => {
+                if !bounds.contains(pos) {
+                    self.hover = None;
+                } else if self.hover.map(|h| h.pos) != Some(pos) {
+                    self.hover = Some(Hover { pos, draws: 0 });
+                }
+            }
+
+            // Any other mouse interaction hides the tooltip until the cursor moves again
+            Event::Mouse { .. } => self.hover = None,
+
+            Event::KeyDown { .. } | Event::Text { .. } => {}
+
+            Event::Draw => {
+                if let Some(hover) = &mut self.hover {
+                    if hover.draws < self.delay {
+                        hover.draws += 1;
+                    } else if let Some(text) = (self.text_fn)(hover.pos, bounds) {
+                        ctx.push_overlay(Overlay {
+                            pos: mint::Point2::from([hover.pos.x + 1, hover.pos.y + 1]),
+                            voxels: KataText::from_str(&text)
+                                .voxels
+                                .into_iter()
+                                .map(|v| v.background(Some(color::LIGHT_GRAY)))
+                                .collect(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T, F> Element for Tooltip<T, F>
+where
+    T: Element,
+    F: FnMut(mint::Point2<i32>, SRect) -> Option<String>,
+{
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.inner.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        self.update_hover(ctx, event, bounds);
+        self.inner.handle_event(ctx, event, bounds)
+    }
+}
+
+impl<T, F> Row for Tooltip<T, F>
+where
+    T: Row,
+    F: FnMut(mint::Point2<i32>, SRect) -> Option<String>,
+{
+    fn draw_selected(&mut self, ctx: &mut UiContext, bounds: SRect) {
+        self.update_hover(ctx, Event::Draw, bounds);
+        self.inner.draw_selected(ctx, bounds);
+    }
+
+    fn preview(&self) -> Vec<Voxel2> {
+        self.inner.preview()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.inner.is_enabled()
+    }
+}
+
+/// Wraps an element that can be disabled through a binding. A disabled element is drawn
+/// dimmed and acts on no other events, letting them through to its siblings instead.
+pub struct Enabled<T> {
+    inner: T,
+    enabled: Binding<bool>,
+}
+
+impl<T> Enabled<T> {
+    pub(super) fn new(inner: T, enabled: Binding<bool>) -> Self {
+        Self { inner, enabled }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: Element> Element for Enabled<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.inner.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if self.enabled.get() {
+            return self.inner.handle_event(ctx, event, bounds);
+        }
+
+        if let Event::Draw = event {
+            ctx.push_dimmed();
+            let _ = self.inner.handle_event(ctx, event, bounds);
+            ctx.pop_dimmed();
+        }
+
+        Ok(Continue)
+    }
+}
+
+impl<T: Row> Row for Enabled<T> {
+    fn draw_selected(&mut self, ctx: &mut UiContext, bounds: SRect) {
+        if self.enabled.get() {
+            self.inner.draw_selected(ctx, bounds);
+        } else {
+            ctx.push_dimmed();
+            self.inner.draw_selected(ctx, bounds);
+            ctx.pop_dimmed();
+        }
+    }
+
+    fn preview(&self) -> Vec<Voxel2> {
+        self.inner.preview()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.get() && self.inner.is_enabled()
+    }
+}
+
+/// Whether a `WithEvents` handler runs before or after its element.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HandlerOrder {
+    Before,
+    After,
+}
+
+/// Wraps an element with an event handler. Whichever of the two runs first can stop an event
+/// from reaching the other by returning `Stop`, which is then passed on to the parent. Draw
+/// events always reach both so that stopping one never hides the element.
+pub struct WithEvents<T, F> {
+    element: T,
+    handler: F,
+    order: HandlerOrder,
+}
+
+impl<T, F> WithEvents<T, F>
+where
+    T: Element,
+    F: FnMut(&mut T, &mut UiContext, Event, SRect) -> EventResult,
+{
+    pub fn new(element: T, handler: F, order: HandlerOrder) -> Self {
+        Self {
+            element,
+            handler,
+            order,
+        }
+    }
+}
+
+impl<T, F> Deref for WithEvents<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl<T, F> DerefMut for WithEvents<T, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.element
+    }
+}
+
+impl<T, F> Element for WithEvents<T, F>
+where
+    T: Element,
+    F: FnMut(&mut T, &mut UiContext, Event, SRect) -> EventResult,
+{
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.element.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let handler = &mut self.handler;
+        let mut state = (&mut self.element, ctx);
+
+        let run_handler =
+            |(element, ctx): &mut (&mut T, &mut UiContext)| handler(element, ctx, event, bounds);
+        let run_element = |(element, ctx): &mut (&mut T, &mut UiContext)| {
+            element.handle_event(ctx, event, bounds)
+        };
+
+        match self.order {
+            HandlerOrder::Before => run_in_order(&mut state, event, run_handler, run_element),
+            HandlerOrder::After => run_in_order(&mut state, event, run_element, run_handler),
+        }
+    }
+}
+
+/// Runs `first`, then `second` unless `first` stopped the event. Draw events always run both.
+fn run_in_order<S, F1, F2>(state: &mut S, event: Event, first: F1, second: F2) -> EventResult
+where
+    F1: FnOnce(&mut S) -> EventResult,
+    F2: FnOnce(&mut S) -> EventResult,
+{
+    let first = first(state);
+
+    match (first, event) {
+        (Err(Stop), Event::Draw) => {
+            let _ = second(state);
+            Err(Stop)
+        }
+
+        (Err(Stop), _) => Err(Stop),
+        (Ok(Continue), _) => second(state),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{
+        test::{sized_placeholder, ConstraintsProbe},
+        ElementExt, FlexElement, FlexLayout, TextGridBatch, DIMMED_FACTOR,
+    };
+    use flo_binding::{bind, MutableBound};
+    use ggez::input::mouse::MouseButton;
+
+    #[test]
+    fn test_enabled() {
+        let enabled = bind(false);
+        let clicks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let mut text = KataText::from_str("ab")
+            .with_events({
+                let clicks = clicks.clone();
+                move |_, _, e, _| {
+                    if let Event::Mouse { .. } = e {
+                        clicks.set(clicks.get() + 1);
+                    }
+
+                    Err(Stop)
+                }
+            })
+            .with_enabled(enabled.clone());
+
+        let grid = TextGridBatch::render(&mut text, Size::new(2, 1));
+        assert_eq!(grid.lines(), vec!["ab"]);
+        assert_eq!(
+            grid.cell(0, 0).foreground,
+            color::WHITE.dimmed(DIMMED_FACTOR)
+        );
+
+        let bounds = SRect::new(0, 0, 2, 1);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        let click = Event::Mouse {
+            pos: mint::Point2::from([0, 0]),
+            e: MouseEvent::ButtonDown {
+                button: MouseButton::Left,
+            },
+        };
+
+        assert!(text.handle_event(&mut ctx, click, bounds).is_ok());
+        assert_eq!(clicks.get(), 0);
+
+        enabled.set(true);
+        assert!(text.handle_event(&mut ctx, click, bounds).is_err());
+        assert_eq!(clicks.get(), 1);
+
+        let grid = TextGridBatch::render(&mut text, Size::new(2, 1));
+        assert_eq!(grid.cell(0, 0).foreground, color::WHITE);
+    }
+
+    #[test]
+    fn test_centered_bounds() {
+        let bounds = SRect::new(10, 10, 5, 4);
+
+        assert_eq!(
+            centered_bounds(bounds, Size::new(3, 2)),
+            SRect::new(11, 11, 3, 2)
+        );
+        assert_eq!(
+            centered_bounds(bounds, Size::new(8, 2)),
+            SRect::new(10, 11, 5, 2)
+        );
+        assert_eq!(
+            centered_bounds(SRect::new(0, 0, 0, 0), Size::new(8, 8)),
+            SRect::new(0, 0, 0, 0)
+        );
+    }
+
+    fn run_logged(order: HandlerOrder, event: Event, stop: &str) -> (Vec<&'static str>, bool) {
+        let mut log = Vec::new();
+
+        let respond = |name| {
+            if name == stop {
+                Err(Stop)
+            } else {
+                Ok(Continue)
+            }
+        };
+        let handler = |log: &mut Vec<_>| {
+            log.push("handler");
+            respond("handler")
+        };
+        let element = |log: &mut Vec<_>| {
+            log.push("element");
+            respond("element")
+        };
+
+        let result = match order {
+            HandlerOrder::Before => run_in_order(&mut log, event, handler, element),
+            HandlerOrder::After => run_in_order(&mut log, event, element, handler),
+        };
+
+        (log, result.is_err())
+    }
+
+    #[test]
+    fn test_handler_order() {
+        use HandlerOrder::{After, Before};
+
+        let click = Event::Mouse {
+            pos: mint::Point2::from([0, 0]),
+            e: MouseEvent::ButtonDown {
+                button: MouseButton::Left,
+            },
+        };
+
+        assert_eq!(
+            run_logged(Before, click, ""),
+            (vec!["handler", "element"], false)
+        );
+        assert_eq!(
+            run_logged(Before, click, "handler"),
+            (vec!["handler"], true)
+        );
+        assert_eq!(
+            run_logged(Before, click, "element"),
+            (vec!["handler", "element"], true)
+        );
+
+        assert_eq!(
+            run_logged(After, click, ""),
+            (vec!["element", "handler"], false)
+        );
+        assert_eq!(run_logged(After, click, "element"), (vec!["element"], true));
+        assert_eq!(
+            run_logged(After, click, "handler"),
+            (vec!["element", "handler"], true)
+        );
+
+        // Draw always reaches both
+        assert_eq!(
+            run_logged(Before, Event::Draw, "handler"),
+            (vec!["handler", "element"], true)
+        );
+        assert_eq!(
+            run_logged(After, Event::Draw, "element"),
+            (vec!["element", "handler"], true)
+        );
+    }
+
+    #[test]
+    fn test_frame_layout() {
+        let mut frame = Frame::new(ConstraintsProbe::new()).with_title("Title");
+
+        let size = frame.layout(BoxConstraints::exact(Size::new(10, 5)));
+
+        assert_eq!(size, Size::new(10, 5));
+        assert_eq!(
+            frame.inner.inner.constraints,
+            Some(BoxConstraints::exact(Size::new(8, 3)))
+        );
+
+        let mut frame = Frame::new(ConstraintsProbe::new());
+
+        frame.layout(BoxConstraints::new(Size::ZERO, Size::new(1, 1)));
+
+        assert_eq!(
+            frame.inner.inner.constraints,
+            Some(BoxConstraints::exact(Size::ZERO))
+        );
+    }
+
+    #[test]
+    fn test_padding_layout() {
+        let mut padding = Padding::new(sized_placeholder(Size::new(3, 2)), 1, 2, 1, 2);
+
+        let size = padding.layout(BoxConstraints::new(Size::ZERO, Size::new(10, 10)));
+        assert_eq!(size, Size::new(7, 4));
+        assert!(!padding.overflow);
+
+        // Too little room for the insets
+        let size = padding.layout(BoxConstraints::new(Size::ZERO, Size::new(3, 3)));
+        assert_eq!(size, Size::new(3, 3));
+
+        // Padding inside a fixed flex slot takes up the child plus its insets
+        let mut flex = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(Padding::new(
+                sized_placeholder(Size::new(3, 2)),
+                1,
+                2,
+                1,
+                2,
+            ))),
+            FlexElement::flex(Box::new(ConstraintsProbe::new()), 1),
+        ]);
+
+        let size = flex.layout(BoxConstraints::new(Size::ZERO, Size::new(10, 10)));
+        assert_eq!(size, Size::new(7, 10));
+        assert_eq!(flex.elements[0].size, Some(4));
+        assert_eq!(flex.elements[1].size, Some(6));
+    }
+
+    #[test]
+    fn test_panel_edge() {
+        let bounds = SRect::new(1, 1, 3, 3);
+        let edges: Vec<_> = bounds.points().map(|p| panel_edge(bounds, p)).collect();
+
+        assert_eq!(
+            edges,
+            vec![
+                Some(0x264),
+                Some(0x265),
+                Some(0x263),
+                Some(0x266),
+                None,
+                Some(0x266),
+                Some(0x261),
+                Some(0x265),
+                Some(0x262),
+            ]
+        );
+
+        let bounds = SRect::new(0, 0, 5, 1);
+        assert!(bounds.points().all(|p| panel_edge(bounds, p).is_none()));
+
+        let mut panel = PanelBackground::new(ConstraintsProbe::new()).inset();
+        let size = panel.layout(BoxConstraints::exact(Size::new(6, 4)));
+        assert_eq!(size, Size::new(6, 4));
+        assert_eq!(
+            panel.inner.inner.constraints,
+            Some(BoxConstraints::exact(Size::new(4, 2)))
+        );
+    }
+}
@@ -0,0 +1,740 @@
+use std::{cmp::Ordering, ops::Range};
+
+use flo_binding::{bind, Binding, Bound};
+use ggez::mint;
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::voxel::Voxel2,
+    ui::{
+        BoxConstraints, Continue, Element, Event, EventResult, LayoutCache, LayoutDirection,
+        MouseEvent, Size, UiContext,
+    },
+};
+
+pub struct StackedLayout {
+    elements: Vec<StackedElement>,
+    direction: LayoutDirection,
+    dividers: bool,
+}
+
+impl StackedLayout {
+    pub fn empty(direction: LayoutDirection) -> Self {
+        Self::from_vec(direction, Vec::new())
+    }
+
+    pub fn from_vec(direction: LayoutDirection, elements: Vec<StackedElement>) -> Self {
+        Self {
+            elements,
+            direction,
+            dividers: false,
+        }
+    }
+
+    pub fn horizontal(elements: Vec<StackedElement>) -> Self {
+        Self::from_vec(LayoutDirection::Horizontal, elements)
+    }
+
+    pub fn vertical(elements: Vec<StackedElement>) -> Self {
+        Self::from_vec(LayoutDirection::Vertical, elements)
+    }
+
+    pub fn with_dividers(self) -> Self {
+        Self {
+            dividers: true,
+            ..self
+        }
+    }
+
+    fn scan_sizes(&self) -> (u32, usize) {
+        let mut total_size = 0;
+        let mut free = 0;
+
+        for element in self.elements.iter() {
+            if let Some(size) = element.size {
+                total_size += size;
+            } else {
+                free += 1;
+            }
+        }
+
+        (total_size, free)
+    }
+}
+
+impl Element for StackedLayout {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("StackedLayout relayout");
+
+        let (total_size, free) = self.scan_sizes();
+        let n = self.elements.len() as u32;
+
+        let mut size_allowance = constraints.max[self.direction];
+        if self.dividers {
+            size_allowance = size_allowance.saturating_sub(n.saturating_sub(1));
+        }
+
+        let fullness = total_size.cmp(&size_allowance);
+
+        match (fullness, free) {
+            (Ordering::Equal, 0) => {
+                // We're all good
+                trace!("StackedLayout children-only relayout");
+
+                for element in self.elements.iter_mut() {
+                    element.layout(BoxConstraints::exact(
+                        constraints
+                            .max
+                            .with_dir(self.direction, element.size.unwrap()),
+                    ));
+                }
+            }
+
+            (Ordering::Greater, _) | (Ordering::Equal, _) => {
+                // Overfull - Full relayout
+                trace!("StackedLayout full relayout");
+                for (i, element) in self.elements.iter_mut().enumerate() {
+                    let s = spread(i as u32, size_allowance, n);
+
+                    element.size = Some(s);
+                    element.layout(BoxConstraints::exact(
+                        constraints.max.with_dir(self.direction, s),
+                    ));
+                }
+            }
+
+            (Ordering::Less, 0) => {
+                // Add to existing elements in equal parts
+                trace!("StackedLayout adding to existing elements");
+                for (i, element) in self.elements.iter_mut().enumerate() {
+                    let s =
+                        element.size.unwrap() + spread(i as u32, size_allowance - total_size, n);
+
+                    element.size = Some(s);
+                    element.layout(BoxConstraints::exact(
+                        constraints.max.with_dir(self.direction, s),
+                    ));
+                }
+            }
+
+            (Ordering::Less, _) => {
+                // Spread to free elements in equal parts
+                trace!("StackedLayout adding to new elements");
+                for (i, element) in self
+                    .elements
+                    .iter_mut()
+                    .filter(|e| e.size.is_none())
+                    .enumerate()
+                {
+                    let s = element.size.unwrap_or_else(|| {
+                        spread(i as u32, size_allowance - total_size, free as u32)
+                    });
+
+                    element.size = Some(s);
+
+                    element.layout(BoxConstraints::exact(
+                        constraints.max.with_dir(self.direction, s),
+                    ));
+                }
+            }
+        };
+
+        constraints.max
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let mut offset = 0;
+
+        for (i, element) in self.elements.iter_mut().enumerate() {
+            if self.dividers && i > 0 {
+                let div_bounds = bounds.slice_dir(self.direction, offset..(offset + 1));
+
+                match event {
+                    Event::Draw => {
+                        let div_voxel = Voxel2::new(match self.direction {
+                            LayoutDirection::Horizontal => 0x266,
+                            LayoutDirection::Vertical => 0x265,
+                        });
+
+                        for p in div_bounds.points() {
+                            ctx.draw(&div_voxel, p);
+                        }
+                    }
+
+                    _ => {}
+                }
+
+                offset += 1;
+            }
+
+            let element_size = element.size.unwrap();
+            let element_bounds = bounds.slice_dir(self.direction, offset..(offset + element_size));
+
+            element
+                .cache
+                .handle_event(element.element.as_mut(), ctx, event, element_bounds)?;
+
+            offset += element_size;
+        }
+
+        Ok(Continue)
+    }
+}
+
+#[inline(always)]
+fn spread(i: u32, total: u32, n: u32) -> u32 {
+    total / n + if i < total % n { 1 } else { 0 }
+}
+
+pub struct StackedElement {
+    element: Box<dyn Element>,
+    size: Option<u32>,
+    cache: LayoutCache,
+}
+
+impl StackedElement {
+    pub fn new(element: Box<dyn Element>) -> Self {
+        Self {
+            element,
+            size: None,
+            cache: LayoutCache::default(),
+        }
+    }
+
+    fn layout(&mut self, constraints: BoxConstraints) {
+        self.cache.layout(self.element.as_mut(), constraints);
+    }
+}
+
+impl From<Box<dyn Element>> for StackedElement {
+    fn from(element: Box<dyn Element>) -> Self {
+        Self::new(element)
+    }
+}
+
+pub struct FlexLayout {
+    elements: Vec<FlexElement>,
+    direction: LayoutDirection,
+}
+
+impl FlexLayout {
+    pub fn empty(direction: LayoutDirection) -> Self {
+        Self::from_vec(direction, Vec::new())
+    }
+
+    pub fn from_vec(direction: LayoutDirection, elements: Vec<FlexElement>) -> Self {
+        Self {
+            elements,
+            direction,
+        }
+    }
+
+    pub fn horizontal(elements: Vec<FlexElement>) -> Self {
+        Self::from_vec(LayoutDirection::Horizontal, elements)
+    }
+
+    pub fn vertical(elements: Vec<FlexElement>) -> Self {
+        Self::from_vec(LayoutDirection::Vertical, elements)
+    }
+}
+
+impl Element for FlexLayout {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("FlexLayout relayout");
+
+        let mut free = constraints.max;
+        let mut max_other = 0;
+
+        for fixed_element in self.elements.iter_mut().filter(|e| e.flex == 0) {
+            // Fixed elements are stretched across the layout if its minimum asks for it
+            let element_size = fixed_element.layout(BoxConstraints::new(
+                Size::ZERO.with_dir(
+                    self.direction.other(),
+                    constraints.min[self.direction.other()],
+                ),
+                free,
+            ));
+
+            free = free.shrink(Size::ZERO.with_dir(self.direction, element_size[self.direction]));
+            fixed_element.size = Some(element_size[self.direction]);
+            max_other = max_other.max(element_size[self.direction.other()]);
+        }
+
+        let total_flex: u32 = self.elements.iter().map(|e| e.flex).sum();
+
+        let mut start_flex = 0;
+        for flex_element in self.elements.iter_mut().filter(|e| e.flex > 0) {
+            let end_flex = start_flex + flex_element.flex;
+
+            let element_size = spread_flex(start_flex..end_flex, free[self.direction], total_flex);
+
+            flex_element.layout(BoxConstraints::exact(
+                free.with_dir(self.direction, element_size),
+            ));
+
+            flex_element.size = Some(element_size);
+            start_flex = end_flex;
+        }
+
+        constraints.constrain(
+            if total_flex > 0 {
+                constraints.max
+            } else {
+                Size::default().with_dir(
+                    self.direction,
+                    constraints.max.dir(self.direction) - free.dir(self.direction),
+                )
+            }
+            .with_dir(self.direction.other(), max_other),
+        )
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let mut offset = 0;
+
+        for element in self.elements.iter_mut() {
+            let element_size = element.size.unwrap();
+            let element_bounds = bounds.slice_dir(self.direction, offset..(offset + element_size));
+
+            element
+                .cache
+                .handle_event(element.element.as_mut(), ctx, event, element_bounds)?;
+
+            offset += element_size;
+        }
+
+        Ok(Continue)
+    }
+}
+
+#[inline(always)]
+fn spread_flex(flex_range: Range<u32>, total: u32, total_flex: u32) -> u32 {
+    let flex = flex_range.end - flex_range.start;
+    let threshold = total % total_flex;
+
+    (total / total_flex * flex)
+        + if flex_range.start < threshold {
+            (threshold - flex_range.start).min(flex)
+        } else {
+            0
+        }
+}
+
+pub struct FlexElement {
+    pub element: Box<dyn Element>,
+    pub flex: u32,
+    size: Option<u32>,
+    cache: LayoutCache,
+}
+
+impl FlexElement {
+    pub fn flex(element: Box<dyn Element>, flex: u32) -> Self {
+        Self {
+            element,
+            flex,
+            size: None,
+            cache: LayoutCache::default(),
+        }
+    }
+
+    pub fn fixed(element: Box<dyn Element>) -> Self {
+        Self::flex(element, 0)
+    }
+
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        self.cache.layout(self.element.as_mut(), constraints)
+    }
+}
+
+pub struct GridLayout {
+    elements: Vec<Box<dyn Element>>,
+    cell_size: Size,
+    columns: u32,
+    selected: Binding<Option<usize>>,
+    highlight: Option<Voxel2>,
+}
+
+impl GridLayout {
+    pub fn new(cell_size: Size, elements: Vec<Box<dyn Element>>) -> Self {
+        assert!(cell_size.width > 0 && cell_size.height > 0);
+
+        Self {
+            elements,
+            cell_size,
+            columns: 1,
+            selected: bind(None),
+            highlight: None,
+        }
+    }
+
+    /// Draws `highlight` behind the cell whose index is held in `selected`.
+    pub fn with_selection(self, selected: Binding<Option<usize>>, highlight: Voxel2) -> Self {
+        Self {
+            selected,
+            highlight: Some(highlight),
+            ..self
+        }
+    }
+
+    pub fn columns(&self) -> u32 {
+        self.columns
+    }
+
+    pub fn rows(&self) -> u32 {
+        let n = self.elements.len() as u32;
+        n / self.columns + if n % self.columns > 0 { 1 } else { 0 }
+    }
+
+    /// The rows of cells taken up by the child at `index`, counted from the top of the grid, e.g.
+    /// for scrolling it into view.
+    pub fn row_span(&self, index: usize) -> Range<u32> {
+        let top = index as u32 / self.columns * self.cell_size.height;
+        top..top + self.cell_size.height
+    }
+
+    fn cell_bounds(&self, bounds: SRect, index: usize) -> SRect {
+        let index = index as u32;
+
+        SRect::new(
+            bounds.x + (index % self.columns * self.cell_size.width) as i32,
+            bounds.y + (index / self.columns * self.cell_size.height) as i32,
+            self.cell_size.width,
+            self.cell_size.height,
+        )
+    }
+
+    /// Returns the index of the child whose cell contains `pos`, given the grid was laid out
+    /// into `bounds`.
+    pub fn index_at<P>(&self, bounds: SRect, pos: P) -> Option<usize>
+    where
+        P: Into<mint::Point2<i32>>,
+    {
+        let pos = pos.into();
+
+        if !bounds.contains(pos) {
+            return None;
+        }
+
+        let column = (pos.x - bounds.x) as u32 / self.cell_size.width;
+        let row = (pos.y - bounds.y) as u32 / self.cell_size.height;
+
+        if column >= self.columns {
+            return None;
+        }
+
+        let index = (row * self.columns + column) as usize;
+
+        if index < self.elements.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+}
+
+impl Element for GridLayout {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("GridLayout relayout");
+
+        let n = self.elements.len() as u32;
+
+        self.columns = (constraints.max.width / self.cell_size.width).min(n).max(1);
+
+        for element in self.elements.iter_mut() {
+            element.layout(BoxConstraints::exact(self.cell_size));
+        }
+
+        constraints.constrain(Size::new(
+            self.columns * self.cell_size.width,
+            self.rows() * self.cell_size.height,
+        ))
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        // Button and wheel events only go to the cell under the cursor, everything else is
+        // broadcast so that children can react to the cursor leaving them
+        let target = match event {
+            Event::Mouse {
+                e: MouseEvent::Move,
+                ..
+            }
+            | Event::KeyDown { .. }
+            | Event::Text { .. }
+            | Event::Draw => None,
+            Event::Mouse { pos, .. } => Some(self.index_at(bounds, pos)),
+        };
+
+        let selected = self.selected.get();
+        let visible = ctx.visible_rect();
+
+        for i in 0..self.elements.len() {
+            let cell = self.cell_bounds(bounds, i);
+
+            if !bounds.contains_rect(&cell) {
+                continue;
+            }
+
+            if let (Event::Draw, Some(visible)) = (event, visible) {
+                if !cell.overlaps(&visible) {
+                    continue;
+                }
+            }
+
+            if let Some(target) = target {
+                if target != Some(i) {
+                    continue;
+                }
+            }
+
+            if let (Event::Draw, Some(highlight)) = (event, &self.highlight) {
+                if selected == Some(i) {
+                    for p in cell.points() {
+                        ctx.draw(highlight, p);
+                    }
+                }
+            }
+
+            self.elements[i].handle_event(ctx, event, cell)?;
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub struct Placeholder<F> {
+    voxel: Voxel2,
+    size_fn: F,
+}
+
+impl<F> Placeholder<F>
+where
+    F: Fn(BoxConstraints) -> Size,
+{
+    pub fn new(voxel: Voxel2, size_fn: F) -> Self {
+        Self { voxel, size_fn }
+    }
+}
+
+impl<F> Element for Placeholder<F>
+where
+    F: Fn(BoxConstraints) -> Size,
+{
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        (self.size_fn)(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Draw => {
+                for p in bounds.points() {
+                    ctx.draw(&self.voxel, p);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub struct Filling {
+    voxel: Voxel2,
+}
+
+impl Filling {
+    pub fn new(voxel: Voxel2) -> Self {
+        Self { voxel }
+    }
+
+    pub fn blank() -> Self {
+        Self::new(Voxel2::default())
+    }
+}
+
+impl Element for Filling {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        constraints.max
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Draw => {
+                for p in bounds.points() {
+                    ctx.draw(&self.voxel, p);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{
+        test::{assert_render, ConstraintsProbe},
+        KataText,
+    };
+    use flo_binding::MutableBound;
+
+    #[test]
+    fn test_grid_layout() {
+        let mut grid = GridLayout::new(
+            Size::new(2, 1),
+            (0..7)
+                .map(|_| Box::new(ConstraintsProbe::new()) as Box<dyn Element>)
+                .collect(),
+        );
+
+        let size = grid.layout(BoxConstraints::new(Size::ZERO, Size::new(7, 10)));
+
+        assert_eq!(grid.columns(), 3);
+        assert_eq!(grid.rows(), 3);
+        assert_eq!(size, Size::new(6, 3));
+
+        let bounds = SRect::new(10, 20, 6, 3);
+
+        assert_eq!(grid.index_at(bounds, [10, 20]), Some(0));
+        assert_eq!(grid.index_at(bounds, [11, 20]), Some(0));
+        assert_eq!(grid.index_at(bounds, [12, 20]), Some(1));
+        assert_eq!(grid.index_at(bounds, [15, 21]), Some(5));
+        assert_eq!(grid.index_at(bounds, [10, 22]), Some(6));
+        assert_eq!(grid.index_at(bounds, [12, 22]), None);
+        assert_eq!(grid.index_at(bounds, [16, 20]), None);
+        assert_eq!(grid.index_at(bounds, [9, 20]), None);
+    }
+
+    #[test]
+    fn test_flex_layout_overflow() {
+        let mut flex = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(KataText::from_str("abcdef"))),
+            FlexElement::fixed(Box::new(ConstraintsProbe::new())),
+        ]);
+
+        // The text wraps onto 3 lines but only 2 fit
+        let size = flex.layout(BoxConstraints::new(Size::new(2, 0), Size::new(2, 2)));
+
+        assert_eq!(size, Size::new(2, 2));
+        assert_eq!(flex.elements[0].size, Some(2));
+        assert!(flex.elements[0].cache.overflow);
+        assert_eq!(flex.elements[1].size, Some(0));
+        assert!(!flex.elements[1].cache.overflow);
+
+        // Fixed elements get the minimum across the layout
+        let mut flex =
+            FlexLayout::horizontal(vec![FlexElement::fixed(Box::new(KataText::from_str("ab")))]);
+
+        let size = flex.layout(BoxConstraints::new(Size::new(0, 3), Size::new(5, 3)));
+
+        assert_eq!(size, Size::new(2, 3));
+        assert!(!flex.elements[0].cache.overflow);
+    }
+
+    /// Counts how many times it was laid out.
+    struct CountingProbe {
+        layouts: std::rc::Rc<std::cell::Cell<u32>>,
+    }
+
+    impl Element for CountingProbe {
+        fn layout(&mut self, constraints: BoxConstraints) -> Size {
+            self.layouts.set(self.layouts.get() + 1);
+            constraints.max
+        }
+
+        fn handle_event(
+            &mut self,
+            _ctx: &mut UiContext,
+            _event: Event,
+            _bounds: SRect,
+        ) -> EventResult {
+            Ok(Continue)
+        }
+    }
+
+    #[test]
+    fn test_layout_cache() {
+        let counters: Vec<std::rc::Rc<std::cell::Cell<u32>>> =
+            (0..2).map(|_| Default::default()).collect();
+        let probe = |i: usize| {
+            Box::new(CountingProbe {
+                layouts: counters[i].clone(),
+            })
+        };
+        let counts = || counters.iter().map(|c| c.get()).collect::<Vec<_>>();
+
+        let mut flex = FlexLayout::vertical(vec![
+            FlexElement::fixed(Box::new(Placeholder::new(Voxel2::default(), |c| {
+                c.constrain(Size::new(1, 1))
+            }))),
+            FlexElement::flex(probe(0), 1),
+            FlexElement::flex(probe(1), 1),
+        ]);
+
+        let constraints = BoxConstraints::new(Size::ZERO, Size::new(10, 10));
+
+        flex.layout(constraints);
+        assert_eq!(counts(), vec![1, 1]);
+
+        // Nothing changed
+        flex.layout(constraints);
+        assert_eq!(counts(), vec![1, 1]);
+
+        // Only the dirty child is laid out again
+        flex.elements[2].cache.mark_dirty();
+        flex.layout(constraints);
+        assert_eq!(counts(), vec![1, 2]);
+
+        flex.layout(BoxConstraints::new(Size::ZERO, Size::new(12, 10)));
+        assert_eq!(counts(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_render_stacked_dividers() {
+        let text = |s| StackedElement::new(Box::new(KataText::from_str(s)));
+
+        assert_render(
+            &mut StackedLayout::horizontal(vec![text("a"), text("b"), text("c")]).with_dividers(),
+            Size::new(8, 1),
+            &["a |b |c "],
+        );
+        assert_render(
+            &mut StackedLayout::vertical(vec![text("a"), text("b")]).with_dividers(),
+            Size::new(3, 5),
+            &["a  ", "   ", "---", "b  ", "   "],
+        );
+    }
+
+    #[test]
+    fn test_spread() {
+        for n in 1..100 {
+            for total in 1..100 {
+                assert_eq!((0..n).map(|i| spread(i, total, n)).sum::<u32>(), total);
+            }
+        }
+    }
+
+    #[test]
+    fn text_flex_spread() {
+        for total_flex in 1..10 {
+            for total in 1..100 {
+                for flex_1 in 0..total_flex {
+                    for flex_2 in flex_1..total_flex {
+                        let flex_range_1 = 0..flex_1;
+                        let flex_range_2 = flex_1..flex_2;
+                        let flex_range_3 = flex_2..total_flex;
+
+                        assert_eq!(
+                            spread_flex(flex_range_1, total, total_flex)
+                                + spread_flex(flex_range_2, total, total_flex)
+                                + spread_flex(flex_range_3, total, total_flex),
+                            total
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
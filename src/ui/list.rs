@@ -0,0 +1,944 @@
+use flo_binding::{Binding, Bound, MutableBound};
+use ggez::{
+    input::{keyboard::KeyCode, mouse::MouseButton},
+    mint,
+};
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::voxel::Voxel2,
+    ui::{
+        BoxConstraints, Continue, Element, Event, EventResult, LayoutCache, LayoutDirection,
+        MouseEvent, Overlay, ScrollView, Size, Stop, UiContext,
+    },
+};
+
+pub struct List {
+    view: ScrollView<ListElements>,
+}
+
+impl List {
+    pub fn new() -> Self {
+        Self::from_vec(Vec::new())
+    }
+
+    pub fn from_vec(elements: Vec<ListElement>) -> Self {
+        Self {
+            view: ScrollView::new(ListElements {
+                elements,
+                direction: LayoutDirection::Vertical,
+            }),
+        }
+    }
+
+    /// Lays the elements out one after another along `direction` and scrolls that way.
+    pub fn with_direction(mut self, direction: LayoutDirection) -> Self {
+        self.view.inner_mut().direction = direction;
+
+        Self {
+            view: self.view.with_direction(direction),
+        }
+    }
+
+    pub fn elements(&self) -> &[ListElement] {
+        &self.view.inner().elements
+    }
+
+    pub fn elements_mut(&mut self) -> &mut Vec<ListElement> {
+        &mut self.view.inner_mut().elements
+    }
+}
+
+impl Element for List {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("List relayout");
+
+        self.view.layout(constraints)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        self.view.handle_event(ctx, event, bounds)
+    }
+}
+
+/// The elements of a `List`, stacked one after another.
+struct ListElements {
+    elements: Vec<ListElement>,
+    direction: LayoutDirection,
+}
+
+impl Element for ListElements {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        let size = layout_list_elements(
+            &mut self.elements,
+            self.direction,
+            BoxConstraints::new(
+                Size::ZERO,
+                constraints.max.with_dir(self.direction, u32::max_value()),
+            ),
+        );
+
+        constraints.constrain(size)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let visible = ctx.visible_rect();
+        let mut offset = 0;
+
+        for element in self.elements.iter_mut() {
+            if let Some(size) = element.size {
+                let element_bounds = match self.direction {
+                    LayoutDirection::Horizontal => {
+                        SRect::new(bounds.x + offset, bounds.y, size.width, size.height)
+                    }
+                    LayoutDirection::Vertical => {
+                        SRect::new(bounds.x, bounds.y + offset, size.width, size.height)
+                    }
+                };
+                offset += size[self.direction] as i32;
+
+                if let (Event::Draw, Some(visible)) = (event, visible) {
+                    if !element_bounds.overlaps(&visible) {
+                        continue;
+                    }
+                }
+
+                element
+                    .cache
+                    .handle_event(element.element.as_mut(), ctx, event, element_bounds)?;
+            }
+        }
+
+        Ok(Continue)
+    }
+}
+
+fn layout_list_elements(
+    elements: &mut [ListElement],
+    direction: LayoutDirection,
+    constraints: BoxConstraints,
+) -> Size {
+    let mut size = Size::new(0, 0);
+
+    for element in elements {
+        let element_size = element.cache.layout(element.element.as_mut(), constraints);
+
+        size[direction] += element_size[direction];
+        size[direction.other()] = size[direction.other()].max(element_size[direction.other()]);
+
+        element.size = Some(element_size);
+    }
+
+    size
+}
+
+pub struct ListElement {
+    pub element: Box<dyn Element>,
+    size: Option<Size>,
+    cache: LayoutCache,
+}
+
+impl ListElement {
+    pub fn new(element: Box<dyn Element>) -> Self {
+        Self {
+            element,
+            size: None,
+            cache: LayoutCache::default(),
+        }
+    }
+}
+
+impl From<Box<dyn Element>> for ListElement {
+    fn from(element: Box<dyn Element>) -> Self {
+        Self::new(element)
+    }
+}
+
+/// An element that can be used as a row of a `SelectableList`.
+pub trait Row: Element {
+    /// Draws the row the way it should look while selected.
+    fn draw_selected(&mut self, ctx: &mut UiContext, bounds: SRect);
+
+    /// A single line that stands in for the row while it's being dragged.
+    fn preview(&self) -> Vec<Voxel2>;
+
+    /// Disabled rows can't be selected or activated.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+}
+
+/// Where the row at `index` ends up after the row at `from` is moved to `to`.
+pub fn moved_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// A scrolling list of rows, one of which can be selected by clicking it or with the up and
+/// down keys while the list has focus.
+pub struct SelectableList {
+    view: ScrollView<SelectableRows>,
+    selected: Binding<Option<usize>>,
+    height: u32,
+    focused: bool,
+    drag: Option<RowDrag>,
+    on_select: Option<Box<dyn FnMut(usize)>>,
+    on_activate: Option<Box<dyn FnMut(usize)>>,
+    on_move: Option<Box<dyn FnMut(usize, usize)>>,
+}
+
+/// A row of a `SelectableList` that has been lifted by dragging it.
+struct RowDrag {
+    from: usize,
+    pos: mint::Point2<i32>,
+}
+
+impl SelectableList {
+    pub fn new(selected: Binding<Option<usize>>) -> Self {
+        Self::from_vec(Vec::new(), selected)
+    }
+
+    pub fn from_vec(rows: Vec<Box<dyn Row>>, selected: Binding<Option<usize>>) -> Self {
+        let mut it = Self {
+            view: ScrollView::new(SelectableRows {
+                rows,
+                heights: Vec::new(),
+                selected: selected.clone(),
+                clicked: None,
+                double_clicked: None,
+            }),
+            selected,
+            height: 0,
+            focused: false,
+            drag: None,
+            on_select: None,
+            on_activate: None,
+            on_move: None,
+        };
+
+        it.clamp_selection();
+        it
+    }
+
+    /// Calls `on_select` with the index of the row whenever the selection is changed from the
+    /// list itself.
+    pub fn with_on_select<F>(self, on_select: F) -> Self
+    where
+        F: FnMut(usize) + 'static,
+    {
+        Self {
+            on_select: Some(Box::new(on_select)),
+            ..self
+        }
+    }
+
+    /// Calls `on_activate` with the index of the row when it's double clicked, or when Enter
+    /// is pressed while it's selected.
+    pub fn with_on_activate<F>(self, on_activate: F) -> Self
+    where
+        F: FnMut(usize) + 'static,
+    {
+        Self {
+            on_activate: Some(Box::new(on_activate)),
+            ..self
+        }
+    }
+
+    /// Lets rows be reordered by dragging them, calling `on_move` with the old and new index
+    /// of the row after it's dropped. Dropping a row outside of the list leaves it where it was.
+    pub fn with_on_move<F>(self, on_move: F) -> Self
+    where
+        F: FnMut(usize, usize) + 'static,
+    {
+        Self {
+            on_move: Some(Box::new(on_move)),
+            ..self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.view.inner().rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected.get()
+    }
+
+    pub fn push(&mut self, row: Box<dyn Row>) {
+        self.view.inner_mut().rows.push(row);
+    }
+
+    /// Replaces all of the rows, keeping the selected index if it's still in range.
+    pub fn set_rows(&mut self, rows: Vec<Box<dyn Row>>) {
+        let inner = self.view.inner_mut();
+        inner.rows = rows;
+        inner.heights.clear();
+
+        self.clamp_selection();
+    }
+
+    /// Removes a row, keeping the selection on the same row if it's still there.
+    pub fn remove(&mut self, index: usize) -> Box<dyn Row> {
+        let rows = self.view.inner_mut();
+        let row = rows.rows.remove(index);
+
+        if index < rows.heights.len() {
+            rows.heights.remove(index);
+        }
+
+        if let Some(selected) = self.selected.get() {
+            if selected > index {
+                self.selected.set(Some(selected - 1));
+            }
+        }
+
+        self.clamp_selection();
+        row
+    }
+
+    /// Moves the row at `from` so that it ends up at `to`, keeping the selection on the same
+    /// row.
+    pub fn move_row(&mut self, from: usize, to: usize) {
+        let rows = self.view.inner_mut();
+        let row = rows.rows.remove(from);
+        rows.rows.insert(to, row);
+
+        if from < rows.heights.len() && to < rows.heights.len() {
+            let height = rows.heights.remove(from);
+            rows.heights.insert(to, height);
+        }
+
+        if let Some(selected) = self.selected.get() {
+            self.selected.set(Some(moved_index(selected, from, to)));
+        }
+    }
+
+    /// Selects a row and scrolls it into view.
+    pub fn select(&mut self, ctx: &mut UiContext, index: usize) {
+        if !self.is_enabled(index) {
+            return;
+        }
+
+        if self.selected.get() != Some(index) {
+            self.selected.set(Some(index));
+
+            if let Some(on_select) = &mut self.on_select {
+                on_select(index);
+            }
+        }
+
+        let rows = self.view.inner();
+
+        if let Some(&height) = rows.heights.get(index) {
+            let top: u32 = rows.heights[..index].iter().sum();
+            let scroll = self.view.scroll_pos();
+
+            if top < scroll {
+                self.view.scroll_to(ctx, top);
+            } else if top + height > scroll + self.height {
+                self.view
+                    .scroll_to(ctx, (top + height).saturating_sub(self.height));
+            }
+        }
+    }
+
+    /// The row under `pos`, where dropping a row below the last one moves it to the end.
+    fn row_at(&self, bounds: SRect, pos: mint::Point2<i32>) -> Option<usize> {
+        let viewport = self.view.viewport(bounds);
+
+        if !viewport.contains(pos) || self.is_empty() {
+            return None;
+        }
+
+        let rows = self.view.inner();
+        rows.index_at(self.view.inner_bounds(bounds), pos)
+            .or(Some(self.len() - 1))
+    }
+
+    /// Lifts, moves and drops rows. Only called when the list has an `on_move` callback.
+    fn handle_drag(&mut self, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Mouse {
+                pos,
+                e:
+                    MouseEvent::ButtonDrag {
+                        button: MouseButton::Left,
+                        start_pos,
+                    },
+            } => {
+                if self.drag.is_none() && (pos.y as i64 - start_pos.y as i64).abs() > 1 {
+                    if let Some(from) = self.row_at(bounds, start_pos) {
+                        self.drag = Some(RowDrag { from, pos });
+                    }
+                }
+
+                if let Some(drag) = &mut self.drag {
+                    drag.pos = pos;
+                    return Err(Stop);
+                }
+            }
+
+            Event::Mouse {
+                pos,
+                e:
+                    MouseEvent::ButtonUp {
+                        button: MouseButton::Left,
+                    },
+            } => {
+                if let Some(drag) = self.drag.take() {
+                    if let Some(to) = self.row_at(bounds, pos) {
+                        if to != drag.from {
+                            self.move_row(drag.from, to);
+
+                            if let Some(on_move) = &mut self.on_move {
+                                on_move(drag.from, to);
+                            }
+                        }
+                    }
+
+                    return Err(Stop);
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(Continue)
+    }
+
+    /// Whether there's a row at `index` that can be selected.
+    pub fn is_enabled(&self, index: usize) -> bool {
+        self.view
+            .inner()
+            .rows
+            .get(index)
+            .map_or(false, |row| row.is_enabled())
+    }
+
+    fn activate(&mut self, index: usize) {
+        if !self.is_enabled(index) {
+            return;
+        }
+
+        if let Some(on_activate) = &mut self.on_activate {
+            on_activate(index);
+        }
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.len();
+
+        if let Some(selected) = self.selected.get() {
+            if selected >= len {
+                self.selected.set(len.checked_sub(1));
+            }
+        }
+    }
+}
+
+impl Element for SelectableList {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("SelectableList relayout");
+
+        self.clamp_selection();
+
+        let size = self.view.layout(constraints);
+        self.height = size.height;
+        size
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Mouse {
+                pos,
+                e: MouseEvent::ButtonDown { .. },
+            } => self.focused = bounds.contains(pos),
+
+            Event::KeyDown { keycode, .. } if self.focused && !self.is_empty() => {
+                let selected = self.selected.get();
+                let len = self.len();
+
+                // Skip over disabled rows, staying put if there are none left to go to
+                match keycode {
+                    KeyCode::Up => {
+                        let above = selected.unwrap_or(len);
+                        if let Some(index) = (0..above).rev().find(|&i| self.is_enabled(i)) {
+                            self.select(ctx, index);
+                        }
+
+                        return Err(Stop);
+                    }
+
+                    KeyCode::Down => {
+                        let below = selected.map_or(0, |s| s + 1);
+                        if let Some(index) = (below..len).find(|&i| self.is_enabled(i)) {
+                            self.select(ctx, index);
+                        }
+
+                        return Err(Stop);
+                    }
+
+                    KeyCode::Return => {
+                        if let Some(selected) = selected {
+                            self.activate(selected);
+                            return Err(Stop);
+                        }
+                    }
+
+                    _ => {}
+                }
+            }
+
+            _ => {}
+        }
+
+        if self.on_move.is_some() {
+            self.handle_drag(event, bounds)?;
+        }
+
+        let result = self.view.handle_event(ctx, event, bounds);
+
+        if let (Event::Draw, Some(drag)) = (event, &self.drag) {
+            ctx.push_overlay(Overlay {
+                pos: drag.pos,
+                voxels: self.view.inner().rows[drag.from].preview(),
+            });
+        }
+
+        if let Some(index) = self.view.inner_mut().clicked.take() {
+            self.select(ctx, index);
+            return Err(Stop);
+        }
+
+        if let Some(index) = self.view.inner_mut().double_clicked.take() {
+            self.activate(index);
+            return Err(Stop);
+        }
+
+        result
+    }
+}
+
+/// The rows of a `SelectableList`, stacked on top of each other.
+struct SelectableRows {
+    rows: Vec<Box<dyn Row>>,
+    heights: Vec<u32>,
+    selected: Binding<Option<usize>>,
+    clicked: Option<usize>,
+    double_clicked: Option<usize>,
+}
+
+impl SelectableRows {
+    fn index_at(&self, bounds: SRect, pos: mint::Point2<i32>) -> Option<usize> {
+        if !bounds.contains(pos) {
+            return None;
+        }
+
+        let mut bottom = bounds.y;
+
+        for (i, &height) in self.heights.iter().enumerate() {
+            bottom += height as i32;
+
+            if pos.y < bottom {
+                return Some(i);
+            }
+        }
+
+        None
+    }
+}
+
+impl Element for SelectableRows {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        let row_constraints = BoxConstraints::new(
+            Size::ZERO,
+            Size::new(constraints.max.width, u32::max_value()),
+        );
+
+        let mut size = Size::ZERO;
+        self.heights.clear();
+
+        for row in self.rows.iter_mut() {
+            let row_size = row.layout(row_constraints);
+
+            self.heights.push(row_size.height);
+            size.width = size.width.max(row_size.width);
+            size.height += row_size.height;
+        }
+
+        constraints.constrain(size)
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Event::Mouse { pos, e } = event {
+            match e {
+                MouseEvent::ButtonDown {
+                    button: MouseButton::Left,
+                } => self.clicked = self.index_at(bounds, pos),
+
+                MouseEvent::ButtonDoubleClick {
+                    button: MouseButton::Left,
+                } => self.double_clicked = self.index_at(bounds, pos),
+
+                _ => {}
+            }
+        }
+
+        let selected = self.selected.get();
+        let visible = ctx.visible_rect();
+        let mut y = 0;
+
+        for (i, (row, &height)) in self.rows.iter_mut().zip(self.heights.iter()).enumerate() {
+            // Rows span the whole width so that the selection highlight does too
+            let row_bounds = SRect::new(bounds.x, bounds.y + y, bounds.w, height);
+            y += height as i32;
+
+            if let Event::Draw = event {
+                if let Some(visible) = visible {
+                    if !row_bounds.overlaps(&visible) {
+                        continue;
+                    }
+                }
+
+                if selected == Some(i) {
+                    row.draw_selected(ctx, row_bounds);
+                    continue;
+                }
+            }
+
+            row.handle_event(ctx, event, row_bounds)?;
+        }
+
+        Ok(Continue)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{
+        test::{assert_render, sized_placeholder},
+        Centered, ElementExt, KataText, TextGridBatch,
+    };
+    use flo_binding::bind;
+    use ggez::input::keyboard::KeyMods;
+
+    #[test]
+    fn test_selectable_list_remove() {
+        let rows = || {
+            ["a", "b", "c", "d"]
+                .iter()
+                .map(|&s| Box::new(KataText::from_str(s)) as Box<dyn Row>)
+                .collect()
+        };
+
+        let mut list = SelectableList::from_vec(rows(), bind(Some(2)));
+        list.layout(BoxConstraints::new(Size::ZERO, Size::new(4, 2)));
+
+        // Rows before the selection shift it up
+        list.remove(0);
+        assert_eq!(list.selected(), Some(1));
+
+        // Removing the selected row keeps the same index when possible
+        list.remove(1);
+        assert_eq!(list.selected(), Some(1));
+
+        // Otherwise it's clamped to the last row
+        list.remove(1);
+        assert_eq!(list.selected(), Some(0));
+
+        list.remove(0);
+        assert_eq!(list.selected(), None);
+
+        let list = SelectableList::from_vec(rows(), bind(Some(10)));
+        assert_eq!(list.selected(), Some(3));
+    }
+
+    #[test]
+    fn test_selectable_list_double_click() {
+        let activated = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut list = SelectableList::from_vec(
+            ["a", "b", "c"]
+                .iter()
+                .map(|&s| Box::new(KataText::from_str(s)) as Box<dyn Row>)
+                .collect(),
+            bind(None),
+        )
+        .with_on_activate({
+            let activated = activated.clone();
+            move |index| activated.borrow_mut().push(index)
+        });
+
+        let bounds = SRect::new(0, 0, 4, 3);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        list.layout(BoxConstraints::exact(bounds.size()));
+
+        let mut click = |e| {
+            let event = Event::Mouse {
+                pos: mint::Point2::from([0, 1]),
+                e,
+            };
+            let _ = list.handle_event(&mut ctx, event, bounds);
+        };
+
+        click(MouseEvent::ButtonDown {
+            button: MouseButton::Left,
+        });
+        assert!(activated.borrow().is_empty());
+
+        click(MouseEvent::ButtonDoubleClick {
+            button: MouseButton::Left,
+        });
+        assert_eq!(*activated.borrow(), vec![1]);
+        assert_eq!(list.selected(), Some(1));
+    }
+
+    #[test]
+    fn test_selectable_list_disabled_rows() {
+        let enabled = bind(false);
+        let mut list = SelectableList::from_vec(
+            vec![
+                Box::new(KataText::from_str("a")) as Box<dyn Row>,
+                Box::new(KataText::from_str("b").with_enabled(enabled.clone())),
+                Box::new(KataText::from_str("c")),
+            ],
+            bind(Some(0)),
+        );
+
+        let bounds = SRect::new(0, 0, 4, 3);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        list.layout(BoxConstraints::exact(bounds.size()));
+        list.focused = true;
+
+        let mut key = |keycode| {
+            let event = Event::KeyDown {
+                keycode,
+                mods: KeyMods::NONE,
+            };
+            let _ = list.handle_event(&mut ctx, event, bounds);
+            list.selected()
+        };
+
+        assert_eq!(key(KeyCode::Down), Some(2));
+        assert_eq!(key(KeyCode::Down), Some(2));
+        assert_eq!(key(KeyCode::Up), Some(0));
+
+        enabled.set(true);
+        assert_eq!(key(KeyCode::Down), Some(1));
+    }
+
+    #[test]
+    fn test_moved_index() {
+        let moved = |from, to| (0..4).map(|i| moved_index(i, from, to)).collect::<Vec<_>>();
+
+        assert_eq!(moved(0, 3), vec![3, 0, 1, 2]);
+        assert_eq!(moved(3, 0), vec![1, 2, 3, 0]);
+        assert_eq!(moved(1, 2), vec![0, 2, 1, 3]);
+        assert_eq!(moved(2, 2), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_selectable_list_drag() {
+        let moves = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut list = SelectableList::from_vec(
+            ["a", "b", "c", "d"]
+                .iter()
+                .map(|&s| Box::new(KataText::from_str(s)) as Box<dyn Row>)
+                .collect(),
+            bind(Some(0)),
+        )
+        .with_on_move({
+            let moves = moves.clone();
+            move |from, to| moves.borrow_mut().push((from, to))
+        });
+
+        let bounds = SRect::new(0, 0, 4, 6);
+        list.layout(BoxConstraints::new(Size::ZERO, bounds.size()));
+
+        let order = |list: &SelectableList| -> String {
+            list.view
+                .inner()
+                .rows
+                .iter()
+                .map(|row| row.preview()[0].char_offset as u8 as char)
+                .collect()
+        };
+        let drag = |x, y| Event::Mouse {
+            pos: mint::Point2::from([x, y]),
+            e: MouseEvent::ButtonDrag {
+                button: MouseButton::Left,
+                start_pos: mint::Point2::from([0, 0]),
+            },
+        };
+        let drop = |x, y| Event::Mouse {
+            pos: mint::Point2::from([x, y]),
+            e: MouseEvent::ButtonUp {
+                button: MouseButton::Left,
+            },
+        };
+
+        // Moving by a single cell doesn't lift the row
+        assert!(list.handle_drag(drag(0, 1), bounds).is_ok());
+        assert!(list.drag.is_none());
+
+        assert!(list.handle_drag(drag(0, 2), bounds).is_err());
+        assert!(list.handle_drag(drop(0, 2), bounds).is_err());
+        assert_eq!(order(&list), "bcad");
+        assert_eq!(list.selected(), Some(2));
+        assert_eq!(*moves.borrow(), vec![(0, 2)]);
+
+        // Dropping below the last row moves it to the end
+        let _ = list.handle_drag(drag(0, 5), bounds);
+        let _ = list.handle_drag(drop(0, 5), bounds);
+        assert_eq!(order(&list), "cadb");
+        assert_eq!(list.selected(), Some(1));
+
+        // Dropping outside of the list cancels the move
+        let _ = list.handle_drag(drag(0, 3), bounds);
+        let _ = list.handle_drag(drop(10, 3), bounds);
+        assert_eq!(order(&list), "cadb");
+        assert!(list.drag.is_none());
+        assert_eq!(moves.borrow().len(), 2);
+    }
+
+    fn text_list(direction: LayoutDirection) -> List {
+        List::from_vec(
+            ["ab", "cde"]
+                .iter()
+                .map(|&s| ListElement::new(Box::new(KataText::from_str(s))))
+                .collect(),
+        )
+        .with_direction(direction)
+    }
+
+    #[test]
+    fn test_vertical_list_layout() {
+        let mut list = text_list(LayoutDirection::Vertical);
+
+        let size = list.layout(BoxConstraints::new(Size::ZERO, Size::new(4, 3)));
+        assert_eq!(size, Size::new(3, 2));
+        assert_eq!(list.view.scrollbar_size, None);
+
+        let size = list.layout(BoxConstraints::new(Size::ZERO, Size::new(4, 1)));
+        assert_eq!(size, Size::new(4, 1));
+        assert_eq!(list.view.scrollbar_size, Some(Size::new(1, 1)));
+        assert_eq!(list.view.scrollbar.scroll_max.get(), 1);
+        assert_eq!(
+            list.view.viewport(SRect::new(0, 0, 4, 1)),
+            SRect::new(0, 0, 3, 1)
+        );
+    }
+
+    #[test]
+    fn test_render_scrolled_list() {
+        let text = |s| ListElement::new(Box::new(KataText::from_str(s)));
+        let mut list = List::from_vec(vec![text("a1\na2"), text("b1\nb2"), text("c1\nc2")]);
+        let size = Size::new(3, 3);
+        let mut ctx = UiContext::new(TextGridBatch::new(size));
+
+        list.layout(BoxConstraints::exact(size));
+        list.view.scroll_to(&mut ctx, 1);
+
+        // The first element starts a row above the viewport, so only its second line shows
+        assert_render(&mut list, size, &["a2^", "b1#", "b2v"]);
+        assert_eq!(
+            list.view.inner_bounds(SRect::new(0, 0, 3, 3)),
+            SRect::new(0, -1, 2, 6)
+        );
+    }
+
+    #[test]
+    fn test_click_scrolled_list() {
+        let mut list = SelectableList::from_vec(
+            ["a", "b", "c", "d"]
+                .iter()
+                .map(|&s| Box::new(KataText::from_str(s)) as Box<dyn Row>)
+                .collect(),
+            bind(None),
+        );
+        let bounds = SRect::new(0, 0, 2, 2);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+
+        list.layout(BoxConstraints::exact(bounds.size()));
+        list.view.scroll_to(&mut ctx, 2);
+
+        let click = Event::Mouse {
+            pos: mint::Point2::from([0, 1]),
+            e: MouseEvent::ButtonDown {
+                button: MouseButton::Left,
+            },
+        };
+
+        assert!(list.handle_event(&mut ctx, click, bounds).is_err());
+        assert_eq!(list.selected(), Some(3));
+    }
+
+    #[test]
+    fn test_horizontal_list_layout() {
+        let mut list = text_list(LayoutDirection::Horizontal);
+
+        let size = list.layout(BoxConstraints::new(Size::ZERO, Size::new(6, 3)));
+        assert_eq!(size, Size::new(5, 1));
+        assert_eq!(list.view.scrollbar_size, None);
+        assert_eq!(
+            list.elements().iter().map(|e| e.size).collect::<Vec<_>>(),
+            vec![Some(Size::new(2, 1)), Some(Size::new(3, 1))]
+        );
+
+        // Overflowing puts the scrollbar along the bottom
+        let size = list.layout(BoxConstraints::new(Size::ZERO, Size::new(4, 3)));
+        assert_eq!(size, Size::new(4, 2));
+        assert_eq!(list.view.scrollbar_size, Some(Size::new(4, 1)));
+        assert_eq!(list.view.scrollbar.scroll_max.get(), 1);
+
+        let bounds = SRect::new(10, 10, 4, 2);
+        assert_eq!(list.view.viewport(bounds), SRect::new(10, 10, 4, 1));
+        assert_eq!(
+            list.view.scrollbar_bounds(bounds, Size::new(4, 1)),
+            SRect::new(10, 11, 4, 1)
+        );
+    }
+
+    #[test]
+    fn test_centered_layout() {
+        let mut centered = Centered::new(sized_placeholder(Size::new(2, 1)));
+
+        let size = centered.layout(BoxConstraints::new(Size::ZERO, Size::new(6, 5)));
+        assert_eq!(size, Size::new(6, 5));
+
+        let size = centered.layout(BoxConstraints::new(
+            Size::ZERO,
+            Size::new(u32::max_value(), u32::max_value()),
+        ));
+        assert_eq!(size, Size::new(2, 1));
+
+        // Centered rows of a vertical list span its width but keep their own height
+        let mut list = List::from_vec(
+            (0..2)
+                .map(|_| {
+                    ListElement::new(Box::new(Centered::new(sized_placeholder(Size::new(2, 1)))))
+                })
+                .collect(),
+        );
+
+        let size = list.layout(BoxConstraints::new(Size::ZERO, Size::new(6, 5)));
+        assert_eq!(size, Size::new(6, 2));
+        assert_eq!(list.view.scrollbar_size, None);
+        assert_eq!(
+            list.elements().iter().map(|e| e.size).collect::<Vec<_>>(),
+            vec![Some(Size::new(6, 1)), Some(Size::new(6, 1))]
+        );
+    }
+}
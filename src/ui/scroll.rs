@@ -0,0 +1,540 @@
+use std::ops::Range;
+
+use flo_binding::{bind, Binding, Bound, MutableBound};
+use ggez::{input::mouse::MouseButton, mint};
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::{color, voxel::Voxel2},
+    ui::{
+        BoxConstraints, Continue, Element, Event, EventResult, LayoutDirection, MouseEvent,
+        PointExt, Size, Stop, UiContext,
+    },
+};
+
+pub struct ScrollView<T> {
+    inner: T,
+    inner_size: Size,
+    pub(super) scrollbar: ScrollBar,
+    pub(super) scrollbar_size: Option<Size>,
+}
+
+impl<T: Element> ScrollView<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            inner_size: Size::ZERO,
+            scrollbar: ScrollBar::new(bind(0), bind(0), LayoutDirection::Vertical),
+            scrollbar_size: None,
+        }
+    }
+
+    /// Scrolls along `direction` instead of vertically. A horizontal scrollbar sits along the
+    /// bottom edge.
+    pub fn with_direction(mut self, direction: LayoutDirection) -> Self {
+        self.scrollbar.direction = direction;
+        self
+    }
+
+    pub fn direction(&self) -> LayoutDirection {
+        self.scrollbar.direction
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn scroll_pos(&self) -> u32 {
+        self.scrollbar.scroll_pos.get()
+    }
+
+    pub fn scroll_to(&mut self, ctx: &mut UiContext, new_pos: u32) {
+        self.scrollbar.scroll_to(ctx, new_pos);
+    }
+
+    /// Scrolls as little as possible to show `span` of the content along the scrolling direction,
+    /// given the view was laid out into `bounds`. A span longer than the viewport is shown from
+    /// its start.
+    pub fn scroll_into_view(&mut self, ctx: &mut UiContext, bounds: SRect, span: Range<u32>) {
+        let length = self.viewport(bounds).size()[self.direction()];
+        let pos = self.scroll_pos();
+
+        let new_pos = if span.start < pos || span.end - span.start > length {
+            span.start
+        } else if span.end > pos + length {
+            span.end - length
+        } else {
+            pos
+        };
+
+        if new_pos != pos {
+            self.scroll_to(ctx, new_pos);
+        }
+    }
+
+    /// The part of `bounds` that shows the content, i.e. everything but the scrollbar.
+    pub(super) fn viewport(&self, bounds: SRect) -> SRect {
+        let cross = self.direction().other();
+        let scrollbar_size = self.scrollbar_size.map(|s| s[cross]).unwrap_or(0);
+
+        bounds.slice_dir(
+            cross,
+            0..bounds.size()[cross].saturating_sub(scrollbar_size),
+        )
+    }
+
+    pub(super) fn scrollbar_bounds(&self, bounds: SRect, scrollbar_size: Size) -> SRect {
+        match self.direction() {
+            LayoutDirection::Horizontal => SRect::new(
+                bounds.x,
+                bounds.bottom() - scrollbar_size.height as i32,
+                scrollbar_size.width.min(bounds.w),
+                scrollbar_size.height,
+            ),
+
+            LayoutDirection::Vertical => SRect::new(
+                bounds.right() - scrollbar_size.width as i32,
+                bounds.y,
+                scrollbar_size.width,
+                scrollbar_size.height.min(bounds.h),
+            ),
+        }
+    }
+
+    /// Where the content is placed, which is moved back by the scroll position so that whatever
+    /// has been scrolled past lies before the viewport.
+    pub(super) fn inner_bounds(&self, bounds: SRect) -> SRect {
+        let viewport = self.viewport(bounds);
+        let scroll = self.scroll_pos() as i32;
+
+        let (x, y) = match self.direction() {
+            LayoutDirection::Horizontal => (viewport.x - scroll, viewport.y),
+            LayoutDirection::Vertical => (viewport.x, viewport.y - scroll),
+        };
+
+        SRect::new(x, y, self.inner_size.width, self.inner_size.height)
+    }
+}
+
+impl<T: Element> Element for ScrollView<T> {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("ScrollView relayout");
+
+        let direction = self.direction();
+        let cross = direction.other();
+
+        // The content gets as much room as it wants along the scrolling direction
+        let unbounded = |cross_max: u32| {
+            BoxConstraints::new(
+                Size::ZERO.with_dir(cross, constraints.min[cross].min(cross_max)),
+                Size::ZERO
+                    .with_dir(direction, u32::max_value())
+                    .with_dir(cross, cross_max),
+            )
+        };
+
+        let mut inner_size = self.inner.layout(unbounded(constraints.max[cross]));
+
+        self.scrollbar_size = if inner_size[direction] > constraints.max[direction] {
+            trace!("ScrollView overflow");
+            // If the content overflows, display the scrollbar and give the content what's left
+            let scrollbar_size = self.scrollbar.layout(BoxConstraints::exact(
+                Size::ZERO
+                    .with_dir(direction, constraints.max[direction])
+                    .with_dir(cross, 1),
+            ));
+
+            inner_size = self.inner.layout(unbounded(
+                constraints.max[cross].saturating_sub(scrollbar_size[cross]),
+            ));
+
+            Some(scrollbar_size)
+        } else {
+            None
+        };
+
+        let scroll_max = inner_size[direction].saturating_sub(constraints.max[direction]);
+        self.inner_size = inner_size;
+        self.scrollbar.scroll_max.set(scroll_max);
+        self.scrollbar
+            .scroll_pos
+            .set(self.scrollbar.scroll_pos.get().min(scroll_max));
+
+        let scrollbar_size = self.scrollbar_size.map(|s| s[cross]).unwrap_or(0);
+        constraints.constrain(
+            Size::ZERO
+                .with_dir(
+                    direction,
+                    inner_size[direction].min(constraints.max[direction]),
+                )
+                .with_dir(cross, inner_size[cross] + scrollbar_size),
+        )
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        if let Some(scrollbar_size) = self.scrollbar_size {
+            let scrollbar_bounds = self.scrollbar_bounds(bounds, scrollbar_size);
+            self.scrollbar.handle_event(ctx, event, scrollbar_bounds)?;
+        }
+
+        let viewport = self.viewport(bounds);
+        let inner_bounds = self.inner_bounds(bounds);
+
+        match event {
+            Event::Draw => {
+                ctx.push_viewport(viewport);
+                let result = self.inner.handle_event(ctx, event, inner_bounds);
+                ctx.pop_viewport();
+                result?;
+            }
+
+            Event::Mouse { pos, e } => {
+                let inside = match e {
+                    MouseEvent::ButtonDrag { start_pos, .. } => viewport.contains(start_pos),
+                    _ => viewport.contains(pos),
+                };
+
+                if inside {
+                    self.inner.handle_event(ctx, event, inner_bounds)?;
+                } else if let MouseEvent::Move = e {
+                    // Let the content know that the cursor has left it
+                    self.inner.handle_event(
+                        ctx,
+                        Event::Mouse {
+                            pos: mint::Point2::from([i32::max_value(), i32::max_value()]),
+                            e,
+                        },
+                        inner_bounds,
+                    )?;
+                }
+
+                if self.scrollbar_size.is_some() && viewport.contains(pos) {
+                    match e {
+                        MouseEvent::WheelUp => {
+                            self.scrollbar.scroll_up(ctx);
+                            return Err(Stop);
+                        }
+
+                        MouseEvent::WheelDown => {
+                            self.scrollbar.scroll_down(ctx);
+                            return Err(Stop);
+                        }
+
+                        _ => {}
+                    }
+                }
+            }
+
+            Event::KeyDown { .. } | Event::Text { .. } => {
+                self.inner.handle_event(ctx, event, inner_bounds)?
+            }
+        }
+
+        Ok(Continue)
+    }
+}
+
+pub struct ScrollBar {
+    pub scroll_pos: Binding<u32>,
+    pub scroll_max: Binding<u32>,
+    pub direction: LayoutDirection,
+}
+
+impl ScrollBar {
+    pub fn new(
+        scroll_pos: Binding<u32>,
+        scroll_max: Binding<u32>,
+        direction: LayoutDirection,
+    ) -> Self {
+        Self {
+            scroll_pos,
+            scroll_max,
+            direction,
+        }
+    }
+
+    pub fn scroll_up(&mut self, ctx: &mut UiContext) {
+        self.scroll_to(ctx, self.scroll_pos.get().saturating_sub(1));
+    }
+
+    pub fn scroll_down(&mut self, ctx: &mut UiContext) {
+        self.scroll_to(ctx, self.scroll_pos.get() + 1);
+    }
+
+    /// Scrolling only moves what's drawn, so it doesn't need a relayout.
+    pub fn scroll_to(&mut self, _ctx: &mut UiContext, new_pos: u32) {
+        self.scroll_pos.set(new_pos.min(self.scroll_max.get()));
+    }
+
+    /// How far along the track between the arrows the caret is drawn.
+    fn caret_pos(&self, size: Size) -> u32 {
+        let scroll_max = self.scroll_max.get();
+        let track = size.dir(self.direction).saturating_sub(3);
+
+        if scroll_max == 0 {
+            return 0;
+        }
+
+        let scroll_r = self.scroll_pos.get().min(scroll_max) as f32 / scroll_max as f32;
+        (scroll_r * track as f32).round() as u32
+    }
+
+    /// The scroll position that puts the caret at `track_pos` along the track.
+    fn scroll_pos_at(&self, track_pos: u32, size: Size) -> u32 {
+        let track = size.dir(self.direction).saturating_sub(3);
+
+        if track == 0 {
+            return 0;
+        }
+
+        (track_pos.min(track) as f32 / track as f32 * self.scroll_max.get() as f32).round() as u32
+    }
+}
+
+impl Element for ScrollBar {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("ScrollBar relayout");
+
+        match self.direction {
+            LayoutDirection::Horizontal => {
+                Size::new(constraints.max.width, constraints.min.height.max(1))
+            }
+
+            LayoutDirection::Vertical => {
+                Size::new(constraints.min.width.max(1), constraints.max.height)
+            }
+        }
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        let length = bounds.size()[self.direction];
+
+        match event.cull(bounds) {
+            Some(Event::Mouse { pos, e }) => match e {
+                MouseEvent::WheelUp => {
+                    self.scroll_up(ctx);
+                    Err(Stop)
+                }
+
+                MouseEvent::WheelDown => {
+                    self.scroll_down(ctx);
+                    Err(Stop)
+                }
+
+                MouseEvent::ButtonDown { button } if button == MouseButton::Left => {
+                    let scrollbar_pos =
+                        (pos.dir(self.direction) - bounds.point().dir(self.direction)) as u32;
+
+                    if self.scroll_max.get() == 0 {
+                        // Nothing to scroll
+                    } else if scrollbar_pos == 0 {
+                        self.scroll_up(ctx);
+                    } else if scrollbar_pos == length - 1 {
+                        self.scroll_down(ctx);
+                    } else if scrollbar_pos - 1 != self.caret_pos(bounds.size()) {
+                        let scroll_pos = self.scroll_pos_at(scrollbar_pos - 1, bounds.size());
+                        self.scroll_to(ctx, scroll_pos);
+                    }
+
+                    Err(Stop)
+                }
+
+                MouseEvent::ButtonDrag { button, start_pos }
+                    if button == MouseButton::Left
+                        && length > 2
+                        && bounds
+                            .slice_dir(self.direction, 1..(length - 1))
+                            .contains(start_pos) =>
+                {
+                    let track_pos = (pos.dir(self.direction)
+                        - (bounds.dir_start(self.direction) + 1))
+                        .max(0) as u32;
+                    let scroll_pos = self.scroll_pos_at(track_pos, bounds.size());
+
+                    self.scroll_to(ctx, scroll_pos);
+
+                    Err(Stop)
+                }
+
+                _ => Ok(Continue),
+            },
+
+            Some(Event::Draw) => {
+                let (start_arrow, end_arrow) = match self.direction {
+                    LayoutDirection::Horizontal => (0x11, 0x10),
+                    LayoutDirection::Vertical => (0x1E, 0x1F),
+                };
+
+                let start_arrow = Voxel2::new(start_arrow).background(Some(color::GRAY));
+                let end_arrow = Voxel2::new(end_arrow).background(Some(color::GRAY));
+                let caret = Voxel2::new(0x2EC).background(Some(color::GRAY));
+                let bg = Voxel2::new(0).background(Some(color::DARK_GRAY));
+
+                let caret_pos = 1 + self.caret_pos(bounds.size());
+
+                for (pos, line) in bounds.lines(self.direction) {
+                    let i = (pos - bounds.dir_start(self.direction)) as u32;
+                    let voxel = if i == 0 {
+                        &start_arrow
+                    } else if i == length - 1 {
+                        &end_arrow
+                    } else if i == caret_pos {
+                        &caret
+                    } else {
+                        &bg
+                    };
+
+                    for p in line {
+                        ctx.draw(voxel, p);
+                    }
+                }
+
+                Ok(Continue)
+            }
+
+            _ => Ok(Continue),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::{
+        test::{assert_render, ConstraintsProbe},
+        GridLayout, TextGridBatch,
+    };
+
+    #[test]
+    fn test_scroll_view_layout() {
+        let mut view = ScrollView::new(GridLayout::new(
+            Size::new(1, 1),
+            (0..40)
+                .map(|_| Box::new(ConstraintsProbe::new()) as Box<dyn Element>)
+                .collect(),
+        ));
+
+        // Fits without scrolling
+        let size = view.layout(BoxConstraints::new(Size::ZERO, Size::new(8, 5)));
+        assert_eq!(size, Size::new(8, 5));
+        assert_eq!(view.scrollbar_size, None);
+        assert_eq!(view.scrollbar.scroll_max.get(), 0);
+
+        // Overflows, so a column goes to the scrollbar
+        let size = view.layout(BoxConstraints::new(Size::ZERO, Size::new(8, 4)));
+        assert_eq!(size, Size::new(8, 4));
+        assert_eq!(view.scrollbar_size, Some(Size::new(1, 4)));
+        assert_eq!(view.inner().columns(), 7);
+        assert_eq!(view.scrollbar.scroll_max.get(), 2);
+        assert_eq!(
+            view.viewport(SRect::new(0, 0, 8, 4)),
+            SRect::new(0, 0, 7, 4)
+        );
+    }
+
+    #[test]
+    fn test_scroll_into_view() {
+        let mut view = ScrollView::new(GridLayout::new(
+            Size::new(1, 1),
+            (0..40)
+                .map(|_| Box::new(ConstraintsProbe::new()) as Box<dyn Element>)
+                .collect(),
+        ));
+        let bounds = SRect::new(0, 0, 8, 3);
+        let mut ctx = UiContext::new(TextGridBatch::new(bounds.size()));
+        view.layout(BoxConstraints::exact(bounds.size()));
+
+        let mut scroll_to_child = |view: &mut ScrollView<GridLayout>, index| {
+            let span = view.inner().row_span(index);
+            view.scroll_into_view(&mut ctx, bounds, span);
+            view.scroll_pos()
+        };
+
+        // Seven columns with the scrollbar, so six rows of which three are shown
+        assert_eq!(view.inner().row_span(15), 2..3);
+        assert_eq!(scroll_to_child(&mut view, 15), 0);
+        assert_eq!(scroll_to_child(&mut view, 21), 1);
+        assert_eq!(scroll_to_child(&mut view, 39), 3);
+        assert_eq!(scroll_to_child(&mut view, 30), 3);
+        assert_eq!(scroll_to_child(&mut view, 3), 0);
+    }
+
+    #[test]
+    fn test_scrollbar_caret_pos() {
+        let caret_pos = |scroll_pos, scroll_max, height| {
+            ScrollBar::new(
+                bind(scroll_pos),
+                bind(scroll_max),
+                LayoutDirection::Vertical,
+            )
+            .caret_pos(Size::new(1, height))
+        };
+
+        // The track between the arrows is 8 cells long, so the caret goes from 0 to 7
+        assert_eq!(caret_pos(0, 0, 10), 0);
+
+        assert_eq!(caret_pos(0, 1, 10), 0);
+        assert_eq!(caret_pos(1, 1, 10), 7);
+
+        assert_eq!(caret_pos(0, 2, 10), 0);
+        assert_eq!(caret_pos(1, 2, 10), 4);
+        assert_eq!(caret_pos(2, 2, 10), 7);
+
+        assert_eq!(caret_pos(0, 10, 10), 0);
+        assert_eq!(caret_pos(3, 10, 10), 2);
+        assert_eq!(caret_pos(10, 10, 10), 7);
+        assert_eq!(caret_pos(20, 10, 10), 7);
+
+        for height in 0..=3 {
+            for &scroll_max in &[0, 1, 2, 10] {
+                assert_eq!(caret_pos(scroll_max, scroll_max, height), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scrollbar_scroll_pos_at() {
+        let scroll_pos_at = |track_pos, scroll_max, width| {
+            ScrollBar::new(bind(0), bind(scroll_max), LayoutDirection::Horizontal)
+                .scroll_pos_at(track_pos, Size::new(width, 1))
+        };
+
+        assert_eq!(scroll_pos_at(0, 10, 10), 0);
+        assert_eq!(scroll_pos_at(7, 10, 10), 10);
+        assert_eq!(scroll_pos_at(100, 10, 10), 10);
+        assert_eq!(scroll_pos_at(4, 0, 10), 0);
+
+        for width in 0..=3 {
+            assert_eq!(scroll_pos_at(1, 10, width), 0);
+        }
+    }
+
+    #[test]
+    fn test_render_scrollbar() {
+        assert_render(
+            &mut ScrollBar::new(bind(0), bind(10), LayoutDirection::Vertical),
+            Size::new(1, 5),
+            &["^", "#", ".", ".", "v"],
+        );
+        assert_render(
+            &mut ScrollBar::new(bind(10), bind(10), LayoutDirection::Vertical),
+            Size::new(1, 5),
+            &["^", ".", ".", "#", "v"],
+        );
+        assert_render(
+            &mut ScrollBar::new(bind(5), bind(10), LayoutDirection::Horizontal),
+            Size::new(6, 1),
+            &["<..#.>"],
+        );
+        assert_render(
+            &mut ScrollBar::new(bind(0), bind(0), LayoutDirection::Horizontal),
+            Size::new(2, 1),
+            &["<>"],
+        );
+    }
+}
@@ -0,0 +1,450 @@
+use std::ops::Range;
+
+use ggez::mint;
+use log::trace;
+
+use crate::{
+    geometry::rect::SRect,
+    rendering::{
+        color::{self, Color},
+        voxel::Voxel2,
+    },
+    ui::{BoxConstraints, Continue, Element, Event, EventResult, Row, Size, UiContext},
+};
+
+/// Where `KataText` is allowed to break lines that don't fit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    /// Break after whichever glyph reaches the edge.
+    Chars,
+    /// Break at spaces, falling back to `Chars` for words longer than a line.
+    Words,
+}
+
+pub struct KataText {
+    pub voxels: Vec<Voxel2>,
+    wrap: WrapMode,
+    newlines: Vec<usize>,
+    lines: Vec<Range<usize>>,
+}
+
+impl KataText {
+    pub fn from_voxels(voxels: Vec<Voxel2>) -> Self {
+        Self {
+            voxels,
+            wrap: WrapMode::Words,
+            newlines: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    pub fn from_colored_str(s: &str, color: Color) -> Self {
+        Self::from_spans(&[(s, color)])
+    }
+
+    pub fn from_spans(spans: &[(&str, Color)]) -> Self {
+        let mut text = Self::from_voxels(Vec::new());
+
+        for &(s, color) in spans {
+            text.push_span(s, color, None);
+        }
+
+        text
+    }
+
+    /// Parses text with inline color tags. `{red}` switches to a color from
+    /// `rendering::color::by_name`, `{red:blue}` also sets the background, `{/}` goes back to
+    /// the previous colors and `{{` is a literal brace. Anything else in braces, including a
+    /// tag that's never closed, is kept as plain text.
+    pub fn from_markup(s: &str) -> Self {
+        let mut text = Self::from_voxels(Vec::new());
+        let mut styles = vec![(color::WHITE, None)];
+        let mut rest = s;
+
+        while !rest.is_empty() {
+            let (foreground, background) = *styles.last().unwrap();
+
+            let open = match rest.find('{') {
+                Some(open) => open,
+                None => {
+                    text.push_span(rest, foreground, background);
+                    break;
+                }
+            };
+
+            text.push_span(&rest[..open], foreground, background);
+            rest = &rest[open..];
+
+            if rest.starts_with("{{") {
+                text.push_span("{", foreground, background);
+                rest = &rest[2..];
+                continue;
+            }
+
+            let tag = rest.find('}').map(|close| &rest[1..close]);
+
+            match tag.and_then(MarkupTag::parse) {
+                Some(MarkupTag::Open(style)) => styles.push(style),
+
+                Some(MarkupTag::Close) => {
+                    if styles.len() > 1 {
+                        styles.pop();
+                    }
+                }
+
+                None => {
+                    text.push_span("{", foreground, background);
+                    rest = &rest[1..];
+                    continue;
+                }
+            }
+
+            rest = &rest[tag.unwrap().len() + 2..];
+        }
+
+        text
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        Self::from_colored_str(s, color::WHITE)
+    }
+
+    /// Lays out `rows` of voxels one per line, e.g. art imported from an image. Rows wider than
+    /// the text break at whichever glyph reaches the edge.
+    pub fn from_rows(rows: Vec<Vec<Voxel2>>) -> Self {
+        let mut text = Self::from_voxels(Vec::new()).with_wrap(WrapMode::Chars);
+
+        for (i, row) in rows.into_iter().enumerate() {
+            if i > 0 {
+                text.newlines.push(text.voxels.len());
+            }
+
+            text.voxels.extend(row);
+        }
+
+        text
+    }
+
+    pub fn with_wrap(self, wrap: WrapMode) -> Self {
+        Self { wrap, ..self }
+    }
+
+    /// The ranges of `voxels` on each line, as of the last layout.
+    pub fn lines(&self) -> &[Range<usize>] {
+        &self.lines
+    }
+
+    /// Appends `s` in the given colors. Non-ASCII characters show up as a square.
+    pub fn push_span(&mut self, s: &str, foreground: Color, background: Option<Color>) {
+        for (i, c) in s.char_indices() {
+            if c == '\n' {
+                self.newlines.push(self.voxels.len());
+                continue;
+            }
+
+            self.voxels.push(
+                Voxel2::new(if c.is_ascii() {
+                    u16::from(s.as_bytes()[i])
+                } else {
+                    0x082D // Square
+                })
+                .foreground(foreground)
+                .background(background),
+            );
+        }
+    }
+
+    fn break_lines(&mut self, width: u32) {
+        self.lines.clear();
+
+        if self.voxels.is_empty() && self.newlines.is_empty() {
+            return;
+        }
+
+        let mut start = 0;
+
+        for end in self
+            .newlines
+            .iter()
+            .copied()
+            .chain(std::iter::once(self.voxels.len()))
+        {
+            let end = end.min(self.voxels.len()).max(start);
+            self.wrap_paragraph(start..end, width as usize);
+            start = end;
+        }
+    }
+
+    fn wrap_paragraph(&mut self, paragraph: Range<usize>, width: usize) {
+        let is_space = |voxel: &Voxel2| voxel.char_offset == u16::from(b' ');
+        let mut start = paragraph.start;
+
+        while paragraph.end - start > width {
+            let space = match self.wrap {
+                WrapMode::Chars => None,
+                WrapMode::Words => ((start + 1)..=(start + width))
+                    .rev()
+                    .find(|&i| is_space(&self.voxels[i])),
+            };
+
+            match space {
+                Some(space) => {
+                    // The space the line was broken at isn't drawn
+                    self.lines.push(start..space);
+                    start = space + 1;
+                }
+
+                None => {
+                    self.lines.push(start..(start + width));
+                    start += width;
+                }
+            }
+        }
+
+        self.lines.push(start..paragraph.end);
+    }
+
+    fn placed_voxels(
+        &self,
+        origin: mint::Point2<i32>,
+    ) -> impl Iterator<Item = (&Voxel2, mint::Point2<i32>)> + '_ {
+        self.lines.iter().enumerate().flat_map(move |(row, line)| {
+            self.voxels
+                .get(line.clone())
+                .unwrap_or(&[])
+                .iter()
+                .enumerate()
+                .map(move |(column, voxel)| {
+                    (
+                        voxel,
+                        mint::Point2::from([origin.x + column as i32, origin.y + row as i32]),
+                    )
+                })
+        })
+    }
+}
+
+enum MarkupTag {
+    Open((Color, Option<Color>)),
+    Close,
+}
+
+impl MarkupTag {
+    fn parse(tag: &str) -> Option<Self> {
+        if tag == "/" {
+            return Some(MarkupTag::Close);
+        }
+
+        let mut names = tag.splitn(2, ':');
+        let foreground = color::by_name(names.next()?)?;
+        let background = match names.next() {
+            Some(name) => Some(color::by_name(name)?),
+            None => None,
+        };
+
+        Some(MarkupTag::Open((foreground, background)))
+    }
+}
+
+impl From<&str> for KataText {
+    fn from(s: &str) -> Self {
+        Self::from_str(s)
+    }
+}
+
+impl Element for KataText {
+    fn layout(&mut self, constraints: BoxConstraints) -> Size {
+        trace!("Text relayout");
+
+        if constraints.max.width == 0 {
+            self.lines.clear();
+            Size::new(0, 0)
+        } else {
+            self.break_lines(constraints.max.width);
+
+            let longest = self.lines.iter().map(|l| l.len()).max().unwrap_or(0) as u32;
+
+            Size::new(
+                longest
+                    .min(constraints.max.width)
+                    .max(constraints.min.width),
+                self.lines.len() as u32,
+            )
+        }
+    }
+
+    fn handle_event(&mut self, ctx: &mut UiContext, event: Event, bounds: SRect) -> EventResult {
+        match event {
+            Event::Draw => {
+                for (voxel, pos) in self.placed_voxels(bounds.point()) {
+                    ctx.draw(voxel, pos);
+                }
+                Ok(Continue)
+            }
+
+            _ => Ok(Continue),
+        }
+    }
+}
+
+impl Row for KataText {
+    fn draw_selected(&mut self, ctx: &mut UiContext, bounds: SRect) {
+        let fill = Voxel2::new(0).background(Some(color::WHITE));
+
+        for p in bounds.points() {
+            ctx.draw(&fill, p);
+        }
+
+        for (voxel, pos) in self.placed_voxels(bounds.point()) {
+            ctx.draw(&voxel.clone().inverted(), pos);
+        }
+    }
+
+    fn preview(&self) -> Vec<Voxel2> {
+        match self.lines.first() {
+            Some(line) => self.voxels[line.clone()].to_vec(),
+            None => self.voxels.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ui::test::assert_render;
+
+    fn text_lines(text: &mut KataText, width: u32) -> Vec<String> {
+        let size = text.layout(BoxConstraints::new(Size::ZERO, Size::new(width, 100)));
+        assert_eq!(size.height as usize, text.lines().len());
+
+        text.lines()
+            .iter()
+            .map(|line| {
+                text.voxels[line.clone()]
+                    .iter()
+                    .map(|v| v.char_offset as u8 as char)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_text_wrap() {
+        let mut text = KataText::from_str("the quick brown fox");
+
+        assert_eq!(text_lines(&mut text, 19), vec!["the quick brown fox"]);
+        assert_eq!(text_lines(&mut text, 10), vec!["the quick", "brown fox"]);
+        assert_eq!(
+            text_lines(&mut text, 5),
+            vec!["the", "quick", "brown", "fox"]
+        );
+        assert_eq!(
+            text_lines(&mut text, 3),
+            vec!["the", "qui", "ck", "bro", "wn", "fox"]
+        );
+
+        let mut text = KataText::from_str("the quick brown fox").with_wrap(WrapMode::Chars);
+
+        assert_eq!(text_lines(&mut text, 10), vec!["the quick ", "brown fox"]);
+        assert_eq!(
+            text_lines(&mut text, 4),
+            vec!["the ", "quic", "k br", "own ", "fox"]
+        );
+    }
+
+    #[test]
+    fn test_text_newlines() {
+        let mut text = KataText::from_str("ab\n\ncd ef\n");
+
+        assert_eq!(text_lines(&mut text, 10), vec!["ab", "", "cd ef", ""]);
+        assert_eq!(text_lines(&mut text, 3), vec!["ab", "", "cd", "ef", ""]);
+        assert_eq!(
+            text.layout(BoxConstraints::new(Size::ZERO, Size::new(10, 10))),
+            Size::new(5, 4)
+        );
+
+        assert_eq!(
+            text_lines(&mut KataText::from_str(""), 10),
+            Vec::<String>::new()
+        );
+
+        let row = |s: &str| s.bytes().map(|b| Voxel2::new(u16::from(b))).collect();
+        let mut text = KataText::from_rows(vec![row("ab c"), row(""), row("de")]);
+
+        assert_eq!(text_lines(&mut text, 10), vec!["ab c", "", "de"]);
+        assert_eq!(text_lines(&mut text, 3), vec!["ab ", "c", "", "de"]);
+    }
+
+    fn text_spans(text: &KataText) -> Vec<(char, Color, Option<Color>)> {
+        text.voxels
+            .iter()
+            .map(|v| (v.char_offset as u8 as char, v.foreground, v.background))
+            .collect()
+    }
+
+    #[test]
+    fn test_text_markup() {
+        use color::{BLUE, RED, WHITE};
+
+        assert_eq!(
+            text_spans(&KataText::from_markup("a{red}b{blue:red}c{/}d{/}e")),
+            vec![
+                ('a', WHITE, None),
+                ('b', RED, None),
+                ('c', BLUE, Some(RED)),
+                ('d', RED, None),
+                ('e', WHITE, None),
+            ]
+        );
+
+        // Tags that are never closed last until the end
+        assert_eq!(
+            text_spans(&KataText::from_markup("{red}ab")),
+            vec![('a', RED, None), ('b', RED, None)]
+        );
+
+        // Stray closing tags are ignored
+        assert_eq!(
+            text_spans(&KataText::from_markup("{/}a")),
+            vec![('a', WHITE, None)]
+        );
+
+        // Unterminated and unknown tags are plain text
+        for s in &["{red", "{red:", "{nope}", "{red:nope}", "{}", "{{"] {
+            let expected = if *s == "{{" { "{" } else { *s };
+
+            assert_eq!(
+                text_spans(&KataText::from_markup(s)),
+                expected
+                    .chars()
+                    .map(|c| (c, WHITE, None))
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        assert_eq!(
+            text_spans(&KataText::from_markup("{red}{{x}")),
+            vec![('{', RED, None), ('x', RED, None), ('}', RED, None)]
+        );
+
+        // Non-ASCII characters still become a square
+        assert_eq!(
+            KataText::from_markup("{green}\u{e9}").voxels[0].char_offset,
+            0x082D
+        );
+    }
+
+    #[test]
+    fn test_render_text_wrap() {
+        assert_render(
+            &mut KataText::from_str("the quick brown fox"),
+            Size::new(10, 3),
+            &["the quick ", "brown fox ", "          "],
+        );
+        assert_render(
+            &mut KataText::from_str("the quick brown fox").with_wrap(WrapMode::Chars),
+            Size::new(6, 4),
+            &["the qu", "ick br", "own fo", "x     "],
+        );
+    }
+}
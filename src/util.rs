@@ -1,11 +1,19 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use failure::{format_err, Fallible};
 use lazy_static::lazy_static;
 use na::*;
 use ndarray::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{constants::*, rendering::tile::Tile};
 
 lazy_static! {
-    pub static ref MAX_LOOKUP_RANGE: usize = (LIGHT_RANGE + 1).max(PLAYER_SIGHT_RANGE + 1);
+    pub static ref MAX_LOOKUP_RANGE: usize = MAX_CAST_RANGE + 1;
     pub static ref ATAN_CASTING_LOOKUP: Array2<f32> =
         Array2::from_shape_fn((*MAX_LOOKUP_RANGE, *MAX_LOOKUP_RANGE), |(x, y)| (x as f32
             / (y + 1) as f32)
@@ -36,3 +44,83 @@ fn _get_cube_points(pos: Point3<f32>) -> Vec<Point3<f32>> {
         Point3::new(pos.x + 0.9, pos.y + 0.9, pos.z + 0.9),
     ]
 }
+
+/// Reads `path` as JSON, or gives the default if there's no file there.
+pub fn try_load<T, P>(path: P) -> Fallible<T>
+where
+    T: DeserializeOwned + Default,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if path.is_file() {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format_err!("{} isn't valid: {}", path.display(), e))
+    } else {
+        Ok(T::default())
+    }
+}
+
+/// Like `try_load`, but if the file is there and isn't valid JSON this gives the default along
+/// with what's wrong with it, so the caller can deal with the file before it's overwritten.
+pub fn try_load_lenient<T, P>(path: P) -> Fallible<(T, Option<serde_json::Error>)>
+where
+    T: DeserializeOwned + Default,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if path.is_file() {
+        let json = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read {}: {}", path.display(), e))?;
+        match serde_json::from_str(&json) {
+            Ok(value) => Ok((value, None)),
+            Err(e) => Ok((T::default(), Some(e))),
+        }
+    } else {
+        Ok((T::default(), None))
+    }
+}
+
+/// Moves the file at `path` out of the way, next to where it was with the time added to its
+/// name, and returns where it went.
+pub fn back_up_file<P>(path: P) -> Fallible<PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format_err!("{} isn't a file", path.display()))?;
+
+    let backup_path = path.with_file_name(format!(
+        "{}.{}.bak",
+        file_name.to_string_lossy(),
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    fs::rename(path, &backup_path).map_err(|e| {
+        format_err!(
+            "Failed to move {} to {}: {}",
+            path.display(),
+            backup_path.display(),
+            e
+        )
+    })?;
+
+    Ok(backup_path)
+}
+
+/// Writes `value` as pretty printed JSON. It goes to a temporary file first, so a failed write
+/// leaves the old file as it was.
+pub fn write_json<T, P>(path: P, value: &T) -> Fallible<()>
+where
+    T: Serialize,
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(value)?)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use internship::IStr;
+use log::warn;
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{rendering::tile::TileType, util::try_load};
+
+/// Where the editor saves weapon models and the game loads them from.
+pub const WEAPONS_PATH: &str = "weapons.json";
+/// What the editor's voxels are matched to tiles by, as they are for stamps.
+pub const TILE_MAPPING_PATH: &str = "tile_mapping.json";
+/// The weapon that's there even without a `weapons.json`, and that's given when none is named.
+pub const DEFAULT_WEAPON: &str = "rifle";
+
+/// One cell of a weapon model: a tile by name, e.g. `"Barrel"`, or a voxel from the editor that's
+/// matched to a tile by `tile_mapping.json`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum WeaponCell {
+    Tile(TileType),
+    Voxel(IStr),
+}
+
+impl WeaponCell {
+    /// The cell painted with `name` in the editor, which is air if there's nothing there.
+    pub fn from_name(name: Option<&IStr>) -> Self {
+        match name {
+            Some(name) => serde_json::from_value(Value::String(String::from(&**name)))
+                .unwrap_or_else(|_| WeaponCell::Voxel(name.clone())),
+            None => WeaponCell::Tile(TileType::Air),
+        }
+    }
+
+    /// What the cell is painted with in the editor, which is nothing for air.
+    pub fn name(&self) -> Option<IStr> {
+        match self {
+            WeaponCell::Tile(TileType::Air) => None,
+            WeaponCell::Tile(tile_type) => match serde_json::to_value(tile_type) {
+                Ok(Value::String(name)) => Some(IStr::new(&name)),
+                _ => None,
+            },
+            WeaponCell::Voxel(name) => Some(name.clone()),
+        }
+    }
+
+    /// The tile the cell is held as, or `None` for a voxel that `mapping` has no tile for.
+    pub fn tile(&self, mapping: &BTreeMap<IStr, TileType>) -> Option<TileType> {
+        match self {
+            WeaponCell::Tile(tile_type) => Some(*tile_type),
+            WeaponCell::Voxel(name) => mapping.get(name).copied(),
+        }
+    }
+}
+
+/// Every weapon model by name, as kept in `weapons.json`. Each is a list of rows from the top,
+/// with the muzzle on the left.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct WeaponModels {
+    pub models: BTreeMap<IStr, Vec<Vec<WeaponCell>>>,
+}
+
+impl Default for WeaponModels {
+    /// Just the rifle the game started out with.
+    fn default() -> Self {
+        use TileType::*;
+
+        let rows = vec![
+            vec![
+                Air, Air, FrontSight, Air, Air, Air, Air, RearSight, Air, Air, Air,
+            ],
+            vec![
+                BarrelEnd, BarrelEnd, GasBlock, Barrel, Barrel, RecLower, RecLower, RecLower, Air,
+                StockUpper, StockUpper,
+            ],
+            vec![
+                Air, Air, Air, Air, Air, Air, Magazine, Grip, Stock, Stock, Stock,
+            ],
+        ];
+
+        let rifle: Vec<Vec<WeaponCell>> = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(WeaponCell::Tile).collect())
+            .collect();
+
+        let mut models = BTreeMap::new();
+        models.insert(IStr::new(DEFAULT_WEAPON), rifle);
+        Self { models }
+    }
+}
+
+/// The weapon models turned into tiles, ready to be held.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weapons {
+    models: BTreeMap<IStr, Array2<TileType>>,
+}
+
+impl Weapons {
+    /// Loads `weapons.json`, or just the default weapon when it's missing or can't be read.
+    pub fn load() -> Self {
+        let mapping = try_load(TILE_MAPPING_PATH).unwrap_or_else(|e| {
+            warn!("Failed to load {}: {}", TILE_MAPPING_PATH, e);
+            BTreeMap::new()
+        });
+
+        let models = try_load(WEAPONS_PATH).unwrap_or_else(|e| {
+            warn!("Failed to load {}: {}", WEAPONS_PATH, e);
+            WeaponModels::default()
+        });
+
+        Self::new(&models, &mapping)
+    }
+
+    /// Turns `models` into tiles, using `mapping` for the voxels. Rows that are shorter than the
+    /// rest and voxels without a tile are left as air.
+    pub fn new(models: &WeaponModels, mapping: &BTreeMap<IStr, TileType>) -> Self {
+        let mut unmapped = BTreeSet::new();
+
+        let models = models
+            .models
+            .iter()
+            .map(|(name, rows)| {
+                let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+                let tiles = Array2::from_shape_fn((rows.len(), width), |(y, x)| {
+                    let cell = match rows[y].get(x) {
+                        Some(cell) => cell,
+                        None => return TileType::Air,
+                    };
+
+                    cell.tile(mapping).unwrap_or_else(|| {
+                        unmapped.extend(cell.name());
+                        TileType::Air
+                    })
+                });
+
+                (name.clone(), tiles)
+            })
+            .collect();
+
+        if !unmapped.is_empty() {
+            let unmapped: Vec<&str> = unmapped.iter().map(|name: &IStr| &**name).collect();
+            warn!(
+                "{} has no tiles for {}, which are left empty",
+                TILE_MAPPING_PATH,
+                unmapped.join(", ")
+            );
+        }
+
+        Self { models }
+    }
+
+    /// The model of the weapon called `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&Array2<TileType>> {
+        self.models.get(&IStr::new(name))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &IStr> {
+        self.models.keys()
+    }
+
+    /// The weapon after `current` by name, going back around to the first after the last. Without
+    /// `current` it's the default weapon, or the first if that's gone.
+    pub fn next(&self, current: Option<&str>) -> Option<&IStr> {
+        match current {
+            Some(current) => self
+                .names()
+                .find(|&name| &**name > current)
+                .or_else(|| self.names().next()),
+            None => self
+                .names()
+                .find(|&name| &**name == DEFAULT_WEAPON)
+                .or_else(|| self.names().next()),
+        }
+    }
+}
+
+impl Default for Weapons {
+    fn default() -> Self {
+        Self::new(&WeaponModels::default(), &BTreeMap::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn models(json: &str) -> WeaponModels {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_default_rifle() {
+        let weapons = Weapons::default();
+        let rifle = weapons.get(DEFAULT_WEAPON).unwrap();
+
+        assert_eq!(rifle.dim(), (3, 11));
+        assert_eq!(rifle[[0, 2]], TileType::FrontSight);
+        assert_eq!(rifle[[1, 0]], TileType::BarrelEnd);
+        assert_eq!(rifle[[2, 10]], TileType::Stock);
+
+        // It's written out as it's read back in
+        let json = serde_json::to_string(&WeaponModels::default()).unwrap();
+        assert!(json.starts_with(r#"{"rifle":[["Air","Air","FrontSight""#));
+        assert_eq!(models(&json), WeaponModels::default());
+    }
+
+    #[test]
+    fn test_weapon_cells() {
+        let models = models(r#"{ "pistol": [["Barrel", "slide", "grip"], ["Air"]] }"#);
+        let rows = &models.models[&IStr::new("pistol")];
+        assert_eq!(rows[0][0], WeaponCell::Tile(TileType::Barrel));
+        assert_eq!(rows[0][1], WeaponCell::Voxel(IStr::new("slide")));
+
+        for cell in rows.iter().flatten() {
+            assert_eq!(&WeaponCell::from_name(cell.name().as_ref()), cell);
+        }
+
+        let mut mapping = BTreeMap::new();
+        mapping.insert(IStr::new("grip"), TileType::Grip);
+        let weapons = Weapons::new(&models, &mapping);
+        let pistol = weapons.get("pistol").unwrap();
+
+        // The unmapped voxel and the end of the short row are left empty
+        assert_eq!(
+            *pistol,
+            arr2(&[
+                [TileType::Barrel, TileType::Air, TileType::Grip],
+                [TileType::Air, TileType::Air, TileType::Air],
+            ])
+        );
+    }
+
+    #[test]
+    fn test_next_weapon() {
+        let models = models(r#"{ "pistol": [["Barrel"]], "shotgun": [], "rifle": [] }"#);
+        let weapons = Weapons::new(&models, &BTreeMap::new());
+        let next = |current| weapons.next(current).map(|name| String::from(&**name));
+
+        assert_eq!(next(None).as_deref(), Some(DEFAULT_WEAPON));
+        assert_eq!(next(Some("pistol")).as_deref(), Some("rifle"));
+        assert_eq!(next(Some("shotgun")).as_deref(), Some("pistol"));
+        // A model that's gone still has a place to carry on from
+        assert_eq!(next(Some("revolver")).as_deref(), Some("rifle"));
+
+        let empty = Weapons::new(&models(r#"{}"#), &BTreeMap::new());
+        assert_eq!(empty.next(None), None);
+        assert_eq!(empty.get(DEFAULT_WEAPON), None);
+    }
+}
@@ -1,4 +1,12 @@
-pub mod chunk;
+pub mod debug;
+pub mod dirty;
 pub mod entity;
+pub mod explored;
 pub mod gameworld;
+pub mod regions;
+pub mod save;
+pub mod sound;
+pub mod stamp;
 pub mod util;
+
+pub use gameworld::{DecalType, TileMetadata, World};
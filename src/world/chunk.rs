@@ -1,2 +0,0 @@
-#[derive(Default)]
-pub struct Chunk {}
@@ -0,0 +1,147 @@
+use std::{fs, path::Path};
+
+use failure::{format_err, Fallible};
+use na::Point3;
+use ndarray::prelude::*;
+
+use crate::{
+    generation::world::{generate_chunk, ChunkGenPackage},
+    rendering::tile::{Tile, TileType},
+    settings::Settings,
+};
+
+/// The character a tile is shown as in text dumps.
+pub fn tile_char(tile_type: TileType) -> char {
+    match tile_type {
+        TileType::Air => '.',
+        TileType::Rock0
+        | TileType::Rock1
+        | TileType::Rock2
+        | TileType::Rock3
+        | TileType::Rock4
+        | TileType::Rock5
+        | TileType::Rock6
+        | TileType::Rock7 => '#',
+        TileType::Mushroom => '"',
+        TileType::Candle => 'i',
+        TileType::Glowstick => '*',
+        TileType::Crystal => '%',
+        TileType::Lava => '~',
+        TileType::DoorClosed => '+',
+        TileType::DoorOpen => '\'',
+        TileType::Lever => '!',
+        TileType::Artifact => '$',
+        TileType::Extraction => 'x',
+        // Parts of the gun, which shouldn't turn up in the world
+        TileType::FrontSight
+        | TileType::RearSight
+        | TileType::Barrel
+        | TileType::BarrelEnd
+        | TileType::GasBlock
+        | TileType::RecUpper
+        | TileType::RecLower
+        | TileType::RecLowerHalf
+        | TileType::RecLowerBack
+        | TileType::Magazine
+        | TileType::Stock
+        | TileType::StockUpper
+        | TileType::Grip => '=',
+    }
+}
+
+/// The tiles at height `y` as text, with a line for each z and a character for each x.
+///
+/// Panics if `y` is outside `tiles`.
+pub fn slice_to_string(tiles: ArrayView3<Tile>, y: usize) -> String {
+    let slice = tiles.index_axis(Axis(1), y);
+    let (width, depth) = slice.dim();
+    let mut text = String::with_capacity((width + 1) * depth);
+
+    for z in 0..depth {
+        text.extend((0..width).map(|x| tile_char(slice[[x, z]].tile_type)));
+        text.push('\n');
+    }
+
+    text
+}
+
+/// The slices at each of `y_levels`, each under a line saying which it is.
+pub fn slices_to_string(tiles: ArrayView3<Tile>, y_levels: &[usize]) -> String {
+    y_levels
+        .iter()
+        .map(|&y| format!("y = {}\n{}", y, slice_to_string(tiles.view(), y)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Generates a chunk from `seed` without a window and dumps the slices at `y_levels`, or at
+/// every height if there are none. They're printed, or written to `out_dir` as
+/// `seed_<seed>_y_<y>.txt` if it's given.
+pub fn dump_slices(
+    settings: &Settings,
+    seed: u64,
+    y_levels: &[usize],
+    out_dir: Option<&Path>,
+) -> Fallible<()> {
+    let package = ChunkGenPackage::from_seed(seed)
+        .with_noise_scales(settings.noise_scale, settings.noise_weight_scale)
+        .with_chunk_size(settings.chunk_size);
+    let tiles = generate_chunk(Point3::origin(), &package);
+
+    let height = tiles.dim().1;
+    let all_levels: Vec<_> = (0..height).collect();
+    let y_levels = if y_levels.is_empty() {
+        &all_levels[..]
+    } else {
+        y_levels
+    };
+
+    if let Some(&y) = y_levels.iter().find(|&&y| y >= height) {
+        return Err(format_err!(
+            "There's no y = {} in a chunk {} tiles high",
+            y,
+            height
+        ));
+    }
+
+    match out_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)?;
+            for &y in y_levels {
+                let path = dir.join(format!("seed_{}_y_{}.txt", seed, y));
+                fs::write(&path, slice_to_string(tiles.view(), y))
+                    .map_err(|e| format_err!("Failed to write {}: {}", path.display(), e))?;
+            }
+        }
+        None => print!("{}", slices_to_string(tiles.view(), y_levels)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::tile::tiles_from_fn;
+
+    fn tiles() -> Array3<Tile> {
+        tiles_from_fn((3, 2, 2), |(x, y, z)| match (x, y, z) {
+            (0, 0, _) => TileType::Rock3,
+            (2, 0, 1) => TileType::Mushroom,
+            (1, 1, 0) => TileType::Glowstick,
+            _ => TileType::Air,
+        })
+    }
+
+    #[test]
+    fn test_slice_to_string() {
+        let tiles = tiles();
+
+        assert_eq!(slice_to_string(tiles.view(), 0), "#..\n#.\"\n");
+        assert_eq!(slice_to_string(tiles.view(), 1), ".*.\n...\n");
+        assert_eq!(
+            slices_to_string(tiles.view(), &[1, 0]),
+            "y = 1\n.*.\n...\n\ny = 0\n#..\n#.\"\n"
+        );
+    }
+}
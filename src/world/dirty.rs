@@ -0,0 +1,197 @@
+use crate::geometry::aabb::IAabb3;
+
+/// How many regions each consumer keeps before the nearest ones get merged.
+pub const DEFAULT_REGION_CAP: usize = 32;
+
+/// Identifies one of the systems draining a `DirtyRegions`, from `DirtyRegions::register`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DirtyConsumer(usize);
+
+/// Keeps track of which cells have changed for each system that cares, e.g. lighting and
+/// saving, so that each can catch up in its own time. Changes next to each other are merged
+/// into one box, and once a consumer has more than `cap` boxes the two nearest are merged, so
+/// the boxes can end up covering cells that didn't change.
+pub struct DirtyRegions {
+    cap: usize,
+    pending: Vec<Vec<IAabb3>>,
+}
+
+impl Default for DirtyRegions {
+    fn default() -> Self {
+        Self::new(DEFAULT_REGION_CAP)
+    }
+}
+
+impl DirtyRegions {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap: cap.max(1),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Adds a consumer, which only sees changes made from now on.
+    pub fn register(&mut self) -> DirtyConsumer {
+        self.pending.push(Vec::new());
+        DirtyConsumer(self.pending.len() - 1)
+    }
+
+    /// Records that everything in `region` has changed.
+    pub fn mark(&mut self, region: IAabb3) {
+        if region.is_empty() {
+            return;
+        }
+
+        for regions in self.pending.iter_mut() {
+            add_region(regions, region, self.cap);
+        }
+    }
+
+    /// The regions changed since `consumer` last took them.
+    pub fn pending(&self, consumer: DirtyConsumer) -> &[IAabb3] {
+        &self.pending[consumer.0]
+    }
+
+    /// Takes the regions changed since `consumer` last took them, leaving the other consumers'
+    /// alone.
+    pub fn take(&mut self, consumer: DirtyConsumer) -> Vec<IAabb3> {
+        std::mem::take(&mut self.pending[consumer.0])
+    }
+}
+
+fn add_region(regions: &mut Vec<IAabb3>, region: IAabb3, cap: usize) {
+    let mut region = region;
+
+    // Merging can make the region touch others it didn't before, so keep going until it doesn't
+    while let Some(i) = regions
+        .iter()
+        .position(|r| r.outset(1).intersect(&region).is_some())
+    {
+        region = region.union(&regions.swap_remove(i));
+    }
+
+    regions.push(region);
+
+    while regions.len() > cap {
+        let (i, j) = nearest_pair(regions);
+        let merged = regions[i].union(&regions[j]);
+        regions.swap_remove(j);
+        regions[i] = merged;
+    }
+}
+
+/// The indices of the two regions with the smallest gap between them, with the first lower.
+fn nearest_pair(regions: &[IAabb3]) -> (usize, usize) {
+    let mut nearest = (0, 1);
+    let mut nearest_gap = i32::MAX;
+
+    for i in 0..regions.len() {
+        for j in (i + 1)..regions.len() {
+            let g = gap(&regions[i], &regions[j]);
+
+            if g < nearest_gap {
+                nearest = (i, j);
+                nearest_gap = g;
+            }
+        }
+    }
+
+    nearest
+}
+
+/// How many cells apart two boxes are along the axis where they're furthest apart.
+fn gap(a: &IAabb3, b: &IAabb3) -> i32 {
+    (0..3)
+        .map(|i| (a.min[i] - b.max[i]).max(b.min[i] - a.max[i]).max(0))
+        .max()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use na::Point3;
+
+    use super::*;
+
+    fn cell(x: i32, y: i32, z: i32) -> IAabb3 {
+        IAabb3::from_center_radius(Point3::new(x, y, z), 0)
+    }
+
+    #[test]
+    fn test_merge_touching() {
+        let mut dirty = DirtyRegions::default();
+        let consumer = dirty.register();
+
+        dirty.mark(cell(0, 0, 0));
+        dirty.mark(cell(1, 0, 0));
+        dirty.mark(cell(2, 1, 0));
+        assert_eq!(
+            dirty.pending(consumer),
+            &[IAabb3::new(Point3::new(0, 0, 0), Point3::new(3, 2, 1))]
+        );
+
+        // Apart from the first, until something joins them up
+        dirty.mark(cell(4, 0, 0));
+        assert_eq!(dirty.pending(consumer).len(), 2);
+        dirty.mark(cell(3, 0, 0));
+        assert_eq!(
+            dirty.pending(consumer),
+            &[IAabb3::new(Point3::new(0, 0, 0), Point3::new(5, 2, 1))]
+        );
+
+        dirty.mark(IAabb3::new(Point3::new(9, 9, 9), Point3::new(9, 10, 10)));
+        assert_eq!(dirty.pending(consumer).len(), 1);
+    }
+
+    #[test]
+    fn test_consumers_take_separately() {
+        let mut dirty = DirtyRegions::default();
+        let lighting = dirty.register();
+
+        dirty.mark(cell(0, 0, 0));
+        let saving = dirty.register();
+        dirty.mark(cell(8, 8, 8));
+
+        assert_eq!(dirty.take(lighting), vec![cell(0, 0, 0), cell(8, 8, 8)]);
+        assert!(dirty.pending(lighting).is_empty());
+        assert_eq!(dirty.pending(saving), &[cell(8, 8, 8)]);
+
+        dirty.mark(cell(4, 4, 4));
+        assert_eq!(dirty.take(lighting), vec![cell(4, 4, 4)]);
+        assert_eq!(dirty.take(saving), vec![cell(8, 8, 8), cell(4, 4, 4)]);
+        assert!(dirty.take(saving).is_empty());
+    }
+
+    #[test]
+    fn test_cap_merges_nearest() {
+        let mut dirty = DirtyRegions::new(2);
+        let consumer = dirty.register();
+
+        dirty.mark(cell(0, 0, 0));
+        dirty.mark(cell(20, 0, 0));
+        dirty.mark(cell(3, 0, 0));
+
+        let mut regions = dirty.take(consumer);
+        regions.sort_by_key(|r| r.min.x);
+        assert_eq!(
+            regions,
+            vec![
+                IAabb3::new(Point3::new(0, 0, 0), Point3::new(4, 1, 1)),
+                cell(20, 0, 0),
+            ]
+        );
+
+        for x in 0..10 {
+            dirty.mark(cell(x * 3, x * 3, 0));
+            assert!(dirty.pending(consumer).len() <= 2);
+        }
+
+        // However they were merged, every changed cell is still covered
+        for x in 0..10 {
+            assert!(dirty
+                .pending(consumer)
+                .iter()
+                .any(|r| r.contains(Point3::new(x * 3, x * 3, 0))));
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use ndarray::prelude::*;
+
+use crate::rendering::fov::VisibleCell;
+
+/// Which cells the player has seen so far, so that the minimap only shows what they know.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explored {
+    seen: Array3<bool>,
+}
+
+impl Explored {
+    /// Nothing seen yet, in a world with `dim` tiles.
+    pub fn new(dim: (usize, usize, usize)) -> Self {
+        Self {
+            seen: Array3::from_elem(dim, false),
+        }
+    }
+
+    pub fn mark_visible(&mut self, visible: &[VisibleCell]) {
+        for cell in visible {
+            if let Some(seen) = self.seen.get_mut(cell.index()) {
+                *seen = true;
+            }
+        }
+    }
+
+    /// Whether the cell at `index` has been seen. Cells outside the world never have been.
+    pub fn is_explored(&self, index: [usize; 3]) -> bool {
+        self.seen.get(index).copied().unwrap_or(false)
+    }
+
+    /// How many cells have been seen.
+    pub fn count(&self) -> usize {
+        self.seen.iter().filter(|&&seen| seen).count()
+    }
+}
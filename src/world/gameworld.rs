@@ -1 +1,487 @@
+use std::collections::{HashMap, VecDeque};
 
+use ggez::graphics::Color;
+use na::Point3;
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{CHUNK_SIZE, DECALS_PER_CHUNK},
+    geometry::{aabb::IAabb3, util::*},
+    rendering::tile::*,
+    world::dirty::{DirtyConsumer, DirtyRegions},
+};
+
+/// A mark left on a tile, drawn over its glyph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DecalType {
+    /// Where a shot hit rock.
+    BulletPock,
+    /// Where something burnt.
+    Scorch,
+}
+
+impl DecalType {
+    pub const ALL: [DecalType; 2] = [DecalType::BulletPock, DecalType::Scorch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DecalType::BulletPock => "pock",
+            DecalType::Scorch => "scorch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|decal| decal.name() == name)
+    }
+
+    /// The glyph drawn over the tile's own.
+    pub fn glyph(self) -> u16 {
+        match self {
+            DecalType::BulletPock => 0x07,
+            DecalType::Scorch => 0xB0,
+        }
+    }
+}
+
+/// What a tile needs to know beyond its type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct TileMetadata {
+    /// The door a lever opens and closes.
+    #[serde(default)]
+    pub target: Option<[i32; 3]>,
+    /// Should only be changed through `World::add_decal`, so that the oldest can be cleared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decal: Option<DecalType>,
+}
+
+/// Everything in the world: the tiles, the lights placed in it and a record of what's changed.
+pub struct World {
+    /// The loaded tiles, which is a single chunk until chunks can be streamed in.
+    chunks: Array3<Tile>,
+    lights: Vec<(Point3<usize>, Color)>,
+    /// Kept by position, so it stays with a tile when the tile's type changes.
+    metadata: HashMap<Point3<i32>, TileMetadata>,
+    /// Where the decals in each chunk are, oldest first, by which chunk it is.
+    decals: HashMap<Point3<i32>, VecDeque<Point3<i32>>>,
+    /// Goes up every time a tile changes.
+    revision: u64,
+    dirty: DirtyRegions,
+}
+
+impl World {
+    pub fn new(chunks: Array3<Tile>, lights: Vec<(Point3<usize>, Color)>) -> Self {
+        Self {
+            chunks,
+            lights,
+            metadata: HashMap::new(),
+            decals: HashMap::new(),
+            revision: 0,
+            dirty: DirtyRegions::default(),
+        }
+    }
+
+    pub fn bounds(&self) -> IAabb3 {
+        IAabb3::from_dim(self.chunks.dim())
+    }
+
+    /// The tile at `pos`, or `None` if it's outside the world.
+    pub fn tile(&self, pos: Point3<i32>) -> Option<&Tile> {
+        try_to_usize_point(pos, self.chunks.dim()).map(|i| &self.chunks[[i.x, i.y, i.z]])
+    }
+
+    /// Changes the type of the tile at `pos` and marks it dirty. Returns false and does nothing
+    /// if `pos` is outside the world.
+    pub fn set_tile(&mut self, pos: Point3<i32>, tile_type: TileType) -> bool {
+        let index = match try_to_usize_point(pos, self.chunks.dim()) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        self.chunks[[index.x, index.y, index.z]].tile_type = tile_type;
+        self.revision += 1;
+        self.dirty.mark(IAabb3::from_center_radius(pos, 0));
+
+        true
+    }
+
+    pub fn metadata(&self, pos: Point3<i32>) -> Option<&TileMetadata> {
+        self.metadata.get(&pos)
+    }
+
+    /// Every tile's metadata, in no particular order.
+    pub fn all_metadata(&self) -> impl Iterator<Item = (Point3<i32>, &TileMetadata)> {
+        self.metadata.iter().map(|(&pos, metadata)| (pos, metadata))
+    }
+
+    /// Gives the tile at `pos` `metadata`, or takes its metadata away if it's `None`. Returns
+    /// false and does nothing if `pos` is outside the world.
+    pub fn set_metadata(&mut self, pos: Point3<i32>, metadata: Option<TileMetadata>) -> bool {
+        if self.tile(pos).is_none() {
+            return false;
+        }
+
+        match metadata {
+            Some(metadata) => self.metadata.insert(pos, metadata),
+            None => self.metadata.remove(&pos),
+        };
+
+        true
+    }
+
+    /// Leaves `decal` on the tile at `pos`, in place of any it had. Once a chunk has
+    /// `DECALS_PER_CHUNK` of them the oldest is cleared to make room. Returns false and does
+    /// nothing if `pos` is outside the world.
+    pub fn add_decal(&mut self, pos: Point3<i32>, decal: DecalType) -> bool {
+        if self.tile(pos).is_none() {
+            return false;
+        }
+
+        let chunk = pos.map(|c| c.div_euclid(CHUNK_SIZE as i32));
+        let decals = self.decals.entry(chunk).or_default();
+        decals.retain(|&other| other != pos);
+        if decals.len() == DECALS_PER_CHUNK {
+            if let Some(oldest) = decals.pop_front() {
+                if let Some(metadata) = self.metadata.get_mut(&oldest) {
+                    metadata.decal = None;
+                    if *metadata == TileMetadata::default() {
+                        self.metadata.remove(&oldest);
+                    }
+                }
+            }
+        }
+        decals.push_back(pos);
+        self.metadata.entry(pos).or_default().decal = Some(decal);
+
+        true
+    }
+
+    /// Every decal, chunk by chunk and oldest first within each, so that adding them to another
+    /// world in this order clears the same ones first.
+    pub fn decals(&self) -> Vec<(Point3<i32>, DecalType)> {
+        let mut chunks: Vec<_> = self.decals.iter().collect();
+        chunks.sort_unstable_by_key(|&(chunk, _)| [chunk.x, chunk.y, chunk.z]);
+
+        chunks
+            .into_iter()
+            .flat_map(|(_, decals)| decals)
+            .filter_map(|&pos| Some((pos, self.metadata(pos)?.decal?)))
+            .collect()
+    }
+
+    /// Opens or closes the door at `pos`, or the one the lever at `pos` is connected to. The
+    /// door is changed with `set_tile`, so the lighting around it is worked out again. Returns
+    /// whether a door was opened or closed.
+    pub fn interact(&mut self, pos: Point3<i32>) -> bool {
+        let door = match self.tile(pos).map(|tile| tile.tile_type) {
+            Some(TileType::Lever) => match self.metadata(pos).and_then(|m| m.target) {
+                Some(target) => Point3::from(target),
+                None => return false,
+            },
+            _ => pos,
+        };
+
+        let toggled = match self.tile(door).map(|tile| tile.tile_type) {
+            Some(TileType::DoorClosed) => TileType::DoorOpen,
+            Some(TileType::DoorOpen) => TileType::DoorClosed,
+            _ => return false,
+        };
+
+        self.set_tile(door, toggled)
+    }
+
+    /// All of the tiles, e.g. for the helpers in `world::util`.
+    pub fn tiles(&self) -> &Array3<Tile> {
+        &self.chunks
+    }
+
+    /// All of the tiles, for changes that don't count as changing the world, like lighting.
+    /// Anything else should go through `set_tile` so that it's marked dirty.
+    pub fn tiles_mut(&mut self) -> &mut Array3<Tile> {
+        &mut self.chunks
+    }
+
+    /// The tiles in `region`, cut down to the part of it inside the world.
+    pub fn view_region(&self, region: &IAabb3) -> ArrayView3<Tile> {
+        let [xs, ys, zs] = region.clamp_to(&self.bounds()).ranges();
+        self.chunks.slice(s![xs, ys, zs])
+    }
+
+    pub fn lights(&self) -> &[(Point3<usize>, Color)] {
+        &self.lights
+    }
+
+    /// Takes away the lights placed at `pos`, e.g. when whatever was giving them off is taken.
+    /// Returns whether there were any.
+    pub fn remove_light(&mut self, pos: Point3<usize>) -> bool {
+        let before = self.lights.len();
+        self.lights.retain(|&(light_pos, _)| light_pos != pos);
+
+        self.lights.len() != before
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Starts keeping track of changes for something that needs to catch up with them.
+    pub fn register_dirty_consumer(&mut self) -> DirtyConsumer {
+        self.dirty.register()
+    }
+
+    pub fn dirty_regions(&self) -> &DirtyRegions {
+        &self.dirty
+    }
+
+    /// Takes the parts of the world that have changed since `consumer` last took them.
+    pub fn take_dirty_regions(&mut self, consumer: DirtyConsumer) -> Vec<IAabb3> {
+        self.dirty.take(consumer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constants::LIGHT_RANGE,
+        rendering::lighting::{compute_lighting, ShadowcastScratch},
+        world::util::{clip_velocity, collides_at},
+    };
+    use na::Vector3;
+
+    fn world(size: usize) -> World {
+        let tiles = tiles_from_fn((size, size, size), |_| TileType::Air);
+
+        World::new(tiles, vec![(Point3::new(1, 1, 1), Color::WHITE)])
+    }
+
+    #[test]
+    fn test_tile_at_boundaries() {
+        let world = world(4);
+
+        assert_eq!(
+            world.tile(Point3::new(0, 0, 0)).map(|t| t.pos),
+            Some(Point3::new(0.0, 0.0, 0.0))
+        );
+        assert_eq!(
+            world.tile(Point3::new(3, 3, 3)).map(|t| t.pos),
+            Some(Point3::new(3.0, 3.0, 3.0))
+        );
+        assert!(world.tile(Point3::new(4, 0, 0)).is_none());
+        assert!(world.tile(Point3::new(0, 4, 0)).is_none());
+        assert!(world.tile(Point3::new(0, 0, -1)).is_none());
+        assert_eq!(world.bounds(), IAabb3::from_dim((4, 4, 4)));
+        assert_eq!(world.lights(), &[(Point3::new(1, 1, 1), Color::WHITE)]);
+    }
+
+    #[test]
+    fn test_set_tile_marks_dirty() {
+        let mut world = world(4);
+        let consumer = world.register_dirty_consumer();
+        assert_eq!(world.revision(), 0);
+
+        assert!(!world.set_tile(Point3::new(-1, 0, 0), TileType::Rock0));
+        assert!(!world.set_tile(Point3::new(0, 0, 4), TileType::Rock0));
+        assert_eq!(world.revision(), 0);
+        assert!(world.dirty_regions().pending(consumer).is_empty());
+
+        assert!(world.set_tile(Point3::new(3, 3, 3), TileType::Rock0));
+        assert_eq!(
+            world.tile(Point3::new(3, 3, 3)).map(|t| t.tile_type),
+            Some(TileType::Rock0)
+        );
+        assert_eq!(world.revision(), 1);
+
+        assert!(world.set_tile(Point3::new(2, 3, 3), TileType::Rock0));
+        assert!(world.set_tile(Point3::new(0, 0, 0), TileType::Rock0));
+        assert_eq!(world.revision(), 3);
+
+        assert_eq!(
+            world.take_dirty_regions(consumer),
+            vec![
+                IAabb3::new(Point3::new(2, 3, 3), Point3::new(4, 4, 4)),
+                IAabb3::from_center_radius(Point3::new(0, 0, 0), 0),
+            ]
+        );
+        assert!(world.take_dirty_regions(consumer).is_empty());
+        assert_eq!(world.revision(), 3);
+    }
+
+    #[test]
+    fn test_view_region() {
+        let world = world(4);
+
+        let view = world.view_region(&IAabb3::from_center_radius(Point3::new(0, 3, 1), 1));
+        assert_eq!(view.dim(), (2, 2, 3));
+        assert_eq!(view[[0, 0, 0]].pos, Point3::new(0.0, 2.0, 0.0));
+
+        let outside = IAabb3::new(Point3::new(5, 5, 5), Point3::new(7, 7, 7));
+        assert_eq!(world.view_region(&outside).len(), 0);
+
+        assert_eq!(world.view_region(&world.bounds()).dim(), (4, 4, 4));
+    }
+
+    /// A 9x9x9 room with a wall across it at x = 3, and a door in the wall at (3, 4, 4).
+    fn walled_world() -> World {
+        let mut world = world(9);
+        for y in 0..9 {
+            for z in 0..9 {
+                world.set_tile(Point3::new(3, y, z), TileType::Rock0);
+            }
+        }
+        world.set_tile(Point3::new(3, 4, 4), TileType::DoorClosed);
+        world
+    }
+
+    fn light_behind_door(world: &mut World) -> bool {
+        compute_lighting(
+            world.tiles_mut(),
+            &[(Point3::new(1, 4, 4), Color::WHITE)],
+            LIGHT_RANGE,
+            &mut ShadowcastScratch::default(),
+        );
+        world.tiles()[[4, 4, 4]].illuminated()
+    }
+
+    #[test]
+    fn test_door_blocks_light() {
+        let mut world = walled_world();
+        let door = Point3::new(3, 4, 4);
+        assert!(!light_behind_door(&mut world));
+
+        let consumer = world.register_dirty_consumer();
+        assert!(world.interact(door));
+        assert_eq!(
+            world.tile(door).map(|t| t.tile_type),
+            Some(TileType::DoorOpen)
+        );
+        assert_eq!(
+            world.take_dirty_regions(consumer),
+            vec![IAabb3::from_center_radius(door, 0)]
+        );
+        assert!(light_behind_door(&mut world));
+
+        assert!(world.interact(door));
+        assert!(!light_behind_door(&mut world));
+    }
+
+    #[test]
+    fn test_door_blocks_walking() {
+        let mut world = walled_world();
+        let door = Point3::new(3, 4, 4);
+        let pos = Point3::new(2.5, 4.5, 4.5);
+        let push = Vector3::new(1.0, 0.0, 0.0);
+
+        let mut vel = push;
+        clip_velocity(pos, &mut vel, world.tiles());
+        assert_eq!(vel, Vector3::zeros());
+        assert!(collides_at(pos + push, world.tiles()));
+
+        world.interact(door);
+        let mut vel = push;
+        clip_velocity(pos, &mut vel, world.tiles());
+        assert_eq!(vel, push);
+        assert!(!collides_at(pos + push, world.tiles()));
+    }
+
+    #[test]
+    fn test_lever_opens_remote_door() {
+        let mut world = walled_world();
+        let door = Point3::new(3, 4, 4);
+        let lever = Point3::new(0, 0, 0);
+
+        // Nothing to pull until it's connected, and air and rock do nothing
+        assert!(world.set_tile(lever, TileType::Lever));
+        assert!(!world.interact(lever));
+        assert!(!world.interact(Point3::new(1, 1, 1)));
+        assert!(!world.interact(Point3::new(3, 0, 0)));
+        assert!(!world.interact(Point3::new(-1, 0, 0)));
+
+        assert!(world.set_metadata(
+            lever,
+            Some(TileMetadata {
+                target: Some([3, 4, 4]),
+                ..TileMetadata::default()
+            })
+        ));
+        assert!(!world.set_metadata(Point3::new(9, 0, 0), Some(TileMetadata::default())));
+        assert!(world.interact(lever));
+        assert_eq!(
+            world.tile(door).map(|t| t.tile_type),
+            Some(TileType::DoorOpen)
+        );
+        assert_eq!(
+            world.tile(lever).map(|t| t.tile_type),
+            Some(TileType::Lever)
+        );
+
+        assert!(world.interact(lever));
+        assert_eq!(
+            world.tile(door).map(|t| t.tile_type),
+            Some(TileType::DoorClosed)
+        );
+
+        assert!(world.set_metadata(lever, None));
+        assert_eq!(world.metadata(lever), None);
+        assert!(!world.interact(lever));
+    }
+
+    #[test]
+    fn test_decals_capped_per_chunk() {
+        // Two chunks side by side along x
+        let tiles = tiles_from_fn((CHUNK_SIZE + 1, 8, 8), |_| TileType::Rock0);
+        let mut world = World::new(tiles, Vec::new());
+        let first_chunk: Vec<_> = IAabb3::from_dim((CHUNK_SIZE, 8, 8))
+            .iter_cells()
+            .take(DECALS_PER_CHUNK + 2)
+            .collect();
+        let other_chunk = Point3::new(CHUNK_SIZE as i32, 0, 0);
+        let lever = first_chunk[1];
+
+        assert!(!world.add_decal(Point3::new(-1, 0, 0), DecalType::Scorch));
+        assert!(world.add_decal(other_chunk, DecalType::Scorch));
+        world.set_metadata(
+            lever,
+            Some(TileMetadata {
+                target: Some([0, 0, 0]),
+                ..TileMetadata::default()
+            }),
+        );
+        for &pos in &first_chunk[..DECALS_PER_CHUNK] {
+            assert!(world.add_decal(pos, DecalType::BulletPock));
+        }
+        // Marking it again makes it the newest
+        world.add_decal(first_chunk[0], DecalType::Scorch);
+
+        world.add_decal(first_chunk[DECALS_PER_CHUNK], DecalType::BulletPock);
+        assert_eq!(world.metadata(lever).and_then(|m| m.decal), None);
+        assert_eq!(
+            world.metadata(lever).and_then(|m| m.target),
+            Some([0, 0, 0])
+        );
+        world.add_decal(first_chunk[DECALS_PER_CHUNK + 1], DecalType::BulletPock);
+        assert_eq!(world.metadata(first_chunk[2]), None);
+        assert_eq!(
+            world.metadata(first_chunk[0]).and_then(|m| m.decal),
+            Some(DecalType::Scorch)
+        );
+
+        let decals = world.decals();
+        assert_eq!(decals.len(), DECALS_PER_CHUNK + 1);
+        assert_eq!(decals[0], (first_chunk[3], DecalType::BulletPock));
+        assert_eq!(
+            decals[DECALS_PER_CHUNK - 3],
+            (first_chunk[0], DecalType::Scorch)
+        );
+        assert_eq!(decals[DECALS_PER_CHUNK], (other_chunk, DecalType::Scorch));
+        assert_eq!(world.all_metadata().count(), DECALS_PER_CHUNK + 2);
+    }
+
+    #[test]
+    fn test_decal_names() {
+        for &decal in &DecalType::ALL {
+            assert_eq!(DecalType::from_name(decal.name()), Some(decal));
+        }
+        assert_eq!(DecalType::from_name("footprint"), None);
+    }
+}
@@ -0,0 +1,169 @@
+use ggez::graphics::Color;
+use na::Point3;
+use ndarray::prelude::*;
+
+use crate::{
+    rendering::drawable::*,
+    world::{
+        dirty::DirtyConsumer,
+        util::{label_regions, neighbours, Adjacency},
+        World,
+    },
+};
+
+/// Splits the world into the pockets of see-through cells that light can't get between, so that
+/// lights sealed off from the player can be left out of lighting altogether. Relabelled whenever
+/// the world changes.
+pub struct SealedRegions {
+    /// The region of each see-through cell, counting up from 1, or 0 for solid ones.
+    labels: Array3<u32>,
+    dirty: DirtyConsumer,
+}
+
+impl SealedRegions {
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            labels: label(world),
+            dirty: world.register_dirty_consumer(),
+        }
+    }
+
+    /// Labels the regions again if any tiles have changed since the last time. Returns whether
+    /// they were.
+    pub fn refresh(&mut self, world: &mut World) -> bool {
+        if world.take_dirty_regions(self.dirty).is_empty() {
+            return false;
+        }
+
+        self.labels = label(world);
+        true
+    }
+
+    /// The region `pos` is in, or `None` if it's solid or outside the world.
+    pub fn region(&self, pos: Point3<usize>) -> Option<u32> {
+        match self.labels.get([pos.x, pos.y, pos.z]) {
+            Some(&label) if label != 0 => Some(label),
+            _ => None,
+        }
+    }
+
+    /// Whether a light at `pos` can shine into `region`. Lights in solid tiles, like crystals
+    /// in a wall, shine out into whichever regions touch them.
+    fn light_reaches(&self, pos: Point3<usize>, region: u32) -> bool {
+        self.region(pos) == Some(region)
+            || neighbours(pos, self.labels.dim(), Adjacency::Corner26)
+                .any(|n| self.region(n) == Some(region))
+    }
+
+    /// The lights that can reach the region `from` is in, along with how many were left out.
+    /// If `from` isn't in a region all of them are kept.
+    pub fn visible_lights(
+        &self,
+        lights: &[(Point3<usize>, Color)],
+        from: Point3<usize>,
+    ) -> (Vec<(Point3<usize>, Color)>, usize) {
+        let region = match self.region(from) {
+            Some(region) => region,
+            None => return (lights.to_vec(), 0),
+        };
+
+        let visible: Vec<_> = lights
+            .iter()
+            .filter(|&&(pos, _)| self.light_reaches(pos, region))
+            .cloned()
+            .collect();
+        let skipped = lights.len() - visible.len();
+
+        (visible, skipped)
+    }
+}
+
+fn label(world: &World) -> Array3<u32> {
+    label_regions(world.tiles().view(), Adjacency::Corner26, |tile| {
+        tile.tile_type.is_transparent()
+    })
+    .0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        constants::LIGHT_RANGE,
+        rendering::{
+            lighting::{compute_lighting, ShadowcastScratch},
+            tile::{tiles_from_fn, TileType},
+        },
+    };
+
+    /// Two rooms along x split by a rock wall at x = 4, with a light in the one on the right.
+    fn world() -> World {
+        let tiles = tiles_from_fn((9, 3, 3), |(x, _, _)| {
+            if x == 4 {
+                TileType::Rock0
+            } else {
+                TileType::Air
+            }
+        });
+
+        World::new(tiles, vec![(Point3::new(7, 1, 1), Color::WHITE)])
+    }
+
+    #[test]
+    fn test_sealed_light_skipped() {
+        let mut world = world();
+        let regions = SealedRegions::new(&mut world);
+        let player = Point3::new(1, 1, 1);
+
+        assert_ne!(regions.region(player), regions.region(Point3::new(7, 1, 1)));
+        assert_eq!(regions.region(Point3::new(4, 1, 1)), None);
+        assert_eq!(regions.visible_lights(world.lights(), player), (vec![], 1));
+
+        // From inside the lit room, or from inside the wall, it's kept
+        let all = (world.lights().to_vec(), 0);
+        assert_eq!(
+            regions.visible_lights(world.lights(), Point3::new(6, 0, 2)),
+            all
+        );
+        assert_eq!(
+            regions.visible_lights(world.lights(), Point3::new(4, 1, 1)),
+            all
+        );
+    }
+
+    #[test]
+    fn test_light_in_wall_shines_both_ways() {
+        let mut world = world();
+        let crystal = (Point3::new(4, 2, 0), Color::WHITE);
+        let regions = SealedRegions::new(&mut world);
+
+        for &player in &[Point3::new(0, 0, 0), Point3::new(8, 2, 2)] {
+            assert_eq!(
+                regions.visible_lights(&[crystal], player),
+                (vec![crystal], 0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_opening_wall_lets_light_in() {
+        let mut world = world();
+        let mut regions = SealedRegions::new(&mut world);
+        let mut scratch = ShadowcastScratch::new(LIGHT_RANGE);
+        let player = Point3::new(2, 1, 1);
+
+        let mut frame = |world: &mut World, regions: &mut SealedRegions| {
+            regions.refresh(world);
+            let (lights, _) = regions.visible_lights(world.lights(), player);
+            compute_lighting(world.tiles_mut(), &lights, LIGHT_RANGE, &mut scratch);
+            world.tiles()[[player.x, player.y, player.z]].illuminated()
+        };
+
+        assert!(!frame(&mut world, &mut regions));
+        assert!(!regions.refresh(&mut world));
+
+        world.set_tile(Point3::new(4, 1, 1), TileType::Air);
+        assert!(frame(&mut world, &mut regions));
+        assert_eq!(regions.region(player), regions.region(Point3::new(7, 1, 1)));
+    }
+}
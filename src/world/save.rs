@@ -0,0 +1,466 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use failure::{format_err, Fallible};
+use ggez::graphics::Color;
+use na::Point3;
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::*,
+    inventory::{Inventory, ItemKind},
+    rendering::tile::*,
+    run::Run,
+    util::write_json,
+    world::{DecalType, TileMetadata, World},
+};
+
+/// Where saved worlds go, in a folder each.
+pub const SAVES_DIR: &str = "saves";
+
+/// Goes up whenever the save format changes, so that saves in an older format are turned away
+/// instead of being read wrong.
+pub const SAVE_VERSION: u32 = 1;
+
+const QUICKSAVE_FILE: &str = "quicksave.json";
+
+/// Where the world called `name` is quicksaved. The name has to be a plain folder name.
+pub fn quicksave_path(name: &str) -> Fallible<PathBuf> {
+    let mut components = Path::new(name).components();
+
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => {
+            Ok(Path::new(SAVES_DIR).join(name).join(QUICKSAVE_FILE))
+        }
+        _ => Err(format_err!("\"{}\" can't be used as a world name", name)),
+    }
+}
+
+/// Where the player was, where they were looking, what they were carrying and how far through
+/// the run they'd got.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PlayerSave {
+    pub pos: [f32; 3],
+    pub facing: [f32; 2],
+    /// Missing from saves made before the player could carry anything.
+    #[serde(default)]
+    pub inventory: Inventory,
+    /// Missing from saves made before runs were kept, which start the run over.
+    #[serde(default)]
+    pub run: Run,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LightSave {
+    pub pos: [usize; 3],
+    pub color: [f32; 3],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct MetadataSave {
+    pub pos: [i32; 3],
+    pub metadata: TileMetadata,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DecalSave {
+    pub pos: [i32; 3],
+    pub decal: DecalType,
+}
+
+/// Everything needed to carry on in a world later. Lighting isn't kept, since it's worked out
+/// again from the lights.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub tiles: Array3<TileType>,
+    pub lights: Vec<LightSave>,
+    /// Missing from saves made before tiles had any.
+    #[serde(default)]
+    pub metadata: Vec<MetadataSave>,
+    /// Kept apart from the rest of the metadata and oldest first, so the oldest are still the
+    /// first to be cleared after loading.
+    #[serde(default)]
+    pub decals: Vec<DecalSave>,
+    pub player: PlayerSave,
+}
+
+/// Read on its own first, so a save in another format gets a clear message rather than
+/// whatever it fails to parse on.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+impl SaveFile {
+    pub fn new(world: &World, player: PlayerSave) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            tiles: world.tiles().map(|tile| tile.tile_type),
+            lights: world
+                .lights()
+                .iter()
+                .map(|(pos, color)| LightSave {
+                    pos: [pos.x, pos.y, pos.z],
+                    color: [color.r, color.g, color.b],
+                })
+                .collect(),
+            metadata: saved_metadata(world),
+            decals: world
+                .decals()
+                .into_iter()
+                .map(|(pos, decal)| DecalSave {
+                    pos: [pos.x, pos.y, pos.z],
+                    decal,
+                })
+                .collect(),
+            player,
+        }
+    }
+
+    /// Writes the save to `path`, making its folder if need be.
+    pub fn write<P>(&self, path: P) -> Fallible<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        write_json(path, self)
+    }
+
+    /// Reads the save at `path`, failing if it's in another format or doesn't make sense.
+    pub fn read<P>(path: P) -> Fallible<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| format_err!("Failed to read {}: {}", path.display(), e))?;
+
+        let header: SaveHeader = serde_json::from_str(&json)
+            .map_err(|e| format_err!("{} isn't a save: {}", path.display(), e))?;
+        if header.version != SAVE_VERSION {
+            return Err(format_err!(
+                "{} is in version {} of the save format, but only version {} can be loaded",
+                path.display(),
+                header.version,
+                SAVE_VERSION
+            ));
+        }
+
+        let save: Self = serde_json::from_str(&json)
+            .map_err(|e| format_err!("{} is corrupt: {}", path.display(), e))?;
+        save.check()
+            .map_err(|e| format_err!("{} is corrupt: {}", path.display(), e))?;
+
+        Ok(save)
+    }
+
+    /// Turns the save back into a world, with no lighting yet, and where the player was.
+    pub fn into_world(self) -> (World, PlayerSave) {
+        let tile_types = self.tiles;
+        let tiles = tiles_from_fn(tile_types.dim(), |(x, y, z)| tile_types[[x, y, z]]);
+
+        let lights = self
+            .lights
+            .iter()
+            .map(|light| {
+                let [r, g, b] = light.color;
+                (Point3::from(light.pos), Color::new(r, g, b, 1.0))
+            })
+            .collect();
+
+        let mut world = World::new(tiles, lights);
+        for saved in &self.metadata {
+            world.set_metadata(Point3::from(saved.pos), Some(saved.metadata));
+        }
+        for saved in &self.decals {
+            world.add_decal(Point3::from(saved.pos), saved.decal);
+        }
+
+        (world, self.player)
+    }
+
+    /// Makes sure nothing in the save is outside the world it describes.
+    fn check(&self) -> Fallible<()> {
+        let (width, height, depth) = self.tiles.dim();
+        let size = [width, height, depth];
+
+        if size.iter().any(|&side| side == 0 || side > MAX_CHUNK_SIZE) {
+            return Err(format_err!("the world is {}x{}x{}", width, height, depth));
+        }
+
+        if let Some(light) = self
+            .lights
+            .iter()
+            .find(|light| light.pos.iter().zip(&size).any(|(&c, &side)| c >= side))
+        {
+            return Err(format_err!(
+                "there's a light outside the world at {:?}",
+                light.pos
+            ));
+        }
+
+        let outside = |pos: &[i32; 3]| {
+            pos.iter()
+                .zip(&size)
+                .any(|(&c, &side)| c < 0 || c as usize >= side)
+        };
+        if let Some(saved) = self.metadata.iter().find(|saved| outside(&saved.pos)) {
+            return Err(format_err!(
+                "there's metadata outside the world at {:?}",
+                saved.pos
+            ));
+        }
+        if let Some(saved) = self.decals.iter().find(|saved| outside(&saved.pos)) {
+            return Err(format_err!(
+                "there's a decal outside the world at {:?}",
+                saved.pos
+            ));
+        }
+
+        let player = &self.player;
+        let inside = player
+            .pos
+            .iter()
+            .zip(&size)
+            .all(|(&c, &side)| c >= 0.0 && c < side as f32);
+        if !inside || !player.facing.iter().all(|c| c.is_finite()) {
+            return Err(format_err!("the player is at {:?}", player));
+        }
+
+        if let Some(kind) = ItemKind::ALL
+            .iter()
+            .copied()
+            .find(|&kind| player.inventory.count(kind) > kind.max_carried())
+        {
+            return Err(format_err!(
+                "the player is carrying {} {}",
+                player.inventory.count(kind),
+                kind.name()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The world's tile metadata in order of position, so the same world always saves the same.
+/// Decals are saved on their own.
+fn saved_metadata(world: &World) -> Vec<MetadataSave> {
+    let mut metadata: Vec<_> = world
+        .all_metadata()
+        .map(|(pos, &metadata)| MetadataSave {
+            pos: [pos.x, pos.y, pos.z],
+            metadata: TileMetadata {
+                decal: None,
+                ..metadata
+            },
+        })
+        .filter(|saved| saved.metadata != TileMetadata::default())
+        .collect();
+    metadata.sort_unstable_by_key(|saved| saved.pos);
+    metadata
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::run::{GameObjective, RunResults};
+    use std::time::Duration;
+
+    fn save() -> SaveFile {
+        SaveFile {
+            version: SAVE_VERSION,
+            tiles: Array3::from_shape_fn((4, 4, 4), |(_, y, _)| {
+                if y == 0 {
+                    TileType::Rock0
+                } else {
+                    TileType::Air
+                }
+            }),
+            lights: vec![LightSave {
+                pos: [1, 1, 1],
+                color: [1.0, 0.5, 0.0],
+            }],
+            metadata: vec![MetadataSave {
+                pos: [2, 1, 3],
+                metadata: TileMetadata {
+                    target: Some([0, 1, 0]),
+                    ..TileMetadata::default()
+                },
+            }],
+            decals: vec![
+                DecalSave {
+                    pos: [3, 0, 2],
+                    decal: DecalType::BulletPock,
+                },
+                DecalSave {
+                    pos: [2, 1, 3],
+                    decal: DecalType::Scorch,
+                },
+            ],
+            player: PlayerSave {
+                pos: [2.0, 1.0, 2.5],
+                facing: [0.5, -0.25],
+                inventory: inventory(),
+                run: Run::new(),
+            },
+        }
+    }
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::new();
+        inventory.add(ItemKind::Ammo, 30);
+        inventory.add(ItemKind::Flare, 2);
+        inventory
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join("katakomb_test_saves")
+            .join(name)
+            .join(QUICKSAVE_FILE)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_path("round_trip");
+        save().write(&path).unwrap();
+
+        let loaded = SaveFile::read(&path).unwrap();
+        assert_eq!(loaded, save());
+
+        let (world, player) = loaded.into_world();
+        assert_eq!(player, save().player);
+        assert_eq!(player.inventory.count(ItemKind::Ammo), 30);
+        assert_eq!(world.revision(), 0);
+        assert_eq!(
+            world.lights(),
+            &[(Point3::new(1, 1, 1), Color::new(1.0, 0.5, 0.0, 1.0))]
+        );
+        assert_eq!(
+            world
+                .tile(Point3::new(3, 0, 2))
+                .map(|t| (t.pos, t.tile_type)),
+            Some((Point3::new(3.0, 0.0, 2.0), TileType::Rock0))
+        );
+        assert_eq!(
+            world.metadata(Point3::new(2, 1, 3)).and_then(|m| m.target),
+            Some([0, 1, 0])
+        );
+        assert_eq!(
+            world.metadata(Point3::new(2, 1, 3)).and_then(|m| m.decal),
+            Some(DecalType::Scorch)
+        );
+        assert_eq!(
+            world.decals(),
+            vec![
+                (Point3::new(3, 0, 2), DecalType::BulletPock),
+                (Point3::new(2, 1, 3), DecalType::Scorch),
+            ]
+        );
+
+        assert_eq!(SaveFile::new(&world, player), save());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_run_round_trip() {
+        let path = temp_path("run");
+        let mut saved = save();
+        saved.player.run = Run {
+            objective: GameObjective::ReturnToEntrance,
+            elapsed: Duration::from_millis(83_250),
+            shots_fired: 12,
+        };
+        saved.write(&path).unwrap();
+
+        let (_, player) = SaveFile::read(&path).unwrap().into_world();
+        assert_eq!(player.run, saved.player.run);
+
+        // Escaping keeps the results, so a save made after it still shows them
+        saved.player.run.objective = GameObjective::Escaped(RunResults {
+            elapsed: Duration::from_secs(90),
+            shots_fired: 12,
+            tiles_explored: 340,
+        });
+        saved.write(&path).unwrap();
+        let (_, player) = SaveFile::read(&path).unwrap().into_world();
+        assert_eq!(player.run, saved.player.run);
+
+        // From before runs were kept
+        let mut old = serde_json::to_value(&save()).unwrap();
+        old["player"].as_object_mut().unwrap().remove("run");
+        fs::write(&path, old.to_string()).unwrap();
+        let (_, player) = SaveFile::read(&path).unwrap().into_world();
+        assert_eq!(player.run, Run::new());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_bad_saves() {
+        let path = temp_path("bad");
+
+        let newer = SaveFile {
+            version: SAVE_VERSION + 1,
+            ..save()
+        };
+        newer.write(&path).unwrap();
+        let error = SaveFile::read(&path).unwrap_err().to_string();
+        assert!(error.contains("version"), "{}", error);
+
+        let mut outside = save();
+        outside.lights[0].pos = [1, 4, 1];
+        outside.write(&path).unwrap();
+        assert!(SaveFile::read(&path).is_err());
+
+        let mut stray = save();
+        stray.metadata[0].pos = [2, -1, 3];
+        stray.write(&path).unwrap();
+        assert!(SaveFile::read(&path).is_err());
+
+        let mut stray = save();
+        stray.decals[1].pos = [2, 1, 4];
+        stray.write(&path).unwrap();
+        assert!(SaveFile::read(&path).is_err());
+
+        let mut lost = save();
+        lost.player.pos = [2.0, -1.0, 2.0];
+        lost.write(&path).unwrap();
+        assert!(SaveFile::read(&path).is_err());
+
+        let json = serde_json::to_string(&save())
+            .unwrap()
+            .replace(r#""ammo":30"#, r#""ammo":1000"#);
+        fs::write(&path, json).unwrap();
+        let error = SaveFile::read(&path).unwrap_err().to_string();
+        assert!(error.contains("1000 ammo"), "{}", error);
+
+        fs::write(&path, r#"{ "version": 1, "tiles": "#).unwrap();
+        assert!(SaveFile::read(&path).is_err());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert!(SaveFile::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_quicksave_path() {
+        assert_eq!(
+            quicksave_path("caves").unwrap(),
+            Path::new(SAVES_DIR).join("caves").join(QUICKSAVE_FILE)
+        );
+
+        for name in &["", "..", "a/b", "/tmp"] {
+            assert!(quicksave_path(name).is_err(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, hash::Hash};
+
+use na::{distance, Point3};
+use ndarray::prelude::*;
+
+use crate::{rendering::tile::Tile, world::util::line_of_sight};
+
+/// How loud a sound is, compared to having nothing in the way, once it's completely blocked.
+pub const OCCLUDED_VOLUME: f32 = 0.3;
+/// The low-pass cutoff for sounds with nothing in the way, high enough to leave them as they are.
+pub const OPEN_CUTOFF_HZ: u32 = 20_000;
+/// The low-pass cutoff for sounds that are completely blocked.
+pub const MUFFLED_CUTOFF_HZ: u32 = 600;
+/// How many tics the occlusion of an emitter is reused for before casting it again.
+pub const OCCLUSION_CACHE_TICS: u64 = 8;
+
+/// How a sound should be played to be heard from where the player is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    /// From 0 for silent to 1 for as loud as the sound is.
+    pub volume: f32,
+    /// Where to low-pass the sound, e.g. with `rodio::Source::low_pass`.
+    pub cutoff_hz: u32,
+}
+
+impl Attenuation {
+    /// A sound `dist` away through `occlusion`, from 0 with nothing in the way to 1 behind a
+    /// wall, which fades out completely by `range`.
+    pub fn new(dist: f32, occlusion: f32, range: f32) -> Self {
+        let occlusion = occlusion.max(0.0).min(1.0);
+        let falloff = if range > 0.0 {
+            (1.0 - dist / range).max(0.0).min(1.0)
+        } else {
+            0.0
+        };
+
+        // Spread out evenly in octaves rather than Hz, as that's how it's heard
+        let muffling = MUFFLED_CUTOFF_HZ as f32 / OPEN_CUTOFF_HZ as f32;
+        let cutoff_hz = OPEN_CUTOFF_HZ as f32 * muffling.powf(occlusion);
+
+        Self {
+            volume: falloff * (1.0 - occlusion * (1.0 - OCCLUDED_VOLUME)),
+            cutoff_hz: cutoff_hz.round() as u32,
+        }
+    }
+
+    pub fn is_muffled(self) -> bool {
+        self.cutoff_hz < OPEN_CUTOFF_HZ
+    }
+}
+
+/// How a sound made at `emitter` is heard at `listener`, quieter and muffled by the walls in
+/// between.
+pub fn attenuation(
+    tiles: ArrayView3<Tile>,
+    listener: Point3<f32>,
+    emitter: Point3<f32>,
+    range: f32,
+) -> Attenuation {
+    let occlusion = line_of_sight(tiles, listener, emitter).occlusion;
+    Attenuation::new(distance(&listener, &emitter), occlusion, range)
+}
+
+/// The occlusion of each emitter as of the tic it was last cast on. Walls hardly ever change, so
+/// it's only cast again every `OCCLUSION_CACHE_TICS` tics, while the distance is kept up to date.
+#[derive(Clone, Debug)]
+pub struct OcclusionCache<K> {
+    cast: HashMap<K, (f32, u64)>,
+}
+
+impl<K: Eq + Hash> OcclusionCache<K> {
+    pub fn new() -> Self {
+        Self {
+            cast: HashMap::new(),
+        }
+    }
+
+    /// How the sound of emitter `key` at `emitter` is heard at `listener` on tic `now`.
+    pub fn attenuation(
+        &mut self,
+        tiles: ArrayView3<Tile>,
+        key: K,
+        listener: Point3<f32>,
+        emitter: Point3<f32>,
+        range: f32,
+        now: u64,
+    ) -> Attenuation {
+        let occlusion = match self.cast.get(&key) {
+            Some(&(occlusion, tic)) if now < tic + OCCLUSION_CACHE_TICS => occlusion,
+            _ => {
+                let occlusion = line_of_sight(tiles, listener, emitter).occlusion;
+                self.cast.insert(key, (occlusion, now));
+                occlusion
+            }
+        };
+
+        Attenuation::new(distance(&listener, &emitter), occlusion, range)
+    }
+
+    /// Forgets the emitters whose occlusion is too old to reuse on tic `now`, e.g. because they've
+    /// stopped making sounds.
+    pub fn expire(&mut self, now: u64) {
+        self.cast
+            .retain(|_, &mut (_, tic)| now < tic + OCCLUSION_CACHE_TICS);
+    }
+
+    pub fn len(&self) -> usize {
+        self.cast.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cast.is_empty()
+    }
+}
+
+impl<K: Eq + Hash> Default for OcclusionCache<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rendering::tile::{tiles_from_fn, TileType};
+
+    /// Open air with a rock wall across x = 4 if `wall` is set.
+    fn tiles(wall: bool) -> Array3<Tile> {
+        tiles_from_fn((9, 3, 3), |(x, _, _)| {
+            if wall && x == 4 {
+                TileType::Rock0
+            } else {
+                TileType::Air
+            }
+        })
+    }
+
+    #[test]
+    fn test_muffled_behind_wall() {
+        let listener = Point3::new(1.5, 1.5, 1.5);
+        let emitter = Point3::new(7.5, 1.5, 1.5);
+
+        let open = attenuation(tiles(false).view(), listener, emitter, 16.0);
+        assert!(!open.is_muffled());
+        assert!((open.volume - (1.0 - 6.0 / 16.0)).abs() < 1e-5);
+
+        let walled = attenuation(tiles(true).view(), listener, emitter, 16.0);
+        assert!(walled.is_muffled());
+        assert_eq!(walled.cutoff_hz, MUFFLED_CUTOFF_HZ);
+        assert!((walled.volume - open.volume * OCCLUDED_VOLUME).abs() < 1e-5);
+
+        // On the same side of the wall it's heard as it is
+        let near = attenuation(
+            tiles(true).view(),
+            listener,
+            Point3::new(3.5, 0.5, 2.5),
+            16.0,
+        );
+        assert!(!near.is_muffled());
+    }
+
+    #[test]
+    fn test_attenuation_range() {
+        assert_eq!(Attenuation::new(0.0, 0.0, 16.0).volume, 1.0);
+        assert_eq!(Attenuation::new(20.0, 0.0, 16.0).volume, 0.0);
+        assert_eq!(Attenuation::new(1.0, 0.0, 0.0).volume, 0.0);
+
+        // Partly blocked is somewhere in between
+        let half = Attenuation::new(0.0, 0.5, 16.0);
+        assert!(half.cutoff_hz < OPEN_CUTOFF_HZ && half.cutoff_hz > MUFFLED_CUTOFF_HZ);
+        assert!(half.volume < 1.0 && half.volume > OCCLUDED_VOLUME);
+    }
+
+    #[test]
+    fn test_occlusion_cache() {
+        let open = tiles(false);
+        let walled = tiles(true);
+        let listener = Point3::new(1.5, 1.5, 1.5);
+        let emitter = Point3::new(7.5, 1.5, 1.5);
+        let mut cache = OcclusionCache::new();
+
+        let heard = |cache: &mut OcclusionCache<u32>, tiles: &Array3<Tile>, now| {
+            cache.attenuation(tiles.view(), 7, listener, emitter, 16.0, now)
+        };
+
+        assert!(!heard(&mut cache, &open, 0).is_muffled());
+        // The wall isn't noticed until the cast is too old to reuse
+        assert!(!heard(&mut cache, &walled, OCCLUSION_CACHE_TICS - 1).is_muffled());
+        assert!(heard(&mut cache, &walled, OCCLUSION_CACHE_TICS).is_muffled());
+
+        cache.expire(OCCLUSION_CACHE_TICS * 2 - 1);
+        assert_eq!(cache.len(), 1);
+        cache.expire(OCCLUSION_CACHE_TICS * 2);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use internship::IStr;
+use na::{Point3, Vector3};
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    rendering::{tile::*, voxel::Model},
+    world::World,
+};
+
+/// Where the editor exports stamps and the game picks them up from.
+pub const STAMP_PATH: &str = "stamp.json";
+
+/// A block of tiles to be written into the world, e.g. a model exported from the editor. Empty
+/// cells leave whatever is already there.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(from = "StampFile", into = "StampFile")]
+pub struct TileStamp {
+    pub tiles: Array3<Option<TileType>>,
+    /// The point that's placed where the stamp is aimed, in tiles from its first corner.
+    pub pivot: Point3<f32>,
+}
+
+impl TileStamp {
+    /// A stamp of `tiles` that's placed by its first corner.
+    pub fn new(tiles: Array3<Option<TileType>>) -> Self {
+        Self {
+            tiles,
+            pivot: Point3::origin(),
+        }
+    }
+}
+
+/// How a stamp is saved. Stamps saved before they had a pivot are just their tiles, and are
+/// placed by their first corner like they were then.
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum StampFile {
+    Stamp {
+        tiles: Array3<Option<TileType>>,
+        pivot: [f32; 3],
+    },
+    Tiles(Array3<Option<TileType>>),
+}
+
+impl From<StampFile> for TileStamp {
+    fn from(file: StampFile) -> Self {
+        match file {
+            StampFile::Stamp { tiles, pivot } => Self {
+                tiles,
+                pivot: pivot.into(),
+            },
+            StampFile::Tiles(tiles) => Self::new(tiles),
+        }
+    }
+}
+
+impl From<TileStamp> for StampFile {
+    fn from(stamp: TileStamp) -> Self {
+        StampFile::Stamp {
+            tiles: stamp.tiles,
+            pivot: stamp.pivot.coords.into(),
+        }
+    }
+}
+
+/// Turns `model` into a stamp using `mapping` to pick the tile for each voxel, keeping its pivot.
+/// Returns the stamp along with the voxels that have no tile, which are left empty.
+pub fn from_model(model: &Model, mapping: &BTreeMap<IStr, TileType>) -> (TileStamp, Vec<IStr>) {
+    let mut unmapped = BTreeSet::new();
+
+    let tiles = model.voxels.map(|name| {
+        name.as_ref().and_then(|name| {
+            let tile_type = mapping.get(name).copied();
+            if tile_type.is_none() {
+                unmapped.insert(name.clone());
+            }
+
+            tile_type
+        })
+    });
+
+    let stamp = TileStamp {
+        tiles,
+        pivot: model.pivot,
+    };
+
+    (stamp, unmapped.into_iter().collect())
+}
+
+/// Writes `stamp` into `world` with its pivot at `at`, rounded to the nearest tile, leaving out
+/// any part that falls outside. Returns how many tiles were written.
+pub fn apply(world: &mut World, at: Point3<i32>, stamp: &TileStamp) -> usize {
+    let pivot = stamp.pivot.coords.map(|c| c.round() as i32);
+    let origin = at - pivot;
+    let mut written = 0;
+
+    for ((x, y, z), tile_type) in stamp.tiles.indexed_iter() {
+        let tile_type = match tile_type {
+            Some(tile_type) => *tile_type,
+            None => continue,
+        };
+
+        let pos = origin + Vector3::new(x as i32, y as i32, z as i32);
+        if world.set_tile(pos, tile_type) {
+            written += 1;
+        }
+    }
+
+    written
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn air(size: usize) -> World {
+        let tiles = tiles_from_fn((size, size, size), |_| TileType::Air);
+
+        World::new(tiles, Vec::new())
+    }
+
+    #[test]
+    fn test_model_round_trip() {
+        let mut voxels = Array3::from_elem((2, 2, 1), None);
+        voxels[(0, 0, 0)] = Some(IStr::new("rock"));
+        voxels[(1, 1, 0)] = Some(IStr::new("candle"));
+        voxels[(1, 0, 0)] = Some(IStr::new("unknown"));
+        let model = Model::new(voxels).with_pivot(Point3::origin());
+
+        let mapping = vec![
+            (IStr::new("rock"), TileType::Rock0),
+            (IStr::new("candle"), TileType::Candle),
+        ]
+        .into_iter()
+        .collect();
+
+        let (stamp, unmapped) = from_model(&model, &mapping);
+        assert_eq!(unmapped, vec![IStr::new("unknown")]);
+        assert_eq!(stamp.tiles[(0, 0, 0)], Some(TileType::Rock0));
+        assert_eq!(stamp.tiles[(1, 0, 0)], None);
+
+        let mut world = air(4);
+        assert_eq!(apply(&mut world, Point3::new(1, 2, 3), &stamp), 2);
+        let tiles = world.tiles();
+        assert_eq!(tiles[[1, 2, 3]].tile_type, TileType::Rock0);
+        assert_eq!(tiles[[2, 3, 3]].tile_type, TileType::Candle);
+        assert_eq!(tiles[[2, 2, 3]].tile_type, TileType::Air);
+        assert_eq!(
+            tiles
+                .iter()
+                .filter(|t| t.tile_type != TileType::Air)
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_apply_clips_to_bounds() {
+        let stamp = TileStamp::new(Array3::from_elem((3, 3, 3), Some(TileType::Rock1)));
+        let mut world = air(4);
+
+        assert_eq!(apply(&mut world, Point3::new(-1, -1, -1), &stamp), 8);
+        assert_eq!(apply(&mut world, Point3::new(3, 3, 3), &stamp), 1);
+        assert_eq!(apply(&mut world, Point3::new(4, 0, 0), &stamp), 0);
+        assert_eq!(world.tiles()[[3, 3, 3]].tile_type, TileType::Rock1);
+        assert_eq!(world.revision(), 9);
+    }
+
+    #[test]
+    fn test_apply_at_pivot() {
+        let mut voxels = Array3::from_elem((3, 1, 3), None);
+        voxels[(0, 0, 0)] = Some(IStr::new("rock"));
+        voxels[(1, 0, 1)] = Some(IStr::new("candle"));
+        let model = Model::new(voxels);
+        let mapping = vec![
+            (IStr::new("rock"), TileType::Rock0),
+            (IStr::new("candle"), TileType::Candle),
+        ]
+        .into_iter()
+        .collect();
+
+        let (stamp, _) = from_model(&model, &mapping);
+        assert_eq!(stamp.pivot, Point3::new(1.0, 0.0, 1.0));
+
+        // The middle of the model lands where it's aimed
+        let mut world = air(4);
+        assert_eq!(apply(&mut world, Point3::new(2, 1, 2), &stamp), 2);
+        assert_eq!(world.tiles()[[2, 1, 2]].tile_type, TileType::Candle);
+        assert_eq!(world.tiles()[[1, 1, 1]].tile_type, TileType::Rock0);
+    }
+
+    #[test]
+    fn test_old_stamps_load() {
+        let old = TileStamp::new(Array3::from_elem((1, 2, 1), Some(TileType::Rock0)));
+        let json = serde_json::to_string(&old.tiles).unwrap();
+        let loaded: TileStamp = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, old);
+
+        let new = TileStamp {
+            pivot: Point3::new(0.0, 0.5, 0.0),
+            ..old
+        };
+        let json = serde_json::to_string(&new).unwrap();
+        let loaded: TileStamp = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, new);
+    }
+}
@@ -1,103 +1,537 @@
+use std::collections::VecDeque;
+
 use na::*;
 use ndarray::prelude::*;
 
 use crate::{
-    constants::*,
-    geometry::util::*,
-    rendering::{drawable::*, light::Light, tile::*},
+    constants::{NEAR_PLANE, PLAYER_EYE_HEIGHT},
+    geometry::{aabb::IAabb3, util::*},
+    rendering::{drawable::*, tile::*},
     util::*,
 };
 
 pub fn world_pos_to_index(pos: Point3<f32>) -> Point3<usize> {
-    Point3::new(
-        pos.x.floor() as usize,
-        pos.y.floor() as usize,
-        pos.z.floor() as usize,
-    )
+    to_usize_point(floor_point(pos))
+}
+
+/// Which cells count as touching one another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Adjacency {
+    /// Cells sharing a face.
+    Face6,
+    /// Cells sharing a face or an edge.
+    Edge18,
+    /// Cells sharing a face, an edge or a corner.
+    Corner26,
+}
+
+impl Adjacency {
+    /// The offsets from a cell to its neighbours.
+    pub fn offsets(self) -> impl Iterator<Item = Vector3<i32>> {
+        let max_axes = match self {
+            Adjacency::Face6 => 1,
+            Adjacency::Edge18 => 2,
+            Adjacency::Corner26 => 3,
+        };
+
+        IAabb3::from_center_radius(Point3::origin(), 1)
+            .iter_cells()
+            .map(|p| p.coords)
+            .filter(move |offset| {
+                let axes = offset.iter().filter(|&&c| c != 0).count();
+                axes > 0 && axes <= max_axes
+            })
+    }
 }
 
-pub fn _world_pos_to_int(pos: Point3<f32>) -> Point3<i32> {
-    Point3::new(
-        pos.x.floor() as i32,
-        pos.y.floor() as i32,
-        pos.z.floor() as i32,
-    )
+/// The neighbours of `pos` in an array with dimensions `dim`, leaving out any past its edges.
+pub fn neighbours(
+    pos: Point3<usize>,
+    dim: (usize, usize, usize),
+    adjacency: Adjacency,
+) -> impl Iterator<Item = Point3<usize>> {
+    let pos = pos.map(|c| c as i32);
+
+    adjacency
+        .offsets()
+        .filter_map(move |offset| try_to_usize_point(pos + offset, dim))
 }
 
-pub fn any_neighbour_is<F>(array: ArrayView3<Tile>, pos: Point3<i32>, f: F) -> bool
+pub fn any_neighbour_is<F>(
+    array: ArrayView3<Tile>,
+    pos: Point3<usize>,
+    adjacency: Adjacency,
+    f: F,
+) -> bool
 where
     F: Fn(&Tile) -> bool,
 {
-    for x in -1..=1 {
-        for y in -1..=1 {
-            for z in -1..=1 {
-                let x_index = (pos.x + x) as usize;
-                let y_index = (pos.y + y) as usize;
-                let z_index = (pos.z + z) as usize;
-
-                if x_index < array.dim().0
-                    && y_index < array.dim().1
-                    && z_index < array.dim().2
-                    && f(&array[[x_index, y_index, z_index]])
-                {
-                    return true;
-                }
+    neighbours(pos, array.dim(), adjacency).any(|p| f(&array[[p.x, p.y, p.z]]))
+}
+
+pub fn all_neighbours_are<F>(
+    array: ArrayView3<Tile>,
+    pos: Point3<usize>,
+    adjacency: Adjacency,
+    f: F,
+) -> bool
+where
+    F: Fn(&Tile) -> bool,
+{
+    neighbours(pos, array.dim(), adjacency).all(|p| f(&array[[p.x, p.y, p.z]]))
+}
+
+/// Every cell matching `predicate` that can be reached from `start` without passing through one
+/// that doesn't, including `start` itself. Empty if `start` doesn't match.
+pub fn flood_fill<F>(
+    tiles: ArrayView3<Tile>,
+    start: Point3<usize>,
+    adjacency: Adjacency,
+    predicate: F,
+) -> Vec<Point3<usize>>
+where
+    F: Fn(&Tile) -> bool,
+{
+    let mut labels = Array3::zeros(tiles.dim());
+    let mut cells = Vec::new();
+
+    fill_region(tiles, &mut labels, start, 1, adjacency, &predicate, |p| {
+        cells.push(p)
+    });
+
+    cells
+}
+
+/// Like `flood_fill`, but also how many steps each cell is from `start`, in the order they're
+/// reached, so the nearest come first.
+pub fn path_distances<F>(
+    tiles: ArrayView3<Tile>,
+    start: Point3<usize>,
+    adjacency: Adjacency,
+    predicate: F,
+) -> Vec<(Point3<usize>, u32)>
+where
+    F: Fn(&Tile) -> bool,
+{
+    let mut reached = Vec::new();
+    if !is_in_array(tiles, start) || !predicate(&tiles[[start.x, start.y, start.z]]) {
+        return reached;
+    }
+
+    let mut visited = Array3::from_elem(tiles.dim(), false);
+    visited[[start.x, start.y, start.z]] = true;
+    let mut queue = VecDeque::from(vec![(start, 0)]);
+
+    while let Some((p, distance)) = queue.pop_front() {
+        reached.push((p, distance));
+
+        for n in neighbours(p, tiles.dim(), adjacency) {
+            let index = [n.x, n.y, n.z];
+            if !visited[index] && predicate(&tiles[index]) {
+                visited[index] = true;
+                queue.push_back((n, distance + 1));
             }
         }
     }
 
-    false
+    reached
+}
+
+/// Splits the cells matching `predicate` into connected regions. Each cell is labelled with its
+/// region, counting up from 1, or 0 if it doesn't match. Also returns how many regions there are.
+pub fn label_regions<F>(
+    tiles: ArrayView3<Tile>,
+    adjacency: Adjacency,
+    predicate: F,
+) -> (Array3<u32>, u32)
+where
+    F: Fn(&Tile) -> bool,
+{
+    let mut labels = Array3::zeros(tiles.dim());
+    let mut count = 0;
+
+    for ((x, y, z), tile) in tiles.indexed_iter() {
+        if labels[[x, y, z]] == 0 && predicate(tile) {
+            count += 1;
+            fill_region(
+                tiles,
+                &mut labels,
+                Point3::new(x, y, z),
+                count,
+                adjacency,
+                &predicate,
+                |_| {},
+            );
+        }
+    }
+
+    (labels, count)
 }
 
-//Tries to fire a bresenham hitscan, returns dest if no collisions
+/// Gives `label` to every unlabelled cell connected to `start` that matches `predicate`. Uses a
+/// stack rather than recursion, since a region can be most of a chunk.
+fn fill_region<F, V>(
+    tiles: ArrayView3<Tile>,
+    labels: &mut Array3<u32>,
+    start: Point3<usize>,
+    label: u32,
+    adjacency: Adjacency,
+    predicate: &F,
+    mut visit: V,
+) where
+    F: Fn(&Tile) -> bool,
+    V: FnMut(Point3<usize>),
+{
+    let matches = |labels: &Array3<u32>, p: Point3<usize>| {
+        labels[[p.x, p.y, p.z]] == 0 && predicate(&tiles[[p.x, p.y, p.z]])
+    };
+
+    if !is_in_array(tiles, start) || !matches(labels, start) {
+        return;
+    }
+
+    labels[[start.x, start.y, start.z]] = label;
+    let mut stack = vec![start];
+
+    while let Some(p) = stack.pop() {
+        visit(p);
+
+        for n in neighbours(p, tiles.dim(), adjacency) {
+            if matches(labels, n) {
+                labels[[n.x, n.y, n.z]] = label;
+                stack.push(n);
+            }
+        }
+    }
+}
+
+/// How a hitscan ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hitscan {
+    /// The ray reached an opaque tile.
+    Hit(Point3<i32>),
+    /// The ray left the array. Holds the last tile it passed through inside it, or `None` if it
+    /// started outside.
+    OutOfBounds(Option<Point3<i32>>),
+    /// The ray reached `dest` without hitting anything.
+    Clear,
+}
+
+/// Fires a ray along a bresenham line from `src` to `dest`, stopping at the first opaque tile
+/// or where it leaves the array.
 pub fn _try_bresenham_hitscan(
     tile_array: ArrayView3<Tile>,
     src: Point3<i32>,
     dest: Point3<i32>,
-) -> Point3<i32> {
-    if src.x >= 0
-        && src.x < CHUNK_SIZE as i32
-        && src.y >= 0
-        && src.y < CHUNK_SIZE as i32
-        && src.z >= 0
-        && src.z < CHUNK_SIZE as i32
-    {
-        for ray_point in calculate_bresenham(src, dest) {
-            let ray_tile = tile_array[[
-                ray_point.x as usize,
-                ray_point.y as usize,
-                ray_point.z as usize,
-            ]]
-            .clone();
-
-            if ray_point.x >= 0
-                && ray_point.x < CHUNK_SIZE as i32
-                && ray_point.y >= 0
-                && ray_point.y < CHUNK_SIZE as i32
-                && ray_point.z >= 0
-                && ray_point.z < CHUNK_SIZE as i32
-            {
-                if !ray_tile.tile_type.is_transparent() {
-                    return ray_point;
+) -> Hitscan {
+    let mut last = None;
+
+    for ray_point in BresenhamIter::new(src, dest) {
+        let index = match try_to_usize_point(ray_point, tile_array.dim()) {
+            Some(index) => index,
+            None => return Hitscan::OutOfBounds(last),
+        };
+
+        if !tile_array[[index.x, index.y, index.z]]
+            .tile_type
+            .is_transparent()
+        {
+            return Hitscan::Hit(ray_point);
+        }
+
+        last = Some(ray_point);
+    }
+
+    Hitscan::Clear
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TileAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl TileAxis {
+    pub const ALL: [TileAxis; 3] = [TileAxis::X, TileAxis::Y, TileAxis::Z];
+
+    pub fn index(self) -> usize {
+        match self {
+            TileAxis::X => 0,
+            TileAxis::Y => 1,
+            TileAxis::Z => 2,
+        }
+    }
+}
+
+/// A side of a tile, e.g. the one a ray came in through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TileFace {
+    pub axis: TileAxis,
+    /// Whether the face is on the side of the tile that's further along `axis`.
+    pub positive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The index of the tile that was hit.
+    pub cell: Point3<i32>,
+    /// The face the ray entered the tile through.
+    pub face: TileFace,
+    /// How far along the ray the tile was entered.
+    pub t: f32,
+}
+
+impl RayHit {
+    /// The point where the ray entered the tile.
+    pub fn point(&self, origin: Point3<f32>, dir: &Unit<Vector3<f32>>) -> Point3<f32> {
+        origin + dir.into_inner() * self.t
+    }
+}
+
+/// The tiles a ray passes through in order (Amanatides and Woo's voxel traversal), starting
+/// after the one it starts in and going no further than `max_dist`. Where the ray passes
+/// exactly through an edge or corner, it goes through a tile next to it rather than slipping
+/// between them. Doesn't know about any array, so callers need to check the bounds themselves.
+struct RayCells {
+    cell: Point3<i32>,
+    step: [i32; 3],
+    /// How far along the ray the next tile boundary is on each axis.
+    t_max: [f32; 3],
+    /// How far along the ray it is from one tile boundary to the next on each axis.
+    t_delta: [f32; 3],
+    max_dist: f32,
+}
+
+impl RayCells {
+    fn new(origin: Point3<f32>, dir: Unit<Vector3<f32>>, max_dist: f32) -> Self {
+        let cell = floor_point(origin);
+        let mut step = [0; 3];
+        let mut t_max = [f32::INFINITY; 3];
+        let mut t_delta = [f32::INFINITY; 3];
+
+        for axis in 0..3 {
+            let d = dir[axis];
+
+            if d > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = (cell[axis] as f32 + 1.0 - origin[axis]) / d;
+                t_delta[axis] = 1.0 / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (origin[axis] - cell[axis] as f32) / -d;
+                t_delta[axis] = 1.0 / -d;
+            }
+        }
+
+        Self {
+            cell,
+            step,
+            t_max,
+            t_delta,
+            max_dist,
+        }
+    }
+}
+
+impl Iterator for RayCells {
+    type Item = RayHit;
+
+    fn next(&mut self) -> Option<RayHit> {
+        let t_max = &self.t_max;
+        let axis = TileAxis::ALL
+            .iter()
+            .copied()
+            .min_by(|a, b| t_max[a.index()].partial_cmp(&t_max[b.index()]).unwrap())
+            .unwrap();
+        let i = axis.index();
+        let t = self.t_max[i];
+
+        if t > self.max_dist {
+            return None;
+        }
+
+        self.cell[i] += self.step[i];
+        self.t_max[i] += self.t_delta[i];
+
+        Some(RayHit {
+            cell: self.cell,
+            face: TileFace {
+                axis,
+                // Moving along the axis enters through the face at the lower end
+                positive: self.step[i] < 0,
+            },
+            t,
+        })
+    }
+}
+
+/// Finds the first tile that isn't transparent along a ray, visiting every tile the ray passes
+/// through in order. The tile the ray starts in is skipped, and the ray stops when it leaves
+/// `tile_array` or goes further than `max_dist`.
+pub fn raycast(
+    tile_array: ArrayView3<Tile>,
+    origin: Point3<f32>,
+    dir: Unit<Vector3<f32>>,
+    max_dist: f32,
+) -> Option<RayHit> {
+    raycast_until(tile_array, origin, dir, max_dist, |tile| {
+        !tile.tile_type.is_transparent()
+    })
+}
+
+/// Like `raycast`, but finds the first tile that `stop` is true for.
+pub fn raycast_until<F>(
+    tile_array: ArrayView3<Tile>,
+    origin: Point3<f32>,
+    dir: Unit<Vector3<f32>>,
+    max_dist: f32,
+    stop: F,
+) -> Option<RayHit>
+where
+    F: Fn(&Tile) -> bool,
+{
+    let bounds = IAabb3::from_dim(tile_array.dim());
+
+    RayCells::new(origin, dir, max_dist)
+        .take_while(|hit| bounds.contains(hit.cell))
+        .find(|hit| {
+            let cell = hit.cell;
+            stop(&tile_array[[cell.x as usize, cell.y as usize, cell.z as usize]])
+        })
+}
+
+/// What's under something, found by `probe_ground`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ground {
+    /// How far down the ground is.
+    pub distance: f32,
+    /// The tile being stood on, or `None` for the bottom of the world.
+    pub tile_type: Option<TileType>,
+}
+
+/// Looks for solid ground at most `max_dist` under a square footprint reaching `half_width` from
+/// `pos` along x and z. This casts a ray down from each corner of the footprint and returns the
+/// closest ground, so standing half off a ledge still counts as standing on it.
+pub fn probe_ground(
+    tiles: ArrayView3<Tile>,
+    pos: Point3<f32>,
+    half_width: f32,
+    max_dist: f32,
+) -> Option<Ground> {
+    [(-1.0, -1.0), (-1.0, 1.0), (1.0, -1.0), (1.0, 1.0)]
+        .iter()
+        .filter_map(|&(dx, dz)| {
+            let corner = pos + Vector3::new(dx * half_width, 0.0, dz * half_width);
+            ground_below(tiles, corner, max_dist)
+        })
+        .min_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap())
+}
+
+/// The ground straight down from `pos`, starting with the tile `pos` is in.
+fn ground_below(tiles: ArrayView3<Tile>, pos: Point3<f32>, max_dist: f32) -> Option<Ground> {
+    let ground_at = |cell: Point3<i32>, distance: f32| match try_to_usize_point(cell, tiles.dim()) {
+        Some(index) => {
+            let tile_type = tiles[[index.x, index.y, index.z]].tile_type;
+
+            if tile_type.collides() {
+                Some(Ground {
+                    distance,
+                    tile_type: Some(tile_type),
+                })
+            } else {
+                None
+            }
+        }
+        // Off the sides of the world there's nothing to stand on
+        None if cell.y < 0 => Some(Ground {
+            distance,
+            tile_type: None,
+        }),
+        None => None,
+    };
+
+    ground_at(floor_point(pos), 0.0).or_else(|| {
+        RayCells::new(pos, -Vector3::y_axis(), max_dist).find_map(|hit| ground_at(hit.cell, hit.t))
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LosResult {
+    /// Whether nothing at all is in the way.
+    pub clear: bool,
+    /// How much of whatever's at the far end is hidden, from 0 to 1.
+    pub occlusion: f32,
+    /// The first tile in the way, if it's in the array.
+    pub first_block: Option<Point3<usize>>,
+}
+
+/// How well something at `to` can be seen from `from`. The tiles at either end don't count, so
+/// the face of a wall can be seen from the open tile in front of it. Anything outside `tiles`
+/// blocks the view completely.
+pub fn line_of_sight(tiles: ArrayView3<Tile>, from: Point3<f32>, to: Point3<f32>) -> LosResult {
+    let mut result = LosResult {
+        clear: true,
+        occlusion: 0.0,
+        first_block: None,
+    };
+
+    let (dir, dist) = match Unit::try_new_and_get(to - from, f32::EPSILON) {
+        Some(dir) => dir,
+        None => return result,
+    };
+    let target = floor_point(to);
+
+    for hit in RayCells::new(from, dir, dist) {
+        if hit.cell == target {
+            break;
+        }
+
+        let opacity = match try_to_usize_point(hit.cell, tiles.dim()) {
+            Some(index) => {
+                let opacity = tile_opacity(&tiles[[index.x, index.y, index.z]]);
+
+                if opacity > 0.0 && result.first_block.is_none() {
+                    result.first_block = Some(index);
                 }
+
+                opacity
             }
+            None => 1.0,
+        };
+
+        result.occlusion += opacity;
+
+        if result.occlusion >= 1.0 {
+            result.occlusion = 1.0;
+            break;
         }
-    } else {
-        return src;
     }
 
-    return dest;
+    result.clear = result.occlusion == 0.0;
+    result
+}
+
+/// How much light a tile stops, from 0 to 1. Tiles don't have their own opacity yet, so anything
+/// that isn't transparent stops all of it.
+fn tile_opacity(tile: &Tile) -> f32 {
+    if tile.tile_type.is_transparent() {
+        0.0
+    } else {
+        1.0
+    }
 }
 
 //Tries to fire a floating point hitscan, returns dest if no collisions
 //This assumes that whatever is being scanned against is in an evenly spaced grid of tile size 1*1*1
+#[deprecated(note = "steps a whole tile at a time, so it can miss corners; use `raycast`")]
 pub fn _try_ray_hitscan(
     tile_array: ArrayView3<Tile>,
     src: Point3<f32>,
     dest: Point3<f32>,
 ) -> Point3<f32> {
     if is_in_array(tile_array, world_pos_to_index(src)) {
-        let distance = euclidean_distance_squared(src, dest).sqrt();
+        let distance = crate::geometry::util::distance(src, dest);
         let distance_ratios = Point3::new(
             (dest.x - src.x) / distance,
             (dest.y - src.y) / distance,
@@ -136,59 +570,709 @@ pub fn _try_ray_hitscan(
     return dest;
 }
 
-pub fn _hitscan_tile(
-    tile_array: ArrayView3<Tile>,
-    src: Point3<f32>,
-    dest: Point3<f32>,
-) -> Vec<Point3<f32>> {
-    let mut hits = Vec::new();
+/// How much of the tile at `dest` is hidden from `src`, from 0 if it can be seen completely to 1
+/// if it can't be seen at all, e.g. to tell whether someone standing there is in cover. This
+/// averages the line of sight to points just inside each corner of the tile.
+pub fn cover_fraction(tiles: ArrayView3<Tile>, src: Point3<f32>, dest: Point3<usize>) -> f32 {
+    let corner = dest.map(|c| c as f32) + Vector3::repeat(0.05);
+    let samples = get_cube_points(corner);
+
+    samples
+        .iter()
+        .map(|&sample| line_of_sight(tiles, src, sample).occlusion)
+        .sum::<f32>()
+        / samples.len() as f32
+}
+
+/// How far a sound made at `src` travels before hitting something, in each direction out to
+/// `range`, e.g. for working out the echoes of a gunshot. Directions where the sound leaves the
+/// array or goes past `range` without hitting anything don't echo, so they're left out.
+pub fn echo_distances(tiles: ArrayView3<Tile>, src: Point3<f32>, range: usize) -> Vec<f32> {
+    SpherePoints::surface(range)
+        .iter()
+        .filter_map(|point| {
+            let dir = Unit::try_new(point.coords, f32::EPSILON)?;
+            raycast(tiles, src, dir, range as f32).map(|hit| hit.t)
+        })
+        .collect()
+}
+
+/// The tile at a world position, or `None` if it's outside the array.
+pub fn try_get_tile_at(pos: Point3<f32>, tile_array: &Array3<Tile>) -> Option<&Tile> {
+    try_to_usize_point(floor_point(pos), tile_array.dim())
+        .map(|index| &tile_array[[index.x, index.y, index.z]])
+}
+
+/// Whether something at `pos` would be inside a solid tile. Anything outside the array counts as
+/// solid, so nothing can leave it.
+pub fn collides_at(pos: Point3<f32>, tile_array: &Array3<Tile>) -> bool {
+    try_get_tile_at(pos, tile_array).map_or(true, |tile| tile.tile_type.collides())
+}
 
-    for target in get_cube_points(dest) {
-        let hit = _try_ray_hitscan(tile_array, src, target);
+/// Zeroes each axis of `vel` that would carry `pos` into something solid.
+pub fn clip_velocity(pos: Point3<f32>, vel: &mut Vector3<f32>, tile_array: &Array3<Tile>) {
+    for i in 0..3 {
+        let mut step = Vector3::zeros();
+        step[i] = vel[i];
 
-        if world_pos_to_index(hit) != world_pos_to_index(target) {
-            hits.push(hit);
+        if collides_at(pos + step, tile_array) {
+            vel[i] = 0.0;
         }
     }
+}
+
+/// Where the eye of someone standing at `feet` goes: `PLAYER_EYE_HEIGHT` above them, or lower
+/// if that's inside something solid, like a low ceiling, so that the camera never sees through
+/// rock. It's never put below the feet.
+pub fn clamp_eye(feet: Point3<f32>, tile_array: &Array3<Tile>) -> Point3<f32> {
+    let mut eye = feet + Vector3::y() * PLAYER_EYE_HEIGHT;
 
-    hits
+    if collides_at(eye, tile_array) {
+        // Just under the ceiling, far enough from it that it isn't cut by the near plane
+        eye.y = (eye.y.floor() - NEAR_PLANE).max(feet.y);
+    }
+
+    eye
 }
 
-pub fn _get_light_hitscans(
-    light: &Light,
-    lighting_sphere: &Vec<Point3<f32>>,
-    tile_array: ArrayView3<Tile>,
-) -> Vec<Point3<f32>> {
-    let mut ray_hits = Vec::new();
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    // tile_array[[
-    //         light.pos.x.floor() as usize,
-    //         light.pos.y.floor() as usize,
-    //         light.pos.z.floor() as usize,
-    //     ]]
-    //     .illumination = 0.9;
+    fn tiles(size: usize, rock: &[[usize; 3]]) -> Array3<Tile> {
+        let mut tiles = tiles_from_fn((size, size, size), |_| TileType::Air);
 
-    let light_target: Point3<f32> = Point3::origin() + (light.facing * light.range).coords;
+        for &index in rock {
+            tiles[index].tile_type = TileType::Rock0;
+        }
 
-    for target_point in lighting_sphere {
-        let target_point_offset = Point3::new(
-            target_point.x + light.pos.x + light_target.x,
-            target_point.y + light.pos.y + light_target.y,
-            target_point.z + light.pos.z + light_target.z,
-        );
+        tiles
+    }
+
+    #[test]
+    fn test_neighbour_counts() {
+        let dim = (4, 4, 4);
 
-        ray_hits.append(&mut _hitscan_tile(
-            tile_array,
-            light.pos,
-            target_point_offset,
+        // Indexed by how many axes the cell is on the edge of the array along
+        let expected = [
+            (Adjacency::Face6, [6, 5, 4, 3]),
+            (Adjacency::Edge18, [18, 13, 9, 6]),
+            (Adjacency::Corner26, [26, 17, 11, 7]),
+        ];
+
+        for cell in IAabb3::from_dim(dim).iter_cells() {
+            let pos = to_usize_point(cell);
+            let edges = (0..3).filter(|&i| pos[i] == 0 || pos[i] == 3).count();
+
+            for &(adjacency, counts) in &expected {
+                let found: Vec<_> = neighbours(pos, dim, adjacency).collect();
+                assert_eq!(found.len(), counts[edges], "{:?} {}", adjacency, pos);
+
+                for n in found.iter() {
+                    assert_ne!(*n, pos);
+                    assert!(neighbours(*n, dim, adjacency).any(|back| back == pos));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighbours_are() {
+        let tiles = tiles(3, &[[0, 1, 1], [1, 1, 0], [2, 2, 2]]);
+        let rock = |t: &Tile| t.tile_type == TileType::Rock0;
+        let centre = Point3::new(1, 1, 1);
+
+        assert!(any_neighbour_is(
+            tiles.view(),
+            centre,
+            Adjacency::Face6,
+            rock
+        ));
+        assert!(!all_neighbours_are(
+            tiles.view(),
+            centre,
+            Adjacency::Face6,
+            rock
+        ));
+
+        // The cell itself isn't its own neighbour
+        assert!(!any_neighbour_is(
+            tiles.view(),
+            Point3::new(2, 2, 2),
+            Adjacency::Corner26,
+            rock
+        ));
+
+        // Only touches rock along an edge
+        let corner = Point3::new(0, 0, 0);
+        assert!(!any_neighbour_is(
+            tiles.view(),
+            corner,
+            Adjacency::Face6,
+            rock
+        ));
+        assert!(any_neighbour_is(
+            tiles.view(),
+            corner,
+            Adjacency::Edge18,
+            rock
+        ));
+        assert!(all_neighbours_are(
+            tiles.view(),
+            corner,
+            Adjacency::Face6,
+            |t| t.tile_type == TileType::Air
         ));
     }
 
-    ray_hits
-}
+    /// Solid rock apart from two pockets of air, plus a pair of cells that only touch each other
+    /// along an edge and the second pocket at a corner.
+    fn pockets() -> Array3<Tile> {
+        let mut tiles = tiles(5, &[]);
+        tiles.iter_mut().for_each(|t| t.tile_type = TileType::Rock0);
+
+        for &index in &[
+            [1, 1, 1],
+            [1, 1, 2],
+            [1, 2, 2],
+            [3, 3, 3],
+            [3, 3, 2],
+            [3, 1, 1],
+            [4, 2, 1],
+        ] {
+            tiles[index].tile_type = TileType::Air;
+        }
+
+        tiles
+    }
+
+    #[test]
+    fn test_flood_fill() {
+        let tiles = pockets();
+        let air = |t: &Tile| t.tile_type == TileType::Air;
+
+        let mut filled = flood_fill(tiles.view(), Point3::new(1, 2, 2), Adjacency::Face6, air);
+        filled.sort_by_key(|p| (p.x, p.y, p.z));
+        assert_eq!(
+            filled,
+            vec![
+                Point3::new(1, 1, 1),
+                Point3::new(1, 1, 2),
+                Point3::new(1, 2, 2)
+            ]
+        );
+
+        let start = Point3::new(3, 1, 1);
+        assert_eq!(
+            flood_fill(tiles.view(), start, Adjacency::Face6, air).len(),
+            1
+        );
+        assert_eq!(
+            flood_fill(tiles.view(), start, Adjacency::Edge18, air).len(),
+            2
+        );
+        assert_eq!(
+            flood_fill(tiles.view(), start, Adjacency::Corner26, air).len(),
+            4
+        );
+
+        // Starting in rock or outside the array
+        assert!(flood_fill(tiles.view(), Point3::new(0, 0, 0), Adjacency::Face6, air).is_empty());
+        assert!(flood_fill(tiles.view(), Point3::new(5, 0, 0), Adjacency::Face6, air).is_empty());
+    }
+
+    #[test]
+    fn test_path_distances() {
+        let tiles = pockets();
+        let air = |t: &Tile| t.tile_type == TileType::Air;
+
+        assert_eq!(
+            path_distances(tiles.view(), Point3::new(1, 2, 2), Adjacency::Face6, air),
+            vec![
+                (Point3::new(1, 2, 2), 0),
+                (Point3::new(1, 1, 2), 1),
+                (Point3::new(1, 1, 1), 2),
+            ]
+        );
+
+        // Diagonal steps count as one
+        let distances =
+            path_distances(tiles.view(), Point3::new(3, 1, 1), Adjacency::Corner26, air);
+        assert_eq!(distances.len(), 4);
+        assert_eq!(distances[2], (Point3::new(3, 3, 2), 2));
+        assert_eq!(distances[3], (Point3::new(3, 3, 3), 3));
+
+        assert!(
+            path_distances(tiles.view(), Point3::new(0, 0, 0), Adjacency::Face6, air).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_label_regions() {
+        let tiles = pockets();
+        let air = |t: &Tile| t.tile_type == TileType::Air;
+
+        let (labels, count) = label_regions(tiles.view(), Adjacency::Face6, air);
+        assert_eq!(count, 4);
+        assert_eq!(labels[[0, 0, 0]], 0);
+        assert_eq!(labels[[1, 1, 1]], labels[[1, 2, 2]]);
+        assert_ne!(labels[[1, 1, 1]], labels[[3, 3, 3]]);
+        assert_ne!(labels[[3, 1, 1]], labels[[4, 2, 1]]);
+        assert_eq!(labels.iter().filter(|&&l| l != 0).count(), 7);
+        assert_eq!(labels.iter().max(), Some(&4));
+
+        assert_eq!(label_regions(tiles.view(), Adjacency::Edge18, air).1, 3);
+
+        let (labels, count) = label_regions(tiles.view(), Adjacency::Corner26, air);
+        assert_eq!(count, 2);
+        assert_eq!(labels[[3, 1, 1]], labels[[3, 3, 3]]);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_label_regions_chunk_time() {
+        use std::time::{Duration, Instant};
+
+        use crate::constants::CHUNK_SIZE;
+
+        // A checkerboard makes every cell its own face-connected region, which is the most
+        // regions a chunk can have
+        let mut tiles = tiles(CHUNK_SIZE, &[]);
+        for ((x, y, z), tile) in tiles.indexed_iter_mut() {
+            if (x + y + z) % 2 == 0 {
+                tile.tile_type = TileType::Rock0;
+            }
+        }
+
+        let start = Instant::now();
+        let (_, count) = label_regions(tiles.view(), Adjacency::Face6, |t| {
+            t.tile_type.is_transparent()
+        });
+        let full = label_regions(tiles.view(), Adjacency::Corner26, |_| true);
+
+        assert_eq!(count as usize, CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE / 2);
+        assert_eq!(full.1, 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_line_of_sight_corridor() {
+        let mut tiles = tiles(8, &[]);
+        for ((x, y, z), tile) in tiles.indexed_iter_mut() {
+            let corridor = (y == 1 && z == 1) || (x == 6 && z == 1 && y >= 1);
+            if !corridor {
+                tile.tile_type = TileType::Rock0;
+            }
+        }
+
+        let start = Point3::new(0.5, 1.5, 1.5);
+        let end = Point3::new(7.5, 1.5, 1.5);
+        assert_eq!(
+            line_of_sight(tiles.view(), start, end),
+            LosResult {
+                clear: true,
+                occlusion: 0.0,
+                first_block: None,
+            }
+        );
+
+        // Around the corner
+        let around = line_of_sight(tiles.view(), start, Point3::new(6.5, 6.5, 1.5));
+        assert!(!around.clear);
+        assert_eq!(around.occlusion, 1.0);
+        assert_eq!(around.first_block.map(|p| p.y), Some(2));
+
+        // A wall can be seen from the tile in front of it
+        let wall = Point3::new(7.5, 1.5, 2.5);
+        assert!(line_of_sight(tiles.view(), Point3::new(7.5, 1.5, 1.5), wall).clear);
+
+        tiles[[4, 1, 1]].tile_type = TileType::Rock0;
+        let blocked = line_of_sight(tiles.view(), start, end);
+        assert!(!blocked.clear);
+        assert_eq!(blocked.occlusion, 1.0);
+        assert_eq!(blocked.first_block, Some(Point3::new(4, 1, 1)));
+    }
+
+    #[test]
+    fn test_line_of_sight_edges() {
+        let tiles = tiles(4, &[[1, 0, 0], [0, 1, 0], [0, 2, 0], [1, 2, 0], [2, 2, 0]]);
+
+        // Exactly through the corner between two rocks
+        let squeeze = line_of_sight(
+            tiles.view(),
+            Point3::new(0.5, 0.5, 0.5),
+            Point3::new(1.5, 1.5, 0.5),
+        );
+        assert_eq!(squeeze.first_block, Some(Point3::new(1, 0, 0)));
+
+        // Grazing along the wall without touching it, then tilted just enough to
+        assert!(
+            line_of_sight(
+                tiles.view(),
+                Point3::new(1.5, 1.99, 0.5),
+                Point3::new(3.5, 1.99, 0.5)
+            )
+            .clear
+        );
+        assert_eq!(
+            line_of_sight(
+                tiles.view(),
+                Point3::new(3.5, 1.9, 0.5),
+                Point3::new(1.5, 2.05, 0.5)
+            )
+            .first_block,
+            Some(Point3::new(2, 2, 0))
+        );
+
+        // Going nowhere, and out of the array
+        assert!(
+            line_of_sight(
+                tiles.view(),
+                Point3::new(3.5, 0.5, 0.5),
+                Point3::new(3.5, 0.5, 0.5)
+            )
+            .clear
+        );
+        let outside = line_of_sight(
+            tiles.view(),
+            Point3::new(3.5, 0.5, 0.5),
+            Point3::new(3.5, -2.5, 0.5),
+        );
+        assert_eq!(outside.occlusion, 1.0);
+        assert_eq!(outside.first_block, None);
+    }
+
+    #[test]
+    fn test_cover_fraction() {
+        let mut tiles = tiles(8, &[]);
+        let src = Point3::new(0.5, 3.5, 4.5);
+        let dest = Point3::new(6, 4, 4);
+
+        assert_eq!(cover_fraction(tiles.view(), src, dest), 0.0);
+
+        // A wall that only hides the bottom half of the tile
+        for y in 0..4 {
+            for z in 0..8 {
+                tiles[[3, y, z]].tile_type = TileType::Rock0;
+            }
+        }
+        assert_eq!(cover_fraction(tiles.view(), src, dest), 0.5);
+
+        tiles[[3, 4, 4]].tile_type = TileType::Rock0;
+        tiles[[3, 5, 4]].tile_type = TileType::Rock0;
+        assert_eq!(cover_fraction(tiles.view(), src, dest), 1.0);
+
+        // Past the edge of the array
+        assert_eq!(cover_fraction(tiles.view(), src, Point3::new(0, 3, 9)), 1.0);
+    }
+
+    #[test]
+    fn test_echo_distances() {
+        let mut tiles = tiles(9, &[]);
+        let src = Point3::new(4.5, 4.5, 4.5);
 
-pub fn get_tile_at(pos: Point3<f32>, tile_array: &Array3<Tile>) -> Tile {
-    let index = world_pos_to_index(pos);
+        assert!(echo_distances(tiles.view(), src, 3).is_empty());
 
-    tile_array[[index.x, index.y, index.z]].clone()
+        // A closed room, so every direction echoes off a wall 3.5 to 3.5 * sqrt(3) tiles away
+        for (index, tile) in tiles.indexed_iter_mut() {
+            if [index.0, index.1, index.2]
+                .iter()
+                .any(|&c| c == 0 || c == 8)
+            {
+                tile.tile_type = TileType::Rock0;
+            }
+        }
+
+        let echoes = echo_distances(tiles.view(), src, 7);
+        assert_eq!(echoes.len(), SpherePoints::surface(7).len());
+        assert!(echoes
+            .iter()
+            .all(|&t| t >= 3.5 - 1e-4 && t <= 3.5 * 3f32.sqrt() + 1e-4));
+
+        assert!(echo_distances(tiles.view(), src, 0).is_empty());
+    }
+
+    #[test]
+    fn test_probe_ground_ledge_and_hole() {
+        // A floor one tile thick, with a hole at (2, 0, 2) and nothing past x = 4
+        let mut rock = Vec::new();
+        for x in 0..4 {
+            for z in 0..5 {
+                if (x, z) != (2, 2) {
+                    rock.push([x, 0, z]);
+                }
+            }
+        }
+        let tiles = tiles(5, &rock);
+        let probe =
+            |x: f32, y: f32, z: f32| probe_ground(tiles.view(), Point3::new(x, y, z), 0.3, 4.0);
+
+        assert_eq!(
+            probe(1.5, 1.0, 0.5),
+            Some(Ground {
+                distance: 0.0,
+                tile_type: Some(TileType::Rock0),
+            })
+        );
+        assert_eq!(probe(1.5, 1.5, 0.5).map(|g| g.distance), Some(0.5));
+
+        // Half off the ledge still stands on it, but not once the whole footprint is past it
+        assert_eq!(probe(4.1, 1.0, 0.5).map(|g| g.distance), Some(0.0));
+        assert_eq!(
+            probe(4.5, 1.0, 0.5),
+            Some(Ground {
+                distance: 1.0,
+                tile_type: None,
+            })
+        );
+
+        // Over the hole, and standing across its edge
+        assert_eq!(probe(2.5, 1.0, 2.5).map(|g| g.tile_type), Some(None));
+        assert_eq!(probe(2.5, 1.0, 2.5).map(|g| g.distance), Some(1.0));
+        assert_eq!(probe(2.5, 1.0, 2.9).map(|g| g.distance), Some(0.0));
+
+        // Sunk a little into the floor
+        assert_eq!(probe(1.5, 0.9, 0.5).map(|g| g.distance), Some(0.0));
+
+        // Too far down to find
+        assert_eq!(
+            probe_ground(tiles.view(), Point3::new(1.5, 4.5, 0.5), 0.3, 2.0),
+            None
+        );
+    }
+
+    #[test]
+    fn test_probe_ground_steps() {
+        // Steps going down along x, the top of each one a tile below the last
+        let mut rock = Vec::new();
+        for x in 0..4 {
+            for y in 0..(4 - x) {
+                rock.push([x, y, 0]);
+            }
+        }
+        let tiles = tiles(5, &rock);
+
+        let mut y = 4.0;
+        let mut x = 0.5;
+        while x < 4.0 {
+            let ground = probe_ground(tiles.view(), Point3::new(x, y, 0.5), 0.3, 4.0).unwrap();
+            y -= ground.distance;
+
+            // Never further down than the step the footprint's furthest back corner is over
+            let step = (x - 0.3).floor() as i32;
+            assert_eq!(y, (4 - step) as f32, "at x = {}", x);
+
+            x += 0.25;
+        }
+    }
+
+    #[test]
+    fn test_bresenham_hitscan_leaves_every_face() {
+        let tiles = tiles(4, &[]);
+        let src = Point3::new(1, 2, 1);
+
+        for &(dest, last) in &[
+            ([9, 2, 1], [3, 2, 1]),
+            ([-9, 2, 1], [0, 2, 1]),
+            ([1, 9, 1], [1, 3, 1]),
+            ([1, -9, 1], [1, 0, 1]),
+            ([1, 2, 9], [1, 2, 3]),
+            ([1, 2, -9], [1, 2, 0]),
+        ] {
+            assert_eq!(
+                _try_bresenham_hitscan(tiles.view(), src, Point3::from(dest)),
+                Hitscan::OutOfBounds(Some(Point3::from(last)))
+            );
+        }
+
+        // Diagonally out through a corner
+        assert_eq!(
+            _try_bresenham_hitscan(tiles.view(), src, Point3::new(-5, 8, -5)),
+            Hitscan::OutOfBounds(Some(Point3::new(0, 3, 0)))
+        );
+
+        assert_eq!(
+            _try_bresenham_hitscan(tiles.view(), Point3::new(-1, 0, 0), Point3::new(2, 0, 0)),
+            Hitscan::OutOfBounds(None)
+        );
+    }
+
+    #[test]
+    fn test_bresenham_hitscan_hit_and_clear() {
+        let tiles = tiles(4, &[[3, 2, 1]]);
+        let src = Point3::new(0, 2, 1);
+
+        assert_eq!(
+            _try_bresenham_hitscan(tiles.view(), src, Point3::new(9, 2, 1)),
+            Hitscan::Hit(Point3::new(3, 2, 1))
+        );
+        assert_eq!(
+            _try_bresenham_hitscan(tiles.view(), src, Point3::new(2, 2, 1)),
+            Hitscan::Clear
+        );
+        assert_eq!(
+            _try_bresenham_hitscan(tiles.view(), src, Point3::new(0, 0, 3)),
+            Hitscan::Clear
+        );
+    }
+
+    #[test]
+    fn test_try_get_tile_at() {
+        let tiles = tiles(4, &[[1, 2, 3]]);
+
+        assert_eq!(
+            try_get_tile_at(Point3::new(1.5, 2.0, 3.99), &tiles).map(|t| t.tile_type),
+            Some(TileType::Rock0)
+        );
+        assert!(try_get_tile_at(Point3::new(4.0, 0.0, 0.0), &tiles).is_none());
+        assert!(try_get_tile_at(Point3::new(-0.1, 0.0, 0.0), &tiles).is_none());
+
+        assert!(collides_at(Point3::new(1.5, 2.5, 3.5), &tiles));
+        assert!(!collides_at(Point3::new(0.5, 0.5, 0.5), &tiles));
+        assert!(collides_at(Point3::new(0.5, -0.5, 0.5), &tiles));
+        assert!(collides_at(Point3::new(0.5, 0.5, 4.5), &tiles));
+    }
+
+    #[test]
+    fn test_movement_stays_in_array() {
+        let tiles = tiles(4, &[]);
+
+        // Pushing hard out of every face, the same way Katakomb::update moves the player
+        for &push in &[
+            Vector3::x(),
+            -Vector3::x(),
+            Vector3::y(),
+            -Vector3::y(),
+            Vector3::z(),
+            -Vector3::z(),
+            Vector3::new(1.0, -1.0, 1.0),
+        ] {
+            let mut pos = Point3::new(2.5, 2.5, 2.5);
+            let mut vel = Vector3::zeros();
+
+            for _ in 0..100 {
+                vel += push * 0.3;
+                clip_velocity(pos, &mut vel, &tiles);
+
+                let new_pos = pos + vel;
+                if !collides_at(new_pos, &tiles) {
+                    pos = new_pos;
+                }
+
+                assert!(
+                    try_get_tile_at(pos, &tiles).is_some(),
+                    "{:?} left at {}",
+                    push,
+                    pos
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_raycast_along_axis() {
+        let tiles = tiles(8, &[[5, 2, 2], [6, 2, 2]]);
+        let origin = Point3::new(1.5, 2.5, 2.5);
+
+        let hit = raycast(tiles.view(), origin, Vector3::x_axis(), 10.0).unwrap();
+        assert_eq!(hit.cell, Point3::new(5, 2, 2));
+        assert_eq!(
+            hit.face,
+            TileFace {
+                axis: TileAxis::X,
+                positive: false,
+            }
+        );
+        assert!((hit.t - 3.5).abs() < 1e-5);
+        assert!((hit.point(origin, &Vector3::x_axis()).x - 5.0).abs() < 1e-5);
+
+        // Too short, the other way and out of the array
+        assert_eq!(raycast(tiles.view(), origin, Vector3::x_axis(), 3.0), None);
+        assert_eq!(
+            raycast(tiles.view(), origin, -Vector3::x_axis(), 10.0),
+            None
+        );
+        assert_eq!(
+            raycast(tiles.view(), origin, Vector3::y_axis(), 100.0),
+            None
+        );
+
+        // Coming back from the other side
+        let hit = raycast(
+            tiles.view(),
+            Point3::new(7.5, 2.5, 2.5),
+            -Vector3::x_axis(),
+            10.0,
+        )
+        .unwrap();
+        assert_eq!(hit.cell, Point3::new(6, 2, 2));
+        assert!(hit.face.positive);
+        assert!((hit.t - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_raycast_until() {
+        let mut tiles = tiles(8, &[[5, 2, 2]]);
+        tiles[[3, 2, 2]].tile_type = TileType::Lever;
+        let origin = Point3::new(1.5, 2.5, 2.5);
+
+        // Light passes the lever, but it's what's found when looking for something to pull
+        let hit = raycast(tiles.view(), origin, Vector3::x_axis(), 10.0).unwrap();
+        assert_eq!(hit.cell, Point3::new(5, 2, 2));
+        let hit = raycast_until(tiles.view(), origin, Vector3::x_axis(), 10.0, |tile| {
+            tile.tile_type.is_interactable()
+        })
+        .unwrap();
+        assert_eq!(hit.cell, Point3::new(3, 2, 2));
+    }
+
+    #[test]
+    fn test_raycast_corner() {
+        // Only the diagonal neighbour is open
+        let tiles = tiles(4, &[[2, 1, 1], [1, 2, 1]]);
+        let dir = Unit::new_normalize(Vector3::new(1.0, 1.0, 0.0));
+
+        let hit = raycast(tiles.view(), Point3::new(1.5, 1.5, 1.5), dir, 10.0).unwrap();
+        assert!(hit.cell == Point3::new(2, 1, 1) || hit.cell == Point3::new(1, 2, 1));
+        assert!((hit.t - 0.5 * 2f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_raycast_distance() {
+        let tiles = tiles(16, &[[12, 9, 4]]);
+        let origin = Point3::new(1.25, 2.5, 3.75);
+        let target = Point3::new(12.5, 9.5, 4.5);
+        let (dir, _) = Unit::new_and_get(target - origin);
+
+        let hit = raycast(tiles.view(), origin, dir, 100.0).unwrap();
+        assert_eq!(hit.cell, Point3::new(12, 9, 4));
+
+        // The ray enters where it crosses the nearest of the tile's lower faces, x = 12 here
+        let t = (12.0 - origin.x) / dir.x;
+        assert_eq!(hit.face.axis, TileAxis::X);
+        assert!((hit.t - t).abs() < 1e-4);
+    }
+    #[test]
+    fn test_clamp_eye() {
+        // A crawlspace one tile high at y = 1, with rock above and below it
+        let mut rock: Vec<_> = (0..4)
+            .flat_map(|x| (0..4).flat_map(move |z| vec![[x, 0, z], [x, 2, z]]))
+            .collect();
+        rock.retain(|&index| index != [3, 2, 3]);
+        let tiles = tiles(4, &rock);
+
+        let feet = Point3::new(1.5, 1.0, 1.5);
+        let eye = clamp_eye(feet, &tiles);
+        assert!(!collides_at(eye, &tiles));
+        assert!(eye.y < 2.0 - NEAR_PLANE + 1e-5 && eye.y >= feet.y);
+        assert_eq!((eye.x, eye.z), (feet.x, feet.z));
+
+        // Higher up in the crawlspace, but never under the feet
+        let feet = Point3::new(1.5, 1.95, 1.5);
+        assert_eq!(clamp_eye(feet, &tiles), feet);
+
+        // Where there's a hole in the ceiling there's room to stand up
+        let feet = Point3::new(3.5, 1.0, 3.5);
+        assert_eq!(clamp_eye(feet, &tiles), Point3::new(3.5, 2.0, 3.5));
+    }
 }